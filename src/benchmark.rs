@@ -0,0 +1,479 @@
+//! The shared code path of the read benchmarks.
+//!
+//! [`benchmark_read`] and [`async_benchmark_read`] read an entire array, every chunk, or every inner chunk of a sharded array, and return a [`BenchmarkReadReport`] with the throughput, cache hit rates, and the distribution of per-request latency.
+
+use std::{
+    fmt::Display,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use zarrs::{
+    array::{
+        codec::{CodecOptions, CodecOptionsBuilder},
+        Array, ArrayShardedExt, ArrayShardedReadableExt, ArrayShardedReadableExtCache,
+    },
+    array_subset::ArraySubset,
+    storage::ReadableStorageTraits,
+};
+
+use crate::{calculate_chunk_and_codec_concurrency, Cache, ChunkCacheArgs};
+
+/// Read benchmark arguments.
+#[derive(Parser, Debug, Clone)]
+pub struct BenchmarkReadArgs {
+    /// Number of concurrent chunks.
+    #[arg(long)]
+    pub concurrent_chunks: Option<usize>,
+
+    /// Read the entire array in one operation.
+    ///
+    /// If set, `concurrent_chunks` is ignored.
+    #[arg(long, default_value_t = false)]
+    pub read_all: bool,
+
+    /// Read the inner chunks of a sharded array rather than its shards.
+    ///
+    /// Shard indexes are cached with the sync API, and the chunk cache is not used.
+    #[arg(long, default_value_t = false, conflicts_with = "read_all")]
+    pub inner_chunks: bool,
+
+    /// Ignore checksums.
+    ///
+    /// If set, checksum validation in codecs (e.g. crc32c) is skipped.
+    #[arg(long, default_value_t = false)]
+    pub ignore_checksums: bool,
+
+    /// The number of times to read every chunk.
+    ///
+    /// Repeated reads can be combined with a chunk cache to model repeated-access workloads (e.g. viewers).
+    #[arg(long, default_value_t = 1)]
+    pub repeat: usize,
+
+    /// Decoded chunk cache options.
+    ///
+    /// The chunk cache is not used if --read-all or --inner-chunks is set.
+    #[command(flatten)]
+    pub cache: ChunkCacheArgs,
+}
+
+/// The hit rate of a cache.
+#[derive(Debug, Clone)]
+pub struct CacheStats {
+    /// The name of the cache.
+    pub name: &'static str,
+    /// The number of requests.
+    pub requests: usize,
+    /// The number of cache misses.
+    pub misses: usize,
+}
+
+impl CacheStats {
+    /// Returns the hit rate of the cache (from 0 to 1), or zero if there were no requests.
+    #[must_use]
+    pub fn hit_rate(&self) -> f64 {
+        if self.requests > 0 {
+            self.requests.saturating_sub(self.misses) as f64 / self.requests as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} requests, {} misses ({:.2}% hit rate)",
+            self.name,
+            self.requests,
+            self.misses,
+            100.0 * self.hit_rate()
+        )
+    }
+}
+
+/// The distribution of per-request latency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencySummary {
+    /// The number of requests.
+    pub requests: usize,
+    /// The mean latency.
+    pub mean: Duration,
+    /// The minimum latency.
+    pub min: Duration,
+    /// The median latency.
+    pub p50: Duration,
+    /// The 90th percentile latency.
+    pub p90: Duration,
+    /// The 99th percentile latency.
+    pub p99: Duration,
+    /// The maximum latency.
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    /// Summarise `latencies`, returning [`None`] if they are empty.
+    ///
+    /// Percentiles use the nearest-rank method.
+    #[must_use]
+    pub fn new(mut latencies: Vec<Duration>) -> Option<Self> {
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort_unstable();
+        let requests = latencies.len();
+        let percentile = |p: f64| {
+            let rank = (p * requests as f64).ceil() as usize;
+            latencies[rank.clamp(1, requests) - 1]
+        };
+        Some(Self {
+            requests,
+            mean: latencies.iter().sum::<Duration>() / u32::try_from(requests).unwrap_or(u32::MAX),
+            min: latencies[0],
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: latencies[requests - 1],
+        })
+    }
+}
+
+impl Display for LatencySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1e3;
+        write!(
+            f,
+            "Request latency: {} requests, mean {:.2}ms, min {:.2}ms, p50 {:.2}ms, p90 {:.2}ms, p99 {:.2}ms, max {:.2}ms",
+            self.requests,
+            ms(self.mean),
+            ms(self.min),
+            ms(self.p50),
+            ms(self.p90),
+            ms(self.p99),
+            ms(self.max),
+        )
+    }
+}
+
+/// The result of a read benchmark.
+#[derive(Debug, Clone)]
+pub struct BenchmarkReadReport {
+    /// The number of decoded bytes.
+    pub bytes_decoded: usize,
+    /// The duration of the benchmark.
+    pub duration: Duration,
+    /// The distribution of per-request latency.
+    pub latency: Option<LatencySummary>,
+    /// The hit rates of the chunk cache or shard index cache, if used.
+    pub caches: Vec<CacheStats>,
+}
+
+impl BenchmarkReadReport {
+    /// Print the report of a benchmark of the array at `path`.
+    pub fn print(&self, path: &str) {
+        let duration = self.duration.as_secs_f32();
+        println!(
+            "Decoded {} in {:.2}ms ({:.2}MB decoded @ {:.2}GB/s)",
+            path,
+            duration * 1e3,
+            self.bytes_decoded as f32 / 1e6,
+            (/* GB */self.bytes_decoded as f32 * 1e-9) / duration,
+        );
+        if let Some(latency) = &self.latency {
+            println!("{latency}");
+        }
+        for cache in &self.caches {
+            println!("{cache}");
+        }
+    }
+}
+
+/// The requests of a read benchmark and their concurrency.
+struct ReadPlan {
+    /// The chunks (or inner chunks) to read.
+    chunks: ArraySubset,
+    concurrent_limit: usize,
+    codec_options: CodecOptions,
+}
+
+impl ReadPlan {
+    fn new<TStorage: ?Sized>(
+        array: &Array<TStorage>,
+        args: &BenchmarkReadArgs,
+    ) -> anyhow::Result<Self> {
+        let concurrent_target = crate::concurrent_target();
+        if args.inner_chunks {
+            let Some(inner_chunk_grid_shape) = array.inner_chunk_grid_shape() else {
+                anyhow::bail!("--inner-chunks requires a sharded array");
+            };
+            // Each request decodes a single inner chunk, so codec concurrency is only useful if there are few concurrent requests
+            let chunks = ArraySubset::new_with_shape(inner_chunk_grid_shape);
+            let concurrent_limit = args
+                .concurrent_chunks
+                .unwrap_or(concurrent_target)
+                .clamp(1, chunks.num_elements_usize().max(1));
+            Ok(Self {
+                chunks,
+                concurrent_limit,
+                codec_options: CodecOptionsBuilder::new()
+                    .concurrent_target((concurrent_target / concurrent_limit).max(1))
+                    .build(),
+            })
+        } else {
+            let chunks = ArraySubset::new_with_shape(array.chunk_grid_shape().unwrap());
+            let chunk_representation =
+                array.chunk_array_representation(&vec![0; array.dimensionality()])?;
+            let (concurrent_limit, codec_concurrent_target) = calculate_chunk_and_codec_concurrency(
+                concurrent_target,
+                args.concurrent_chunks,
+                array.codecs(),
+                chunks.num_elements_usize(),
+                &chunk_representation,
+            );
+            Ok(Self {
+                chunks,
+                concurrent_limit: concurrent_limit.max(1),
+                codec_options: CodecOptionsBuilder::new()
+                    .concurrent_target(codec_concurrent_target)
+                    .build(),
+            })
+        }
+    }
+}
+
+/// Benchmark reading `array` with the sync API.
+///
+/// # Errors
+/// Returns an error if `--inner-chunks` is set and the array is not sharded, or a read fails.
+pub fn benchmark_read<TStorage: ?Sized + ReadableStorageTraits + 'static>(
+    array: &Array<TStorage>,
+    args: &BenchmarkReadArgs,
+) -> anyhow::Result<BenchmarkReadReport> {
+    let latencies = Mutex::new(Vec::new());
+    let timed = |f: &dyn Fn() -> anyhow::Result<usize>| {
+        let start = Instant::now();
+        let size = f()?;
+        latencies.lock().unwrap().push(start.elapsed());
+        Ok::<_, anyhow::Error>(size)
+    };
+
+    let start = Instant::now();
+    let bytes_decoded = Mutex::new(0);
+    let mut caches = vec![];
+    if args.read_all {
+        for _ in 0..args.repeat {
+            *bytes_decoded.lock().unwrap() +=
+                timed(&|| Ok(array.retrieve_array_subset(&array.subset_all())?.size()))?;
+        }
+    } else {
+        let plan = ReadPlan::new(array, args)?;
+        let cache = if args.inner_chunks {
+            None
+        } else {
+            Cache::new(args.cache.cache_size())
+        };
+        let shard_index_cache = ArrayShardedReadableExtCache::new(array);
+        // NOTE: Could init memory per split with for_each_init and then reuse it with retrieve_chunk_into_array_view_opt.
+        //       But that might be cheating against tensorstore.
+        for _ in 0..args.repeat {
+            let indices = plan.chunks.indices();
+            rayon_iter_concurrent_limit::iter_concurrent_limit!(
+                plan.concurrent_limit,
+                indices,
+                try_for_each,
+                |chunk_indices: Vec<u64>| {
+                    let size = timed(&|| {
+                        Ok(if args.inner_chunks {
+                            array
+                                .retrieve_inner_chunk_opt(
+                                    &shard_index_cache,
+                                    &chunk_indices,
+                                    &plan.codec_options,
+                                )?
+                                .size()
+                        } else if let Some(cache) = &cache {
+                            cache
+                                .retrieve_chunk_opt(array, &chunk_indices, &plan.codec_options)?
+                                .size()
+                        } else {
+                            array
+                                .retrieve_chunk_opt(&chunk_indices, &plan.codec_options)?
+                                .size()
+                        })
+                    })?;
+                    *bytes_decoded.lock().unwrap() += size;
+                    Ok::<_, anyhow::Error>(())
+                }
+            )?;
+        }
+        if args.inner_chunks {
+            caches.push(shard_index_cache_stats(
+                plan.chunks.num_elements_usize() * args.repeat,
+                shard_index_cache.len(),
+            ));
+        } else if let Some(cache) = &cache {
+            caches.push(chunk_cache_stats(cache));
+        }
+    }
+
+    Ok(BenchmarkReadReport {
+        bytes_decoded: bytes_decoded.into_inner().unwrap(),
+        duration: start.elapsed(),
+        latency: LatencySummary::new(latencies.into_inner().unwrap()),
+        caches,
+    })
+}
+
+/// Benchmark reading `array` with the async API.
+///
+/// Each chunk (or inner chunk) request is spawned as a [`tokio`] task.
+///
+/// # Errors
+/// Returns an error if `--inner-chunks` is set and the array is not sharded, or a read fails.
+#[cfg(feature = "async")]
+pub async fn async_benchmark_read<
+    TStorage: ?Sized + zarrs::storage::AsyncReadableStorageTraits + 'static,
+>(
+    array: std::sync::Arc<Array<TStorage>>,
+    args: &BenchmarkReadArgs,
+) -> anyhow::Result<BenchmarkReadReport> {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+
+    let start = Instant::now();
+    let mut bytes_decoded = 0;
+    let mut latencies = vec![];
+    let mut caches = vec![];
+    if args.read_all {
+        for _ in 0..args.repeat {
+            let request_start = Instant::now();
+            bytes_decoded += array
+                .async_retrieve_array_subset(&array.subset_all())
+                .await?
+                .size();
+            latencies.push(request_start.elapsed());
+        }
+    } else {
+        let plan = ReadPlan::new(&*array, args)?;
+        let cache = if args.inner_chunks {
+            None
+        } else {
+            Cache::new(args.cache.cache_size()).map(Arc::new)
+        };
+        // The async API has no shard index cache, so an inner chunk is read as an array subset
+        let inner_chunk_shape = args
+            .inner_chunks
+            .then(|| array.inner_chunk_shape())
+            .flatten()
+            .map(|inner_chunk_shape| inner_chunk_shape.to_array_shape());
+        let chunk_indices = plan.chunks.indices().into_iter().collect::<Vec<_>>();
+        let futures = std::iter::repeat(chunk_indices)
+            .take(args.repeat)
+            .flatten()
+            .map(|chunk_indices| {
+                let array = array.clone();
+                let cache = cache.clone();
+                let inner_chunk_shape = inner_chunk_shape.clone();
+                let codec_options = plan.codec_options.clone();
+                async move {
+                    let request_start = Instant::now();
+                    let size = if let Some(inner_chunk_shape) = inner_chunk_shape {
+                        let inner_chunk_subset = ArraySubset::new_with_start_shape(
+                            std::iter::zip(&chunk_indices, &inner_chunk_shape)
+                                .map(|(index, size)| index * size)
+                                .collect(),
+                            inner_chunk_shape,
+                        )?
+                        .bound(array.shape())?;
+                        array
+                            .async_retrieve_array_subset_opt(&inner_chunk_subset, &codec_options)
+                            .await?
+                            .size()
+                    } else if let Some(cache) = cache {
+                        cache
+                            .async_retrieve_chunk_opt(&array, &chunk_indices, &codec_options)
+                            .await?
+                            .size()
+                    } else {
+                        array
+                            .async_retrieve_chunk_opt(&chunk_indices, &codec_options)
+                            .await?
+                            .size()
+                    };
+                    Ok::<_, anyhow::Error>((size, request_start.elapsed()))
+                }
+            })
+            .map(tokio::task::spawn);
+        let mut stream = futures::stream::iter(futures).buffer_unordered(plan.concurrent_limit);
+        while let Some(item) = stream.next().await {
+            let (size, latency) = item??;
+            bytes_decoded += size;
+            latencies.push(latency);
+        }
+        if let Some(cache) = &cache {
+            caches.push(chunk_cache_stats(cache));
+        }
+    }
+
+    Ok(BenchmarkReadReport {
+        bytes_decoded,
+        duration: start.elapsed(),
+        latency: LatencySummary::new(latencies),
+        caches,
+    })
+}
+
+fn chunk_cache_stats(cache: &Cache) -> CacheStats {
+    let (requests, misses) = cache.requests_misses();
+    CacheStats {
+        name: "Chunk cache",
+        requests,
+        misses,
+    }
+}
+
+/// Every shard index is retrieved once and never evicted, so the misses are the number of cached shard indexes.
+fn shard_index_cache_stats(requests: usize, cached_shard_indexes: usize) -> CacheStats {
+    CacheStats {
+        name: "Shard index cache",
+        requests,
+        misses: cached_shard_indexes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_summary() {
+        assert_eq!(LatencySummary::new(vec![]), None);
+        let latencies = (1..=100).rev().map(Duration::from_millis).collect();
+        let summary = LatencySummary::new(latencies).unwrap();
+        assert_eq!(summary.requests, 100);
+        assert_eq!(summary.mean, Duration::from_micros(50500));
+        assert_eq!(summary.min, Duration::from_millis(1));
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p90, Duration::from_millis(90));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn cache_stats() {
+        let stats = CacheStats {
+            name: "Chunk cache",
+            requests: 8,
+            misses: 2,
+        };
+        assert_eq!(stats.hit_rate(), 0.75);
+        assert_eq!(
+            stats.to_string(),
+            "Chunk cache: 8 requests, 2 misses (75.00% hit rate)"
+        );
+    }
+}
@@ -0,0 +1,437 @@
+//! A matrix of read benchmarks described by a TOML file.
+//!
+//! A [`BenchmarkSuite`] expands its datasets, read patterns, concurrency, and cache settings into [`BenchmarkCase`]s.
+//! Each case is run with [`benchmark_read`], and the results are consolidated as CSV or JSON.
+//!
+//! ```toml
+//! runs = 3
+//! repeat = 2
+//! drop-page-cache-command = ["sudo", "sh", "-c", "sync; echo 3 > /proc/sys/vm/drop_caches"]
+//!
+//! [[datasets]]
+//! name = "zstd"
+//! path = "data/zstd.zarr"
+//!
+//! [[datasets]]
+//! name = "sharded"
+//! path = "data/sharded.zarr"
+//!
+//! [matrix]
+//! patterns = ["all", "chunks", "inner-chunks"]
+//! concurrent-chunks = [1, 8, 32]
+//! page-cache = ["cold", "warm"]
+//! chunk-cache-size = [0, 1000000000]
+//! ```
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use zarrs::array::Array;
+
+use crate::{
+    benchmark::{benchmark_read, BenchmarkReadArgs},
+    filesystem_store, ChunkCacheArgs,
+};
+
+/// A dataset (a Zarr array on the filesystem) of a benchmark suite.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct BenchmarkDataset {
+    /// The dataset name, used to identify results.
+    pub name: String,
+    /// The path of the Zarr array.
+    pub path: PathBuf,
+}
+
+/// The read pattern of a benchmark.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadPattern {
+    /// Read the entire array in one operation.
+    All,
+    /// Read every chunk (or shard).
+    Chunks,
+    /// Read every inner chunk of a sharded array.
+    InnerChunks,
+}
+
+impl ReadPattern {
+    /// Returns the name of the read pattern.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Chunks => "chunks",
+            Self::InnerChunks => "inner-chunks",
+        }
+    }
+}
+
+/// The state of the page cache at the start of each run of a benchmark.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PageCache {
+    /// The page cache is dropped before each run.
+    Cold,
+    /// The array is read once before the runs, so they are served from the page cache.
+    Warm,
+    /// The page cache is bypassed with direct I/O.
+    Direct,
+}
+
+impl PageCache {
+    /// Returns the name of the page cache state.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cold => "cold",
+            Self::Warm => "warm",
+            Self::Direct => "direct",
+        }
+    }
+}
+
+fn default_patterns() -> Vec<ReadPattern> {
+    vec![ReadPattern::Chunks]
+}
+
+fn default_page_cache() -> Vec<PageCache> {
+    vec![PageCache::Warm]
+}
+
+fn default_chunk_cache_size() -> Vec<u64> {
+    vec![0]
+}
+
+/// The benchmark settings, each combination of which is run for every dataset.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BenchmarkMatrix {
+    /// The read patterns. Defaults to `["chunks"]`.
+    #[serde(default = "default_patterns")]
+    pub patterns: Vec<ReadPattern>,
+    /// The numbers of concurrent chunks. Defaults to the default concurrency of [`benchmark_read`].
+    #[serde(default)]
+    pub concurrent_chunks: Vec<usize>,
+    /// The page cache states. Defaults to `["warm"]`.
+    #[serde(default = "default_page_cache")]
+    pub page_cache: Vec<PageCache>,
+    /// The decoded chunk cache sizes (in bytes), zero disables the chunk cache. Defaults to `[0]`.
+    #[serde(default = "default_chunk_cache_size")]
+    pub chunk_cache_size: Vec<u64>,
+}
+
+impl Default for BenchmarkMatrix {
+    fn default() -> Self {
+        Self {
+            patterns: default_patterns(),
+            concurrent_chunks: vec![],
+            page_cache: default_page_cache(),
+            chunk_cache_size: default_chunk_cache_size(),
+        }
+    }
+}
+
+fn default_one() -> usize {
+    1
+}
+
+/// A matrix of read benchmarks.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BenchmarkSuite {
+    /// The number of measured runs of each case. Defaults to 1.
+    #[serde(default = "default_one")]
+    pub runs: usize,
+    /// The number of times every chunk is read in a run (see [`BenchmarkReadArgs::repeat`]). Defaults to 1.
+    #[serde(default = "default_one")]
+    pub repeat: usize,
+    /// Ignore checksums.
+    #[serde(default)]
+    pub ignore_checksums: bool,
+    /// A command that drops the page cache (e.g. with `sudo`).
+    ///
+    /// Defaults to syncing and writing to `/proc/sys/vm/drop_caches` on Linux, which requires root.
+    #[serde(default)]
+    pub drop_page_cache_command: Option<Vec<String>>,
+    /// The datasets.
+    pub datasets: Vec<BenchmarkDataset>,
+    /// The benchmark settings.
+    #[serde(default)]
+    pub matrix: BenchmarkMatrix,
+}
+
+/// A benchmark of a dataset with a combination of settings of a [`BenchmarkMatrix`].
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BenchmarkCase {
+    /// The dataset.
+    #[serde(flatten)]
+    pub dataset: BenchmarkDataset,
+    /// The read pattern.
+    pub pattern: ReadPattern,
+    /// The number of concurrent chunks, [`None`] for the default.
+    pub concurrent_chunks: Option<usize>,
+    /// The page cache state.
+    pub page_cache: PageCache,
+    /// The decoded chunk cache size (in bytes), zero if disabled.
+    pub chunk_cache_size: u64,
+}
+
+/// The result of a run of a [`BenchmarkCase`].
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchmarkSuiteResult {
+    /// The benchmark case.
+    #[serde(flatten)]
+    pub case: BenchmarkCase,
+    /// The run index.
+    pub run: usize,
+    /// The number of decoded bytes.
+    pub bytes_decoded: usize,
+    /// The duration (in milliseconds).
+    pub duration_ms: f64,
+    /// The decoded throughput (in GB/s).
+    pub throughput_gbs: f64,
+    /// The number of requests.
+    pub requests: usize,
+    /// The mean request latency (in milliseconds).
+    pub latency_mean_ms: Option<f64>,
+    /// The median request latency (in milliseconds).
+    pub latency_p50_ms: Option<f64>,
+    /// The 99th percentile request latency (in milliseconds).
+    pub latency_p99_ms: Option<f64>,
+    /// The hit rate of the chunk cache (or shard index cache), if used.
+    pub cache_hit_rate: Option<f64>,
+}
+
+impl BenchmarkSuite {
+    /// Parse a benchmark suite from TOML `text`.
+    ///
+    /// # Errors
+    /// Returns an error if the TOML is invalid or has unknown fields.
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Returns the benchmark cases, in dataset order and then the order of the settings of the matrix.
+    #[must_use]
+    pub fn cases(&self) -> Vec<BenchmarkCase> {
+        let concurrent_chunks = if self.matrix.concurrent_chunks.is_empty() {
+            vec![None]
+        } else {
+            self.matrix
+                .concurrent_chunks
+                .iter()
+                .copied()
+                .map(Some)
+                .collect()
+        };
+        itertools::iproduct!(
+            &self.datasets,
+            &self.matrix.patterns,
+            &concurrent_chunks,
+            &self.matrix.page_cache,
+            &self.matrix.chunk_cache_size
+        )
+        .map(
+            |(dataset, pattern, concurrent_chunks, page_cache, chunk_cache_size)| BenchmarkCase {
+                dataset: dataset.clone(),
+                pattern: *pattern,
+                concurrent_chunks: *concurrent_chunks,
+                page_cache: *page_cache,
+                chunk_cache_size: *chunk_cache_size,
+            },
+        )
+        .collect()
+    }
+
+    /// Drop the page cache, with the `drop_page_cache_command` if set.
+    ///
+    /// # Errors
+    /// Returns an error if the page cache cannot be dropped (e.g. insufficient permissions or not Linux).
+    pub fn drop_page_cache(&self) -> anyhow::Result<()> {
+        if let Some(command) = &self.drop_page_cache_command {
+            let Some((program, args)) = command.split_first() else {
+                anyhow::bail!("drop-page-cache-command is empty");
+            };
+            let status = std::process::Command::new(program).args(args).status()?;
+            if !status.success() {
+                anyhow::bail!("drop-page-cache-command failed with {status}");
+            }
+            Ok(())
+        } else if cfg!(target_os = "linux") {
+            std::process::Command::new("sync").status()?;
+            std::fs::write("/proc/sys/vm/drop_caches", "3").map_err(|err| {
+                anyhow::anyhow!("failed to drop the page cache ({err}), run as root or set drop-page-cache-command")
+            })
+        } else {
+            anyhow::bail!(
+                "dropping the page cache requires drop-page-cache-command on this platform"
+            )
+        }
+    }
+
+    /// Returns the read benchmark arguments of `case`.
+    #[must_use]
+    pub fn read_args(&self, case: &BenchmarkCase) -> BenchmarkReadArgs {
+        BenchmarkReadArgs {
+            concurrent_chunks: case.concurrent_chunks,
+            read_all: case.pattern == ReadPattern::All,
+            inner_chunks: case.pattern == ReadPattern::InnerChunks,
+            ignore_checksums: self.ignore_checksums,
+            repeat: self.repeat,
+            cache: ChunkCacheArgs {
+                cache_size: (case.chunk_cache_size > 0).then_some(case.chunk_cache_size),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Run the measured runs of `case`.
+    ///
+    /// With [`PageCache::Warm`], the array is read once before the runs.
+    /// With [`PageCache::Cold`], the page cache is dropped before each run.
+    ///
+    /// # Errors
+    /// Returns an error if the dataset cannot be opened or read, or the page cache cannot be dropped.
+    pub fn run_case(&self, case: &BenchmarkCase) -> anyhow::Result<Vec<BenchmarkSuiteResult>> {
+        let storage = Arc::new(filesystem_store(
+            &case.dataset.path,
+            case.page_cache == PageCache::Direct,
+        )?);
+        let array = Array::open(storage, "/")?;
+        let args = self.read_args(case);
+        if case.page_cache == PageCache::Warm {
+            benchmark_read(&array, &args)?;
+        }
+        let ms = |duration: Duration| duration.as_secs_f64() * 1e3;
+        (0..self.runs)
+            .map(|run| {
+                if case.page_cache == PageCache::Cold {
+                    self.drop_page_cache()?;
+                }
+                let report = benchmark_read(&array, &args)?;
+                Ok(BenchmarkSuiteResult {
+                    case: case.clone(),
+                    run,
+                    bytes_decoded: report.bytes_decoded,
+                    duration_ms: ms(report.duration),
+                    throughput_gbs: report.bytes_decoded as f64 * 1e-9
+                        / report.duration.as_secs_f64(),
+                    requests: report
+                        .latency
+                        .as_ref()
+                        .map_or(0, |latency| latency.requests),
+                    latency_mean_ms: report.latency.as_ref().map(|latency| ms(latency.mean)),
+                    latency_p50_ms: report.latency.as_ref().map(|latency| ms(latency.p50)),
+                    latency_p99_ms: report.latency.as_ref().map(|latency| ms(latency.p99)),
+                    cache_hit_rate: report.caches.first().map(|cache| cache.hit_rate()),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns `results` as CSV, with a header row.
+///
+/// Unset values (e.g. the default concurrency) are empty.
+#[must_use]
+pub fn results_to_csv(results: &[BenchmarkSuiteResult]) -> String {
+    let optional = |value: Option<f64>| value.map_or_else(String::new, |value| value.to_string());
+    let mut csv = "dataset,path,pattern,concurrent_chunks,page_cache,chunk_cache_size,run,bytes_decoded,duration_ms,throughput_gbs,requests,latency_mean_ms,latency_p50_ms,latency_p99_ms,cache_hit_rate\n".to_string();
+    for result in results {
+        let row = [
+            result.case.dataset.name.clone(),
+            result.case.dataset.path.display().to_string(),
+            result.case.pattern.name().to_string(),
+            result
+                .case
+                .concurrent_chunks
+                .map_or_else(String::new, |concurrent_chunks| {
+                    concurrent_chunks.to_string()
+                }),
+            result.case.page_cache.name().to_string(),
+            result.case.chunk_cache_size.to_string(),
+            result.run.to_string(),
+            result.bytes_decoded.to_string(),
+            result.duration_ms.to_string(),
+            result.throughput_gbs.to_string(),
+            result.requests.to_string(),
+            optional(result.latency_mean_ms),
+            optional(result.latency_p50_ms),
+            optional(result.latency_p99_ms),
+            optional(result.cache_hit_rate),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::{
+        array::{ArrayBuilder, DataType, FillValue},
+        filesystem::FilesystemStore,
+    };
+
+    use super::*;
+
+    #[test]
+    fn benchmark_suite() -> Result<(), Box<dyn std::error::Error>> {
+        let path = tempfile::TempDir::new()?;
+        let array = ArrayBuilder::new(
+            vec![8, 8],
+            DataType::UInt16,
+            vec![4, 4].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(Arc::new(FilesystemStore::new(path.path())?), "/")?;
+        array.store_metadata()?;
+        array.store_array_subset_elements::<u16>(&array.subset_all(), &[1; 64])?;
+
+        let suite = BenchmarkSuite::from_toml(&format!(
+            r#"
+            runs = 2
+            repeat = 2
+
+            [[datasets]]
+            name = "array"
+            path = "{}"
+
+            [matrix]
+            patterns = ["all", "chunks"]
+            concurrent-chunks = [1, 4]
+            chunk-cache-size = [0, 1000000]
+            "#,
+            path.path().display()
+        ))?;
+        assert!(
+            BenchmarkSuite::from_toml("[[datasets]]\nname = \"a\"\npath = \"a\"\nunknown = 1")
+                .is_err()
+        );
+
+        let cases = suite.cases();
+        assert_eq!(cases.len(), 8);
+        assert_eq!(cases[0].pattern, ReadPattern::All);
+        assert_eq!(cases[0].concurrent_chunks, Some(1));
+        assert_eq!(cases[0].page_cache, PageCache::Warm);
+        assert_eq!(cases[1].chunk_cache_size, 1000000);
+        assert!(suite.read_args(&cases[0]).read_all);
+        assert_eq!(suite.read_args(&cases[7]).cache.cache_size, Some(1000000));
+
+        let results = suite.run_case(&cases[7])?;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].run, 1);
+        assert_eq!(results[1].bytes_decoded, 2 * 64 * 2);
+        assert_eq!(results[1].requests, 2 * 4);
+        // The second read of every chunk is a cache hit
+        assert_eq!(results[1].cache_hit_rate, Some(0.5));
+
+        let csv = results_to_csv(&results);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("array,"));
+        assert!(lines[1].contains(",chunks,4,warm,1000000,0,256,"));
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use std::ffi::OsString;
+use std::sync::Arc;
+
+use clap::Parser;
+use zarrs::storage::AsyncReadableStorage;
+use zarrs_tools::{
+    benchmark::{async_benchmark_read, BenchmarkReadArgs},
+    ThreadsArgs,
+};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum Backend {
+    /// The opendal filesystem (or HTTP) store.
+    Opendal,
+    /// An io_uring filesystem store (Linux only). Requires the `io_uring` feature.
+    IoUring,
+}
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_benchmark_read_async";
+
+/// Benchmark zarrs read throughput with the async API.
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Args {
+    /// The zarr array directory.
+    path: String,
+
+    /// The storage backend of a local zarr array.
+    #[arg(long, value_enum, default_value_t = Backend::Opendal)]
+    backend: Backend,
+
+    /// Read benchmark options.
+    #[command(flatten)]
+    read: BenchmarkReadArgs,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+pub fn main(args: Vec<OsString>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = zarrs_tools::config::parse_from::<Args>(NAME, args);
+    args.threads
+        .clone()
+        .install(|| tokio::runtime::Runtime::new()?.block_on(run(args)))
+}
+
+async fn run(args: Args) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    zarrs::config::global_config_mut().set_validate_checksums(!args.read.ignore_checksums);
+
+    let storage: AsyncReadableStorage = if let Backend::IoUring = args.backend {
+        get_io_uring_storage(&args.path)?
+    } else if args.path.starts_with("http") {
+        // opendal
+        let builder = opendal::services::Http::default().endpoint(&args.path);
+        let operator = opendal::Operator::new(builder)?.finish();
+        Arc::new(zarrs_opendal::AsyncOpendalStore::new(operator))
+
+        // object_store
+        // let options = object_store::ClientOptions::new().with_allow_http(true);
+        // let store = object_store::http::HttpBuilder::new()
+        //     .with_url(&args.path)
+        //     .with_client_options(options)
+        //     .build()?;
+        // Arc::new(store::AsyncObjectStore::new(store))
+    } else {
+        // opendal
+        let builder = opendal::services::Fs::default().root(&args.path);
+        let operator = opendal::Operator::new(builder)?.finish();
+        Arc::new(zarrs_opendal::AsyncOpendalStore::new(operator))
+
+        // object_store
+        // let store = object_store::local::LocalFileSystem::new_with_prefix(&args.path)?;
+        // Arc::new(store::AsyncObjectStore::new(store))
+    };
+
+    let array = Arc::new(zarrs::array::Array::async_open(storage.clone(), "/").await?);
+    // println!("{:#?}", array.metadata());
+
+    let report = async_benchmark_read(array, &args.read).await?;
+    report.print(&args.path);
+    Ok(())
+}
+
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+fn get_io_uring_storage(
+    path: &str,
+) -> Result<AsyncReadableStorage, Box<dyn std::error::Error + Send + Sync>> {
+    if path.starts_with("http") {
+        return Err("the io_uring backend does not support URLs".into());
+    }
+    Ok(Arc::new(zarrs_tools::io_uring_store::IoUringStore::new(
+        path,
+    )?))
+}
+
+#[cfg(not(all(feature = "io_uring", target_os = "linux")))]
+fn get_io_uring_storage(
+    _path: &str,
+) -> Result<AsyncReadableStorage, Box<dyn std::error::Error + Send + Sync>> {
+    Err("the io_uring backend requires the io_uring feature on Linux".into())
+}
@@ -0,0 +1,82 @@
+use std::ffi::OsString;
+use std::sync::Arc;
+
+use clap::Parser;
+use zarrs::storage::{
+    storage_adapter::async_to_sync::{AsyncToSyncBlockOn, AsyncToSyncStorageAdapter},
+    AsyncReadableStorage, ReadableStorage,
+};
+use zarrs_tools::{
+    benchmark::{benchmark_read, BenchmarkReadArgs},
+    ThreadsArgs,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_benchmark_read_async_as_sync";
+
+/// Benchmark zarrs read throughput with the sync API.
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Args {
+    /// The path or URL of a zarr array.
+    path: String,
+
+    /// Read benchmark options.
+    #[command(flatten)]
+    read: BenchmarkReadArgs,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+struct TokioBlockOn(tokio::runtime::Runtime);
+
+impl AsyncToSyncBlockOn for TokioBlockOn {
+    fn block_on<F: core::future::Future>(&self, future: F) -> F::Output {
+        self.0.block_on(future)
+    }
+}
+
+pub fn main(args: Vec<OsString>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = zarrs_tools::config::parse_from::<Args>(NAME, args);
+    args.threads.clone().install(|| run(args))
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let storage: AsyncReadableStorage = if args.path.starts_with("http") {
+        // opendal
+        let builder = opendal::services::Http::default().endpoint(&args.path);
+        let operator = opendal::Operator::new(builder)?.finish();
+        Arc::new(zarrs_opendal::AsyncOpendalStore::new(operator))
+
+        // object_store
+        // let options = object_store::ClientOptions::new().with_allow_http(true);
+        // let store = object_store::http::HttpBuilder::new()
+        //     .with_url(&args.path)
+        //     .with_client_options(options)
+        //     .build()?;
+        // Arc::new(store::AsyncObjectStore::new(store))
+    } else {
+        // opendal
+        let builder = opendal::services::Fs::default().root(&args.path);
+        let operator = opendal::Operator::new(builder)?.finish();
+        Arc::new(zarrs_opendal::AsyncOpendalStore::new(operator))
+
+        // object_store
+        // let store = object_store::local::LocalFileSystem::new_with_prefix(&args.path)?;
+        // Arc::new(store::AsyncObjectStore::new(store))
+    };
+
+    let block_on = TokioBlockOn(tokio::runtime::Runtime::new()?);
+    let storage: ReadableStorage = Arc::new(AsyncToSyncStorageAdapter::new(storage, block_on));
+
+    let array = zarrs::array::Array::open(storage.clone(), "/")?;
+    // println!("{:#?}", array.metadata());
+
+    zarrs::config::global_config_mut().set_validate_checksums(!args.read.ignore_checksums);
+
+    let report = benchmark_read(&array, &args.read)?;
+    report.print(&args.path);
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use std::{
+    ffi::OsString,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use clap::Parser;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use zarrs::{
+    array::{codec::CodecOptionsBuilder, ArrayShardedExt},
+    array_subset::ArraySubset,
+    storage::ReadableStorage,
+};
+use zarrs_tools::{
+    benchmark::{benchmark_read, BenchmarkReadArgs},
+    calculate_chunk_and_codec_concurrency, filesystem_store, DirectIo, ThreadsArgs,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_benchmark_read_sync";
+
+/// Benchmark zarrs read throughput with the sync API.
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Args {
+    /// The path or URL of a zarr array.
+    path: String,
+
+    /// Read benchmark options.
+    #[command(flatten)]
+    read: BenchmarkReadArgs,
+
+    /// Sweep a list of read shapes and report the throughput of each.
+    ///
+    /// The array is read in subsets of each shape, with up to --concurrent-chunks subsets read concurrently.
+    /// A read shape is a comma separated list of sizes, `chunk` (the chunk or shard shape), or `inner_chunk` (the inner chunk shape if sharded).
+    /// A `@OFFSET` suffix offsets the subsets along every axis to measure misaligned access.
+    /// If set, --read-all and the chunk cache are ignored.
+    ///
+    /// Examples:
+    ///   --sweep 64,64,64 128,128,128 chunk inner_chunk
+    ///   --sweep chunk chunk@16
+    #[arg(long, verbatim_doc_comment, num_args = 1.., value_parser = parse_read_shape)]
+    sweep: Vec<ReadShape>,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=both`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "both", default_value_t = DirectIo::Off)]
+    direct_io: DirectIo,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+/// The shape of subsets read in a sweep.
+#[derive(Debug, Clone)]
+enum ReadShapeKind {
+    Shape(Vec<u64>),
+    Chunk,
+    InnerChunk,
+}
+
+/// A read shape of a sweep, with an offset along every axis.
+#[derive(Debug, Clone)]
+struct ReadShape {
+    kind: ReadShapeKind,
+    offset: u64,
+}
+
+fn parse_read_shape(read_shape: &str) -> Result<ReadShape, String> {
+    let (kind, offset) = match read_shape.split_once('@') {
+        Some((kind, offset)) => (
+            kind,
+            offset
+                .parse()
+                .map_err(|_| format!("invalid offset {offset}"))?,
+        ),
+        None => (read_shape, 0),
+    };
+    let kind = match kind {
+        "chunk" => ReadShapeKind::Chunk,
+        "inner_chunk" => ReadShapeKind::InnerChunk,
+        shape => ReadShapeKind::Shape(
+            shape
+                .split(',')
+                .map(|size| size.trim().parse::<u64>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("invalid read shape {shape}"))?,
+        ),
+    };
+    Ok(ReadShape { kind, offset })
+}
+
+/// Read `array` in subsets of `read_shape`, returning the number of subsets and decoded bytes.
+fn read_subsets(
+    array: &zarrs::array::Array<dyn zarrs::storage::ReadableStorageTraits>,
+    read_shape: &[u64],
+    offset: u64,
+    concurrent_subsets: Option<usize>,
+) -> Result<(usize, usize), Box<dyn std::error::Error + Send + Sync>> {
+    // The subsets tile the array from the offset, the first subset along each axis is truncated
+    let subsets_shape: Vec<u64> = std::iter::zip(array.shape(), read_shape)
+        .map(|(&size, &read_size)| (size + offset % read_size).div_ceil(read_size))
+        .collect();
+    let subsets = ArraySubset::new_with_shape(subsets_shape);
+    let n_subsets = subsets.num_elements_usize();
+
+    let concurrent_target = zarrs_tools::concurrent_target();
+    let (subsets_concurrent_limit, codec_concurrent_target) = calculate_chunk_and_codec_concurrency(
+        concurrent_target,
+        concurrent_subsets,
+        array.codecs(),
+        n_subsets,
+        &array.chunk_array_representation(&vec![0; array.dimensionality()])?,
+    );
+    let codec_options = CodecOptionsBuilder::new()
+        .concurrent_target(codec_concurrent_target)
+        .build();
+
+    let bytes_decoded = Mutex::new(0);
+    let indices = subsets.indices();
+    rayon_iter_concurrent_limit::iter_concurrent_limit!(
+        subsets_concurrent_limit,
+        indices,
+        try_for_each,
+        |subset_indices: Vec<u64>| {
+            let ranges: Vec<_> = itertools::izip!(&subset_indices, read_shape, array.shape())
+                .map(|(&index, &read_size, &size)| {
+                    let start = (index * read_size).saturating_sub(offset % read_size);
+                    let end = std::cmp::min((index + 1) * read_size - offset % read_size, size);
+                    start..end
+                })
+                .collect();
+            let subset = ArraySubset::new_with_ranges(&ranges);
+            let size = array
+                .retrieve_array_subset_opt(&subset, &codec_options)?
+                .size();
+            *bytes_decoded.lock().unwrap() += size;
+            Ok::<_, zarrs::array::ArrayError>(())
+        }
+    )?;
+    Ok((n_subsets, bytes_decoded.into_inner()?))
+}
+
+pub fn main(args: Vec<OsString>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = zarrs_tools::config::parse_from::<Args>(NAME, args);
+    args.threads.clone().install(|| run(args))
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // opendal
+    // let mut builder = opendal::services::Fs::default();
+    // builder.root(&args.path);
+    // let operator = opendal::Operator::new(builder)?.finish().blocking();
+    // let storage: ReadableStorage = Arc::new(store::OpendalStore::new(operator));
+
+    // Default filesystem store
+    let storage: ReadableStorage =
+        Arc::new(filesystem_store(args.path.clone(), args.direct_io.read())?);
+
+    let array = zarrs::array::Array::open(storage.clone(), "/")?;
+    // println!("{:#?}", array.metadata());
+
+    zarrs::config::global_config_mut().set_validate_checksums(!args.read.ignore_checksums);
+
+    if !args.sweep.is_empty() {
+        let chunk_shape = array
+            .chunk_shape(&vec![0; array.dimensionality()])?
+            .to_array_shape();
+        println!(
+            "{:<24} {:>10} {:>14} {:>12} {:>10}",
+            "Read shape", "Subsets", "Decoded (MB)", "Time (ms)", "GB/s"
+        );
+        for read_shape in &args.sweep {
+            let shape = match &read_shape.kind {
+                ReadShapeKind::Shape(shape) => {
+                    if shape.len() != array.dimensionality() {
+                        Err(format!(
+                            "read shape {shape:?} does not match the array dimensionality"
+                        ))?;
+                    }
+                    shape.clone()
+                }
+                ReadShapeKind::Chunk => chunk_shape.clone(),
+                ReadShapeKind::InnerChunk => array
+                    .inner_chunk_shape()
+                    .map_or_else(|| chunk_shape.clone(), |shape| shape.to_array_shape()),
+            };
+            let shape: Vec<u64> = shape.into_iter().map(|size| size.max(1)).collect();
+            let start = SystemTime::now();
+            let mut subsets = 0;
+            let mut bytes_decoded = 0;
+            for _ in 0..args.read.repeat {
+                let (n_subsets, n_bytes) = read_subsets(
+                    &array,
+                    &shape,
+                    read_shape.offset,
+                    args.read.concurrent_chunks,
+                )?;
+                subsets += n_subsets;
+                bytes_decoded += n_bytes;
+            }
+            let duration = SystemTime::now().duration_since(start)?.as_secs_f32();
+            let label = format!(
+                "{}{}",
+                shape
+                    .iter()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+                if read_shape.offset > 0 {
+                    format!("@{}", read_shape.offset)
+                } else {
+                    String::new()
+                }
+            );
+            println!(
+                "{label:<24} {subsets:>10} {:>14.2} {:>12.2} {:>10.2}",
+                bytes_decoded as f32 / 1e6,
+                duration * 1e3,
+                (bytes_decoded as f32 * 1e-9) / duration,
+            );
+        }
+        return Ok(());
+    }
+
+    let report = benchmark_read(&array, &args.read)?;
+    report.print(&args.path);
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::{ffi::OsString, path::PathBuf};
+
+use clap::Parser;
+use zarrs_tools::{
+    benchmark_suite::{results_to_csv, BenchmarkSuite},
+    ThreadsArgs,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_benchmark_suite";
+
+/// Run a matrix of zarrs read benchmarks described by a TOML file.
+///
+/// Every combination of the datasets, read patterns, concurrency, page cache states, and chunk cache sizes of the suite is benchmarked with the sync API.
+/// Each run is printed as it completes, and the results are consolidated as CSV and/or JSON.
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Args {
+    /// The path of the benchmark suite TOML file.
+    suite: PathBuf,
+
+    /// Write the results to a CSV file.
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Write the results to a JSON file.
+    #[arg(long)]
+    json: Option<PathBuf>,
+
+    /// List the benchmark cases without running them.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+pub fn main(args: Vec<OsString>) -> anyhow::Result<()> {
+    let args = zarrs_tools::config::parse_from::<Args>(NAME, args);
+    args.threads.clone().install(|| run(args))
+}
+
+fn run(args: Args) -> anyhow::Result<()> {
+    let suite = BenchmarkSuite::from_toml(&std::fs::read_to_string(&args.suite)?)?;
+    let cases = suite.cases();
+
+    println!(
+        "{:<24} {:<12} {:>10} {:<6} {:>12} {:>4} {:>14} {:>12} {:>10}",
+        "Dataset",
+        "Pattern",
+        "Concurrent",
+        "Page",
+        "Cache (MB)",
+        "Run",
+        "Decoded (MB)",
+        "Time (ms)",
+        "GB/s"
+    );
+    let mut results = vec![];
+    for case in &cases {
+        let concurrent_chunks = case.concurrent_chunks.map_or_else(
+            || "default".to_string(),
+            |concurrent| concurrent.to_string(),
+        );
+        let cache_size = case.chunk_cache_size as f32 / 1e6;
+        if args.dry_run {
+            println!(
+                "{:<24} {:<12} {concurrent_chunks:>10} {:<6} {cache_size:>12.2}",
+                case.dataset.name,
+                case.pattern.name(),
+                case.page_cache.name(),
+            );
+            continue;
+        }
+        for result in suite.run_case(case)? {
+            println!(
+                "{:<24} {:<12} {concurrent_chunks:>10} {:<6} {cache_size:>12.2} {:>4} {:>14.2} {:>12.2} {:>10.2}",
+                case.dataset.name,
+                case.pattern.name(),
+                case.page_cache.name(),
+                result.run,
+                result.bytes_decoded as f32 / 1e6,
+                result.duration_ms,
+                result.throughput_gbs,
+            );
+            results.push(result);
+        }
+    }
+
+    if args.dry_run {
+        println!("{} cases", cases.len());
+        return Ok(());
+    }
+    if let Some(csv) = &args.csv {
+        std::fs::write(csv, results_to_csv(&results))?;
+    }
+    if let Some(json) = &args.json {
+        std::fs::write(json, serde_json::to_string_pretty(&results)?)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,539 @@
+use clap::Parser;
+use indicatif::{DecimalBytes, ProgressBar, ProgressStyle};
+use std::{
+    ffi::OsString,
+    io::{Read, Seek},
+    path::PathBuf,
+    sync::{atomic::AtomicUsize, Mutex},
+};
+use zarrs_tools::{
+    binary_header::BinaryHeader, filesystem_store, get_array_builder, DirectIo, MemoryBudget,
+    Provenance, ThreadsArgs, WriteShapeArgs, ZarrEncodingArgs,
+};
+
+use zarrs::{
+    array::{
+        codec::{array_to_bytes::bytes::reverse_endianness, ArrayCodecTraits, CodecOptionsBuilder},
+        concurrency::RecommendedConcurrency,
+        Array, DimensionName, Endianness,
+    },
+    array_subset::ArraySubset,
+    config::global_config,
+    filesystem::FilesystemStore,
+    metadata::v3::array::data_type::DataTypeMetadataV3,
+    storage::ListableStorageTraits,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_binary2zarr";
+
+/// Convert an N-dimensional binary array from standard input to a Zarr V3 array.
+///
+/// The data type, array shape, endianness, and dimension names can be inferred from a detached header (see --header).
+#[derive(Parser)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+#[allow(rustdoc::bare_urls)]
+pub struct Cli {
+    /// A header describing the binary data (optional).
+    ///
+    /// Supported formats are NRRD (.nrrd, .nhdr), MetaImage (.mhd, .mha), and JSON (.json).
+    /// The data type, array shape, endianness, and dimension names are inferred from the header unless explicitly set.
+    /// Physical spacing, units, and origin are written to the array attributes.
+    ///
+    /// If the header references a data file (or the data is attached to the header), it is read instead of standard input.
+    ///
+    /// A JSON header holds any of the following fields, with axes ordered slowest varying first:
+    ///   {
+    ///     "data_type": "uint16",
+    ///     "shape": [1080, 1024, 1024],
+    ///     "endianness": "little",
+    ///     "dimension_names": ["z", "y", "x"],
+    ///     "spacing": [2.0, 0.5, 0.5],
+    ///     "units": ["micrometer", "micrometer", "micrometer"],
+    ///     "origin": [0.0, 0.0, 0.0],
+    ///     "data_file": "image.raw",
+    ///     "byte_skip": 0
+    ///   }
+    #[arg(long, verbatim_doc_comment)]
+    header: Option<PathBuf>,
+
+    /// The endianness of the binary data. If unspecified, it is assumed to match the host endianness.
+    #[arg(long, value_parser = parse_endianness)]
+    endianness: Option<Endianness>,
+
+    #[command(flatten)]
+    encoding: ZarrEncodingArgs,
+
+    /// Do not write provenance metadata (the tool, version, arguments, inputs, and timestamp) to the output attributes.
+    #[arg(long, default_value_t = false)]
+    no_provenance: bool,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=both`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "both", default_value_t = DirectIo::Off)]
+    direct_io: DirectIo,
+
+    /// Number of concurrent chunk writers.
+    #[arg(long)]
+    concurrent_chunks: Option<usize>,
+
+    /// Resume an interrupted conversion.
+    ///
+    /// If the output array exists and has a checkpoint, input data before the checkpoint is skipped and the remaining blocks are written.
+    /// The output array shape and data type must match the input.
+    /// If the output array does not exist, it is created.
+    #[arg(long, default_value_t = false)]
+    resume: bool,
+
+    /// Write shape options.
+    ///
+    /// The data is read in blocks of the write shape along the first dimension.
+    #[command(flatten)]
+    write: WriteShapeArgs,
+
+    /// Zarr data type. See https://zarr-specs.readthedocs.io/en/latest/v3/core/v3.0.html#id11
+    ///
+    /// Examples:
+    ///   bool
+    ///   int8 int16 int32 int64
+    ///   uint8 uint16 uint32 uint64
+    ///   float32 float64 float16 bfloat16
+    ///   complex64 complex128
+    ///   r8 r16 r24 r32 r64 (r* where * is a multiple of 8)
+    #[arg(short, long, verbatim_doc_comment, value_parser = parse_data_type, required_unless_present = "header")]
+    data_type: Option<DataTypeMetadataV3>,
+
+    /// Array shape. A comma separated list of the sizes of each array dimension.
+    #[arg(short, long, value_delimiter = ',', required_unless_present = "header")]
+    array_shape: Option<Vec<u64>>,
+
+    /// Dimension names. A comma separated list of the names of each array dimension.
+    #[arg(long, value_delimiter = ',')]
+    dimension_names: Option<Vec<String>>,
+
+    /// The output directory for the zarr array.
+    out: PathBuf,
+    // /// The path to a binary file or a directory of binary files.
+    // #[arg(short, long, num_args = 1..)]
+    // file: Vec<PathBuf>,
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+fn parse_data_type(data_type: &str) -> std::io::Result<DataTypeMetadataV3> {
+    serde_json::from_value(serde_json::Value::String(data_type.to_string()))
+        .map_err(|err| std::io::Error::other(err.to_string()))
+}
+
+fn parse_endianness(endianness: &str) -> std::io::Result<Endianness> {
+    if endianness == "little" {
+        Ok(Endianness::Little)
+    } else if endianness == "big" {
+        Ok(Endianness::Big)
+    } else {
+        Err(std::io::Error::other("Endianness must be little or big"))
+    }
+}
+
+/// The attribute key of the conversion checkpoint.
+///
+/// The checkpoint holds the number of elements along the first dimension that have been fully written.
+/// It is removed when the conversion completes.
+const CHECKPOINT_ATTRIBUTE: &str = "zarrs_binary2zarr_checkpoint";
+
+/// Returns the number of elements along the first dimension written by an interrupted conversion.
+///
+/// Returns [`None`] if the array has no checkpoint (i.e. the conversion completed).
+fn checkpoint_rows(array: &Array<FilesystemStore>) -> Option<u64> {
+    array
+        .attributes()
+        .get(CHECKPOINT_ATTRIBUTE)
+        .and_then(|checkpoint| checkpoint.get("rows"))
+        .and_then(serde_json::Value::as_u64)
+}
+
+/// Returns the number of elements along the first dimension of each block read from the input.
+fn block_shape_n(array: &Array<FilesystemStore>, write: &WriteShapeArgs) -> anyhow::Result<u64> {
+    let chunk_shape = array
+        .chunk_grid()
+        .chunk_shape(&vec![0; array.chunk_grid().dimensionality()], array.shape())
+        .unwrap()
+        .expect("lowest indices should have a chunk shape");
+    let write_shape = write.write_shape(array)?;
+    Ok(write_shape
+        .unwrap_or(chunk_shape.as_slice())
+        .first()
+        .unwrap()
+        .get())
+}
+
+/// Read blocks from `start_block` onwards from `reader` and write them to `array`.
+///
+/// `checkpoint` is called with the number of elements along the first dimension that have been fully written whenever it increases.
+fn reader_to_array(
+    array: &Array<FilesystemStore>,
+    reader: impl Read + Send,
+    endianness: Option<Endianness>,
+    concurrent_chunks: Option<usize>,
+    write: &WriteShapeArgs,
+    start_block: u64,
+    checkpoint: impl Fn(u64) + Sync,
+) -> anyhow::Result<usize> {
+    let data_type_size = array
+        .data_type()
+        .fixed_size()
+        .expect("data type should be fixed size");
+    let dimensionality = array.chunk_grid().dimensionality();
+    let array_shape = array.shape();
+    let array_shape_n = *array_shape.first().unwrap();
+    let write_shape = write.write_shape(array)?;
+    let block_shape_n = block_shape_n(array, write)?;
+    let n_blocks = array_shape_n.div_ceil(block_shape_n);
+    let start_block = std::cmp::min(start_block, n_blocks);
+
+    let bar = ProgressBar::new(n_blocks);
+    let style =
+        ProgressStyle::with_template("[{elapsed_precise}] [{bar}] ({pos}/{len}, ETA {eta})")
+            .unwrap();
+    bar.set_style(style);
+    bar.set_position(start_block);
+
+    let n_blocks = usize::try_from(n_blocks - start_block).unwrap();
+
+    let chunk_representation = array
+        .chunk_array_representation(&vec![0; array.chunk_grid().dimensionality()])
+        .unwrap();
+    let concurrent_target = zarrs_tools::concurrent_target();
+    let (concurrent_chunks, codec_concurrent_target) =
+        zarrs::array::concurrency::calc_concurrency_outer_inner(
+            concurrent_target,
+            &if let Some(concurrent_chunks) = concurrent_chunks {
+                let concurrent_chunks = std::cmp::min(n_blocks, concurrent_chunks);
+                RecommendedConcurrency::new(concurrent_chunks..concurrent_chunks)
+            } else {
+                let concurrent_chunks =
+                    std::cmp::min(n_blocks, global_config().chunk_concurrent_minimum());
+                RecommendedConcurrency::new_minimum(concurrent_chunks)
+            },
+            &array
+                .codecs()
+                .recommended_concurrency(&chunk_representation)
+                .unwrap(),
+        );
+    let codec_options = CodecOptionsBuilder::new()
+        .concurrent_target(codec_concurrent_target)
+        .experimental_partial_encoding(write_shape.is_some())
+        .build();
+
+    #[allow(clippy::mutex_integer)]
+    let idxm = Mutex::new((start_block, reader));
+    // The next block to be written and the written blocks after it
+    let written = Mutex::new((start_block, std::collections::BTreeSet::new()));
+    let bytes_read: AtomicUsize = 0.into();
+    // The in-flight bytes of a block being read and encoded
+    let block_bytes = usize::try_from(
+        array_shape.iter().skip(1).product::<u64>() * block_shape_n * data_type_size as u64 * 2,
+    )
+    .unwrap();
+    let op = |_| {
+        #[allow(clippy::mutex_integer)]
+        let mut idxm = idxm.lock().unwrap();
+        let (idx, reader) = &mut *idxm;
+        let idx = {
+            let idx_current = *idx;
+            *idx += 1;
+            idx_current
+        };
+
+        let start = idx * block_shape_n;
+        let end = std::cmp::min((idx + 1) * block_shape_n, array_shape_n);
+
+        let mut startn: Vec<u64> = vec![start];
+        startn.resize(dimensionality, 0);
+        let mut endn = vec![end];
+        endn.extend(array_shape.iter().skip(1));
+        let array_subset = unsafe { ArraySubset::new_with_start_end_exc_unchecked(startn, endn) };
+
+        let bytes_len =
+            usize::try_from(array_subset.num_elements() * data_type_size as u64).unwrap();
+        let mut subset_bytes = vec![0; bytes_len];
+        reader.read_exact(&mut subset_bytes).unwrap();
+        bytes_read.fetch_add(bytes_len, std::sync::atomic::Ordering::Relaxed);
+
+        drop(idxm);
+
+        if let Some(endianness) = endianness {
+            if !endianness.is_native() {
+                reverse_endianness(&mut subset_bytes, array.data_type());
+            }
+        }
+
+        array
+            .store_array_subset_opt(&array_subset, subset_bytes, &codec_options)
+            .unwrap();
+
+        // Advance the checkpoint past contiguous written blocks
+        let mut written = written.lock().unwrap();
+        let (next, blocks) = &mut *written;
+        blocks.insert(idx);
+        if blocks.first() == Some(&*next) {
+            while blocks.remove(next) {
+                *next += 1;
+            }
+            bar.set_position(*next);
+            checkpoint(std::cmp::min(*next * block_shape_n, array_shape_n));
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    MemoryBudget::global().try_for_each(concurrent_chunks, 0..n_blocks, |_| block_bytes, op)?;
+    Ok(bytes_read.load(std::sync::atomic::Ordering::Relaxed))
+}
+
+/// Open the data described by a header, skipping any leading bytes and a further `skip` bytes.
+///
+/// Standard input is used if the header does not reference a data file.
+fn open_data(header: &BinaryHeader, skip: u64) -> std::io::Result<Box<dyn Read + Send>> {
+    if let Some(data_file) = &header.data_file {
+        let mut file = std::fs::File::open(data_file)?;
+        file.seek(std::io::SeekFrom::Start(header.byte_skip + skip))?;
+        Ok(Box::new(std::io::BufReader::new(file)))
+    } else {
+        let mut stdin = std::io::stdin();
+        std::io::copy(
+            &mut (&mut stdin).take(header.byte_skip + skip),
+            &mut std::io::sink(),
+        )?;
+        Ok(Box::new(stdin))
+    }
+}
+
+pub fn main(arguments: Vec<OsString>) -> anyhow::Result<()> {
+    // Parse and validate arguments
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, &arguments);
+    cli.threads.clone().install(|| run(cli, &arguments))
+}
+
+fn run(cli: Cli, arguments: &[OsString]) -> anyhow::Result<()> {
+    // Read the header
+    let header = cli
+        .header
+        .as_deref()
+        .map(BinaryHeader::from_path)
+        .transpose()?
+        .unwrap_or_default();
+
+    // Get data type
+    let data_type = cli
+        .data_type
+        .as_ref()
+        .or(header.data_type.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("the data type is not set by the header or --data-type"))?;
+    let data_type = zarrs::array::DataType::from_metadata(data_type)?;
+    let array_shape = cli
+        .array_shape
+        .as_ref()
+        .or(header.shape.as_ref())
+        .ok_or_else(|| {
+            anyhow::anyhow!("the array shape is not set by the header or --array-shape")
+        })?;
+    let endianness = cli.endianness.or(header.endianness);
+
+    // Create storage
+    let path_out = cli.out.as_path();
+    let store = std::sync::Arc::new(filesystem_store(path_out, cli.direct_io.write())?);
+
+    // Create array
+    let dimension_names = cli
+        .dimension_names
+        .as_ref()
+        .or(header.dimension_names.as_ref())
+        .map(|f| f.iter().map(DimensionName::new).collect());
+    let mut array_builder =
+        get_array_builder(&cli.encoding, array_shape, data_type, dimension_names)?;
+    for (key, value) in header.attributes() {
+        array_builder.attributes.entry(key).or_insert(value);
+    }
+    if !cli.no_provenance {
+        let inputs = cli
+            .header
+            .iter()
+            .chain(header.data_file.iter())
+            .map(|path| path.to_string_lossy().to_string())
+            .chain(header.data_file.is_none().then(|| "stdin".to_string()))
+            .collect();
+        Provenance::new(NAME, arguments, inputs).insert(&mut array_builder.attributes);
+    }
+    let array_new = array_builder.build(store.clone(), "/")?;
+
+    // Open an existing array to resume, or store the array metadata with an initial checkpoint
+    let resume = cli.resume && path_out.join("zarr.json").exists();
+    let mut array = if resume {
+        let array = Array::open(store.clone(), "/")?;
+        if array.shape() != array_new.shape() || array.data_type() != array_new.data_type() {
+            anyhow::bail!(
+                "cannot resume {path_out:?}, the array shape or data type does not match the input"
+            );
+        }
+        array
+    } else {
+        let mut array = array_new;
+        array.attributes_mut().insert(
+            CHECKPOINT_ATTRIBUTE.to_string(),
+            serde_json::json!({ "rows": 0 }),
+        );
+        array.store_metadata()?;
+        array
+    };
+    let Some(rows) = checkpoint_rows(&array) else {
+        println!("Output {path_out:?} is already complete");
+        return Ok(());
+    };
+    let block_shape_n = block_shape_n(&array, &cli.write)?;
+    let start_block = rows / block_shape_n;
+    let skip = start_block
+        * block_shape_n
+        * array.shape().iter().skip(1).product::<u64>()
+        * array
+            .data_type()
+            .fixed_size()
+            .expect("data type should be fixed size") as u64;
+    if resume {
+        println!(
+            "Resuming {path_out:?} from {rows} of {} elements along the first dimension",
+            array.shape()[0]
+        );
+    }
+
+    // The checkpoint is stored in the metadata of a separate array handle, since blocks are written concurrently
+    let array_checkpoint = Mutex::new(Array::new_with_metadata(
+        store.clone(),
+        "/",
+        array.metadata().clone(),
+    )?);
+    let checkpoint = |rows: u64| {
+        let mut array_checkpoint = array_checkpoint.lock().unwrap();
+        array_checkpoint.attributes_mut().insert(
+            CHECKPOINT_ATTRIBUTE.to_string(),
+            serde_json::json!({ "rows": rows }),
+        );
+        array_checkpoint.store_metadata().unwrap();
+    };
+
+    // Read stdin to the array and write chunks/shards
+    let start = std::time::Instant::now();
+    let reader = open_data(&header, skip)?;
+    let bytes_read: usize = reader_to_array(
+        &array,
+        reader,
+        endianness,
+        cli.concurrent_chunks,
+        &cli.write,
+        start_block,
+        checkpoint,
+    )?;
+
+    // Remove the checkpoint
+    array.attributes_mut().remove(CHECKPOINT_ATTRIBUTE);
+    array.store_metadata()?;
+    let duration_s = start.elapsed().as_secs_f32();
+
+    // Output stats
+    let duration_ms = duration_s * 1.0e3;
+    let size_out = store.size().unwrap();
+    // let space_saving = 100.0 * (1.0 - (size_out as f32 / bytes_read as f32));
+    let relative_size = 100.0 * (size_out as f32 / bytes_read as f32);
+    println!("Output {path_out:?} in {duration_ms:.2}ms ({gbs:.2} GB/s) [{bytes_read} -> {size_out} ({relative_size:.2}%)]",
+    gbs = (bytes_read as f32 * 1e-9) / duration_s,
+        bytes_read = DecimalBytes(bytes_read as u64),
+        size_out = DecimalBytes(size_out),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Convert `data.raw` described by `header.json` in `dir` to `path_out` with `2x4` chunks.
+    fn convert(
+        dir: &std::path::Path,
+        path_out: &std::path::Path,
+        resume: bool,
+    ) -> anyhow::Result<()> {
+        let mut args: Vec<OsString> = vec![
+            NAME.into(),
+            "--header".into(),
+            dir.join("header.json").into(),
+            "--chunk-shape=2,4".into(),
+            "--fill-value=0".into(),
+            "--no-provenance".into(),
+        ];
+        if resume {
+            args.push("--resume".into());
+        }
+        args.push(path_out.into());
+        main(args)
+    }
+
+    /// Write the elements of a `10x4` `uint16` array to `data.raw` in `dir`.
+    fn write_data(dir: &std::path::Path, elements: &[u16]) -> std::io::Result<()> {
+        let bytes: Vec<u8> = elements.iter().flat_map(|e| e.to_ne_bytes()).collect();
+        std::fs::write(dir.join("data.raw"), bytes)
+    }
+
+    fn retrieve_elements(path: &std::path::Path) -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+        let array = Array::open(Arc::new(FilesystemStore::new(path)?), "/")?;
+        assert!(checkpoint_rows(&array).is_none());
+        Ok(array.retrieve_array_subset_elements::<u16>(&array.subset_all())?)
+    }
+
+    #[test]
+    fn binary2zarr_resume() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(
+            dir.path().join("header.json"),
+            r#"{ "data_type": "uint16", "shape": [10, 4], "data_file": "data.raw" }"#,
+        )?;
+        let elements: Vec<u16> = (0..40).collect();
+        write_data(dir.path(), &elements)?;
+
+        // A full conversion
+        let path_full = dir.path().join("full.zarr");
+        convert(dir.path(), &path_full, false)?;
+        assert_eq!(retrieve_elements(&path_full)?, elements);
+
+        // A conversion interrupted after the first 2 blocks (4 rows)
+        let path_resumed = dir.path().join("resumed.zarr");
+        convert(dir.path(), &path_resumed, false)?;
+        let mut array = Array::open(Arc::new(FilesystemStore::new(&path_resumed)?), "/")?;
+        array.attributes_mut().insert(
+            CHECKPOINT_ATTRIBUTE.to_string(),
+            serde_json::json!({ "rows": 4 }),
+        );
+        array.store_metadata()?;
+        for chunk_indices in [[2, 0], [3, 0], [4, 0]] {
+            array.erase_chunk(&chunk_indices)?;
+        }
+
+        // Data before the checkpoint is skipped rather than rewritten
+        let mut elements_resume = elements.clone();
+        elements_resume[..16].fill(u16::MAX);
+        write_data(dir.path(), &elements_resume)?;
+        convert(dir.path(), &path_resumed, true)?;
+        assert_eq!(
+            retrieve_elements(&path_resumed)?,
+            retrieve_elements(&path_full)?
+        );
+
+        // A completed conversion is not resumed
+        write_data(dir.path(), &[u16::MAX; 40])?;
+        convert(dir.path(), &path_resumed, true)?;
+        assert_eq!(retrieve_elements(&path_resumed)?, elements);
+        Ok(())
+    }
+}
@@ -0,0 +1,848 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use clap::Parser;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use itertools::Itertools;
+use serde::Serialize;
+use tempfile::TempDir;
+use zarrs::{
+    array::{Array, ArrayBuilder, ArrayCreateError},
+    filesystem::FilesystemStore,
+    storage::{StorageError, WritableStorageTraits},
+};
+use zarrs_tools::{
+    available_space, create_parent_groups, filesystem_store,
+    filter::{
+        load_run_config, run_config_schema, FilterCommand, FilterCommandTraits, FilterError,
+        FilterTraits, PathOrIdentifier, PathOrTempPath,
+    },
+    node_prefix,
+    progress::{
+        cancel_on_signal, duration_smoothed, PipelineProgress, ProgressCallback, ProgressMode,
+        ProgressStats,
+    },
+    split_node_path, DirectIo, Provenance, ThreadsArgs, WriteShapeArgs, ZarrReencodingArgs,
+};
+
+/// The bytes to bytes codecs of temporary arrays if `--tmp-compress` is set.
+const TMP_BYTES_TO_BYTES_CODECS: &str =
+    r#"[ { "name": "zstd", "configuration": { "level": 1, "checksum": false } } ]"#;
+
+#[derive(clap::ValueEnum, Debug, Clone)]
+enum OutputExists {
+    /// Erase the output
+    Erase,
+    /// Exit if the output already exists
+    Exit,
+}
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_filter";
+
+/// Apply simple image filters (transformations) to a Zarr array.
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Cli {
+    /// Behaviour if the output exists.
+    #[arg(long)]
+    #[clap(value_enum, default_value_t=OutputExists::Erase)]
+    exists: OutputExists,
+
+    /// Directory for temporary arrays.
+    ///
+    /// If omitted, defaults to the platform-specific temporary directory (e.g. ${TMPDIR}, /tmp, etc.)
+    #[arg(long)]
+    pub tmp: Option<PathBuf>,
+
+    /// Compress temporary arrays with a fast codec (zstd level 1) to reduce their size.
+    ///
+    /// This applies to temporary arrays of filters without --bytes-to-bytes-codecs.
+    #[arg(long, default_value_t = false)]
+    pub tmp_compress: bool,
+
+    /// Do not check that temporary arrays fit in the available space of the temporary directory.
+    ///
+    /// By default, a stage with a temporary output fails before it runs if the decoded size of the output exceeds the available space.
+    /// If the output is compressed, a warning is printed instead.
+    #[arg(long, default_value_t = false)]
+    pub no_tmp_space_check: bool,
+
+    /// The maximum number of chunks concurrently processed.
+    ///
+    /// By default, this is set to the number of CPUs.
+    /// Consider reducing this for images with large chunk sizes or on systems with low memory availability.
+    #[arg(long)]
+    pub chunk_limit: Option<usize>,
+
+    /// Write shape options.
+    ///
+    /// Filters inherit the write shape if they do not set it.
+    #[command(flatten)]
+    pub write: WriteShapeArgs,
+
+    /// The random seed of stochastic filters (e.g. add-noise). Random if unset.
+    ///
+    /// Each stochastic filter without a seed uses a distinct seed derived from this seed and its stage index, so pipelines are reproducible.
+    /// The seed of each stochastic filter is recorded in the provenance metadata of its output.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// The unit of progress bars.
+    ///
+    /// Bytes-based progress is proportional to the decoded bytes read, and falls back to steps (e.g. chunks) if unsupported.
+    #[arg(long, value_enum, default_value_t = ProgressMode::Steps)]
+    pub progress_mode: ProgressMode,
+
+    /// Do not write provenance metadata (the tool, version, arguments, inputs, and timestamp) to the output attributes.
+    #[arg(long, default_value_t = false)]
+    pub no_provenance: bool,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=both`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "both", default_value_t = DirectIo::Off)]
+    pub direct_io: DirectIo,
+
+    /// Set a run configuration variable (e.g. --set INPUT=array.zarr).
+    ///
+    /// Strings in a run configuration may reference variables as ${NAME} or ${NAME:-default}.
+    /// Variables are resolved from --set, then the environment, then the default.
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub variables: Vec<(String, String)>,
+
+    /// Write a JSON summary of each stage (filter) of the pipeline to this path (optional).
+    ///
+    /// The summary includes the read/process/write durations, bytes read and written, chunk counts, and output path of each stage.
+    #[arg(long)]
+    pub summary_json: Option<PathBuf>,
+
+    /// Print a JSON Schema of run configurations and exit.
+    ///
+    /// The schema describes a run configuration after variables and includes are resolved, and can be used by editors to validate run configurations.
+    #[arg(long, default_value_t = false)]
+    pub run_config_schema: bool,
+
+    /// Path to a JSON run configuration.
+    pub run_config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    filter: Option<FilterCommand>,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    pub threads: ThreadsArgs,
+}
+
+/// A summary of a stage (filter) of a pipeline.
+#[derive(Serialize)]
+struct StageSummary {
+    /// The index of the stage.
+    stage: usize,
+    /// The filter name.
+    filter: String,
+    /// The output path.
+    output: PathBuf,
+    /// The wall time of the stage (in seconds).
+    duration: f64,
+    /// The time reading, summed over concurrently processed chunks (in seconds).
+    read: f64,
+    /// The time processing, summed over concurrently processed chunks (in seconds).
+    process: f64,
+    /// The time processing each named sub-step (e.g. `gaussian`), summed over concurrently processed chunks (in seconds).
+    process_steps: BTreeMap<&'static str, f64>,
+    /// The time writing, summed over concurrently processed chunks (in seconds).
+    write: f64,
+    /// The number of bytes read, if tracked by the filter.
+    bytes_read: usize,
+    /// The number of bytes written, if tracked by the filter.
+    bytes_written: usize,
+    /// The number of steps (e.g. chunks) processed.
+    chunks: usize,
+}
+
+impl StageSummary {
+    fn new(
+        stage: usize,
+        filter: String,
+        output: PathBuf,
+        duration: std::time::Duration,
+        stats: Option<ProgressStats>,
+    ) -> Self {
+        let stats = stats.unwrap_or(ProgressStats {
+            step: 0,
+            num_steps: 0,
+            bytes_read: 0,
+            bytes_written: 0,
+            num_bytes: None,
+            read: std::time::Duration::ZERO,
+            process: std::time::Duration::ZERO,
+            process_steps: vec![],
+            write: std::time::Duration::ZERO,
+        });
+        Self {
+            stage,
+            filter,
+            output,
+            duration: duration.as_secs_f64(),
+            read: stats.read.as_secs_f64(),
+            process: stats.process.as_secs_f64(),
+            process_steps: stats
+                .process_steps
+                .iter()
+                .map(|(name, duration)| (*name, duration.as_secs_f64()))
+                .collect(),
+            write: stats.write.as_secs_f64(),
+            bytes_read: stats.bytes_read,
+            bytes_written: stats.bytes_written,
+            chunks: stats.step,
+        }
+    }
+}
+
+fn parse_key_value(key_value: &str) -> std::io::Result<(String, String)> {
+    key_value
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| std::io::Error::other(format!("expected KEY=VALUE, got {key_value}")))
+}
+
+fn bar_style_run() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "[{elapsed_precise}/{duration_smoothed}] {bar:40.black/bold} {pos}/{len} ({percent}%) {prefix} {msg}",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+    .with_key("duration_smoothed", duration_smoothed)
+}
+
+fn bar_style_pipeline() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "[{elapsed_precise}/{duration_smoothed}] {bar:40.black/bold} ({percent}%) {prefix} {msg}",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+    .with_key("duration_smoothed", duration_smoothed)
+}
+
+fn bar_style_finish() -> ProgressStyle {
+    ProgressStyle::with_template("[{elapsed_precise}/{elapsed_precise}] {prefix} {msg}")
+        .unwrap_or(ProgressStyle::default_bar())
+}
+
+/// Open the array at `path`, which may have a node path suffix (e.g. `input.zarr#/0`).
+fn load_array<P: Into<PathBuf>>(
+    path: P,
+    direct_io: bool,
+) -> Result<Array<FilesystemStore>, ArrayCreateError> {
+    let (path, node_path) = split_node_path(&path.into());
+    let store = filesystem_store(path, direct_io)
+        .map_err(|err| ArrayCreateError::StorageError(StorageError::Other(err.to_string())))?;
+    Array::open(store.into(), &node_path)
+}
+
+/// Create the array at `path`, which may have a node path suffix (e.g. `output.zarr#/labels/cells`).
+///
+/// Removes the node if it exists, and creates any missing parent groups.
+/// Other nodes in the store are left unchanged.
+fn create_array<P: Into<PathBuf>>(
+    path: P,
+    builder: &ArrayBuilder,
+    direct_io: bool,
+) -> Result<Array<FilesystemStore>, ArrayCreateError> {
+    let (path, node_path) = split_node_path(&path.into());
+    let store = Arc::new(
+        filesystem_store(path, direct_io)
+            .map_err(|err| ArrayCreateError::StorageError(StorageError::Other(err.to_string())))?,
+    );
+    store.erase_prefix(&node_prefix(&node_path)).unwrap();
+    create_parent_groups(&store, &node_path)
+        .map_err(|err| ArrayCreateError::StorageError(StorageError::Other(err.to_string())))?;
+    builder.build(store, &node_path)
+}
+
+/// The optional input and output arrays of a filter.
+type ArrayInputOutput = (
+    Option<Array<FilesystemStore>>,
+    Option<Array<FilesystemStore>>,
+);
+
+/// Returns the input and output arrays of a filter.
+///
+/// The input array is [`None`] for a generator filter, and the output array is [`None`] for an analysis filter.
+fn get_array_input_output(
+    filter: &dyn FilterTraits,
+    filter_command: &FilterCommand,
+    input: &std::path::Path,
+    output: &std::path::Path,
+    reencode: &ZarrReencodingArgs,
+    direct_io: DirectIo,
+) -> Result<ArrayInputOutput, FilterError> {
+    if filter_command.is_generator() {
+        let array_output = create_array(
+            output,
+            &filter.generator_array_builder(reencode)?,
+            direct_io.write(),
+        )?;
+        Ok((None, Some(array_output)))
+    } else if filter_command.is_analysis() {
+        let array_input = load_array(input, direct_io.read())?;
+        Ok((Some(array_input), None))
+    } else {
+        let array_input = load_array(input, direct_io.read())?;
+        let array_output = create_array(
+            output,
+            &filter.output_array_builder(&array_input, reencode)?,
+            direct_io.write(),
+        )?;
+        Ok((Some(array_input), Some(array_output)))
+    }
+}
+
+/// Check that the temporary array `array` at `path` is likely to fit in the available space of its filesystem.
+///
+/// The estimated size is the decoded size of the array, which is an upper bound if the array is compressed.
+/// Returns an error if the estimated size exceeds the available space and the array is uncompressed, otherwise prints a warning.
+fn check_tmp_space(
+    array: &Array<FilesystemStore>,
+    path: &Path,
+    bar: &ProgressBar,
+) -> Result<(), FilterError> {
+    let (Some(element_size), Some(available)) =
+        (array.data_type().fixed_size(), available_space(path))
+    else {
+        return Ok(());
+    };
+    let size = array.shape().iter().product::<u64>() * element_size as u64;
+    if size <= available {
+        return Ok(());
+    }
+    let uncompressed = array.codecs().bytes_to_bytes_codecs().is_empty()
+        && array
+            .codecs()
+            .array_to_bytes_codec()
+            .create_metadata()
+            .is_some_and(|metadata| metadata.name() == "bytes");
+    let message = format!(
+        "the temporary array {path:?} needs up to {:.2}MB, but only {:.2}MB is available in the temporary directory. Set --tmp to a directory with more space{}",
+        size as f64 / 1e6,
+        available as f64 / 1e6,
+        if uncompressed { " or use --tmp-compress" } else { "" },
+    );
+    if uncompressed {
+        Err(FilterError::Other(message))
+    } else {
+        bar.println(format!("Warning: {message}"));
+        Ok(())
+    }
+}
+
+fn get_path(
+    path_or_id: &Option<PathOrIdentifier>,
+    tmp_dir: &std::path::Path,
+    id_to_path: &mut HashMap<String, Arc<TempDir>>,
+    last_output: &Option<std::path::PathBuf>,
+) -> std::io::Result<PathOrTempPath> {
+    if let Some(path_or_id) = path_or_id {
+        match path_or_id {
+            PathOrIdentifier::Identifier(id) => {
+                // Named temporary output
+                let entry = id_to_path.entry(id.clone()).or_insert_with(|| {
+                    tempfile::TempDir::with_prefix_in(id, tmp_dir)
+                        .unwrap()
+                        .into()
+                });
+                Ok(PathOrTempPath::TempPath(entry.clone()))
+            }
+            PathOrIdentifier::Path(path) => {
+                // Long lived output
+                Ok(PathOrTempPath::Path(path.clone()))
+            }
+        }
+    } else {
+        // Unnamed temporary
+        if let Some(last_output) = last_output {
+            Ok(PathOrTempPath::Path(last_output.clone()))
+        } else {
+            Ok(PathOrTempPath::TempPath(
+                tempfile::TempDir::new_in(tmp_dir)?.into(),
+            ))
+        }
+    }
+}
+
+pub fn main(arguments: Vec<OsString>) -> std::process::ExitCode {
+    // Parse command line arguments
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, &arguments);
+    if let Err(err) = cli.threads.clone().install(|| run(cli, &arguments)) {
+        println!("{}", err);
+        std::process::ExitCode::FAILURE
+    } else {
+        std::process::ExitCode::SUCCESS
+    }
+}
+
+fn progress_callback(stats: ProgressStats, bar: &ProgressBar, mode: ProgressMode) {
+    let (position, length) = stats.position_length(mode);
+    bar.set_length(length);
+    bar.set_position(position);
+    if stats.process_steps.is_empty() {
+        bar.set_message(format!(
+            "rw:{:.2}/{:.2} p:{:.2}",
+            stats.read.as_secs_f32(),
+            stats.write.as_secs_f32(),
+            stats.process.as_secs_f32(),
+        ));
+    } else {
+        bar.set_message(format!(
+            "rw:{:.2}/{:.2} p:{:.2} ({})",
+            stats.read.as_secs_f32(),
+            stats.write.as_secs_f32(),
+            stats.process.as_secs_f32(),
+            stats.process_steps_message(),
+        ));
+    }
+}
+
+fn run(cli: Cli, arguments: &[OsString]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Stop at the next chunk on SIGINT/SIGTERM, so temporaries are removed and unfinished outputs have no metadata
+    let cancelled = cancel_on_signal();
+
+    if cli.run_config_schema {
+        println!("{}", serde_json::to_string_pretty(&run_config_schema())?);
+        return Ok(());
+    }
+
+    let start = std::time::Instant::now();
+
+    let multi_progress = MultiProgress::new();
+
+    // Create temporary directory
+    let tmp_dir = if let Some(tmp) = cli.tmp {
+        PathOrTempPath::Path(tmp.clone())
+    } else {
+        PathOrTempPath::TempPath(tempfile::tempdir()?.into())
+    };
+
+    // Get the filters
+    let mut filter_commands: Vec<FilterCommand> = if let Some(config) = cli.run_config {
+        load_run_config(&config, &cli.variables.into_iter().collect())?
+    } else if let Some(filter) = cli.filter {
+        vec![filter]
+    } else {
+        Err(std::io::Error::other("no filters supplied"))?
+    };
+
+    // Setup progress bars, with an overall bar if there are multiple filters
+    let pipeline_bar = (filter_commands.len() > 1).then(|| {
+        let bar = multi_progress.add(ProgressBar::new(PipelineProgress::LENGTH));
+        bar.set_style(bar_style_pipeline());
+        bar.set_prefix("total");
+        bar
+    });
+    let bars = filter_commands
+        .iter()
+        .map(|filter| {
+            let bar = multi_progress.add(ProgressBar::new(1));
+            bar.set_style(bar_style_run());
+            bar.set_prefix(filter.name());
+            bar
+        })
+        .collect_vec();
+
+    // Propagate global settings to filters
+    for (stage, filter) in filter_commands.iter_mut().enumerate() {
+        if let Some(global_chunk_limit) = cli.chunk_limit {
+            let filter_chunk_limit = filter.common_args_mut().chunk_limit_mut();
+            if filter_chunk_limit.is_none() {
+                *filter_chunk_limit = Some(global_chunk_limit);
+            }
+        }
+        if cli.write.write_shape.is_some() {
+            let filter_write = filter.common_args_mut().write_mut();
+            if filter_write.write_shape.is_none() {
+                filter_write.write_shape.clone_from(&cli.write.write_shape);
+            }
+        }
+        if filter.is_stochastic() {
+            // Resolve the seed before the filter is initialised, so that it can be recorded
+            let filter_seed = filter.common_args_mut().seed_mut();
+            if filter_seed.is_none() {
+                *filter_seed = Some(cli.seed.map_or_else(
+                    || fastrand::u64(..),
+                    |seed| fastrand::Rng::with_seed(seed.wrapping_add(stage as u64)).u64(..),
+                ));
+            }
+        }
+    }
+
+    // Get the input and output paths
+    let InputsOutputsExists {
+        input_paths,
+        output_paths,
+        exists,
+    } = get_input_output_paths(&filter_commands, tmp_dir.path())?;
+
+    // Handle an existing output
+    match cli.exists {
+        OutputExists::Exit => {
+            if exists.iter().any(|i| *i) {
+                Err(FilterError::Other("Output exists, exiting".to_string()))?;
+            }
+        }
+        OutputExists::Erase => {}
+    }
+
+    // Instantiate the filters
+    let filters: Vec<Box<dyn FilterTraits>> = filter_commands
+        .iter()
+        .map(|filter| filter.init())
+        .try_collect()?;
+
+    // Collect filters/input/outputs and check compatibility
+    let filter_input_output: Vec<_> = itertools::izip!(
+        &filter_commands,
+        &bars,
+        &filters,
+        &input_paths,
+        &output_paths,
+        &exists
+    )
+    .enumerate()
+    .map(
+        |(i, (filter_command, bar, filter, input, output, exists))| {
+            // Compress temporary outputs with a fast codec if requested and not explicitly encoded
+            let is_temporary = matches!(output, PathOrTempPath::TempPath(_));
+            let mut reencode = filter_command.common_args().reencode().clone();
+            if cli.tmp_compress && is_temporary && reencode.bytes_to_bytes_codecs.is_none() {
+                reencode.bytes_to_bytes_codecs = Some(TMP_BYTES_TO_BYTES_CODECS.to_string());
+            }
+            let (array_input, mut array_output) = get_array_input_output(
+                filter,
+                filter_command,
+                input.path(),
+                output.path(),
+                &reencode,
+                cli.direct_io,
+            )?;
+            let input_str = if let Some(array_input) = &array_input {
+                format!(
+                    "{} {:?} {:?}",
+                    array_input.data_type(),
+                    array_input.shape(),
+                    input.path()
+                )
+            } else {
+                "none".to_string()
+            };
+            let output_str = if let Some(array_output) = &array_output {
+                format!(
+                    "{} {:?} {:?}",
+                    array_output.data_type(),
+                    array_output.shape(),
+                    output.path()
+                )
+            } else {
+                format!("table {:?}", output.path())
+            };
+            bar.println(format!(
+                "{}{}\n\targs:   {}\n\tencode: {}\n\tinput:  {}\n\toutput: {}{}",
+                if filters.len() == 1 {
+                    "".to_string()
+                } else {
+                    format!("{i} ")
+                },
+                filter_command.name(),
+                filter_command.args_str(),
+                filter_command.reencode_str(),
+                input_str,
+                output_str,
+                if *exists { " (overwrite)" } else { "" },
+            ));
+            if let Some(array_output) = &array_output {
+                if filter_command
+                    .common_args()
+                    .write()
+                    .write_shape(array_output)?
+                    .is_some()
+                    && !filter.supports_write_shape()
+                {
+                    bar.println(format!(
+                        "Warning: the {} filter writes whole shards, the write shape is ignored",
+                        filter_command.name()
+                    ));
+                }
+            }
+            let provenance = (!cli.no_provenance).then(|| {
+                let inputs = if array_input.is_some() {
+                    vec![input.path().to_string_lossy().to_string()]
+                } else {
+                    vec![]
+                };
+                let mut provenance = Provenance::new(NAME, arguments, inputs);
+                if filter_command.is_stochastic() {
+                    provenance.seed = *filter_command.common_args().seed();
+                }
+                provenance
+            });
+            if let Some(array_output) = &mut array_output {
+                if let Some(provenance) = &provenance {
+                    provenance.insert(array_output.attributes_mut());
+                }
+                array_output.store_metadata()?; // erased before filter run
+            }
+
+            let chunk_output = array_output
+                .as_ref()
+                .map(|array_output| {
+                    array_output.chunk_array_representation(&vec![0; array_output.dimensionality()])
+                })
+                .transpose()?;
+            let chunk_input = array_input
+                .as_ref()
+                .map(|array_input| {
+                    array_input.chunk_array_representation(&vec![0; array_input.dimensionality()])
+                })
+                .transpose()?;
+            let (chunk_input, chunk_output) = match (chunk_input, chunk_output) {
+                (Some(chunk_input), Some(chunk_output)) => (chunk_input, chunk_output),
+                (Some(chunk), None) | (None, Some(chunk)) => (chunk.clone(), chunk),
+                (None, None) => unreachable!("a filter has an input or an output array"),
+            };
+            filter.is_compatible(&chunk_input, &chunk_output)?;
+
+            // Estimate the work of the filter for the overall progress
+            let num_chunks = array_output
+                .as_ref()
+                .or(array_input.as_ref())
+                .map_or(0, |array| {
+                    array
+                        .chunk_grid_shape()
+                        .map_or(0, |shape| shape.iter().product::<u64>())
+                });
+            let work =
+                num_chunks as f64 * filter.memory_per_chunk(&chunk_input, &chunk_output) as f64;
+            Ok::<_, FilterError>((
+                filter_command.name(),
+                filter,
+                array_input,
+                array_output,
+                output.path(),
+                provenance,
+                is_temporary,
+                work,
+            ))
+        },
+    )
+    .try_collect()?;
+    let pipeline = PipelineProgress::new(
+        &filter_input_output
+            .iter()
+            .map(|(_, _, _, _, _, _, _, work)| *work)
+            .collect_vec(),
+    );
+
+    // Erase output metadata to imply indicating that filter has not run
+    filter_input_output
+        .iter()
+        .filter_map(|(_, _, _, array_output, _, _, _, _)| array_output.as_ref())
+        .try_for_each(|array_output| array_output.erase_metadata())?;
+
+    // Run the filters
+    let mut summaries = Vec::with_capacity(filter_input_output.len());
+    std::iter::zip(filter_input_output, bars)
+        .enumerate()
+        .try_for_each(
+            |(
+                stage,
+                (
+                    (
+                        name,
+                        filter,
+                        array_input,
+                        array_output,
+                        output_path,
+                        provenance,
+                        is_temporary,
+                        _work,
+                    ),
+                    bar,
+                ),
+            )| {
+                // Check the temporary output fits, accounting for the temporaries of previous stages
+                if is_temporary && !cli.no_tmp_space_check {
+                    if let Some(array_output) = &array_output {
+                        check_tmp_space(array_output, output_path, &bar)?;
+                    }
+                }
+                bar.reset();
+                let stage_start = std::time::Instant::now();
+
+                // Retain the latest stats, callbacks from concurrent chunks can arrive out of order
+                let last_stats = Mutex::new(None::<ProgressStats>);
+                let progress_callback = |stats: ProgressStats| {
+                    {
+                        let mut last_stats = last_stats.lock().unwrap();
+                        if last_stats
+                            .as_ref()
+                            .map_or(true, |last_stats| stats.step >= last_stats.step)
+                        {
+                            if let Some(pipeline_bar) = &pipeline_bar {
+                                pipeline_bar.set_position(
+                                    pipeline.position(stage, stats.fraction(cli.progress_mode)),
+                                );
+                                pipeline_bar.set_message(format!(
+                                    "stage {}/{}",
+                                    stage + 1,
+                                    pipeline.num_stages()
+                                ));
+                            }
+                            *last_stats = Some(stats.clone());
+                        }
+                    }
+                    progress_callback(stats, &bar, cli.progress_mode);
+                };
+                let progress_callback =
+                    ProgressCallback::new(&progress_callback).with_cancellation(cancelled);
+                // Run the filter
+                match (&array_input, array_output) {
+                    (Some(array_input), Some(mut array_output)) => {
+                        filter.apply(array_input, &mut array_output, &progress_callback)?;
+                        // Write metadata to indicate that filter is finished
+                        array_output.store_metadata()?;
+                    }
+                    (None, Some(mut array_output)) => {
+                        filter.generate(&mut array_output, &progress_callback)?;
+                        array_output.store_metadata()?;
+                    }
+                    (Some(array_input), None) => {
+                        let mut attributes = serde_json::Map::new();
+                        if let Some(provenance) = &provenance {
+                            provenance.insert(&mut attributes);
+                        }
+                        filter
+                            .analyse(array_input, &progress_callback)?
+                            .write(output_path, attributes)?;
+                    }
+                    (None, None) => unreachable!("a filter has an input or an output array"),
+                }
+
+                bar.set_style(bar_style_finish());
+                bar.set_prefix(format!(
+                    "{} {}",
+                    bar.prefix(),
+                    output_path.to_string_lossy()
+                ));
+                bar.abandon();
+                if let Some(pipeline_bar) = &pipeline_bar {
+                    pipeline_bar.set_position(pipeline.position(stage, 1.0));
+                }
+
+                summaries.push(StageSummary::new(
+                    stage,
+                    name,
+                    output_path.to_path_buf(),
+                    stage_start.elapsed(),
+                    last_stats.into_inner().unwrap(),
+                ));
+                Ok::<(), FilterError>(())
+            },
+        )?;
+
+    if let Some(pipeline_bar) = pipeline_bar {
+        pipeline_bar.set_style(bar_style_finish());
+        pipeline_bar.set_message("");
+        pipeline_bar.abandon();
+    }
+
+    let duration_s = start.elapsed().as_secs_f32();
+    println!("Completed in {duration_s:.2}s");
+
+    // Output the stage summary
+    if summaries.len() > 1 {
+        for summary in &summaries {
+            println!(
+                "{stage} {filter}: {duration:.2}s ({percent:.1}%) rw:{read:.2}/{write:.2} p:{process:.2} chunks:{chunks}",
+                stage = summary.stage,
+                filter = summary.filter,
+                duration = summary.duration,
+                percent = 100.0 * summary.duration / f64::from(duration_s),
+                read = summary.read,
+                write = summary.write,
+                process = summary.process,
+                chunks = summary.chunks,
+            );
+        }
+        if let Some(slowest) = summaries
+            .iter()
+            .max_by(|a, b| a.duration.total_cmp(&b.duration))
+        {
+            println!("Slowest stage: {} {}", slowest.stage, slowest.filter);
+        }
+    }
+    if let Some(summary_json) = &cli.summary_json {
+        std::fs::write(summary_json, serde_json::to_string_pretty(&summaries)?)?;
+    }
+
+    Ok(())
+}
+
+struct InputsOutputsExists {
+    input_paths: Vec<PathOrTempPath>,
+    output_paths: Vec<PathOrTempPath>,
+    exists: Vec<bool>,
+}
+
+fn get_input_output_paths(
+    filters: &[FilterCommand],
+    tmp_dir: &Path,
+) -> Result<InputsOutputsExists, FilterError> {
+    let mut id_to_path = HashMap::<String, Arc<TempDir>>::new();
+    let mut input_paths = Vec::<PathOrTempPath>::with_capacity(filters.len());
+    let mut output_paths = Vec::<PathOrTempPath>::with_capacity(filters.len());
+    let mut last_output: Option<std::path::PathBuf> = None;
+    let mut exists = Vec::<bool>::with_capacity(filters.len());
+    for filter in filters {
+        if let Some(PathOrIdentifier::Path(output_path)) = filter.io_args().output() {
+            let (store_path, node_path) = split_node_path(output_path);
+            exists.push(store_path.join(node_prefix(&node_path).as_str()).exists());
+        } else {
+            exists.push(false);
+        }
+
+        // println!("{filter:#?}");
+        let input_path = get_path(
+            filter.io_args().input(),
+            tmp_dir,
+            &mut id_to_path,
+            &last_output,
+        )?;
+        let output_path = get_path(filter.io_args().output(), tmp_dir, &mut id_to_path, &None)?;
+        if input_paths.is_empty() && !filter.is_generator() {
+            if let PathOrTempPath::TempPath(_) = input_path {
+                Err(std::io::Error::other(
+                    "the first filter must have a valid input path",
+                ))?
+            }
+        }
+        // The output of an analysis filter is a table, so the next filter defaults to its input
+        last_output = Some(if filter.is_analysis() {
+            input_path.path().to_path_buf()
+        } else {
+            output_path.path().to_path_buf()
+        });
+        // println!("{:?} -> {:?}", input_path.path(), output_path.path());
+
+        input_paths.push(input_path);
+        output_paths.push(output_path);
+    }
+
+    Ok(InputsOutputsExists {
+        input_paths,
+        output_paths,
+        exists,
+    })
+}
@@ -0,0 +1,460 @@
+use std::{collections::BTreeMap, ffi::OsString, sync::Arc};
+
+use anyhow::Context;
+use clap::Parser;
+use indicatif::HumanBytes;
+use zarrs::{
+    array::Array,
+    filesystem::FilesystemStore,
+    storage::{ListableStorageTraits, ReadableStorageTraits, StoreKey, WritableStorageTraits},
+};
+use zarrs_tools::{filesystem_store, ThreadsArgs};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_gc";
+
+/// Garbage-collect orphaned keys of a Zarr array or hierarchy.
+///
+/// A key is orphaned if it is shaped like a chunk key of the array that contains it (e.g. `c/1/0` or `1.0`), but does not correspond to a chunk (or shard) within its chunk grid.
+/// Orphaned keys accumulate when an array shrinks or its chunk grid changes.
+///
+/// Other keys that are not metadata documents or chunks (e.g. a README, or the `OME/METADATA.ome.xml` companion of an OME-Zarr hierarchy) are kept, unless --include-non-chunk-keys is set.
+///
+/// By default, orphaned keys are only listed (a dry run).
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Args {
+    /// The path to a zarr array or group.
+    path: String,
+
+    /// Delete orphaned keys.
+    #[arg(long)]
+    delete: bool,
+
+    /// Only print the per-prefix summaries of orphaned keys.
+    #[arg(long)]
+    summary_only: bool,
+
+    /// Also treat keys that are not metadata documents or chunk keys as orphaned.
+    ///
+    /// This includes any key of a group, outside of the hierarchy, or not shaped like a chunk key of an array.
+    #[arg(long)]
+    include_non_chunk_keys: bool,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+/// The names of Zarr V3 and V2 metadata documents.
+const METADATA_DOCUMENTS: [&str; 5] = ["zarr.json", ".zarray", ".zgroup", ".zattrs", ".zmetadata"];
+
+/// A node of the hierarchy, which owns the keys with its prefix.
+enum NodeKeys {
+    /// An array, which owns its metadata and the chunk keys within its chunk grid.
+    Array {
+        array: Box<Array<FilesystemStore>>,
+        chunk_grid_shape: Vec<u64>,
+    },
+    /// A group, which only owns its metadata.
+    Group,
+    /// A node that could not be opened, or an array with chunk keys that cannot be parsed, which owns all of its keys.
+    Unknown,
+}
+
+/// The orphaned keys and their sizes, grouped by the prefix of the node that contains them.
+type OrphanedKeys = BTreeMap<String, Vec<(StoreKey, u64)>>;
+
+/// The prefix of the keys of a node at `node_path` (e.g. `/a/b` becomes `a/b/`).
+fn node_prefix(node_path: &str) -> String {
+    let node_path = node_path.trim_start_matches('/');
+    if node_path.is_empty() {
+        String::new()
+    } else {
+        format!("{node_path}/")
+    }
+}
+
+/// Returns the node keys of an opened `array`.
+///
+/// The array owns all of its keys if it has no chunk grid shape or a chunk key encoding other than `default` or `v2`, since its chunk keys cannot be parsed.
+fn array_node_keys(array: Array<FilesystemStore>) -> NodeKeys {
+    let chunk_key_encoding = array.chunk_key_encoding().create_metadata();
+    match array.chunk_grid_shape() {
+        Some(chunk_grid_shape) if ["default", "v2"].contains(&chunk_key_encoding.name()) => {
+            NodeKeys::Array {
+                array: Box::new(array),
+                chunk_grid_shape,
+            }
+        }
+        _ => {
+            eprintln!(
+                "Skipping {}, which has an unsupported chunk grid or chunk key encoding",
+                array.path()
+            );
+            NodeKeys::Unknown
+        }
+    }
+}
+
+/// Returns true if `relative_key` is shaped like a chunk key, relative to the prefix of its array.
+///
+/// Chunk keys are decimal chunk indices separated by `/` or `.`, with a `c` prefix for the `default` chunk key encoding (e.g. `c/1/0`, `c.1.0`, `1.0`, or `c`).
+fn is_chunk_key_shaped(relative_key: &str) -> bool {
+    let indices = match relative_key.strip_prefix('c') {
+        Some("") => return true,
+        Some(indices) => match indices.strip_prefix(['/', '.']) {
+            Some(indices) => indices,
+            None => return false,
+        },
+        None => relative_key,
+    };
+    indices
+        .split(['/', '.'])
+        .all(|index| !index.is_empty() && index.bytes().all(|c| c.is_ascii_digit()))
+}
+
+/// Returns true if `key` is a chunk key of `array` within its chunk grid.
+fn is_chunk_key(array: &Array<FilesystemStore>, chunk_grid_shape: &[u64], key: &StoreKey) -> bool {
+    let relative_key = &key.as_str()[node_prefix(array.path().as_str()).len()..];
+    // Chunk key encodings (default, v2) separate decimal chunk indices with non-digit characters
+    let chunk_indices = if chunk_grid_shape.is_empty() {
+        Some(vec![])
+    } else {
+        relative_key
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|index| !index.is_empty())
+            .map(|index| index.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()
+    };
+    let Some(chunk_indices) = chunk_indices else {
+        return false;
+    };
+    chunk_indices.len() == chunk_grid_shape.len()
+        && std::iter::zip(&chunk_indices, chunk_grid_shape).all(|(i, s)| i < s)
+        && array.chunk_key(&chunk_indices) == *key
+}
+
+/// Find the orphaned keys of the hierarchy in `storage`.
+///
+/// Keys that are not metadata documents or chunk keys are only orphaned if `include_non_chunk_keys` is true.
+///
+/// # Errors
+/// Returns an error if there is no Zarr metadata in `storage` or it cannot be read.
+fn orphaned_keys(
+    storage: &Arc<FilesystemStore>,
+    include_non_chunk_keys: bool,
+) -> anyhow::Result<OrphanedKeys> {
+    let keys = storage.list()?;
+
+    // Find the nodes of the hierarchy
+    let mut nodes: Vec<(String, NodeKeys)> = vec![];
+    for key in &keys {
+        let (parent, name) = key.as_str().rsplit_once('/').unwrap_or(("", key.as_str()));
+        if !METADATA_DOCUMENTS.contains(&name) {
+            continue;
+        }
+        let node_path = format!("/{parent}");
+        let prefix = node_prefix(&node_path);
+        if nodes.iter().any(|(node_prefix, _)| *node_prefix == prefix) {
+            continue;
+        }
+        let is_group = match name {
+            "zarr.json" => storage
+                .get(key)?
+                .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+                .is_some_and(|metadata| metadata["node_type"] == "group"),
+            ".zarray" => false,
+            _ => !keys
+                .iter()
+                .any(|key| key.as_str() == format!("{prefix}.zarray")),
+        };
+        let node = if is_group {
+            NodeKeys::Group
+        } else {
+            match Array::open(storage.clone(), &node_path) {
+                Ok(array) => array_node_keys(array),
+                Err(err) => {
+                    eprintln!("Skipping {node_path}, which could not be opened: {err}");
+                    NodeKeys::Unknown
+                }
+            }
+        };
+        nodes.push((prefix, node));
+    }
+    if nodes.is_empty() {
+        anyhow::bail!("No Zarr metadata found");
+    }
+    // Match keys to the deepest node first
+    nodes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+    let mut orphans = OrphanedKeys::new();
+    for key in keys {
+        let (_, name) = key.as_str().rsplit_once('/').unwrap_or(("", key.as_str()));
+        if METADATA_DOCUMENTS.contains(&name) {
+            continue;
+        }
+        let node = nodes
+            .iter()
+            .find(|(prefix, _)| key.as_str().starts_with(prefix.as_str()));
+        let orphaned = match node {
+            Some((
+                prefix,
+                NodeKeys::Array {
+                    array,
+                    chunk_grid_shape,
+                },
+            )) => {
+                !is_chunk_key(array, chunk_grid_shape, &key)
+                    && (include_non_chunk_keys
+                        || is_chunk_key_shaped(&key.as_str()[prefix.len()..]))
+            }
+            Some((_, NodeKeys::Unknown)) => false,
+            Some((_, NodeKeys::Group)) | None => include_non_chunk_keys,
+        };
+        if orphaned {
+            let prefix = node.map_or(String::new(), |(prefix, _)| prefix.clone());
+            let size = storage.size_key(&key)?.unwrap_or_default();
+            orphans.entry(prefix).or_default().push((key, size));
+        }
+    }
+    Ok(orphans)
+}
+
+pub fn main(args: Vec<OsString>) -> anyhow::Result<()> {
+    let args = zarrs_tools::config::parse_from::<Args>(NAME, args);
+    args.threads.clone().install(|| run(args))
+}
+
+fn run(args: Args) -> anyhow::Result<()> {
+    let storage = Arc::new(filesystem_store(&args.path, false)?);
+    let orphans = orphaned_keys(&storage, args.include_non_chunk_keys)
+        .with_context(|| format!("Failed to find the orphaned keys of {}", args.path))?;
+
+    if !args.summary_only {
+        for (key, size) in orphans.values().flatten() {
+            println!("{key} ({})", HumanBytes(*size));
+        }
+    }
+    let mut num_keys = 0;
+    let mut num_bytes = 0;
+    for (prefix, keys) in &orphans {
+        let bytes = keys.iter().map(|(_, size)| size).sum::<u64>();
+        println!(
+            "/{prefix}: {} orphaned keys ({})",
+            keys.len(),
+            HumanBytes(bytes)
+        );
+        num_keys += keys.len();
+        num_bytes += bytes;
+    }
+
+    if args.delete {
+        for (key, _) in orphans.values().flatten() {
+            storage.erase(key)?;
+        }
+        println!(
+            "Deleted {num_keys} orphaned keys ({})",
+            HumanBytes(num_bytes)
+        );
+    } else {
+        println!(
+            "Found {num_keys} orphaned keys ({}), run with --delete to delete them",
+            HumanBytes(num_bytes)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use zarrs::{
+        array::{
+            codec::array_to_bytes::sharding::ShardingCodecBuilder, ArrayBuilder, DataType,
+            FillValue,
+        },
+        group::GroupBuilder,
+    };
+
+    use super::*;
+
+    fn array_builder(shape: Vec<u64>) -> ArrayBuilder {
+        ArrayBuilder::new(
+            shape,
+            DataType::UInt16,
+            vec![4, 4].try_into().unwrap(),
+            FillValue::from(0u16),
+        )
+    }
+
+    /// Store ones in a new array at `path` built by `builder`.
+    fn store_ones(
+        storage: &Arc<FilesystemStore>,
+        builder: &ArrayBuilder,
+        path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let array = builder.build(storage.clone(), path)?;
+        array.store_metadata()?;
+        let num_elements = array.shape().iter().product::<u64>() as usize;
+        array.store_array_subset_elements::<u16>(&array.subset_all(), &vec![1; num_elements])?;
+        Ok(())
+    }
+
+    fn orphaned_key_names(
+        storage: &Arc<FilesystemStore>,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(orphaned_keys(storage, false)?
+            .values()
+            .flatten()
+            .map(|(key, _)| key.to_string())
+            .collect())
+    }
+
+    fn gc(path: &Path, delete: bool) -> anyhow::Result<()> {
+        let mut args: Vec<OsString> = vec!["zarrs_gc".into(), path.into()];
+        if delete {
+            args.push("--delete".into());
+        }
+        main(args)
+    }
+
+    #[test]
+    fn chunk_key_shaped() {
+        for key in ["c", "c/1/0", "c.1.0", "1.0", "1/0", "0"] {
+            assert!(is_chunk_key_shaped(key), "{key}");
+        }
+        for key in ["README.md", "c/", "c/a", "cc/0", "1..0", "data.bin", ""] {
+            assert!(!is_chunk_key_shaped(key), "{key}");
+        }
+    }
+
+    #[test]
+    fn gc_shrunk_array() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let storage = Arc::new(FilesystemStore::new(dir.path())?);
+        store_ones(&storage, &array_builder(vec![8, 8]), "/")?;
+        // Shrink the array, orphaning the chunks outside of the chunk grid
+        array_builder(vec![4, 8])
+            .build(storage.clone(), "/")?
+            .store_metadata()?;
+        assert_eq!(orphaned_key_names(&storage)?, ["c/1/0", "c/1/1"]);
+
+        // A dry run deletes nothing
+        let keys = storage.list()?;
+        gc(dir.path(), false)?;
+        assert_eq!(storage.list()?, keys);
+
+        gc(dir.path(), true)?;
+        assert!(orphaned_key_names(&storage)?.is_empty());
+        assert_eq!(storage.list()?.len(), keys.len() - 2);
+        Ok(())
+    }
+
+    #[test]
+    fn gc_sharded_array() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let storage = Arc::new(FilesystemStore::new(dir.path())?);
+        let mut builder = array_builder(vec![8, 8]);
+        builder.array_to_bytes_codec(Arc::new(
+            ShardingCodecBuilder::new(vec![2, 2].try_into()?).build(),
+        ));
+        store_ones(&storage, &builder, "/")?;
+        assert!(orphaned_key_names(&storage)?.is_empty());
+        storage.set(&StoreKey::new("c/2/0")?, vec![0; 8].into())?;
+        assert_eq!(orphaned_key_names(&storage)?, ["c/2/0"]);
+        Ok(())
+    }
+
+    #[test]
+    fn gc_v2_array_slash_separator() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let storage = Arc::new(FilesystemStore::new(dir.path())?);
+        let metadata = serde_json::json!({
+            "zarr_format": 2,
+            "shape": [4, 4],
+            "chunks": [2, 2],
+            "dtype": "<u2",
+            "compressor": null,
+            "fill_value": 0,
+            "order": "C",
+            "filters": null,
+            "dimension_separator": "/"
+        });
+        storage.set(
+            &StoreKey::new(".zarray")?,
+            serde_json::to_vec(&metadata)?.into(),
+        )?;
+        for key in ["0/0", "1/1", "2/0", "0.1"] {
+            storage.set(&StoreKey::new(key)?, vec![0; 8].into())?;
+        }
+        assert_eq!(orphaned_key_names(&storage)?, ["0.1", "2/0"]);
+        Ok(())
+    }
+
+    #[test]
+    fn gc_hierarchy() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let storage = Arc::new(FilesystemStore::new(dir.path())?);
+        GroupBuilder::new()
+            .build(storage.clone(), "/")?
+            .store_metadata()?;
+        GroupBuilder::new()
+            .build(storage.clone(), "/group")?
+            .store_metadata()?;
+        store_ones(&storage, &array_builder(vec![4, 4]), "/group/array")?;
+        store_ones(&storage, &array_builder(vec![4, 4]), "/array")?;
+        storage.set(&StoreKey::new("group/orphan")?, vec![0; 8].into())?;
+        storage.set(&StoreKey::new("README.md")?, vec![0; 8].into())?;
+        storage.set(&StoreKey::new("array/notes.txt")?, vec![0; 8].into())?;
+        storage.set(
+            &StoreKey::new(zarrs_tools::ome_xml::OME_XML_PATH)?,
+            vec![0; 8].into(),
+        )?;
+        storage.set(&StoreKey::new("group/array/c/1/0")?, vec![0; 8].into())?;
+        // An array that cannot be opened owns all of its keys
+        storage.set(
+            &StoreKey::new("unknown/zarr.json")?,
+            br#"{"zarr_format": 3, "node_type": "array"}"#.to_vec().into(),
+        )?;
+        storage.set(&StoreKey::new("unknown/c/0/0")?, vec![0; 8].into())?;
+
+        assert_eq!(orphaned_key_names(&storage)?, ["group/array/c/1/0"]);
+        let mut orphans = orphaned_keys(&storage, true)?
+            .values()
+            .flatten()
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        orphans.sort();
+        assert_eq!(
+            orphans,
+            [
+                "OME/METADATA.ome.xml",
+                "README.md",
+                "array/notes.txt",
+                "group/array/c/1/0",
+                "group/orphan"
+            ]
+        );
+
+        let keys = storage.list()?;
+        gc(dir.path(), false)?;
+        assert_eq!(storage.list()?, keys);
+        gc(dir.path(), true)?;
+        assert!(orphaned_key_names(&storage)?.is_empty());
+        assert_eq!(storage.list()?.len(), keys.len() - 1);
+        // Keys that are not shaped like chunk keys are kept
+        for key in [
+            "group/orphan",
+            "README.md",
+            "array/notes.txt",
+            zarrs_tools::ome_xml::OME_XML_PATH,
+        ] {
+            assert!(storage.get(&StoreKey::new(key)?)?.is_some(), "{key}");
+        }
+        assert!(storage.get(&StoreKey::new("unknown/c/0/0")?)?.is_some());
+        assert!(storage.get(&StoreKey::new("array/c/0/0")?)?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+use std::{ffi::OsString, path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use indicatif::{DecimalBytes, ProgressBar, ProgressStyle};
+use zarrs::{
+    array::{DataType, DimensionName},
+    metadata::v3::array::data_type::DataTypeMetadataV3,
+    storage::{ListableStorageTraits, StorePrefix, WritableStorageTraits},
+};
+use zarrs_tools::{
+    filesystem_store,
+    filter::{
+        filters::generate::{Generate, GenerateArguments},
+        FilterArguments, FilterTraits,
+    },
+    get_array_builder,
+    progress::{duration_smoothed, ProgressCallback, ProgressStats},
+    DirectIo, Provenance, ThreadsArgs, ZarrEncodingArgs,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_generate";
+
+/// Generate a synthetic Zarr V3 array (e.g. for benchmarking or bug reproductions).
+///
+/// The array is filled with a synthetic pattern, optionally with random low-order bits to control its compressibility (see --entropy).
+/// Chunks (or shards) are generated and written independently, so arbitrarily large arrays can be produced.
+#[derive(Parser)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+#[allow(rustdoc::bare_urls)]
+pub struct Cli {
+    #[command(flatten)]
+    generate: GenerateArguments,
+
+    /// The random seed of noise. Random if unset.
+    ///
+    /// The output is reproducible for a given seed and chunk shape, and the seed is recorded in the provenance metadata of the output.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Zarr data type. See https://zarr-specs.readthedocs.io/en/latest/v3/core/v3.0.html#id11
+    ///
+    /// Examples:
+    ///   bool
+    ///   int8 int16 int32 int64
+    ///   uint8 uint16 uint32 uint64
+    ///   float32 float64 float16 bfloat16
+    #[arg(short, long, verbatim_doc_comment, value_parser = parse_data_type)]
+    data_type: DataTypeMetadataV3,
+
+    /// Dimension names. A comma separated list of the names of each array dimension.
+    #[arg(long, value_delimiter = ',')]
+    dimension_names: Option<Vec<String>>,
+
+    #[command(flatten)]
+    encoding: ZarrEncodingArgs,
+
+    /// The maximum number of chunks (or shards) concurrently generated.
+    ///
+    /// By default, this is set based on the number of CPUs and available memory.
+    #[arg(long)]
+    concurrent_chunks: Option<usize>,
+
+    /// Do not write provenance metadata (the tool, version, arguments, inputs, and timestamp) to the output attributes.
+    #[arg(long, default_value_t = false)]
+    no_provenance: bool,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=both`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "both", default_value_t = DirectIo::Off)]
+    direct_io: DirectIo,
+
+    /// The output directory for the zarr array.
+    out: PathBuf,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+fn parse_data_type(data_type: &str) -> std::io::Result<DataTypeMetadataV3> {
+    serde_json::from_value(serde_json::Value::String(data_type.to_string()))
+        .map_err(|err| std::io::Error::other(err.to_string()))
+}
+
+fn bar_style_run() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "[{elapsed_precise}/{duration_smoothed}] {bar:40.black/bold} {pos}/{len} ({percent}%) {msg}",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+    .with_key("duration_smoothed", duration_smoothed)
+}
+
+fn progress_callback(stats: ProgressStats, bar: &ProgressBar) {
+    bar.set_length(stats.num_steps as u64);
+    bar.set_position(stats.step as u64);
+    bar.set_message(format!(
+        "p:{:.2} w:{:.2}",
+        stats.process.as_secs_f32(),
+        stats.write.as_secs_f32(),
+    ));
+}
+
+pub fn main(arguments: Vec<OsString>) -> anyhow::Result<()> {
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, &arguments);
+    cli.threads.clone().install(|| run(cli, &arguments))
+}
+
+fn run(cli: Cli, arguments: &[OsString]) -> anyhow::Result<()> {
+    let data_type = DataType::from_metadata(&cli.data_type)?;
+    let Some(element_size) = data_type.fixed_size() else {
+        anyhow::bail!("unsupported data type {data_type}");
+    };
+
+    // Create storage
+    let path_out = cli.out.as_path();
+    let store = Arc::new(filesystem_store(path_out, cli.direct_io.write())?);
+    store.erase_prefix(&StorePrefix::root())?;
+
+    // Create array
+    let dimension_names = cli
+        .dimension_names
+        .as_ref()
+        .map(|f| f.iter().map(DimensionName::new).collect());
+    let mut array_builder = get_array_builder(
+        &cli.encoding,
+        &cli.generate.shape,
+        data_type,
+        dimension_names,
+    )?;
+    let seed = cli.seed.unwrap_or_else(|| fastrand::u64(..));
+    if !cli.no_provenance {
+        let mut provenance = Provenance::new(NAME, arguments, vec![]);
+        if cli.generate.is_stochastic() {
+            provenance.seed = Some(seed);
+        }
+        provenance.insert(&mut array_builder.attributes);
+    }
+    let mut array = array_builder.build(store.clone(), "/")?;
+
+    // Check the generator is compatible with the array
+    let generate = Generate::new(&cli.generate, seed, cli.concurrent_chunks);
+    let chunk_representation =
+        array.chunk_array_representation(&vec![0; array.dimensionality()])?;
+    generate.is_compatible(&chunk_representation, &chunk_representation)?;
+
+    // Generate chunks, then store array metadata to indicate completion
+    let start = std::time::Instant::now();
+    let bar = ProgressBar::new(0);
+    bar.set_style(bar_style_run());
+    let progress_callback = |stats: ProgressStats| progress_callback(stats, &bar);
+    let progress_callback = ProgressCallback::new(&progress_callback);
+    generate.generate(&mut array, &progress_callback)?;
+    bar.finish_and_clear();
+    array.store_metadata()?;
+    let duration_s = start.elapsed().as_secs_f32();
+
+    // Output stats
+    let duration_ms = duration_s * 1.0e3;
+    let bytes_generated = array.shape().iter().product::<u64>() * element_size as u64;
+    let size_out = store.size()?;
+    let relative_size = 100.0 * (size_out as f32 / bytes_generated as f32);
+    println!("Output {path_out:?} in {duration_ms:.2}ms ({gbs:.2} GB/s) [{bytes_generated} -> {size_out} ({relative_size:.2}%)]",
+        gbs = (bytes_generated as f32 * 1e-9) / duration_s,
+        bytes_generated = DecimalBytes(bytes_generated),
+        size_out = DecimalBytes(size_out),
+    );
+
+    Ok(())
+}
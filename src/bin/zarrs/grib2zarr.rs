@@ -0,0 +1,468 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    ffi::OsString,
+    io::BufReader,
+    path::PathBuf,
+};
+
+use clap::Parser;
+use indicatif::{DecimalBytes, ProgressBar, ProgressStyle};
+use serde_json::json;
+use zarrs::{
+    array::{DataType, DimensionName},
+    array_subset::ArraySubset,
+    storage::ListableStorageTraits,
+};
+use zarrs_tools::{
+    filesystem_store, get_array_builder, DirectIo, Provenance, ThreadsArgs, ZarrEncodingArgs,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_grib2zarr";
+
+/// Convert GRIB2 messages to a Zarr V3 array with dimensions (time, level, y, x).
+///
+/// Messages of a single parameter are stacked by their reference/forecast time and the value of their first fixed surface.
+/// Time and level combinations without a message are set to the fill value.
+/// The data type is float32 and a NaN fill value is recommended, as missing grid points are decoded as NaN.
+///
+/// The time and level coordinates are written to the array attributes under `grib`.
+#[derive(Parser)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Cli {
+    /// The parameter to convert, as a comma separated discipline, category, and number (e.g. 0,0,0 for temperature).
+    ///
+    /// Required if the input contains more than one parameter.
+    #[arg(long, value_delimiter = ',', num_args = 3)]
+    parameter: Option<Vec<u8>>,
+
+    #[command(flatten)]
+    encoding: ZarrEncodingArgs,
+
+    /// Do not write provenance metadata (the tool, version, arguments, inputs, and timestamp) to the output attributes.
+    #[arg(long, default_value_t = false)]
+    no_provenance: bool,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=write`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "write", default_value_t = DirectIo::Off)]
+    direct_io: DirectIo,
+
+    /// The output directory for the zarr array.
+    out: PathBuf,
+
+    /// The input GRIB2 files.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+/// The position of a GRIB2 submessage in the input files.
+struct MessageIndex {
+    input: usize,
+    message: (usize, usize),
+    time: TimeKey,
+    level: LevelKey,
+}
+
+/// The reference time (RFC 3339) and forecast time (value and unit) of a message.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct TimeKey {
+    reference_time: String,
+    forecast_time: Option<(u32, String)>,
+}
+
+/// The value of the first fixed surface of a message.
+#[derive(Clone, Copy, PartialEq)]
+struct LevelKey(f64);
+
+impl Eq for LevelKey {}
+
+impl std::hash::Hash for LevelKey {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl PartialOrd for LevelKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for LevelKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Format a GRIB2 reference time as RFC 3339 (e.g. `2024-01-02T06:00:00Z`).
+///
+/// Returns [`None`] if the date or time is out of range.
+fn rfc3339(time: &grib::UtcDateTime) -> Option<String> {
+    let valid = (1..=12).contains(&time.month)
+        && (1..=31).contains(&time.day)
+        && time.hour < 24
+        && time.minute < 60
+        && time.second < 61;
+    valid.then(|| {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            time.year, time.month, time.day, time.hour, time.minute, time.second
+        )
+    })
+}
+
+fn open_grib(
+    path: &PathBuf,
+) -> anyhow::Result<grib::Grib2<grib::SeekableGrib2Reader<BufReader<std::fs::File>>>> {
+    let file = BufReader::new(std::fs::File::open(path)?);
+    grib::from_reader(file).map_err(|err| anyhow::anyhow!("failed to read {path:?}: {err}"))
+}
+
+pub fn main(arguments: Vec<OsString>) -> anyhow::Result<()> {
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, &arguments);
+    cli.threads.clone().install(|| run(cli, &arguments))
+}
+
+fn run(cli: Cli, arguments: &[OsString]) -> anyhow::Result<()> {
+    // Index the messages of the selected parameter
+    let mut messages = vec![];
+    let mut parameters = BTreeSet::new();
+    let mut grid_shape = None;
+    let mut surface_type = None;
+    for (input, path) in cli.inputs.iter().enumerate() {
+        let grib2 = open_grib(path)?;
+        for (message, submessage) in grib2.iter() {
+            let prod_def = submessage.prod_def();
+            let parameter = [
+                submessage.indicator().discipline,
+                prod_def.parameter_category().unwrap_or(255),
+                prod_def.parameter_number().unwrap_or(255),
+            ];
+            parameters.insert(parameter);
+            if cli
+                .parameter
+                .as_ref()
+                .is_some_and(|selected| selected.as_slice() != parameter)
+            {
+                continue;
+            }
+
+            let shape = submessage
+                .grid_shape()
+                .map_err(|err| anyhow::anyhow!("failed to read {path:?}: {err}"))?;
+            if *grid_shape.get_or_insert(shape) != shape {
+                anyhow::bail!(
+                    "messages have inconsistent grid shapes ({grid_shape:?} and {shape:?})"
+                );
+            }
+
+            let Some(grib::SectionBody::Section1(identification)) = &submessage.1.body.body else {
+                anyhow::bail!("failed to read {path:?}: missing identification section");
+            };
+            let reference_time =
+                rfc3339(&identification.ref_time_unchecked()).ok_or_else(|| {
+                    anyhow::anyhow!("failed to read {path:?}: invalid reference time")
+                })?;
+            let time = TimeKey {
+                reference_time,
+                forecast_time: prod_def.forecast_time().map(|forecast_time| {
+                    (forecast_time.value, format!("{:?}", forecast_time.unit))
+                }),
+            };
+            let level = match prod_def.fixed_surfaces() {
+                Some((surface, _)) => {
+                    if *surface_type.get_or_insert(surface.surface_type) != surface.surface_type {
+                        anyhow::bail!("messages have inconsistent fixed surface types");
+                    }
+                    LevelKey(surface.value())
+                }
+                None => LevelKey(0.0),
+            };
+            messages.push(MessageIndex {
+                input,
+                message,
+                time,
+                level,
+            });
+        }
+    }
+    if cli.parameter.is_none() && parameters.len() > 1 {
+        anyhow::bail!(
+            "the input contains multiple parameters, select one with --parameter: {}",
+            parameters
+                .iter()
+                .map(|[d, c, n]| format!("{d},{c},{n}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+    }
+    let Some((nx, ny)) = grid_shape else {
+        anyhow::bail!("no messages of the selected parameter were found");
+    };
+
+    // Get the time and level coordinates
+    let times: Vec<TimeKey> = messages
+        .iter()
+        .map(|message| message.time.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let levels: Vec<LevelKey> = messages
+        .iter()
+        .map(|message| message.level)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let time_index: HashMap<&TimeKey, u64> = times.iter().zip(0..).collect();
+    let level_index: HashMap<LevelKey, u64> = levels.iter().copied().zip(0..).collect();
+    let mut positions = messages
+        .iter()
+        .map(|message| (time_index[&message.time], level_index[&message.level]))
+        .collect::<Vec<_>>();
+    positions.sort_unstable();
+    if positions.windows(2).any(|w| w[0] == w[1]) {
+        anyhow::bail!("multiple messages of the selected parameter have the same time and level");
+    }
+
+    // Create the array
+    let path_out = cli.out.as_path();
+    let store = std::sync::Arc::new(filesystem_store(path_out, cli.direct_io.write())?);
+    let array_shape = [
+        times.len() as u64,
+        levels.len() as u64,
+        ny as u64,
+        nx as u64,
+    ];
+    let dimension_names = ["time", "level", "y", "x"].map(DimensionName::new).to_vec();
+    let mut array_builder = get_array_builder(
+        &cli.encoding,
+        &array_shape,
+        DataType::Float32,
+        Some(dimension_names),
+    )?;
+    let parameter = parameters.first().copied().unwrap_or_default();
+    let parameter = cli.parameter.as_deref().map_or(parameter, |parameter| {
+        [parameter[0], parameter[1], parameter[2]]
+    });
+    array_builder.attributes.insert(
+        "grib".to_string(),
+        json!({
+            "discipline": parameter[0],
+            "parameter_category": parameter[1],
+            "parameter_number": parameter[2],
+            "reference_time": times.iter().map(|time| &time.reference_time).collect::<Vec<_>>(),
+            "forecast_time": times.iter().map(|time| time.forecast_time.as_ref().map(|(value, _)| value)).collect::<Vec<_>>(),
+            "forecast_time_unit": times.iter().map(|time| time.forecast_time.as_ref().map(|(_, unit)| unit)).collect::<Vec<_>>(),
+            "level": levels.iter().map(|level| level.0).collect::<Vec<_>>(),
+            "level_type": surface_type,
+        }),
+    );
+    if !cli.no_provenance {
+        let inputs = cli
+            .inputs
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        Provenance::new(NAME, arguments, inputs).insert(&mut array_builder.attributes);
+    }
+    let array = array_builder.build(store.clone(), "/")?;
+    array.store_metadata()?;
+
+    // Decode and store each message
+    let start = std::time::Instant::now();
+    let bar = ProgressBar::new(messages.len() as u64);
+    bar.set_style(
+        ProgressStyle::with_template("[{elapsed_precise}] [{bar}] ({pos}/{len}, ETA {eta})")
+            .unwrap(),
+    );
+    let mut bytes_decoded = 0;
+    for (input, path) in cli.inputs.iter().enumerate() {
+        let grib2 = open_grib(path)?;
+        let selected: HashMap<(usize, usize), &MessageIndex> = messages
+            .iter()
+            .filter(|message| message.input == input)
+            .map(|message| (message.message, message))
+            .collect();
+        for (index, submessage) in grib2.iter() {
+            let Some(message) = selected.get(&index) else {
+                continue;
+            };
+            let elements: Vec<f32> = grib::Grib2SubmessageDecoder::from(submessage)
+                .and_then(|decoder| decoder.dispatch().map(Iterator::collect))
+                .map_err(|err| anyhow::anyhow!("failed to decode {path:?}: {err}"))?;
+            let subset = ArraySubset::new_with_ranges(&[
+                time_index[&message.time]..time_index[&message.time] + 1,
+                level_index[&message.level]..level_index[&message.level] + 1,
+                0..ny as u64,
+                0..nx as u64,
+            ]);
+            array.store_array_subset_elements::<f32>(&subset, &elements)?;
+            bytes_decoded += elements.len() * std::mem::size_of::<f32>();
+            bar.inc(1);
+        }
+    }
+    bar.finish_and_clear();
+    let duration_s = start.elapsed().as_secs_f32();
+
+    // Output stats
+    let size_out = store.size()?;
+    println!(
+        "Output {path_out:?} with shape {array_shape:?} in {duration_ms:.2}ms [{bytes_decoded} -> {size_out}]",
+        duration_ms = duration_s * 1.0e3,
+        bytes_decoded = DecimalBytes(bytes_decoded as u64),
+        size_out = DecimalBytes(size_out),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::{array::Array, filesystem::FilesystemStore};
+
+    use super::*;
+
+    /// Encode a GRIB2 message of temperature (0,0,0) on a 3x2 latitude/longitude grid with simple packing.
+    ///
+    /// The message has a reference time of `2024-01-02T<hour>:00:00Z`, a forecast time of `forecast_hours`, and is at the isobaric surface `level` (Pa).
+    /// The values are `reference_value + values[i]`.
+    fn grib2_message(
+        hour: u8,
+        forecast_hours: u32,
+        level: u32,
+        reference_value: f32,
+        values: &[u8; 6],
+    ) -> Vec<u8> {
+        fn section(number: u8, payload: &[u8]) -> Vec<u8> {
+            let mut section = ((payload.len() + 5) as u32).to_be_bytes().to_vec();
+            section.push(number);
+            section.extend_from_slice(payload);
+            section
+        }
+        // Identification: centre, sub-centre, tables, significance of reference time, reference time, status, type
+        let mut identification = vec![0, 7, 0, 0, 2, 1, 1];
+        identification.extend_from_slice(&2024u16.to_be_bytes());
+        identification.extend_from_slice(&[1, 2, hour, 0, 0, 0, 1]);
+        // Grid definition: template 3.0 with 3x2 grid points
+        let mut grid = vec![0];
+        grid.extend_from_slice(&6u32.to_be_bytes());
+        grid.extend_from_slice(&[0, 0, 0, 0]);
+        grid.extend_from_slice(&[6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for value in [3u32, 2, 0, 0xFFFF_FFFF, 2_000_000, 0] {
+            grid.extend_from_slice(&value.to_be_bytes());
+        }
+        grid.push(0x30);
+        for value in [1_000_000u32, 2_000_000, 1_000_000, 1_000_000] {
+            grid.extend_from_slice(&value.to_be_bytes());
+        }
+        grid.push(0);
+        // Product definition: template 4.0 with a forecast time in hours and an isobaric surface
+        let mut product = vec![0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 1];
+        product.extend_from_slice(&forecast_hours.to_be_bytes());
+        product.extend_from_slice(&[100, 0]);
+        product.extend_from_slice(&level.to_be_bytes());
+        product.extend_from_slice(&[255, 0, 0, 0, 0, 0]);
+        // Data representation: template 5.0 with 8 bit values and no scaling
+        let mut representation = 6u32.to_be_bytes().to_vec();
+        representation.extend_from_slice(&[0, 0]);
+        representation.extend_from_slice(&reference_value.to_be_bytes());
+        representation.extend_from_slice(&[0, 0, 0, 0, 8, 0]);
+
+        let mut sections = [
+            section(1, &identification),
+            section(3, &grid),
+            section(4, &product),
+            section(5, &representation),
+            section(6, &[255]),
+            section(7, values),
+        ]
+        .concat();
+        sections.extend_from_slice(b"7777");
+        let mut message = b"GRIB\0\0\0\x02".to_vec();
+        message.extend_from_slice(&((sections.len() + 16) as u64).to_be_bytes());
+        message.extend_from_slice(&sections);
+        message
+    }
+
+    #[test]
+    fn grib2zarr() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let input_0 = dir.path().join("0.grib2");
+        let input_1 = dir.path().join("1.grib2");
+        std::fs::write(
+            &input_0,
+            [
+                grib2_message(0, 6, 85000, 250.0, &[0, 1, 2, 3, 4, 5]),
+                grib2_message(0, 6, 50000, 230.0, &[10, 11, 12, 13, 14, 15]),
+            ]
+            .concat(),
+        )?;
+        // The second reference time has no 85000 Pa message
+        std::fs::write(
+            &input_1,
+            grib2_message(12, 0, 50000, 240.0, &[5, 4, 3, 2, 1, 0]),
+        )?;
+        let out = dir.path().join("out.zarr");
+        // As run by the multicall binary
+        let arguments: Vec<OsString> = vec![
+            "zarrs grib2zarr".into(),
+            "--chunk-shape=1,1,2,3".into(),
+            r#"--fill-value="NaN""#.into(),
+            out.clone().into_os_string(),
+            input_0.into_os_string(),
+            input_1.into_os_string(),
+        ];
+        main(arguments.clone())?;
+
+        let array = Array::open(std::sync::Arc::new(FilesystemStore::new(&out)?), "/")?;
+        assert_eq!(array.shape(), [2, 2, 2, 3]);
+        assert_eq!(array.data_type(), &DataType::Float32);
+        assert_eq!(
+            array
+                .dimension_names()
+                .as_ref()
+                .map(|names| names.iter().map(|name| name.as_str()).collect::<Vec<_>>()),
+            Some(vec![Some("time"), Some("level"), Some("y"), Some("x")])
+        );
+        let grib = &array.attributes()["grib"];
+        assert_eq!(
+            grib["reference_time"],
+            json!(["2024-01-02T00:00:00Z", "2024-01-02T12:00:00Z"])
+        );
+        assert_eq!(grib["forecast_time"], json!([6, 0]));
+        assert_eq!(grib["level"], json!([50000.0, 85000.0]));
+        assert_eq!(grib["level_type"], json!(100));
+        assert_eq!(
+            [
+                &grib["discipline"],
+                &grib["parameter_category"],
+                &grib["parameter_number"]
+            ],
+            [&json!(0); 3]
+        );
+
+        // The provenance records the parsed arguments
+        assert_eq!(
+            array.attributes()[zarrs_tools::PROVENANCE_ATTRIBUTE]["arguments"],
+            json!(arguments
+                .iter()
+                .map(|argument| argument.to_string_lossy())
+                .collect::<Vec<_>>())
+        );
+
+        let elements = array.retrieve_array_subset_elements::<f32>(&array.subset_all())?;
+        assert_eq!(
+            elements[..12],
+            [240.0, 241.0, 242.0, 243.0, 244.0, 245.0, 250.0, 251.0, 252.0, 253.0, 254.0, 255.0]
+        );
+        assert_eq!(elements[12..18], [245.0, 244.0, 243.0, 242.0, 241.0, 240.0]);
+        assert!(elements[18..].iter().all(|element| element.is_nan()));
+        Ok(())
+    }
+}
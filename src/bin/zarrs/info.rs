@@ -0,0 +1,521 @@
+use std::{error::Error, ffi::OsString, process::ExitCode, sync::Arc};
+
+use clap::{Parser, Subcommand};
+use rayon::current_num_threads;
+use serde::Serialize;
+use serde_json::Number;
+use zarrs::{
+    array::{Array, ArrayMetadataOptions, DimensionName, FillValueMetadataV3},
+    array_subset::ArraySubset,
+    group::{Group, GroupMetadataOptions},
+    metadata::v3::array::data_type::DataTypeMetadataV3,
+    node::{Node, NodeMetadata},
+};
+use zarrs_tools::{
+    filesystem_store,
+    info::{ChunkStatisticsHistogram, LintFinding, LintSeverity, OmeMultiscale},
+    sample_chunk_indices, DirectIo, ThreadsArgs,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_info";
+
+/// Get information about a Zarr array or group.
+///
+/// Outputs are JSON encoded.
+#[derive(Parser)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Cli {
+    /// The maximum number of chunks concurrently processed.
+    ///
+    /// Defaults to the RAYON_NUM_THREADS environment variable or the number of logical CPUs.
+    /// Consider reducing this for images with large chunk sizes or on systems with low memory availability.
+    #[arg(long)]
+    chunk_limit: Option<usize>,
+
+    /// Path to the Zarr input array or group.
+    path: std::path::PathBuf,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=both`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "both", default_value_t = DirectIo::Off)]
+    direct_io: DirectIo,
+
+    #[command(subcommand)]
+    command: InfoCommand,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+#[derive(Parser, Debug)]
+struct SampleParams {
+    /// Only read N randomly chosen chunks, so the result is approximate.
+    ///
+    /// All chunks are read (and the result is exact) if the array has no more than N chunks.
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    sample_chunks: Option<usize>,
+
+    /// The random seed for `--sample-chunks`.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// Details of the chunk sample of an approximate result.
+#[derive(Serialize)]
+struct Sample {
+    approximate: bool,
+    num_chunks: u64,
+    num_chunks_sampled: usize,
+    seed: u64,
+}
+
+impl SampleParams {
+    /// Returns the indices of the chunks to read, and the [`Sample`] if they are a sample of the chunks of `array`.
+    fn chunk_indices<TStorage: ?Sized>(
+        &self,
+        array: &Array<TStorage>,
+    ) -> (Vec<Vec<u64>>, Option<Sample>) {
+        let chunks = ArraySubset::new_with_shape(array.chunk_grid_shape().unwrap());
+        let num_chunks = chunks.num_elements();
+        match self.sample_chunks {
+            Some(sample) if (sample as u64) < num_chunks => {
+                let seed = self.seed.unwrap_or_else(|| fastrand::u64(..));
+                let mut rng = fastrand::Rng::with_seed(seed);
+                let chunk_indices = sample_chunk_indices(&chunks, sample, &mut rng);
+                let sample = Sample {
+                    approximate: true,
+                    num_chunks,
+                    num_chunks_sampled: chunk_indices.len(),
+                    seed,
+                };
+                (chunk_indices, Some(sample))
+            }
+            _ => (chunks.indices().into_iter().collect(), None),
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+struct RangeParams {
+    #[command(flatten)]
+    sample: SampleParams,
+}
+
+#[derive(Parser, Debug)]
+struct HistogramParams {
+    n_bins: usize,
+    min: f64,
+    max: f64,
+
+    #[command(flatten)]
+    sample: SampleParams,
+}
+
+#[derive(Parser, Debug)]
+struct CodecMetricsParams {
+    /// The number of chunks to sample.
+    ///
+    /// Chunks are sampled evenly throughout the array.
+    #[arg(long, default_value_t = 10)]
+    num_chunks: usize,
+}
+
+#[derive(Parser, Debug)]
+struct ChunkStatisticsParams {
+    /// The output path of the chunk statistics sidecar group.
+    output: std::path::PathBuf,
+
+    /// Compute a histogram of each chunk with this number of bins.
+    #[arg(
+        long,
+        requires_all = ["histogram_min", "histogram_max"],
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    histogram_bins: Option<usize>,
+
+    /// The lower edge of the first histogram bin.
+    #[arg(long, allow_hyphen_values = true)]
+    histogram_min: Option<f64>,
+
+    /// The upper edge of the last histogram bin.
+    #[arg(long, allow_hyphen_values = true)]
+    histogram_max: Option<f64>,
+}
+
+#[derive(Parser, Debug)]
+struct LintParams {
+    /// Exit with a failure status if there are findings of at least this severity.
+    #[arg(long, value_enum, default_value_t = LintSeverity::Error)]
+    fail_on: LintSeverity,
+}
+
+#[derive(Parser, Debug)]
+struct OmeParams {
+    /// Print a table of the levels of each multiscale rather than JSON.
+    #[arg(long)]
+    table: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum InfoCommand {
+    /// Get the array/group metadata.
+    Metadata,
+    /// Get the array/group metadata (interpreted as V3).
+    MetadataV3,
+    /// Get the array/group attributes.
+    Attributes,
+    /// Get the array shape.
+    Shape,
+    /// Get the array data type.
+    DataType,
+    /// Get the array fill value.
+    FillValue,
+    /// Get the array dimension names.
+    DimensionNames,
+    /// Get the array data range.
+    ///
+    /// With `--sample-chunks`, the range of a random sample of chunks is an approximation and the output includes "approximate": true.
+    Range(RangeParams),
+    /// Get the array data histogram.
+    ///
+    /// With `--sample-chunks`, the histogram of a random sample of chunks is an approximation and the output includes "approximate": true.
+    Histogram(HistogramParams),
+    /// Get the decode time and sizes of each codec for a sample of chunks.
+    CodecMetrics(CodecMetricsParams),
+    /// Write the minimum and maximum (and optionally a histogram) of each chunk to a sidecar group.
+    ///
+    /// The sidecar group contains "min" and "max" arrays with the shape of the chunk grid, and a "histogram" array with an additional trailing bin axis.
+    /// This is an index that viewers and query engines can use to skip chunks (e.g. when thresholding).
+    ChunkStatistics(ChunkStatisticsParams),
+    /// Check the array/group metadata for spec violations and interoperability hazards.
+    ///
+    /// Findings have a severity (info, warning, or error), a code, the node path, and a JSON pointer to the offending metadata.
+    /// The datasets of OME-Zarr multiscales groups are also checked.
+    Lint(LintParams),
+    /// Summarise the levels of the multiscales of an OME-Zarr group.
+    ///
+    /// Each level has a path, shape, chunk shape, shard shape, scale and translation (composed from the coordinate transformations), and stored size in bytes.
+    /// Exits with a failure status if a dataset does not exist or does not match the axes.
+    Ome(OmeParams),
+    /// List the store keys of the array/group (and its children) with their sizes in bytes.
+    ListKeys,
+    /// Get the storage usage (keys and bytes) of the array/group and each of its children (similar to du).
+    ///
+    /// The keys and bytes of a node exclude those of its children, and the total keys and bytes include them.
+    Du,
+}
+
+pub fn main(args: Vec<OsString>) -> ExitCode {
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, args);
+    match cli.threads.clone().install(|| run(cli)) {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            println!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn group_metadata_options_v3() -> GroupMetadataOptions {
+    let mut metadata_options = GroupMetadataOptions::default();
+    metadata_options.set_metadata_convert_version(zarrs::config::MetadataConvertVersion::V3);
+    metadata_options
+}
+
+fn array_metadata_options_v3() -> ArrayMetadataOptions {
+    let mut metadata_options = ArrayMetadataOptions::default();
+    metadata_options.set_metadata_convert_version(zarrs::config::MetadataConvertVersion::V3);
+    metadata_options.set_include_zarrs_metadata(false);
+    metadata_options
+}
+
+/// Print a table of the levels of each multiscale, followed by its validation errors.
+fn print_ome_table(multiscales: &[OmeMultiscale]) {
+    fn format_shape<T: std::fmt::Display>(shape: Option<&Vec<T>>) -> String {
+        shape.map_or_else(
+            || "-".to_string(),
+            |shape| {
+                shape
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("x")
+            },
+        )
+    }
+    for (i, multiscale) in multiscales.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!(
+            "{} (axes: {})",
+            multiscale.name.as_deref().unwrap_or("multiscale"),
+            multiscale.axes.join(", ")
+        );
+        let mut rows = vec![[
+            "path".to_string(),
+            "shape".to_string(),
+            "chunk".to_string(),
+            "shard".to_string(),
+            "scale".to_string(),
+            "size (MB)".to_string(),
+        ]];
+        for level in &multiscale.levels {
+            rows.push([
+                level.path.clone(),
+                format_shape(level.shape.as_ref()),
+                format_shape(level.chunk_shape.as_ref()),
+                format_shape(level.shard_shape.as_ref()),
+                format_shape(level.scale.as_ref()),
+                format!("{:.2}", level.stored_bytes as f64 / 1e6),
+            ]);
+        }
+        let widths: [usize; 6] =
+            std::array::from_fn(|column| rows.iter().map(|row| row[column].len()).max().unwrap());
+        for row in rows {
+            let row = std::iter::zip(row, widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>();
+            println!("{}", row.join("  ").trim_end());
+        }
+        for error in &multiscale.errors {
+            println!("error: {error}");
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, Box<dyn Error + Send + Sync>> {
+    let chunk_limit = cli.chunk_limit.unwrap_or_else(current_num_threads);
+
+    let storage = Arc::new(filesystem_store(&cli.path, cli.direct_io.read())?);
+
+    if let InfoCommand::Lint(lint_params) = &cli.command {
+        let findings = zarrs_tools::info::lint(storage.as_ref(), "/")?;
+        let count = |severity: LintSeverity| {
+            findings
+                .iter()
+                .filter(|finding| finding.severity == severity)
+                .count()
+        };
+        #[derive(Serialize)]
+        struct Lint {
+            errors: usize,
+            warnings: usize,
+            findings: Vec<LintFinding>,
+        }
+        let failed = findings
+            .iter()
+            .any(|finding| finding.severity >= lint_params.fail_on);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&Lint {
+                errors: count(LintSeverity::Error),
+                warnings: count(LintSeverity::Warning),
+                findings,
+            })?
+        );
+        return Ok(if failed {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        });
+    }
+
+    if let InfoCommand::Ome(ome_params) = &cli.command {
+        let multiscales = zarrs_tools::info::summarise_ome_multiscales(&storage, "/")?;
+        if ome_params.table {
+            print_ome_table(&multiscales);
+        } else {
+            println!("{}", serde_json::to_string_pretty(&multiscales)?);
+        }
+        return Ok(
+            if multiscales
+                .iter()
+                .any(|multiscale| !multiscale.errors.is_empty())
+            {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            },
+        );
+    }
+
+    match &cli.command {
+        InfoCommand::ListKeys => {
+            let keys = zarrs_tools::info::list_keys(storage.as_ref(), "/")?;
+            println!("{}", serde_json::to_string_pretty(&keys)?);
+            return Ok(ExitCode::SUCCESS);
+        }
+        InfoCommand::Du => {
+            let usage = zarrs_tools::info::calculate_storage_usage(storage.as_ref(), "/")?;
+            println!("{}", serde_json::to_string_pretty(&usage)?);
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => {}
+    }
+
+    let node = Node::open(&storage, "/")?;
+    if let NodeMetadata::Group(_) = node.metadata() {
+        // Group handling
+        let group = Group::open(storage.clone(), "/")?;
+        match cli.command {
+            InfoCommand::Metadata => {
+                println!("{}", serde_json::to_string_pretty(group.metadata())?);
+            }
+            InfoCommand::MetadataV3 => {
+                let metadata = group.metadata_opt(&group_metadata_options_v3());
+                println!("{}", serde_json::to_string_pretty(&metadata)?);
+            }
+            InfoCommand::Attributes => {
+                println!("{}", serde_json::to_string_pretty(group.attributes())?);
+            }
+            _ => {
+                println!("The {:?} command is not supported for a group", cli.command)
+            }
+        }
+    } else {
+        // Array handling
+        let array = Array::open(storage.clone(), "/")?;
+        match cli.command {
+            InfoCommand::Metadata => {
+                println!("{}", serde_json::to_string_pretty(array.metadata())?);
+            }
+            InfoCommand::MetadataV3 => {
+                let metadata = array.metadata_opt(&array_metadata_options_v3());
+                println!("{}", serde_json::to_string_pretty(&metadata)?);
+            }
+            InfoCommand::Attributes => {
+                println!("{}", serde_json::to_string_pretty(array.attributes())?);
+            }
+            InfoCommand::Shape => {
+                #[derive(Serialize)]
+                struct Shape {
+                    shape: Vec<u64>,
+                }
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&Shape {
+                        shape: array.shape().to_vec()
+                    })?
+                );
+            }
+            InfoCommand::DataType => {
+                #[derive(Serialize)]
+                struct DataType {
+                    data_type: DataTypeMetadataV3,
+                }
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&DataType {
+                        data_type: array.data_type().metadata()
+                    })?
+                );
+            }
+            InfoCommand::FillValue => {
+                #[derive(Serialize)]
+                struct FillValue {
+                    fill_value: FillValueMetadataV3,
+                }
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&FillValue {
+                        fill_value: array.data_type().metadata_fill_value(array.fill_value())
+                    })?
+                );
+            }
+            InfoCommand::DimensionNames => {
+                #[derive(Serialize)]
+                struct DimensionNames {
+                    dimension_names: Option<Vec<DimensionName>>,
+                }
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&DimensionNames {
+                        dimension_names: array.dimension_names().clone()
+                    })?
+                );
+            }
+            InfoCommand::Range(range_params) => {
+                let (chunk_indices, sample) = range_params.sample.chunk_indices(&array);
+                let (min, max) =
+                    zarrs_tools::info::calculate_range(&array, &chunk_indices, chunk_limit)?;
+                #[derive(Serialize)]
+                struct MinMax {
+                    min: Number,
+                    max: Number,
+                    #[serde(flatten)]
+                    sample: Option<Sample>,
+                }
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&MinMax { min, max, sample })?
+                );
+            }
+            InfoCommand::Histogram(histogram_params) => {
+                let (chunk_indices, sample) = histogram_params.sample.chunk_indices(&array);
+                let (bin_edges, hist) = zarrs_tools::info::calculate_histogram(
+                    &array,
+                    histogram_params.n_bins,
+                    histogram_params.min,
+                    histogram_params.max,
+                    &chunk_indices,
+                    chunk_limit,
+                )?;
+                #[derive(Serialize)]
+                struct Histogram {
+                    bin_edges: Vec<f64>,
+                    hist: Vec<u64>,
+                    #[serde(flatten)]
+                    sample: Option<Sample>,
+                }
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&Histogram {
+                        bin_edges,
+                        hist,
+                        sample
+                    })?
+                );
+            }
+            InfoCommand::CodecMetrics(codec_metrics_params) => {
+                let metrics = zarrs_tools::info::calculate_codec_metrics(
+                    &array,
+                    codec_metrics_params.num_chunks,
+                )?;
+                println!("{}", serde_json::to_string_pretty(&metrics)?);
+            }
+            InfoCommand::ChunkStatistics(chunk_statistics_params) => {
+                let store_out = Arc::new(filesystem_store(
+                    &chunk_statistics_params.output,
+                    cli.direct_io.write(),
+                )?);
+                let histogram =
+                    chunk_statistics_params
+                        .histogram_bins
+                        .map(|n_bins| ChunkStatisticsHistogram {
+                            n_bins,
+                            min: chunk_statistics_params.histogram_min.unwrap(),
+                            max: chunk_statistics_params.histogram_max.unwrap(),
+                        });
+                let statistics = zarrs_tools::info::calculate_chunk_statistics(
+                    &array,
+                    store_out,
+                    histogram,
+                    chunk_limit,
+                )?;
+                println!("{}", serde_json::to_string_pretty(&statistics)?);
+            }
+            InfoCommand::Lint(_)
+            | InfoCommand::Ome(_)
+            | InfoCommand::ListKeys
+            | InfoCommand::Du => {
+                unreachable!("handled for arrays and groups")
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
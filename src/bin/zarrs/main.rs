@@ -0,0 +1,294 @@
+use std::{
+    ffi::OsString,
+    path::Path,
+    process::{ExitCode, Termination},
+};
+
+use clap::{Arg, Command, CommandFactory};
+use clap_complete::Shell;
+
+#[cfg(all(feature = "async", feature = "benchmark"))]
+mod benchmark_read_async;
+#[cfg(all(feature = "async", feature = "benchmark"))]
+mod benchmark_read_async_as_sync;
+#[cfg(feature = "benchmark")]
+mod benchmark_read_sync;
+#[cfg(feature = "benchmark")]
+mod benchmark_suite;
+#[cfg(feature = "binary2zarr")]
+mod binary2zarr;
+#[cfg(feature = "filter")]
+mod filter;
+#[cfg(feature = "gc")]
+mod gc;
+#[cfg(feature = "generate")]
+mod generate;
+#[cfg(feature = "grib2zarr")]
+mod grib2zarr;
+#[cfg(feature = "info")]
+mod info;
+#[cfg(feature = "ome")]
+mod ome;
+#[cfg(feature = "query")]
+mod query;
+mod reencode;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "validate")]
+mod validate;
+#[cfg(feature = "zarr2image")]
+mod zarr2image;
+#[cfg(feature = "zarr2points")]
+mod zarr2points;
+
+/// A subcommand of `zarrs`, which is equivalent to the `zarrs_<name>` binary.
+struct Subcommand {
+    /// The subcommand name.
+    name: &'static str,
+    /// Returns the command of the subcommand, for help.
+    command: fn() -> Command,
+    /// Run the subcommand with command line arguments, where the first argument is the binary name.
+    main: fn(Vec<OsString>) -> ExitCode,
+}
+
+macro_rules! subcommand {
+    ($module:ident, $cli:ident) => {
+        Subcommand {
+            name: stringify!($module),
+            command: <$module::$cli as CommandFactory>::command,
+            main: |args| $module::main(args).report(),
+        }
+    };
+}
+
+/// Returns the subcommands of the enabled features.
+fn subcommands() -> Vec<Subcommand> {
+    #[allow(unused_mut)]
+    let mut subcommands = vec![subcommand!(reencode, Args)];
+    #[cfg(feature = "filter")]
+    subcommands.push(subcommand!(filter, Cli));
+    #[cfg(feature = "ome")]
+    subcommands.push(subcommand!(ome, Cli));
+    #[cfg(feature = "info")]
+    subcommands.push(subcommand!(info, Cli));
+    #[cfg(feature = "validate")]
+    subcommands.push(subcommand!(validate, Args));
+    #[cfg(feature = "binary2zarr")]
+    subcommands.push(subcommand!(binary2zarr, Cli));
+    #[cfg(feature = "grib2zarr")]
+    subcommands.push(subcommand!(grib2zarr, Cli));
+    #[cfg(feature = "generate")]
+    subcommands.push(subcommand!(generate, Cli));
+    #[cfg(feature = "serve")]
+    subcommands.push(subcommand!(serve, Cli));
+    #[cfg(feature = "gc")]
+    subcommands.push(subcommand!(gc, Args));
+    #[cfg(feature = "zarr2image")]
+    subcommands.push(subcommand!(zarr2image, Cli));
+    #[cfg(feature = "zarr2points")]
+    subcommands.push(subcommand!(zarr2points, Cli));
+    #[cfg(feature = "query")]
+    subcommands.push(subcommand!(query, Cli));
+    #[cfg(feature = "benchmark")]
+    subcommands.push(subcommand!(benchmark_read_sync, Args));
+    #[cfg(feature = "benchmark")]
+    subcommands.push(subcommand!(benchmark_suite, Args));
+    #[cfg(all(feature = "async", feature = "benchmark"))]
+    subcommands.push(subcommand!(benchmark_read_async, Args));
+    #[cfg(all(feature = "async", feature = "benchmark"))]
+    subcommands.push(subcommand!(benchmark_read_async_as_sync, Args));
+    subcommands
+}
+
+/// The `zarrs` command, which lists the subcommands.
+fn command(subcommands: &[Subcommand]) -> Command {
+    Command::new("zarrs")
+        .version(zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)
+        .about("Tools for creating and manipulating Zarr V3 data.")
+        .long_about(
+            "Tools for creating and manipulating Zarr V3 data.
+
+Each subcommand is equivalent to the zarrs_<subcommand> binary (e.g. `zarrs reencode` is `zarrs_reencode`).
+Options preceding the subcommand are passed to it (e.g. `zarrs --threads 8 reencode in.zarr out.zarr`).
+If this binary is invoked as zarrs_<subcommand> (e.g. through a symbolic link), it runs that subcommand.",
+        )
+        .subcommand_required(true)
+        .subcommands(
+            subcommands
+                .iter()
+                .map(|subcommand| (subcommand.command)().name(subcommand.name)),
+        )
+        .subcommand(completions_command())
+}
+
+/// The `completions` subcommand.
+fn completions_command() -> Command {
+    Command::new("completions")
+        .about("Generate shell completions for zarrs and the zarrs_<subcommand> binaries")
+        .long_about(
+            "Generate shell completions for zarrs and the zarrs_<subcommand> binaries.
+
+Examples:
+  source <(zarrs completions bash)
+  zarrs completions fish zarrs zarrs_reencode > ~/.config/fish/completions/zarrs.fish",
+        )
+        .arg(
+            Arg::new("shell")
+                .required(true)
+                .value_parser(clap::value_parser!(Shell))
+                .help("The shell"),
+        )
+        .arg(
+            Arg::new("binary").num_args(0..).help(
+                "The binaries to complete (e.g. zarrs zarrs_reencode). Defaults to all binaries",
+            ),
+        )
+}
+
+/// Write the shell completions of `zarrs` and the `zarrs_<subcommand>` binaries to standard output.
+fn completions(subcommands: &[Subcommand], args: Vec<OsString>) -> ExitCode {
+    let matches = completions_command().get_matches_from(args);
+    let shell = *matches.get_one::<Shell>("shell").unwrap();
+    let mut commands = vec![("zarrs".to_string(), command(subcommands))];
+    commands.extend(
+        subcommands
+            .iter()
+            .map(|subcommand| (format!("zarrs_{}", subcommand.name), (subcommand.command)())),
+    );
+    let binaries = matches
+        .get_many::<String>("binary")
+        .map(|binaries| binaries.cloned().collect::<Vec<_>>())
+        .unwrap_or_else(|| commands.iter().map(|(name, _)| name.clone()).collect());
+    for binary in &binaries {
+        let Some((name, command)) = commands.iter_mut().find(|(name, _)| name == binary) else {
+            eprintln!("error: {binary} is not a binary with the enabled features");
+            return ExitCode::FAILURE;
+        };
+        clap_complete::generate(shell, command, name.as_str(), &mut std::io::stdout());
+    }
+    ExitCode::SUCCESS
+}
+
+/// Returns the position of the subcommand in `args`, where the first argument is the binary name.
+///
+/// The values of options preceding the subcommand are skipped, so an option value equal to a subcommand name (e.g. `zarrs --cache-dir reencode reencode in.zarr out.zarr`) is not the subcommand.
+fn subcommand_position(args: &[OsString], subcommands: &[Subcommand]) -> Option<usize> {
+    let commands: Vec<Command> = subcommands
+        .iter()
+        .map(|subcommand| (subcommand.command)())
+        .collect();
+    let takes_value = |arg: &str| {
+        commands
+            .iter()
+            .flat_map(Command::get_arguments)
+            .filter(|option| {
+                if let Some(long) = arg.strip_prefix("--") {
+                    option.get_long() == Some(long)
+                } else if let Some(short) = arg.strip_prefix('-') {
+                    let mut chars = short.chars();
+                    chars
+                        .next()
+                        .is_some_and(|short| option.get_short() == Some(short))
+                        && chars.next().is_none()
+                } else {
+                    false
+                }
+            })
+            .any(|option| {
+                option.get_action().takes_values()
+                    && !option.is_require_equals_set()
+                    && option
+                        .get_num_args()
+                        .map_or(true, |num_args| num_args.min_values() > 0)
+            })
+    };
+
+    let mut skip_value = false;
+    for (position, arg) in args.iter().enumerate().skip(1) {
+        if std::mem::take(&mut skip_value) {
+            continue;
+        }
+        let Some(arg) = arg.to_str() else {
+            continue;
+        };
+        if subcommands.iter().any(|subcommand| subcommand.name == arg) {
+            return Some(position);
+        }
+        skip_value = takes_value(arg);
+    }
+    None
+}
+
+fn main() -> ExitCode {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let subcommands = subcommands();
+    let find = |name: &str| {
+        subcommands
+            .iter()
+            .find(|subcommand| subcommand.name == name)
+    };
+
+    // Run the subcommand named by the binary (e.g. a `zarrs_reencode` symbolic link to `zarrs`)
+    let bin_name = args
+        .first()
+        .and_then(|arg| Path::new(arg).file_stem())
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    if let Some(subcommand) = bin_name.strip_prefix("zarrs_").and_then(find) {
+        return (subcommand.main)(args);
+    }
+
+    if args.get(1).is_some_and(|arg| arg == "completions") {
+        let mut completions_args = vec![OsString::from("zarrs completions")];
+        completions_args.extend_from_slice(&args[2..]);
+        return completions(&subcommands, completions_args);
+    }
+
+    // Find the subcommand, the arguments preceding it are global options
+    let position = subcommand_position(&args, &subcommands);
+    let is_help_or_version = args
+        .get(1..position.unwrap_or(args.len()))
+        .unwrap_or_default()
+        .iter()
+        .any(|arg| matches!(arg.to_str(), Some("-h" | "--help" | "-V" | "--version")));
+    match position {
+        Some(position) if !is_help_or_version => {
+            let subcommand = find(args[position].to_str().unwrap()).unwrap();
+            let mut subcommand_args = vec![OsString::from(format!("zarrs {}", subcommand.name))];
+            subcommand_args.extend_from_slice(&args[1..position]);
+            subcommand_args.extend_from_slice(&args[position + 1..]);
+            (subcommand.main)(subcommand_args)
+        }
+        _ => {
+            // Print help, the version, or an error
+            command(&subcommands).get_matches_from(args.iter().take(2));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(args: &[&str]) -> Option<usize> {
+        let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        subcommand_position(&args, &subcommands())
+    }
+
+    #[test]
+    fn subcommand_position_global_options() {
+        assert_eq!(position(&["zarrs", "reencode", "in", "out"]), Some(1));
+        assert_eq!(position(&["zarrs", "--threads", "8", "reencode"]), Some(3));
+        assert_eq!(
+            position(&["zarrs", "--cache-dir", "reencode", "reencode", "in", "out"]),
+            Some(3)
+        );
+        assert_eq!(
+            position(&["zarrs", "--cache-dir=reencode", "reencode", "in", "out"]),
+            Some(2)
+        );
+        assert_eq!(position(&["zarrs", "--cache-dir", "reencode"]), None);
+        assert_eq!(position(&["zarrs", "--help"]), None);
+    }
+}
@@ -0,0 +1,2043 @@
+use std::{
+    error::Error,
+    ffi::OsString,
+    hash::Hash,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use clap::Parser;
+use half::{bf16, f16};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use itertools::Itertools;
+use num_traits::AsPrimitive;
+use ome_zarr_metadata::v0_5::{
+    Axis, AxisType, AxisUnit, CoordinateTransform, CoordinateTransformScale,
+    CoordinateTransformTranslation, MultiscaleImageDataset, MultiscaleImageMetadata, Plate,
+    PlateAcquisition, PlateColumn, PlateRow, PlateWell, Well, WellImage,
+};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use zarrs::{
+    array::{
+        codec::CodecOptions, Array, ArrayBuilder, ArrayCodecTraits, ArrayMetadata,
+        ChunkRepresentation, DataType, Element, ElementOwned, FillValue,
+    },
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+    group::{Group, GroupMetadata, GroupMetadataV3},
+    storage::{StorePrefix, WritableStorageTraits},
+};
+use zarrs_tools::{
+    cast::ElementCastArgs,
+    chunk_encoding_unchanged, concurrent_target, copy_file, do_copy_chunk_files, do_copy_chunks,
+    do_reencode, filesystem_store,
+    filter::{
+        filters::{downsample::Downsample, gaussian::Gaussian},
+        ArraySubsetOverlap, FilterError, FilterTraits,
+    },
+    get_array_builder_reencode,
+    n5::{is_n5_dataset, N5Store},
+    ome_xml::{upsert_image, OmeXmlPixels, OME_XML_PATH},
+    physical_metadata::PhysicalMetadata,
+    progress::{
+        cancel_on_signal, duration_smoothed, PipelineProgress, Progress, ProgressCallback,
+        ProgressMode, ProgressStats,
+    },
+    verify_copy, CacheSize, ChunkSize, CopyVerification, DirectIo, FileCopyMode, Provenance,
+    ReencodeValidation, ThreadsArgs, WriteShapeArgs, ZarrReEncodingChangeType, ZarrReencodingArgs,
+};
+
+#[derive(clap::ValueEnum, Debug, Clone)]
+enum OutputExists {
+    /// Erase the output
+    Erase,
+    /// Overwrite existing files.
+    /// Useful if the output includes additional non-zarr files to be preserved.
+    /// May fail if changing the encoding.
+    Overwrite,
+    /// Exit if the output already exists
+    Exit,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(clap::ValueEnum, Debug, Clone)]
+enum OMEZarrVersion {
+    /// https://ngff.openmicroscopy.org/0.5/
+    #[value(name = "0.5")]
+    V0_5,
+}
+
+impl std::fmt::Display for OMEZarrVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OMEZarrVersion::V0_5 => write!(f, "0.5"),
+        }
+    }
+}
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_ome";
+
+/// The path of the label confidence group in the output group.
+const LABEL_CONFIDENCE_PATH: &str = "label_confidence";
+
+/// Convert a Zarr array to an OME-Zarr multiscales hierarchy.
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Cli {
+    /// The input array path.
+    ///
+    /// This can be a Zarr array or an N5 dataset (raw or gzip compression).
+    input: PathBuf,
+    /// The output group path.
+    ///
+    /// This is the plate group path if --plate-well is set, or the root group path if --bioformats2raw-series is set.
+    output: PathBuf,
+
+    // The OME-Zarr version.
+    #[arg(long, default_value_t = OMEZarrVersion::V0_5)]
+    ome_zarr_version: OMEZarrVersion,
+
+    /// The downsample factor per axis, comma separated.
+    ///
+    /// Defaults to 2 on each axis.
+    #[arg(value_delimiter = ',')]
+    downsample_factor: Option<Vec<u64>>,
+
+    /// Maximum number of downsample levels.
+    #[arg(long, default_value_t = 10)]
+    max_levels: usize,
+
+    /// Stop downsampling after a level with a size of at most this along every spatial axis.
+    ///
+    /// Spatial axes have a space unit (see --physical-units), or are all axes that are not time or channel axes if no axis has a space unit.
+    #[arg(long)]
+    min_size: Option<u64>,
+
+    /// The shape of each downsample level, as a JSON list of shapes.
+    ///
+    /// Each level shape must be an integer downsampling of the previous level (or input) shape, where each axis is divided by a factor and rounded down (to at least 1).
+    /// For example, '[[1,512,512],[1,256,256],[1,64,64]]' creates exactly three downsample levels.
+    #[arg(
+        long,
+        value_parser = parse_level_shapes,
+        conflicts_with_all = ["downsample_factor", "downsample_schedule", "max_levels", "min_size"]
+    )]
+    levels: Option<LevelShapes>,
+
+    /// A per-level downsample schedule, as a JSON list of downsample factors per axis.
+    ///
+    /// Level i (from 1) is downsampled from level i-1 by the i-th factor of the schedule, and the last factor is repeated for subsequent levels.
+    /// For example, '[[1,1,2,2],[1,1,2,2],[1,2,2,2]]' downsamples only the last two axes for the first two levels, and then the last three axes.
+    #[arg(long, value_parser = parse_downsample_schedule, conflicts_with = "downsample_factor")]
+    downsample_schedule: Option<DownsampleSchedule>,
+
+    /// Physical size per axis, comma separated.
+    ///
+    /// Defaults to the physical size of the input, from OME-Zarr multiscales metadata of the input (or its parent group), `spacing` attributes, or OME-XML pixel sizes in the input attributes.
+    /// A translation of the input is retained.
+    #[arg(long, value_delimiter = ',')]
+    physical_size: Option<Vec<f32>>,
+
+    /// Physical units per axis, comma separated.
+    ///
+    /// Set to "channel" for a channel axis.
+    /// Defaults to the physical units of the input.
+    #[arg(long, value_delimiter = ',')]
+    physical_units: Option<Vec<String>>,
+
+    /// OME Zarr dataset name.
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Set to true for discrete data.
+    ///
+    /// Performs majority downsampling instead of creating a Gaussian image pyramid or mean downsampling.
+    #[arg(long)]
+    discrete: bool,
+
+    /// Weight the votes of majority downsampling by the overlap of input elements with the footprint of each output element.
+    ///
+    /// The footprint of an output element spans the input shape divided by the output shape along each axis.
+    /// If the downsample factor does not divide the shape, elements straddling footprints split their vote and no input elements are discarded.
+    /// This reduces blocky artefacts in label pyramids.
+    #[arg(long, requires = "discrete")]
+    weighted_mode: bool,
+
+    /// Write the label confidence of each downsample level to a float32 array at label_confidence/<LEVEL> in the output group.
+    ///
+    /// The label confidence is the fraction of the weighted votes cast for the output label.
+    #[arg(long, requires = "weighted_mode")]
+    label_confidence: bool,
+
+    /// The Gaussian "sigma" to apply when creating a Gaussian image pyramid per axis, comma separated.
+    ///
+    /// This is typically set to 0.5 times the downsample factor for each axis.
+    /// If omitted, then mean downsampling is applied.
+    ///
+    /// Ignored for discrete data.
+    #[arg(long, value_delimiter = ',')]
+    gaussian_sigma: Option<Vec<f32>>,
+
+    /// The Gaussian kernel half size per axis, comma separated.
+    ///
+    /// If omitted, defaults to ceil(3 * sigma).
+    ///
+    /// Ignored for discrete data or if --gaussian-sigma is not set.
+    #[arg(long, value_delimiter = ',')]
+    gaussian_kernel_half_size: Option<Vec<u64>>,
+
+    /// A validity mask array path, with the same shape as the input.
+    ///
+    /// The mask data type must be bool or uint8, with nonzero elements marking valid input elements.
+    /// Mean and Gaussian downsampling ignore invalid elements and renormalise the remaining weights.
+    /// Output elements without any valid input elements are set to the fill value, and are treated as invalid when downsampling subsequent levels.
+    ///
+    /// Not supported for discrete data.
+    #[arg(long, conflicts_with = "discrete")]
+    mask: Option<PathBuf>,
+
+    /// Treat input elements equal to the fill value (or NaN) as invalid.
+    ///
+    /// Mean and Gaussian downsampling ignore invalid elements and renormalise the remaining weights.
+    /// Output elements without any valid input elements are set to the fill value.
+    /// Only the input is compared with the fill value, so downsampled elements equal to the fill value remain valid in subsequent levels.
+    ///
+    /// Not supported for discrete data.
+    #[arg(long, conflicts_with = "discrete")]
+    mask_fill_value: bool,
+
+    /// Exclude NaN elements from mean and Gaussian downsampling.
+    ///
+    /// Output elements are NaN only if all contributing input elements are NaN.
+    ///
+    /// Not supported for discrete data or with a mask.
+    #[arg(long, conflicts_with_all = ["discrete", "mask", "mask_fill_value"])]
+    nan_aware: bool,
+
+    /// Behaviour if the output exists.
+    #[arg(long)]
+    #[clap(value_enum, default_value_t=OutputExists::Erase)]
+    exists: OutputExists,
+
+    /// Attributes (optional).
+    ///
+    /// JSON holding group attributes.
+    #[arg(long)]
+    group_attributes: Option<String>,
+
+    #[command(flatten)]
+    reencoding: ZarrReencodingArgs,
+
+    /// The maximum number of chunks concurrently processed.
+    ///
+    /// By default, this is set to the number of CPUs.
+    /// Consider reducing this for images with large chunk sizes or on systems with low memory availability.
+    #[arg(long)]
+    chunk_limit: Option<usize>,
+
+    /// Write shape options.
+    ///
+    /// The write shape applies to every level.
+    #[command(flatten)]
+    write: WriteShapeArgs,
+
+    /// The method used to copy files if the input is copied without reencoding.
+    ///
+    /// Hard links and reflinks require the input and output to be on the same filesystem.
+    #[arg(long, value_enum, default_value_t = FileCopyMode::Copy)]
+    copy_mode: FileCopyMode,
+
+    /// The verification of files if the input is copied without reencoding.
+    ///
+    /// Files are copied concurrently (up to the chunk limit) with their modification times preserved.
+    #[arg(long, value_enum, default_value_t = CopyVerification::None)]
+    verify_copy: CopyVerification,
+
+    /// The unit of progress bars.
+    ///
+    /// Bytes-based progress is proportional to the decoded bytes read, and falls back to steps (e.g. chunks) if unsupported.
+    #[arg(long, value_enum, default_value_t = ProgressMode::Steps)]
+    progress_mode: ProgressMode,
+
+    /// Do not write provenance metadata (the tool, version, arguments, inputs, and timestamp) to the output attributes.
+    #[arg(long, default_value_t = false)]
+    no_provenance: bool,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=both`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "both", default_value_t = DirectIo::Off)]
+    direct_io: DirectIo,
+
+    /// Write the image as a field of a well in a high content screening (HCS) plate, specified as ROW/COLUMN (e.g. A/1).
+    ///
+    /// The image is written to <OUTPUT>/<ROW>/<COLUMN>/<PLATE_FIELD>.
+    /// The plate and well metadata are created or updated, so a plate can be assembled by running zarrs_ome per field.
+    /// Row and column names must be alphanumeric.
+    #[arg(long, value_parser = parse_plate_well, conflicts_with = "bioformats2raw_series")]
+    plate_well: Option<PlateWellPath>,
+
+    /// The field of view path within the well. Must be alphanumeric.
+    #[arg(long, default_value = "0", value_parser = parse_alphanumeric, requires = "plate_well")]
+    plate_field: String,
+
+    /// The plate name.
+    #[arg(long, requires = "plate_well")]
+    plate_name: Option<String>,
+
+    /// The plate acquisition identifier of the field of view.
+    #[arg(long, requires = "plate_well")]
+    plate_acquisition: Option<u64>,
+
+    /// Write the image as a series of a "bioformats2raw.layout" hierarchy.
+    ///
+    /// The image is written to <OUTPUT>/<SERIES>.
+    /// The root group "bioformats2raw.layout" metadata and the OME group series metadata are created or updated.
+    #[arg(long)]
+    bioformats2raw_series: Option<u64>,
+
+    /// Generate or update the companion OME-XML (OME/METADATA.ome.xml) of a "bioformats2raw.layout" hierarchy.
+    ///
+    /// The image of the series (with the ID Image:<SERIES>) is inserted or replaced, with the sizes, pixel type, and physical sizes of the output.
+    /// Axes must be named t, c, z, y, or x.
+    #[arg(
+        long,
+        requires = "bioformats2raw_series",
+        conflicts_with = "ome_xml_file"
+    )]
+    ome_xml: bool,
+
+    /// Copy a companion OME-XML file to OME/METADATA.ome.xml of a "bioformats2raw.layout" hierarchy.
+    ///
+    /// The sizes and pixel type of the image of the series (with the ID Image:<SERIES>) must match the output, otherwise the differences are reported.
+    #[arg(long, requires = "bioformats2raw_series")]
+    ome_xml_file: Option<PathBuf>,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+/// The path of a plate well.
+#[derive(Debug, Clone)]
+struct PlateWellPath {
+    row: String,
+    column: String,
+}
+
+fn parse_alphanumeric(name: &str) -> std::io::Result<String> {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Ok(name.to_string())
+    } else {
+        Err(std::io::Error::other(format!(
+            "{name:?} is not a valid name, it must be non-empty and alphanumeric"
+        )))
+    }
+}
+
+fn parse_plate_well(well: &str) -> std::io::Result<PlateWellPath> {
+    let (row, column) = well.split_once('/').ok_or_else(|| {
+        std::io::Error::other(format!(
+            "{well:?} is not a valid well, expected ROW/COLUMN (e.g. A/1)"
+        ))
+    })?;
+    Ok(PlateWellPath {
+        row: parse_alphanumeric(row)?,
+        column: parse_alphanumeric(column)?,
+    })
+}
+
+/// The companion OME-XML of an image series.
+struct OmeXmlCompanion {
+    pixels: OmeXmlPixels,
+    physical_sizes: [Option<(f64, String)>; 3],
+    name: String,
+    /// A provided OME-XML document, which has been validated.
+    xml: Option<String>,
+}
+
+/// A per-level downsample schedule.
+#[derive(Debug, Clone)]
+struct DownsampleSchedule(Vec<Vec<u64>>);
+
+impl DownsampleSchedule {
+    /// Returns the downsample factor of level `i` (from 1).
+    fn level(&self, i: usize) -> &[u64] {
+        &self.0[(i - 1).min(self.0.len() - 1)]
+    }
+
+    /// Returns the downsample factors of the levels after level `i`.
+    fn remaining(&self, i: usize) -> &[Vec<u64>] {
+        &self.0[i.min(self.0.len() - 1)..]
+    }
+}
+
+/// The shapes of the downsample levels.
+#[derive(Debug, Clone)]
+struct LevelShapes(Vec<Vec<u64>>);
+
+impl LevelShapes {
+    /// Returns the downsample schedule that creates the level shapes from an input with `shape`.
+    fn downsample_schedule(&self, shape: &[u64]) -> Result<DownsampleSchedule, String> {
+        let mut shape = shape.to_vec();
+        let mut factors = Vec::with_capacity(self.0.len());
+        for (i, level_shape) in self.0.iter().enumerate() {
+            if level_shape.len() != shape.len() {
+                return Err(format!(
+                    "the shape {level_shape:?} of level {} does not match the input dimensionality {}",
+                    i + 1,
+                    shape.len()
+                ));
+            }
+            let factor = std::iter::zip(&shape, level_shape)
+                .map(|(size, level_size)| size / level_size)
+                .collect_vec();
+            if std::iter::zip(&shape, level_shape).zip(&factor).any(
+                |((size, level_size), factor)| {
+                    *factor == 0 || std::cmp::max(size / factor, 1) != *level_size
+                },
+            ) {
+                return Err(format!(
+                    "the shape {level_shape:?} of level {} is not an integer downsampling of {shape:?}",
+                    i + 1
+                ));
+            }
+            factors.push(factor);
+            shape.clone_from(level_shape);
+        }
+        Ok(DownsampleSchedule(factors))
+    }
+}
+
+fn parse_level_shapes(levels: &str) -> std::io::Result<LevelShapes> {
+    let shapes: Vec<Vec<u64>> = serde_json::from_str(levels).map_err(|err| {
+        std::io::Error::other(format!(
+            "{levels:?} is not a valid list of level shapes: {err}"
+        ))
+    })?;
+    if shapes.is_empty() || shapes.iter().flatten().any(|size| *size == 0) {
+        return Err(std::io::Error::other(format!(
+            "{levels:?} is not a valid list of level shapes, expected a non-empty list of shapes with non-zero sizes"
+        )));
+    }
+    Ok(LevelShapes(shapes))
+}
+
+fn parse_downsample_schedule(schedule: &str) -> std::io::Result<DownsampleSchedule> {
+    let factors: Vec<Vec<u64>> = serde_json::from_str(schedule).map_err(|err| {
+        std::io::Error::other(format!(
+            "{schedule:?} is not a valid downsample schedule: {err}"
+        ))
+    })?;
+    if factors.is_empty() || factors.iter().flatten().any(|factor| *factor == 0) {
+        return Err(std::io::Error::other(format!(
+            "{schedule:?} is not a valid downsample schedule, expected a non-empty list of non-zero downsample factors"
+        )));
+    }
+    Ok(DownsampleSchedule(factors))
+}
+
+fn bar_style_run() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "[{elapsed_precise}/{duration_smoothed}] {bar:40.black/bold} {pos}/{len} ({percent}%) {prefix} {msg}",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+    .with_key("duration_smoothed", duration_smoothed)
+}
+
+fn bar_style_pipeline() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "[{elapsed_precise}/{duration_smoothed}] {bar:40.black/bold} ({percent}%) {prefix} {msg}",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+    .with_key("duration_smoothed", duration_smoothed)
+}
+
+fn bar_style_finish() -> ProgressStyle {
+    ProgressStyle::with_template("[{elapsed_precise}/{elapsed_precise}] {prefix} {msg}")
+        .unwrap_or(ProgressStyle::default_bar())
+}
+
+/// Returns the paths of the files in `src` (recursively), relative to `src`.
+fn list_files(src: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(src.join(&dir))? {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            if entry.file_type()?.is_file() {
+                files.push(path);
+            } else {
+                dirs.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Copy the files in `src` to `dst` with up to `concurrent_files` files copied concurrently.
+///
+/// Modification times are preserved, and copies are verified with a [`CopyVerification`].
+fn copy_dir(
+    src: &Path,
+    dst: &Path,
+    mode: FileCopyMode,
+    verification: CopyVerification,
+    concurrent_files: usize,
+    progress_callback: &ProgressCallback,
+) -> std::io::Result<()> {
+    let files = list_files(src)?;
+    let num_bytes = files
+        .iter()
+        .map(|file| Ok(usize::try_from(std::fs::metadata(src.join(file))?.len()).unwrap()))
+        .sum::<std::io::Result<usize>>()?;
+    let progress = Progress::new_with_bytes(files.len(), Some(num_bytes), progress_callback);
+    std::fs::create_dir_all(dst)?;
+    rayon_iter_concurrent_limit::iter_concurrent_limit!(
+        concurrent_files,
+        files,
+        try_for_each,
+        |file: PathBuf| {
+            let (src, dst) = (src.join(&file), dst.join(&file));
+            // Metadata is always copied, since it is updated in place
+            let mode = if file.file_name().is_some_and(|name| name == "zarr.json") {
+                FileCopyMode::Copy
+            } else {
+                mode
+            };
+            progress.write(|| {
+                if let Some(parent) = dst.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                copy_file(&src, &dst, mode)?;
+                if mode != FileCopyMode::Hardlink {
+                    // A hard link shares the modification time of the source
+                    let modified = std::fs::metadata(&src)?.modified()?;
+                    std::fs::File::options()
+                        .write(true)
+                        .open(&dst)?
+                        .set_modified(modified)?;
+                }
+                Ok::<_, std::io::Error>(())
+            })?;
+            progress.read(|| verify_copy(&src, &dst, verification))?;
+            let size = usize::try_from(std::fs::metadata(&dst)?.len()).unwrap();
+            progress.add_bytes_read(size);
+            progress.add_bytes_written(size);
+            progress.next()?;
+            Ok::<_, std::io::Error>(())
+        }
+    )
+}
+
+/// The validity mask of input elements when downsampling.
+///
+/// The validity of [`Mask::Array`] and [`Mask::FillValue`] is determined from level 0, and an element of a downsampled level is valid if any element of its footprint in level 0 is valid.
+/// The factor is the downsample factor of the input level relative to level 0.
+#[derive(Clone, Copy)]
+enum Mask<'a> {
+    /// All elements are valid.
+    None,
+    /// Elements are valid where a mask array of level 0 is nonzero.
+    Array(&'a Array<FilesystemStore>, &'a [u64]),
+    /// Elements are valid where level 0 is not the fill value or NaN.
+    FillValue(&'a Array<FilesystemStore>, &'a [u64]),
+    /// Elements are valid where they are not NaN.
+    Nan,
+}
+
+impl Mask<'_> {
+    /// Return the output value of elements without any valid input elements.
+    fn invalid_value<T: bytemuck::Pod>(self, array_output: &Array<FilesystemStore>) -> T
+    where
+        f64: AsPrimitive<T>,
+    {
+        match self {
+            Mask::Nan => f64::NAN.as_(),
+            _ => fill_value::<T>(array_output),
+        }
+    }
+}
+
+/// Return the fill value of `array` as a `T`.
+fn fill_value<T: bytemuck::Pod>(array: &Array<FilesystemStore>) -> T {
+    bytemuck::pod_read_unaligned(array.fill_value().as_ne_bytes())
+}
+
+/// Return the subset of level 0 covered by `subset` of a level downsampled from level 0 by `factor`.
+fn level0_subset(level0_shape: &[u64], subset: &ArraySubset, factor: &[u64]) -> ArraySubset {
+    let start = std::iter::zip(subset.start(), factor).map(|(start, factor)| start * factor);
+    let end = itertools::izip!(subset.end_exc(), factor, level0_shape)
+        .map(|(end, factor, shape)| std::cmp::min(end * factor, *shape));
+    ArraySubset::new_with_start_end_exc(start.collect(), end.collect()).unwrap()
+}
+
+/// Downsample the level 0 validity `valid` by `factor` to `shape`, where an element is valid if any element of its footprint is valid.
+fn downsample_valid(
+    valid: ndarray::ArrayD<bool>,
+    factor: &[u64],
+    shape: &[u64],
+) -> ndarray::ArrayD<bool> {
+    if factor.iter().all(|factor| *factor == 1) {
+        return valid;
+    }
+    let shape = shape.iter().map(|size| *size as usize).collect_vec();
+    let mut downsampled = ndarray::ArrayD::from_elem(shape, false);
+    let mut index = ndarray::IxDyn(&vec![0; factor.len()]);
+    for (level0_index, valid) in valid.indexed_iter() {
+        if *valid {
+            for (axis, factor) in factor.iter().enumerate() {
+                index[axis] = level0_index[axis] / *factor as usize;
+            }
+            downsampled[&index] = true;
+        }
+    }
+    downsampled
+}
+
+/// Return the validity of the elements of `input_chunk` in `subset` of the input level, or [`None`] if all elements are valid.
+fn retrieve_valid<T>(
+    mask: Mask,
+    subset: &ArraySubset,
+    input_chunk: &ndarray::ArrayD<T>,
+    progress: &Progress,
+) -> Result<Option<ndarray::ArrayD<bool>>, FilterError>
+where
+    T: Element + ElementOwned + bytemuck::Pod + PartialEq + AsPrimitive<f64>,
+{
+    match mask {
+        Mask::None => Ok(None),
+        Mask::Array(mask_array, factor) => {
+            let level0_subset = level0_subset(mask_array.shape(), subset, factor);
+            let valid = progress.read(|| match mask_array.data_type() {
+                DataType::Bool => mask_array.retrieve_array_subset_ndarray::<bool>(&level0_subset),
+                DataType::UInt8 => Ok(mask_array
+                    .retrieve_array_subset_ndarray::<u8>(&level0_subset)?
+                    .mapv(|v| v != 0)),
+                _ => unreachable!("the mask data type is validated on open"),
+            })?;
+            Ok(Some(progress.process_step("mask", || {
+                downsample_valid(valid, factor, subset.shape())
+            })))
+        }
+        Mask::FillValue(array0, factor) => {
+            let fill_value = fill_value::<T>(array0);
+            let is_valid = |v: &T| *v != fill_value && !AsPrimitive::<f64>::as_(*v).is_nan();
+            if factor.iter().all(|factor| *factor == 1) {
+                // The input is level 0
+                return Ok(Some(
+                    progress.process_step("mask", || input_chunk.map(is_valid)),
+                ));
+            }
+            let level0_subset = level0_subset(array0.shape(), subset, factor);
+            let level0_chunk =
+                progress.read(|| array0.retrieve_array_subset_ndarray::<T>(&level0_subset))?;
+            Ok(Some(progress.process_step("mask", || {
+                downsample_valid(level0_chunk.map(is_valid), factor, subset.shape())
+            })))
+        }
+        Mask::Nan => Ok(Some(progress.process_step("mask", || {
+            input_chunk.map(|v| !AsPrimitive::<f64>::as_(*v).is_nan())
+        }))),
+    }
+}
+
+fn apply_chunk_discrete<T>(
+    array_input: &Array<FilesystemStore>,
+    array_output: &Array<FilesystemStore>,
+    output_subset: &ArraySubset,
+    downsample_filter: &Downsample,
+    codec_options: &CodecOptions,
+    progress: &Progress,
+) -> Result<(), FilterError>
+where
+    T: Element + ElementOwned + Copy + Send + Sync + Eq + PartialEq + Hash + AsPrimitive<T>,
+{
+    let downsample_input_subset =
+        downsample_filter.input_subset(array_input.shape(), output_subset);
+    let output_chunk = {
+        let input_chunk = progress
+            .read(|| array_input.retrieve_array_subset_ndarray::<T>(&downsample_input_subset))?;
+        downsample_filter.apply_ndarray_discrete(input_chunk, progress)
+    };
+    progress.write(|| {
+        array_output.store_array_subset_ndarray_opt::<T, _>(
+            output_subset.start(),
+            output_chunk,
+            codec_options,
+        )
+    })?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_chunk_discrete_weighted<T>(
+    array_input: &Array<FilesystemStore>,
+    array_output: &Array<FilesystemStore>,
+    array_confidence: Option<&Array<FilesystemStore>>,
+    output_subset: &ArraySubset,
+    downsample_filter: &Downsample,
+    codec_options: &CodecOptions,
+    progress: &Progress,
+) -> Result<(), FilterError>
+where
+    T: Element + ElementOwned + Copy + Send + Sync + Eq + PartialEq + Hash + AsPrimitive<T>,
+{
+    let downsample_input_subset =
+        downsample_filter.input_subset_weighted(array_input.shape(), output_subset);
+    let (output_chunk, confidence_chunk) = {
+        let input_chunk = progress
+            .read(|| array_input.retrieve_array_subset_ndarray::<T>(&downsample_input_subset))?;
+        downsample_filter.apply_ndarray_discrete_weighted(
+            &input_chunk,
+            array_input.shape(),
+            output_subset,
+            progress,
+        )
+    };
+    progress.write(|| {
+        array_output.store_array_subset_ndarray_opt::<T, _>(
+            output_subset.start(),
+            output_chunk,
+            codec_options,
+        )
+    })?;
+    if let Some(array_confidence) = array_confidence {
+        progress.write(|| {
+            array_confidence.store_array_subset_ndarray_opt::<f32, _>(
+                output_subset.start(),
+                confidence_chunk,
+                codec_options,
+            )
+        })?;
+    }
+    Ok(())
+}
+
+fn apply_chunk_continuous<T>(
+    array_input: &Array<FilesystemStore>,
+    array_output: &Array<FilesystemStore>,
+    output_subset: &ArraySubset,
+    downsample_filter: &Downsample,
+    mask: Mask,
+    codec_options: &CodecOptions,
+    progress: &Progress,
+) -> Result<(), FilterError>
+where
+    T: Element
+        + ElementOwned
+        + bytemuck::Pod
+        + PartialEq
+        + Send
+        + Sync
+        + AsPrimitive<f64>
+        + std::iter::Sum,
+    f64: AsPrimitive<T>,
+{
+    let downsample_input_subset =
+        downsample_filter.input_subset(array_input.shape(), output_subset);
+    let output_chunk = {
+        let input_chunk = progress
+            .read(|| array_input.retrieve_array_subset_ndarray::<T>(&downsample_input_subset))?;
+        if let Some(valid) = retrieve_valid(mask, &downsample_input_subset, &input_chunk, progress)?
+        {
+            downsample_filter.apply_ndarray_continuous_masked(
+                input_chunk,
+                &valid,
+                mask.invalid_value::<T>(array_output),
+                progress,
+            )
+        } else {
+            downsample_filter.apply_ndarray_continuous(input_chunk, progress)
+        }
+    };
+    progress.write(|| {
+        array_output.store_array_subset_ndarray_opt::<T, _>(
+            output_subset.start(),
+            output_chunk,
+            codec_options,
+        )
+    })?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_chunk_continuous_gaussian<T>(
+    array_input: &Array<FilesystemStore>,
+    array_output: &Array<FilesystemStore>,
+    output_subset: &ArraySubset,
+    downsample_filter: &Downsample,
+    gaussian_filter: &Gaussian,
+    mask: Mask,
+    codec_options: &CodecOptions,
+    progress: &Progress,
+) -> Result<(), FilterError>
+where
+    T: Element
+        + ElementOwned
+        + bytemuck::Pod
+        + PartialEq
+        + Send
+        + Sync
+        + AsPrimitive<f32>
+        + AsPrimitive<f64>
+        + std::iter::Sum,
+    f64: AsPrimitive<T>,
+{
+    let downsample_input_subset =
+        downsample_filter.input_subset(array_input.shape(), output_subset);
+    let gaussian_subset_overlap = ArraySubsetOverlap::new(
+        array_input.shape(),
+        &downsample_input_subset,
+        gaussian_filter.kernel_half_size(),
+    );
+    let input_chunk = progress.read(|| {
+        array_input.retrieve_array_subset_ndarray::<T>(gaussian_subset_overlap.subset_input())
+    })?;
+    let valid = retrieve_valid(
+        mask,
+        gaussian_subset_overlap.subset_input(),
+        &input_chunk,
+        progress,
+    )?;
+    let output_chunk = if let Some(valid) = valid {
+        // Normalised convolution: smooth the valid elements and their weights, then divide
+        let gaussian_chunk = progress.process_step("gaussian", || {
+            let weights: ndarray::ArrayD<f32> = valid.map(|v| if *v { 1.0 } else { 0.0 });
+            let input_chunk: ndarray::ArrayD<f32> = ndarray::Zip::from(&input_chunk)
+                .and(&weights)
+                .map_collect(|x, w| AsPrimitive::<f32>::as_(*x) * w);
+            let input_chunk = gaussian_filter.apply_ndarray(input_chunk);
+            let weights = gaussian_filter.apply_ndarray(weights);
+            let output_chunk = ndarray::Zip::from(&input_chunk)
+                .and(&weights)
+                .map_collect(|x, w| if *w > 0.0 { x / w } else { 0.0 });
+            gaussian_subset_overlap.extract_subset(&output_chunk)
+        });
+        let valid = gaussian_subset_overlap.extract_subset(&valid);
+        downsample_filter.apply_ndarray_continuous_masked(
+            gaussian_chunk,
+            &valid,
+            mask.invalid_value::<T>(array_output),
+            progress,
+        )
+    } else {
+        let gaussian_chunk = progress.process_step("gaussian", || {
+            let input_chunk: ndarray::ArrayD<f32> = input_chunk.map(|x| x.as_()); // par?
+            let output_chunk = gaussian_filter.apply_ndarray(input_chunk);
+            gaussian_subset_overlap.extract_subset(&output_chunk)
+        });
+        downsample_filter.apply_ndarray_continuous(gaussian_chunk, progress)
+    };
+    progress.write(|| {
+        array_output.store_array_subset_ndarray_opt::<T, _>(
+            output_subset.start(),
+            output_chunk,
+            codec_options,
+        )
+    })?;
+    Ok(())
+}
+
+fn progress_callback(stats: ProgressStats, bar: &ProgressBar, mode: ProgressMode) {
+    let (position, length) = stats.position_length(mode);
+    bar.set_length(length);
+    bar.set_position(position);
+    if stats.process_steps.is_empty() {
+        bar.set_message(format!(
+            "rw:{:.2}/{:.2} p:{:.2}",
+            stats.read.as_secs_f32(),
+            stats.write.as_secs_f32(),
+            stats.process.as_secs_f32(),
+        ));
+    } else {
+        bar.set_message(format!(
+            "rw:{:.2}/{:.2} p:{:.2} ({})",
+            stats.read.as_secs_f32(),
+            stats.write.as_secs_f32(),
+            stats.process.as_secs_f32(),
+            stats.process_steps_message(),
+        ));
+    }
+}
+
+fn run(cli: Cli, arguments: &[OsString]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Stop at the next chunk on SIGINT/SIGTERM, so unfinished levels have no metadata
+    let cancelled = cancel_on_signal();
+    if cli.reencoding.removes_zarrs_metadata() {
+        zarrs::config::global_config_mut().set_include_zarrs_metadata(false);
+    }
+
+    println!("Input {:?}", cli.input);
+
+    // Get the output image path
+    let output = if let Some(plate_well) = &cli.plate_well {
+        cli.output
+            .join(&plate_well.row)
+            .join(&plate_well.column)
+            .join(&cli.plate_field)
+    } else if let Some(series) = cli.bioformats2raw_series {
+        cli.output.join(series.to_string())
+    } else {
+        cli.output.clone()
+    };
+    if cli.plate_well.is_some() || cli.bioformats2raw_series.is_some() {
+        // Check the root group is not an image before writing
+        let store = Arc::new(FilesystemStore::new(&cli.output)?);
+        get_ome_attributes(&open_or_new_group(&store, &cli.output, "/")?, &cli.output)?;
+    }
+
+    let start = std::time::Instant::now();
+
+    // An N5 input is read through a store presenting it as a Zarr array
+    let (array_in, array_in_n5) = if is_n5_dataset(&cli.input) {
+        let store_in = N5Store::new(&cli.input)?;
+        (None, Some(Array::open(store_in.into(), "/")?))
+    } else {
+        let store_in = filesystem_store(&cli.input, cli.direct_io.read())?;
+        (Some(Array::open(store_in.into(), "/")?), None)
+    };
+    let shape_in = array_in.as_ref().map_or_else(
+        || array_in_n5.as_ref().unwrap().shape().to_vec(),
+        |array_in| array_in.shape().to_vec(),
+    );
+
+    let num_levels = cli
+        .levels
+        .as_ref()
+        .map_or(cli.max_levels, |levels| levels.0.len());
+
+    let downsample_schedule = if let Some(levels) = &cli.levels {
+        levels
+            .downsample_schedule(&shape_in)
+            .map_err(FilterError::Other)?
+    } else {
+        cli.downsample_schedule.unwrap_or_else(|| {
+            DownsampleSchedule(vec![cli
+                .downsample_factor
+                .unwrap_or_else(|| vec![2; shape_in.len()])])
+        })
+    };
+    if let Some(factor) = downsample_schedule
+        .0
+        .iter()
+        .find(|factor| factor.len() != shape_in.len())
+    {
+        Err(FilterError::Other(format!(
+            "the downsample factor {factor:?} does not match the input dimensionality {}",
+            shape_in.len()
+        )))?;
+    }
+
+    // Estimate the work of each level (the number of elements read) for the overall progress
+    let mut level_shape = shape_in.clone();
+    let work = std::iter::once(shape_in.iter().product::<u64>() as f64)
+        .chain((1..=num_levels).map(|i| {
+            let work = level_shape.iter().product::<u64>() as f64;
+            level_shape = std::iter::zip(&level_shape, downsample_schedule.level(i))
+                .map(|(size, factor)| std::cmp::max(size / factor, 1))
+                .collect_vec();
+            work
+        }))
+        .collect_vec();
+    let pipeline = PipelineProgress::new(&work);
+
+    let multi_progress = MultiProgress::new();
+    let pipeline_bar = (num_levels > 0).then(|| {
+        let bar = multi_progress.add(ProgressBar::new(PipelineProgress::LENGTH));
+        bar.set_style(bar_style_pipeline());
+        bar.set_prefix("total");
+        bar
+    });
+    let bars = (0..=num_levels)
+        .map(|level| {
+            let bar = multi_progress.add(ProgressBar::new(1));
+            bar.set_style(bar_style_run());
+            if level == 0 {
+                bar.set_prefix(format!("0 {shape_in:?}"));
+            } else {
+                bar.set_prefix(format!("{}", level));
+            }
+            bar
+        })
+        .collect_vec();
+
+    // Update the overall progress given the fraction complete of a level
+    let pipeline_progress = |level: usize, fraction: f64| {
+        if let Some(pipeline_bar) = &pipeline_bar {
+            pipeline_bar.set_position(pipeline.position(level, fraction));
+            pipeline_bar.set_message(format!("level {level}/{num_levels}"));
+        }
+    };
+
+    let finish_step = |bar: &ProgressBar, path: &Path| {
+        bar.set_style(bar_style_finish());
+        bar.set_prefix(format!("{} {}", bar.prefix(), path.to_string_lossy()));
+        bar.abandon();
+    };
+
+    // Create group
+    let store = std::sync::Arc::new(FilesystemStore::new(&output)?);
+    let mut group = Group::new_with_metadata(
+        store.clone(),
+        "/",
+        GroupMetadata::V3(GroupMetadataV3::default()),
+    )?;
+    if let Some(attributes) = &cli.group_attributes {
+        let mut group_attributes: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(attributes).expect("Group attributes are invalid.");
+        group.attributes_mut().append(&mut group_attributes);
+    }
+
+    // Handle an existing output
+    match cli.exists {
+        OutputExists::Exit => {
+            if output.exists() {
+                Err(FilterError::Other("Output exists, exiting".to_string()))?;
+            }
+        }
+        OutputExists::Erase => {
+            store.erase_prefix(&StorePrefix::root()).unwrap();
+        }
+        OutputExists::Overwrite => {}
+    }
+
+    {
+        let bar = bars.first().unwrap();
+        bar.reset();
+
+        let output_0_path = output.join("0");
+        let progress_callback = |stats: ProgressStats| {
+            pipeline_progress(0, stats.fraction(cli.progress_mode));
+            progress_callback(stats, bar, cli.progress_mode);
+        };
+        let progress_callback =
+            ProgressCallback::new(&progress_callback).with_cancellation(cancelled);
+        if let Some(array_in) = &array_in_n5 {
+            // Reencode the N5 input, copying the encoded chunks if the encoding is unchanged
+            let store_out = Arc::new(filesystem_store(&output, cli.direct_io.write())?);
+            let array_out = get_array_builder_reencode(&cli.reencoding, array_in, None)?
+                .build(store_out.clone(), "/0")?;
+            if chunk_encoding_unchanged(array_in, &array_out) {
+                do_copy_chunks(
+                    array_in,
+                    &array_out,
+                    store_out.as_ref(),
+                    cli.chunk_limit,
+                    &progress_callback,
+                )?;
+            } else {
+                do_reencode(
+                    array_in,
+                    &array_out,
+                    ReencodeValidation::Off,
+                    cli.chunk_limit,
+                    &progress_callback,
+                    CacheSize::None,
+                    cli.write.write_shape.clone(),
+                    None,
+                    None,
+                    None,
+                )?;
+            }
+            array_out.store_metadata()?;
+        } else if let (ZarrReEncodingChangeType::None, Some(ArrayMetadata::V3(_))) = (
+            cli.reencoding.change_type(),
+            array_in.as_ref().map(Array::metadata),
+        ) {
+            // Copy full res input to output if it is Zarr V3 and does not need any changes
+            copy_dir(
+                &cli.input,
+                &output_0_path,
+                cli.copy_mode,
+                cli.verify_copy,
+                cli.chunk_limit.unwrap_or_else(concurrent_target).max(1),
+                &progress_callback,
+            )?;
+        } else {
+            let array_in = array_in.as_ref().unwrap();
+            let reencode = zarrs_tools::filter::filters::reencode::Reencode::new(
+                ElementCastArgs::default(),
+                cli.chunk_limit,
+                cli.write.clone(),
+            );
+            let store_out = Arc::new(filesystem_store(&output, cli.direct_io.write())?);
+            let mut array_out = reencode
+                .output_array_builder(array_in, &cli.reencoding)?
+                .build(store_out.clone(), "/0")?;
+            if chunk_encoding_unchanged(array_in, &array_out) {
+                // Copy the encoded chunks of the input if only the metadata changes
+                do_copy_chunk_files(
+                    array_in,
+                    &array_out,
+                    &cli.input,
+                    &output,
+                    cli.copy_mode,
+                    cli.chunk_limit,
+                    &progress_callback,
+                )?;
+            } else {
+                // Reencode the input
+                reencode.apply(array_in, &mut array_out, &progress_callback)?;
+            }
+            array_out.store_metadata()?;
+        }
+        finish_step(bar, &output_0_path);
+        pipeline_progress(0, 1.0);
+    }
+
+    // Setup attributes
+    let store = std::sync::Arc::new(FilesystemStore::new(&output)?);
+    // store.erase_prefix(&StorePrefix::root()).unwrap();
+    let mut array0 = Array::open(store.clone(), "/0")?;
+
+    // Physical metadata of the input (or its parent group), used if not set on the command line
+    let input_physical_metadata = {
+        let parent = cli
+            .input
+            .parent()
+            .and_then(|parent| FilesystemStore::new(parent).ok())
+            .and_then(|store| Group::open(Arc::new(store), "/").ok());
+        let name = cli.input.file_name().and_then(|name| name.to_str());
+        PhysicalMetadata::from_attributes(
+            array0.attributes(),
+            parent
+                .as_ref()
+                .zip(name)
+                .map(|(parent, name)| (parent.attributes(), name)),
+            array0.dimension_names().as_deref(),
+            array0.dimensionality(),
+        )
+    };
+
+    {
+        // Move array0 attributes to group
+        group.attributes_mut().append(array0.attributes_mut()); // this clears array0 attributes
+        group.attributes_mut().remove_entry("_zarrs");
+        array0.store_metadata()?;
+    }
+
+    // Initialise multiscales metadata
+    let mut axes: Vec<Axis> = Vec::with_capacity(array0.dimensionality());
+    let to_unit = |physical_unit: String| {
+        Some(
+            serde_json::from_value::<AxisUnit>(serde_json::json!(physical_unit))
+                .expect("Not a recognised physical unit"),
+        )
+    };
+    let physical_units = if let Some(physical_units) = cli.physical_units {
+        physical_units.into_iter().map(to_unit).collect_vec()
+    } else if let Some(units) = input_physical_metadata.units {
+        // Skip units of the input not recognised by OME-Zarr
+        units
+            .into_iter()
+            .map(|unit| unit.and_then(|unit| serde_json::from_value::<AxisUnit>(unit.into()).ok()))
+            .collect_vec()
+    } else {
+        vec![None; array0.dimensionality()]
+    };
+
+    let units_to_axis = |name: String, unit: Option<AxisUnit>| {
+        if let Some(unit) = unit {
+            match unit {
+                AxisUnit::Space(unit) => Axis {
+                    name,
+                    r#type: Some(AxisType::Space),
+                    unit: Some(AxisUnit::Space(unit)),
+                },
+                AxisUnit::Time(unit) => Axis {
+                    name,
+                    r#type: Some(AxisType::Time),
+                    unit: Some(AxisUnit::Time(unit)),
+                },
+                AxisUnit::Custom(unit) => {
+                    if unit == "channel" {
+                        Axis {
+                            name,
+                            r#type: Some(AxisType::Channel),
+                            unit: None,
+                        }
+                    } else {
+                        Axis {
+                            name,
+                            r#type: None,
+                            unit: Some(AxisUnit::Custom(unit)),
+                        }
+                    }
+                }
+                _ => unimplemented!("Unsupported axis unit"),
+            }
+        } else {
+            Axis {
+                name,
+                r#type: None,
+                unit: None,
+            }
+        }
+    };
+
+    if let Some(dimension_names) = array0.dimension_names() {
+        for (i, (dimension_name, unit)) in
+            std::iter::zip(dimension_names.iter(), physical_units).enumerate()
+        {
+            let axis = units_to_axis(
+                dimension_name
+                    .as_str()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| i.to_string()),
+                unit,
+            );
+            axes.push(axis)
+        }
+    } else {
+        for (i, unit) in physical_units.into_iter().enumerate() {
+            let axis = units_to_axis(i.to_string(), unit);
+            axes.push(axis)
+        }
+    }
+
+    // Compose the physical size (from the command line or input) and the translation of the input
+    let to_f32 = |values: Vec<f64>| values.into_iter().map(|v| v as f32).collect_vec();
+    let physical_size = cli
+        .physical_size
+        .or_else(|| input_physical_metadata.scale.map(to_f32));
+    let translation = input_physical_metadata
+        .translation
+        .filter(|translation| translation.iter().any(|t| *t != 0.0))
+        .map(to_f32);
+
+    // Check the companion OME-XML against the full resolution array before downsampling
+    let ome_xml = if let (true, Some(series)) = (
+        cli.ome_xml || cli.ome_xml_file.is_some(),
+        cli.bioformats2raw_series,
+    ) {
+        let axis_names = axes.iter().map(|axis| axis.name.clone()).collect_vec();
+        let pixels = OmeXmlPixels::from_array(&axis_names, array0.shape(), array0.data_type())?;
+        let mut physical_sizes = [None, None, None];
+        for (i, axis) in axes.iter().enumerate() {
+            let unit = axis
+                .unit
+                .as_ref()
+                .and_then(|unit| serde_json::to_value(unit).ok())
+                .and_then(|unit| unit.as_str().map(str::to_string));
+            let size = physical_size.as_ref().map(|physical_size| physical_size[i]);
+            let index = ["x", "y", "z"]
+                .iter()
+                .position(|dimension| axis.name.eq_ignore_ascii_case(dimension));
+            if let (Some(unit), Some(size), Some(index)) = (unit, size, index) {
+                physical_sizes[index] = Some((f64::from(size), unit));
+            }
+        }
+        let xml = if let Some(ome_xml_file) = &cli.ome_xml_file {
+            let xml = std::fs::read_to_string(ome_xml_file)?;
+            pixels.validate(&xml, series)?;
+            Some(xml)
+        } else {
+            None
+        };
+        Some(OmeXmlCompanion {
+            pixels,
+            physical_sizes,
+            name: cli.name.clone().unwrap_or_else(|| series.to_string()),
+            xml,
+        })
+    } else {
+        None
+    };
+
+    let base_transform = match (physical_size, translation) {
+        (None, None) => None,
+        (physical_size, translation) => {
+            let physical_size = physical_size.unwrap_or_else(|| vec![1.0; array0.dimensionality()]);
+            let mut transform = vec![CoordinateTransform::Scale(CoordinateTransformScale::from(
+                physical_size,
+            ))];
+            if let Some(translation) = translation {
+                transform.push(CoordinateTransform::Translation(
+                    CoordinateTransformTranslation::from(translation),
+                ));
+            }
+            Some(transform)
+        }
+    };
+
+    // let mut multiscales_metadata = serde_json::Map::with_capacity(3);
+    let serde_json::Value::Object(multiscales_metadata) = serde_json::json!({
+        "description": "Created with zarrs_ome",
+        "repository": env!("CARGO_PKG_REPOSITORY"),
+        "version": zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS,
+    }) else {
+        unreachable!()
+    };
+    let multiscales_metadata: MultiscaleImageMetadata =
+        MultiscaleImageMetadata(multiscales_metadata);
+
+    let downsample_type = if cli.weighted_mode {
+        "weighted_mode"
+    } else if cli.discrete {
+        "mode"
+    } else if cli.gaussian_sigma.is_none() {
+        "average"
+    } else {
+        "gaussian"
+    }
+    .to_string();
+
+    let mut datasets = Vec::with_capacity(num_levels);
+
+    let mut relative_scale = vec![1.0; array0.dimensionality()];
+    {
+        let dataset = MultiscaleImageDataset {
+            path: "0".to_string(),
+            coordinate_transformations: vec![CoordinateTransform::Scale(
+                CoordinateTransformScale::from(relative_scale.clone()),
+            )],
+        };
+        datasets.push(dataset);
+    }
+
+    // Calculate gaussian sigma/kernel size for each axis
+    let gaussian_filter = if let Some(gaussian_sigma) = cli.gaussian_sigma {
+        let kernel_half_size = if let Some(kernel_half_size) = cli.gaussian_kernel_half_size {
+            kernel_half_size
+        } else {
+            gaussian_sigma
+                .iter()
+                .map(|sigma| (sigma * 3.0).ceil() as u64)
+                .collect_vec()
+        };
+        Some(Gaussian::new(
+            gaussian_sigma.clone(),
+            kernel_half_size.clone(),
+            false,
+            None,
+        ))
+    } else {
+        None
+    };
+
+    // Open the validity mask
+    let mask_array = if let Some(mask) = &cli.mask {
+        let mask_array = Array::open(filesystem_store(mask, cli.direct_io.read())?.into(), "/")?;
+        if mask_array.shape() != array0.shape() {
+            Err(FilterError::Other(format!(
+                "the mask shape {:?} does not match the input shape {:?}",
+                mask_array.shape(),
+                array0.shape()
+            )))?;
+        }
+        if !matches!(mask_array.data_type(), DataType::Bool | DataType::UInt8) {
+            Err(FilterError::Other(format!(
+                "the mask data type {} is not bool or uint8",
+                mask_array.data_type()
+            )))?;
+        }
+        Some(mask_array)
+    } else {
+        None
+    };
+    // println!("sigma:{sigma} kernel_half_size:{kernel_half_size}");
+
+    // The axes checked against --min-size
+    let mut spatial_axes = axes
+        .iter()
+        .map(|axis| matches!(axis.r#type, Some(AxisType::Space)))
+        .collect_vec();
+    if !spatial_axes.contains(&true) {
+        spatial_axes = axes
+            .iter()
+            .map(|axis| !matches!(axis.r#type, Some(AxisType::Time | AxisType::Channel)))
+            .collect_vec();
+    }
+
+    if cli.label_confidence {
+        Group::new_with_metadata(
+            store.clone(),
+            &format!("/{LABEL_CONFIDENCE_PATH}"),
+            GroupMetadata::V3(GroupMetadataV3::default()),
+        )?
+        .store_metadata()?;
+    }
+
+    for i in 1..=num_levels {
+        let bar = bars.get(i).unwrap();
+        bar.reset();
+
+        let progress_callback = |stats: ProgressStats| {
+            pipeline_progress(i, stats.fraction(cli.progress_mode));
+            progress_callback(stats, bar, cli.progress_mode);
+        };
+        let progress_callback =
+            ProgressCallback::new(&progress_callback).with_cancellation(cancelled);
+
+        // Input
+        let store = filesystem_store(&output, cli.direct_io.read())?;
+        let array_input = Array::open(store.into(), &format!("/{}", i - 1))?;
+
+        // Stop when the previous level is at most the minimum size along every spatial axis
+        if cli.min_size.is_some_and(|min_size| {
+            std::iter::zip(array_input.shape(), &spatial_axes)
+                .all(|(size, spatial)| !spatial || *size <= min_size)
+        }) {
+            bars[i..=num_levels]
+                .iter()
+                .for_each(|bar| bar.finish_and_clear());
+            break;
+        }
+        // The validity of masked levels is determined from level 0
+        let input_factor = (1..i).fold(vec![1; array0.dimensionality()], |factor, level| {
+            std::iter::zip(factor, downsample_schedule.level(level))
+                .map(|(factor, level_factor)| factor * level_factor)
+                .collect_vec()
+        });
+        let mask = match &mask_array {
+            Some(mask_array) => Mask::Array(mask_array, &input_factor),
+            None if cli.mask_fill_value => Mask::FillValue(&array0, &input_factor),
+            None if cli.nan_aware => Mask::Nan,
+            None => Mask::None,
+        };
+
+        // Filters
+        let downsample_filter = Downsample::new(
+            downsample_schedule.level(i).to_vec(),
+            cli.discrete,
+            cli.nan_aware,
+            false,
+            None,
+        );
+
+        // Setup reencoding (this is a bit hacky)
+        let chunk_representation =
+            array_input.chunk_array_representation(&vec![0; array_input.dimensionality()])?;
+        let output_shape = downsample_filter.output_shape(&array_input).unwrap();
+        let mut reencoding = ZarrReencodingArgs::default();
+        if array_input
+            .codecs()
+            .array_to_bytes_codec()
+            .create_metadata()
+            .unwrap()
+            .name()
+            == "sharding_indexed"
+        {
+            reencoding.shard_shape = Some(
+                std::iter::zip(chunk_representation.shape(), &output_shape)
+                    .map(|(c, s)| ChunkSize::from(std::cmp::min(c.get(), *s)))
+                    .collect_vec(),
+            );
+            let decode_granularity = array_input
+                .codecs()
+                .partial_decode_granularity(&chunk_representation);
+            reencoding.chunk_shape = Some(
+                std::iter::zip(decode_granularity.as_slice(), &output_shape)
+                    .map(|(g, s)| ChunkSize::from(std::cmp::min(g.get(), *s)))
+                    .collect_vec(),
+            );
+        } else {
+            reencoding.chunk_shape = Some(
+                std::iter::zip(
+                    array_input
+                        .chunk_shape(&vec![0; array_input.dimensionality()])?
+                        .as_slice(),
+                    &output_shape,
+                )
+                .map(|(g, s)| ChunkSize::from(std::cmp::min(g.get(), *s)))
+                .collect_vec(),
+            );
+        }
+        // println!("{:?} {:?}", reencoding.chunk_shape, reencoding.shard_shape);
+        let output_builder = downsample_filter.output_array_builder(&array_input, &reencoding)?;
+
+        // Output
+        let output_path = output.join(i.to_string());
+        let output_store = filesystem_store(&output, cli.direct_io.write())?;
+        let array_output = output_builder.build(output_store.into(), &format!("/{}", i))?;
+        bar.set_prefix(format!("{i} {:?}", array_output.shape()));
+        let array_confidence = if cli.label_confidence {
+            let confidence_store = filesystem_store(&output, cli.direct_io.write())?;
+            Some(
+                ArrayBuilder::new(
+                    array_output.shape().to_vec(),
+                    DataType::Float32,
+                    array_output.chunk_grid().clone(),
+                    FillValue::from(0.0f32),
+                )
+                .dimension_names(array_output.dimension_names().clone())
+                .build(
+                    confidence_store.into(),
+                    &format!("/{LABEL_CONFIDENCE_PATH}/{i}"),
+                )?,
+            )
+        } else {
+            None
+        };
+
+        // Scale factor (inverse of downsample factor, accounting for actual changes)
+        // The footprint of a weighted mode output element spans a fractional number of input elements
+        let real_downsample_factor = std::iter::zip(array_input.shape(), array_output.shape())
+            .map(|(i, o)| {
+                if cli.weighted_mode {
+                    *i as f32 / *o as f32
+                } else {
+                    (i / o) as f32
+                }
+            })
+            .collect_vec();
+        std::iter::zip(&mut relative_scale, &real_downsample_factor).for_each(
+            |(scale, downsample_factor)| {
+                *scale *= *downsample_factor;
+            },
+        );
+        // println!("{downsample_factor:?} -> {scale:?}");
+
+        // Chunks
+        let subsets = cli.write.write_subsets(&array_output)?;
+        let codec_options = cli.write.codec_options(&array_output);
+        let progress = Progress::new(subsets.len(), &progress_callback);
+
+        // Get memory usage
+        let output_chunk =
+            array_output.chunk_array_representation(&vec![0; array_input.dimensionality()])?;
+        let downsample_memory =
+            downsample_filter.memory_per_chunk(&output_chunk /* unused */, &output_chunk);
+        let memory_per_chunk = downsample_memory
+            + if let Some(gaussian_filter) = &gaussian_filter {
+                let downsample_input_subset = downsample_filter.input_subset(
+                    array_input.shape(),
+                    &ArraySubset::new_with_shape(output_chunk.shape_u64()),
+                );
+                let downsample_input = ChunkRepresentation::new(
+                    downsample_input_subset
+                        .shape()
+                        .iter()
+                        .map(|s| NonZeroU64::new(*s).unwrap())
+                        .collect_vec(),
+                    array_input.data_type().clone(),
+                    array_input.fill_value().clone(),
+                )?;
+                gaussian_filter.memory_per_chunk(&downsample_input, &downsample_input)
+            } else {
+                0
+            };
+        let chunk_limit = zarrs_tools::filter::calculate_chunk_limit(cli.chunk_limit);
+
+        // Apply
+        zarrs_tools::filter::try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            subsets,
+            |subset: ArraySubset| {
+                macro_rules! discrete_or_continuous {
+                    ( $t:ty ) => {{
+                        if cli.weighted_mode {
+                            apply_chunk_discrete_weighted::<$t>(
+                                &array_input,
+                                &array_output,
+                                array_confidence.as_ref(),
+                                &subset,
+                                &downsample_filter,
+                                &codec_options,
+                                &progress,
+                            )?
+                        } else if cli.discrete {
+                            apply_chunk_discrete::<$t>(
+                                &array_input,
+                                &array_output,
+                                &subset,
+                                &downsample_filter,
+                                &codec_options,
+                                &progress,
+                            )?
+                        } else if let Some(gaussian_filter) = &gaussian_filter {
+                            apply_chunk_continuous_gaussian::<$t>(
+                                &array_input,
+                                &array_output,
+                                &subset,
+                                &downsample_filter,
+                                &gaussian_filter,
+                                mask,
+                                &codec_options,
+                                &progress,
+                            )?
+                        } else {
+                            apply_chunk_continuous::<$t>(
+                                &array_input,
+                                &array_output,
+                                &subset,
+                                &downsample_filter,
+                                mask,
+                                &codec_options,
+                                &progress,
+                            )?
+                        }
+                    }};
+                }
+                macro_rules! continuous {
+                    ( $t:ty ) => {{
+                        if let Some(gaussian_filter) = &gaussian_filter {
+                            apply_chunk_continuous_gaussian::<$t>(
+                                &array_input,
+                                &array_output,
+                                &subset,
+                                &downsample_filter,
+                                &gaussian_filter,
+                                mask,
+                                &codec_options,
+                                &progress,
+                            )?
+                        } else {
+                            apply_chunk_continuous::<$t>(
+                                &array_input,
+                                &array_output,
+                                &subset,
+                                &downsample_filter,
+                                mask,
+                                &codec_options,
+                                &progress,
+                            )?
+                        }
+                    }};
+                }
+                macro_rules! apply {
+                    ( [$( ( $data_type_out:ident, $t:ty,  $inner:ident ) ),* ]) => {
+                        match array_input.data_type() {
+                            $(zarrs::array::DataType::$data_type_out => { $inner!($t) } ,)*
+                            _ => panic!("unsupported data type")
+                        }
+                    };
+                }
+                apply!([
+                    (Int8, i8, discrete_or_continuous),
+                    (Int16, i16, discrete_or_continuous),
+                    (Int32, i32, discrete_or_continuous),
+                    (Int64, i64, discrete_or_continuous),
+                    (UInt8, u8, discrete_or_continuous),
+                    (UInt16, u16, discrete_or_continuous),
+                    (UInt32, u32, discrete_or_continuous),
+                    (UInt64, u64, discrete_or_continuous),
+                    (BFloat16, bf16, continuous),
+                    (Float16, f16, continuous),
+                    (Float32, f32, continuous),
+                    (Float64, f64, continuous)
+                ]);
+
+                progress.next()?;
+                Ok::<_, FilterError>(())
+            },
+        )?;
+
+        // Append multiscales dataset metadata
+        let dataset = MultiscaleImageDataset {
+            path: format!("{i}"),
+            coordinate_transformations: vec![
+                CoordinateTransform::Scale(CoordinateTransformScale::from(relative_scale.clone())),
+                CoordinateTransform::Translation(CoordinateTransformTranslation::from(
+                    relative_scale.iter().map(|s| (s - 1.0) * 0.5).collect_vec(),
+                )),
+            ],
+        };
+        datasets.push(dataset);
+
+        array_output.store_metadata()?;
+        if let Some(array_confidence) = &array_confidence {
+            array_confidence.store_metadata()?;
+        }
+        finish_step(bar, &output_path);
+        pipeline_progress(i, 1.0);
+
+        // Stop when for all axis and remaining levels the output shape is 1 or stride is 1
+        if cli.levels.is_none()
+            && downsample_schedule
+                .remaining(i)
+                .iter()
+                .all(|downsample_factor| {
+                    std::iter::zip(downsample_factor, &output_shape)
+                        .all(|(df, s)| *df == 1 || *s == 1)
+                })
+        {
+            bars[i + 1..=num_levels]
+                .iter()
+                .for_each(|bar| bar.finish_and_clear());
+            break;
+        }
+    }
+
+    match cli.ome_zarr_version {
+        OMEZarrVersion::V0_5 => {
+            let multiscales = vec![ome_zarr_metadata::v0_5::MultiscaleImage {
+                name: cli.name,
+                axes,
+                datasets,
+                coordinate_transformations: base_transform,
+                r#type: Some(downsample_type),
+                metadata: Some(multiscales_metadata),
+            }];
+            let ome = ome_zarr_metadata::v0_5::OmeFields {
+                multiscales: Some(multiscales),
+                ..Default::default()
+            };
+            group
+                .attributes_mut()
+                .insert("ome".to_string(), serde_json::to_value(ome).unwrap());
+        }
+    }
+
+    // Store metadata
+    if !cli.no_provenance {
+        Provenance::new(
+            NAME,
+            arguments,
+            vec![cli.input.to_string_lossy().to_string()],
+        )
+        .insert(group.attributes_mut());
+    }
+    group.store_metadata()?;
+
+    // Update the plate/well or bioformats2raw.layout metadata
+    if let Some(plate_well) = &cli.plate_well {
+        update_plate_metadata(
+            &cli.output,
+            plate_well,
+            &cli.plate_field,
+            cli.plate_name.as_deref(),
+            cli.plate_acquisition,
+        )?;
+    } else if let Some(series) = cli.bioformats2raw_series {
+        update_bioformats2raw_metadata(&cli.output, series)?;
+        if let Some(ome_xml) = ome_xml {
+            write_ome_xml(&cli.output, series, ome_xml)?;
+        }
+    }
+
+    if let Some(pipeline_bar) = pipeline_bar {
+        pipeline_bar.set_style(bar_style_finish());
+        pipeline_bar.set_message("");
+        pipeline_bar.abandon();
+    }
+
+    let duration_s = start.elapsed().as_secs_f32();
+    println!("Output {:?} in {duration_s:.2}s", output);
+
+    Ok(())
+}
+
+/// Open the group at `path` in `store`, or initialise it if it does not exist.
+fn open_or_new_group(
+    store: &Arc<FilesystemStore>,
+    root: &Path,
+    path: &str,
+) -> Result<Group<FilesystemStore>, Box<dyn Error + Send + Sync>> {
+    if root
+        .join(path.trim_start_matches('/'))
+        .join("zarr.json")
+        .exists()
+    {
+        Ok(Group::open(store.clone(), path)?)
+    } else {
+        Ok(Group::new_with_metadata(
+            store.clone(),
+            path,
+            GroupMetadata::V3(GroupMetadataV3::default()),
+        )?)
+    }
+}
+
+/// Return the "ome" attributes of a group, with the version set.
+fn get_ome_attributes(
+    group: &Group<FilesystemStore>,
+    path: &Path,
+) -> Result<serde_json::Map<String, serde_json::Value>, Box<dyn Error + Send + Sync>> {
+    let mut ome = match group.attributes().get("ome") {
+        Some(serde_json::Value::Object(ome)) => ome.clone(),
+        Some(_) => Err(FilterError::Other(format!(
+            "the ome attributes of {path:?} are invalid"
+        )))?,
+        None => serde_json::Map::new(),
+    };
+    if ome.contains_key("multiscales") {
+        Err(FilterError::Other(format!(
+            "{path:?} is an image, not a plate/well/bioformats2raw.layout group"
+        )))?;
+    }
+    ome.insert("version".to_string(), serde_json::json!("0.5"));
+    Ok(ome)
+}
+
+/// Serialise plate metadata.
+///
+/// `ome_zarr_metadata` serialises the well indices as `row_index`/`column_index` rather than `rowIndex`/`columnIndex`.
+fn plate_to_value(plate: &Plate) -> serde_json::Result<serde_json::Value> {
+    let mut plate = serde_json::to_value(plate)?;
+    if let Some(wells) = plate
+        .get_mut("wells")
+        .and_then(serde_json::Value::as_array_mut)
+    {
+        for well in wells
+            .iter_mut()
+            .filter_map(serde_json::Value::as_object_mut)
+        {
+            for (from, to) in [("row_index", "rowIndex"), ("column_index", "columnIndex")] {
+                if let Some(index) = well.remove(from) {
+                    well.insert(to.to_string(), index);
+                }
+            }
+        }
+    }
+    Ok(plate)
+}
+
+/// Create or update the plate, row, and well group metadata for a field of view.
+fn update_plate_metadata(
+    plate_path: &Path,
+    plate_well: &PlateWellPath,
+    field: &str,
+    name: Option<&str>,
+    acquisition: Option<u64>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let store = Arc::new(FilesystemStore::new(plate_path)?);
+    let PlateWellPath { row, column } = plate_well;
+
+    // Well
+    let mut well_group = open_or_new_group(&store, plate_path, &format!("/{row}/{column}"))?;
+    let mut well_ome = get_ome_attributes(&well_group, &plate_path.join(row).join(column))?;
+    let mut well: Well = match well_ome.remove("well") {
+        Some(well) => serde_json::from_value(well)?,
+        None => Well { images: vec![] },
+    };
+    if let Some(image) = well
+        .images
+        .iter_mut()
+        .find(|image| image.path == Path::new(field))
+    {
+        image.acquisition = acquisition;
+    } else {
+        well.images.push(WellImage {
+            path: field.into(),
+            acquisition,
+        });
+    }
+    well.images.sort_by(|a, b| {
+        let (a, b) = (a.path.as_os_str(), b.path.as_os_str());
+        (a.len(), a).cmp(&(b.len(), b))
+    });
+    let field_count = well.images.len() as u64;
+    well_ome.insert("well".to_string(), serde_json::to_value(well)?);
+    well_group
+        .attributes_mut()
+        .insert("ome".to_string(), well_ome.into());
+    well_group.store_metadata()?;
+
+    // Row
+    open_or_new_group(&store, plate_path, &format!("/{row}"))?.store_metadata()?;
+
+    // Plate
+    let mut plate_group = open_or_new_group(&store, plate_path, "/")?;
+    let mut plate_ome = get_ome_attributes(&plate_group, plate_path)?;
+    let mut plate: Plate = match plate_ome.remove("plate") {
+        Some(plate) => serde_json::from_value(plate)?,
+        None => Plate {
+            acquisitions: None,
+            columns: vec![],
+            field_count: None,
+            name: None,
+            rows: vec![],
+            wells: vec![],
+        },
+    };
+    if let Some(name) = name {
+        plate.name = Some(name.to_string());
+    }
+    if let Some(acquisition) = acquisition {
+        let acquisitions = plate.acquisitions.get_or_insert_with(Vec::new);
+        if !acquisitions.iter().any(|a| a.id == acquisition) {
+            acquisitions.push(PlateAcquisition {
+                id: acquisition,
+                name: None,
+                maximumfieldcount: None,
+                description: None,
+                starttime: None,
+                endtime: None,
+            });
+        }
+    }
+    plate.field_count = std::cmp::max(plate.field_count, NonZeroU64::new(field_count));
+
+    // Add the row/column/well and recompute the well indices, with rows and columns in natural order (A..Z, AA.., 1..9, 10..)
+    if !plate.rows.iter().any(|r| &r.name == row) {
+        plate.rows.push(PlateRow { name: row.clone() });
+    }
+    if !plate.columns.iter().any(|c| &c.name == column) {
+        plate.columns.push(PlateColumn {
+            name: column.clone(),
+        });
+    }
+    plate
+        .rows
+        .sort_by(|a, b| (a.name.len(), &a.name).cmp(&(b.name.len(), &b.name)));
+    plate
+        .columns
+        .sort_by(|a, b| (a.name.len(), &a.name).cmp(&(b.name.len(), &b.name)));
+    let well_path = PathBuf::from(format!("{row}/{column}"));
+    if !plate.wells.iter().any(|w| w.path == well_path) {
+        plate.wells.push(PlateWell {
+            path: well_path,
+            row_index: 0,
+            column_index: 0,
+        });
+    }
+    for well in &mut plate.wells {
+        let path = well.path.to_string_lossy();
+        let Some((row, column)) = path.split_once('/') else {
+            Err(FilterError::Other(format!(
+                "the plate well path {path} is invalid"
+            )))?
+        };
+        let row_index = plate.rows.iter().position(|r| r.name == row);
+        let column_index = plate.columns.iter().position(|c| c.name == column);
+        let (Some(row_index), Some(column_index)) = (row_index, column_index) else {
+            Err(FilterError::Other(format!(
+                "the plate well {path} does not match the plate rows/columns"
+            )))?
+        };
+        well.row_index = row_index as u64;
+        well.column_index = column_index as u64;
+    }
+    plate
+        .wells
+        .sort_by_key(|well| (well.row_index, well.column_index));
+
+    plate_ome.insert("plate".to_string(), plate_to_value(&plate)?);
+    plate_group
+        .attributes_mut()
+        .insert("ome".to_string(), plate_ome.into());
+    plate_group.store_metadata()?;
+
+    Ok(())
+}
+
+/// Create or update the root group "bioformats2raw.layout" and OME group series metadata for an image series.
+fn update_bioformats2raw_metadata(
+    root_path: &Path,
+    series: u64,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let store = Arc::new(FilesystemStore::new(root_path)?);
+
+    // Root
+    let mut root_group = open_or_new_group(&store, root_path, "/")?;
+    let mut root_ome = get_ome_attributes(&root_group, root_path)?;
+    root_ome.insert("bioformats2raw.layout".to_string(), serde_json::json!(3));
+    root_group
+        .attributes_mut()
+        .insert("ome".to_string(), root_ome.into());
+    root_group.store_metadata()?;
+
+    // OME
+    let mut ome_group = open_or_new_group(&store, root_path, "/OME")?;
+    let mut ome_ome = get_ome_attributes(&ome_group, &root_path.join("OME"))?;
+    let mut series_list: Vec<String> = match ome_ome.remove("series") {
+        Some(series) => serde_json::from_value(series)?,
+        None => vec![],
+    };
+    if !series_list.contains(&series.to_string()) {
+        series_list.push(series.to_string());
+    }
+    series_list.sort_by(|a, b| (a.len(), a).cmp(&(b.len(), b)));
+    ome_ome.insert("series".to_string(), serde_json::to_value(series_list)?);
+    ome_group
+        .attributes_mut()
+        .insert("ome".to_string(), ome_ome.into());
+    ome_group.store_metadata()?;
+
+    Ok(())
+}
+
+/// Write the companion OME-XML of an image series to a "bioformats2raw.layout" hierarchy.
+///
+/// A provided OME-XML document is copied, otherwise the image of the series is inserted or replaced in the existing document (if any).
+fn write_ome_xml(
+    root_path: &Path,
+    series: u64,
+    ome_xml: OmeXmlCompanion,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let path = root_path.join(OME_XML_PATH);
+    let xml = if let Some(xml) = ome_xml.xml {
+        xml
+    } else {
+        let xml = std::fs::read_to_string(&path).ok();
+        upsert_image(
+            xml.as_deref(),
+            series,
+            &ome_xml.name,
+            &ome_xml.pixels,
+            &ome_xml.physical_sizes,
+        )
+    };
+    ome_xml.pixels.validate(&xml, series)?;
+    std::fs::write(path, xml)?;
+    Ok(())
+}
+
+pub fn main(arguments: Vec<OsString>) -> std::process::ExitCode {
+    // Parse command line arguments
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, &arguments);
+    if let Err(err) = cli.threads.clone().install(|| run(cli, &arguments)) {
+        println!("{}", err);
+        std::process::ExitCode::FAILURE
+    } else {
+        std::process::ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_fill_value_downsampled_levels() -> Result<(), Box<dyn Error + Send + Sync>> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.zarr");
+        let output = dir.path().join("output.ome.zarr");
+
+        let array = ArrayBuilder::new(
+            vec![4, 4],
+            DataType::Float32,
+            vec![4, 4].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(Arc::new(FilesystemStore::new(&input)?), "/")?;
+        array.store_metadata()?;
+        #[rustfmt::skip]
+        let elements: Vec<f32> = vec![
+            -1.0, 1.0, 4.0, 4.0,
+            -1.0, 1.0, 4.0, 4.0,
+             4.0, 4.0, 4.0, 4.0,
+             4.0, 4.0, 0.0, 4.0,
+        ];
+        array.store_array_subset_elements(&array.subset_all(), &elements)?;
+
+        let arguments: Vec<OsString> = vec![
+            NAME.into(),
+            "--mask-fill-value".into(),
+            input.into(),
+            output.clone().into(),
+        ];
+        run(Cli::try_parse_from(&arguments)?, &arguments)?;
+
+        // The fill value in the input is masked
+        let store = Arc::new(FilesystemStore::new(&output)?);
+        let level1 = Array::open(store.clone(), "/1")?;
+        assert_eq!(
+            level1.retrieve_array_subset_elements::<f32>(&level1.subset_all())?,
+            [0.0, 4.0, 4.0, 4.0]
+        );
+        // A downsampled element equal to the fill value is valid
+        let level2 = Array::open(store, "/2")?;
+        assert_eq!(
+            level2.retrieve_array_subset_elements::<f32>(&level2.subset_all())?,
+            [3.0]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use std::{ffi::OsString, path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use serde_json::json;
+use zarrs::{array::Array, array_subset::ArraySubset};
+use zarrs_tools::{
+    filesystem_store,
+    query::{
+        parse_box, parse_points_csv, query_box_statistics, query_box_values, query_points,
+        Statistic,
+    },
+    DirectIo, ThreadsArgs,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_query";
+
+/// Query the statistics or values of boxes, or the values of points, of a Zarr array.
+///
+/// Only the chunks intersecting each box or point are read, so spot queries of large arrays are fast.
+/// NaN elements are excluded from the statistics of a box.
+///
+/// Results are written to standard output as CSV (a row per box or point) or JSON.
+/// CSV columns are named by the dimension names, or axis0, axis1, ...
+#[derive(Parser)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+#[command(group(clap::ArgGroup::new("query").required(true).args(["boxes", "points"])))]
+pub struct Cli {
+    /// The input directory of the zarr array.
+    input: PathBuf,
+
+    /// A box to query, given as an offset and shape (e.g. 0,0,0:64,64,64).
+    ///
+    /// Can be specified multiple times.
+    #[arg(long = "box", value_name = "OFFSET:SHAPE", value_parser = parse_box)]
+    boxes: Vec<ArraySubset>,
+
+    /// A CSV of points to query, with the coordinates of a point per row.
+    ///
+    /// A header row is skipped, and columns beyond the array dimensionality are ignored (e.g. the value column of zarrs_zarr2points output).
+    #[arg(long)]
+    points: Option<PathBuf>,
+
+    /// The statistics of each box (comma delimited).
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [Statistic::Count, Statistic::Sum, Statistic::Mean, Statistic::Min, Statistic::Max])]
+    statistics: Vec<Statistic>,
+
+    /// Output the values of the elements of each box rather than statistics.
+    #[arg(long, conflicts_with = "statistics")]
+    values: bool,
+
+    /// Output JSON rather than CSV.
+    #[arg(long)]
+    json: bool,
+
+    /// Number of concurrent chunks.
+    #[arg(long)]
+    concurrent_chunks: Option<usize>,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=read`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "read", default_value_t = DirectIo::Off)]
+    direct_io: DirectIo,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+pub fn main(args: Vec<OsString>) -> anyhow::Result<()> {
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, args);
+    cli.threads.clone().install(|| run(cli))
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    let store = Arc::new(filesystem_store(&cli.input, cli.direct_io.read())?);
+    let array = Array::open(store, "/")?;
+
+    let axes = (0..array.dimensionality())
+        .map(|i| {
+            array
+                .dimension_names()
+                .as_ref()
+                .and_then(|dimension_names| dimension_names[i].as_str())
+                .map_or_else(|| format!("axis{i}"), str::to_string)
+        })
+        .collect::<Vec<_>>();
+    let join = |values: &[u64]| {
+        values
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    if let Some(points) = &cli.points {
+        let points = parse_points_csv(&std::fs::read_to_string(points)?, axes.len())?;
+        let values = query_points(&array, &points, cli.concurrent_chunks)?;
+        if cli.json {
+            let results = std::iter::zip(&points, &values)
+                .map(|(point, value)| json!({ "point": point, "value": value }))
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&results)?);
+        } else {
+            println!("{},value", axes.join(","));
+            for (point, value) in std::iter::zip(&points, &values) {
+                println!("{},{value}", join(point));
+            }
+        }
+    } else if cli.values {
+        let mut results = vec![];
+        if !cli.json {
+            println!("box,{},value", axes.join(","));
+        }
+        for (i, subset) in cli.boxes.iter().enumerate() {
+            let values = query_box_values(&array, subset)?;
+            if cli.json {
+                results.push(json!({
+                    "offset": subset.start(),
+                    "shape": subset.shape(),
+                    "values": values,
+                }));
+            } else {
+                for (indices, value) in std::iter::zip(&subset.indices(), values) {
+                    println!("{i},{},{value}", join(&indices));
+                }
+            }
+        }
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&results)?);
+        }
+    } else {
+        let mut results = vec![];
+        if !cli.json {
+            let columns = axes
+                .iter()
+                .map(|axis| format!("{axis}_offset"))
+                .chain(axes.iter().map(|axis| format!("{axis}_shape")))
+                .chain(cli.statistics.iter().map(|s| s.name().to_string()))
+                .collect::<Vec<_>>();
+            println!("{}", columns.join(","));
+        }
+        for subset in &cli.boxes {
+            let statistics = query_box_statistics(&array, subset, cli.concurrent_chunks)?;
+            if cli.json {
+                let mut result = json!({ "offset": subset.start(), "shape": subset.shape() });
+                for statistic in &cli.statistics {
+                    result[statistic.name()] = if *statistic == Statistic::Count {
+                        json!(statistics.count)
+                    } else {
+                        json!(statistics.statistic(*statistic))
+                    };
+                }
+                results.push(result);
+            } else {
+                let statistics = cli
+                    .statistics
+                    .iter()
+                    .map(|statistic| statistics.statistic(*statistic).to_string())
+                    .collect::<Vec<_>>();
+                println!(
+                    "{},{},{}",
+                    join(subset.start()),
+                    join(subset.shape()),
+                    statistics.join(",")
+                );
+            }
+        }
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&results)?);
+        }
+    }
+    Ok(())
+}
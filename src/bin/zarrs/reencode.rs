@@ -0,0 +1,1264 @@
+use core::f32;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use clap::Parser;
+use hmac::{Hmac, Mac};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon_iter_concurrent_limit::iter_concurrent_limit;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use zarrs::array::{Array, ArrayShardedExt, DataType};
+use zarrs::filesystem::FilesystemStore;
+use zarrs::group::{Group, GroupBuilder};
+use zarrs::storage::{
+    storage_adapter::async_to_sync::{AsyncToSyncBlockOn, AsyncToSyncStorageAdapter},
+    AsyncReadableListableStorage, ListableStorageTraits, ReadableListableStorage,
+    ReadableListableStorageTraits, WritableStorageTraits,
+};
+use zarrs_opendal::AsyncOpendalStore;
+use zarrs_tools::{
+    cast::convert_fill_value,
+    chunk_encoding_unchanged, create_parent_groups, do_copy_chunk_files, do_copy_chunks,
+    do_reencode, do_verify,
+    error_metrics::{ErrorMetrics, ErrorMetricsArgs},
+    filesystem_store,
+    fill_value_from_data::{fill_value_from_data, FillValueFromData},
+    get_array_builder_reencode,
+    journal::ChunkJournal,
+    metadata_comparison::{MetadataComparisonArgs, MetadataMismatch},
+    n5::{is_n5_dataset, N5Store},
+    node_prefix, parse_size,
+    progress::{duration_smoothed, ProgressCallback, ProgressMode, ProgressStats},
+    rate_limit::RateLimitStorageAdapter,
+    reshard::{do_reshard, reshard_compatible},
+    resolve_chunk_shape, resolve_shard_shape, split_node_path,
+    transcode::{do_transcode, transcode_compatible},
+    ChunkCacheArgs, ChunkSize, CodecPreset, DirectIo, FileCopyMode, Provenance, RateLimitArgs,
+    ReadCacheArgs, ReencodeStats, ReencodeValidation, ThreadsArgs, VerifyReport, WriteShapeArgs,
+    ZarrReencodingArgs, AUTO_CHUNK_SIZE_DEFAULT,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_reencode";
+
+/// Reencode a Zarr array.
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Args {
+    #[command(flatten)]
+    encoding: ZarrReencodingArgs,
+
+    /// The zarr array input path or URL.
+    ///
+    /// This is a hierarchy if --recursive is set.
+    /// A local N5 dataset is also supported (raw or gzip compression).
+    /// An optional node path suffix selects a node within the store (e.g. input.zarr#/0).
+    path_in: String,
+
+    /// The zarr array output directory.
+    ///
+    /// An optional node path suffix selects a node within the store (e.g. output.zarr#/0).
+    /// Only the output node is replaced, other nodes in the store are left unchanged and missing parent groups are created.
+    path_out: String,
+
+    /// Reencode every array in a hierarchy.
+    ///
+    /// The hierarchy structure and group attributes are preserved.
+    /// The encoding arguments apply to every array.
+    #[arg(long, default_value_t = false, conflicts_with = "stats_json")]
+    recursive: bool,
+
+    /// Number of concurrent arrays if --recursive is set.
+    ///
+    /// Defaults to the number of CPUs.
+    /// Arrays share the global thread pool, so many small arrays can be processed concurrently.
+    #[arg(long, requires = "recursive")]
+    concurrent_arrays: Option<usize>,
+
+    /// Number of concurrent chunks.
+    #[arg(long)]
+    concurrent_chunks: Option<usize>,
+
+    /// Ignore checksums.
+    ///
+    /// If set, checksum validation in codecs (e.g. crc32c) is skipped.
+    #[arg(long, default_value_t = false)]
+    ignore_checksums: bool,
+
+    /// Validate written data.
+    ///
+    /// `round-trip` compares written data to the data read back from the output.
+    /// `input` additionally compares written data to an independent (uncached) read of the input.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "round-trip", default_value_t = ReencodeValidation::Off)]
+    validate: ReencodeValidation,
+
+    /// Error metrics options.
+    #[command(flatten)]
+    error: ErrorMetricsArgs,
+
+    /// Metadata comparison options.
+    ///
+    /// The dimension names, units, and attributes of the output are compared with the input before the data is written.
+    #[command(flatten)]
+    metadata: MetadataComparisonArgs,
+
+    /// Decode and reencode chunks even if the chunk encoding is unchanged.
+    ///
+    /// By default, encoded chunks are copied without decoding if only the array metadata changes (e.g. attributes or dimension names).
+    /// Similarly, encoded inner chunks are moved between shards without decoding if only the shard shape (or shard index encoding) changes, or an unsharded array is sharded with unchanged chunks.
+    /// If only the bytes to bytes codecs (e.g. compression) change, chunks are transcoded by decoding and reencoding only those codecs.
+    /// Chunks are always decoded and reencoded if --validate or the error metrics are enabled.
+    #[arg(long, default_value_t = false)]
+    force_reencode: bool,
+
+    /// The method used to copy chunk files if the chunk encoding is unchanged and the input is local.
+    ///
+    /// Hard links and reflinks require the input and output to be on the same filesystem.
+    #[arg(long, value_enum, default_value_t = FileCopyMode::Copy)]
+    copy_mode: FileCopyMode,
+
+    /// The unit of progress bars.
+    ///
+    /// Bytes-based progress is proportional to the decoded bytes read, and falls back to steps (e.g. chunks) if unsupported.
+    #[arg(long, value_enum, default_value_t = ProgressMode::Steps)]
+    progress_mode: ProgressMode,
+
+    /// Do not write provenance metadata (the tool, version, arguments, inputs, and timestamp) to the output attributes.
+    #[arg(long, default_value_t = false)]
+    no_provenance: bool,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=both`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "both", default_value_t = DirectIo::Read)]
+    direct_io: DirectIo,
+
+    /// Print verbose information, such as the array header.
+    #[arg(long, short, default_value_t = false)]
+    verbose: bool,
+
+    #[command(flatten)]
+    cache: ChunkCacheArgs,
+
+    /// Array shape (optional). A comma separated list of the array size along each dimension.
+    ///
+    /// The array is cropped or padded with the fill value at the high end of each dimension.
+    /// Defaults to the input array shape.
+    #[arg(long, value_delimiter = ',', conflicts_with = "recursive")]
+    array_shape: Option<Vec<u64>>,
+
+    /// Set the fill value of the output from the data of the input (optional).
+    ///
+    /// `corners` uses the most common value of the corner elements of the array.
+    /// `mode` uses the most common element of the existing input chunks, or a sample of chunks with --fill-value-sample.
+    /// NaN elements are counted as one value.
+    /// This is useful when converting legacy data with a meaningless fill value, so that chunks of only the detected fill value are not written.
+    #[arg(long, value_enum, conflicts_with = "fill_value")]
+    set_fill_value_from_data: Option<FillValueFromData>,
+
+    /// Only scan N randomly chosen input chunks if --set-fill-value-from-data is `mode`.
+    #[arg(long, requires = "set_fill_value_from_data")]
+    fill_value_sample: Option<usize>,
+
+    /// Write shape options.
+    #[command(flatten)]
+    write: WriteShapeArgs,
+
+    /// Prefetch the input chunks of each output chunk (or shard) into the chunk cache, with up to N concurrent chunk requests.
+    ///
+    /// This coalesces many small reads into concurrent requests, which can drastically reduce the time to reencode a remote (e.g. HTTP or S3) unsharded input to a sharded output.
+    /// Requires a shared chunk cache (--cache-size or --cache-chunks) that can hold the decoded input chunks of --concurrent-chunks output chunks.
+    #[arg(long)]
+    prefetch: Option<usize>,
+
+    /// Record the completed output chunks in an append-only journal at this path (optional).
+    ///
+    /// Each line of the journal is a JSON object with the run (the provenance timestamp) and the chunk indices, appended as each chunk is completed.
+    /// This records which chunks were written by which run, and enables --resume.
+    /// Chunks are always decoded and reencoded, as with --force-reencode.
+    #[arg(long, conflicts_with = "recursive")]
+    journal: Option<PathBuf>,
+
+    /// Resume an interrupted reencode, skipping the output chunks completed in the journal.
+    ///
+    /// The output is not erased and the journal is appended to.
+    /// The output chunk encoding must match that of the interrupted run.
+    #[arg(long, requires = "journal")]
+    resume: bool,
+
+    /// Write a JSON summary of the reencode to this path (optional).
+    ///
+    /// The summary includes the tool versions, the input/output metadata, per-phase durations, sizes, throughput, chunk counts, and the chunk cache hit rate.
+    /// This is intended for benchmarking harnesses and CI.
+    #[arg(long)]
+    stats_json: Option<PathBuf>,
+
+    /// Automatically choose the chunk shape for a target decoded chunk size (optional).
+    ///
+    /// Accepts a size in bytes with an optional unit (e.g. 1MiB, 4MB).
+    /// The largest auto dimension of the chunk shape is halved until the chunk size does not exceed the target.
+    /// All dimensions are auto if --chunk-shape is not specified.
+    #[arg(long, value_parser = parse_size, conflicts_with = "chunk_grid")]
+    auto_chunk: Option<u64>,
+
+    /// Automatically choose the shard shape for a target decoded shard size (optional).
+    ///
+    /// Accepts a size in bytes with an optional unit (e.g. 256MiB, 1GB).
+    /// The smallest auto dimension of the shard shape is doubled while the shard size does not exceed the target.
+    /// All dimensions are auto if --shard-shape is not specified.
+    /// The chunk shape is set by --auto-chunk or --chunk-shape, otherwise it matches the input array.
+    #[arg(long, value_parser = parse_size, conflicts_with = "chunk_grid")]
+    auto_shard: Option<u64>,
+
+    /// A codec preset (optional).
+    ///
+    /// A preset expands into a curated codec chain and chunk/shard size targets.
+    /// Explicit flags take precedence: --bytes-to-bytes-codecs replaces the codecs, --auto-chunk and --auto-shard replace the size targets, and explicit sizes in --chunk-shape and --shard-shape are retained.
+    /// The chunk and shard shapes are unchanged if --chunk-grid is set.
+    #[arg(long, value_enum)]
+    preset: Option<CodecPreset>,
+
+    /// Verify the output after it is written by re-reading output chunks and comparing them to the input.
+    ///
+    /// Unlike --validate, this is a separate pass after all chunks are written, which detects writes that are not (yet) visible on eventually consistent stores (e.g. object stores).
+    /// All chunks are verified unless --verify-sample is set.
+    /// Fails if any verified chunk does not match the input.
+    #[arg(long, default_value_t = false, conflicts_with = "recursive")]
+    verify: bool,
+
+    /// Only verify N randomly chosen output chunks.
+    #[arg(long, requires = "verify")]
+    verify_sample: Option<usize>,
+
+    /// The random seed for --verify-sample.
+    ///
+    /// Defaults to a random seed, which is recorded in the verification report.
+    #[arg(long, requires = "verify_sample")]
+    verify_seed: Option<u64>,
+
+    /// Write a signed JSON verification report to this path (optional).
+    ///
+    /// The report holds the provenance, the input and output, and the verification result, and its signature is computed over the compact JSON serialisation of the report.
+    #[arg(long, requires = "verify")]
+    verify_report: Option<PathBuf>,
+
+    /// The path to a key for signing the verification report with HMAC-SHA256 (optional).
+    ///
+    /// If unset, the report is signed with a SHA-256 digest, which detects corruption but not tampering.
+    #[arg(long, requires = "verify_report")]
+    verify_key: Option<PathBuf>,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+
+    /// Storage rate limit options.
+    #[command(flatten)]
+    rate_limit: RateLimitArgs,
+
+    /// On-disk read cache options.
+    #[command(flatten)]
+    read_cache: ReadCacheArgs,
+}
+
+fn bar_style_run() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "[{elapsed_precise}/{duration_smoothed}] {bar:40.black/bold} {pos}/{len} ({percent}%) {prefix} {msg}",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+    .with_key("duration_smoothed", duration_smoothed)
+}
+
+fn bar_style_finish() -> ProgressStyle {
+    ProgressStyle::with_template("[{elapsed_precise}/{elapsed_precise}] {prefix} {msg}")
+        .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn progress_callback(stats: ProgressStats, bar: &ProgressBar, mode: ProgressMode) {
+    let (position, length) = stats.position_length(mode);
+    bar.set_length(length);
+    bar.set_position(position);
+    if stats.process_steps.is_empty() {
+        bar.set_message(format!(
+            "rw:{:.2}/{:.2} p:{:.2}",
+            stats.read.as_secs_f32(),
+            stats.write.as_secs_f32(),
+            stats.process.as_secs_f32(),
+        ));
+    } else {
+        bar.set_message(format!(
+            "rw:{:.2}/{:.2} p:{:.2} ({})",
+            stats.read.as_secs_f32(),
+            stats.write.as_secs_f32(),
+            stats.process.as_secs_f32(),
+            stats.process_steps_message(),
+        ));
+    }
+}
+
+struct TokioBlockOn(tokio::runtime::Runtime);
+
+impl AsyncToSyncBlockOn for TokioBlockOn {
+    fn block_on<F: core::future::Future>(&self, future: F) -> F::Output {
+        self.0.block_on(future)
+    }
+}
+
+fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// The store path of `path`, excluding any node path suffix (e.g. `input.zarr#/0` becomes `input.zarr`).
+fn store_path(path: &str) -> String {
+    split_node_path(Path::new(path))
+        .0
+        .to_string_lossy()
+        .to_string()
+}
+
+fn get_storage(path: &str, direct_io: bool) -> anyhow::Result<ReadableListableStorage> {
+    if is_url(path) {
+        let builder = opendal::services::Http::default().endpoint(path);
+        let operator = opendal::Operator::new(builder)?.finish();
+        let storage: AsyncReadableListableStorage = Arc::new(AsyncOpendalStore::new(operator));
+        let block_on = TokioBlockOn(tokio::runtime::Runtime::new()?);
+        Ok(Arc::new(AsyncToSyncStorageAdapter::new(storage, block_on)))
+    // } else if path.starts_with("s3://") {
+    //     let endpoint = "";
+    //     let bucket = "";
+    //     let root = "";
+    //     let region = "auto";
+    //     let builder = opendal::services::S3::default()
+    //         .endpoint(&endpoint)
+    //         .region(&region)
+    //         .root(path)
+    //         .allow_anonymous()
+    //         .bucket(&bucket);
+    //     let operator = opendal::Operator::new(builder)?.finish();
+    //     Arc::new(AsyncOpendalStore::new(operator))
+    } else if is_n5_dataset(Path::new(path)) {
+        Ok(Arc::new(N5Store::new(path)?))
+    } else {
+        Ok(Arc::new(filesystem_store(path, direct_io)?))
+    }
+}
+
+/// Set the chunk and/or shard shape of the encoding arguments from the auto chunk/shard target sizes.
+///
+/// The auto dimensions of the chunk/shard shape are resolved, and all dimensions are auto if the chunk/shard shape is unspecified.
+fn set_auto_chunk_shard_shape<TStorage: ?Sized>(
+    encoding: &mut ZarrReencodingArgs,
+    auto_chunk: Option<u64>,
+    auto_shard: Option<u64>,
+    array_in: &zarrs::array::Array<TStorage>,
+    array_shape: &[u64],
+) -> anyhow::Result<()> {
+    let data_type = if let Some(data_type) = &encoding.data_type {
+        DataType::from_metadata(data_type)?
+    } else {
+        array_in.data_type().clone()
+    };
+    let Some(element_size) = data_type.fixed_size() else {
+        anyhow::bail!(
+            "automatic chunking is not supported for the variable sized data type {data_type}"
+        );
+    };
+
+    if let Some(auto_chunk) = auto_chunk {
+        let chunk_shape = encoding
+            .chunk_shape
+            .clone()
+            .unwrap_or_else(|| vec![ChunkSize::Auto; array_shape.len()]);
+        let chunk_shape = resolve_chunk_shape(&chunk_shape, array_shape, element_size, auto_chunk);
+        println!(
+            "Auto chunk shape: {chunk_shape:?} ({:.2}MB decoded)",
+            (chunk_shape.iter().product::<u64>() * element_size as u64) as f32 / 1e6
+        );
+        encoding.chunk_shape = Some(chunk_shape.into_iter().map(ChunkSize::from).collect());
+    }
+
+    if let Some(auto_shard) = auto_shard {
+        let chunk_shape: Vec<u64> = if let Some(chunk_shape) = &encoding.chunk_shape {
+            resolve_chunk_shape(
+                chunk_shape,
+                array_shape,
+                element_size,
+                AUTO_CHUNK_SIZE_DEFAULT,
+            )
+        } else if let Some(inner_chunk_shape) = array_in.inner_chunk_shape() {
+            inner_chunk_shape.to_array_shape()
+        } else {
+            array_in
+                .chunk_shape(&vec![0; array_in.dimensionality()])?
+                .to_array_shape()
+        };
+        let shard_shape = encoding
+            .shard_shape
+            .clone()
+            .unwrap_or_else(|| vec![ChunkSize::Auto; array_shape.len()]);
+        let shard_shape = resolve_shard_shape(
+            &shard_shape,
+            array_shape,
+            &chunk_shape,
+            element_size,
+            auto_shard,
+        );
+        println!(
+            "Auto shard shape: {shard_shape:?} ({:.2}MB decoded, {} chunks per shard)",
+            (shard_shape.iter().product::<u64>() * element_size as u64) as f32 / 1e6,
+            std::iter::zip(&shard_shape, &chunk_shape)
+                .map(|(shard_size, chunk_size)| shard_size / chunk_size)
+                .product::<u64>()
+        );
+        encoding.chunk_shape = Some(chunk_shape.into_iter().map(ChunkSize::from).collect());
+        encoding.shard_shape = Some(shard_shape.into_iter().map(ChunkSize::from).collect());
+    }
+
+    Ok(())
+}
+
+/// The output storage, which is rate limited if any rate limits are set.
+type OutputStorage = RateLimitStorageAdapter<FilesystemStore>;
+
+/// The method used to write the chunks of an output array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReencodeMethod {
+    /// Copy encoded chunks without decoding (see [`do_copy_chunks`]).
+    Copy,
+    /// Move encoded inner chunks between shards without decoding (see [`do_reshard`]).
+    Reshard,
+    /// Decode and reencode only the bytes to bytes codecs (see [`do_transcode`]).
+    Transcode,
+    /// Decode and reencode chunks (see [`do_reencode`]).
+    Reencode,
+}
+
+impl ReencodeMethod {
+    /// Returns the fastest method that supports the arguments.
+    ///
+    /// Only [`do_reencode`] supports validation, error metrics, and journaling, so the fast paths are skipped if any are enabled.
+    fn new<TStorageIn: ?Sized, TStorageOut: ?Sized>(
+        args: &Args,
+        journal: bool,
+        array_in: &Array<TStorageIn>,
+        array_out: &Array<TStorageOut>,
+    ) -> Self {
+        let force_reencode = args.force_reencode
+            || journal
+            || args.validate != ReencodeValidation::Off
+            || args.error.tolerance().is_some();
+        if force_reencode {
+            Self::Reencode
+        } else if chunk_encoding_unchanged(array_in, array_out) {
+            Self::Copy
+        } else if reshard_compatible(array_in, array_out) {
+            Self::Reshard
+        } else if transcode_compatible(array_in, array_out) {
+            Self::Transcode
+        } else {
+            Self::Reencode
+        }
+    }
+}
+
+/// Reencode the array at `path_in` of the input storage to `path_out` of the output storage.
+///
+/// The `provenance` is written to the output attributes unless `--no-provenance` is set.
+fn reencode_array(
+    args: &Args,
+    storage_in: &ReadableListableStorage,
+    storage_out: &Arc<OutputStorage>,
+    path_in: &str,
+    path_out: &str,
+    provenance: &Provenance,
+    progress_callback: &ProgressCallback,
+) -> anyhow::Result<(
+    Array<dyn ReadableListableStorageTraits>,
+    Array<OutputStorage>,
+    ReencodeStats,
+)> {
+    let array_in = Array::open(storage_in.clone(), path_in)?;
+    if args.verbose {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&array_in.metadata()).unwrap()
+        );
+    }
+
+    if let Some(array_shape) = &args.array_shape {
+        if array_shape.len() != array_in.dimensionality() {
+            anyhow::bail!(
+                "The array shape {array_shape:?} does not match the dimensionality of the input array shape {:?}",
+                array_in.shape()
+            );
+        }
+    }
+    let array_shape = args
+        .array_shape
+        .clone()
+        .unwrap_or_else(|| array_in.shape().to_vec());
+
+    let mut encoding = args.encoding.clone();
+    let (mut auto_chunk, mut auto_shard) = (args.auto_chunk, args.auto_shard);
+    if let Some(preset) = args.preset {
+        preset.apply_codecs(&mut encoding);
+        if encoding.chunk_grid.is_none() {
+            auto_chunk = auto_chunk.or(Some(preset.chunk_size()));
+            auto_shard = auto_shard.or(preset.shard_size());
+        }
+    }
+    if auto_chunk.is_some() || auto_shard.is_some() {
+        set_auto_chunk_shard_shape(
+            &mut encoding,
+            auto_chunk,
+            auto_shard,
+            &array_in,
+            &array_shape,
+        )?;
+    }
+    if let Some(method) = args.set_fill_value_from_data {
+        if let Some(fill_value) = fill_value_from_data(&array_in, method, args.fill_value_sample)? {
+            let data_type = if let Some(data_type) = &encoding.data_type {
+                DataType::from_metadata(data_type)?
+            } else {
+                array_in.data_type().clone()
+            };
+            let fill_value = convert_fill_value(
+                array_in.data_type(),
+                &fill_value,
+                &data_type,
+                encoding.fill_value_cast.unwrap_or_default(),
+            )?;
+            let fill_value = data_type.metadata_fill_value(&fill_value);
+            println!(
+                "{path_out}: fill value {} from data",
+                serde_json::to_string(&fill_value)?
+            );
+            encoding.fill_value = Some(fill_value);
+        } else {
+            eprintln!(
+                "Warning: {path_out}: no data to detect a fill value, the fill value is unchanged"
+            );
+        }
+    }
+    let builder = get_array_builder_reencode(&encoding, &array_in, Some(array_shape))?;
+    let mut array_out = builder.build(storage_out.clone(), path_out)?;
+    if !args.no_provenance {
+        provenance.insert(array_out.attributes_mut());
+    }
+    let journal = if let Some(journal) = &args.journal {
+        if args.resume {
+            if let Ok(array_out_previous) = Array::open(storage_out.clone(), path_out) {
+                if !chunk_encoding_unchanged(&array_out_previous, &array_out) {
+                    anyhow::bail!(
+                        "Cannot resume: the chunk encoding of {path_out} differs from the interrupted run"
+                    );
+                }
+            }
+        }
+        let journal = ChunkJournal::open(journal, &provenance.timestamp, args.resume)?;
+        if args.resume {
+            println!(
+                "{path_out}: resuming with {} completed chunks",
+                journal.num_completed()
+            );
+        }
+        Some(journal)
+    } else {
+        None
+    };
+    array_out.store_metadata()?;
+
+    for difference in args
+        .metadata
+        .check(&array_in, &array_out, MetadataMismatch::Warn)?
+    {
+        eprintln!("Warning: {path_out}: {difference}");
+    }
+
+    let method = ReencodeMethod::new(args, journal.is_some(), &array_in, &array_out);
+    let stats = match method {
+        ReencodeMethod::Copy => {
+            let (store_path_in, store_path_out) =
+                (store_path(&args.path_in), store_path(&args.path_out));
+            if is_url(&store_path_in)
+                || is_n5_dataset(Path::new(&store_path_in))
+                || !args.rate_limit.is_unlimited()
+            {
+                do_copy_chunks(
+                    &array_in,
+                    &array_out,
+                    storage_out.as_ref(),
+                    args.concurrent_chunks,
+                    progress_callback,
+                )?
+            } else {
+                do_copy_chunk_files(
+                    &array_in,
+                    &array_out,
+                    Path::new(&store_path_in),
+                    Path::new(&store_path_out),
+                    args.copy_mode,
+                    args.concurrent_chunks,
+                    progress_callback,
+                )?
+            }
+        }
+        ReencodeMethod::Reshard => do_reshard(
+            &array_in,
+            &array_out,
+            storage_out.as_ref(),
+            args.concurrent_chunks,
+            progress_callback,
+        )?,
+        ReencodeMethod::Transcode => do_transcode(
+            &array_in,
+            &array_out,
+            storage_out.as_ref(),
+            args.concurrent_chunks,
+            progress_callback,
+        )?,
+        ReencodeMethod::Reencode => do_reencode(
+            &array_in,
+            &array_out,
+            args.validate,
+            args.concurrent_chunks,
+            progress_callback,
+            args.cache.cache_size(),
+            args.write.write_shape.clone(),
+            args.prefetch,
+            args.error.tolerance(),
+            journal.as_ref(),
+        )?,
+    };
+    Ok((array_in, array_out, stats))
+}
+
+/// Returns the array and group paths of the hierarchy at `node_path`.
+fn hierarchy_nodes(
+    storage: &ReadableListableStorage,
+    node_path: &str,
+) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    let mut array_paths = vec![];
+    let mut group_paths = vec![];
+    for key in storage.list_prefix(&node_prefix(node_path))? {
+        let (prefix, name) = key.as_str().rsplit_once('/').unwrap_or(("", key.as_str()));
+        let path = format!("/{prefix}");
+        match name {
+            "zarr.json" => {
+                let Some(metadata) = storage.get(&key)? else {
+                    continue;
+                };
+                let metadata: serde_json::Value = serde_json::from_slice(&metadata)?;
+                match metadata["node_type"].as_str() {
+                    Some("array") => array_paths.push(path),
+                    Some("group") => group_paths.push(path),
+                    _ => {}
+                }
+            }
+            ".zarray" => array_paths.push(path),
+            ".zgroup" => group_paths.push(path),
+            _ => {}
+        }
+    }
+    Ok((array_paths, group_paths))
+}
+
+/// The output node path of the input node at `path`, given the node paths of the input and output hierarchies.
+fn output_node_path(path: &str, node_path_in: &str, node_path_out: &str) -> String {
+    let relative_path = path
+        .strip_prefix(node_path_in.trim_end_matches('/'))
+        .unwrap_or(path);
+    let path = format!("{}{relative_path}", node_path_out.trim_end_matches('/'));
+    if path.is_empty() {
+        "/".to_string()
+    } else {
+        path
+    }
+}
+
+/// Reencode every array of the hierarchy at `node_path_in` to `node_path_out`, processing multiple arrays concurrently.
+///
+/// Group attributes are preserved.
+/// The progress of all arrays is aggregated into one progress bar.
+fn reencode_hierarchy(
+    args: &Args,
+    storage_in: &ReadableListableStorage,
+    storage_out: &Arc<OutputStorage>,
+    node_path_in: &str,
+    node_path_out: &str,
+    provenance: &Provenance,
+) -> anyhow::Result<()> {
+    let start = std::time::Instant::now();
+    let (array_paths, group_paths) = hierarchy_nodes(storage_in, node_path_in)?;
+
+    for path in &group_paths {
+        let group = Group::open(storage_in.clone(), path)?;
+        GroupBuilder::new()
+            .attributes(group.attributes().clone())
+            .build(
+                storage_out.clone(),
+                &output_node_path(path, node_path_in, node_path_out),
+            )?
+            .store_metadata()?;
+    }
+
+    let num_arrays = array_paths.len();
+    let concurrent_arrays = args
+        .concurrent_arrays
+        .unwrap_or_else(zarrs_tools::concurrent_target)
+        .max(1);
+
+    let bar = ProgressBar::new(0);
+    bar.set_style(bar_style_run());
+    let array_progress = (0..num_arrays)
+        .map(|_| (AtomicUsize::new(0), AtomicUsize::new(0)))
+        .collect::<Vec<_>>();
+    let position = AtomicUsize::new(0);
+    let length = AtomicUsize::new(0);
+    let num_arrays_done = AtomicUsize::new(0);
+    let bytes_decoded = AtomicUsize::new(0);
+    let update_bar = || {
+        bar.set_length(length.load(Ordering::Relaxed) as u64);
+        bar.set_position(position.load(Ordering::Relaxed) as u64);
+        bar.set_message(format!(
+            "{}/{num_arrays} arrays",
+            num_arrays_done.load(Ordering::Relaxed)
+        ));
+    };
+
+    let arrays = array_paths.iter().enumerate().collect::<Vec<_>>();
+    iter_concurrent_limit!(
+        concurrent_arrays,
+        arrays,
+        try_for_each,
+        |(index, path): (usize, &String)| {
+            let (array_position, array_length) = &array_progress[index];
+            let array_progress_callback = |stats: ProgressStats| {
+                // Accumulate the change in progress of this array, callbacks may be out of order
+                let (stats_position, stats_length) = stats.position_length(args.progress_mode);
+                let (stats_position, stats_length) =
+                    (stats_position as usize, stats_length as usize);
+                let position_prev = array_position.fetch_max(stats_position, Ordering::Relaxed);
+                position.fetch_add(
+                    stats_position.saturating_sub(position_prev),
+                    Ordering::Relaxed,
+                );
+                let length_prev = array_length.fetch_max(stats_length, Ordering::Relaxed);
+                length.fetch_add(stats_length.saturating_sub(length_prev), Ordering::Relaxed);
+                update_bar();
+            };
+            let array_progress_callback = ProgressCallback::new(&array_progress_callback);
+            let (_, _, stats) = reencode_array(
+                args,
+                storage_in,
+                storage_out,
+                path,
+                &output_node_path(path, node_path_in, node_path_out),
+                provenance,
+                &array_progress_callback,
+            )?;
+            bytes_decoded.fetch_add(stats.bytes_decoded, Ordering::Relaxed);
+            num_arrays_done.fetch_add(1, Ordering::Relaxed);
+            update_bar();
+            Ok::<_, anyhow::Error>(())
+        }
+    )?;
+    bar.set_style(bar_style_finish());
+    bar.finish_and_clear();
+
+    let duration = start.elapsed().as_secs_f32();
+    let size_in = storage_in.size_prefix(&node_prefix(node_path_in)).ok();
+    let size_out = storage_out
+        .size_prefix(&node_prefix(node_path_out))
+        .unwrap_or_default();
+    println!(
+        "Reencode {} to {}\n\tarrays: {num_arrays}\n\tgroups: {}\n\ttotal:  {:.2}ms\n\tsize:   {:.2}MB to {:.2}MB ({:.2}MB uncompressed)",
+        args.path_in,
+        args.path_out,
+        group_paths.len(),
+        duration * 1e3, // ms
+        size_in.map_or(f32::NAN, |size| size as f32) / 1e6, // MB
+        size_out as f32 / 1e6, // MB
+        bytes_decoded.into_inner() as f32 / 1e6, // MB
+    );
+    Ok(())
+}
+
+pub fn main(arguments: Vec<OsString>) -> anyhow::Result<()> {
+    let args = zarrs_tools::config::parse_from::<Args>(NAME, &arguments);
+    args.threads.clone().install(|| run(args, &arguments))
+}
+
+fn run(args: Args, arguments: &[OsString]) -> anyhow::Result<()> {
+    zarrs::config::global_config_mut().set_validate_checksums(!args.ignore_checksums);
+    if args.encoding.removes_zarrs_metadata() {
+        zarrs::config::global_config_mut().set_include_zarrs_metadata(false);
+    }
+
+    let (store_path_in, node_path_in) = split_node_path(Path::new(&args.path_in));
+    let (store_path_out, node_path_out) = split_node_path(Path::new(&args.path_out));
+    let storage_in = get_storage(&store_path_in.to_string_lossy(), args.direct_io.read())?;
+    let storage_in = if is_url(&store_path_in.to_string_lossy()) {
+        args.read_cache
+            .cache(storage_in, &store_path_in.to_string_lossy())?
+    } else {
+        storage_in
+    };
+    let storage_in: ReadableListableStorage = if args.rate_limit.is_unlimited() {
+        storage_in
+    } else {
+        Arc::new(args.rate_limit.input(storage_in))
+    };
+    let storage_out = Arc::new(args.rate_limit.output(Arc::new(filesystem_store(
+        &store_path_out,
+        args.direct_io.write(),
+    )?)));
+    if !args.resume {
+        storage_out
+            .erase_prefix(&node_prefix(&node_path_out))
+            .unwrap();
+    }
+    create_parent_groups(&storage_out, &node_path_out)?;
+
+    let provenance = Provenance::new(NAME, arguments, vec![args.path_in.clone()]);
+    if args.recursive {
+        return reencode_hierarchy(
+            &args,
+            &storage_in,
+            &storage_out,
+            &node_path_in,
+            &node_path_out,
+            &provenance,
+        );
+    }
+
+    let bar = ProgressBar::new(0);
+    bar.set_style(bar_style_run());
+    let reencode_progress_callback =
+        |stats: ProgressStats| progress_callback(stats, &bar, args.progress_mode);
+    let reencode_progress_callback = ProgressCallback::new(&reencode_progress_callback);
+
+    let (array_in, array_out, stats) = reencode_array(
+        &args,
+        &storage_in,
+        &storage_out,
+        &node_path_in,
+        &node_path_out,
+        &provenance,
+        &reencode_progress_callback,
+    )?;
+    bar.set_style(bar_style_finish());
+    bar.finish_and_clear();
+    let size_in = storage_in.size_prefix(&node_prefix(&node_path_in)).ok();
+    let size_out = storage_out
+        .size_prefix(&node_prefix(&node_path_out))
+        .unwrap_or_default();
+    let ReencodeStats {
+        duration,
+        duration_read,
+        duration_write,
+        bytes_decoded,
+        ..
+    } = stats;
+    let size_in_f32 = size_in.map_or(f32::NAN, |size| size as f32);
+    println!(
+        "Reencode {} to {}\n\tread:  ~{:.2}ms @ {:.2}GB/s\n\twrite: ~{:.2}ms @ {:.2}GB/s\n\ttotal: {:.2}ms\n\tsize:  {:.2}MB to {:.2}MB ({:.2}MB uncompressed)",
+        args.path_in,
+        args.path_out,
+        duration_read * 1e3, // ms
+        size_in_f32 / 1e9 / duration_read, // GB/s
+        duration_write * 1e3, // ms
+        size_out as f32 / 1e9 / duration_write, // GB/s
+        duration * 1e3, // ms
+        size_in_f32 / 1e6, // MB
+        size_out as f32 / 1e6, // MB
+        bytes_decoded as f32 / 1e6, // MB
+    );
+    if let Some(ErrorMetrics {
+        max_absolute,
+        mean_absolute,
+        max_relative,
+        mean_relative,
+        ..
+    }) = stats.error_metrics
+    {
+        println!(
+            "Error {} relative to {}\n\tabsolute: max {max_absolute:e} mean {mean_absolute:e}\n\trelative: max {max_relative:e} mean {mean_relative:e}",
+            args.path_out, args.path_in,
+        );
+    }
+
+    if args.verify {
+        let bar = ProgressBar::new(0);
+        bar.set_style(bar_style_run());
+        bar.set_prefix("verify");
+        let progress_callback =
+            |stats: ProgressStats| progress_callback(stats, &bar, ProgressMode::Steps);
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let report = do_verify(
+            &array_in,
+            &array_out,
+            args.verify_sample,
+            args.verify_seed,
+            args.concurrent_chunks,
+            &progress_callback,
+        )?;
+        bar.set_style(bar_style_finish());
+        bar.finish_and_clear();
+        println!(
+            "Verify {} against {}\n\tchunks:     {}/{}\n\tmismatched: {}\n\ttotal:      {:.2}ms",
+            args.path_out,
+            args.path_in,
+            report.num_chunks_verified,
+            report.num_chunks,
+            report.mismatched_chunks.len(),
+            report.duration * 1e3, // ms
+        );
+        if let Some(verify_report) = &args.verify_report {
+            let key = args.verify_key.as_ref().map(std::fs::read).transpose()?;
+            let report = SignedVerifyReport::new(&args, &provenance, &report, key.as_deref())?;
+            std::fs::write(verify_report, serde_json::to_string_pretty(&report)?)?;
+        }
+        if !report.passed() {
+            anyhow::bail!(
+                "Verification failed: {} chunks do not match the input (e.g. {:?})",
+                report.mismatched_chunks.len(),
+                report.mismatched_chunks[0]
+            );
+        }
+    }
+
+    if let Some(stats_json) = &args.stats_json {
+        let summary = ReencodeSummary::new(
+            &args,
+            serde_json::to_value(array_in.metadata())?,
+            serde_json::to_value(array_out.metadata())?,
+            size_in,
+            size_out,
+            &stats,
+        );
+        std::fs::write(stats_json, serde_json::to_string_pretty(&summary)?)?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ReencodeSummary {
+    zarrs_tools_version: &'static str,
+    zarrs_version: &'static str,
+    input: ReencodeSummaryArray,
+    output: ReencodeSummaryArray,
+    duration_s: ReencodeSummaryDuration,
+    bytes_decoded: usize,
+    throughput_gbs: ReencodeSummaryThroughput,
+    chunks: ReencodeSummaryChunks,
+    cache: Option<ReencodeSummaryCache>,
+    error: Option<ErrorMetrics>,
+}
+
+#[derive(Serialize)]
+struct ReencodeSummaryArray {
+    path: String,
+    metadata: serde_json::Value,
+    /// The stored size (bytes), if the store supports querying its size.
+    size: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ReencodeSummaryDuration {
+    total: f32,
+    read: f32,
+    write: f32,
+}
+
+#[derive(Serialize)]
+struct ReencodeSummaryThroughput {
+    read: Option<f32>,
+    write: f32,
+    decoded: f32,
+}
+
+#[derive(Serialize)]
+struct ReencodeSummaryChunks {
+    output: usize,
+    writes: usize,
+}
+
+#[derive(Serialize)]
+struct ReencodeSummaryCache {
+    requests: usize,
+    misses: usize,
+    hit_rate: Option<f32>,
+}
+
+impl ReencodeSummary {
+    fn new(
+        args: &Args,
+        metadata_in: serde_json::Value,
+        metadata_out: serde_json::Value,
+        size_in: Option<u64>,
+        size_out: u64,
+        stats: &ReencodeStats,
+    ) -> Self {
+        Self {
+            zarrs_tools_version: env!("CARGO_PKG_VERSION"),
+            zarrs_version: zarrs::version::version_str(),
+            input: ReencodeSummaryArray {
+                path: args.path_in.clone(),
+                metadata: metadata_in,
+                size: size_in,
+            },
+            output: ReencodeSummaryArray {
+                path: args.path_out.clone(),
+                metadata: metadata_out,
+                size: Some(size_out),
+            },
+            duration_s: ReencodeSummaryDuration {
+                total: stats.duration,
+                read: stats.duration_read,
+                write: stats.duration_write,
+            },
+            bytes_decoded: stats.bytes_decoded,
+            throughput_gbs: ReencodeSummaryThroughput {
+                read: size_in.map(|size_in| size_in as f32 / 1e9 / stats.duration_read),
+                write: size_out as f32 / 1e9 / stats.duration_write,
+                decoded: stats.bytes_decoded as f32 / 1e9 / stats.duration,
+            },
+            chunks: ReencodeSummaryChunks {
+                output: stats.num_chunks,
+                writes: stats.num_writes,
+            },
+            cache: stats
+                .cache_requests_misses
+                .map(|(requests, misses)| ReencodeSummaryCache {
+                    requests,
+                    misses,
+                    hit_rate: (requests > 0).then(|| (requests - misses) as f32 / requests as f32),
+                }),
+            error: stats.error_metrics,
+        }
+    }
+}
+
+/// A verification report with its signature.
+#[derive(Serialize)]
+struct SignedVerifyReport {
+    report: serde_json::Value,
+    signature: VerifySignature,
+}
+
+#[derive(Serialize)]
+struct VerifySignature {
+    /// Either `hmac-sha256` or `sha256`.
+    algorithm: &'static str,
+    /// The hex encoded signature of the compact JSON serialisation of the report.
+    value: String,
+}
+
+impl SignedVerifyReport {
+    /// Sign a report of `verify_report` with the `provenance` written to the output attributes.
+    fn new(
+        args: &Args,
+        provenance: &Provenance,
+        verify_report: &VerifyReport,
+        key: Option<&[u8]>,
+    ) -> anyhow::Result<Self> {
+        let report = serde_json::json!({
+            "provenance": provenance,
+            "input": args.path_in,
+            "output": args.path_out,
+            "verification": verify_report,
+            "passed": verify_report.passed(),
+        });
+        let bytes = serde_json::to_vec(&report)?;
+        let (algorithm, digest) = if let Some(key) = key {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key)
+                .map_err(|err| anyhow::anyhow!("invalid verification key: {err}"))?;
+            mac.update(&bytes);
+            ("hmac-sha256", mac.finalize().into_bytes().to_vec())
+        } else {
+            ("sha256", Sha256::digest(&bytes).to_vec())
+        };
+        Ok(Self {
+            report,
+            signature: VerifySignature {
+                algorithm,
+                value: digest.iter().map(|byte| format!("{byte:02x}")).collect(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use super::*;
+
+    /// Reencode a `u16` array built by `builder` with the command line arguments `args`.
+    ///
+    /// Returns the reencode method and stats.
+    fn reencode_with_args(
+        builder: &ArrayBuilder,
+        args: &[&str],
+    ) -> Result<(ReencodeMethod, ReencodeStats), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let (path_in, path_out) = (dir.path().join("in.zarr"), dir.path().join("out.zarr"));
+        let (path_in, path_out) = (
+            path_in.to_string_lossy().to_string(),
+            path_out.to_string_lossy().to_string(),
+        );
+
+        let storage_in: ReadableListableStorage = Arc::new(FilesystemStore::new(&path_in)?);
+        let array = builder.build(Arc::new(FilesystemStore::new(&path_in)?), "/")?;
+        array.store_metadata()?;
+        let elements = (0..array.shape().iter().product::<u64>())
+            .map(|i| i as u16)
+            .collect::<Vec<_>>();
+        array.store_array_subset_elements::<u16>(&array.subset_all(), &elements)?;
+
+        let args = Args::try_parse_from(
+            std::iter::once("zarrs_reencode")
+                .chain(args.iter().copied())
+                .chain([path_in.as_str(), path_out.as_str()]),
+        )?;
+        let storage_out = Arc::new(
+            args.rate_limit
+                .output(Arc::new(FilesystemStore::new(&path_out)?)),
+        );
+        let progress_callback = |_| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let (array_in, array_out, stats) = reencode_array(
+            &args,
+            &storage_in,
+            &storage_out,
+            "/",
+            "/",
+            &Provenance::new(NAME, &[], vec![path_in.clone()]),
+            &progress_callback,
+        )?;
+        assert_eq!(
+            array_out.retrieve_array_subset_elements::<u16>(&array_out.subset_all())?,
+            elements
+        );
+        let method = ReencodeMethod::new(&args, false, &array_in, &array_out);
+        Ok((method, stats))
+    }
+
+    #[test]
+    fn reencode_validate_unchanged_encoding() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            vec![4, 4].try_into()?,
+            FillValue::from(0u16),
+        );
+
+        let (method, stats) = reencode_with_args(&builder, &[])?;
+        assert_eq!(method, ReencodeMethod::Copy);
+        assert_eq!(stats.bytes_decoded, 0);
+
+        let (method, stats) = reencode_with_args(&builder, &["--validate=input"])?;
+        assert_eq!(method, ReencodeMethod::Reencode);
+        assert!(stats.bytes_decoded > 0);
+
+        let (method, stats) = reencode_with_args(&builder, &["--error-metrics"])?;
+        assert_eq!(method, ReencodeMethod::Reencode);
+        assert_eq!(
+            stats.error_metrics.map(|metrics| metrics.max_absolute),
+            Some(0.0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_validate_reshard() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            vec![2, 2].try_into()?,
+            FillValue::from(0u16),
+        );
+
+        let (method, stats) = reencode_with_args(&builder, &["--shard-shape=4,4"])?;
+        assert_eq!(method, ReencodeMethod::Reshard);
+        assert_eq!(stats.bytes_decoded, 0);
+
+        let (method, stats) = reencode_with_args(&builder, &["--shard-shape=4,4", "--validate"])?;
+        assert_eq!(method, ReencodeMethod::Reencode);
+        assert!(stats.bytes_decoded > 0);
+
+        let (method, stats) =
+            reencode_with_args(&builder, &["--shard-shape=4,4", "--max-absolute-error=0"])?;
+        assert_eq!(method, ReencodeMethod::Reencode);
+        assert!(stats.error_metrics.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_validate_transcode() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            vec![4, 4].try_into()?,
+            FillValue::from(0u16),
+        );
+        let zstd = r#"--bytes-to-bytes-codecs=[{"name":"zstd","configuration":{"level":3,"checksum":false}}]"#;
+
+        let (method, stats) = reencode_with_args(&builder, &[zstd])?;
+        assert_eq!(method, ReencodeMethod::Transcode);
+        assert!(stats.error_metrics.is_none());
+
+        let (method, _) = reencode_with_args(&builder, &[zstd, "--validate=round-trip"])?;
+        assert_eq!(method, ReencodeMethod::Reencode);
+
+        let (method, stats) = reencode_with_args(&builder, &[zstd, "--error-metrics"])?;
+        assert_eq!(method, ReencodeMethod::Reencode);
+        assert!(stats.error_metrics.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_array_shape_dimensionality() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            vec![4, 4].try_into()?,
+            FillValue::from(0u16),
+        );
+        let err = reencode_with_args(&builder, &["--array-shape=7,6,1"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("The array shape [7, 6, 1] does not match the dimensionality"));
+        Ok(())
+    }
+
+    #[test]
+    fn verify_report_provenance() -> Result<(), Box<dyn std::error::Error>> {
+        let args = Args::try_parse_from(["zarrs_reencode", "in.zarr", "out.zarr"])?;
+        let provenance = Provenance {
+            timestamp: "2000-01-01T00:00:00Z".to_string(),
+            ..Provenance::new(NAME, &[], vec![args.path_in.clone()])
+        };
+        let verify_report = VerifyReport {
+            num_chunks: 1,
+            num_chunks_verified: 1,
+            seed: None,
+            mismatched_chunks: vec![],
+            duration: 0.0,
+        };
+        let report = SignedVerifyReport::new(&args, &provenance, &verify_report, None)?;
+        assert_eq!(
+            report.report["provenance"],
+            serde_json::to_value(&provenance)?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+use std::{ffi::OsString, net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use clap::Parser;
+use zarrs::storage::{byte_range::ByteRange, AsyncReadableStorage, StoreKey};
+use zarrs_opendal::AsyncOpendalStore;
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_serve";
+
+/// Serve a Zarr hierarchy over HTTP (read-only).
+///
+/// Supports ranged requests and optional CORS, so that a hierarchy can be viewed directly in tools like neuroglancer or vizarr.
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Cli {
+    /// The zarr hierarchy path or URL.
+    ///
+    /// If a URL is supplied, requests are proxied to the remote store.
+    path: String,
+
+    /// The address to listen on.
+    #[arg(long, default_value = "127.0.0.1")]
+    address: std::net::IpAddr,
+
+    /// The port to listen on.
+    #[arg(long, short, default_value_t = 8080)]
+    port: u16,
+
+    /// Enable CORS (cross-origin resource sharing).
+    #[arg(long, default_value_t = false)]
+    cors: bool,
+
+    /// The allowed CORS origin.
+    #[arg(long, default_value = "*", requires = "cors")]
+    cors_origin: HeaderValue,
+
+    /// Print each request.
+    #[arg(long, short, default_value_t = false)]
+    verbose: bool,
+}
+
+struct ServeState {
+    storage: AsyncReadableStorage,
+    cors: Option<HeaderValue>,
+    verbose: bool,
+}
+
+/// The byte range of a HTTP `Range` request header.
+#[derive(Debug, PartialEq, Eq)]
+enum RangeRequest {
+    /// The full value (no range requested, or the range is ignored).
+    Full,
+    /// An inclusive byte range.
+    Partial(u64, u64),
+    /// The range cannot be satisfied.
+    Unsatisfiable,
+}
+
+/// Parse a HTTP `Range` request header for a value of `size` bytes.
+///
+/// Only a single byte range is supported, multiple ranges or malformed ranges are ignored.
+fn parse_range(range: &str, size: u64) -> RangeRequest {
+    let Some(range) = range.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if range.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = range.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 || size == 0 {
+                RangeRequest::Unsatisfiable
+            } else {
+                RangeRequest::Partial(size.saturating_sub(suffix), size - 1)
+            }
+        }
+        (Ok(start), Err(_)) if end.is_empty() => {
+            if start >= size {
+                RangeRequest::Unsatisfiable
+            } else {
+                RangeRequest::Partial(start, size - 1)
+            }
+        }
+        (Ok(start), Ok(end)) if start <= end => {
+            if start >= size {
+                RangeRequest::Unsatisfiable
+            } else {
+                RangeRequest::Partial(start, end.min(size - 1))
+            }
+        }
+        _ => RangeRequest::Full,
+    }
+}
+
+/// Return the content type of a store key.
+fn content_type(key: &StoreKey) -> &'static str {
+    let name = key.as_str().rsplit('/').next().unwrap_or_default();
+    if name.ends_with(".json") || matches!(name, ".zarray" | ".zgroup" | ".zattrs" | ".zmetadata") {
+        "application/json"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+fn add_cors_headers(headers: &mut HeaderMap, cors: Option<&HeaderValue>) {
+    if let Some(origin) = cors {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        headers.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static("Accept-Ranges, Content-Length, Content-Range"),
+        );
+        if origin != "*" {
+            headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+        }
+    }
+}
+
+async fn serve(
+    State(state): State<Arc<ServeState>>,
+    method: Method,
+    uri: Uri,
+    request_headers: HeaderMap,
+) -> Response {
+    let range = request_headers
+        .get(header::RANGE)
+        .and_then(|range| range.to_str().ok());
+    let mut response = serve_key(&state, &method, uri.path(), range).await;
+    add_cors_headers(response.headers_mut(), state.cors.as_ref());
+    if state.verbose {
+        println!(
+            "{method} {} {}{}",
+            uri.path(),
+            range.map(|range| format!("{range} ")).unwrap_or_default(),
+            response.status()
+        );
+    }
+    response
+}
+
+async fn serve_key(
+    state: &ServeState,
+    method: &Method,
+    path: &str,
+    range: Option<&str>,
+) -> Response {
+    const ALLOW: HeaderValue = HeaderValue::from_static("GET, HEAD, OPTIONS");
+    match *method {
+        Method::GET | Method::HEAD => {}
+        Method::OPTIONS => {
+            let mut response = StatusCode::NO_CONTENT.into_response();
+            let headers = response.headers_mut();
+            headers.insert(header::ALLOW, ALLOW);
+            if state.cors.is_some() {
+                headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, ALLOW);
+                headers.insert(
+                    header::ACCESS_CONTROL_ALLOW_HEADERS,
+                    HeaderValue::from_static("Range"),
+                );
+                headers.insert(
+                    header::ACCESS_CONTROL_MAX_AGE,
+                    HeaderValue::from_static("86400"),
+                );
+            }
+            return response;
+        }
+        _ => return (StatusCode::METHOD_NOT_ALLOWED, [(header::ALLOW, ALLOW)]).into_response(),
+    }
+
+    // Reject relative path components (after percent-decoding) to prevent serving files outside of the hierarchy
+    let Ok(path) = percent_encoding::percent_decode_str(path).decode_utf8() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let path = path.trim_start_matches('/');
+    if path
+        .split('/')
+        .any(|component| matches!(component, "." | ".."))
+    {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let Ok(key) = StoreKey::new(path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let size = match state.storage.size_key(&key).await {
+        Ok(Some(size)) => size,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            eprintln!("Error: {key}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let range = range.map_or(RangeRequest::Full, |range| parse_range(range, size));
+    let (status, byte_range, content_range) = match range {
+        RangeRequest::Full => (StatusCode::OK, ByteRange::FromStart(0, None), None),
+        RangeRequest::Partial(start, end) => (
+            StatusCode::PARTIAL_CONTENT,
+            ByteRange::FromStart(start, Some(end - start + 1)),
+            Some(format!("bytes {start}-{end}/{size}")),
+        ),
+        RangeRequest::Unsatisfiable => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{size}"))],
+            )
+                .into_response();
+        }
+    };
+    let content_length = byte_range.length(size);
+
+    let mut response = if *method == Method::HEAD {
+        Response::new(Body::empty())
+    } else {
+        match state
+            .storage
+            .get_partial_values_key(&key, &[byte_range])
+            .await
+        {
+            Ok(Some(mut bytes)) => Response::new(Body::from(bytes.remove(0))),
+            Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+            Err(err) => {
+                eprintln!("Error: {key}: {err}");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    };
+    *response.status_mut() = status;
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(content_type(&key)),
+    );
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.insert(header::CONTENT_LENGTH, content_length.into());
+    if let Some(content_range) = content_range {
+        if let Ok(content_range) = HeaderValue::from_str(&content_range) {
+            headers.insert(header::CONTENT_RANGE, content_range);
+        }
+    }
+    response
+}
+
+fn get_storage(path: &str) -> anyhow::Result<AsyncReadableStorage> {
+    let operator = if path.starts_with("http://") || path.starts_with("https://") {
+        let builder = opendal::services::Http::default().endpoint(path);
+        opendal::Operator::new(builder)?.finish()
+    } else {
+        let path = std::fs::canonicalize(path)?;
+        let builder = opendal::services::Fs::default().root(&path.to_string_lossy());
+        opendal::Operator::new(builder)?.finish()
+    };
+    Ok(Arc::new(AsyncOpendalStore::new(operator)))
+}
+
+pub fn main(args: Vec<OsString>) -> anyhow::Result<()> {
+    tokio::runtime::Runtime::new()?.block_on(run(args))
+}
+
+async fn run(args: Vec<OsString>) -> anyhow::Result<()> {
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, args);
+
+    let state = Arc::new(ServeState {
+        storage: get_storage(&cli.path)?,
+        cors: cli.cors.then_some(cli.cors_origin),
+        verbose: cli.verbose,
+    });
+    let app = Router::new().fallback(serve).with_state(state);
+
+    let address = SocketAddr::new(cli.address, cli.port);
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    println!("Serving {} at http://{}", cli.path, listener.local_addr()?);
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_range_single() {
+        assert_eq!(parse_range("bytes=2-5", 10), RangeRequest::Partial(2, 5));
+        assert_eq!(parse_range("bytes=2-20", 10), RangeRequest::Partial(2, 9));
+        // Open-ended
+        assert_eq!(parse_range("bytes=4-", 10), RangeRequest::Partial(4, 9));
+        // Suffix
+        assert_eq!(parse_range("bytes=-3", 10), RangeRequest::Partial(7, 9));
+        assert_eq!(parse_range("bytes=-20", 10), RangeRequest::Partial(0, 9));
+    }
+
+    #[test]
+    fn parse_range_unsatisfiable() {
+        assert_eq!(parse_range("bytes=10-12", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=10-", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-3", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn parse_range_ignored() {
+        // Multiple ranges
+        assert_eq!(parse_range("bytes=0-1, 4-5", 10), RangeRequest::Full);
+        // Malformed ranges
+        assert_eq!(parse_range("bytes=5-2", 10), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=a-b", 10), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=5", 10), RangeRequest::Full);
+        assert_eq!(parse_range("items=0-1", 10), RangeRequest::Full);
+    }
+
+    /// Serve a hierarchy with a chunk `c/0/0` and a key `a b/c` in a subdirectory of a temporary directory with a file `secret` outside of the hierarchy.
+    fn serve_state() -> Result<(tempfile::TempDir, ServeState), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("hierarchy.zarr");
+        std::fs::create_dir_all(root.join("c/0"))?;
+        std::fs::create_dir_all(root.join("a b"))?;
+        std::fs::write(root.join("zarr.json"), br#"{"zarr_format":3}"#)?;
+        std::fs::write(root.join("c/0/0"), (0..10).collect::<Vec<u8>>())?;
+        std::fs::write(root.join("a b/c"), [1, 2, 3])?;
+        std::fs::write(dir.path().join("secret"), [0])?;
+        let state = ServeState {
+            storage: get_storage(&root.to_string_lossy())?,
+            cors: None,
+            verbose: false,
+        };
+        Ok((dir, state))
+    }
+
+    async fn body(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn serve_key_full() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, state) = serve_state()?;
+        let response = serve_key(&state, &Method::GET, "/zarr.json", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(body(response).await, br#"{"zarr_format":3}"#);
+
+        let response = serve_key(&state, &Method::HEAD, "/c/0/0", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "10");
+        assert!(body(response).await.is_empty());
+
+        let response = serve_key(&state, &Method::GET, "/c/0/1", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serve_key_partial() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, state) = serve_state()?;
+        let response = serve_key(&state, &Method::GET, "/c/0/0", Some("bytes=-3")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 7-9/10");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "3");
+        assert_eq!(body(response).await, [7, 8, 9]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serve_key_unsatisfiable() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, state) = serve_state()?;
+        let response = serve_key(&state, &Method::GET, "/c/0/0", Some("bytes=10-")).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serve_key_traversal() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, state) = serve_state()?;
+        for path in [
+            "/../secret",
+            "/c/../../secret",
+            "/%2e%2e/secret",
+            "/c/%2E%2E/%2e%2e/secret",
+            "/..%2Fsecret",
+            "/./zarr.json",
+        ] {
+            let response = serve_key(&state, &Method::GET, path, None).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{path}");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serve_key_percent_encoded() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, state) = serve_state()?;
+        let response = serve_key(&state, &Method::GET, "/a%20b/c", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, [1, 2, 3]);
+        let response = serve_key(&state, &Method::GET, "/c%2F0%2F0", Some("bytes=0-1")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body(response).await, [0, 1]);
+        // Invalid UTF-8
+        let response = serve_key(&state, &Method::GET, "/%ff", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+}
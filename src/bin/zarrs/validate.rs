@@ -0,0 +1,557 @@
+use std::ffi::OsString;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use clap::Parser;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon_iter_concurrent_limit::iter_concurrent_limit;
+use zarrs::array::{codec::CodecOptionsBuilder, Array};
+use zarrs::array_subset::ArraySubset;
+use zarrs::storage::{
+    byte_range::ByteRange,
+    storage_adapter::async_to_sync::{AsyncToSyncBlockOn, AsyncToSyncStorageAdapter},
+    AsyncReadableListableStorage, ReadableListableStorage,
+};
+use zarrs_opendal::AsyncOpendalStore;
+use zarrs_tools::{
+    calculate_chunk_and_codec_concurrency, filesystem_store,
+    metadata_comparison::{MetadataComparisonArgs, MetadataMismatch},
+    progress::duration_smoothed,
+    sample_chunk_indices, DirectIo, ReadCacheArgs, ThreadsArgs,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_validate";
+
+/// Compare the data in two Zarr arrays.
+///
+/// Equality of the arrays is determined by comparing the shape, data type, and data.
+///
+/// Differences in encoding (e.g codecs, chunk key encoding) are ignored.
+/// Differences in dimension names, units, and attributes are ignored unless compared with --compare-dimension-names, --compare-units, or --compare-attributes.
+#[derive(Parser, Debug)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Args {
+    /// The path or URL of the first zarr array.
+    first: String,
+
+    /// The path or URL of the second zarr array.
+    second: String,
+
+    /// Number of concurrent chunks to compare.
+    #[arg(long)]
+    concurrent_chunks: Option<usize>,
+
+    /// Subset offset (optional). A comma separated list of the offset along each array dimension.
+    ///
+    /// Only the region defined by `--offset` and `--shape` is compared.
+    /// Defaults to the array origin.
+    #[arg(long, value_delimiter = ',')]
+    offset: Option<Vec<u64>>,
+
+    /// Subset shape (optional). A comma separated list of the size along each array dimension.
+    ///
+    /// Defaults to the remainder of the array after `--offset`.
+    #[arg(long, value_delimiter = ',')]
+    shape: Option<Vec<u64>>,
+
+    /// Only compare N randomly chosen chunks (of the comparison grid, see `--grid`) in the subset.
+    #[arg(long)]
+    sample: Option<usize>,
+
+    /// The random seed for `--sample`.
+    #[arg(long, requires = "sample")]
+    seed: Option<u64>,
+
+    /// The chunk grid iterated over when comparing the arrays.
+    ///
+    /// Comparing arrays with different chunking (or sharding) on the grid of one array can re-read chunks (or shards) of the other many times.
+    #[arg(long, value_enum, default_value_t = ComparisonGrid::First)]
+    grid: ComparisonGrid,
+
+    /// Compare the sizes and stored crc32c checksums of encoded chunks instead of decoding them.
+    ///
+    /// Requires that the arrays have identical encoding (chunk grid, fill value, and codecs) with a codec chain ending in crc32c.
+    /// Chunks with mismatched checksums (or that only exist in one array) are decoded and compared.
+    #[arg(long, conflicts_with = "grid")]
+    checksum_only: bool,
+
+    /// Metadata comparison options.
+    #[command(flatten)]
+    metadata: MetadataComparisonArgs,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=both`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "both", default_value_t = DirectIo::Read)]
+    direct_io: DirectIo,
+
+    /// On-disk read cache options.
+    #[command(flatten)]
+    read_cache: ReadCacheArgs,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+/// The chunk grid iterated over when comparing arrays.
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum ComparisonGrid {
+    /// The chunk (or shard) grid of the first array.
+    First,
+    /// The chunk (or shard) grid of the second array.
+    Second,
+    /// The coarsest grid aligned with the chunks (or shards) of both arrays.
+    ///
+    /// The chunk shape is the least common multiple of the chunk (or shard) shapes of the arrays, so each chunk of either array is read once.
+    Coarser,
+}
+
+fn bar_style_run() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "[{elapsed_precise}/{duration_smoothed}] {bar:40.black/bold} {pos}/{len} ({percent}%) {prefix} {msg}",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+    .with_key("duration_smoothed", duration_smoothed)
+}
+
+struct TokioBlockOn(tokio::runtime::Runtime);
+
+impl AsyncToSyncBlockOn for TokioBlockOn {
+    fn block_on<F: core::future::Future>(&self, future: F) -> F::Output {
+        self.0.block_on(future)
+    }
+}
+
+fn get_storage(
+    path: &str,
+    direct_io: bool,
+    read_cache: &ReadCacheArgs,
+) -> anyhow::Result<ReadableListableStorage> {
+    if path.starts_with("http://") || path.starts_with("https://") {
+        let builder = opendal::services::Http::default().endpoint(path);
+        let operator = opendal::Operator::new(builder)?.finish();
+        let storage: AsyncReadableListableStorage = Arc::new(AsyncOpendalStore::new(operator));
+        let block_on = TokioBlockOn(tokio::runtime::Runtime::new()?);
+        Ok(read_cache.cache(
+            Arc::new(AsyncToSyncStorageAdapter::new(storage, block_on)),
+            path,
+        )?)
+    // } else if path.starts_with("s3://") {
+    //     let endpoint = "";
+    //     let bucket = "";
+    //     let root = "";
+    //     let region = "auto";
+    //     let builder = opendal::services::S3::default()
+    //         .endpoint(&endpoint)
+    //         .region(&region)
+    //         .root(path)
+    //         .allow_anonymous()
+    //         .bucket(&bucket);
+    //     let operator = opendal::Operator::new(builder)?.finish();
+    //     Arc::new(AsyncOpendalStore::new(operator))
+    } else {
+        Ok(Arc::new(filesystem_store(path, direct_io)?))
+    }
+}
+
+pub fn main(args: Vec<OsString>) {
+    match try_main(args) {
+        Ok(success) => println!("{}", success),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn try_main(args: Vec<OsString>) -> anyhow::Result<String> {
+    let args = zarrs_tools::config::parse_from::<Args>(NAME, args);
+    args.threads.clone().install(|| run(args))
+}
+
+fn run(args: Args) -> anyhow::Result<String> {
+    let storage1 = get_storage(&args.first, args.direct_io.read(), &args.read_cache)?;
+    let storage2 = get_storage(&args.second, args.direct_io.read(), &args.read_cache)?;
+    let array1 = zarrs::array::Array::open(storage1.clone(), "/").unwrap();
+    let array2 = zarrs::array::Array::open(storage2.clone(), "/").unwrap();
+
+    let bar = ProgressBar::new(0);
+    bar.set_style(bar_style_run());
+
+    if array1.shape() != array2.shape() {
+        anyhow::bail!(
+            "Array shapes do not match: {:?} vs {:?}",
+            array1.shape(),
+            array2.shape()
+        );
+    } else if array1.data_type() != array2.data_type() {
+        anyhow::bail!(
+            "Array data types do not match: {} vs {}",
+            array1.data_type(),
+            array2.data_type()
+        );
+    }
+
+    for difference in args
+        .metadata
+        .check(&array1, &array2, MetadataMismatch::Error)?
+    {
+        eprintln!("Warning: {difference}");
+    }
+
+    let subset = get_subset(
+        array1.shape(),
+        args.offset.as_deref(),
+        args.shape.as_deref(),
+    )?;
+    if args.checksum_only {
+        check_checksum_encoding(&array1, &array2)?;
+    }
+    let chunk_shape1 = array1.chunk_shape(&vec![0; array1.dimensionality()])?;
+    let chunk_shape2 = array2.chunk_shape(&vec![0; array2.dimensionality()])?;
+    // The comparison grid is the (identical) chunk grid of the arrays if comparing checksums
+    let comparison_chunk_shape: Vec<u64> = match args.grid {
+        ComparisonGrid::First => chunk_shape1.iter().map(|c| c.get()).collect(),
+        ComparisonGrid::Second => chunk_shape2.iter().map(|c| c.get()).collect(),
+        ComparisonGrid::Coarser => std::iter::zip(chunk_shape1.iter(), chunk_shape2.iter())
+            .map(|(c1, c2)| lcm(c1.get(), c2.get()))
+            .collect(),
+    };
+    let chunks = chunks_in_subset(&comparison_chunk_shape, &subset)?;
+    let chunk_indices = if let Some(sample) = args.sample {
+        let mut rng = args
+            .seed
+            .map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+        sample_chunk_indices(&chunks, sample, &mut rng)
+    } else {
+        chunks.indices().into_iter().collect::<Vec<_>>()
+    };
+
+    let chunk_representation = array1
+        .chunk_array_representation(&vec![0; array1.chunk_grid().dimensionality()])
+        .unwrap();
+
+    let concurrent_target = zarrs_tools::concurrent_target();
+    let (chunks_concurrent_limit, codec_concurrent_target) = calculate_chunk_and_codec_concurrency(
+        concurrent_target,
+        args.concurrent_chunks,
+        array1.codecs(),
+        chunk_indices.len(),
+        &chunk_representation,
+    );
+    let codec_options = CodecOptionsBuilder::new()
+        .concurrent_target(codec_concurrent_target)
+        .build();
+
+    let num_iterations = chunk_indices.len();
+    bar.set_length(num_iterations as u64);
+    let step = AtomicU64::new(0);
+    let checksum_matches = AtomicU64::new(0);
+    iter_concurrent_limit!(
+        chunks_concurrent_limit,
+        chunk_indices,
+        try_for_each,
+        |chunk_indices: Vec<u64>| {
+            let step = step.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            bar.set_position(step);
+            let chunk_subset = ArraySubset::new_with_start_shape(
+                std::iter::zip(&chunk_indices, &comparison_chunk_shape)
+                    .map(|(i, c)| i * c)
+                    .collect(),
+                comparison_chunk_shape.clone(),
+            )?;
+            let chunk_subset = chunk_subset.overlap(&subset)?;
+            if args.checksum_only
+                && chunk_checksums_match(&storage1, &array1, &storage2, &array2, &chunk_indices)?
+            {
+                checksum_matches.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                return Ok(());
+            }
+            let bytes_first = array1.retrieve_array_subset_opt(&chunk_subset, &codec_options)?;
+            let bytes_second = array2.retrieve_array_subset_opt(&chunk_subset, &codec_options)?;
+            if bytes_first == bytes_second {
+                Ok(())
+            } else {
+                anyhow::bail!("Data differs in region: {chunk_subset}");
+            }
+        }
+    )?;
+    bar.finish_and_clear();
+
+    let checksum_matches = if args.checksum_only {
+        format!(
+            " ({} of {num_iterations} chunks by checksum)",
+            checksum_matches.into_inner()
+        )
+    } else {
+        String::new()
+    };
+    if args.sample.is_some() || subset != array1.subset_all() {
+        Ok(format!(
+            "Success: {} and {} match in {} chunks of {subset}{checksum_matches}",
+            args.first, args.second, num_iterations
+        ))
+    } else {
+        Ok(format!(
+            "Success: {} and {} match{checksum_matches}",
+            args.first, args.second
+        ))
+    }
+}
+
+/// Check that the encoded chunks of `array1` and `array2` can be compared by their stored crc32c checksums.
+fn check_checksum_encoding<TStorage: ?Sized>(
+    array1: &Array<TStorage>,
+    array2: &Array<TStorage>,
+) -> anyhow::Result<()> {
+    let codecs1 = array1.codecs().create_metadatas();
+    let codecs2 = array2.codecs().create_metadatas();
+    for (name, codecs) in [("first", &codecs1), ("second", &codecs2)] {
+        if codecs.last().map(|codec| codec.name()) != Some("crc32c") {
+            anyhow::bail!(
+                "--checksum-only requires the codecs of the {name} array to end with crc32c"
+            );
+        }
+    }
+    if codecs1 != codecs2
+        || array1.chunk_grid().create_metadata() != array2.chunk_grid().create_metadata()
+        || array1.fill_value() != array2.fill_value()
+    {
+        anyhow::bail!(
+            "--checksum-only requires the arrays to have the same chunk grid, fill value, and codecs"
+        );
+    }
+    Ok(())
+}
+
+/// Returns true if the encoded chunks at `chunk_indices` of `array1` and `array2` exist and have the same size and trailing crc32c checksum.
+fn chunk_checksums_match<TStorage: ?Sized>(
+    storage1: &ReadableListableStorage,
+    array1: &Array<TStorage>,
+    storage2: &ReadableListableStorage,
+    array2: &Array<TStorage>,
+    chunk_indices: &[u64],
+) -> anyhow::Result<bool> {
+    let key1 = array1.chunk_key(chunk_indices);
+    let key2 = array2.chunk_key(chunk_indices);
+    let (Some(size1), Some(size2)) = (storage1.size_key(&key1)?, storage2.size_key(&key2)?) else {
+        return Ok(false);
+    };
+    if size1 != size2 {
+        return Ok(false);
+    }
+    let checksum1 = storage1.get_partial_values_key(&key1, &[ByteRange::Suffix(4)])?;
+    let checksum2 = storage2.get_partial_values_key(&key2, &[ByteRange::Suffix(4)])?;
+    Ok(checksum1.is_some() && checksum1 == checksum2)
+}
+
+/// Get the subset to validate from an optional offset and shape.
+fn get_subset(
+    array_shape: &[u64],
+    offset: Option<&[u64]>,
+    shape: Option<&[u64]>,
+) -> anyhow::Result<ArraySubset> {
+    let offset = offset.map_or_else(|| vec![0; array_shape.len()], <[u64]>::to_vec);
+    if offset.len() != array_shape.len() {
+        anyhow::bail!("Offset dimensionality does not match the array dimensionality");
+    }
+    let shape = if let Some(shape) = shape {
+        if shape.len() != array_shape.len() {
+            anyhow::bail!("Shape dimensionality does not match the array dimensionality");
+        }
+        shape.to_vec()
+    } else {
+        std::iter::zip(array_shape, &offset)
+            .map(|(a, o)| a.saturating_sub(*o))
+            .collect()
+    };
+    let subset = ArraySubset::new_with_start_shape(offset, shape)?;
+    if !subset.inbounds(array_shape) {
+        anyhow::bail!("Subset {subset} is out of bounds of the array shape {array_shape:?}");
+    }
+    Ok(subset)
+}
+
+/// The least common multiple of `a` and `b`.
+fn lcm(a: u64, b: u64) -> u64 {
+    let gcd = {
+        let (mut a, mut b) = (a, b);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    a / gcd * b
+}
+
+/// Get the indices of the chunks with `chunk_shape` intersecting `subset`.
+fn chunks_in_subset(chunk_shape: &[u64], subset: &ArraySubset) -> anyhow::Result<ArraySubset> {
+    let start = std::iter::zip(subset.start(), chunk_shape).map(|(s, c)| s / c);
+    let end = std::iter::zip(subset.end_exc(), chunk_shape).map(|(e, c)| e.div_ceil(*c));
+    Ok(ArraySubset::new_with_start_end_exc(
+        start.collect(),
+        end.collect(),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::{
+        array::{
+            codec::{BytesToBytesCodecTraits, Crc32cCodec},
+            ArrayBuilder, DataType, FillValue,
+        },
+        filesystem::FilesystemStore,
+    };
+
+    use super::*;
+
+    /// Create a `4x4` array with `2x2` chunks at `path` with the `bytes_to_bytes_codecs`, and elements `0..16`.
+    fn create_array(
+        path: &std::path::Path,
+        bytes_to_bytes_codecs: Vec<Arc<dyn BytesToBytesCodecTraits>>,
+    ) -> Result<Array<FilesystemStore>, Box<dyn std::error::Error>> {
+        let mut builder = ArrayBuilder::new(
+            vec![4, 4],
+            DataType::UInt8,
+            vec![2, 2].try_into()?,
+            FillValue::from(0u8),
+        );
+        builder.bytes_to_bytes_codecs(bytes_to_bytes_codecs);
+        let array = builder.build(Arc::new(FilesystemStore::new(path)?), "/")?;
+        array.store_metadata()?;
+        array
+            .store_array_subset_elements::<u8>(&array.subset_all(), &(0..16).collect::<Vec<_>>())?;
+        Ok(array)
+    }
+
+    /// Validate the arrays at `first` and `second` with `--checksum-only`.
+    fn validate_checksum_only(
+        first: &std::path::Path,
+        second: &std::path::Path,
+    ) -> anyhow::Result<String> {
+        try_main(vec![
+            "zarrs_validate".into(),
+            "--checksum-only".into(),
+            first.into(),
+            second.into(),
+        ])
+    }
+
+    #[test]
+    fn validate_checksum_only_match() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let (first, second) = (
+            dir.path().join("first.zarr"),
+            dir.path().join("second.zarr"),
+        );
+        create_array(&first, vec![Arc::new(Crc32cCodec::new())])?;
+        create_array(&second, vec![Arc::new(Crc32cCodec::new())])?;
+        let success = validate_checksum_only(&first, &second)?;
+        assert!(success.ends_with("match (4 of 4 chunks by checksum)"));
+        Ok(())
+    }
+
+    #[test]
+    fn validate_checksum_only_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let (first, second) = (
+            dir.path().join("first.zarr"),
+            dir.path().join("second.zarr"),
+        );
+        create_array(&first, vec![Arc::new(Crc32cCodec::new())])?;
+        let array = create_array(&second, vec![Arc::new(Crc32cCodec::new())])?;
+
+        // A chunk with a different (but valid) checksum is decoded and compared
+        array.store_chunk_elements::<u8>(&[1, 0], &[0, 1, 2, 3])?;
+        let storage1: ReadableListableStorage = Arc::new(FilesystemStore::new(&first)?);
+        let storage2: ReadableListableStorage = Arc::new(FilesystemStore::new(&second)?);
+        let array1 = Array::open(storage1.clone(), "/")?;
+        let array2 = Array::open(storage2.clone(), "/")?;
+        check_checksum_encoding(&array1, &array2)?;
+        assert!(chunk_checksums_match(
+            &storage1,
+            &array1,
+            &storage2,
+            &array2,
+            &[0, 0]
+        )?);
+        assert!(!chunk_checksums_match(
+            &storage1,
+            &array1,
+            &storage2,
+            &array2,
+            &[1, 0]
+        )?);
+        assert_eq!(
+            validate_checksum_only(&first, &second)
+                .unwrap_err()
+                .to_string(),
+            "Data differs in region: [2..4, 0..2]"
+        );
+
+        // A chunk only in one array is decoded and compared
+        array
+            .store_array_subset_elements::<u8>(&array.subset_all(), &(0..16).collect::<Vec<_>>())?;
+        array.erase_chunk(&[0, 1])?;
+        assert!(!chunk_checksums_match(
+            &storage1,
+            &array1,
+            &storage2,
+            &array2,
+            &[0, 1]
+        )?);
+        assert_eq!(
+            validate_checksum_only(&first, &second)
+                .unwrap_err()
+                .to_string(),
+            "Data differs in region: [0..2, 2..4]"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn validate_checksum_only_encoding() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let (first, second, third) = (
+            dir.path().join("first.zarr"),
+            dir.path().join("second.zarr"),
+            dir.path().join("third.zarr"),
+        );
+        let array1 = create_array(&first, vec![Arc::new(Crc32cCodec::new())])?;
+        let array2 = create_array(&second, vec![])?;
+        let array3 = create_array(
+            &third,
+            vec![Arc::new(Crc32cCodec::new()), Arc::new(Crc32cCodec::new())],
+        )?;
+        assert_eq!(
+            check_checksum_encoding(&array1, &array2)
+                .unwrap_err()
+                .to_string(),
+            "--checksum-only requires the codecs of the second array to end with crc32c"
+        );
+        assert_eq!(
+            check_checksum_encoding(&array1, &array3)
+                .unwrap_err()
+                .to_string(),
+            "--checksum-only requires the arrays to have the same chunk grid, fill value, and codecs"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn seed_requires_sample() {
+        assert!(Args::try_parse_from(["zarrs_validate", "--seed=1", "a.zarr", "b.zarr"]).is_err());
+        assert!(Args::try_parse_from([
+            "zarrs_validate",
+            "--sample=2",
+            "--seed=1",
+            "a.zarr",
+            "b.zarr"
+        ])
+        .is_ok());
+    }
+}
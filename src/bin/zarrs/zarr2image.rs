@@ -0,0 +1,390 @@
+use std::{ffi::OsString, path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use image::{imageops::FilterType, DynamicImage, ImageBuffer, Luma};
+use ndarray::{Array2, ArrayD, Axis};
+use num_traits::AsPrimitive;
+use zarrs::{
+    array::{Array, DataType, ElementOwned},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+use zarrs_tools::{filesystem_store, DirectIo, ThreadsArgs};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_zarr2image";
+
+/// Export a 2D image (slice, projection, or mosaic) of a Zarr array to PNG, JPEG, or TIFF.
+///
+/// This is intended for quick visual inspection of conversions and for creating thumbnails.
+/// The image format is determined by the output file extension (.png, .jpg/.jpeg, .tif/.tiff).
+#[derive(Parser)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Cli {
+    /// The input directory of the zarr array.
+    input: PathBuf,
+
+    /// The output image path.
+    output: PathBuf,
+
+    /// The array axes of the image rows and columns. Comma delimited.
+    ///
+    /// Defaults to the last two axes.
+    #[arg(long, value_delimiter = ',', num_args = 2)]
+    axes: Option<Vec<usize>>,
+
+    /// The position along the other axes. A comma separated list with an element per array dimension.
+    ///
+    /// Elements of the image (and projection or mosaic) axes are ignored.
+    /// Defaults to the middle of each axis.
+    #[arg(long, value_delimiter = ',')]
+    position: Option<Vec<u64>>,
+
+    /// Project along an axis rather than slicing (e.g. a maximum intensity projection).
+    #[arg(long, value_enum, requires = "projection_axis")]
+    projection: Option<Projection>,
+
+    /// The axis of the projection.
+    #[arg(long, requires = "projection")]
+    projection_axis: Option<usize>,
+
+    /// Tile evenly spaced slices along this axis into a mosaic.
+    #[arg(long, conflicts_with = "projection")]
+    mosaic: Option<usize>,
+
+    /// The number of tiles of a mosaic.
+    ///
+    /// Tiles are arranged in a square-ish grid, row by row.
+    #[arg(long, default_value_t = 16)]
+    mosaic_tiles: usize,
+
+    /// The intensity window, mapped to black and white. Comma delimited.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 2,
+        allow_hyphen_values(true),
+        conflicts_with = "percentile"
+    )]
+    window: Option<Vec<f64>>,
+
+    /// The intensity window as low and high percentiles (in [0, 100]) of the image. Comma delimited.
+    ///
+    /// Non-finite values are excluded.
+    /// Defaults to 0,100 (the image minimum and maximum) if --window is not set.
+    #[arg(long, value_delimiter = ',', num_args = 2)]
+    percentile: Option<Vec<f64>>,
+
+    /// The bit depth of the output image.
+    ///
+    /// A bit depth of 16 is not supported by JPEG.
+    #[arg(long, value_enum, default_value_t = BitDepth::Eight)]
+    bit_depth: BitDepth,
+
+    /// Downscale the image (preserving the aspect ratio) so that its width and height do not exceed this size.
+    #[arg(long)]
+    max_size: Option<u32>,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=read`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "read", default_value_t = DirectIo::Off)]
+    direct_io: DirectIo,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+/// A projection along an axis.
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum Projection {
+    /// The maximum (a maximum intensity projection).
+    Max,
+    /// The minimum.
+    Min,
+    /// The mean.
+    Mean,
+}
+
+/// The bit depth of an image.
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum BitDepth {
+    /// 8-bit.
+    #[value(name = "8")]
+    Eight,
+    /// 16-bit.
+    #[value(name = "16")]
+    Sixteen,
+}
+
+/// Retrieve `subset` of `array` as `f64`.
+fn retrieve_f64(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+) -> anyhow::Result<ArrayD<f64>> {
+    fn retrieve<T: ElementOwned + AsPrimitive<f64>>(
+        array: &Array<FilesystemStore>,
+        subset: &ArraySubset,
+    ) -> anyhow::Result<ArrayD<f64>> {
+        Ok(array
+            .retrieve_array_subset_ndarray::<T>(subset)?
+            .mapv(AsPrimitive::as_))
+    }
+    match array.data_type() {
+        DataType::Bool => Ok(array
+            .retrieve_array_subset_ndarray::<bool>(subset)?
+            .mapv(f64::from)),
+        DataType::Int8 => retrieve::<i8>(array, subset),
+        DataType::Int16 => retrieve::<i16>(array, subset),
+        DataType::Int32 => retrieve::<i32>(array, subset),
+        DataType::Int64 => retrieve::<i64>(array, subset),
+        DataType::UInt8 => retrieve::<u8>(array, subset),
+        DataType::UInt16 => retrieve::<u16>(array, subset),
+        DataType::UInt32 => retrieve::<u32>(array, subset),
+        DataType::UInt64 => retrieve::<u64>(array, subset),
+        DataType::Float16 => retrieve::<half::f16>(array, subset),
+        DataType::BFloat16 => retrieve::<half::bf16>(array, subset),
+        DataType::Float32 => retrieve::<f32>(array, subset),
+        DataType::Float64 => retrieve::<f64>(array, subset),
+        data_type => anyhow::bail!("unsupported data type {data_type}"),
+    }
+}
+
+/// Convert an array with singleton dimensions other than `axes` to a 2D image.
+fn to_image(array: &ArrayD<f64>, axes: [usize; 2]) -> Array2<f64> {
+    let (rows, cols) = (array.shape()[axes[0]], array.shape()[axes[1]]);
+    if axes[0] < axes[1] {
+        Array2::from_shape_vec((rows, cols), array.iter().copied().collect()).unwrap()
+    } else {
+        Array2::from_shape_vec((cols, rows), array.iter().copied().collect())
+            .unwrap()
+            .reversed_axes()
+            .as_standard_layout()
+            .into_owned()
+    }
+}
+
+/// Returns the subset of the image at `position`, optionally spanning `range` along `axis`.
+fn image_subset(
+    shape: &[u64],
+    axes: [usize; 2],
+    position: &[u64],
+    range: Option<(usize, std::ops::Range<u64>)>,
+) -> ArraySubset {
+    let ranges = (0..shape.len()).map(|i| {
+        if axes.contains(&i) {
+            0..shape[i]
+        } else if let Some((_, range)) = range.as_ref().filter(|(axis, _)| *axis == i) {
+            range.clone()
+        } else {
+            position[i]..position[i] + 1
+        }
+    });
+    ArraySubset::new_with_ranges(&ranges.collect::<Vec<_>>())
+}
+
+/// Project along `axis`, reading slabs of the chunk (or shard) thickness to bound memory usage.
+fn project(
+    array: &Array<FilesystemStore>,
+    axes: [usize; 2],
+    position: &[u64],
+    projection: Projection,
+    axis: usize,
+) -> anyhow::Result<Array2<f64>> {
+    let shape = array.shape();
+    let thickness = array.chunk_shape(&vec![0; shape.len()])?[axis].get();
+    let mut image: Option<Array2<f64>> = None;
+    let mut start = 0;
+    while start < shape[axis] {
+        let end = (start + thickness).min(shape[axis]);
+        let subset = image_subset(shape, axes, position, Some((axis, start..end)));
+        let slab = retrieve_f64(array, &subset)?;
+        let slab = match projection {
+            Projection::Max => slab.fold_axis(Axis(axis), f64::NEG_INFINITY, |a, b| a.max(*b)),
+            Projection::Min => slab.fold_axis(Axis(axis), f64::INFINITY, |a, b| a.min(*b)),
+            Projection::Mean => slab.sum_axis(Axis(axis)),
+        };
+        let slab = to_image(&slab.insert_axis(Axis(axis)), axes);
+        image = Some(match (image, projection) {
+            (None, _) => slab,
+            (Some(image), Projection::Max) => ndarray::Zip::from(&image)
+                .and(&slab)
+                .map_collect(|a, b| a.max(*b)),
+            (Some(image), Projection::Min) => ndarray::Zip::from(&image)
+                .and(&slab)
+                .map_collect(|a, b| a.min(*b)),
+            (Some(image), Projection::Mean) => image + slab,
+        });
+        start = end;
+    }
+    let image = image.ok_or_else(|| anyhow::anyhow!("the projection axis is empty"))?;
+    Ok(match projection {
+        Projection::Mean => image / shape[axis] as f64,
+        Projection::Max | Projection::Min => image,
+    })
+}
+
+/// Tile `num_tiles` evenly spaced slices along `axis` into a mosaic.
+fn mosaic(
+    array: &Array<FilesystemStore>,
+    axes: [usize; 2],
+    position: &[u64],
+    axis: usize,
+    num_tiles: usize,
+) -> anyhow::Result<Array2<f64>> {
+    let shape = array.shape();
+    let num_tiles = num_tiles.clamp(1, usize::try_from(shape[axis]).unwrap().max(1));
+    let columns = (num_tiles as f64).sqrt().ceil() as usize;
+    let rows = num_tiles.div_ceil(columns);
+    let (tile_rows, tile_cols) = (
+        usize::try_from(shape[axes[0]]).unwrap(),
+        usize::try_from(shape[axes[1]]).unwrap(),
+    );
+    let mut image = Array2::from_elem((rows * tile_rows, columns * tile_cols), f64::NAN);
+    for tile in 0..num_tiles {
+        // The centre of each of num_tiles equal intervals
+        let index = (2 * tile as u64 + 1) * shape[axis] / (2 * num_tiles as u64);
+        let mut position = position.to_vec();
+        position[axis] = index;
+        let subset = image_subset(shape, axes, &position, None);
+        let slice = to_image(&retrieve_f64(array, &subset)?, axes);
+        let (row, column) = (tile / columns, tile % columns);
+        image
+            .slice_mut(ndarray::s![
+                row * tile_rows..(row + 1) * tile_rows,
+                column * tile_cols..(column + 1) * tile_cols
+            ])
+            .assign(&slice);
+    }
+    Ok(image)
+}
+
+/// Returns the `percentile` (in [0, 100]) of the sorted `values`.
+fn percentile(values: &[f64], percentile: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let index = (percentile.clamp(0.0, 100.0) / 100.0 * (values.len() - 1) as f64).round();
+    values[index as usize]
+}
+
+pub fn main(args: Vec<OsString>) -> anyhow::Result<()> {
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, args);
+    cli.threads.clone().install(|| run(cli))
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    let store = Arc::new(filesystem_store(&cli.input, cli.direct_io.read())?);
+    let array = Array::open(store, "/")?;
+    let shape = array.shape().to_vec();
+    let dimensionality = shape.len();
+    if dimensionality < 2 {
+        anyhow::bail!("the array must have at least 2 dimensions");
+    }
+
+    // Validate axes
+    let axes = cli
+        .axes
+        .as_deref()
+        .map_or([dimensionality - 2, dimensionality - 1], |axes| {
+            [axes[0], axes[1]]
+        });
+    if axes[0] == axes[1] || axes.iter().any(|axis| *axis >= dimensionality) {
+        anyhow::bail!(
+            "the image axes {axes:?} are invalid for an array with {dimensionality} dimensions"
+        );
+    }
+    for axis in [cli.projection_axis, cli.mosaic].into_iter().flatten() {
+        if axes.contains(&axis) || axis >= dimensionality {
+            anyhow::bail!(
+                "the projection or mosaic axis {axis} must be a non-image axis of the array"
+            );
+        }
+    }
+    let position = cli
+        .position
+        .clone()
+        .unwrap_or_else(|| shape.iter().map(|size| size / 2).collect());
+    if position.len() != dimensionality
+        || std::iter::zip(&position, &shape)
+            .enumerate()
+            .any(|(i, (p, s))| !axes.contains(&i) && p >= s)
+    {
+        anyhow::bail!("the position {position:?} is outside of the array shape {shape:?}");
+    }
+
+    // Extract the image
+    let image = match (cli.projection, cli.projection_axis, cli.mosaic) {
+        (Some(projection), Some(axis), _) => project(&array, axes, &position, projection, axis)?,
+        (_, _, Some(axis)) => mosaic(&array, axes, &position, axis, cli.mosaic_tiles)?,
+        _ => to_image(
+            &retrieve_f64(&array, &image_subset(&shape, axes, &position, None))?,
+            axes,
+        ),
+    };
+
+    // Window
+    let (low, high) = if let Some(window) = &cli.window {
+        (window[0], window[1])
+    } else {
+        let percentiles = cli.percentile.as_deref().unwrap_or(&[0.0, 100.0]);
+        let mut values: Vec<f64> = image.iter().copied().filter(|v| v.is_finite()).collect();
+        values.sort_by(f64::total_cmp);
+        (
+            percentile(&values, percentiles[0]),
+            percentile(&values, percentiles[1]),
+        )
+    };
+    let scale = if high > low { 1.0 / (high - low) } else { 0.0 };
+    let normalise = |value: f64| {
+        if value.is_nan() {
+            0.0
+        } else {
+            ((value - low) * scale).clamp(0.0, 1.0)
+        }
+    };
+
+    // Encode
+    let (height, width) = image.dim();
+    let (width, height) = (u32::try_from(width)?, u32::try_from(height)?);
+    let mut output = match cli.bit_depth {
+        BitDepth::Eight => DynamicImage::ImageLuma8(
+            ImageBuffer::<Luma<u8>, _>::from_raw(
+                width,
+                height,
+                image
+                    .iter()
+                    .map(|v| (normalise(*v) * f64::from(u8::MAX)).round() as u8)
+                    .collect(),
+            )
+            .unwrap(),
+        ),
+        BitDepth::Sixteen => DynamicImage::ImageLuma16(
+            ImageBuffer::<Luma<u16>, _>::from_raw(
+                width,
+                height,
+                image
+                    .iter()
+                    .map(|v| (normalise(*v) * f64::from(u16::MAX)).round() as u16)
+                    .collect(),
+            )
+            .unwrap(),
+        ),
+    };
+    if let Some(max_size) = cli.max_size {
+        if width > max_size || height > max_size {
+            output = output.resize(max_size, max_size, FilterType::Triangle);
+        }
+    }
+    output.save(&cli.output)?;
+
+    println!(
+        "Output {:?} ({}x{}, window [{low}, {high}])",
+        cli.output,
+        output.width(),
+        output.height()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,355 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use clap::Parser;
+use indicatif::{ProgressBar, ProgressStyle};
+use num_traits::AsPrimitive;
+use parquet::{
+    basic::{Compression, Repetition, Type as PhysicalType, ZstdLevel},
+    data_type::{DoubleType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::types::Type,
+};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon_iter_concurrent_limit::iter_concurrent_limit;
+use zarrs::{
+    array::{Array, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+use zarrs_tools::{
+    concurrent_target, filesystem_store,
+    progress::{duration_smoothed, Progress, ProgressCallback, ProgressStats},
+    DirectIo, ThreadsArgs,
+};
+
+/// The tool name, used for configuration tables and provenance.
+pub const NAME: &str = "zarrs_zarr2points";
+
+/// Export the coordinates and values of the nonzero (or above threshold) elements of a Zarr array to CSV or Parquet.
+///
+/// This is intended for converting sparse segmentations or detections into tabular form for analysis.
+/// The output format is determined by the output file extension (.csv or .parquet).
+/// There is a column per array dimension (named by the dimension names, or axis0, axis1, ...) and a value column.
+///
+/// Chunks are processed concurrently and their points are streamed to the output, so rows are grouped by chunk but chunks are not in order.
+#[derive(Parser)]
+#[command(author, version=zarrs_tools::ZARRS_TOOLS_VERSION_WITH_ZARRS)]
+pub struct Cli {
+    /// The input directory of the zarr array.
+    input: PathBuf,
+
+    /// The output path (.csv or .parquet).
+    output: PathBuf,
+
+    /// Export elements greater than this threshold rather than nonzero elements.
+    #[arg(long, allow_hyphen_values(true))]
+    threshold: Option<f64>,
+
+    /// The maximum number of rows of a Parquet row group.
+    #[arg(long, default_value_t = 1024 * 1024)]
+    row_group_size: usize,
+
+    /// Number of concurrent chunks.
+    #[arg(long)]
+    concurrent_chunks: Option<usize>,
+
+    /// Use direct I/O (bypassing the page cache) for filesystem stores.
+    ///
+    /// `--direct-io` without a value is equivalent to `--direct-io=read`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "read", default_value_t = DirectIo::Off)]
+    direct_io: DirectIo,
+
+    /// Thread pool options.
+    #[command(flatten)]
+    threads: ThreadsArgs,
+}
+
+/// Points in columnar form.
+struct Points {
+    coordinates: Vec<Vec<i64>>,
+    values: Vec<f64>,
+}
+
+impl Points {
+    fn new(dimensionality: usize) -> Self {
+        Self {
+            coordinates: vec![vec![]; dimensionality],
+            values: vec![],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn append(&mut self, other: &mut Points) {
+        for (coordinates, other) in std::iter::zip(&mut self.coordinates, &mut other.coordinates) {
+            coordinates.append(other);
+        }
+        self.values.append(&mut other.values);
+    }
+}
+
+/// A streaming writer of points.
+enum PointWriter {
+    Csv(BufWriter<File>),
+    Parquet {
+        writer: Box<SerializedFileWriter<File>>,
+        buffer: Points,
+        row_group_size: usize,
+    },
+}
+
+impl PointWriter {
+    fn new(
+        path: &std::path::Path,
+        columns: &[String],
+        row_group_size: usize,
+    ) -> anyhow::Result<Self> {
+        let file = File::create(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => {
+                let mut writer = BufWriter::new(file);
+                writeln!(writer, "{}", columns.join(","))?;
+                Ok(Self::Csv(writer))
+            }
+            Some("parquet") => {
+                let fields = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, column)| {
+                        let physical_type = if i + 1 < columns.len() {
+                            PhysicalType::INT64
+                        } else {
+                            PhysicalType::DOUBLE
+                        };
+                        Type::primitive_type_builder(column, physical_type)
+                            .with_repetition(Repetition::REQUIRED)
+                            .build()
+                            .map(Arc::new)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let schema = Type::group_type_builder("schema")
+                    .with_fields(fields)
+                    .build()?;
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::ZSTD(ZstdLevel::default()))
+                    .build();
+                Ok(Self::Parquet {
+                    writer: Box::new(SerializedFileWriter::new(
+                        file,
+                        Arc::new(schema),
+                        Arc::new(properties),
+                    )?),
+                    buffer: Points::new(columns.len() - 1),
+                    row_group_size: row_group_size.max(1),
+                })
+            }
+            _ => anyhow::bail!("the output {path:?} must have a .csv or .parquet extension"),
+        }
+    }
+
+    fn write(&mut self, points: &mut Points) -> anyhow::Result<()> {
+        match self {
+            Self::Csv(writer) => {
+                for (i, value) in points.values.iter().enumerate() {
+                    for coordinates in &points.coordinates {
+                        write!(writer, "{},", coordinates[i])?;
+                    }
+                    writeln!(writer, "{value}")?;
+                }
+            }
+            Self::Parquet {
+                buffer,
+                row_group_size,
+                ..
+            } => {
+                buffer.append(points);
+                if buffer.len() >= *row_group_size {
+                    self.flush_row_group()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the buffered points of a Parquet writer as a row group.
+    fn flush_row_group(&mut self) -> anyhow::Result<()> {
+        let Self::Parquet { writer, buffer, .. } = self else {
+            return Ok(());
+        };
+        if buffer.len() == 0 {
+            return Ok(());
+        }
+        let mut row_group = writer.next_row_group()?;
+        for coordinates in &buffer.coordinates {
+            let mut column = row_group.next_column()?.unwrap();
+            column
+                .typed::<Int64Type>()
+                .write_batch(coordinates, None, None)?;
+            column.close()?;
+        }
+        let mut column = row_group.next_column()?.unwrap();
+        column
+            .typed::<DoubleType>()
+            .write_batch(&buffer.values, None, None)?;
+        column.close()?;
+        row_group.close()?;
+        *buffer = Points::new(buffer.coordinates.len());
+        Ok(())
+    }
+
+    fn finish(mut self) -> anyhow::Result<()> {
+        self.flush_row_group()?;
+        match self {
+            Self::Csv(mut writer) => writer.flush()?,
+            Self::Parquet { writer, .. } => {
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Retrieve `subset` of `array` as `f64`.
+fn retrieve_f64(array: &Array<FilesystemStore>, subset: &ArraySubset) -> anyhow::Result<Vec<f64>> {
+    macro_rules! retrieve {
+        ( $( ( $data_type:ident, $t:ty ) ),* ) => {
+            match array.data_type() {
+                DataType::Bool => Ok(array
+                    .retrieve_array_subset_elements::<bool>(subset)?
+                    .into_iter()
+                    .map(f64::from)
+                    .collect()),
+                $(DataType::$data_type => Ok(array
+                    .retrieve_array_subset_elements::<$t>(subset)?
+                    .into_iter()
+                    .map(AsPrimitive::<f64>::as_)
+                    .collect()),)*
+                data_type => anyhow::bail!("unsupported data type {data_type}"),
+            }
+        };
+    }
+    retrieve!(
+        (Int8, i8),
+        (Int16, i16),
+        (Int32, i32),
+        (Int64, i64),
+        (UInt8, u8),
+        (UInt16, u16),
+        (UInt32, u32),
+        (UInt64, u64),
+        (BFloat16, half::bf16),
+        (Float16, half::f16),
+        (Float32, f32),
+        (Float64, f64)
+    )
+}
+
+/// Returns the points of the selected elements of a chunk.
+fn chunk_points(
+    array: &Array<FilesystemStore>,
+    chunk_indices: &[u64],
+    threshold: Option<f64>,
+    progress: &Progress,
+) -> anyhow::Result<Points> {
+    let subset = array.chunk_subset_bounded(chunk_indices)?;
+    let values = progress.read(|| retrieve_f64(array, &subset))?;
+    Ok(progress.process(|| {
+        let mut points = Points::new(array.dimensionality());
+        let selected = |value: f64| match threshold {
+            Some(threshold) => value > threshold,
+            None => value != 0.0 && !value.is_nan(),
+        };
+        for (indices, value) in std::iter::zip(&subset.indices(), values) {
+            if selected(value) {
+                for (coordinates, index) in std::iter::zip(&mut points.coordinates, indices) {
+                    coordinates.push(i64::try_from(index).unwrap());
+                }
+                points.values.push(value);
+            }
+        }
+        points
+    }))
+}
+
+fn progress_callback(stats: ProgressStats, bar: &ProgressBar) {
+    bar.set_length(stats.num_steps as u64);
+    bar.set_position(stats.step as u64);
+    bar.set_message(format!(
+        "rw:{:.2}/{:.2} p:{:.2}",
+        stats.read.as_secs_f32(),
+        stats.write.as_secs_f32(),
+        stats.process.as_secs_f32(),
+    ));
+}
+
+pub fn main(args: Vec<OsString>) -> anyhow::Result<()> {
+    let cli = zarrs_tools::config::parse_from::<Cli>(NAME, args);
+    cli.threads.clone().install(|| run(cli))
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    let store = Arc::new(filesystem_store(&cli.input, cli.direct_io.read())?);
+    let array = Array::open(store, "/")?;
+
+    let columns = (0..array.dimensionality())
+        .map(|i| {
+            array
+                .dimension_names()
+                .as_ref()
+                .and_then(|dimension_names| dimension_names[i].as_str())
+                .map_or_else(|| format!("axis{i}"), str::to_string)
+        })
+        .chain(std::iter::once("value".to_string()))
+        .collect::<Vec<_>>();
+    let writer = Mutex::new(PointWriter::new(&cli.output, &columns, cli.row_group_size)?);
+
+    let bar = ProgressBar::new(0);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}/{duration_smoothed}] {bar:40.black/bold} {pos}/{len} ({percent}%) {msg}",
+        )
+        .unwrap_or(ProgressStyle::default_bar())
+        .with_key("duration_smoothed", duration_smoothed),
+    );
+    let progress_callback = |stats: ProgressStats| progress_callback(stats, &bar);
+    let progress_callback = ProgressCallback::new(&progress_callback);
+
+    let chunks = ArraySubset::new_with_shape(array.chunk_grid_shape().unwrap());
+    let progress = Progress::new(chunks.num_elements_usize(), &progress_callback);
+    let num_points = AtomicU64::new(0);
+    let indices = chunks.indices();
+    iter_concurrent_limit!(
+        cli.concurrent_chunks
+            .unwrap_or_else(concurrent_target)
+            .max(1),
+        indices,
+        try_for_each,
+        |chunk_indices: Vec<u64>| {
+            let mut points = chunk_points(&array, &chunk_indices, cli.threshold, &progress)?;
+            num_points.fetch_add(points.len() as u64, Ordering::Relaxed);
+            progress.write(|| writer.lock().unwrap().write(&mut points))?;
+            progress.next()?;
+            Ok::<_, anyhow::Error>(())
+        }
+    )?;
+    writer.into_inner().unwrap().finish()?;
+    bar.finish_and_clear();
+
+    println!(
+        "Exported {} points to {:?}",
+        num_points.into_inner(),
+        cli.output
+    );
+    Ok(())
+}
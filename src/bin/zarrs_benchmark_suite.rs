@@ -0,0 +1,6 @@
+#[path = "zarrs/benchmark_suite.rs"]
+mod benchmark_suite;
+
+fn main() -> anyhow::Result<()> {
+    benchmark_suite::main(std::env::args_os().collect())
+}
@@ -0,0 +1,6 @@
+#[path = "zarrs/gc.rs"]
+mod gc;
+
+fn main() -> anyhow::Result<()> {
+    gc::main(std::env::args_os().collect())
+}
@@ -0,0 +1,6 @@
+#[path = "zarrs/generate.rs"]
+mod generate;
+
+fn main() -> anyhow::Result<()> {
+    generate::main(std::env::args_os().collect())
+}
@@ -0,0 +1,6 @@
+#[path = "zarrs/grib2zarr.rs"]
+mod grib2zarr;
+
+fn main() -> anyhow::Result<()> {
+    grib2zarr::main(std::env::args_os().collect())
+}
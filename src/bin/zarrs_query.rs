@@ -0,0 +1,6 @@
+#[path = "zarrs/query.rs"]
+mod query;
+
+fn main() -> anyhow::Result<()> {
+    query::main(std::env::args_os().collect())
+}
@@ -0,0 +1,6 @@
+#[path = "zarrs/serve.rs"]
+mod serve;
+
+fn main() -> anyhow::Result<()> {
+    serve::main(std::env::args_os().collect())
+}
@@ -0,0 +1,6 @@
+#[path = "zarrs/zarr2image.rs"]
+mod zarr2image;
+
+fn main() -> anyhow::Result<()> {
+    zarr2image::main(std::env::args_os().collect())
+}
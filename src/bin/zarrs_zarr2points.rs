@@ -0,0 +1,6 @@
+#[path = "zarrs/zarr2points.rs"]
+mod zarr2points;
+
+fn main() -> anyhow::Result<()> {
+    zarr2points::main(std::env::args_os().collect())
+}
@@ -0,0 +1,613 @@
+//! Detached headers describing raw binary data.
+//!
+//! Supported formats:
+//!  - [NRRD](https://teem.sourceforge.net/nrrd/format.html) (`.nrrd`, `.nhdr`),
+//!  - [MetaImage](https://itk.org/Wiki/ITK/MetaIO/Documentation) (`.mhd`, `.mha`), and
+//!  - JSON (`.json`), which deserialises directly into a [`BinaryHeader`].
+//!
+//! The array shape and per-axis properties of a [`BinaryHeader`] are in C order (slowest varying axis first), matching Zarr.
+//! NRRD and MetaImage list axes fastest varying first, so they are reversed when parsed.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use zarrs::{array::Endianness, metadata::v3::array::data_type::DataTypeMetadataV3};
+
+/// A binary header error.
+#[derive(Debug, thiserror::Error)]
+pub enum BinaryHeaderError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    JSONError(#[from] serde_json::Error),
+    #[error("invalid header field {0}: {1}")]
+    InvalidField(String, String),
+    #[error("missing header field {0}")]
+    MissingField(String),
+    #[error("unsupported header: {0}")]
+    Unsupported(String),
+}
+
+/// A description of raw binary data.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BinaryHeader {
+    /// The data type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<DataTypeMetadataV3>,
+    /// The array shape.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape: Option<Vec<u64>>,
+    /// The endianness of the data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endianness: Option<Endianness>,
+    /// The dimension names.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimension_names: Option<Vec<String>>,
+    /// The physical spacing between elements along each axis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spacing: Option<Vec<f64>>,
+    /// The physical units of each axis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units: Option<Vec<String>>,
+    /// The physical position of the first element.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Vec<f64>>,
+    /// The path to the data file.
+    ///
+    /// A relative path is relative to the directory of the header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_file: Option<PathBuf>,
+    /// The number of bytes to skip at the start of the data.
+    #[serde(default)]
+    pub byte_skip: u64,
+}
+
+fn invalid(field: &str, value: &str) -> BinaryHeaderError {
+    BinaryHeaderError::InvalidField(field.to_string(), value.to_string())
+}
+
+fn parse_list<T: std::str::FromStr>(field: &str, value: &str) -> Result<Vec<T>, BinaryHeaderError> {
+    value
+        .split_whitespace()
+        .map(|v| v.parse().map_err(|_| invalid(field, value)))
+        .collect()
+}
+
+/// Parse a list of double quoted strings, e.g. `"x" "y" "z"`.
+fn parse_quoted_list(field: &str, value: &str) -> Result<Vec<String>, BinaryHeaderError> {
+    let mut strings = Vec::new();
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let (string, remainder) = rest
+            .strip_prefix('"')
+            .and_then(|rest| rest.split_once('"'))
+            .ok_or_else(|| invalid(field, value))?;
+        strings.push(string.to_string());
+        rest = remainder.trim_start();
+    }
+    Ok(strings)
+}
+
+fn data_type(name: &str) -> DataTypeMetadataV3 {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).unwrap()
+}
+
+/// Resolve a data file path relative to the directory of a header.
+fn resolve_data_file(header_path: &Path, data_file: &str) -> PathBuf {
+    header_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(data_file)
+}
+
+/// Read header lines until the first empty line or a line for which `is_last` returns true.
+///
+/// Returns the lines and the number of bytes read.
+fn read_header_lines(
+    path: &Path,
+    is_last: impl Fn(&str) -> bool,
+) -> Result<(Vec<String>, u64), BinaryHeaderError> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let mut lines = Vec::new();
+    let mut bytes_read = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let len = reader.read_line(&mut line)?;
+        bytes_read += len as u64;
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        if len == 0 || trimmed.is_empty() {
+            break;
+        }
+        lines.push(trimmed.to_string());
+        if is_last(trimmed) {
+            break;
+        }
+    }
+    Ok((lines, bytes_read))
+}
+
+impl BinaryHeader {
+    /// Read a header from `path`, with the format determined by its extension.
+    ///
+    /// # Errors
+    /// Returns a [`BinaryHeaderError`] if the header cannot be read, is invalid, or is unsupported.
+    pub fn from_path(path: &Path) -> Result<Self, BinaryHeaderError> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "nrrd" | "nhdr" => Self::from_nrrd(path),
+            "mhd" | "mha" => Self::from_mhd(path),
+            "json" => Self::from_json(path),
+            _ => Err(BinaryHeaderError::Unsupported(format!(
+                "unrecognised header extension {extension:?}, expected nrrd, nhdr, mhd, mha, or json"
+            ))),
+        }
+    }
+
+    /// Read a JSON header from `path`.
+    ///
+    /// # Errors
+    /// Returns a [`BinaryHeaderError`] if the header cannot be read or is invalid.
+    pub fn from_json(path: &Path) -> Result<Self, BinaryHeaderError> {
+        let mut header: Self = serde_json::from_slice(&std::fs::read(path)?)?;
+        header.data_file = header
+            .data_file
+            .map(|data_file| resolve_data_file(path, &data_file.to_string_lossy()));
+        Ok(header)
+    }
+
+    /// Read a NRRD header from `path`.
+    ///
+    /// If the header does not reference a data file, the data is assumed to follow the header.
+    /// Only the `raw` encoding is supported.
+    ///
+    /// # Errors
+    /// Returns a [`BinaryHeaderError`] if the header cannot be read, is invalid, or is unsupported.
+    pub fn from_nrrd(path: &Path) -> Result<Self, BinaryHeaderError> {
+        let (lines, header_bytes) = read_header_lines(path, |_| false)?;
+        let mut lines = lines.into_iter();
+        if !lines.next().is_some_and(|magic| magic.starts_with("NRRD")) {
+            return Err(BinaryHeaderError::Unsupported(
+                "missing NRRD magic".to_string(),
+            ));
+        }
+        let fields: HashMap<String, String> = lines
+            .filter(|line| !line.starts_with('#') && !line.contains(":="))
+            .filter_map(|line| {
+                line.split_once(": ")
+                    .map(|(field, value)| (field.to_lowercase(), value.trim().to_string()))
+            })
+            .collect();
+        let field = |name: &str| fields.get(name).map(String::as_str);
+        let required = |name: &str| {
+            field(name).ok_or_else(|| BinaryHeaderError::MissingField(name.to_string()))
+        };
+
+        let data_type_field = required("type")?;
+        let data_type = match data_type_field {
+            "signed char" | "int8" | "int8_t" => data_type("int8"),
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => data_type("uint8"),
+            "short" | "short int" | "signed short" | "signed short int" | "int16" | "int16_t" => {
+                data_type("int16")
+            }
+            "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => {
+                data_type("uint16")
+            }
+            "int" | "signed int" | "int32" | "int32_t" => data_type("int32"),
+            "uint" | "unsigned int" | "uint32" | "uint32_t" => data_type("uint32"),
+            "longlong"
+            | "long long"
+            | "long long int"
+            | "signed long long"
+            | "signed long long int"
+            | "int64"
+            | "int64_t" => data_type("int64"),
+            "ulonglong"
+            | "unsigned long long"
+            | "unsigned long long int"
+            | "uint64"
+            | "uint64_t" => data_type("uint64"),
+            "float" => data_type("float32"),
+            "double" => data_type("float64"),
+            _ => return Err(invalid("type", data_type_field)),
+        };
+
+        let encoding = required("encoding")?;
+        if encoding != "raw" {
+            return Err(BinaryHeaderError::Unsupported(format!(
+                "NRRD encoding {encoding:?}, only raw is supported"
+            )));
+        }
+        if field("line skip").is_some_and(|line_skip| line_skip != "0") {
+            return Err(BinaryHeaderError::Unsupported("NRRD line skip".to_string()));
+        }
+
+        let dimension: usize = required("dimension")?
+            .parse()
+            .map_err(|_| invalid("dimension", &fields["dimension"]))?;
+        let mut shape: Vec<u64> = parse_list("sizes", required("sizes")?)?;
+        if shape.len() != dimension {
+            return Err(invalid("sizes", &fields["sizes"]));
+        }
+        shape.reverse();
+
+        let endianness = match field("endian") {
+            Some("little") => Some(Endianness::Little),
+            Some("big") => Some(Endianness::Big),
+            Some(endian) => return Err(invalid("endian", endian)),
+            None => None,
+        };
+
+        // Per-axis fields
+        let axis_strings = |name: &str| -> Result<Option<Vec<String>>, BinaryHeaderError> {
+            field(name)
+                .map(|value| {
+                    let mut strings = parse_quoted_list(name, value)?;
+                    if strings.len() != dimension {
+                        return Err(invalid(name, value));
+                    }
+                    strings.reverse();
+                    Ok(strings)
+                })
+                .transpose()
+        };
+        let dimension_names = axis_strings("labels")?;
+        let units = axis_strings("space units")?.or(axis_strings("units")?);
+
+        let spacing = if let Some(spacings) = field("spacings") {
+            let mut spacing: Vec<f64> = spacings
+                .split_whitespace()
+                .map(|spacing| {
+                    if spacing.eq_ignore_ascii_case("nan") {
+                        Ok(f64::NAN)
+                    } else {
+                        spacing.parse().map_err(|_| invalid("spacings", spacings))
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+            if spacing.len() != dimension {
+                return Err(invalid("spacings", spacings));
+            }
+            spacing.reverse();
+            Some(spacing)
+        } else if let Some(directions) = field("space directions") {
+            // The spacing is the norm of each direction vector, non-spatial axes are "none"
+            let mut spacing = directions
+                .split_whitespace()
+                .map(|direction| {
+                    if direction == "none" {
+                        Ok(f64::NAN)
+                    } else {
+                        let vector: Vec<f64> = parse_list(
+                            "space directions",
+                            &direction.trim_matches(['(', ')']).replace(',', " "),
+                        )?;
+                        Ok(vector.iter().map(|v| v * v).sum::<f64>().sqrt())
+                    }
+                })
+                .collect::<Result<Vec<_>, BinaryHeaderError>>()?;
+            if spacing.len() != dimension {
+                return Err(invalid("space directions", directions));
+            }
+            spacing.reverse();
+            Some(spacing)
+        } else {
+            None
+        };
+
+        let origin = field("space origin")
+            .map(|origin| {
+                let mut origin: Vec<f64> = parse_list(
+                    "space origin",
+                    &origin.trim_matches(['(', ')']).replace(',', " "),
+                )?;
+                origin.reverse();
+                Ok::<_, BinaryHeaderError>(origin)
+            })
+            .transpose()?;
+
+        let byte_skip = match field("byte skip") {
+            Some("-1") => {
+                return Err(BinaryHeaderError::Unsupported(
+                    "NRRD byte skip -1".to_string(),
+                ))
+            }
+            Some(byte_skip) => byte_skip
+                .parse()
+                .map_err(|_| invalid("byte skip", byte_skip))?,
+            None => 0,
+        };
+        let (data_file, byte_skip) = match field("data file").or(field("datafile")) {
+            Some(data_file) if data_file.starts_with("LIST") || data_file.contains(' ') => {
+                return Err(BinaryHeaderError::Unsupported(
+                    "NRRD multiple data files".to_string(),
+                ))
+            }
+            Some(data_file) => (resolve_data_file(path, data_file), byte_skip),
+            None => (path.to_path_buf(), header_bytes + byte_skip),
+        };
+
+        Ok(Self {
+            data_type: Some(data_type),
+            shape: Some(shape),
+            endianness,
+            dimension_names,
+            spacing,
+            units,
+            origin,
+            data_file: Some(data_file),
+            byte_skip,
+        })
+    }
+
+    /// Read a MetaImage header from `path`.
+    ///
+    /// Multi-channel images have a trailing channel axis.
+    /// Compressed data is not supported.
+    ///
+    /// # Errors
+    /// Returns a [`BinaryHeaderError`] if the header cannot be read, is invalid, or is unsupported.
+    pub fn from_mhd(path: &Path) -> Result<Self, BinaryHeaderError> {
+        let (lines, header_bytes) = read_header_lines(path, |line| {
+            line.trim_start().starts_with("ElementDataFile")
+        })?;
+        let fields: HashMap<String, String> = lines
+            .iter()
+            .filter_map(|line| {
+                line.split_once('=')
+                    .map(|(field, value)| (field.trim().to_string(), value.trim().to_string()))
+            })
+            .collect();
+        let field = |name: &str| fields.get(name).map(String::as_str);
+        let required = |name: &str| {
+            field(name).ok_or_else(|| BinaryHeaderError::MissingField(name.to_string()))
+        };
+        let boolean = |name: &str| -> Result<Option<bool>, BinaryHeaderError> {
+            field(name)
+                .map(|value| match value.to_lowercase().as_str() {
+                    "true" => Ok(true),
+                    "false" => Ok(false),
+                    _ => Err(invalid(name, value)),
+                })
+                .transpose()
+        };
+
+        if boolean("CompressedData")?.unwrap_or(false) {
+            return Err(BinaryHeaderError::Unsupported(
+                "MetaImage compressed data".to_string(),
+            ));
+        }
+
+        let element_type = required("ElementType")?;
+        let data_type = match element_type {
+            "MET_CHAR" => data_type("int8"),
+            "MET_UCHAR" => data_type("uint8"),
+            "MET_SHORT" => data_type("int16"),
+            "MET_USHORT" => data_type("uint16"),
+            "MET_INT" | "MET_LONG" => data_type("int32"),
+            "MET_UINT" | "MET_ULONG" => data_type("uint32"),
+            "MET_LONG_LONG" => data_type("int64"),
+            "MET_ULONG_LONG" => data_type("uint64"),
+            "MET_FLOAT" => data_type("float32"),
+            "MET_DOUBLE" => data_type("float64"),
+            _ => return Err(invalid("ElementType", element_type)),
+        };
+
+        let dimension: usize = required("NDims")?
+            .parse()
+            .map_err(|_| invalid("NDims", &fields["NDims"]))?;
+        let axis_list = |name: &str| -> Result<Option<Vec<f64>>, BinaryHeaderError> {
+            field(name)
+                .map(|value| {
+                    let mut list: Vec<f64> = parse_list(name, value)?;
+                    if list.len() != dimension {
+                        return Err(invalid(name, value));
+                    }
+                    list.reverse();
+                    Ok(list)
+                })
+                .transpose()
+        };
+
+        let mut shape: Vec<u64> = parse_list("DimSize", required("DimSize")?)?;
+        if shape.len() != dimension {
+            return Err(invalid("DimSize", &fields["DimSize"]));
+        }
+        shape.reverse();
+        let mut spacing = axis_list("ElementSpacing")?.or(axis_list("ElementSize")?);
+        let mut origin = axis_list("Offset")?
+            .or(axis_list("Origin")?)
+            .or(axis_list("Position")?);
+        let channels: u64 = field("ElementNumberOfChannels")
+            .map(|channels| {
+                channels
+                    .parse()
+                    .map_err(|_| invalid("ElementNumberOfChannels", channels))
+            })
+            .transpose()?
+            .unwrap_or(1);
+        if channels > 1 {
+            shape.push(channels);
+            if let Some(spacing) = &mut spacing {
+                spacing.push(f64::NAN);
+            }
+            if let Some(origin) = &mut origin {
+                origin.push(0.0);
+            }
+        }
+
+        let endianness = boolean("ElementByteOrderMSB")?
+            .or(boolean("BinaryDataByteOrderMSB")?)
+            .or(boolean("ByteOrderMSB")?)
+            .map(|msb| {
+                if msb {
+                    Endianness::Big
+                } else {
+                    Endianness::Little
+                }
+            });
+
+        let data_file = required("ElementDataFile")?;
+        let header_size: Option<u64> = match field("HeaderSize") {
+            Some("-1") => {
+                return Err(BinaryHeaderError::Unsupported(
+                    "MetaImage header size -1".to_string(),
+                ))
+            }
+            Some(header_size) => Some(
+                header_size
+                    .parse()
+                    .map_err(|_| invalid("HeaderSize", header_size))?,
+            ),
+            None => None,
+        };
+        let (data_file, byte_skip) = match data_file {
+            "LOCAL" => (path.to_path_buf(), header_bytes + header_size.unwrap_or(0)),
+            _ if data_file.starts_with("LIST") || data_file.contains('%') => {
+                return Err(BinaryHeaderError::Unsupported(
+                    "MetaImage multiple data files".to_string(),
+                ))
+            }
+            _ => (resolve_data_file(path, data_file), header_size.unwrap_or(0)),
+        };
+
+        Ok(Self {
+            data_type: Some(data_type),
+            shape: Some(shape),
+            endianness,
+            dimension_names: None,
+            spacing,
+            units: None,
+            origin,
+            data_file: Some(data_file),
+            byte_skip,
+        })
+    }
+
+    /// Return the physical properties of the header (`spacing`, `units`, and `origin`) as array attributes.
+    #[must_use]
+    pub fn attributes(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut attributes = serde_json::Map::new();
+        if let Some(spacing) = &self.spacing {
+            // NaN (e.g. a non-spatial axis) is not representable in JSON and becomes null
+            attributes.insert("spacing".to_string(), serde_json::json!(spacing));
+        }
+        if let Some(units) = &self.units {
+            attributes.insert("units".to_string(), serde_json::json!(units));
+        }
+        if let Some(origin) = &self.origin {
+            attributes.insert("origin".to_string(), serde_json::json!(origin));
+        }
+        attributes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn binary_header_nrrd() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let header_path = path.path().join("image.nhdr");
+        std::fs::write(
+            &header_path,
+            r#"NRRD0004
+# Complete NRRD file format specification at:
+# http://teem.sourceforge.net/nrrd/format.html
+type: unsigned short
+dimension: 3
+space: left-posterior-superior
+sizes: 5 4 3
+space directions: (0.5,0,0) (0,0.5,0) (0,0,2)
+labels: "x" "y" "z"
+endian: big
+encoding: raw
+space origin: (1,2,3)
+data file: image.raw
+"#,
+        )?;
+        let header = BinaryHeader::from_path(&header_path)?;
+        assert_eq!(header.data_type, Some(data_type("uint16")));
+        assert_eq!(header.shape, Some(vec![3, 4, 5]));
+        assert_eq!(header.endianness, Some(Endianness::Big));
+        assert_eq!(
+            header.dimension_names,
+            Some(vec!["z".to_string(), "y".to_string(), "x".to_string()])
+        );
+        assert_eq!(header.spacing, Some(vec![2.0, 0.5, 0.5]));
+        assert_eq!(header.origin, Some(vec![3.0, 2.0, 1.0]));
+        assert_eq!(header.data_file, Some(path.path().join("image.raw")));
+        assert_eq!(header.byte_skip, 0);
+
+        // Attached data follows the header
+        let header_path = path.path().join("image.nrrd");
+        let header_str = "NRRD0004\ntype: uint8\ndimension: 1\nsizes: 4\nencoding: raw\n\n";
+        std::fs::write(
+            &header_path,
+            [header_str.as_bytes(), &[0, 1, 2, 3]].concat(),
+        )?;
+        let header = BinaryHeader::from_path(&header_path)?;
+        assert_eq!(header.data_file, Some(header_path));
+        assert_eq!(header.byte_skip, header_str.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn binary_header_mhd() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let header_path = path.path().join("image.mhd");
+        std::fs::write(
+            &header_path,
+            "ObjectType = Image
+NDims = 3
+BinaryData = True
+BinaryDataByteOrderMSB = False
+CompressedData = False
+Offset = 0 0 1
+ElementSpacing = 0.5 0.5 2
+DimSize = 5 4 3
+ElementNumberOfChannels = 2
+ElementType = MET_FLOAT
+ElementDataFile = image.raw
+",
+        )?;
+        let header = BinaryHeader::from_path(&header_path)?;
+        assert_eq!(header.data_type, Some(data_type("float32")));
+        assert_eq!(header.shape, Some(vec![3, 4, 5, 2]));
+        assert_eq!(header.endianness, Some(Endianness::Little));
+        assert_eq!(header.origin, Some(vec![1.0, 0.0, 0.0, 0.0]));
+        assert_eq!(header.data_file, Some(path.path().join("image.raw")));
+        let attributes = header.attributes();
+        assert_eq!(
+            attributes["spacing"],
+            serde_json::json!([2.0, 0.5, 0.5, null])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn binary_header_json() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let header_path = path.path().join("image.json");
+        std::fs::write(
+            &header_path,
+            r#"{"data_type": "int16", "shape": [3, 4], "endianness": "little", "units": ["um", "um"]}"#,
+        )?;
+        let header = BinaryHeader::from_path(&header_path)?;
+        assert_eq!(header.data_type, Some(data_type("int16")));
+        assert_eq!(header.shape, Some(vec![3, 4]));
+        assert_eq!(header.data_file, None);
+        assert!(BinaryHeader::from_path(&path.path().join("image.tif")).is_err());
+        Ok(())
+    }
+}
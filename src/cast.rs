@@ -0,0 +1,949 @@
+use half::{bf16, f16};
+use num_complex::Complex;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zarrs::array::{DataType, FillValue};
+
+/// The policy applied when a value is not representable in a target data type.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CastPolicy {
+    /// Return an error if a value is not exactly representable.
+    ///
+    /// This includes a loss of precision, such as a float64 value that is not exactly representable as a float32.
+    Error,
+    /// Clamp values to the range of the target data type.
+    ///
+    /// Floating point values are truncated towards zero when cast to an integer, and NaN becomes zero.
+    /// Non-zero values are true when cast to a bool.
+    #[default]
+    Saturate,
+    /// Wrap integers around the range of the target data type (two's complement truncation).
+    ///
+    /// Floating point values are truncated towards zero when cast to an integer, and NaN becomes zero.
+    /// Floating point values that overflow become infinite.
+    Wrap,
+}
+
+/// A fill value conversion error.
+#[derive(Debug, Error)]
+pub enum FillValueConversionError {
+    #[error("fill value conversion from {_0} to {_1} is not supported")]
+    Unsupported(DataType, DataType),
+    #[error("fill value {_0} is not representable by {_1}, set the fill value explicitly or use a saturate/wrap cast policy")]
+    NotRepresentable(String, DataType),
+    #[error("fill value is incompatible with {_0}")]
+    IncompatibleFillValue(DataType),
+}
+
+/// A decoded scalar fill value.
+#[derive(Debug, Clone, PartialEq)]
+enum Scalar {
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Complex(f64, f64),
+    RawBits(Vec<u8>),
+}
+
+impl std::fmt::Display for Scalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scalar::Bool(value) => write!(f, "{value}"),
+            Scalar::Int(value) => write!(f, "{value}"),
+            Scalar::Float(value) => write!(f, "{value}"),
+            Scalar::Complex(re, im) => write!(f, "{re}+{im}i"),
+            Scalar::RawBits(bytes) => write!(f, "{bytes:?}"),
+        }
+    }
+}
+
+fn decode_scalar(
+    data_type: &DataType,
+    fill_value: &FillValue,
+) -> Result<Scalar, FillValueConversionError> {
+    let bytes = fill_value.as_ne_bytes();
+    macro_rules! from_ne_bytes {
+        ( $t:ty ) => {
+            <$t>::from_ne_bytes(
+                bytes.try_into().map_err(|_| {
+                    FillValueConversionError::IncompatibleFillValue(data_type.clone())
+                })?,
+            )
+        };
+    }
+    macro_rules! from_ne_bytes_complex {
+        ( $t:ty ) => {{
+            let size = std::mem::size_of::<$t>();
+            if bytes.len() != size * 2 {
+                return Err(FillValueConversionError::IncompatibleFillValue(
+                    data_type.clone(),
+                ));
+            }
+            let re = <$t>::from_ne_bytes(bytes[..size].try_into().unwrap());
+            let im = <$t>::from_ne_bytes(bytes[size..].try_into().unwrap());
+            Scalar::Complex(f64::from(re), f64::from(im))
+        }};
+    }
+    Ok(match data_type {
+        DataType::Bool => Scalar::Bool(from_ne_bytes!(u8) != 0),
+        DataType::Int8 => Scalar::Int(from_ne_bytes!(i8).into()),
+        DataType::Int16 => Scalar::Int(from_ne_bytes!(i16).into()),
+        DataType::Int32 => Scalar::Int(from_ne_bytes!(i32).into()),
+        DataType::Int64 => Scalar::Int(from_ne_bytes!(i64).into()),
+        DataType::UInt8 => Scalar::Int(from_ne_bytes!(u8).into()),
+        DataType::UInt16 => Scalar::Int(from_ne_bytes!(u16).into()),
+        DataType::UInt32 => Scalar::Int(from_ne_bytes!(u32).into()),
+        DataType::UInt64 => Scalar::Int(from_ne_bytes!(u64).into()),
+        DataType::Float16 => Scalar::Float(from_ne_bytes!(f16).to_f64()),
+        DataType::BFloat16 => Scalar::Float(from_ne_bytes!(bf16).to_f64()),
+        DataType::Float32 => Scalar::Float(from_ne_bytes!(f32).into()),
+        DataType::Float64 => Scalar::Float(from_ne_bytes!(f64)),
+        DataType::Complex64 => from_ne_bytes_complex!(f32),
+        DataType::Complex128 => from_ne_bytes_complex!(f64),
+        DataType::RawBits(_) => Scalar::RawBits(bytes.to_vec()),
+        _ => {
+            return Err(FillValueConversionError::Unsupported(
+                data_type.clone(),
+                data_type.clone(),
+            ))
+        }
+    })
+}
+
+/// Convert a scalar to an integer in the range `min..=max`.
+///
+/// The result is outside of the range only with [`CastPolicy::Wrap`], in which case the caller truncates it.
+fn scalar_to_int(
+    scalar: &Scalar,
+    min: i128,
+    max: i128,
+    policy: CastPolicy,
+    data_type: &DataType,
+) -> Result<i128, FillValueConversionError> {
+    let not_representable =
+        || FillValueConversionError::NotRepresentable(scalar.to_string(), data_type.clone());
+    let value = match scalar {
+        Scalar::Bool(value) => i128::from(*value),
+        Scalar::Int(value) => *value,
+        Scalar::Float(value) | Scalar::Complex(value, _) => {
+            if let Scalar::Complex(_, im) = scalar {
+                if *im != 0.0 && policy == CastPolicy::Error {
+                    return Err(not_representable());
+                }
+            }
+            if value.is_nan() {
+                match policy {
+                    CastPolicy::Error => return Err(not_representable()),
+                    CastPolicy::Saturate | CastPolicy::Wrap => 0,
+                }
+            } else {
+                let truncated = value.trunc();
+                if truncated != *value && policy == CastPolicy::Error {
+                    return Err(not_representable());
+                }
+                // NOTE: saturates at the i128 range, which exceeds all target ranges
+                truncated as i128
+            }
+        }
+        Scalar::RawBits(_) => unreachable!("raw bits are reinterpreted"),
+    };
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        match policy {
+            CastPolicy::Error => Err(not_representable()),
+            CastPolicy::Saturate => Ok(value.clamp(min, max)),
+            CastPolicy::Wrap => Ok(value),
+        }
+    }
+}
+
+/// Convert a scalar to a float with a finite range of `-max..=max`.
+///
+/// `round` rounds a value to the precision of the target data type, which must be exact with [`CastPolicy::Error`].
+fn scalar_to_float(
+    scalar: &Scalar,
+    max: f64,
+    round: impl Fn(f64) -> f64,
+    policy: CastPolicy,
+    data_type: &DataType,
+) -> Result<f64, FillValueConversionError> {
+    let not_representable =
+        || FillValueConversionError::NotRepresentable(scalar.to_string(), data_type.clone());
+    let value = match scalar {
+        Scalar::Bool(value) => f64::from(u8::from(*value)),
+        Scalar::Int(value) => {
+            let value_float = *value as f64;
+            if value_float as i128 != *value && policy == CastPolicy::Error {
+                return Err(not_representable());
+            }
+            value_float
+        }
+        Scalar::Float(value) => *value,
+        Scalar::Complex(re, im) => {
+            if *im != 0.0 && policy == CastPolicy::Error {
+                return Err(not_representable());
+            }
+            *re
+        }
+        Scalar::RawBits(_) => unreachable!("raw bits are reinterpreted"),
+    };
+    if !value.is_finite() || value.abs() <= max {
+        if value.is_finite() && round(value) != value && policy == CastPolicy::Error {
+            return Err(not_representable());
+        }
+        Ok(value)
+    } else {
+        match policy {
+            CastPolicy::Error => Err(not_representable()),
+            CastPolicy::Saturate => Ok(value.clamp(-max, max)),
+            CastPolicy::Wrap => Ok(value.signum() * f64::INFINITY),
+        }
+    }
+}
+
+/// Convert an array fill value to a new data type.
+///
+/// Conversions between all numeric data types (`bool`, integers, floats, and complex) are supported.
+/// Raw bits are reinterpreted if the input and output data types have the same size.
+///
+/// Values that are not exactly representable in the output data type are handled according to the cast `policy`.
+///
+/// # Errors
+/// Returns a [`FillValueConversionError`] if the conversion is not supported or the fill value is not representable with [`CastPolicy::Error`].
+pub fn convert_fill_value(
+    data_type_in: &DataType,
+    fill_value_in: &FillValue,
+    data_type_out: &DataType,
+    policy: CastPolicy,
+) -> Result<FillValue, FillValueConversionError> {
+    if data_type_in == data_type_out {
+        return Ok(fill_value_in.clone());
+    }
+
+    let unsupported =
+        || FillValueConversionError::Unsupported(data_type_in.clone(), data_type_out.clone());
+    if matches!(data_type_in, DataType::RawBits(_)) || matches!(data_type_out, DataType::RawBits(_))
+    {
+        // Reinterpret the fill value bytes
+        return match (data_type_in.fixed_size(), data_type_out.fixed_size()) {
+            (Some(size_in), Some(size_out)) if size_in == size_out => {
+                Ok(FillValue::new(fill_value_in.as_ne_bytes().to_vec()))
+            }
+            _ => Err(unsupported()),
+        };
+    }
+
+    let scalar = decode_scalar(data_type_in, fill_value_in).map_err(|err| match err {
+        FillValueConversionError::Unsupported(..) => unsupported(),
+        err => err,
+    })?;
+
+    macro_rules! to_int {
+        ( $t:ty ) => {{
+            let value = scalar_to_int(
+                &scalar,
+                <$t>::MIN.into(),
+                <$t>::MAX.into(),
+                policy,
+                data_type_out,
+            )?;
+            FillValue::from(value as $t)
+        }};
+    }
+    macro_rules! to_float {
+        ( $max:expr, $round:expr ) => {
+            scalar_to_float(&scalar, $max, $round, policy, data_type_out)?
+        };
+    }
+    macro_rules! to_complex {
+        ( $t:ty, $round:expr ) => {{
+            let max = f64::from(<$t>::MAX);
+            let (re, im) = match &scalar {
+                Scalar::Complex(re, im) => (
+                    scalar_to_float(&Scalar::Float(*re), max, $round, policy, data_type_out)?,
+                    scalar_to_float(&Scalar::Float(*im), max, $round, policy, data_type_out)?,
+                ),
+                _ => (to_float!(max, $round), 0.0),
+            };
+            let mut bytes = Vec::with_capacity(std::mem::size_of::<$t>() * 2);
+            bytes.extend((re as $t).to_ne_bytes());
+            bytes.extend((im as $t).to_ne_bytes());
+            FillValue::new(bytes)
+        }};
+    }
+
+    Ok(match data_type_out {
+        DataType::Bool => {
+            let value = match (&scalar, policy) {
+                (Scalar::Bool(value), _) => *value,
+                (_, CastPolicy::Saturate) => {
+                    scalar_to_int(
+                        &scalar,
+                        i128::MIN,
+                        i128::MAX,
+                        CastPolicy::Saturate,
+                        data_type_out,
+                    )? != 0
+                }
+                _ => scalar_to_int(&scalar, 0, 1, policy, data_type_out)? & 1 != 0,
+            };
+            FillValue::from(value)
+        }
+        DataType::Int8 => to_int!(i8),
+        DataType::Int16 => to_int!(i16),
+        DataType::Int32 => to_int!(i32),
+        DataType::Int64 => to_int!(i64),
+        DataType::UInt8 => to_int!(u8),
+        DataType::UInt16 => to_int!(u16),
+        DataType::UInt32 => to_int!(u32),
+        DataType::UInt64 => to_int!(u64),
+        DataType::Float16 => {
+            FillValue::from(f16::from_f64(to_float!(f16::MAX.to_f64(), |value| {
+                f16::from_f64(value).to_f64()
+            })))
+        }
+        DataType::BFloat16 => {
+            FillValue::from(bf16::from_f64(to_float!(bf16::MAX.to_f64(), |value| {
+                bf16::from_f64(value).to_f64()
+            })))
+        }
+        DataType::Float32 => {
+            FillValue::from(to_float!(f64::from(f32::MAX), |value| f64::from(value as f32)) as f32)
+        }
+        DataType::Float64 => FillValue::from(to_float!(f64::MAX, |value| value)),
+        DataType::Complex64 => to_complex!(f32, |value| f64::from(value as f32)),
+        DataType::Complex128 => to_complex!(f64, |value| value),
+        _ => return Err(unsupported()),
+    })
+}
+
+/// The rounding applied when casting a floating point element to an integer.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CastRounding {
+    /// Round towards zero.
+    #[default]
+    Truncate,
+    /// Round to the nearest integer, with ties away from zero.
+    Round,
+    /// Round towards negative infinity.
+    Floor,
+    /// Round towards positive infinity.
+    Ceil,
+}
+
+impl CastRounding {
+    fn apply(self, value: f64) -> f64 {
+        match self {
+            CastRounding::Truncate => value.trunc(),
+            CastRounding::Round => value.round(),
+            CastRounding::Floor => value.floor(),
+            CastRounding::Ceil => value.ceil(),
+        }
+    }
+}
+
+/// The handling of NaN when casting a floating point element to an integer.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CastNan {
+    /// NaN becomes zero.
+    #[default]
+    Zero,
+    /// Return an error.
+    Error,
+}
+
+/// An element cast error.
+#[derive(Debug, Error)]
+pub enum ElementCastError {
+    #[error("NaN is not representable by an integer, use a zero NaN cast policy")]
+    Nan,
+    #[error(
+        "{_0} is out of the range of the output data type, use a saturate/wrap overflow policy"
+    )]
+    Overflow(String),
+}
+
+/// Explicit policies for casting elements between data types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::Args, Serialize, Deserialize, JsonSchema)]
+pub struct ElementCastArgs {
+    /// The policy for elements out of the range of the output data type.
+    ///
+    /// Floating point elements are rounded (see --rounding) before integer range checks.
+    #[arg(long, value_enum, default_value_t = CastPolicy::Saturate)]
+    #[serde(default = "default_overflow")]
+    pub overflow: CastPolicy,
+
+    /// The rounding of floating point elements cast to an integer.
+    #[arg(long, value_enum, default_value_t = CastRounding::Truncate)]
+    #[serde(default)]
+    pub rounding: CastRounding,
+
+    /// The handling of NaN elements cast to an integer.
+    #[arg(long, value_enum, default_value_t = CastNan::Zero)]
+    #[serde(default)]
+    pub nan: CastNan,
+}
+
+fn default_overflow() -> CastPolicy {
+    CastPolicy::Saturate
+}
+
+impl Default for ElementCastArgs {
+    fn default() -> Self {
+        Self {
+            overflow: default_overflow(),
+            rounding: CastRounding::default(),
+            nan: CastNan::default(),
+        }
+    }
+}
+
+/// An array element type supported by [`ElementCastArgs::cast`].
+pub trait CastElement: Copy + Send + Sync + 'static {
+    /// True for floating point types.
+    const FLOAT: bool;
+    /// True for `bool`.
+    const BOOL: bool = false;
+    /// The integer range of the type (ignored for floating point types).
+    const INT_RANGE: (i128, i128) = (0, 0);
+    /// The maximum finite value of the type (ignored for integer types).
+    const FLOAT_MAX: f64 = 0.0;
+
+    /// Convert an integer element to an [`i128`].
+    fn to_i128(self) -> i128;
+    /// Convert a floating point element to an [`f64`].
+    fn to_f64(self) -> f64;
+    /// Convert from an [`i128`], truncating out of range values.
+    fn from_i128(value: i128) -> Self;
+    /// Convert from an [`f64`].
+    fn from_f64(value: f64) -> Self;
+}
+
+impl CastElement for bool {
+    const FLOAT: bool = false;
+    const BOOL: bool = true;
+    const INT_RANGE: (i128, i128) = (0, 1);
+
+    fn to_i128(self) -> i128 {
+        i128::from(self)
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(u8::from(self))
+    }
+
+    fn from_i128(value: i128) -> Self {
+        value & 1 != 0
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value != 0.0
+    }
+}
+
+macro_rules! impl_cast_element_int {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl CastElement for $t {
+                const FLOAT: bool = false;
+                const INT_RANGE: (i128, i128) = (<$t>::MIN as i128, <$t>::MAX as i128);
+
+                fn to_i128(self) -> i128 {
+                    self.into()
+                }
+
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+
+                fn from_i128(value: i128) -> Self {
+                    value as $t
+                }
+
+                fn from_f64(value: f64) -> Self {
+                    value as $t
+                }
+            }
+        )*
+    };
+}
+impl_cast_element_int!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+macro_rules! impl_cast_element_float {
+    ( $( ($t:ty, $max:expr, $to_f64:expr, $from_f64:expr) ),* ) => {
+        $(
+            impl CastElement for $t {
+                const FLOAT: bool = true;
+                const FLOAT_MAX: f64 = $max;
+
+                fn to_i128(self) -> i128 {
+                    Self::to_f64(self) as i128
+                }
+
+                fn to_f64(self) -> f64 {
+                    $to_f64(self)
+                }
+
+                fn from_i128(value: i128) -> Self {
+                    Self::from_f64(value as f64)
+                }
+
+                fn from_f64(value: f64) -> Self {
+                    $from_f64(value)
+                }
+            }
+        )*
+    };
+}
+impl_cast_element_float!(
+    (f16, f16::MAX.to_f64_const(), f16::to_f64, f16::from_f64),
+    (bf16, bf16::MAX.to_f64_const(), bf16::to_f64, bf16::from_f64),
+    (f32, f32::MAX as f64, f64::from, |value: f64| value as f32),
+    (
+        f64,
+        f64::MAX,
+        std::convert::identity,
+        std::convert::identity
+    )
+);
+
+impl ElementCastArgs {
+    /// Cast an element from `TIn` to `TOut`.
+    ///
+    /// Unlike [`convert_fill_value`] with [`CastPolicy::Error`], rounding floating point elements to an integer is not an error.
+    ///
+    /// # Errors
+    /// Returns an [`ElementCastError`] if the element is NaN with [`CastNan::Error`] or out of range with [`CastPolicy::Error`].
+    pub fn cast<TIn: CastElement, TOut: CastElement>(
+        &self,
+        value: TIn,
+    ) -> Result<TOut, ElementCastError> {
+        if TOut::FLOAT {
+            let value = if TIn::FLOAT {
+                value.to_f64()
+            } else {
+                value.to_i128() as f64
+            };
+            if value.is_finite() && value.abs() > TOut::FLOAT_MAX {
+                match self.overflow {
+                    CastPolicy::Error => Err(ElementCastError::Overflow(value.to_string())),
+                    CastPolicy::Saturate => Ok(TOut::from_f64(
+                        value.clamp(-TOut::FLOAT_MAX, TOut::FLOAT_MAX),
+                    )),
+                    CastPolicy::Wrap => Ok(TOut::from_f64(value.signum() * f64::INFINITY)),
+                }
+            } else {
+                Ok(TOut::from_f64(value))
+            }
+        } else {
+            let value = if TIn::FLOAT {
+                let value = value.to_f64();
+                if value.is_nan() {
+                    match self.nan {
+                        CastNan::Zero => return Ok(TOut::from_i128(0)),
+                        CastNan::Error => return Err(ElementCastError::Nan),
+                    }
+                }
+                // NOTE: saturates at the i128 range, which exceeds all target ranges
+                self.rounding.apply(value) as i128
+            } else {
+                value.to_i128()
+            };
+            let (min, max) = TOut::INT_RANGE;
+            if (min..=max).contains(&value) {
+                Ok(TOut::from_i128(value))
+            } else {
+                match self.overflow {
+                    CastPolicy::Error => Err(ElementCastError::Overflow(value.to_string())),
+                    CastPolicy::Saturate if TOut::BOOL => Ok(TOut::from_i128(1)),
+                    CastPolicy::Saturate => Ok(TOut::from_i128(value.clamp(min, max))),
+                    CastPolicy::Wrap => Ok(TOut::from_i128(value)),
+                }
+            }
+        }
+    }
+
+    /// Cast `elements` from `TIn` to `TOut` in parallel.
+    ///
+    /// # Errors
+    /// Returns an [`ElementCastError`] if any element cannot be cast, see [`ElementCastArgs::cast`].
+    pub fn cast_elements<TIn: CastElement, TOut: CastElement>(
+        &self,
+        elements: &[TIn],
+    ) -> Result<Vec<TOut>, ElementCastError> {
+        elements.par_iter().map(|value| self.cast(*value)).collect()
+    }
+
+    /// Cast a complex element from `Complex<TIn>` to `Complex<TOut>`, casting the real and imaginary parts independently.
+    ///
+    /// # Errors
+    /// Returns an [`ElementCastError`] if either part cannot be cast, see [`ElementCastArgs::cast`].
+    pub fn cast_complex<TIn: CastElement, TOut: CastElement>(
+        &self,
+        value: Complex<TIn>,
+    ) -> Result<Complex<TOut>, ElementCastError> {
+        Ok(Complex::new(self.cast(value.re)?, self.cast(value.im)?))
+    }
+
+    /// Cast complex `elements` from `Complex<TIn>` to `Complex<TOut>` in parallel.
+    ///
+    /// # Errors
+    /// Returns an [`ElementCastError`] if any element cannot be cast, see [`ElementCastArgs::cast_complex`].
+    pub fn cast_elements_complex<TIn: CastElement, TOut: CastElement>(
+        &self,
+        elements: &[Complex<TIn>],
+    ) -> Result<Vec<Complex<TOut>>, ElementCastError> {
+        elements
+            .par_iter()
+            .map(|value| self.cast_complex(*value))
+            .collect()
+    }
+
+    /// Cast real `elements` from `TIn` to `Complex<TOut>` with a zero imaginary part in parallel.
+    ///
+    /// # Errors
+    /// Returns an [`ElementCastError`] if any element cannot be cast, see [`ElementCastArgs::cast`].
+    pub fn cast_elements_real_to_complex<TIn: CastElement, TOut: CastElement>(
+        &self,
+        elements: &[TIn],
+    ) -> Result<Vec<Complex<TOut>>, ElementCastError> {
+        elements
+            .par_iter()
+            .map(|value| Ok(Complex::new(self.cast(*value)?, TOut::from_f64(0.0))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA_TYPES: [DataType; 16] = [
+        DataType::Bool,
+        DataType::Int8,
+        DataType::Int16,
+        DataType::Int32,
+        DataType::Int64,
+        DataType::UInt8,
+        DataType::UInt16,
+        DataType::UInt32,
+        DataType::UInt64,
+        DataType::Float16,
+        DataType::Float32,
+        DataType::Float64,
+        DataType::BFloat16,
+        DataType::Complex64,
+        DataType::Complex128,
+        DataType::RawBits(2),
+    ];
+
+    fn fill_value_one(data_type: &DataType) -> FillValue {
+        data_type
+            .fill_value_from_metadata(&match data_type {
+                DataType::Complex64 | DataType::Complex128 => {
+                    serde_json::from_str("[1.0, 0.0]").unwrap()
+                }
+                DataType::RawBits(_) => serde_json::from_str("[1, 0]").unwrap(),
+                DataType::Bool => serde_json::from_str("true").unwrap(),
+                _ => serde_json::from_str("1").unwrap(),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn convert_fill_value_matrix() {
+        for data_type_in in &DATA_TYPES {
+            let fill_value_in = fill_value_one(data_type_in);
+            for data_type_out in &DATA_TYPES {
+                let fill_value_out = convert_fill_value(
+                    data_type_in,
+                    &fill_value_in,
+                    data_type_out,
+                    CastPolicy::Error,
+                );
+                match (data_type_in, data_type_out) {
+                    (DataType::RawBits(_), DataType::RawBits(_)) => {
+                        assert_eq!(fill_value_out.unwrap(), fill_value_in);
+                    }
+                    (DataType::RawBits(size), _) | (_, DataType::RawBits(size)) => {
+                        let other = if let DataType::RawBits(_) = data_type_in {
+                            data_type_out
+                        } else {
+                            data_type_in
+                        };
+                        if other.fixed_size() == Some(*size) {
+                            assert_eq!(
+                                fill_value_out.unwrap().as_ne_bytes(),
+                                fill_value_in.as_ne_bytes()
+                            );
+                        } else {
+                            assert!(fill_value_out.is_err());
+                        }
+                    }
+                    _ => {
+                        assert_eq!(
+                            fill_value_out.unwrap(),
+                            fill_value_one(data_type_out),
+                            "{data_type_in} -> {data_type_out}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn convert_fill_value_unsupported() {
+        for data_type in [DataType::String, DataType::Binary] {
+            assert!(matches!(
+                convert_fill_value(
+                    &DataType::Float32,
+                    &FillValue::from(0.0f32),
+                    &data_type,
+                    CastPolicy::Saturate
+                ),
+                Err(FillValueConversionError::Unsupported(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn convert_fill_value_policies() {
+        let convert = |data_type_in: &DataType,
+                       fill_value_in: FillValue,
+                       data_type_out: &DataType,
+                       policy: CastPolicy| {
+            convert_fill_value(data_type_in, &fill_value_in, data_type_out, policy)
+        };
+
+        // Integer narrowing
+        let cases = [
+            (CastPolicy::Error, None),
+            (CastPolicy::Saturate, Some(255u8)),
+            (CastPolicy::Wrap, Some(44u8)),
+        ];
+        for (policy, expected) in cases {
+            let out = convert(&DataType::Int32, 300i32.into(), &DataType::UInt8, policy);
+            assert_eq!(out.ok(), expected.map(FillValue::from));
+        }
+        let cases = [
+            (CastPolicy::Error, None),
+            (CastPolicy::Saturate, Some(0u8)),
+            (CastPolicy::Wrap, Some(255u8)),
+        ];
+        for (policy, expected) in cases {
+            let out = convert(&DataType::Int8, (-1i8).into(), &DataType::UInt8, policy);
+            assert_eq!(out.ok(), expected.map(FillValue::from));
+        }
+
+        // Float to integer
+        let cases = [
+            (CastPolicy::Error, None),
+            (CastPolicy::Saturate, Some(1i16)),
+            (CastPolicy::Wrap, Some(1i16)),
+        ];
+        for (policy, expected) in cases {
+            let out = convert(&DataType::Float32, 1.5f32.into(), &DataType::Int16, policy);
+            assert_eq!(out.ok(), expected.map(FillValue::from));
+        }
+        let cases = [
+            (CastPolicy::Error, None),
+            (CastPolicy::Saturate, Some(0u16)),
+            (CastPolicy::Wrap, Some(0u16)),
+        ];
+        for (policy, expected) in cases {
+            let out = convert(
+                &DataType::Float64,
+                f64::NAN.into(),
+                &DataType::UInt16,
+                policy,
+            );
+            assert_eq!(out.ok(), expected.map(FillValue::from));
+        }
+        let cases = [
+            (CastPolicy::Error, None),
+            (CastPolicy::Saturate, Some(i8::MIN)),
+            (CastPolicy::Wrap, Some(0i8)),
+        ];
+        for (policy, expected) in cases {
+            let out = convert(
+                &DataType::Float64,
+                (-256.0f64).into(),
+                &DataType::Int8,
+                policy,
+            );
+            assert_eq!(out.ok(), expected.map(FillValue::from));
+        }
+
+        // Float narrowing
+        let cases = [
+            (CastPolicy::Error, None),
+            (CastPolicy::Saturate, Some(f16::MAX)),
+            (CastPolicy::Wrap, Some(f16::INFINITY)),
+        ];
+        for (policy, expected) in cases {
+            let out = convert(
+                &DataType::UInt32,
+                100_000u32.into(),
+                &DataType::Float16,
+                policy,
+            );
+            assert_eq!(out.ok(), expected.map(FillValue::from));
+        }
+        for policy in [CastPolicy::Error, CastPolicy::Saturate, CastPolicy::Wrap] {
+            let out = convert(
+                &DataType::Float64,
+                f64::NAN.into(),
+                &DataType::Float32,
+                policy,
+            );
+            assert!(f32::from_ne_bytes(out.unwrap().as_ne_bytes().try_into().unwrap()).is_nan());
+        }
+
+        // Float precision loss
+        let cases = [
+            (CastPolicy::Error, None),
+            (CastPolicy::Saturate, Some(0.1f32)),
+            (CastPolicy::Wrap, Some(0.1f32)),
+        ];
+        for (policy, expected) in cases {
+            let out = convert(
+                &DataType::Float64,
+                0.1f64.into(),
+                &DataType::Float32,
+                policy,
+            );
+            assert_eq!(out.ok(), expected.map(FillValue::from));
+        }
+        assert_eq!(
+            convert(
+                &DataType::Float64,
+                0.5f64.into(),
+                &DataType::Float32,
+                CastPolicy::Error
+            )
+            .unwrap(),
+            FillValue::from(0.5f32)
+        );
+        assert!(convert(
+            &DataType::Int64,
+            (i64::MAX - 1).into(),
+            &DataType::Float64,
+            CastPolicy::Error
+        )
+        .is_err());
+
+        // Complex to real
+        let complex = FillValue::new([2.0f32.to_ne_bytes(), 1.0f32.to_ne_bytes()].concat());
+        assert!(convert(
+            &DataType::Complex64,
+            complex.clone(),
+            &DataType::Float32,
+            CastPolicy::Error
+        )
+        .is_err());
+        assert_eq!(
+            convert(
+                &DataType::Complex64,
+                complex,
+                &DataType::Float32,
+                CastPolicy::Saturate
+            )
+            .unwrap(),
+            FillValue::from(2.0f32)
+        );
+
+        // Bool
+        assert!(convert(
+            &DataType::UInt8,
+            2u8.into(),
+            &DataType::Bool,
+            CastPolicy::Error
+        )
+        .is_err());
+        assert_eq!(
+            convert(
+                &DataType::UInt8,
+                2u8.into(),
+                &DataType::Bool,
+                CastPolicy::Saturate
+            )
+            .unwrap(),
+            FillValue::from(true)
+        );
+        assert_eq!(
+            convert(
+                &DataType::UInt8,
+                2u8.into(),
+                &DataType::Bool,
+                CastPolicy::Wrap
+            )
+            .unwrap(),
+            FillValue::from(false)
+        );
+    }
+
+    #[test]
+    fn cast_elements_policies() {
+        let cast = |overflow, rounding, nan| ElementCastArgs {
+            overflow,
+            rounding,
+            nan,
+        };
+        let elements = [-1.5f32, 0.5, 1.5, 300.7];
+
+        let args = ElementCastArgs::default();
+        let out: Vec<u8> = args.cast_elements(&elements).unwrap();
+        assert_eq!(out, [0, 0, 1, 255]);
+
+        let args = cast(CastPolicy::Saturate, CastRounding::Round, CastNan::Zero);
+        let out: Vec<i16> = args.cast_elements(&elements).unwrap();
+        assert_eq!(out, [-2, 1, 2, 301]);
+
+        let args = cast(CastPolicy::Wrap, CastRounding::Floor, CastNan::Zero);
+        let out: Vec<u8> = args.cast_elements(&elements).unwrap();
+        assert_eq!(out, [254, 0, 1, 44]);
+
+        let args = cast(CastPolicy::Error, CastRounding::Ceil, CastNan::Zero);
+        let out: Result<Vec<u8>, _> = args.cast_elements(&elements);
+        assert!(matches!(out, Err(ElementCastError::Overflow(_))));
+        let out: Vec<i16> = args.cast_elements(&elements).unwrap();
+        assert_eq!(out, [-1, 1, 2, 301]);
+
+        // NaN
+        let elements = [f64::NAN, 1.0];
+        let out: Vec<i32> = ElementCastArgs::default().cast_elements(&elements).unwrap();
+        assert_eq!(out, [0, 1]);
+        let args = cast(CastPolicy::Saturate, CastRounding::Truncate, CastNan::Error);
+        let out: Result<Vec<i32>, _> = args.cast_elements(&elements);
+        assert!(matches!(out, Err(ElementCastError::Nan)));
+        let out: Vec<f32> = args.cast_elements(&elements).unwrap();
+        assert!(out[0].is_nan());
+
+        // Integer and float narrowing
+        let out: Vec<i8> = ElementCastArgs::default()
+            .cast_elements(&[-1000i32, 1000])
+            .unwrap();
+        assert_eq!(out, [i8::MIN, i8::MAX]);
+        let out: Vec<f16> = ElementCastArgs::default()
+            .cast_elements(&[100_000u32])
+            .unwrap();
+        assert_eq!(out, [f16::MAX]);
+        let out: Vec<bool> = ElementCastArgs::default()
+            .cast_elements(&[0u8, 1, 2])
+            .unwrap();
+        assert_eq!(out, [false, true, true]);
+    }
+}
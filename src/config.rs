@@ -0,0 +1,262 @@
+//! Default argument values from a `zarrs_tools.toml` configuration file.
+//!
+//! Configuration files are read from (in increasing order of precedence):
+//!  - `$XDG_CONFIG_HOME/zarrs_tools/zarrs_tools.toml` (or `~/.config/zarrs_tools/zarrs_tools.toml`),
+//!  - `zarrs_tools.toml` in the current directory, and
+//!  - the path in the `ZARRS_TOOLS_CONFIG` environment variable.
+//!
+//! Keys are long argument names without the leading `--`.
+//! The `[defaults]` table applies to every binary (arguments a binary does not have are ignored), and a table named after a binary (e.g. `[zarrs_reencode]`) applies to that binary only.
+//! Command line arguments take precedence over configuration files.
+//!
+//! ```toml
+//! [defaults]
+//! threads = 8
+//! progress-mode = "bytes"
+//!
+//! [zarrs_reencode]
+//! cache-size = "1GB"
+//! concurrent-chunks = 4
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use clap::{Command, FromArgMatches};
+
+/// The name of a configuration file.
+pub const CONFIG_FILE_NAME: &str = "zarrs_tools.toml";
+
+/// A configuration error.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read {_0:?}: {_1}")]
+    Read(PathBuf, std::io::Error),
+    #[error("failed to parse {_0:?}: {_1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("{_0:?}: [{_1}] is not a table")]
+    NotATable(PathBuf, String),
+    #[error("{_0:?}: {_1} has an unsupported value, expected a string, number, boolean, or array")]
+    UnsupportedValue(PathBuf, String),
+    #[error("{_0:?}: {_1} is not an argument of {_2}")]
+    UnknownArgument(PathBuf, String, String),
+}
+
+/// Returns the paths of the configuration files in increasing order of precedence.
+pub fn config_paths() -> Vec<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    let mut paths = vec![];
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join("zarrs_tools").join(CONFIG_FILE_NAME));
+    }
+    paths.push(PathBuf::from(CONFIG_FILE_NAME));
+    if let Some(path) = std::env::var_os("ZARRS_TOOLS_CONFIG") {
+        paths.push(PathBuf::from(path));
+    }
+    paths
+}
+
+/// Convert a configuration value to a command line argument value.
+fn argument_value(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(argument_value)
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.join(",")),
+        toml::Value::Datetime(_) | toml::Value::Table(_) => None,
+    }
+}
+
+/// Returns the default argument values of the binary `name` from a configuration file.
+///
+/// Values of binary specific tables take precedence over the `[defaults]` table.
+///
+/// # Errors
+/// Returns a [`ConfigError`] if the configuration is invalid.
+pub fn config_defaults(
+    path: &Path,
+    config: &str,
+    name: &str,
+) -> Result<BTreeMap<String, String>, ConfigError> {
+    let config: toml::value::Table =
+        toml::from_str(config).map_err(|err| ConfigError::Parse(path.to_path_buf(), err))?;
+    let mut defaults = BTreeMap::new();
+    for table_name in ["defaults", name] {
+        let Some(table) = config.get(table_name) else {
+            continue;
+        };
+        let table = table
+            .as_table()
+            .ok_or_else(|| ConfigError::NotATable(path.to_path_buf(), table_name.to_string()))?;
+        for (key, value) in table {
+            let value = argument_value(value)
+                .ok_or_else(|| ConfigError::UnsupportedValue(path.to_path_buf(), key.clone()))?;
+            defaults.insert(key.clone(), value);
+        }
+    }
+    Ok(defaults)
+}
+
+/// Set the default values of the arguments of `command` (and its subcommands) from the configuration file at `path`.
+///
+/// # Errors
+/// Returns a [`ConfigError`] if the configuration is invalid, or an argument in the table of the binary `name` does not exist.
+pub fn apply_config(
+    mut command: Command,
+    path: &Path,
+    config: &str,
+    name: &str,
+) -> Result<Command, ConfigError> {
+    let config_table: toml::value::Table =
+        toml::from_str(config).map_err(|err| ConfigError::Parse(path.to_path_buf(), err))?;
+    if let Some(table) = config_table.get(name).and_then(toml::Value::as_table) {
+        for key in table.keys() {
+            if !has_argument(&command, key) {
+                return Err(ConfigError::UnknownArgument(
+                    path.to_path_buf(),
+                    key.clone(),
+                    name.to_string(),
+                ));
+            }
+        }
+    }
+    for (key, value) in config_defaults(path, config, name)? {
+        command = set_default(command, &key, value);
+    }
+    Ok(command)
+}
+
+/// Returns true if `command` or any of its subcommands has an argument with the long name `long`.
+fn has_argument(command: &Command, long: &str) -> bool {
+    command
+        .get_arguments()
+        .any(|arg| arg.get_long() == Some(long))
+        || command
+            .get_subcommands()
+            .any(|subcommand| has_argument(subcommand, long))
+}
+
+/// Set the default value of the argument with the long name `long` of `command` and its subcommands.
+fn set_default(mut command: Command, long: &str, value: String) -> Command {
+    let id = command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(long))
+        .map(|arg| arg.get_id().clone());
+    if let Some(id) = id {
+        // The command is built once per process, so leaking the value is inconsequential
+        let value: &'static str = value.clone().leak();
+        command = command.mut_arg(id, |arg| arg.default_value(value));
+    }
+    let subcommands = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect::<Vec<_>>();
+    for subcommand in subcommands {
+        let value = value.clone();
+        command = command.mut_subcommand(subcommand, |subcommand| {
+            set_default(subcommand, long, value)
+        });
+    }
+    command
+}
+
+/// Parse the command line arguments of the binary `name`, with default values from configuration files (see [`config_paths`]).
+///
+/// Exits on error, like [`clap::Parser::parse`].
+pub fn parse<T: clap::Parser>(name: &str) -> T {
+    parse_from(name, std::env::args_os())
+}
+
+/// Parse `args` as the command line arguments of the binary `name`, with default values from configuration files (see [`config_paths`]).
+///
+/// The first argument is the binary name.
+/// Exits on error, like [`clap::Parser::parse_from`].
+pub fn parse_from<T: clap::Parser>(
+    name: &str,
+    args: impl IntoIterator<Item = impl Into<std::ffi::OsString> + Clone>,
+) -> T {
+    let mut command = T::command();
+    for path in config_paths() {
+        if !path.is_file() {
+            continue;
+        }
+        let config = std::fs::read_to_string(&path)
+            .map_err(|err| ConfigError::Read(path.clone(), err))
+            .and_then(|config| apply_config(command, &path, &config, name));
+        command = match config {
+            Ok(command) => command,
+            Err(err) => {
+                clap::Error::raw(clap::error::ErrorKind::InvalidValue, format!("{err}\n")).exit()
+            }
+        };
+    }
+    let mut matches = command.get_matches_from(args);
+    <T as FromArgMatches>::from_arg_matches_mut(&mut matches)
+        .unwrap_or_else(|err| err.format(&mut T::command()).exit())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::*;
+
+    #[derive(Parser, Debug)]
+    struct Args {
+        path: String,
+        #[arg(long)]
+        threads: Option<usize>,
+        #[arg(long, default_value_t = false)]
+        verbose: bool,
+        #[arg(long, value_delimiter = ',')]
+        chunk_shape: Option<Vec<u64>>,
+    }
+
+    const CONFIG: &str = r#"
+[defaults]
+threads = 8
+cache-size = "1GB"
+
+[test]
+threads = 4
+verbose = true
+chunk-shape = [32, 32]
+"#;
+
+    #[test]
+    fn config_apply() {
+        let path = Path::new(CONFIG_FILE_NAME);
+        let defaults = config_defaults(path, CONFIG, "test").unwrap();
+        assert_eq!(defaults["threads"], "4");
+        assert_eq!(defaults["cache-size"], "1GB");
+        assert_eq!(defaults["chunk-shape"], "32,32");
+
+        let command = apply_config(Args::command(), path, CONFIG, "test").unwrap();
+        let mut matches = command.clone().get_matches_from(["test", "a"]);
+        let args = Args::from_arg_matches_mut(&mut matches).unwrap();
+        assert_eq!(args.threads, Some(4));
+        assert!(args.verbose);
+        assert_eq!(args.chunk_shape, Some(vec![32, 32]));
+
+        // Command line arguments take precedence
+        let mut matches = command.get_matches_from(["test", "a", "--threads", "2"]);
+        let args = Args::from_arg_matches_mut(&mut matches).unwrap();
+        assert_eq!(args.threads, Some(2));
+
+        // Unknown arguments of binary specific tables are errors
+        let config = "[test]\ncache-size = \"1GB\"\n";
+        assert!(matches!(
+            apply_config(Args::command(), path, config, "test"),
+            Err(ConfigError::UnknownArgument(..))
+        ));
+    }
+}
@@ -0,0 +1,272 @@
+use half::{bf16, f16};
+use num_traits::AsPrimitive;
+use serde::Serialize;
+use zarrs::array::{ArrayBytes, ArrayError, DataType, ElementOwned};
+
+/// The error of output elements relative to input elements, such as that introduced by a lossy codec (e.g. `bitround` or `zfp`).
+///
+/// Elements with a non-finite (e.g. NaN) input are excluded.
+/// A NaN output of a finite input has an infinite error.
+/// Relative errors are relative to the magnitude of the input, and exclude elements with a zero input.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ErrorMetrics {
+    /// The number of compared elements.
+    pub num_elements: u64,
+    /// The maximum absolute error.
+    pub max_absolute: f64,
+    /// The mean absolute error.
+    pub mean_absolute: f64,
+    /// The number of compared elements with a non-zero input.
+    pub num_elements_relative: u64,
+    /// The maximum relative error.
+    pub max_relative: f64,
+    /// The mean relative error.
+    pub mean_relative: f64,
+}
+
+/// The maximum tolerated error of [`ErrorMetrics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ErrorTolerance {
+    /// The maximum tolerated absolute error.
+    pub max_absolute: Option<f64>,
+    /// The maximum tolerated relative error.
+    pub max_relative: Option<f64>,
+}
+
+/// An error metrics error.
+#[derive(Debug, thiserror::Error)]
+pub enum ErrorMetricsError {
+    #[error(transparent)]
+    Array(#[from] ArrayError),
+    #[error("error metrics do not support the data type {_0}")]
+    UnsupportedDataType(String),
+}
+
+/// Error metrics options.
+#[derive(clap::Parser, Debug, Clone, Default)]
+pub struct ErrorMetricsArgs {
+    /// Report the error of the output relative to the input (e.g. introduced by a lossy bitround or zfp codec).
+    ///
+    /// The maximum and mean absolute and relative errors are computed during the reencode by reading back each written chunk.
+    #[arg(long, default_value_t = false)]
+    pub error_metrics: bool,
+
+    /// Fail if the absolute error of any element exceeds this tolerance.
+    ///
+    /// Implies --error-metrics.
+    /// The reencode stops at the first chunk that exceeds the tolerance.
+    #[arg(long)]
+    pub max_absolute_error: Option<f64>,
+
+    /// Fail if the relative error of any element exceeds this tolerance.
+    ///
+    /// Implies --error-metrics.
+    /// The reencode stops at the first chunk that exceeds the tolerance.
+    #[arg(long)]
+    pub max_relative_error: Option<f64>,
+}
+
+impl ErrorMetricsArgs {
+    /// Returns the error tolerance if error metrics are enabled.
+    #[must_use]
+    pub fn tolerance(&self) -> Option<ErrorTolerance> {
+        (self.error_metrics
+            || self.max_absolute_error.is_some()
+            || self.max_relative_error.is_some())
+        .then_some(ErrorTolerance {
+            max_absolute: self.max_absolute_error,
+            max_relative: self.max_relative_error,
+        })
+    }
+}
+
+impl ErrorMetrics {
+    /// Compute the error metrics of `output` elements relative to `input` elements.
+    ///
+    /// # Panics
+    /// Panics if `input` and `output` have a different length.
+    #[must_use]
+    pub fn from_elements<T: AsPrimitive<f64>>(input: &[T], output: &[T]) -> Self {
+        assert_eq!(input.len(), output.len());
+        let mut metrics = Self::default();
+        let (mut sum_absolute, mut sum_relative) = (0.0, 0.0);
+        for (input, output) in std::iter::zip(input, output) {
+            let (input, output): (f64, f64) = (input.as_(), output.as_());
+            if !input.is_finite() {
+                continue;
+            }
+            let absolute = (output - input).abs();
+            let absolute = if absolute.is_nan() {
+                f64::INFINITY
+            } else {
+                absolute
+            };
+            metrics.num_elements += 1;
+            metrics.max_absolute = metrics.max_absolute.max(absolute);
+            sum_absolute += absolute;
+            if input != 0.0 {
+                let relative = absolute / input.abs();
+                metrics.num_elements_relative += 1;
+                metrics.max_relative = metrics.max_relative.max(relative);
+                sum_relative += relative;
+            }
+        }
+        if metrics.num_elements > 0 {
+            metrics.mean_absolute = sum_absolute / metrics.num_elements as f64;
+        }
+        if metrics.num_elements_relative > 0 {
+            metrics.mean_relative = sum_relative / metrics.num_elements_relative as f64;
+        }
+        metrics
+    }
+
+    /// Compute the error metrics of `output` bytes relative to `input` bytes with `data_type`.
+    ///
+    /// # Errors
+    /// Returns an [`ErrorMetricsError`] if the data type is unsupported or the bytes are incompatible with the data type.
+    pub fn from_bytes(
+        data_type: &DataType,
+        input: &ArrayBytes,
+        output: &ArrayBytes,
+    ) -> Result<Self, ErrorMetricsError> {
+        fn from_bytes_t<T: ElementOwned + AsPrimitive<f64>>(
+            data_type: &DataType,
+            input: &ArrayBytes,
+            output: &ArrayBytes,
+        ) -> Result<ErrorMetrics, ErrorMetricsError> {
+            let input = T::from_array_bytes(data_type, input.clone())?;
+            let output = T::from_array_bytes(data_type, output.clone())?;
+            Ok(ErrorMetrics::from_elements(&input, &output))
+        }
+        match data_type {
+            DataType::Int8 => from_bytes_t::<i8>(data_type, input, output),
+            DataType::Int16 => from_bytes_t::<i16>(data_type, input, output),
+            DataType::Int32 => from_bytes_t::<i32>(data_type, input, output),
+            DataType::Int64 => from_bytes_t::<i64>(data_type, input, output),
+            DataType::UInt8 => from_bytes_t::<u8>(data_type, input, output),
+            DataType::UInt16 => from_bytes_t::<u16>(data_type, input, output),
+            DataType::UInt32 => from_bytes_t::<u32>(data_type, input, output),
+            DataType::UInt64 => from_bytes_t::<u64>(data_type, input, output),
+            DataType::Float16 => from_bytes_t::<f16>(data_type, input, output),
+            DataType::BFloat16 => from_bytes_t::<bf16>(data_type, input, output),
+            DataType::Float32 => from_bytes_t::<f32>(data_type, input, output),
+            DataType::Float64 => from_bytes_t::<f64>(data_type, input, output),
+            _ => Err(ErrorMetricsError::UnsupportedDataType(
+                data_type.to_string(),
+            )),
+        }
+    }
+
+    /// Returns true if error metrics support `data_type`.
+    #[must_use]
+    pub fn supports_data_type(data_type: &DataType) -> bool {
+        matches!(
+            data_type,
+            DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float16
+                | DataType::BFloat16
+                | DataType::Float32
+                | DataType::Float64
+        )
+    }
+
+    /// Combine the error metrics of two disjoint sets of elements.
+    #[must_use]
+    pub fn merge(&self, other: &Self) -> Self {
+        let mean = |mean_a: f64, num_a: u64, mean_b: f64, num_b: u64| {
+            if num_a + num_b == 0 {
+                0.0
+            } else {
+                (mean_a * num_a as f64 + mean_b * num_b as f64) / (num_a + num_b) as f64
+            }
+        };
+        Self {
+            num_elements: self.num_elements + other.num_elements,
+            max_absolute: self.max_absolute.max(other.max_absolute),
+            mean_absolute: mean(
+                self.mean_absolute,
+                self.num_elements,
+                other.mean_absolute,
+                other.num_elements,
+            ),
+            num_elements_relative: self.num_elements_relative + other.num_elements_relative,
+            max_relative: self.max_relative.max(other.max_relative),
+            mean_relative: mean(
+                self.mean_relative,
+                self.num_elements_relative,
+                other.mean_relative,
+                other.num_elements_relative,
+            ),
+        }
+    }
+
+    /// Returns a description of the first tolerance of `tolerance` that is exceeded, if any.
+    #[must_use]
+    pub fn exceeds(&self, tolerance: &ErrorTolerance) -> Option<String> {
+        if let Some(max_absolute) = tolerance.max_absolute {
+            if self.max_absolute > max_absolute {
+                return Some(format!(
+                    "the maximum absolute error {} exceeds {max_absolute}",
+                    self.max_absolute
+                ));
+            }
+        }
+        if let Some(max_relative) = tolerance.max_relative {
+            if self.max_relative > max_relative {
+                return Some(format!(
+                    "the maximum relative error {} exceeds {max_relative}",
+                    self.max_relative
+                ));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_metrics() {
+        let metrics = ErrorMetrics::from_elements(
+            &[1.0f32, 2.0, 0.0, -4.0, f32::NAN],
+            &[1.0f32, 2.5, 0.5, -3.0, 7.0],
+        );
+        assert_eq!(metrics.num_elements, 4);
+        assert_eq!(metrics.max_absolute, 1.0);
+        assert_eq!(metrics.mean_absolute, 0.5);
+        assert_eq!(metrics.num_elements_relative, 3);
+        assert_eq!(metrics.max_relative, 0.25);
+        assert_eq!(metrics.mean_relative, 0.5 / 3.0);
+
+        let merged = metrics.merge(&ErrorMetrics::from_elements(&[10u8, 20], &[12u8, 20]));
+        assert_eq!(merged.num_elements, 6);
+        assert_eq!(merged.max_absolute, 2.0);
+        assert_eq!(merged.mean_absolute, 4.0 / 6.0);
+        assert_eq!(merged.num_elements_relative, 5);
+        assert_eq!(merged.max_relative, 0.25);
+        assert!((merged.mean_relative - 0.7 / 5.0).abs() < 1e-12);
+
+        let tolerance = ErrorTolerance {
+            max_absolute: Some(2.0),
+            max_relative: None,
+        };
+        assert!(merged.exceeds(&tolerance).is_none());
+        let tolerance = ErrorTolerance {
+            max_absolute: Some(2.0),
+            max_relative: Some(0.2),
+        };
+        assert!(merged.exceeds(&tolerance).is_some());
+
+        let nan = ErrorMetrics::from_elements(&[1.0f64], &[f64::NAN]);
+        assert_eq!(nan.max_absolute, f64::INFINITY);
+    }
+}
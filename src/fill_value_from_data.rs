@@ -0,0 +1,191 @@
+//! Detection of the fill value of an array from its data (e.g. to replace the meaningless fill value of legacy data).
+
+use std::collections::HashMap;
+
+use half::{bf16, f16};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use zarrs::{
+    array::{Array, DataType, FillValue},
+    array_subset::ArraySubset,
+    storage::ReadableStorageTraits,
+};
+
+use crate::sample_chunk_indices;
+
+/// The method of detecting a fill value from the data of an array.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillValueFromData {
+    /// The most common value of the corner elements of the array.
+    Corners,
+    /// The most common element of the chunks of the array.
+    Mode,
+}
+
+/// The number of occurrences of each element, keyed by the element bytes.
+type ElementCounts = HashMap<Vec<u8>, usize>;
+
+/// Replace NaN components of the `element` of `data_type` with a canonical NaN, so that all NaN elements are counted as one value.
+fn canonicalise_nan(data_type: &DataType, element: &mut [u8]) {
+    macro_rules! canonicalise {
+        ( $t:ty ) => {{
+            for component in element.chunks_exact_mut(std::mem::size_of::<$t>()) {
+                if <$t>::from_ne_bytes(component.try_into().unwrap()).is_nan() {
+                    component.copy_from_slice(&<$t>::NAN.to_ne_bytes());
+                }
+            }
+        }};
+    }
+    match data_type {
+        DataType::Float16 => canonicalise!(f16),
+        DataType::BFloat16 => canonicalise!(bf16),
+        DataType::Float32 | DataType::Complex64 => canonicalise!(f32),
+        DataType::Float64 | DataType::Complex128 => canonicalise!(f64),
+        _ => {}
+    }
+}
+
+/// Count the elements of `bytes` with the fixed size `data_type`.
+fn count_elements(data_type: &DataType, bytes: &[u8], counts: &mut ElementCounts) {
+    let element_size = data_type.fixed_size().unwrap();
+    for element in bytes.chunks_exact(element_size) {
+        let mut element = element.to_vec();
+        canonicalise_nan(data_type, &mut element);
+        *counts.entry(element).or_insert(0) += 1;
+    }
+}
+
+/// Returns the most common element of `counts`, with ties broken by the smallest element bytes.
+fn most_common(counts: ElementCounts) -> Option<FillValue> {
+    counts
+        .into_iter()
+        .max_by(|(element_a, count_a), (element_b, count_b)| {
+            count_a.cmp(count_b).then(element_b.cmp(element_a))
+        })
+        .map(|(element, _)| FillValue::new(element))
+}
+
+/// Detect a fill value of `array` from its data.
+///
+/// With [`FillValueFromData::Mode`], only `sample` randomly chosen chunks are scanned if set, with a fixed seed so that the detected fill value is reproducible.
+/// Chunks that do not exist are skipped, since they do not hold data.
+/// NaN elements are counted as one value.
+///
+/// Returns [`None`] if no elements were scanned (e.g. the array is empty, or no chunks exist).
+///
+/// # Errors
+/// Returns an error if the data type does not have a fixed size or the array cannot be read.
+pub fn fill_value_from_data<TStorage: ?Sized + ReadableStorageTraits + 'static>(
+    array: &Array<TStorage>,
+    method: FillValueFromData,
+    sample: Option<usize>,
+) -> anyhow::Result<Option<FillValue>> {
+    let data_type = array.data_type();
+    if data_type.fixed_size().is_none() {
+        anyhow::bail!(
+            "detecting a fill value is not supported for the variable sized data type {data_type}"
+        );
+    }
+    if array.shape().contains(&0) {
+        return Ok(None);
+    }
+
+    match method {
+        FillValueFromData::Corners => {
+            let dimensionality = array.dimensionality();
+            let mut counts = ElementCounts::new();
+            for corner in 0..(1usize << dimensionality) {
+                let start = array
+                    .shape()
+                    .iter()
+                    .enumerate()
+                    .map(|(axis, size)| {
+                        if (corner >> axis) & 1 == 1 {
+                            size - 1
+                        } else {
+                            0
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let subset = ArraySubset::new_with_start_shape(start, vec![1; dimensionality])?;
+                let bytes = array.retrieve_array_subset(&subset)?;
+                count_elements(data_type, &bytes.into_fixed().unwrap(), &mut counts);
+            }
+            Ok(most_common(counts))
+        }
+        FillValueFromData::Mode => {
+            let chunks = ArraySubset::new_with_shape(array.chunk_grid_shape().unwrap());
+            let chunk_indices = if let Some(sample) = sample {
+                sample_chunk_indices(&chunks, sample, &mut fastrand::Rng::with_seed(0))
+            } else {
+                chunks.indices().into_iter().collect::<Vec<_>>()
+            };
+            let counts = chunk_indices
+                .into_par_iter()
+                .map(|chunk_indices| -> anyhow::Result<ElementCounts> {
+                    let mut counts = ElementCounts::new();
+                    let Some(bytes) = array.retrieve_chunk_if_exists(&chunk_indices)? else {
+                        return Ok(counts);
+                    };
+                    let chunk_subset = array.chunk_subset(&chunk_indices)?;
+                    let chunk_subset_bounded = array.chunk_subset_bounded(&chunk_indices)?;
+                    let bytes = if chunk_subset == chunk_subset_bounded {
+                        bytes
+                    } else {
+                        // Exclude the elements of an edge chunk outside of the array
+                        array.retrieve_array_subset(&chunk_subset_bounded)?
+                    };
+                    count_elements(data_type, &bytes.into_fixed().unwrap(), &mut counts);
+                    Ok(counts)
+                })
+                .try_reduce(ElementCounts::new, |mut counts_a, counts_b| {
+                    for (element, count) in counts_b {
+                        *counts_a.entry(element).or_insert(0) += count;
+                    }
+                    Ok(counts_a)
+                })?;
+            Ok(most_common(counts))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zarrs::{array::ArrayBuilder, storage::store::MemoryStore};
+
+    use super::*;
+
+    #[test]
+    fn fill_value_from_data_methods() -> Result<(), Box<dyn std::error::Error>> {
+        let array = ArrayBuilder::new(
+            vec![4, 4],
+            DataType::Float32,
+            vec![2, 2].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        #[rustfmt::skip]
+        let elements = [
+            f32::NAN, 1.0, 1.0, -f32::NAN,
+            1.0,      1.0, 1.0, 1.0,
+            2.0,      2.0, 1.0, 1.0,
+            f32::NAN, 2.0, 1.0, 3.0,
+        ];
+        array.store_array_subset_elements::<f32>(&array.subset_all(), &elements)?;
+
+        let fill_value = fill_value_from_data(&array, FillValueFromData::Corners, None)?.unwrap();
+        assert!(f32::from_ne_bytes(fill_value.as_ne_bytes().try_into()?).is_nan());
+
+        let fill_value = fill_value_from_data(&array, FillValueFromData::Mode, None)?.unwrap();
+        assert_eq!(fill_value, FillValue::from(1.0f32));
+
+        // Chunks that do not exist are skipped
+        array.store_array_subset_elements::<f32>(&array.subset_all(), &[0.0; 16])?;
+        assert_eq!(
+            fill_value_from_data(&array, FillValueFromData::Mode, Some(2))?,
+            None
+        );
+        Ok(())
+    }
+}
@@ -1,55 +1,98 @@
 mod array_subset_overlap;
+mod broadcast_array;
+mod element_conversion;
 mod filter_arguments;
 mod filter_command;
 mod filter_common_arguments;
 mod filter_error;
 mod filter_input_output_arguments;
+mod filter_table;
 mod filter_traits;
 mod kernel;
+mod missing_data;
 mod path_or_identifier;
 mod path_or_temp_path;
+mod run_config;
 // mod chunk_cache;
 
 pub mod filters {
+    pub mod add_noise;
+    pub mod auto_rescale;
+    pub mod cast;
     pub mod clamp;
+    pub mod complex_to_real;
     pub mod crop;
+    pub mod destripe;
     pub mod downsample;
     pub mod equal;
+    pub mod flat_field;
+    #[cfg(feature = "fft")]
+    pub mod frequency_filter;
     pub mod gaussian;
+    pub mod generate;
+    pub mod gradient;
     pub mod gradient_magnitude;
     pub mod guided_filter;
+    pub mod local_maxima;
+    pub mod lut;
+    pub mod mask;
+    pub mod normalise_slices;
     pub mod reencode;
+    pub mod relabel_sequential;
+    pub mod remap_labels;
     pub mod replace_value;
     pub mod rescale;
+    pub mod slice;
+    pub mod stitch;
+    pub mod string_transform;
     pub mod summed_area_table;
+    pub mod vesselness;
+    pub mod watershed;
 }
 
 pub use array_subset_overlap::ArraySubsetOverlap;
+pub use broadcast_array::BroadcastArray;
+pub use element_conversion::{
+    retrieve_elements_as, retrieve_ndarray_as, store_elements_as, store_ndarray_as, WorkingElement,
+};
 pub use filter_arguments::FilterArguments;
 pub use filter_command::{FilterCommand, FilterCommandTraits};
 pub use filter_common_arguments::FilterCommonArguments;
 pub use filter_error::FilterError;
-pub use filter_input_output_arguments::FilterInputOutputArguments;
+pub use filter_input_output_arguments::{
+    FilterInputOutputArguments, FilterInputTableArguments, FilterOutputArguments,
+};
+pub use filter_table::FilterTable;
 pub use filter_traits::FilterTraits;
+pub use missing_data::MissingData;
 pub use path_or_identifier::PathOrIdentifier;
 pub use path_or_temp_path::PathOrTempPath;
+pub use run_config::{load_run_config, run_config_schema};
 // pub use chunk_cache::{ChunkCache, retrieve_array_subset_ndarray_cached};
 
-use sysinfo::{MemoryRefreshKind, RefreshKind, System};
+use crate::MemoryBudget;
 
-/// Calculates the chunk limit based on the amount of available memory.
-pub fn calculate_chunk_limit(memory_per_chunk: usize) -> Result<usize, FilterError> {
-    let system = System::new_with_specifics(
-        RefreshKind::new().with_memory(MemoryRefreshKind::new().with_ram()),
-    );
-    let available_memory = usize::try_from(system.available_memory()).unwrap();
-    let available_memory_target = available_memory * 8 / 10; // 80%
-    let chunk_limit = available_memory_target / memory_per_chunk;
-    if chunk_limit == 0 {
-        Err(FilterError::Other(
-            "There is not enough available memory to process a single output chunk. Consider reducing the chunk shape (or shard shape if sharding)".to_string(),
-        ))
-    } else {
-        Ok(chunk_limit)
-    }
+/// Returns the chunk limit, which defaults to the number of threads in the rayon thread pool.
+///
+/// Chunks are admitted by their memory with [`try_for_each_chunk`], so the chunk limit only bounds the number of chunks in flight.
+#[must_use]
+pub fn calculate_chunk_limit(chunk_limit: Option<usize>) -> usize {
+    chunk_limit
+        .unwrap_or_else(rayon::current_num_threads)
+        .max(1)
+}
+
+/// Apply `op` to each of `items` with at most `chunk_limit` items in flight.
+///
+/// Each item is admitted once `memory_per_chunk` bytes fit within the shared memory budget (see [`MemoryBudget::try_for_each`]).
+///
+/// # Errors
+/// Returns the first error returned by `op`.
+pub fn try_for_each_chunk<T: Send, E: Send>(
+    chunk_limit: usize,
+    memory_per_chunk: usize,
+    items: impl IntoIterator<Item = T>,
+    op: impl Fn(T) -> Result<(), E> + Sync,
+) -> Result<(), E> {
+    MemoryBudget::global().try_for_each(chunk_limit, items, |_| memory_per_chunk, op)
 }
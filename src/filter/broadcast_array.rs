@@ -0,0 +1,149 @@
+use ndarray::{ArrayD, IxDyn};
+use zarrs::{array::Array, array_subset::ArraySubset, filesystem::FilesystemStore};
+
+use super::{filter_error::FilterError, filters::local_maxima::retrieve_values};
+
+/// A secondary input array broadcast over the axes of an input array (e.g. a 2D reference image over each slice of a 3D stack).
+///
+/// Each axis of the secondary array corresponds to an axis of the input, and must have the size of that axis or a size of 1.
+/// Input axes without a corresponding axis are broadcast.
+pub struct BroadcastArray {
+    array: Array<FilesystemStore>,
+    axes: Option<Vec<usize>>,
+}
+
+impl BroadcastArray {
+    /// Create a new broadcast array.
+    ///
+    /// `axes` are the input axes of each axis of `array`, which default to the trailing input axes.
+    pub fn new(array: Array<FilesystemStore>, axes: Option<Vec<usize>>) -> Self {
+        Self { array, axes }
+    }
+
+    /// Returns the array.
+    pub fn array(&self) -> &Array<FilesystemStore> {
+        &self.array
+    }
+
+    /// Returns the input axes of each axis of the array given the input dimensionality.
+    fn axes(&self, dimensionality: usize) -> Vec<usize> {
+        self.axes.clone().unwrap_or_else(|| {
+            let offset = dimensionality.saturating_sub(self.array.dimensionality());
+            (offset..dimensionality).collect()
+        })
+    }
+
+    /// Check that the array can be broadcast to `shape`.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the axes are invalid or the array shape is incompatible with `shape`.
+    pub fn check(&self, shape: &[u64]) -> Result<(), FilterError> {
+        let axes = self.axes(shape.len());
+        let shape_array = self.array.shape();
+        if axes.len() != shape_array.len()
+            || axes.windows(2).any(|axes| axes[0] >= axes[1])
+            || axes.last().is_some_and(|axis| *axis >= shape.len())
+        {
+            return Err(FilterError::InvalidParameters(format!(
+                "the axes {axes:?} of an array with shape {shape_array:?} must be increasing input axes of an input with shape {shape:?}"
+            )));
+        }
+        for (axis, size) in std::iter::zip(&axes, shape_array) {
+            if *size != 1 && *size != shape[*axis] {
+                return Err(FilterError::InvalidParameters(format!(
+                    "an array with shape {shape_array:?} cannot be broadcast to shape {shape:?} with axes {axes:?}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Retrieve the values of the array broadcast to `subset` of the input.
+    ///
+    /// The array must be [checked](BroadcastArray::check) against the input shape.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the array cannot be read or has an unsupported data type.
+    pub fn retrieve_values(&self, subset: &ArraySubset) -> Result<Vec<f64>, FilterError> {
+        let axes = self.axes(subset.dimensionality());
+        let shape_array = self.array.shape();
+        let ranges = subset.to_ranges();
+        let subset_array = ArraySubset::new_with_ranges(
+            &std::iter::zip(&axes, shape_array)
+                .map(|(axis, size)| {
+                    if *size == 1 {
+                        0..1
+                    } else {
+                        ranges[*axis].clone()
+                    }
+                })
+                .collect::<Vec<_>>(),
+        );
+        let values = retrieve_values(&self.array, &subset_array)?;
+
+        // Insert broadcast axes and broadcast to the shape of the subset
+        let mut shape_values = vec![1; subset.dimensionality()];
+        for (axis, size) in std::iter::zip(&axes, subset_array.shape()) {
+            shape_values[*axis] = usize::try_from(*size).unwrap();
+        }
+        let values = ArrayD::from_shape_vec(shape_values, values).unwrap();
+        let shape = subset
+            .shape()
+            .iter()
+            .map(|size| usize::try_from(*size).unwrap())
+            .collect::<Vec<_>>();
+        Ok(values
+            .broadcast(IxDyn(&shape))
+            .unwrap()
+            .iter()
+            .copied()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, sync::Arc};
+
+    use zarrs::array::{ArrayBuilder, DataType, FillValue};
+
+    use super::*;
+
+    #[test]
+    fn broadcast_array() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![1, 3],
+            DataType::UInt8,
+            vec![1, 3].try_into()?,
+            FillValue::from(0u8),
+        )
+        .build(store, "/")?;
+        array.store_array_subset_elements::<u8>(&array.subset_all(), &[1, 2, 3])?;
+
+        // Trailing axes
+        let broadcast = BroadcastArray::new(array, None);
+        broadcast.check(&[2, 4, 3])?;
+        assert!(broadcast.check(&[2, 4, 4]).is_err());
+        let subset = ArraySubset::new_with_ranges(&[0..2, 1..3, 1..3]);
+        assert_eq!(
+            broadcast.retrieve_values(&subset)?,
+            [2.0, 3.0, 2.0, 3.0, 2.0, 3.0, 2.0, 3.0]
+        );
+
+        // Explicit axes
+        let broadcast = BroadcastArray::new(broadcast.array, Some(vec![0, 1]));
+        broadcast.check(&[5, 3, 2])?;
+        assert!(broadcast.check(&[5, 2, 2]).is_err());
+        let subset = ArraySubset::new_with_ranges(&[0..1, 0..3, 0..2]);
+        assert_eq!(
+            broadcast.retrieve_values(&subset)?,
+            [1.0, 1.0, 2.0, 2.0, 3.0, 3.0]
+        );
+        assert!(BroadcastArray::new(broadcast.array, Some(vec![1, 0]))
+            .check(&[3, 3])
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+use ndarray::ArrayD;
+use num_traits::AsPrimitive;
+use zarrs::{
+    array::{
+        codec::CodecOptions, data_type::UnsupportedDataTypeError, Array, ArrayError, DataType,
+        Element,
+    },
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use super::filter_error::FilterError;
+
+/// A working element type of a filter (i.e. [`f32`] or [`f64`]).
+///
+/// Elements of any numeric (or bool) data type are converted to and from the working type as they are retrieved and stored, with `as` semantics (and any non-zero value is stored as `true`).
+/// A filter that computes in a working type is instantiated once per working type, rather than once per combination of input and output data types.
+pub trait WorkingElement: Copy + Send + Sync + 'static {
+    /// Retrieve the elements of `subset` of `array` converted to the working type.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the data type of `array` is not supported or the elements cannot be retrieved.
+    fn retrieve(
+        array: &Array<FilesystemStore>,
+        subset: &ArraySubset,
+    ) -> Result<Vec<Self>, FilterError>;
+
+    /// Convert `elements` to the data type of `array` and store them in `subset`.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the data type of `array` is not supported or the elements cannot be stored.
+    fn store(
+        array: &Array<FilesystemStore>,
+        subset: &ArraySubset,
+        elements: &[Self],
+        options: &CodecOptions,
+    ) -> Result<(), FilterError>;
+}
+
+fn retrieve_as<TIn, T>(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+) -> Result<Vec<T>, FilterError>
+where
+    TIn: bytemuck::Pod + AsPrimitive<T>,
+    T: Copy + 'static,
+{
+    // Convert straight from the decoded bytes, rather than through an intermediate Vec<TIn>
+    let bytes = array
+        .retrieve_array_subset(subset)?
+        .into_fixed()
+        .map_err(ArrayError::from)?;
+    Ok(match bytemuck::try_cast_slice::<u8, TIn>(&bytes) {
+        Ok(elements) => elements.iter().map(|value| value.as_()).collect(),
+        Err(_) => bytes
+            .chunks_exact(std::mem::size_of::<TIn>())
+            .map(|value| bytemuck::pod_read_unaligned::<TIn>(value).as_())
+            .collect(),
+    })
+}
+
+fn store_as<T, TOut>(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+    elements: &[T],
+    options: &CodecOptions,
+) -> Result<(), FilterError>
+where
+    T: AsPrimitive<TOut>,
+    TOut: Element + Copy + 'static,
+{
+    let elements = elements.iter().map(|value| value.as_()).collect::<Vec<_>>();
+    Ok(array.store_array_subset_elements_opt::<TOut>(subset, &elements, options)?)
+}
+
+macro_rules! impl_working_element {
+    ( $t:ty ) => {
+        impl WorkingElement for $t {
+            fn retrieve(
+                array: &Array<FilesystemStore>,
+                subset: &ArraySubset,
+            ) -> Result<Vec<Self>, FilterError> {
+                match array.data_type() {
+                    DataType::Bool | DataType::UInt8 => retrieve_as::<u8, Self>(array, subset),
+                    DataType::Int8 => retrieve_as::<i8, Self>(array, subset),
+                    DataType::Int16 => retrieve_as::<i16, Self>(array, subset),
+                    DataType::Int32 => retrieve_as::<i32, Self>(array, subset),
+                    DataType::Int64 => retrieve_as::<i64, Self>(array, subset),
+                    DataType::UInt16 => retrieve_as::<u16, Self>(array, subset),
+                    DataType::UInt32 => retrieve_as::<u32, Self>(array, subset),
+                    DataType::UInt64 => retrieve_as::<u64, Self>(array, subset),
+                    DataType::BFloat16 => retrieve_as::<half::bf16, Self>(array, subset),
+                    DataType::Float16 => retrieve_as::<half::f16, Self>(array, subset),
+                    DataType::Float32 => retrieve_as::<f32, Self>(array, subset),
+                    DataType::Float64 => retrieve_as::<f64, Self>(array, subset),
+                    data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+                }
+            }
+
+            fn store(
+                array: &Array<FilesystemStore>,
+                subset: &ArraySubset,
+                elements: &[Self],
+                options: &CodecOptions,
+            ) -> Result<(), FilterError> {
+                match array.data_type() {
+                    DataType::Bool => {
+                        let elements = elements
+                            .iter()
+                            .map(|value| *value != 0.0)
+                            .collect::<Vec<_>>();
+                        Ok(array
+                            .store_array_subset_elements_opt::<bool>(subset, &elements, options)?)
+                    }
+                    DataType::Int8 => store_as::<Self, i8>(array, subset, elements, options),
+                    DataType::Int16 => store_as::<Self, i16>(array, subset, elements, options),
+                    DataType::Int32 => store_as::<Self, i32>(array, subset, elements, options),
+                    DataType::Int64 => store_as::<Self, i64>(array, subset, elements, options),
+                    DataType::UInt8 => store_as::<Self, u8>(array, subset, elements, options),
+                    DataType::UInt16 => store_as::<Self, u16>(array, subset, elements, options),
+                    DataType::UInt32 => store_as::<Self, u32>(array, subset, elements, options),
+                    DataType::UInt64 => store_as::<Self, u64>(array, subset, elements, options),
+                    DataType::BFloat16 => {
+                        store_as::<Self, half::bf16>(array, subset, elements, options)
+                    }
+                    DataType::Float16 => {
+                        store_as::<Self, half::f16>(array, subset, elements, options)
+                    }
+                    DataType::Float32 => store_as::<Self, f32>(array, subset, elements, options),
+                    DataType::Float64 => store_as::<Self, f64>(array, subset, elements, options),
+                    data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+                }
+            }
+        }
+    };
+}
+
+impl_working_element!(f32);
+impl_working_element!(f64);
+
+/// Retrieve the elements of `subset` of `array` converted to the working type `T`.
+///
+/// # Errors
+/// Returns a [`FilterError`] if the data type of `array` is not supported or the elements cannot be retrieved.
+pub fn retrieve_elements_as<T: WorkingElement>(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+) -> Result<Vec<T>, FilterError> {
+    T::retrieve(array, subset)
+}
+
+/// Retrieve `subset` of `array` as an [`ArrayD`] of the working type `T`.
+///
+/// # Errors
+/// Returns a [`FilterError`] if the data type of `array` is not supported or the elements cannot be retrieved.
+pub fn retrieve_ndarray_as<T: WorkingElement>(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+) -> Result<ArrayD<T>, FilterError> {
+    let elements = T::retrieve(array, subset)?;
+    Ok(ArrayD::from_shape_vec(subset.shape_usize(), elements).unwrap())
+}
+
+/// Convert `elements` of the working type `T` to the data type of `array` and store them in `subset`.
+///
+/// # Errors
+/// Returns a [`FilterError`] if the data type of `array` is not supported or the elements cannot be stored.
+pub fn store_elements_as<T: WorkingElement>(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+    elements: &[T],
+    options: &CodecOptions,
+) -> Result<(), FilterError> {
+    T::store(array, subset, elements, options)
+}
+
+/// Convert an [`ArrayD`] of the working type `T` to the data type of `array` and store it at `subset_start`.
+///
+/// # Errors
+/// Returns a [`FilterError`] if the data type of `array` is not supported or the elements cannot be stored.
+pub fn store_ndarray_as<T: WorkingElement>(
+    array: &Array<FilesystemStore>,
+    subset_start: &[u64],
+    ndarray: &ArrayD<T>,
+    options: &CodecOptions,
+) -> Result<(), FilterError> {
+    let subset = ArraySubset::new_with_ranges(
+        &std::iter::zip(subset_start, ndarray.shape())
+            .map(|(start, size)| *start..start + *size as u64)
+            .collect::<Vec<_>>(),
+    );
+    let ndarray = ndarray.as_standard_layout();
+    T::store(array, &subset, ndarray.as_slice().unwrap(), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use super::*;
+
+    #[test]
+    fn element_conversion() -> Result<(), Box<dyn std::error::Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = Arc::new(FilesystemStore::new(path.path())?);
+        let uint8 = ArrayBuilder::new(
+            vec![2, 2],
+            DataType::UInt8,
+            vec![2, 2].try_into()?,
+            FillValue::from(0u8),
+        )
+        .build(store.clone(), "/uint8")?;
+        let boolean = ArrayBuilder::new(
+            vec![2, 2],
+            DataType::Bool,
+            vec![2, 2].try_into()?,
+            FillValue::from(false),
+        )
+        .build(store.clone(), "/bool")?;
+        let subset = uint8.subset_all();
+        let options = CodecOptions::default();
+
+        // Float to integer conversion saturates and truncates
+        store_elements_as::<f64>(&uint8, &subset, &[-1.0, 1.5, 255.0, 300.0], &options)?;
+        assert_eq!(
+            uint8.retrieve_array_subset_elements::<u8>(&subset)?,
+            [0, 1, 255, 255]
+        );
+        assert_eq!(
+            retrieve_elements_as::<f32>(&uint8, &subset)?,
+            [0.0, 1.0, 255.0, 255.0]
+        );
+
+        let ndarray = ndarray::array![[0.0f32, 2.0], [0.5, 1.0]].into_dyn();
+        store_ndarray_as(&boolean, &[0, 0], &ndarray.t().to_owned(), &options)?;
+        assert_eq!(
+            boolean.retrieve_array_subset_elements::<bool>(&subset)?,
+            [false, true, true, true]
+        );
+        assert_eq!(
+            retrieve_ndarray_as::<f64>(&boolean, &subset)?,
+            ndarray::array![[0.0, 1.0], [1.0, 1.0]].into_dyn()
+        );
+
+        // Any non-zero value (including NaN) is true
+        store_elements_as::<f32>(&boolean, &subset, &[f32::NAN, -0.5, 0.0, -0.0], &options)?;
+        assert_eq!(
+            boolean.retrieve_array_subset_elements::<bool>(&subset)?,
+            [true, true, false, false]
+        );
+        Ok(())
+    }
+}
@@ -3,6 +3,11 @@ use super::{FilterCommonArguments, FilterError, FilterTraits};
 pub trait FilterArguments {
     fn name(&self) -> String;
 
+    /// Returns true if the filter has a stochastic component, which uses the seed of the common arguments.
+    fn is_stochastic(&self) -> bool {
+        false
+    }
+
     fn init(
         &self,
         common_args: &FilterCommonArguments,
@@ -1,13 +1,15 @@
 use clap::{Parser, Subcommand};
 use enum_dispatch::enum_dispatch;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::{
     filter_error::FilterError, filter_traits::FilterTraits, filters, FilterArguments,
-    FilterCommonArguments, FilterInputOutputArguments,
+    FilterCommonArguments, FilterInputOutputArguments, FilterInputTableArguments,
+    FilterOutputArguments,
 };
 
-#[derive(Debug, Clone, Parser, Deserialize)]
+#[derive(Debug, Clone, Parser, Deserialize, JsonSchema)]
 pub struct FilterCombinedArgs<TArgs: FilterArguments + Serialize + clap::Args> {
     #[command(flatten)]
     #[serde(flatten)]
@@ -20,6 +22,34 @@ pub struct FilterCombinedArgs<TArgs: FilterArguments + Serialize + clap::Args> {
     common_args: FilterCommonArguments,
 }
 
+/// The arguments of a generator filter, which has no input array.
+#[derive(Debug, Clone, Parser, Deserialize, JsonSchema)]
+pub struct FilterGeneratorCombinedArgs<TArgs: FilterArguments + Serialize + clap::Args> {
+    #[command(flatten)]
+    #[serde(flatten)]
+    output: FilterOutputArguments,
+    #[command(flatten)]
+    #[serde(flatten)]
+    args: TArgs,
+    #[command(flatten)]
+    #[serde(flatten)]
+    common_args: FilterCommonArguments,
+}
+
+/// The arguments of an analysis filter, which outputs a table rather than an array.
+#[derive(Debug, Clone, Parser, Deserialize, JsonSchema)]
+pub struct FilterAnalysisCombinedArgs<TArgs: FilterArguments + Serialize + clap::Args> {
+    #[command(flatten)]
+    #[serde(flatten)]
+    input_table: FilterInputTableArguments,
+    #[command(flatten)]
+    #[serde(flatten)]
+    args: TArgs,
+    #[command(flatten)]
+    #[serde(flatten)]
+    common_args: FilterCommonArguments,
+}
+
 #[enum_dispatch]
 pub trait FilterCommandTraits {
     fn name(&self) -> String;
@@ -27,7 +57,17 @@ pub trait FilterCommandTraits {
     fn reencode_str(&self) -> String {
         serde_json::to_string(&self.common_args().reencode()).unwrap()
     }
-    fn io_args(&self) -> &FilterInputOutputArguments;
+    fn io_args(&self) -> FilterInputOutputArguments;
+    /// Returns true if the filter is a generator, which has no input array.
+    fn is_generator(&self) -> bool {
+        false
+    }
+    /// Returns true if the filter is an analysis filter, which outputs a table rather than an array.
+    fn is_analysis(&self) -> bool {
+        false
+    }
+    /// Returns true if the filter has a stochastic component, which uses the seed of the common arguments.
+    fn is_stochastic(&self) -> bool;
     fn common_args(&self) -> &FilterCommonArguments;
     fn common_args_mut(&mut self) -> &mut FilterCommonArguments;
     fn init(&self) -> Result<Box<dyn FilterTraits>, FilterError>;
@@ -44,8 +84,87 @@ impl<TArgs: FilterArguments + Serialize + clap::Args> FilterCommandTraits
         serde_json::to_string(&self.args).unwrap()
     }
 
-    fn io_args(&self) -> &FilterInputOutputArguments {
-        &self.input_output
+    fn is_stochastic(&self) -> bool {
+        self.args.is_stochastic()
+    }
+
+    fn io_args(&self) -> FilterInputOutputArguments {
+        self.input_output.clone()
+    }
+
+    fn common_args(&self) -> &FilterCommonArguments {
+        &self.common_args
+    }
+
+    fn common_args_mut(&mut self) -> &mut FilterCommonArguments {
+        &mut self.common_args
+    }
+
+    fn init(&self) -> Result<Box<dyn FilterTraits>, FilterError> {
+        self.args.init(&self.common_args)
+    }
+}
+
+impl<TArgs: FilterArguments + Serialize + clap::Args> FilterCommandTraits
+    for FilterGeneratorCombinedArgs<TArgs>
+{
+    fn name(&self) -> String {
+        self.args.name()
+    }
+
+    fn args_str(&self) -> String {
+        serde_json::to_string(&self.args).unwrap()
+    }
+
+    fn is_stochastic(&self) -> bool {
+        self.args.is_stochastic()
+    }
+
+    fn io_args(&self) -> FilterInputOutputArguments {
+        FilterInputOutputArguments::new(None, self.output.output().clone())
+    }
+
+    fn is_generator(&self) -> bool {
+        true
+    }
+
+    fn common_args(&self) -> &FilterCommonArguments {
+        &self.common_args
+    }
+
+    fn common_args_mut(&mut self) -> &mut FilterCommonArguments {
+        &mut self.common_args
+    }
+
+    fn init(&self) -> Result<Box<dyn FilterTraits>, FilterError> {
+        self.args.init(&self.common_args)
+    }
+}
+
+impl<TArgs: FilterArguments + Serialize + clap::Args> FilterCommandTraits
+    for FilterAnalysisCombinedArgs<TArgs>
+{
+    fn name(&self) -> String {
+        self.args.name()
+    }
+
+    fn args_str(&self) -> String {
+        serde_json::to_string(&self.args).unwrap()
+    }
+
+    fn is_stochastic(&self) -> bool {
+        self.args.is_stochastic()
+    }
+
+    fn io_args(&self) -> FilterInputOutputArguments {
+        FilterInputOutputArguments::new(
+            self.input_table.input().clone(),
+            self.input_table.output().clone(),
+        )
+    }
+
+    fn is_analysis(&self) -> bool {
+        true
     }
 
     fn common_args(&self) -> &FilterCommonArguments {
@@ -61,7 +180,7 @@ impl<TArgs: FilterArguments + Serialize + clap::Args> FilterCommandTraits
     }
 }
 
-#[derive(Debug, Clone, Subcommand, Deserialize)]
+#[derive(Debug, Clone, Subcommand, Deserialize, JsonSchema)]
 #[serde(tag = "filter", rename_all = "snake_case")]
 #[enum_dispatch(FilterCommandTraits)]
 pub enum FilterCommand {
@@ -69,16 +188,24 @@ pub enum FilterCommand {
     Reencode(FilterCombinedArgs<filters::reencode::ReencodeArguments>),
     /// Crop an array given an offset and shape.
     Crop(FilterCombinedArgs<filters::crop::CropArguments>),
+    /// Slice an array with optional steps and axis removal.
+    Slice(FilterCombinedArgs<filters::slice::SliceArguments>),
     /// Rescale array values given a multiplier and offset.
     Rescale(FilterCombinedArgs<filters::rescale::RescaleArguments>),
+    /// Rescale array values from input percentiles to the range of the output data type, recording the transform in the output attributes.
+    AutoRescale(FilterCombinedArgs<filters::auto_rescale::AutoRescaleArguments>),
     /// Clamp values between a minimum and maximum.
     Clamp(FilterCombinedArgs<filters::clamp::ClampArguments>),
+    /// Apply a gamma curve or a 1D lookup table per element.
+    Lut(FilterCombinedArgs<filters::lut::LutArguments>),
     /// Return a binary image where the input is equal to some value.
     Equal(FilterCombinedArgs<filters::equal::EqualArguments>),
     /// Downsample an image given a stride.
     Downsample(FilterCombinedArgs<filters::downsample::DownsampleArguments>),
     /// Compute the gradient magnitude.
     GradientMagnitude(FilterCombinedArgs<filters::gradient_magnitude::GradientMagnitudeArguments>),
+    /// Compute the gradient (per-axis partial derivatives) in a new trailing dimension.
+    Gradient(FilterCombinedArgs<filters::gradient::GradientArguments>),
     /// Apply a Gaussian kernel.
     Gaussian(FilterCombinedArgs<filters::gaussian::GaussianArguments>),
     /// Compute a summed area table (integral image).
@@ -87,4 +214,37 @@ pub enum FilterCommand {
     GuidedFilter(FilterCombinedArgs<filters::guided_filter::GuidedFilterArguments>),
     /// Replace a value with another value.
     ReplaceValue(FilterCombinedArgs<filters::replace_value::ReplaceValueArguments>),
+    /// Compute the vesselness (Frangi or Sato tube enhancement) at one or more scales.
+    Vesselness(FilterCombinedArgs<filters::vesselness::VesselnessArguments>),
+    /// Remove stripe artefacts along an axis (e.g. light-sheet shadows).
+    Destripe(FilterCombinedArgs<filters::destripe::DestripeArguments>),
+    /// Normalise each slice along an axis independently (z-score or min-max), e.g. to correct intensity drift of a time-lapse.
+    NormaliseSlices(FilterCombinedArgs<filters::normalise_slices::NormaliseSlicesArguments>),
+    /// Apply a frequency domain (FFT) lowpass, highpass, bandpass, or bandstop filter.
+    #[cfg(feature = "fft")]
+    FrequencyFilter(FilterCombinedArgs<filters::frequency_filter::FrequencyFilterArguments>),
+    /// Generate synthetic content (constant, noise, gradient, or checkerboard) without an input array.
+    Generate(FilterGeneratorCombinedArgs<filters::generate::GenerateArguments>),
+    /// Assemble an array from overlapping tiles given a CSV of tile paths and offsets, with linear blending in overlap regions.
+    Stitch(FilterGeneratorCombinedArgs<filters::stitch::StitchArguments>),
+    /// Add uniform or normal noise to an array.
+    AddNoise(FilterCombinedArgs<filters::add_noise::AddNoiseArguments>),
+    /// Cast to the output data type with explicit rounding, overflow, and NaN policies.
+    Cast(FilterCombinedArgs<filters::cast::CastArguments>),
+    /// Remap integer labels (e.g. of a segmentation) given a CSV or JSON mapping.
+    RemapLabels(FilterCombinedArgs<filters::remap_labels::RemapLabelsArguments>),
+    /// Relabel integer labels into a compact sequential range, recording the mapping in the output attributes.
+    RelabelSequential(FilterCombinedArgs<filters::relabel_sequential::RelabelSequentialArguments>),
+    /// Set elements outside of a mask array to the fill value.
+    Mask(FilterCombinedArgs<filters::mask::MaskArguments>),
+    /// Apply a flat field correction, (input - dark) / flat, with reference arrays broadcast over the input.
+    FlatField(FilterCombinedArgs<filters::flat_field::FlatFieldArguments>),
+    /// Convert a complex array to the magnitude, phase, real part, or imaginary part.
+    ComplexToReal(FilterCombinedArgs<filters::complex_to_real::ComplexToRealArguments>),
+    /// Transform strings with a regular expression replacement and/or case conversion.
+    StringTransform(FilterCombinedArgs<filters::string_transform::StringTransformArguments>),
+    /// Seeded watershed segmentation of an intensity (e.g. gradient magnitude) array.
+    Watershed(FilterCombinedArgs<filters::watershed::WatershedArguments>),
+    /// Detect local maxima (peaks) above a threshold with a minimum separation, output as a point table.
+    LocalMaxima(FilterAnalysisCombinedArgs<filters::local_maxima::LocalMaximaArguments>),
 }
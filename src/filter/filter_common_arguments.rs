@@ -1,9 +1,10 @@
 use clap::Parser;
+use schemars::JsonSchema;
 use serde::Deserialize;
 
-use crate::ZarrReencodingArgs;
+use crate::{WriteShapeArgs, ZarrReencodingArgs};
 
-#[derive(Debug, Clone, Parser, Deserialize)]
+#[derive(Debug, Clone, Parser, Deserialize, JsonSchema)]
 pub struct FilterCommonArguments {
     /// Reencoding args.
     #[command(flatten)]
@@ -13,6 +14,18 @@ pub struct FilterCommonArguments {
     /// Inherits the top level arg if left unset.
     #[arg(long)]
     chunk_limit: Option<usize>,
+    /// The random seed of a stochastic filter (e.g. add-noise). Random if unset.
+    ///
+    /// The output is reproducible for a given seed and chunk shape.
+    /// Inherits the top level arg if left unset.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Write shape options.
+    ///
+    /// Inherits the top level arg if left unset.
+    #[command(flatten)]
+    #[serde(flatten)]
+    write: WriteShapeArgs,
 }
 
 impl FilterCommonArguments {
@@ -27,4 +40,20 @@ impl FilterCommonArguments {
     pub fn chunk_limit_mut(&mut self) -> &mut Option<usize> {
         &mut self.chunk_limit
     }
+
+    pub fn seed(&self) -> &Option<u64> {
+        &self.seed
+    }
+
+    pub fn seed_mut(&mut self) -> &mut Option<u64> {
+        &mut self.seed
+    }
+
+    pub fn write(&self) -> &WriteShapeArgs {
+        &self.write
+    }
+
+    pub fn write_mut(&mut self) -> &mut WriteShapeArgs {
+        &mut self.write
+    }
 }
@@ -7,6 +7,12 @@ use zarrs::{
     storage::StorageError,
 };
 
+use crate::{
+    cast::{ElementCastError, FillValueConversionError},
+    progress::Cancelled,
+    ArrayBuilderReencodeError,
+};
+
 #[derive(Debug, Error)]
 pub enum FilterError {
     #[error(transparent)]
@@ -24,7 +30,15 @@ pub enum FilterError {
     #[error(transparent)]
     IncompatibleFillValue(#[from] IncompatibleFillValueMetadataError),
     #[error(transparent)]
+    FillValueConversion(#[from] FillValueConversionError),
+    #[error(transparent)]
+    ElementCast(#[from] ElementCastError),
+    #[error(transparent)]
+    ArrayBuilderReencode(#[from] ArrayBuilderReencodeError),
+    #[error(transparent)]
     IOError(#[from] std::io::Error),
+    #[error("The filter was {_0}")]
+    Cancelled(#[from] Cancelled),
     #[error("{_0}")]
     Other(String),
 }
@@ -1,19 +1,57 @@
 use clap::Parser;
+use schemars::JsonSchema;
 use serde::Deserialize;
 
 use super::path_or_identifier::{parse_path_or_identifier, PathOrIdentifier};
 
-#[derive(Debug, Clone, Parser, Deserialize)]
+#[derive(Debug, Clone, Parser, Deserialize, JsonSchema)]
 pub struct FilterInputOutputArguments {
-    /// Path to zarr input array.
+    /// Path to zarr input array, optionally with a node path suffix (e.g. input.zarr#/0).
     #[arg(required = true, value_parser = parse_path_or_identifier)]
     input: Option<PathOrIdentifier>,
-    /// Path to zarr output array.
+    /// Path to zarr output array, optionally with a node path suffix (e.g. output.zarr#/labels/cells).
+    #[arg(required = true, value_parser = parse_path_or_identifier)]
+    output: Option<PathOrIdentifier>,
+}
+
+#[derive(Debug, Clone, Parser, Deserialize, JsonSchema)]
+pub struct FilterOutputArguments {
+    /// Path to zarr output array, optionally with a node path suffix (e.g. output.zarr#/labels/cells).
+    #[arg(required = true, value_parser = parse_path_or_identifier)]
+    output: Option<PathOrIdentifier>,
+}
+
+#[derive(Debug, Clone, Parser, Deserialize, JsonSchema)]
+pub struct FilterInputTableArguments {
+    /// Path to zarr input array, optionally with a node path suffix (e.g. input.zarr#/0).
+    #[arg(required = true, value_parser = parse_path_or_identifier)]
+    input: Option<PathOrIdentifier>,
+    /// Path to the output table (.csv, .json, or otherwise a zarr array).
     #[arg(required = true, value_parser = parse_path_or_identifier)]
     output: Option<PathOrIdentifier>,
 }
 
 impl FilterInputOutputArguments {
+    pub fn new(input: Option<PathOrIdentifier>, output: Option<PathOrIdentifier>) -> Self {
+        Self { input, output }
+    }
+
+    pub fn input(&self) -> &Option<PathOrIdentifier> {
+        &self.input
+    }
+
+    pub fn output(&self) -> &Option<PathOrIdentifier> {
+        &self.output
+    }
+}
+
+impl FilterOutputArguments {
+    pub fn output(&self) -> &Option<PathOrIdentifier> {
+        &self.output
+    }
+}
+
+impl FilterInputTableArguments {
     pub fn input(&self) -> &Option<PathOrIdentifier> {
         &self.input
     }
@@ -0,0 +1,198 @@
+use std::{path::Path, sync::Arc};
+
+use zarrs::{
+    array::{ArrayBuilder, DataType, FillValue},
+    filesystem::FilesystemStore,
+    storage::WritableStorageTraits,
+};
+
+use crate::{create_parent_groups, node_prefix, split_node_path};
+
+use super::FilterError;
+
+/// A table output by an analysis filter (e.g. a list of points), rather than an array.
+///
+/// A table is written as CSV (`.csv`), JSON (`.json`, an array of objects), or otherwise as a 2D `float64` Zarr array with the shape `[rows, columns]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterTable {
+    columns: Vec<String>,
+    rows: Vec<Vec<f64>>,
+}
+
+impl FilterTable {
+    /// Create a new empty table with `columns`.
+    #[must_use]
+    pub fn new(columns: Vec<String>) -> Self {
+        Self {
+            columns,
+            rows: vec![],
+        }
+    }
+
+    /// Returns the column names.
+    #[must_use]
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Returns the rows.
+    #[must_use]
+    pub fn rows(&self) -> &[Vec<f64>] {
+        &self.rows
+    }
+
+    /// Append rows.
+    ///
+    /// # Panics
+    /// Panics if the length of a row does not match the number of columns.
+    pub fn extend(&mut self, rows: impl IntoIterator<Item = Vec<f64>>) {
+        for row in rows {
+            assert_eq!(row.len(), self.columns.len());
+            self.rows.push(row);
+        }
+    }
+
+    /// Sort the rows lexicographically.
+    pub fn sort(&mut self) {
+        self.rows.sort_by(|a, b| {
+            std::iter::zip(a, b)
+                .map(|(a, b)| a.total_cmp(b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+
+    /// Returns the table as CSV with a header row.
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv = self.columns.join(",");
+        csv.push('\n');
+        for row in &self.rows {
+            csv.push_str(&row.iter().map(f64::to_string).collect::<Vec<_>>().join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Returns the table as a JSON array of objects, with a key per column.
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        self.rows
+            .iter()
+            .map(|row| {
+                std::iter::zip(&self.columns, row)
+                    .map(|(column, value)| (column.clone(), serde_json::json!(value)))
+                    .collect::<serde_json::Map<_, _>>()
+            })
+            .collect()
+    }
+
+    /// Write the table to `path`, in a format depending on its extension.
+    ///
+    /// A Zarr array `path` may have a node path suffix (e.g. `output.zarr#/tables/points`).
+    ///
+    /// The column names and `attributes` (e.g. provenance) are written to the attributes of a Zarr array, and are otherwise ignored.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the table cannot be written.
+    pub fn write(
+        &self,
+        path: &Path,
+        mut attributes: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<(), FilterError> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => std::fs::write(path, self.to_csv())?,
+            Some("json") => std::fs::write(path, serde_json::to_string_pretty(&self.to_json())?)?,
+            _ => {
+                let (store_path, node_path) = split_node_path(path);
+                let store = Arc::new(FilesystemStore::new(store_path).map_err(|err| {
+                    FilterError::Other(format!("cannot create the table {path:?}: {err}"))
+                })?);
+                store.erase_prefix(&node_prefix(&node_path))?;
+                create_parent_groups(&store, &node_path)
+                    .map_err(|err| FilterError::Other(err.to_string()))?;
+                let num_rows = self.rows.len() as u64;
+                let num_columns = self.columns.len() as u64;
+                attributes.insert("columns".to_string(), serde_json::json!(self.columns));
+                let array = ArrayBuilder::new(
+                    vec![num_rows, num_columns],
+                    DataType::Float64,
+                    vec![num_rows.max(1), num_columns.max(1)]
+                        .try_into()
+                        .unwrap(),
+                    FillValue::from(0.0f64),
+                )
+                .dimension_names(Some(["row", "column"]))
+                .attributes(attributes)
+                .build(store, &node_path)?;
+                array.store_metadata()?;
+                if num_rows > 0 {
+                    array.store_array_subset_elements::<f64>(
+                        &array.subset_all(),
+                        &self.rows.concat(),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use zarrs::{array::Array, group::Group};
+
+    use super::*;
+
+    #[test]
+    fn filter_table_write() -> Result<(), Box<dyn std::error::Error>> {
+        let mut table = FilterTable::new(vec!["y".to_string(), "x".to_string()]);
+        table.extend([vec![2.0, 1.0], vec![0.0, 3.5]]);
+        table.sort();
+        assert_eq!(table.to_csv(), "y,x\n0,3.5\n2,1\n");
+        assert_eq!(
+            table.to_json(),
+            serde_json::json!([{"y": 0.0, "x": 3.5}, {"y": 2.0, "x": 1.0}])
+        );
+
+        let path = tempfile::TempDir::new()?;
+        let path = path.path().join("table.zarr");
+        table.write(&path, serde_json::Map::new())?;
+        let array = Array::open(Arc::new(FilesystemStore::new(&path)?), "/")?;
+        assert_eq!(array.shape(), &[2, 2]);
+        assert_eq!(array.attributes()["columns"], serde_json::json!(["y", "x"]));
+        assert_eq!(
+            array.retrieve_array_subset_elements::<f64>(&array.subset_all())?,
+            vec![0.0, 3.5, 2.0, 1.0]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn filter_table_write_node_path() -> Result<(), Box<dyn std::error::Error>> {
+        let mut table = FilterTable::new(vec!["x".to_string()]);
+        table.extend([vec![1.0]]);
+
+        let path = tempfile::TempDir::new()?;
+        let store_path = path.path().join("hierarchy.zarr");
+        table.write(
+            &PathBuf::from(format!("{}#/tables/a", store_path.display())),
+            serde_json::Map::new(),
+        )?;
+        table.write(
+            &PathBuf::from(format!("{}#/tables/b", store_path.display())),
+            serde_json::Map::new(),
+        )?;
+
+        let store = Arc::new(FilesystemStore::new(&store_path)?);
+        Group::open(store.clone(), "/")?;
+        Group::open(store.clone(), "/tables")?;
+        for node_path in ["/tables/a", "/tables/b"] {
+            let array = Array::open(store.clone(), node_path)?;
+            assert_eq!(array.shape(), &[1, 1]);
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,17 @@
 use zarrs::{
-    array::{Array, ArrayBuilder, ArrayShape, ChunkRepresentation, DataType, FillValue},
+    array::{
+        Array, ArrayBuilder, ArrayShape, ArrayShardedExt, ChunkRepresentation, DataType,
+        DimensionName, FillValue,
+    },
     filesystem::FilesystemStore,
 };
 
 use crate::{
-    convert_fill_value, get_array_builder_reencode, progress::ProgressCallback, ZarrReencodingArgs,
+    array_builder_v2_to_v3, array_dimension_names, cast::convert_fill_value,
+    get_array_builder_reencode, progress::ProgressCallback, ChunkSize, ZarrReencodingArgs,
 };
 
-use super::filter_error::FilterError;
+use super::{filter_error::FilterError, FilterTable};
 
 pub trait FilterTraits {
     /// Checks if the input and output are compatible.
@@ -26,12 +30,35 @@ pub trait FilterTraits {
         chunk_output: &ChunkRepresentation,
     ) -> usize;
 
+    /// Returns true if the filter writes sharded outputs incrementally with the write shape of its common arguments.
+    ///
+    /// Otherwise, each output chunk (or shard) is written as a whole.
+    fn supports_write_shape(&self) -> bool {
+        false
+    }
+
     /// Returns an [`ArrayShape`] if the filter changes the array shape.
     #[allow(unused_variables)]
     fn output_shape(&self, array_input: &Array<FilesystemStore>) -> Option<ArrayShape> {
         None
     }
 
+    /// Returns the input axis of each output axis if the filter changes the array dimensionality.
+    ///
+    /// New output axes are [`None`].
+    /// This is used to derive the output chunk shape, shard shape, and dimension names from the input.
+    /// A filter returning axes must also return an [`output_shape`](FilterTraits::output_shape).
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the filter is incompatible with the input array.
+    #[allow(unused_variables)]
+    fn output_axes(
+        &self,
+        array_input: &Array<FilesystemStore>,
+    ) -> Result<Option<Vec<Option<usize>>>, FilterError> {
+        Ok(None)
+    }
+
     /// Returns a [`DataType`] and [`FillValue`] if the filter changes the data type.
     #[allow(unused_variables)]
     fn output_data_type(
@@ -41,11 +68,15 @@ pub trait FilterTraits {
         None
     }
 
+    /// Returns the [`ArrayBuilder`] of the output array.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the input fill value cannot be converted to the output data type.
     fn output_array_builder(
         &self,
         array_input: &Array<FilesystemStore>,
         reencoding_args: &ZarrReencodingArgs,
-    ) -> ArrayBuilder {
+    ) -> Result<ArrayBuilder, FilterError> {
         let mut reencoding_args = reencoding_args.clone();
 
         if let Some(data_type) = &reencoding_args.data_type {
@@ -58,7 +89,8 @@ pub trait FilterTraits {
                         array_input.data_type(),
                         array_input.fill_value(),
                         &data_type,
-                    )));
+                        reencoding_args.fill_value_cast.unwrap_or_default(),
+                    )?));
             }
             reencoding_args.data_type = Some(data_type.metadata());
         } else if let Some((data_type, fill_value)) = self.output_data_type(array_input) {
@@ -67,11 +99,68 @@ pub trait FilterTraits {
             reencoding_args.fill_value = Some(data_type.metadata_fill_value(&fill_value));
         }
 
-        get_array_builder_reencode(
-            &reencoding_args,
-            array_input,
-            self.output_shape(array_input),
-        )
+        let output_axes = self.output_axes(array_input)?;
+        let output_shape = self.output_shape(array_input);
+        if let (Some(output_axes), Some(output_shape), None) =
+            (&output_axes, &output_shape, &reencoding_args.chunk_grid)
+        {
+            // Map the input chunk/shard shape to the output axes if not explicitly set
+            let map_axes = |shape_input: &[u64]| {
+                std::iter::zip(output_axes, output_shape)
+                    .map(|(axis, size)| {
+                        ChunkSize::from(
+                            axis.map_or(*size, |axis| std::cmp::min(shape_input[axis], *size))
+                                .max(1),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let chunk_shape_input = array_input
+                .chunk_shape(&vec![0; array_input.dimensionality()])?
+                .to_array_shape();
+            if let Some(inner_chunk_shape_input) = array_input.inner_chunk_shape() {
+                if reencoding_args.chunk_shape.is_none() {
+                    reencoding_args.chunk_shape =
+                        Some(map_axes(&inner_chunk_shape_input.to_array_shape()));
+                }
+                if reencoding_args.shard_shape.is_none() {
+                    reencoding_args.shard_shape = Some(map_axes(&chunk_shape_input));
+                }
+            } else if reencoding_args.chunk_shape.is_none() {
+                reencoding_args.chunk_shape = Some(map_axes(&chunk_shape_input));
+            }
+        }
+
+        // Dimensions are renamed after mapping the input dimension names to the output axes
+        let rename_dim = output_axes
+            .as_ref()
+            .and_then(|_| reencoding_args.rename_dim.take());
+        let mut array_builder =
+            get_array_builder_reencode(&reencoding_args, array_input, output_shape)?;
+        array_builder_v2_to_v3(array_input, &mut array_builder, &reencoding_args);
+        reencoding_args.rename_dim = rename_dim;
+
+        if let (Some(output_axes), None) = (&output_axes, &reencoding_args.dimension_names) {
+            // Map the input dimension names to the output axes if not explicitly set
+            let dimension_names = array_dimension_names(array_input).map(|dimension_names| {
+                output_axes
+                    .iter()
+                    .map(|axis| {
+                        axis.map_or_else(DimensionName::default, |axis| {
+                            dimension_names[axis].clone()
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            });
+            let dimension_names = reencoding_args
+                .output_dimension_names(dimension_names.as_deref(), output_axes.len())?
+                .or(dimension_names);
+            if dimension_names.is_some() {
+                array_builder.dimension_names(dimension_names);
+            }
+        }
+
+        Ok(array_builder)
     }
 
     fn apply(
@@ -80,6 +169,50 @@ pub trait FilterTraits {
         output: &mut Array<FilesystemStore>,
         progress_callback: &ProgressCallback,
     ) -> Result<(), FilterError>;
+
+    /// Returns the [`ArrayBuilder`] of the output array of a generator filter, which has no input array.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the filter is not a generator or the output array builder cannot be created.
+    #[allow(unused_variables)]
+    fn generator_array_builder(
+        &self,
+        reencoding_args: &ZarrReencodingArgs,
+    ) -> Result<ArrayBuilder, FilterError> {
+        Err(FilterError::Other(
+            "the filter requires an input array".to_string(),
+        ))
+    }
+
+    /// Fill the output array of a generator filter.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the filter is not a generator or the output cannot be written.
+    #[allow(unused_variables)]
+    fn generate(
+        &self,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        Err(FilterError::Other(
+            "the filter requires an input array".to_string(),
+        ))
+    }
+
+    /// Analyse the input array of an analysis filter, which outputs a [`FilterTable`] rather than an array.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the filter is not an analysis filter or the input cannot be read.
+    #[allow(unused_variables)]
+    fn analyse(
+        &self,
+        input: &Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<FilterTable, FilterError> {
+        Err(FilterError::Other(
+            "the filter does not output a table".to_string(),
+        ))
+    }
 }
 
 impl<T: FilterTraits + ?Sized> FilterTraits for Box<T> {
@@ -112,15 +245,28 @@ impl<T: FilterTraits + ?Sized> FilterTraits for Box<T> {
         (**self).memory_per_chunk(chunk_input, chunk_output)
     }
 
+    #[inline]
+    fn supports_write_shape(&self) -> bool {
+        (**self).supports_write_shape()
+    }
+
     #[inline]
     fn output_array_builder(
         &self,
         array_input: &Array<FilesystemStore>,
         reencoding_args: &ZarrReencodingArgs,
-    ) -> ArrayBuilder {
+    ) -> Result<ArrayBuilder, FilterError> {
         (**self).output_array_builder(array_input, reencoding_args)
     }
 
+    #[inline]
+    fn output_axes(
+        &self,
+        array_input: &Array<FilesystemStore>,
+    ) -> Result<Option<Vec<Option<usize>>>, FilterError> {
+        (**self).output_axes(array_input)
+    }
+
     #[inline]
     fn output_data_type(
         &self,
@@ -133,4 +279,30 @@ impl<T: FilterTraits + ?Sized> FilterTraits for Box<T> {
     fn output_shape(&self, array_input: &Array<FilesystemStore>) -> Option<ArrayShape> {
         (**self).output_shape(array_input)
     }
+
+    #[inline]
+    fn generator_array_builder(
+        &self,
+        reencoding_args: &ZarrReencodingArgs,
+    ) -> Result<ArrayBuilder, FilterError> {
+        (**self).generator_array_builder(reencoding_args)
+    }
+
+    #[inline]
+    fn generate(
+        &self,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        (**self).generate(output, progress_callback)
+    }
+
+    #[inline]
+    fn analyse(
+        &self,
+        input: &Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<FilterTable, FilterError> {
+        (**self).analyse(input, progress_callback)
+    }
 }
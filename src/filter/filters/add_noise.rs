@@ -0,0 +1,262 @@
+use clap::{Parser, ValueEnum};
+use num_traits::AsPrimitive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{codec::CodecOptions, data_type::UnsupportedDataTypeError, Array, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        retrieve_elements_as, store_elements_as, try_for_each_chunk, FilterArguments,
+        FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+use super::generate::{chunk_rng, sample_standard_normal};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize, JsonSchema, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseDistribution {
+    /// Uniform noise between low and high.
+    Uniform,
+    /// Normal (Gaussian) noise with a mean and standard deviation.
+    #[default]
+    Normal,
+}
+
+fn default_low() -> f64 {
+    -1.0
+}
+
+fn default_high() -> f64 {
+    1.0
+}
+
+fn default_std() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct AddNoiseArguments {
+    /// The noise distribution.
+    #[arg(value_enum)]
+    pub distribution: NoiseDistribution,
+    /// The low value of uniform noise.
+    #[arg(long, default_value_t = default_low(), allow_hyphen_values(true))]
+    #[serde(default = "default_low")]
+    pub low: f64,
+    /// The high value of uniform noise.
+    #[arg(long, default_value_t = default_high(), allow_hyphen_values(true))]
+    #[serde(default = "default_high")]
+    pub high: f64,
+    /// The mean of normal noise.
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values(true))]
+    #[serde(default)]
+    pub mean: f64,
+    /// The standard deviation of normal noise.
+    #[arg(long, default_value_t = default_std())]
+    #[serde(default = "default_std")]
+    pub std: f64,
+}
+
+impl FilterArguments for AddNoiseArguments {
+    fn name(&self) -> String {
+        "add_noise".to_string()
+    }
+
+    fn is_stochastic(&self) -> bool {
+        true
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        if self.distribution == NoiseDistribution::Uniform && self.low > self.high {
+            return Err(FilterError::InvalidParameters(
+                "add noise low must not be greater than high".to_string(),
+            ));
+        }
+        if self.std < 0.0 {
+            return Err(FilterError::InvalidParameters(
+                "add noise standard deviation must not be negative".to_string(),
+            ));
+        }
+        Ok(Box::new(AddNoise::new(
+            self,
+            common_args.seed().unwrap_or_else(|| fastrand::u64(..)),
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// Add random noise to an array.
+pub struct AddNoise {
+    arguments: AddNoiseArguments,
+    seed: u64,
+    chunk_limit: Option<usize>,
+}
+
+impl AddNoise {
+    pub fn new(arguments: &AddNoiseArguments, seed: u64, chunk_limit: Option<usize>) -> Self {
+        Self {
+            arguments: arguments.clone(),
+            seed,
+            chunk_limit,
+        }
+    }
+
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        // Determine the input and output subset
+        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
+
+        let elements_in =
+            progress.read(|| retrieve_elements_as::<f64>(input, &input_output_subset))?;
+
+        let elements_out: Vec<f64> =
+            progress.process(|| self.apply_elements(&elements_in, chunk_indices));
+        drop(elements_in);
+
+        progress.write(|| {
+            store_elements_as(
+                output,
+                &input_output_subset,
+                &elements_out,
+                &CodecOptions::default(),
+            )
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+
+    /// Add noise to the elements of the chunk at `chunk_indices`.
+    pub fn apply_elements<TIn, TOut>(&self, elements_in: &[TIn], chunk_indices: &[u64]) -> Vec<TOut>
+    where
+        TIn: AsPrimitive<f64>,
+        TOut: Copy + 'static,
+        f64: AsPrimitive<TOut>,
+    {
+        let args = &self.arguments;
+        let mut rng = chunk_rng(self.seed, chunk_indices);
+        elements_in
+            .iter()
+            .map(|value| {
+                let value_f64: f64 = value.as_();
+                let noise = match args.distribution {
+                    NoiseDistribution::Uniform => args.low + (args.high - args.low) * rng.f64(),
+                    NoiseDistribution::Normal => {
+                        args.mean + args.std * sample_standard_normal(&mut rng)
+                    }
+                };
+                (value_f64 + noise).as_()
+            })
+            .collect::<Vec<TOut>>()
+    }
+}
+
+impl FilterTraits for AddNoise {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Bool
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float16
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::BFloat16 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize() * chunk_input.fixed_element_size().unwrap()
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| self.apply_chunk(input, output, &chunk_indices, &progress),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_noise_uniform() {
+        let arguments = AddNoiseArguments::parse_from([
+            "add_noise",
+            "uniform",
+            "--low",
+            "-0.5",
+            "--high",
+            "0.5",
+        ]);
+        let add_noise = AddNoise::new(&arguments, 1, None);
+        let elements_in = vec![10.0f32; 64];
+        let elements_out: Vec<f32> = add_noise.apply_elements(&elements_in, &[0, 0]);
+        assert!(elements_out
+            .iter()
+            .all(|value| (9.5..=10.5).contains(value)));
+        assert!(elements_out.iter().any(|value| *value != 10.0));
+
+        // Reproducible for a seed and chunk
+        let elements_out_repeat: Vec<f32> = add_noise.apply_elements(&elements_in, &[0, 0]);
+        assert_eq!(elements_out, elements_out_repeat);
+        let elements_out_other: Vec<f32> = add_noise.apply_elements(&elements_in, &[0, 1]);
+        assert_ne!(elements_out, elements_out_other);
+    }
+}
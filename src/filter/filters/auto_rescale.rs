@@ -0,0 +1,427 @@
+use clap::Parser;
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        retrieve_elements_as, store_elements_as, try_for_each_chunk, FilterArguments,
+        FilterCommonArguments,
+    },
+    info::{calculate_histogram, calculate_range},
+    progress::{Progress, ProgressCallback},
+    WriteShapeArgs,
+};
+
+/// The attribute key of the transform written by [`AutoRescale`].
+pub const AUTO_RESCALE_ATTRIBUTE: &str = "auto_rescale";
+
+fn default_percentiles() -> Vec<f64> {
+    vec![0.0, 100.0]
+}
+
+fn default_bins() -> usize {
+    65536
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct AutoRescaleArguments {
+    /// The low and high percentiles (in [0, 100]) of the input mapped to the output range, comma delimited.
+    ///
+    /// The default maps the input minimum and maximum.
+    #[arg(long, value_delimiter = ',', num_args = 2, default_values_t = default_percentiles())]
+    #[serde(default = "default_percentiles")]
+    pub percentiles: Vec<f64>,
+
+    /// The output range, comma delimited. Defaults to the full range of integer output data types, or 0,1 for floating point output data types.
+    #[arg(long, value_delimiter = ',', num_args = 2, allow_hyphen_values(true))]
+    #[serde(default)]
+    pub output_range: Option<Vec<f64>>,
+
+    /// The number of histogram bins between the input minimum and maximum used to estimate percentiles.
+    #[arg(long, default_value_t = default_bins())]
+    #[serde(default = "default_bins")]
+    pub bins: usize,
+}
+
+impl FilterArguments for AutoRescaleArguments {
+    fn name(&self) -> String {
+        "auto_rescale".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        let [low, high] = self.percentiles[..] else {
+            return Err(FilterError::InvalidParameters(
+                "percentiles must have a low and high percentile".to_string(),
+            ));
+        };
+        if !(0.0..=100.0).contains(&low) || !(low..=100.0).contains(&high) {
+            return Err(FilterError::InvalidParameters(format!(
+                "percentiles {low},{high} must be increasing and in [0, 100]"
+            )));
+        }
+        let output_range = match self.output_range.as_deref() {
+            Some(&[min, max]) if min <= max => Some((min, max)),
+            Some(_) => {
+                return Err(FilterError::InvalidParameters(
+                    "the output range must have a minimum and a greater or equal maximum"
+                        .to_string(),
+                ))
+            }
+            None => None,
+        };
+        Ok(Box::new(AutoRescale::new(
+            (low, high),
+            output_range,
+            self.bins.max(1),
+            *common_args.chunk_limit(),
+            common_args.write().clone(),
+        )))
+    }
+}
+
+/// Returns the default output range of `data_type`, the full range of integer data types or [0, 1] for floating point data types.
+fn default_output_range(data_type: &DataType) -> (f64, f64) {
+    match data_type {
+        DataType::Int8 => (i8::MIN.into(), i8::MAX.into()),
+        DataType::Int16 => (i16::MIN.into(), i16::MAX.into()),
+        DataType::Int32 => (i32::MIN.into(), i32::MAX.into()),
+        DataType::Int64 => (i64::MIN as f64, i64::MAX as f64),
+        DataType::UInt8 => (u8::MIN.into(), u8::MAX.into()),
+        DataType::UInt16 => (u16::MIN.into(), u16::MAX.into()),
+        DataType::UInt32 => (u32::MIN.into(), u32::MAX.into()),
+        DataType::UInt64 => (u64::MIN as f64, u64::MAX as f64),
+        _ => (0.0, 1.0),
+    }
+}
+
+/// Returns the value at `percentile` (in [0, 100]) of a histogram with `bin_edges` and `counts`.
+///
+/// The value is linearly interpolated within the bin containing the percentile.
+fn histogram_percentile(bin_edges: &[f64], counts: &[u64], percentile: f64) -> f64 {
+    let total: u64 = counts.iter().sum();
+    let target = percentile / 100.0 * total as f64;
+    let mut cumulative = 0;
+    for (bin, count) in counts.iter().enumerate() {
+        if *count > 0 && (cumulative + count) as f64 >= target {
+            let fraction = ((target - cumulative as f64) / *count as f64).clamp(0.0, 1.0);
+            return bin_edges[bin] + fraction * (bin_edges[bin + 1] - bin_edges[bin]);
+        }
+        cumulative += count;
+    }
+    *bin_edges.last().unwrap()
+}
+
+/// Rescale values from low and high percentiles of the input to the full range of the output data type (or an explicit output range).
+///
+/// The input range is computed from the input minimum and maximum, or estimated from a histogram of the input for other percentiles.
+/// Values outside of the input range are clamped, and values are rounded for integer output data types.
+/// The applied transform, `output = input * scale + offset`, is written to the output attributes under [`AUTO_RESCALE_ATTRIBUTE`] such that it can be inverted.
+pub struct AutoRescale {
+    percentiles: (f64, f64),
+    output_range: Option<(f64, f64)>,
+    bins: usize,
+    chunk_limit: Option<usize>,
+    write: WriteShapeArgs,
+}
+
+impl AutoRescale {
+    pub fn new(
+        percentiles: (f64, f64),
+        output_range: Option<(f64, f64)>,
+        bins: usize,
+        chunk_limit: Option<usize>,
+        write: WriteShapeArgs,
+    ) -> Self {
+        Self {
+            percentiles,
+            output_range,
+            bins,
+            chunk_limit,
+            write,
+        }
+    }
+
+    /// Returns the input range at the percentiles of `input`.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the input cannot be read.
+    pub fn input_range(
+        &self,
+        input: &Array<FilesystemStore>,
+        chunk_limit: usize,
+    ) -> Result<(f64, f64), FilterError> {
+        let chunk_indices = ArraySubset::new_with_shape(input.chunk_grid_shape().unwrap())
+            .indices()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let (min, max) = calculate_range(input, &chunk_indices, chunk_limit)?;
+        let (min, max) = (min.as_f64().unwrap(), max.as_f64().unwrap());
+        let (low, high) = self.percentiles;
+        if (low == 0.0 && high == 100.0) || min == max {
+            return Ok((min, max));
+        }
+        let (bin_edges, counts) =
+            calculate_histogram(input, self.bins, min, max, &chunk_indices, chunk_limit)?;
+        let low = if low == 0.0 {
+            min
+        } else {
+            histogram_percentile(&bin_edges, &counts, low)
+        };
+        let high = if high == 100.0 {
+            max
+        } else {
+            histogram_percentile(&bin_edges, &counts, high)
+        };
+        Ok((low, high))
+    }
+
+    /// Returns the scale and offset mapping `input_range` to `output_range`.
+    pub fn scale_offset(input_range: (f64, f64), output_range: (f64, f64)) -> (f64, f64) {
+        let scale = if input_range.1 > input_range.0 {
+            (output_range.1 - output_range.0) / (input_range.1 - input_range.0)
+        } else {
+            1.0
+        };
+        (scale, output_range.0 - input_range.0 * scale)
+    }
+
+    /// Rescale `elements` in place, clamping to `output_range` and rounding if `round` is true.
+    pub fn apply_elements_inplace(
+        &self,
+        elements: &mut [f64],
+        scale: f64,
+        offset: f64,
+        output_range: (f64, f64),
+        round: bool,
+    ) {
+        elements.par_iter_mut().for_each(|value| {
+            let rescaled = value
+                .mul_add(scale, offset)
+                .clamp(output_range.0, output_range.1);
+            *value = if round { rescaled.round() } else { rescaled };
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        input_output_subset: &ArraySubset,
+        scale: f64,
+        offset: f64,
+        output_range: (f64, f64),
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let mut elements =
+            progress.read(|| retrieve_elements_as::<f64>(input, input_output_subset))?;
+        let round = !matches!(
+            output.data_type(),
+            DataType::BFloat16 | DataType::Float16 | DataType::Float32 | DataType::Float64
+        );
+        progress.process(|| {
+            self.apply_elements_inplace(&mut elements, scale, offset, output_range, round);
+        });
+
+        progress.write(|| {
+            store_elements_as(
+                output,
+                input_output_subset,
+                &elements,
+                &self.write.codec_options(output),
+            )
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for AutoRescale {
+    fn supports_write_shape(&self) -> bool {
+        true
+    }
+
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float16
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::BFloat16 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize() * chunk_input.fixed_element_size().unwrap()
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let input_range = self.input_range(input, chunk_limit)?;
+        let output_range = self
+            .output_range
+            .unwrap_or_else(|| default_output_range(output.data_type()));
+        let (scale, offset) = Self::scale_offset(input_range, output_range);
+
+        let subsets = self.write.write_subsets(output)?;
+        let progress = Progress::new(subsets.len(), progress_callback);
+        let output_ref = &*output;
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            subsets,
+            |subset: ArraySubset| {
+                self.apply_chunk(
+                    input,
+                    output_ref,
+                    &subset,
+                    scale,
+                    offset,
+                    output_range,
+                    &progress,
+                )
+            },
+        )?;
+
+        // Record the transform
+        output.attributes_mut().insert(
+            AUTO_RESCALE_ATTRIBUTE.to_string(),
+            serde_json::json!({
+                "scale": scale,
+                "offset": offset,
+                "input_range": [input_range.0, input_range.1],
+                "output_range": [output_range.0, output_range.1],
+                "percentiles": [self.percentiles.0, self.percentiles.1],
+            }),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, sync::Arc};
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn auto_rescale() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![101],
+            DataType::UInt16,
+            vec![10].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(store.clone(), "/input")?;
+        let elements = (0..=100).map(|i| i * 10).collect::<Vec<u16>>();
+        array.store_array_subset_elements::<u16>(&array.subset_all(), &elements)?;
+
+        let progress_callback = |_stats: ProgressStats| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let output = |name: &str| {
+            ArrayBuilder::new(
+                vec![101],
+                DataType::UInt8,
+                vec![10].try_into().unwrap(),
+                FillValue::from(0u8),
+            )
+            .build(store.clone(), name)
+        };
+
+        // Minimum and maximum
+        let mut array_output = output("/output")?;
+        AutoRescale::new((0.0, 100.0), None, 65536, None, WriteShapeArgs::default()).apply(
+            &array,
+            &mut array_output,
+            &progress_callback,
+        )?;
+        let elements_out =
+            array_output.retrieve_array_subset_elements::<u8>(&array_output.subset_all())?;
+        assert_eq!(elements_out[0], 0);
+        assert_eq!(elements_out[50], 128);
+        assert_eq!(elements_out[100], 255);
+        let transform = &array_output.attributes()[AUTO_RESCALE_ATTRIBUTE];
+        let (scale, offset) = (
+            transform["scale"].as_f64().unwrap(),
+            transform["offset"].as_f64().unwrap(),
+        );
+        assert!(((255.0 - offset) / scale - 1000.0).abs() < 1e-9);
+
+        // Percentiles, clamping outside of the input range
+        let mut array_output = output("/output_percentiles")?;
+        AutoRescale::new(
+            (10.0, 90.0),
+            Some((0.0, 80.0)),
+            1000,
+            None,
+            WriteShapeArgs::default(),
+        )
+        .apply(&array, &mut array_output, &progress_callback)?;
+        let elements_out =
+            array_output.retrieve_array_subset_elements::<u8>(&array_output.subset_all())?;
+        assert_eq!(elements_out[0], 0);
+        assert_eq!(elements_out[10], 0);
+        assert_eq!(elements_out[50], 40);
+        assert_eq!(elements_out[90], 80);
+        assert_eq!(elements_out[100], 80);
+        Ok(())
+    }
+
+    #[test]
+    fn auto_rescale_histogram_percentile() {
+        let bin_edges = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let counts = [1, 1, 1, 1];
+        assert_eq!(histogram_percentile(&bin_edges, &counts, 0.0), 0.0);
+        assert_eq!(histogram_percentile(&bin_edges, &counts, 50.0), 2.0);
+        assert_eq!(histogram_percentile(&bin_edges, &counts, 100.0), 4.0);
+    }
+}
@@ -0,0 +1,308 @@
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    cast::{CastElement, ElementCastArgs},
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+    WriteShapeArgs,
+};
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct CastArguments {
+    #[command(flatten)]
+    #[serde(flatten)]
+    pub cast: ElementCastArgs,
+}
+
+impl FilterArguments for CastArguments {
+    fn name(&self) -> String {
+        "cast".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        Ok(Box::new(Cast::new(
+            self.cast,
+            *common_args.chunk_limit(),
+            common_args.write().clone(),
+        )))
+    }
+}
+
+/// Cast the elements of an array to the output data type with explicit rounding, overflow, and NaN policies.
+pub struct Cast {
+    cast: ElementCastArgs,
+    chunk_limit: Option<usize>,
+    write: WriteShapeArgs,
+}
+
+impl Cast {
+    pub fn new(cast: ElementCastArgs, chunk_limit: Option<usize>, write: WriteShapeArgs) -> Self {
+        Self {
+            cast,
+            chunk_limit,
+            write,
+        }
+    }
+
+    pub fn apply_chunk<TIn, TOut>(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        input_output_subset: &ArraySubset,
+        progress: &Progress,
+    ) -> Result<(), FilterError>
+    where
+        TIn: ElementOwned + CastElement,
+        TOut: Element + CastElement,
+    {
+        let input_elements =
+            progress.read(|| input.retrieve_array_subset_elements::<TIn>(input_output_subset))?;
+
+        let output_elements =
+            progress.process(|| self.cast.cast_elements::<TIn, TOut>(&input_elements))?;
+        drop(input_elements);
+
+        progress.write(|| {
+            output.store_array_subset_elements_opt::<TOut>(
+                input_output_subset,
+                &output_elements,
+                &self.write.codec_options(output),
+            )
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for Cast {
+    fn supports_write_shape(&self) -> bool {
+        true
+    }
+
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Bool
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float16
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::BFloat16 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize() * chunk_input.fixed_element_size().unwrap()
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let subsets = self.write.write_subsets(output)?;
+        let progress = Progress::new(subsets.len(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            subsets,
+            |subset: ArraySubset| {
+                macro_rules! apply_output {
+                    ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
+                        match output.data_type() {
+                            $(DataType::$data_type_out => { self.apply_chunk::<$type_in, $type_out>(input, output, &subset, &progress) } ,)*
+                            _ => panic!()
+                        }
+                    };
+                }
+                macro_rules! apply_input {
+                    ([$( ( $data_type_in:ident, $type_in:ty ) ),* ]) => {
+                            match input.data_type() {
+                                $(
+                                    DataType::$data_type_in => {
+                                        apply_output!($type_in, [
+                                            (Bool, bool),
+                                            (Int8, i8),
+                                            (Int16, i16),
+                                            (Int32, i32),
+                                            (Int64, i64),
+                                            (UInt8, u8),
+                                            (UInt16, u16),
+                                            (UInt32, u32),
+                                            (UInt64, u64),
+                                            (BFloat16, half::bf16),
+                                            (Float16, half::f16),
+                                            (Float32, f32),
+                                            (Float64, f64)
+                                        ]
+                                    )}
+                                ,)*
+                                _ => panic!()
+                            }
+                        };
+                    }
+                apply_input!([
+                    (Bool, bool),
+                    (Int8, i8),
+                    (Int16, i16),
+                    (Int32, i32),
+                    (Int64, i64),
+                    (UInt8, u8),
+                    (UInt16, u16),
+                    (UInt32, u32),
+                    (UInt64, u64),
+                    (BFloat16, half::bf16),
+                    (Float16, half::f16),
+                    (Float32, f32),
+                    (Float64, f64)
+                ])
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, num::NonZeroU64};
+
+    use zarrs::array::{
+        codec::array_to_bytes::sharding::ShardingCodecBuilder, ArrayBuilder, FillValue,
+    };
+
+    use crate::{
+        cast::{CastNan, CastPolicy, CastRounding},
+        progress::ProgressStats,
+    };
+
+    use super::*;
+
+    #[test]
+    fn cast_round_saturate() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![4],
+            DataType::Float32,
+            vec![4].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<f32>(&array.subset_all(), &[-1.5, 0.4, 2.5, 1000.0])?;
+
+        let mut array_output = ArrayBuilder::new(
+            vec![4],
+            DataType::UInt8,
+            vec![4].try_into()?,
+            FillValue::from(0u8),
+        )
+        .build(store, "/output")?;
+        let cast = ElementCastArgs {
+            overflow: CastPolicy::Saturate,
+            rounding: CastRounding::Round,
+            nan: CastNan::Zero,
+        };
+        let progress_callback = |_stats: ProgressStats| {};
+        Cast::new(cast, None, WriteShapeArgs::default()).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<u8>(&array_output.subset_all())?;
+        assert_eq!(elements, [0, 0, 3, 255]);
+        Ok(())
+    }
+
+    #[test]
+    fn cast_write_shape() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![6],
+            DataType::Float32,
+            vec![6].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<f32>(
+            &array.subset_all(),
+            &[0.4, 1.6, 2.5, 3.0, 4.4, 5.5],
+        )?;
+
+        let mut array_output = ArrayBuilder::new(
+            vec![6],
+            DataType::UInt8,
+            vec![8].try_into()?,
+            FillValue::from(0u8),
+        )
+        .array_to_bytes_codec(std::sync::Arc::new(
+            ShardingCodecBuilder::new(vec![2].try_into()?).build(),
+        ))
+        .build(store, "/output")?;
+        let write = WriteShapeArgs {
+            write_shape: Some(vec![NonZeroU64::new(4).unwrap()]),
+        };
+        let subsets = write.write_subsets(&array_output)?;
+        assert_eq!(
+            subsets,
+            [
+                ArraySubset::new_with_shape(vec![4]),
+                ArraySubset::new_with_start_end_exc(vec![4], vec![6]).unwrap()
+            ]
+        );
+
+        let progress_callback = |_stats: ProgressStats| {};
+        Cast::new(ElementCastArgs::default(), None, write).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<u8>(&array_output.subset_all())?;
+        assert_eq!(elements, [0, 1, 2, 3, 4, 5]);
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use clap::Parser;
 use num_traits::AsPrimitive;
-use rayon::iter::{IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
     array::{data_type::UnsupportedDataTypeError, Array, DataType},
@@ -11,12 +12,13 @@ use zarrs::{
 use crate::{
     filter::{
         calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
-        FilterArguments, FilterCommonArguments,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
     },
     progress::{Progress, ProgressCallback},
+    WriteShapeArgs,
 };
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
 pub struct ClampArguments {
     /// Minimum.
     pub min: f64,
@@ -37,6 +39,7 @@ impl FilterArguments for ClampArguments {
             self.min,
             self.max,
             *common_args.chunk_limit(),
+            common_args.write().clone(),
         )))
     }
 }
@@ -45,14 +48,16 @@ pub struct Clamp {
     min: f64,
     max: f64,
     chunk_limit: Option<usize>,
+    write: WriteShapeArgs,
 }
 
 impl Clamp {
-    pub fn new(min: f64, max: f64, chunk_limit: Option<usize>) -> Self {
+    pub fn new(min: f64, max: f64, chunk_limit: Option<usize>, write: WriteShapeArgs) -> Self {
         Self {
             min,
             max,
             chunk_limit,
+            write,
         }
     }
 
@@ -71,6 +76,10 @@ impl Clamp {
 }
 
 impl FilterTraits for Clamp {
+    fn supports_write_shape(&self) -> bool {
+        true
+    }
+
     fn is_compatible(
         &self,
         chunk_input: &zarrs::array::ChunkRepresentation,
@@ -113,29 +122,25 @@ impl FilterTraits for Clamp {
     ) -> Result<(), FilterError> {
         assert_eq!(output.shape(), input.shape());
 
-        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
-        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+        let subsets = self.write.write_subsets(output)?;
+        let codec_options = self.write.codec_options(output);
+        let progress = Progress::new(subsets.len(), progress_callback);
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
-        let indices = chunks.indices();
-        rayon_iter_concurrent_limit::iter_concurrent_limit!(
+        try_for_each_chunk(
             chunk_limit,
-            indices,
-            try_for_each,
-            |chunk_indices: Vec<u64>| {
+            memory_per_chunk,
+            subsets,
+            |input_output_subset: ArraySubset| {
                 macro_rules! apply_input {
                     ( $t_out:ty, [$( ( $data_type_in:ident, $t_in:ty ) ),* ]) => {
                         match input.data_type() {
                             $(DataType::$data_type_in => {
-                                let input_output_subset = output.chunk_subset_bounded(&chunk_indices).unwrap();
                                 let mut elements_in =
                                     progress.read(|| input.retrieve_array_subset_elements::<$t_in>(&input_output_subset))?;
                                 progress.process(|| self.apply_elements_inplace::<$t_in>(&mut elements_in))?;
@@ -150,7 +155,7 @@ impl FilterTraits for Clamp {
                                         let elements_out = elements_in.iter().map(|v| v.as_()).collect::<Vec<_>>();
                                         drop(elements_in);
                                         progress.write(|| {
-                                            output.store_array_subset_elements::<$t_out>(&input_output_subset, &elements_out)
+                                            output.store_array_subset_elements_opt::<$t_out>(&input_output_subset, &elements_out, &codec_options)
                                         })?;
                                 //     }}
                                 // }
@@ -202,9 +207,9 @@ impl FilterTraits for Clamp {
                     (Float64, f64)
                 ]);
 
-                progress.next();
+                progress.next()?;
                 Ok::<_, FilterError>(())
-            }
+            },
         )?;
 
         Ok(())
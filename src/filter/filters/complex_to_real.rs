@@ -0,0 +1,298 @@
+use clap::Parser;
+use num_complex::Complex;
+use num_traits::AsPrimitive;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{
+        data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned, FillValue,
+    },
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+/// The part of a complex element to extract.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplexPart {
+    /// The magnitude (absolute value).
+    #[default]
+    Magnitude,
+    /// The phase (argument) in radians, in the range [-pi, pi].
+    Phase,
+    /// The real part.
+    Real,
+    /// The imaginary part.
+    Imaginary,
+}
+
+impl ComplexPart {
+    /// Extract the part of a complex element.
+    pub fn apply(self, value: Complex<f64>) -> f64 {
+        match self {
+            ComplexPart::Magnitude => value.norm(),
+            ComplexPart::Phase => value.arg(),
+            ComplexPart::Real => value.re,
+            ComplexPart::Imaginary => value.im,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct ComplexToRealArguments {
+    /// The part of the complex elements to extract.
+    #[arg(long, value_enum, default_value_t = ComplexPart::Magnitude)]
+    #[serde(default)]
+    pub part: ComplexPart,
+}
+
+impl FilterArguments for ComplexToRealArguments {
+    fn name(&self) -> String {
+        "complex_to_real".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        Ok(Box::new(ComplexToReal::new(
+            self.part,
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// Convert a complex array to a real array of the magnitude, phase, real part, or imaginary part of each element.
+pub struct ComplexToReal {
+    part: ComplexPart,
+    chunk_limit: Option<usize>,
+}
+
+impl ComplexToReal {
+    pub fn new(part: ComplexPart, chunk_limit: Option<usize>) -> Self {
+        Self { part, chunk_limit }
+    }
+
+    pub fn apply_chunk<TIn, TOut>(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError>
+    where
+        TIn: Send + Sync + Copy + AsPrimitive<f64>,
+        TOut: Element + Send + Sync + Copy + 'static,
+        Complex<TIn>: ElementOwned,
+        f64: AsPrimitive<TOut>,
+    {
+        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
+
+        let elements_in = progress
+            .read(|| input.retrieve_array_subset_elements::<Complex<TIn>>(&input_output_subset))?;
+        let elements_out = progress.process(|| self.apply_elements::<TIn, TOut>(&elements_in));
+        drop(elements_in);
+
+        progress.write(|| {
+            output.store_array_subset_elements::<TOut>(&input_output_subset, &elements_out)
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+
+    pub fn apply_elements<TIn, TOut>(&self, elements_in: &[Complex<TIn>]) -> Vec<TOut>
+    where
+        TIn: Send + Sync + Copy + AsPrimitive<f64>,
+        TOut: Send + Sync + Copy + 'static,
+        f64: AsPrimitive<TOut>,
+    {
+        elements_in
+            .par_iter()
+            .map(|value| {
+                self.part
+                    .apply(Complex::new(value.re.as_(), value.im.as_()))
+                    .as_()
+            })
+            .collect()
+    }
+}
+
+impl FilterTraits for ComplexToReal {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        match chunk_input.data_type() {
+            DataType::Complex64 | DataType::Complex128 => {}
+            data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        };
+        match chunk_output.data_type() {
+            DataType::Float16 | DataType::BFloat16 | DataType::Float32 | DataType::Float64 => {}
+            data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        };
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize() * chunk_input.fixed_element_size().unwrap()
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn output_data_type(&self, input: &Array<FilesystemStore>) -> Option<(DataType, FillValue)> {
+        let fill_value = input.fill_value().as_ne_bytes();
+        let part = |re: f64, im: f64| self.part.apply(Complex::new(re, im));
+        match input.data_type() {
+            DataType::Complex64 => {
+                let re = f32::from_ne_bytes(fill_value[..4].try_into().unwrap());
+                let im = f32::from_ne_bytes(fill_value[4..].try_into().unwrap());
+                let fill_value = part(f64::from(re), f64::from(im)) as f32;
+                Some((DataType::Float32, FillValue::from(fill_value)))
+            }
+            DataType::Complex128 => {
+                let re = f64::from_ne_bytes(fill_value[..8].try_into().unwrap());
+                let im = f64::from_ne_bytes(fill_value[8..].try_into().unwrap());
+                Some((DataType::Float64, FillValue::from(part(re, im))))
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                macro_rules! apply_output {
+                    ( $t_in:ty, [$( ( $data_type:ident, $t_out:ty ) ),* ]) => {
+                        match output.data_type() {
+                            $(DataType::$data_type => { self.apply_chunk::<$t_in, $t_out>(input, output, &chunk_indices, &progress) } ,)*
+                            _ => panic!()
+                        }
+                    };
+                }
+                match input.data_type() {
+                    DataType::Complex64 => apply_output!(
+                        f32,
+                        [
+                            (BFloat16, half::bf16),
+                            (Float16, half::f16),
+                            (Float32, f32),
+                            (Float64, f64)
+                        ]
+                    ),
+                    DataType::Complex128 => apply_output!(
+                        f64,
+                        [
+                            (BFloat16, half::bf16),
+                            (Float16, half::f16),
+                            (Float32, f32),
+                            (Float64, f64)
+                        ]
+                    ),
+                    _ => panic!(),
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::ArrayBuilder;
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn complex_to_real() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![4],
+            DataType::Complex64,
+            vec![2].try_into()?,
+            FillValue::from(Complex::<f32>::new(0.0, 0.0)),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<Complex<f32>>(
+            &array.subset_all(),
+            &[
+                Complex::new(3.0, 4.0),
+                Complex::new(-1.0, 0.0),
+                Complex::new(0.0, 2.0),
+                Complex::new(1.5, -2.5),
+            ],
+        )?;
+
+        let progress_callback = |_stats: ProgressStats| {};
+        let filter = ComplexToReal::new(ComplexPart::Magnitude, None);
+        let (data_type, fill_value) = filter.output_data_type(&array).unwrap();
+        assert_eq!(data_type, DataType::Float32);
+        let mut array_output =
+            ArrayBuilder::new(vec![4], data_type, vec![2].try_into()?, fill_value)
+                .build(store.clone(), "/magnitude")?;
+        filter.apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<f32>(&array_output.subset_all())?;
+        assert_eq!(elements[..3], [5.0, 1.0, 2.0]);
+
+        let mut array_output = ArrayBuilder::new(
+            vec![4],
+            DataType::Float64,
+            vec![4].try_into()?,
+            FillValue::from(0.0f64),
+        )
+        .build(store, "/imaginary")?;
+        ComplexToReal::new(ComplexPart::Imaginary, None).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<f64>(&array_output.subset_all())?;
+        assert_eq!(elements, [4.0, 0.0, 2.0, -2.5]);
+        Ok(())
+    }
+}
@@ -1,22 +1,24 @@
 use clap::Parser;
-use num_traits::AsPrimitive;
-use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
-    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
+    array::{Array, DataType, Element, ElementOwned},
     array_subset::ArraySubset,
     filesystem::FilesystemStore,
 };
 
 use crate::{
+    cast::{CastElement, ElementCastArgs},
     filter::{
         calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
-        FilterArguments, FilterCommonArguments,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
     },
     progress::{Progress, ProgressCallback},
 };
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
+use super::reencode::{cast_bytes_to_complex, is_castable, is_complex};
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
 pub struct CropArguments {
     /// Crop offset, comma delimited.
     #[arg(required = true, value_delimiter = ',')]
@@ -24,6 +26,10 @@ pub struct CropArguments {
     /// Crop shape, comma delimited.
     #[arg(required = true, value_delimiter = ',')]
     pub shape: Vec<u64>,
+    /// Cast policies if the data type is changed.
+    #[command(flatten)]
+    #[serde(flatten)]
+    pub cast: ElementCastArgs,
 }
 
 impl FilterArguments for CropArguments {
@@ -38,6 +44,7 @@ impl FilterArguments for CropArguments {
         Ok(Box::new(Crop::new(
             self.offset.clone(),
             self.shape.clone(),
+            self.cast,
             *common_args.chunk_limit(),
         )))
     }
@@ -46,14 +53,21 @@ impl FilterArguments for CropArguments {
 pub struct Crop {
     offset: Vec<u64>,
     shape: Vec<u64>,
+    cast: ElementCastArgs,
     chunk_limit: Option<usize>,
 }
 
 impl Crop {
-    pub fn new(offset: Vec<u64>, shape: Vec<u64>, chunk_limit: Option<usize>) -> Self {
+    pub fn new(
+        offset: Vec<u64>,
+        shape: Vec<u64>,
+        cast: ElementCastArgs,
+        chunk_limit: Option<usize>,
+    ) -> Self {
         Self {
             offset,
             shape,
+            cast,
             chunk_limit,
         }
     }
@@ -85,7 +99,29 @@ impl Crop {
         let (input_subset, output_subset) = self.get_input_output_subset(output, chunk_indices);
         let output_bytes = progress.read(|| input.retrieve_array_subset(&input_subset))?;
         progress.write(|| output.store_array_subset(&output_subset, output_bytes))?;
-        progress.next();
+        progress.next()?;
+        Ok(())
+    }
+
+    pub fn apply_chunk_convert_complex(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let (input_subset, output_subset) = self.get_input_output_subset(output, chunk_indices);
+        let input_bytes = progress.read(|| input.retrieve_array_subset(&input_subset))?;
+        let output_bytes = progress.process(|| {
+            cast_bytes_to_complex(
+                &self.cast,
+                input_bytes,
+                input.data_type(),
+                output.data_type(),
+            )
+        })?;
+        progress.write(|| output.store_array_subset(&output_subset, output_bytes))?;
+        progress.next()?;
         Ok(())
     }
 
@@ -97,27 +133,23 @@ impl Crop {
         progress: &Progress,
     ) -> Result<(), FilterError>
     where
-        TIn: ElementOwned + Send + Sync + AsPrimitive<TOut>,
-        TOut: Element + Send + Sync + Copy + 'static,
+        TIn: ElementOwned + CastElement,
+        TOut: Element + CastElement,
     {
         let (input_subset, output_subset) = self.get_input_output_subset(output, chunk_indices);
 
         let input_elements =
             progress.read(|| input.retrieve_array_subset_elements::<TIn>(&input_subset))?;
 
-        let output_elements = progress.process(|| {
-            input_elements
-                .par_iter()
-                .map(|input| input.as_())
-                .collect::<Vec<TOut>>()
-        });
+        let output_elements =
+            progress.process(|| self.cast.cast_elements::<TIn, TOut>(&input_elements))?;
         drop(input_elements);
 
         progress.write(|| {
             output.store_array_subset_elements::<TOut>(&output_subset, &output_elements)
         })?;
 
-        progress.next();
+        progress.next()?;
         Ok(())
     }
 }
@@ -128,25 +160,7 @@ impl FilterTraits for Crop {
         chunk_input: &zarrs::array::ChunkRepresentation,
         chunk_output: &zarrs::array::ChunkRepresentation,
     ) -> Result<(), FilterError> {
-        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
-            match data_type {
-                DataType::Bool
-                | DataType::Int8
-                | DataType::Int16
-                | DataType::Int32
-                | DataType::Int64
-                | DataType::UInt8
-                | DataType::UInt16
-                | DataType::UInt32
-                | DataType::UInt64
-                | DataType::Float16
-                | DataType::Float32
-                | DataType::Float64
-                | DataType::BFloat16 => {}
-                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
-            };
-        }
-        Ok(())
+        is_castable(chunk_input.data_type(), chunk_output.data_type())
     }
 
     fn memory_per_chunk(
@@ -154,7 +168,9 @@ impl FilterTraits for Crop {
         _chunk_input: &zarrs::array::ChunkRepresentation,
         chunk_output: &zarrs::array::ChunkRepresentation,
     ) -> usize {
-        chunk_output.fixed_element_size().unwrap()
+        chunk_output
+            .fixed_element_size()
+            .unwrap_or(std::mem::size_of::<u64>())
     }
 
     fn output_shape(&self, _input: &Array<FilesystemStore>) -> Option<Vec<u64>> {
@@ -172,23 +188,22 @@ impl FilterTraits for Crop {
         let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
         let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
         let indices = chunks.indices();
-        rayon_iter_concurrent_limit::iter_concurrent_limit!(
+        try_for_each_chunk(
             chunk_limit,
-            indices,
-            try_for_each,
+            memory_per_chunk,
+            &indices,
             |chunk_indices: Vec<u64>| {
                 if input.data_type() == output.data_type() {
                     self.apply_chunk(input, output, &chunk_indices, &progress)
+                } else if is_complex(output.data_type()) {
+                    self.apply_chunk_convert_complex(input, output, &chunk_indices, &progress)
                 } else {
                     macro_rules! apply_output {
                         ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
@@ -204,7 +219,7 @@ impl FilterTraits for Crop {
                                 $(
                                     DataType::$data_type_in => {
                                         apply_output!($type_in, [
-                                            (Bool, u8),
+                                            (Bool, bool),
                                             (Int8, i8),
                                             (Int16, i16),
                                             (Int32, i32),
@@ -225,7 +240,7 @@ impl FilterTraits for Crop {
                         };
                     }
                     apply_input!([
-                        (Bool, u8),
+                        (Bool, bool),
                         (Int8, i8),
                         (Int16, i16),
                         (Int32, i32),
@@ -240,9 +255,115 @@ impl FilterTraits for Crop {
                         (Float64, f64)
                     ])
                 }
-            }
+            },
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use num_complex::Complex;
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    /// Crop elements `1..5` of a 1D array with chunks of 4 into an array with chunks of 3.
+    fn crop<TIn: ElementOwned, TOut: ElementOwned>(
+        data_type_in: DataType,
+        fill_value_in: FillValue,
+        data_type_out: DataType,
+        fill_value_out: FillValue,
+        elements: &[TIn],
+    ) -> Result<Vec<TOut>, Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![elements.len() as u64],
+            data_type_in,
+            vec![4].try_into()?,
+            fill_value_in,
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<TIn>(&array.subset_all(), elements)?;
+
+        let crop = Crop::new(vec![1], vec![4], ElementCastArgs::default(), None);
+        let mut array_output =
+            ArrayBuilder::new(vec![4], data_type_out, vec![3].try_into()?, fill_value_out)
+                .build(store, "/output")?;
+        crop.is_compatible(
+            &array.chunk_array_representation(&[0])?,
+            &array_output.chunk_array_representation(&[0])?,
         )?;
+        let progress_callback = |_stats: ProgressStats| {};
+        crop.apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        Ok(array_output.retrieve_array_subset_elements::<TOut>(&array_output.subset_all())?)
+    }
 
+    #[test]
+    fn crop_complex() -> Result<(), Box<dyn Error>> {
+        let elements: Vec<Complex<f32>> = (0..6u8)
+            .map(|i| Complex::new(f32::from(i), -f32::from(i) / 2.0))
+            .collect();
+        let fill_value = FillValue::from(Complex::<f32>::new(0.0, 0.0));
+        let elements_out = crop::<Complex<f32>, Complex<f32>>(
+            DataType::Complex64,
+            fill_value.clone(),
+            DataType::Complex64,
+            fill_value.clone(),
+            &elements,
+        )?;
+        assert_eq!(elements_out, elements[1..5]);
+
+        let elements_out = crop::<Complex<f32>, Complex<f64>>(
+            DataType::Complex64,
+            fill_value,
+            DataType::Complex128,
+            FillValue::from(Complex::<f64>::new(0.0, 0.0)),
+            &elements,
+        )?;
+        let elements_ref: Vec<Complex<f64>> = elements[1..5]
+            .iter()
+            .map(|value| Complex::new(f64::from(value.re), f64::from(value.im)))
+            .collect();
+        assert_eq!(elements_out, elements_ref);
+
+        let elements: Vec<Complex<f64>> = (0..6u8)
+            .map(|i| Complex::new(-f64::from(i), f64::from(i) * 1.5))
+            .collect();
+        let fill_value = FillValue::from(Complex::<f64>::new(0.0, 0.0));
+        let elements_out = crop::<Complex<f64>, Complex<f64>>(
+            DataType::Complex128,
+            fill_value.clone(),
+            DataType::Complex128,
+            fill_value,
+            &elements,
+        )?;
+        assert_eq!(elements_out, elements[1..5]);
+        Ok(())
+    }
+
+    #[test]
+    fn crop_string() -> Result<(), Box<dyn Error>> {
+        // The crop crosses input and output chunk boundaries
+        let elements = ["a", "", "bc", "déf", "ghij", "k"].map(String::from);
+        let elements_out = crop::<String, String>(
+            DataType::String,
+            FillValue::from(""),
+            DataType::String,
+            FillValue::from(""),
+            &elements,
+        )?;
+        assert_eq!(elements_out, elements[1..5]);
         Ok(())
     }
 }
@@ -0,0 +1,301 @@
+use clap::Parser;
+use ndarray::{ArrayD, Axis, Zip};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{codec::CodecOptions, data_type::UnsupportedDataTypeError, Array, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        retrieve_ndarray_as, store_ndarray_as, try_for_each_chunk, ArraySubsetOverlap,
+        FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+fn default_length() -> u64 {
+    50
+}
+
+fn default_width() -> u64 {
+    3
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct DestripeArguments {
+    /// The axis the stripes run along (e.g. the light-sheet illumination axis).
+    pub axis: usize,
+    /// The axis across the stripes.
+    pub across_axis: usize,
+    /// Median window half size along the stripes. Window is 2 x length + 1.
+    ///
+    /// This should be larger than the structures of interest.
+    #[arg(long, default_value_t = default_length())]
+    #[serde(default = "default_length")]
+    pub length: u64,
+    /// Median window half size across the stripes. Window is 2 x width + 1.
+    ///
+    /// This should be larger than the stripe width.
+    #[arg(long, default_value_t = default_width())]
+    #[serde(default = "default_width")]
+    pub width: u64,
+}
+
+impl FilterArguments for DestripeArguments {
+    fn name(&self) -> String {
+        "destripe".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        if self.axis == self.across_axis {
+            return Err(FilterError::InvalidParameters(
+                "destripe axis and across axis must differ".to_string(),
+            ));
+        }
+        if self.width == 0 {
+            return Err(FilterError::InvalidParameters(
+                "destripe width must be positive".to_string(),
+            ));
+        }
+        Ok(Box::new(Destripe::new(self, *common_args.chunk_limit())))
+    }
+}
+
+/// A median-based destriping filter.
+///
+/// The stripe profile is estimated as the median along the stripes minus its median across the stripes, and is subtracted from the input.
+pub struct Destripe {
+    arguments: DestripeArguments,
+    chunk_limit: Option<usize>,
+}
+
+impl Destripe {
+    pub fn new(arguments: &DestripeArguments, chunk_limit: Option<usize>) -> Self {
+        Self {
+            arguments: arguments.clone(),
+            chunk_limit,
+        }
+    }
+
+    fn overlap(&self, ndim: usize) -> Vec<u64> {
+        let mut overlap = vec![0; ndim];
+        overlap[self.arguments.axis] = self.arguments.length;
+        overlap[self.arguments.across_axis] = self.arguments.width;
+        overlap
+    }
+
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let subset_output = output.chunk_subset_bounded(chunk_indices).unwrap();
+        let subset_overlap = ArraySubsetOverlap::new(
+            input.shape(),
+            &subset_output,
+            &self.overlap(input.dimensionality()),
+        );
+
+        let input_array =
+            progress.read(|| retrieve_ndarray_as::<f32>(input, subset_overlap.subset_input()))?;
+
+        let output_array = progress.process(|| {
+            let output_array = self.apply_ndarray(input_array);
+            subset_overlap.extract_subset(&output_array)
+        });
+
+        progress.write(|| {
+            store_ndarray_as(
+                output,
+                subset_output.start(),
+                &output_array,
+                &CodecOptions::default(),
+            )
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+
+    pub fn apply_ndarray(&self, mut input: ndarray::ArrayD<f32>) -> ndarray::ArrayD<f32> {
+        let along = median_filter_1d(self.arguments.axis, self.arguments.length, &input);
+        let across = median_filter_1d(self.arguments.across_axis, self.arguments.width, &along);
+        Zip::from(&mut input)
+            .and(&along)
+            .and(&across)
+            .par_for_each(|value, along, across| *value -= along - across);
+        input
+    }
+}
+
+/// Apply a 1D median filter with window half size `half_size` along `axis`.
+///
+/// The window is truncated at the boundaries.
+fn median_filter_1d(axis: usize, half_size: u64, input: &ArrayD<f32>) -> ArrayD<f32> {
+    let half_size = usize::try_from(half_size).unwrap();
+    let mut output = ArrayD::<f32>::zeros(input.shape());
+    Zip::from(output.lanes_mut(Axis(axis)))
+        .and(input.lanes(Axis(axis)))
+        .par_for_each(|mut output, input| {
+            let input = input.to_vec();
+            let mut window = Vec::with_capacity(2 * half_size + 1);
+            for (k, output) in output.iter_mut().enumerate() {
+                window.clear();
+                window.extend_from_slice(
+                    &input[k.saturating_sub(half_size)
+                        ..std::cmp::min(k + half_size + 1, input.len())],
+                );
+                let mid = window.len() / 2;
+                let (_, median, _) = window.select_nth_unstable_by(mid, f32::total_cmp);
+                *output = *median;
+            }
+        });
+    output
+}
+
+impl FilterTraits for Destripe {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        let ndim = chunk_input.dimensionality();
+        if self.arguments.axis >= ndim || self.arguments.across_axis >= ndim {
+            return Err(FilterError::InvalidParameters(format!(
+                "destripe axes {} and {} must be less than the array dimensionality {ndim}",
+                self.arguments.axis, self.arguments.across_axis
+            )));
+        }
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Bool
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float16
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::BFloat16 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        let overlap = self.overlap(chunk_input.dimensionality());
+        let num_input_elements = usize::try_from(
+            chunk_output
+                .shape()
+                .iter()
+                .zip(&overlap)
+                .map(|(s, overlap)| s.get() + overlap * 2)
+                .product::<u64>(),
+        )
+        .unwrap();
+        let num_output_elements = chunk_output.num_elements_usize();
+        // input, median along, and median across
+        num_input_elements
+            * (chunk_input.data_type().fixed_size().unwrap() + core::mem::size_of::<f32>() * 3)
+            + num_output_elements
+                * (core::mem::size_of::<f32>() + chunk_output.data_type().fixed_size().unwrap())
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| self.apply_chunk(input, output, &chunk_indices, &progress),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::ProgressStats;
+
+    use super::*;
+    use std::error::Error;
+    use zarrs::array::ArrayBuilder;
+
+    #[test]
+    fn destripe() -> Result<(), Box<dyn Error>> {
+        // A gradient along the first axis with stripes along the first axis
+        let path = tempfile::TempDir::new()?;
+        let store = FilesystemStore::new(path.path())?;
+        let array = ArrayBuilder::new(
+            vec![32, 32],
+            DataType::Float32,
+            vec![8, 8].try_into()?,
+            0.0f32.into(),
+        )
+        .build(store.into(), "/")?;
+        let background = |y: usize| 5.0 + 0.1 * y as f32;
+        let stripe = |x: usize| if x % 7 == 0 { 10.0 } else { 0.0 };
+        array.store_array_subset_ndarray(
+            &[0, 0],
+            ndarray::Array2::from_shape_fn((32, 32), |(y, x)| background(y) + stripe(x)),
+        )?;
+
+        let arguments = DestripeArguments {
+            axis: 0,
+            across_axis: 1,
+            length: 8,
+            width: 2,
+        };
+        let destripe = Destripe::new(&arguments, None);
+        let path = tempfile::TempDir::new()?;
+        let store: FilesystemStore = FilesystemStore::new(path.path())?;
+        let mut array_output = array.builder().build(store.into(), "/")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        destripe.apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements = array_output.retrieve_array_subset_ndarray::<f32>(&array.subset_all())?;
+
+        // The stripes are removed and the background is preserved
+        let elements_ref =
+            ndarray::Array2::from_shape_fn((32, 32), |(y, _x)| background(y)).into_dyn();
+        approx::assert_abs_diff_eq!(elements, elements_ref, epsilon = 1e-4);
+
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use clap::Parser;
 use num_traits::{AsPrimitive, FromPrimitive};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
     array::{data_type::UnsupportedDataTypeError, Array, DataType},
@@ -13,12 +14,12 @@ use zarrs::{
 use crate::{
     filter::{
         calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
-        FilterArguments, FilterCommonArguments,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments, MissingData,
     },
     progress::{Progress, ProgressCallback},
 };
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
 pub struct DownsampleArguments {
     /// Downsample stride, comma delimited.
     #[arg(required = true, value_delimiter = ',')]
@@ -27,6 +28,18 @@ pub struct DownsampleArguments {
     /// Perform majority filtering (mode downsampling).
     #[arg(long, default_value_t = false)]
     pub discrete: bool,
+    /// Exclude NaN elements from the mean, outputting NaN only if all contributing elements are NaN.
+    ///
+    /// Ignored for discrete downsampling.
+    #[arg(long, default_value_t = false)]
+    #[serde(default)]
+    pub nan_aware: bool,
+    /// Treat fill value and NaN input elements as missing data.
+    ///
+    /// Missing elements are excluded from the mean (or mode if discrete), and output elements without any valid input elements are the output fill value.
+    #[arg(long, default_value_t = false)]
+    #[serde(default)]
+    pub missing_data: bool,
 }
 
 impl FilterArguments for DownsampleArguments {
@@ -41,6 +54,8 @@ impl FilterArguments for DownsampleArguments {
         Ok(Box::new(Downsample::new(
             self.stride.clone(),
             self.discrete,
+            self.nan_aware,
+            self.missing_data,
             *common_args.chunk_limit(),
         )))
     }
@@ -49,14 +64,24 @@ impl FilterArguments for DownsampleArguments {
 pub struct Downsample {
     stride: Vec<u64>,
     discrete: bool,
+    nan_aware: bool,
+    missing_data: bool,
     chunk_limit: Option<usize>,
 }
 
 impl Downsample {
-    pub fn new(stride: Vec<u64>, discrete: bool, chunk_limit: Option<usize>) -> Self {
+    pub fn new(
+        stride: Vec<u64>,
+        discrete: bool,
+        nan_aware: bool,
+        missing_data: bool,
+        chunk_limit: Option<usize>,
+    ) -> Self {
         Self {
             stride,
             discrete,
+            nan_aware,
+            missing_data,
             chunk_limit,
         }
     }
@@ -69,6 +94,29 @@ impl Downsample {
         ArraySubset::new_with_start_end_exc(input_start.collect(), input_end.collect()).unwrap()
     }
 
+    /// Returns the output shape of an input with `input_shape`.
+    fn output_shape_of(&self, input_shape: &[u64]) -> Vec<u64> {
+        std::iter::zip(input_shape, &self.stride)
+            .map(|(shape, stride)| std::cmp::max(shape / stride, 1))
+            .collect()
+    }
+
+    /// Returns the input subset overlapping the footprints of `output_subset` for area weighted downsampling.
+    ///
+    /// The footprint of an output element spans the input shape divided by the output shape along each axis, which is fractional if the stride does not divide the input shape.
+    pub fn input_subset_weighted(
+        &self,
+        input_shape: &[u64],
+        output_subset: &ArraySubset,
+    ) -> ArraySubset {
+        let output_shape = self.output_shape_of(input_shape);
+        let input_start = itertools::izip!(output_subset.start(), input_shape, &output_shape)
+            .map(|(start, input, output)| start * input / output);
+        let input_end = itertools::izip!(output_subset.end_exc(), input_shape, &output_shape)
+            .map(|(end, input, output)| (end * input).div_ceil(*output));
+        ArraySubset::new_with_start_end_exc(input_start.collect(), input_end.collect()).unwrap()
+    }
+
     pub fn apply_ndarray_continuous<TIn, TOut>(
         &self,
         input: ndarray::ArrayD<TIn>,
@@ -79,11 +127,23 @@ impl Downsample {
         TOut: Copy + Send + Sync + std::iter::Sum + 'static,
         f64: AsPrimitive<TOut>,
     {
-        progress.process(|| {
+        progress.process_step("downsample", || {
             let chunk_size: Vec<usize> = std::iter::zip(&self.stride, input.shape())
                 .map(|(stride, shape)| std::cmp::min(usize::try_from(*stride).unwrap(), *shape))
                 .collect();
             ndarray::Zip::from(input.exact_chunks(chunk_size)).par_map_collect(|chunk| {
+                if self.nan_aware {
+                    let (sum, count) = chunk
+                        .iter()
+                        .map(|v| AsPrimitive::<f64>::as_(*v))
+                        .filter(|v| !v.is_nan())
+                        .fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+                    return if count == 0 {
+                        f64::NAN.as_()
+                    } else {
+                        (sum / f64::from_usize(count).unwrap()).as_()
+                    };
+                }
                 (chunk
                     .iter()
                     .map(|v| AsPrimitive::<f64>::as_(*v))
@@ -96,6 +156,43 @@ impl Downsample {
         })
     }
 
+    /// Mean downsample `input`, ignoring elements that are not `valid`.
+    ///
+    /// Output elements without any valid input elements are set to `fill_value`.
+    pub fn apply_ndarray_continuous_masked<TIn, TOut>(
+        &self,
+        input: ndarray::ArrayD<TIn>,
+        valid: &ndarray::ArrayD<bool>,
+        fill_value: TOut,
+        progress: &Progress,
+    ) -> ndarray::ArrayD<TOut>
+    where
+        TIn: Copy + Send + Sync + AsPrimitive<f64>,
+        TOut: Copy + Send + Sync + 'static,
+        f64: AsPrimitive<TOut>,
+    {
+        assert_eq!(input.shape(), valid.shape());
+        progress.process_step("downsample", || {
+            let chunk_size: Vec<usize> = std::iter::zip(&self.stride, input.shape())
+                .map(|(stride, shape)| std::cmp::min(usize::try_from(*stride).unwrap(), *shape))
+                .collect();
+            ndarray::Zip::from(input.exact_chunks(chunk_size.clone()))
+                .and(valid.exact_chunks(chunk_size))
+                .par_map_collect(|chunk, valid| {
+                    let (sum, count) = std::iter::zip(&chunk, &valid)
+                        .filter(|(_, valid)| **valid)
+                        .fold((0.0, 0usize), |(sum, count), (v, _)| {
+                            (sum + AsPrimitive::<f64>::as_(*v), count + 1)
+                        });
+                    if count == 0 {
+                        fill_value
+                    } else {
+                        (sum / f64::from_usize(count).unwrap()).as_()
+                    }
+                })
+        })
+    }
+
     pub fn apply_ndarray_discrete<TIn, TOut>(
         &self,
         input: ndarray::ArrayD<TIn>,
@@ -105,7 +202,7 @@ impl Downsample {
         TIn: Copy + Send + Sync + PartialEq + Eq + core::hash::Hash + AsPrimitive<TOut>,
         TOut: Copy + Send + Sync + 'static,
     {
-        progress.process(|| {
+        progress.process_step("downsample", || {
             let chunk_size: Vec<usize> = std::iter::zip(&self.stride, input.shape())
                 .map(|(stride, shape)| std::cmp::min(usize::try_from(*stride).unwrap(), *shape))
                 .collect();
@@ -118,6 +215,139 @@ impl Downsample {
             })
         })
     }
+
+    /// Mode downsample `input`, ignoring elements that are not `valid`.
+    ///
+    /// Output elements without any valid input elements are set to `fill_value`.
+    pub fn apply_ndarray_discrete_masked<TIn, TOut>(
+        &self,
+        input: ndarray::ArrayD<TIn>,
+        valid: &ndarray::ArrayD<bool>,
+        fill_value: TOut,
+        progress: &Progress,
+    ) -> ndarray::ArrayD<TOut>
+    where
+        TIn: Copy + Send + Sync + PartialEq + Eq + core::hash::Hash + AsPrimitive<TOut>,
+        TOut: Copy + Send + Sync + 'static,
+    {
+        assert_eq!(input.shape(), valid.shape());
+        progress.process_step("downsample", || {
+            let chunk_size: Vec<usize> = std::iter::zip(&self.stride, input.shape())
+                .map(|(stride, shape)| std::cmp::min(usize::try_from(*stride).unwrap(), *shape))
+                .collect();
+            ndarray::Zip::from(input.exact_chunks(chunk_size.clone()))
+                .and(valid.exact_chunks(chunk_size))
+                .par_map_collect(|chunk, valid| {
+                    let mut map = HashMap::<TIn, usize>::new();
+                    for (element, _) in std::iter::zip(&chunk, &valid).filter(|(_, valid)| **valid)
+                    {
+                        *map.entry(*element).or_insert(0) += 1;
+                    }
+                    map.iter()
+                        .max_by(|a, b| a.1.cmp(b.1))
+                        .map_or(fill_value, |(element, _)| element.as_())
+                })
+        })
+    }
+
+    /// Area weighted mode downsample `input`, returning the output and the confidence of each output element.
+    ///
+    /// `input` is the [`input_subset_weighted`](Downsample::input_subset_weighted) of `output_subset` of an input with `input_shape`.
+    /// Each input element votes for its value with a weight of its overlap with the footprint of an output element, so elements straddling the footprints of adjacent output elements split their vote.
+    /// The confidence is the fraction of the votes cast for the output value.
+    /// Ties are resolved in favour of the value encountered first.
+    pub fn apply_ndarray_discrete_weighted<TIn, TOut>(
+        &self,
+        input: &ndarray::ArrayD<TIn>,
+        input_shape: &[u64],
+        output_subset: &ArraySubset,
+        progress: &Progress,
+    ) -> (ndarray::ArrayD<TOut>, ndarray::ArrayD<f32>)
+    where
+        TIn: Copy + Send + Sync + PartialEq + Eq + core::hash::Hash + AsPrimitive<TOut>,
+        TOut: Copy + Send + Sync + 'static,
+    {
+        let output_shape = self.output_shape_of(input_shape);
+        let input_subset = self.input_subset_weighted(input_shape, output_subset);
+        assert_eq!(input.shape(), input_subset.shape_usize());
+        progress.process_step("downsample", || {
+            // The input range of each output element along each axis and the overlap of each input element (scaled by the output size)
+            let footprints: Vec<Vec<(std::ops::Range<usize>, Vec<f64>)>> = itertools::izip!(
+                output_subset.start(),
+                output_subset.end_exc(),
+                input_subset.start(),
+                input_shape,
+                &output_shape
+            )
+            .map(
+                |(&output_start, output_end, &input_start, &input_size, &output_size)| {
+                    (output_start..output_end)
+                        .map(|j| {
+                            let start = j * input_size / output_size;
+                            let end = ((j + 1) * input_size).div_ceil(output_size);
+                            let overlaps = (start..end)
+                                .map(|k| {
+                                    let overlap =
+                                        std::cmp::min((k + 1) * output_size, (j + 1) * input_size)
+                                            - std::cmp::max(k * output_size, j * input_size);
+                                    f64::from_u64(overlap).unwrap()
+                                })
+                                .collect();
+                            let range = usize::try_from(start - input_start).unwrap()
+                                ..usize::try_from(end - input_start).unwrap();
+                            (range, overlaps)
+                        })
+                        .collect()
+                },
+            )
+            .collect();
+
+            let (output, confidence): (Vec<TOut>, Vec<f32>) =
+                ArraySubset::new_with_shape(output_subset.shape().to_vec())
+                    .indices()
+                    .into_par_iter()
+                    .map(|indices| {
+                        let footprint = std::iter::zip(&footprints, &indices)
+                            .map(|(footprints, j)| &footprints[usize::try_from(*j).unwrap()])
+                            .collect::<Vec<_>>();
+                        let window = input.slice_each_axis(|axis| {
+                            ndarray::Slice::from(footprint[axis.axis.index()].0.clone())
+                        });
+                        let mut order = Vec::new();
+                        let mut votes = HashMap::<TIn, f64>::new();
+                        for (index, element) in window.indexed_iter() {
+                            let weight = footprint
+                                .iter()
+                                .enumerate()
+                                .map(|(axis, (_, overlaps))| overlaps[index[axis]])
+                                .product::<f64>();
+                            *votes.entry(*element).or_insert_with(|| {
+                                order.push(*element);
+                                0.0
+                            }) += weight;
+                        }
+                        let total = votes.values().sum::<f64>();
+                        let (element, weight) = order.iter().fold(
+                            (order[0], votes[&order[0]]),
+                            |(element_max, weight_max), element| {
+                                let weight = votes[element];
+                                if weight > weight_max {
+                                    (*element, weight)
+                                } else {
+                                    (element_max, weight_max)
+                                }
+                            },
+                        );
+                        (element.as_(), (weight / total) as f32)
+                    })
+                    .unzip();
+            let shape = output_subset.shape_usize();
+            (
+                ndarray::ArrayD::from_shape_vec(shape.clone(), output).unwrap(),
+                ndarray::ArrayD::from_shape_vec(shape, confidence).unwrap(),
+            )
+        })
+    }
 }
 
 impl FilterTraits for Downsample {
@@ -160,11 +390,7 @@ impl FilterTraits for Downsample {
     }
 
     fn output_shape(&self, input: &Array<FilesystemStore>) -> Option<Vec<u64>> {
-        Some(
-            std::iter::zip(input.shape(), &self.stride)
-                .map(|(shape, stride)| std::cmp::max(shape / stride, 1))
-                .collect(),
-        )
+        Some(self.output_shape_of(input.shape()))
     }
 
     fn apply(
@@ -174,24 +400,25 @@ impl FilterTraits for Downsample {
         progress_callback: &ProgressCallback,
     ) -> Result<(), FilterError> {
         assert_eq!(output.shape(), self.output_shape(input).unwrap());
+        let missing_data = self
+            .missing_data
+            .then(|| MissingData::from_arrays(input, output))
+            .transpose()?;
 
         let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
         let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
         let indices = chunks.indices();
-        rayon_iter_concurrent_limit::iter_concurrent_limit!(
+        try_for_each_chunk(
             chunk_limit,
-            indices,
-            try_for_each,
+            memory_per_chunk,
+            &indices,
             |chunk_indices: Vec<u64>| {
                 // Determine the input and output subset
                 let output_subset = output.chunk_subset_bounded(&chunk_indices).unwrap();
@@ -201,10 +428,27 @@ impl FilterTraits for Downsample {
                     ( $t_in:ty, $t_out:ty ) => {{
                         let input_array = progress
                             .read(|| input.retrieve_array_subset_ndarray::<$t_in>(&input_subset))?;
-                        let output_array = if self.discrete {
-                            self.apply_ndarray_discrete(input_array, &progress)
-                        } else {
-                            self.apply_ndarray_continuous(input_array, &progress)
+                        let output_array = match (&missing_data, self.discrete) {
+                            (Some(missing_data), true) => {
+                                let valid = missing_data.valid(&input_array);
+                                self.apply_ndarray_discrete_masked(
+                                    input_array,
+                                    &valid,
+                                    missing_data.fill_value(),
+                                    &progress,
+                                )
+                            }
+                            (Some(missing_data), false) => {
+                                let valid = missing_data.valid(&input_array);
+                                self.apply_ndarray_continuous_masked(
+                                    input_array,
+                                    &valid,
+                                    missing_data.fill_value(),
+                                    &progress,
+                                )
+                            }
+                            (None, true) => self.apply_ndarray_discrete(input_array, &progress),
+                            (None, false) => self.apply_ndarray_continuous(input_array, &progress),
                         };
                         progress.write(|| {
                             output.store_array_subset_ndarray::<$t_out, _>(
@@ -218,7 +462,17 @@ impl FilterTraits for Downsample {
                     ( $t_in:ty, $t_out:ty ) => {{
                         let input_array = progress
                             .read(|| input.retrieve_array_subset_ndarray::<$t_in>(&input_subset))?;
-                        let output_array = self.apply_ndarray_continuous(input_array, &progress);
+                        let output_array = if let Some(missing_data) = &missing_data {
+                            let valid = missing_data.valid(&input_array);
+                            self.apply_ndarray_continuous_masked(
+                                input_array,
+                                &valid,
+                                missing_data.fill_value(),
+                                &progress,
+                            )
+                        } else {
+                            self.apply_ndarray_continuous(input_array, &progress)
+                        };
                         progress.write(|| {
                             output.store_array_subset_ndarray::<$t_out, _>(
                                 output_subset.start(),
@@ -277,11 +531,115 @@ impl FilterTraits for Downsample {
                     (Float64, f64)
                 ]);
 
-                progress.next();
+                progress.next()?;
                 Ok::<_, FilterError>(())
-            }
+            },
         )?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::ProgressStats;
+
+    #[test]
+    fn downsample_continuous_masked() {
+        let downsample = Downsample::new(vec![2, 2], false, false, false, None);
+        let progress_callback = |_stats: ProgressStats| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let progress = Progress::new(1, &progress_callback);
+
+        let input = ndarray::ArrayD::<f32>::from_shape_vec(
+            vec![2, 4],
+            vec![1.0, 3.0, 0.0, 0.0, 0.0, 5.0, 0.0, 0.0],
+        )
+        .unwrap();
+        let valid = input.map(|v| *v != 0.0);
+        let output: ndarray::ArrayD<f32> =
+            downsample.apply_ndarray_continuous_masked(input.clone(), &valid, -1.0, &progress);
+        assert_eq!(output.as_slice().unwrap(), &[3.0, -1.0]);
+
+        // Unmasked averages are dragged toward the fill value
+        let output: ndarray::ArrayD<f32> = downsample.apply_ndarray_continuous(input, &progress);
+        assert_eq!(output.as_slice().unwrap(), &[2.25, 0.0]);
+    }
+
+    #[test]
+    fn downsample_continuous_nan_aware() {
+        let progress_callback = |_stats: ProgressStats| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let progress = Progress::new(1, &progress_callback);
+
+        let input = ndarray::ArrayD::<f32>::from_shape_vec(
+            vec![2, 4],
+            vec![
+                1.0,
+                f32::NAN,
+                f32::NAN,
+                f32::NAN,
+                3.0,
+                f32::NAN,
+                f32::NAN,
+                f32::NAN,
+            ],
+        )
+        .unwrap();
+        let output: ndarray::ArrayD<f32> = Downsample::new(vec![2, 2], false, true, false, None)
+            .apply_ndarray_continuous(input.clone(), &progress);
+        assert_eq!(output[[0, 0]], 2.0);
+        assert!(output[[0, 1]].is_nan());
+
+        let output: ndarray::ArrayD<f32> = Downsample::new(vec![2, 2], false, false, false, None)
+            .apply_ndarray_continuous(input, &progress);
+        assert!(output[[0, 0]].is_nan());
+    }
+
+    #[test]
+    fn downsample_discrete_masked() {
+        let downsample = Downsample::new(vec![2, 2], true, false, true, None);
+        let progress_callback = |_stats: ProgressStats| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let progress = Progress::new(1, &progress_callback);
+
+        let input = ndarray::ArrayD::<u8>::from_shape_vec(vec![2, 4], vec![0, 0, 0, 0, 0, 3, 0, 0])
+            .unwrap();
+        let valid = input.map(|v| *v != 0);
+        let output: ndarray::ArrayD<u8> =
+            downsample.apply_ndarray_discrete_masked(input, &valid, 9, &progress);
+        assert_eq!(output.as_slice().unwrap(), &[3, 9]);
+    }
+
+    #[test]
+    fn downsample_discrete_weighted() {
+        let downsample = Downsample::new(vec![1, 2], true, false, false, None);
+        let progress_callback = |_stats: ProgressStats| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let progress = Progress::new(1, &progress_callback);
+
+        // Each output element has a footprint of 2.5 input elements
+        let input_shape = [1, 5];
+        let input = ndarray::ArrayD::<u8>::from_shape_vec(vec![1, 5], vec![1, 2, 2, 1, 1]).unwrap();
+        let output_subset = ArraySubset::new_with_shape(vec![1, 2]);
+        assert_eq!(
+            downsample.input_subset_weighted(&input_shape, &output_subset),
+            ArraySubset::new_with_shape(vec![1, 5])
+        );
+        let (output, confidence): (ndarray::ArrayD<u8>, _) = downsample
+            .apply_ndarray_discrete_weighted(&input, &input_shape, &output_subset, &progress);
+        assert_eq!(output.as_slice().unwrap(), &[2, 1]);
+        assert_eq!(confidence.as_slice().unwrap(), &[0.6, 0.8]);
+
+        // The footprint of the second output element straddles the third input element
+        let output_subset = ArraySubset::new_with_ranges(&[0..1, 1..2]);
+        let input_subset = downsample.input_subset_weighted(&input_shape, &output_subset);
+        assert_eq!(input_subset, ArraySubset::new_with_ranges(&[0..1, 2..5]));
+        let input = input.slice(ndarray::s![.., 2..5]).to_owned().into_dyn();
+        let (output, confidence): (ndarray::ArrayD<u8>, _) = downsample
+            .apply_ndarray_discrete_weighted(&input, &input_shape, &output_subset, &progress);
+        assert_eq!(output.as_slice().unwrap(), &[1]);
+        assert_eq!(confidence.as_slice().unwrap(), &[0.8]);
+    }
+}
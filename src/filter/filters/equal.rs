@@ -1,6 +1,7 @@
 use clap::Parser;
 use num_traits::AsPrimitive;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
     array::{
@@ -17,11 +18,11 @@ use crate::{
 };
 
 use crate::filter::{
-    calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits, FilterArguments,
-    FilterCommonArguments,
+    calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+    try_for_each_chunk, FilterArguments, FilterCommonArguments,
 };
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
 pub struct EqualArguments {
     /// The value to compare against.
     ///
@@ -32,6 +33,7 @@ pub struct EqualArguments {
     ///   float: 0.0 "NaN" "Infinity" "-Infinity"
     ///   r*: "[0, 255]"
     #[arg(allow_hyphen_values(true), value_parser = parse_fill_value)]
+    #[schemars(with = "serde_json::Value")]
     pub value: FillValueMetadataV3,
 }
 
@@ -140,20 +142,17 @@ impl FilterTraits for Equal {
             .fill_value_from_metadata(&self.value)
             .unwrap();
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
         let indices = chunks.indices();
-        rayon_iter_concurrent_limit::iter_concurrent_limit!(
+        try_for_each_chunk(
             chunk_limit,
-            indices,
-            try_for_each,
+            memory_per_chunk,
+            &indices,
             |chunk_indices: Vec<u64>| {
                 let input_output_subset = output.chunk_subset_bounded(&chunk_indices).unwrap();
                 macro_rules! apply_input {
@@ -174,7 +173,7 @@ impl FilterTraits for Equal {
                                     output.store_array_subset_elements::<$t_out>(&input_output_subset, &output_elements)
                                 })?;
 
-                                progress.next();
+                                progress.next()?;
                                 Ok(())
                             } ,)*
                             _ => panic!()
@@ -211,7 +210,7 @@ impl FilterTraits for Equal {
                     (Bool, u8), // bool != bytemuck::Pod, but apply_chunk only stores 0 or 1, so can store as u8
                     (UInt8, u8)
                 ])
-            }
+            },
         )
     }
 }
@@ -0,0 +1,299 @@
+use std::{path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use num_traits::AsPrimitive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, BroadcastArray, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+use super::local_maxima::retrieve_values;
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct FlatFieldArguments {
+    /// The path to the flat field reference array.
+    pub flat: PathBuf,
+
+    /// The path to the dark (background) reference array, which is subtracted before dividing by the flat field.
+    #[arg(long)]
+    #[serde(default)]
+    pub dark: Option<PathBuf>,
+
+    /// The input axes of each axis of the reference arrays, comma delimited. Defaults to the trailing axes.
+    ///
+    /// Reference axes must have the size of the input axis or a size of 1, and other input axes are broadcast.
+    /// For example, a flat field with shape [Y, X] is applied to each slice of an input with shape [Z, Y, X] with the default axes, or each channel of an input with shape [Y, X, C] with axes 0,1.
+    #[arg(long, value_delimiter = ',')]
+    #[serde(default)]
+    pub axes: Option<Vec<usize>>,
+
+    /// A multiplier of the corrected values (e.g. the mean of the flat field to retain the intensity range).
+    #[arg(long, default_value_t = default_scale(), allow_hyphen_values(true))]
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+impl FilterArguments for FlatFieldArguments {
+    fn name(&self) -> String {
+        "flat_field".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        let open = |path: &PathBuf| -> Result<BroadcastArray, FilterError> {
+            let store = FilesystemStore::new(path).map_err(|err| {
+                FilterError::InvalidParameters(format!("cannot open reference {path:?}: {err}"))
+            })?;
+            let array = Array::open(Arc::new(store), "/")?;
+            Ok(BroadcastArray::new(array, self.axes.clone()))
+        };
+        Ok(Box::new(FlatField::new(
+            open(&self.flat)?,
+            self.dark.as_ref().map(open).transpose()?,
+            self.scale,
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// Apply a flat field correction, `(input - dark) / flat * scale`, with reference arrays broadcast over the input.
+pub struct FlatField {
+    flat: BroadcastArray,
+    dark: Option<BroadcastArray>,
+    scale: f64,
+    chunk_limit: Option<usize>,
+}
+
+impl FlatField {
+    pub fn new(
+        flat: BroadcastArray,
+        dark: Option<BroadcastArray>,
+        scale: f64,
+        chunk_limit: Option<usize>,
+    ) -> Self {
+        Self {
+            flat,
+            dark,
+            scale,
+            chunk_limit,
+        }
+    }
+
+    /// Correct `value` given the `flat` and `dark` reference values.
+    ///
+    /// A zero flat field value results in a non-finite value.
+    pub fn correct(&self, value: f64, flat: f64, dark: f64) -> f64 {
+        (value - dark) / flat * self.scale
+    }
+
+    pub fn apply_chunk<TOut>(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError>
+    where
+        TOut: Element + Copy + Send + Sync + 'static,
+        f64: AsPrimitive<TOut>,
+    {
+        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
+        let values = progress.read(|| retrieve_values(input, &input_output_subset))?;
+        let flat = progress.read(|| self.flat.retrieve_values(&input_output_subset))?;
+        let dark = self
+            .dark
+            .as_ref()
+            .map(|dark| progress.read(|| dark.retrieve_values(&input_output_subset)))
+            .transpose()?;
+        let elements_out = progress.process(|| {
+            let dark = dark.unwrap_or_else(|| vec![0.0; values.len()]);
+            itertools::izip!(values, flat, dark)
+                .map(|(value, flat, dark)| self.correct(value, flat, dark).as_())
+                .collect::<Vec<TOut>>()
+        });
+        progress.write(|| {
+            output.store_array_subset_elements::<TOut>(&input_output_subset, &elements_out)
+        })?;
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for FlatField {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        for data_type in std::iter::once(chunk_input.data_type())
+            .chain(std::iter::once(chunk_output.data_type()))
+            .chain(std::iter::once(self.flat.array().data_type()))
+            .chain(self.dark.iter().map(|dark| dark.array().data_type()))
+        {
+            match data_type {
+                DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::BFloat16
+                | DataType::Float16
+                | DataType::Float32
+                | DataType::Float64 => {}
+                data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize()
+            * (chunk_input.fixed_element_size().unwrap() + 3 * std::mem::size_of::<f64>())
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+        self.flat.check(input.shape())?;
+        if let Some(dark) = &self.dark {
+            dark.check(input.shape())?;
+        }
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        let output_ref = &*output;
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                macro_rules! apply_output {
+                    ([$( ( $data_type:ident, $t_out:ty ) ),* ]) => {
+                        match output_ref.data_type() {
+                            $(DataType::$data_type => self.apply_chunk::<$t_out>(input, output_ref, &chunk_indices, &progress),)*
+                            _ => panic!()
+                        }
+                    };
+                }
+                apply_output!([
+                    (Int8, i8),
+                    (Int16, i16),
+                    (Int32, i32),
+                    (Int64, i64),
+                    (UInt8, u8),
+                    (UInt16, u16),
+                    (UInt32, u32),
+                    (UInt64, u64),
+                    (BFloat16, half::bf16),
+                    (Float16, half::f16),
+                    (Float32, f32),
+                    (Float64, f64)
+                ])
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn flat_field() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![2, 3],
+            DataType::UInt16,
+            vec![1, 2].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<u16>(&array.subset_all(), &[12, 22, 42, 14, 24, 44])?;
+
+        let flat = ArrayBuilder::new(
+            vec![3],
+            DataType::Float32,
+            vec![3].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(store.clone(), "/flat")?;
+        flat.store_array_subset_elements::<f32>(&flat.subset_all(), &[0.5, 1.0, 2.0])?;
+
+        let dark = ArrayBuilder::new(
+            vec![2, 1],
+            DataType::UInt16,
+            vec![2, 1].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(store.clone(), "/dark")?;
+        dark.store_array_subset_elements::<u16>(&dark.subset_all(), &[2, 4])?;
+
+        let mut array_output = ArrayBuilder::new(
+            vec![2, 3],
+            DataType::Float32,
+            vec![2, 2].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(store.clone(), "/output")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        FlatField::new(
+            BroadcastArray::new(flat, None),
+            Some(BroadcastArray::new(dark, None)),
+            2.0,
+            None,
+        )
+        .apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<f32>(&array_output.subset_all())?;
+        assert_eq!(elements, [40.0, 40.0, 40.0, 40.0, 40.0, 40.0]);
+        Ok(())
+    }
+}
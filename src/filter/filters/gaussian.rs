@@ -1,11 +1,10 @@
 use clap::Parser;
 use itertools::Itertools;
 use ndarray::ArrayD;
-use num_traits::AsPrimitive;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
-    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
+    array::{codec::CodecOptions, data_type::UnsupportedDataTypeError, Array, DataType},
     array_subset::ArraySubset,
     filesystem::FilesystemStore,
 };
@@ -13,12 +12,13 @@ use zarrs::{
 use crate::{
     filter::{
         calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
-        kernel::apply_1d_kernel, ArraySubsetOverlap, FilterArguments, FilterCommonArguments,
+        kernel::apply_1d_kernel, retrieve_ndarray_as, store_ndarray_as, try_for_each_chunk,
+        ArraySubsetOverlap, FilterArguments, FilterCommonArguments, MissingData,
     },
     progress::{Progress, ProgressCallback},
 };
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema, Default)]
 pub struct GaussianArguments {
     /// Gaussian kernel sigma per axis, comma delimited.
     #[arg(required = true, value_delimiter = ',')]
@@ -26,6 +26,12 @@ pub struct GaussianArguments {
     /// Gaussian kernel half size per axis, comma delimited. Kernel is 2 x half size + 1.
     #[arg(required = true, value_delimiter = ',')]
     kernel_half_size: Vec<u64>,
+    /// Treat fill value and NaN input elements as missing data.
+    ///
+    /// Missing elements are excluded from the kernel (normalised convolution), and output elements without any valid elements within the kernel are the output fill value.
+    #[arg(long, default_value_t = false)]
+    #[serde(default)]
+    missing_data: bool,
 }
 
 impl FilterArguments for GaussianArguments {
@@ -40,6 +46,7 @@ impl FilterArguments for GaussianArguments {
         Ok(Box::new(Gaussian::new(
             self.sigma.clone(),
             self.kernel_half_size.clone(),
+            self.missing_data,
             *common_args.chunk_limit(),
         )))
     }
@@ -48,11 +55,17 @@ impl FilterArguments for GaussianArguments {
 pub struct Gaussian {
     kernel: Vec<ndarray::Array1<f32>>,
     kernel_half_size: Vec<u64>,
+    missing_data: bool,
     chunk_limit: Option<usize>,
 }
 
 impl Gaussian {
-    pub fn new(sigma: Vec<f32>, kernel_half_size: Vec<u64>, chunk_limit: Option<usize>) -> Self {
+    pub fn new(
+        sigma: Vec<f32>,
+        kernel_half_size: Vec<u64>,
+        missing_data: bool,
+        chunk_limit: Option<usize>,
+    ) -> Self {
         let kernel = std::iter::zip(&sigma, &kernel_half_size)
             .map(|(sigma, kernel_half_size)| {
                 create_sampled_gaussian_kernel(*sigma, *kernel_half_size)
@@ -61,6 +74,7 @@ impl Gaussian {
         Self {
             kernel,
             kernel_half_size,
+            missing_data,
             chunk_limit,
         }
     }
@@ -69,40 +83,58 @@ impl Gaussian {
         &self.kernel_half_size
     }
 
-    pub fn apply_chunk<TIn, TOut>(
+    pub fn apply_chunk(
         &self,
         input: &Array<FilesystemStore>,
         output: &Array<FilesystemStore>,
         chunk_indices: &[u64],
+        missing_data: Option<&MissingData>,
         progress: &Progress,
-    ) -> Result<(), FilterError>
-    where
-        TIn: ElementOwned + Send + Sync + AsPrimitive<f32>,
-        TOut: Element + Send + Sync + Copy + 'static,
-        f32: AsPrimitive<TOut>,
-    {
+    ) -> Result<(), FilterError> {
         let subset_output = output.chunk_subset_bounded(chunk_indices).unwrap();
         let subset_overlap =
             ArraySubsetOverlap::new(input.shape(), &subset_output, &self.kernel_half_size);
 
-        let input_array = progress
-            .read(|| input.retrieve_array_subset_ndarray::<TIn>(subset_overlap.subset_input()))?;
+        if let Some(missing_data) = missing_data {
+            // Missing values are identified in f64 so that the input fill value is compared exactly
+            let input_array = progress
+                .read(|| retrieve_ndarray_as::<f64>(input, subset_overlap.subset_input()))?;
+            let output_array = progress.process(|| {
+                let valid = missing_data.valid(&input_array);
+                let input_array = input_array.mapv(|x| x as f32);
+                let output_array = self.apply_ndarray_masked(input_array, &valid);
+                let output_array = subset_overlap.extract_subset(&output_array);
+                output_array.mapv(|x| x.map_or_else(|| missing_data.fill_value::<f64>(), f64::from))
+            });
+            drop(input_array);
 
-        let output_array = progress.process(|| {
-            let input_array = input_array.mapv(|x| x.as_()); // par?
-            let output_array = self.apply_ndarray(input_array);
-            let output_array = subset_overlap.extract_subset(&output_array);
-            Ok::<_, FilterError>(output_array.mapv(|x| x.as_())) // par?
-        })?;
-        drop(input_array);
+            progress.write(|| {
+                store_ndarray_as(
+                    output,
+                    subset_output.start(),
+                    &output_array,
+                    &CodecOptions::default(),
+                )
+            })?;
+        } else {
+            let input_array = progress
+                .read(|| retrieve_ndarray_as::<f32>(input, subset_overlap.subset_input()))?;
+            let output_array = progress.process(|| {
+                let output_array = self.apply_ndarray(input_array);
+                subset_overlap.extract_subset(&output_array)
+            });
 
-        progress.write(|| {
-            output
-                .store_array_subset_ndarray::<TOut, _>(subset_output.start(), output_array)
-                .unwrap()
-        });
+            progress.write(|| {
+                store_ndarray_as(
+                    output,
+                    subset_output.start(),
+                    &output_array,
+                    &CodecOptions::default(),
+                )
+            })?;
+        }
 
-        progress.next();
+        progress.next()?;
         Ok(())
     }
 
@@ -116,6 +148,26 @@ impl Gaussian {
         }
         gaussian
     }
+
+    /// Apply the Gaussian kernel to the `valid` elements of `input` (normalised convolution).
+    ///
+    /// Output elements without any valid elements within the kernel are [`None`].
+    pub fn apply_ndarray_masked(
+        &self,
+        input: ndarray::ArrayD<f32>,
+        valid: &ndarray::ArrayD<bool>,
+    ) -> ndarray::ArrayD<Option<f32>> {
+        assert_eq!(input.shape(), valid.shape());
+        let weights: ndarray::ArrayD<f32> = valid.map(|v| if *v { 1.0 } else { 0.0 });
+        let input = ndarray::Zip::from(&input)
+            .and(&weights)
+            .map_collect(|x, w| if *w > 0.0 { x * w } else { 0.0 });
+        let input = self.apply_ndarray(input);
+        let weights = self.apply_ndarray(weights);
+        ndarray::Zip::from(&input)
+            .and(&weights)
+            .map_collect(|x, w| (*w > 0.0).then(|| x / w))
+    }
 }
 
 impl FilterTraits for Gaussian {
@@ -173,75 +225,34 @@ impl FilterTraits for Gaussian {
         progress_callback: &ProgressCallback,
     ) -> Result<(), FilterError> {
         assert_eq!(output.shape(), input.shape());
+        let missing_data = self
+            .missing_data
+            .then(|| MissingData::from_arrays(input, output))
+            .transpose()?;
 
         let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
         let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
         let indices = chunks.indices();
-        rayon_iter_concurrent_limit::iter_concurrent_limit!(
+        try_for_each_chunk(
             chunk_limit,
-            indices,
-            try_for_each,
+            memory_per_chunk,
+            &indices,
             |chunk_indices: Vec<u64>| {
-                macro_rules! apply_output {
-                    ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
-                        match output.data_type() {
-                            $(DataType::$data_type_out => { self.apply_chunk::<$type_in, $type_out>(&input, &output, &chunk_indices, &progress) } ,)*
-                            _ => panic!()
-                        }
-                    };
-                }
-                macro_rules! apply_input {
-                ([$( ( $data_type_in:ident, $type_in:ty ) ),* ]) => {
-                        match input.data_type() {
-                            $(
-                                DataType::$data_type_in => {
-                                    apply_output!($type_in, [
-                                        (Bool, u8),
-                                        (Int8, i8),
-                                        (Int16, i16),
-                                        (Int32, i32),
-                                        (Int64, i64),
-                                        (UInt8, u8),
-                                        (UInt16, u16),
-                                        (UInt32, u32),
-                                        (UInt64, u64),
-                                        (BFloat16, half::bf16),
-                                        (Float16, half::f16),
-                                        (Float32, f32),
-                                        (Float64, f64)
-                                    ]
-                                )}
-                            ,)*
-                            _ => panic!()
-                        }
-                    };
-                }
-                apply_input!([
-                    (Bool, u8),
-                    (Int8, i8),
-                    (Int16, i16),
-                    (Int32, i32),
-                    (Int64, i64),
-                    (UInt8, u8),
-                    (UInt16, u16),
-                    (UInt32, u32),
-                    (UInt64, u64),
-                    (BFloat16, half::bf16),
-                    (Float16, half::f16),
-                    (Float32, f32),
-                    (Float64, f64)
-                ])
-            }
+                self.apply_chunk(
+                    input,
+                    output,
+                    &chunk_indices,
+                    missing_data.as_ref(),
+                    &progress,
+                )
+            },
         )?;
 
         Ok(())
@@ -299,7 +310,7 @@ mod tests {
         let store: FilesystemStore = FilesystemStore::new(path.path())?;
         let mut array_output = array.builder().build(store.into(), "/")?;
         let progress_callback = |_stats: ProgressStats| {};
-        Gaussian::new(vec![1.0; 2], vec![3; 2], None).apply(
+        Gaussian::new(vec![1.0; 2], vec![3; 2], false, None).apply(
             &array,
             &mut array_output,
             &ProgressCallback::new(&progress_callback),
@@ -318,4 +329,19 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn gaussian_masked() {
+        let gaussian = Gaussian::new(vec![1.0], vec![1], true, None);
+        let input = ndarray::array![2.0f32, 0.0, 0.0, 0.0, 4.0].into_dyn();
+        let valid = input.map(|v| *v != 0.0);
+        let output = gaussian.apply_ndarray_masked(input, &valid);
+
+        // Valid elements are not smeared into missing data
+        approx::assert_abs_diff_eq!(output[0].unwrap(), 2.0);
+        approx::assert_abs_diff_eq!(output[1].unwrap(), 2.0);
+        assert_eq!(output[2], None);
+        approx::assert_abs_diff_eq!(output[3].unwrap(), 4.0);
+        approx::assert_abs_diff_eq!(output[4].unwrap(), 4.0);
+    }
 }
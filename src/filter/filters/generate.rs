@@ -0,0 +1,473 @@
+use std::sync::Arc;
+
+use clap::{Parser, ValueEnum};
+use ndarray::{ArrayD, Dimension};
+use num_traits::AsPrimitive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{
+        data_type::UnsupportedDataTypeError, Array, ArrayBuilder, DataType, Element, FillValue,
+    },
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+    storage::store::MemoryStore,
+};
+
+use crate::{
+    auto_chunk_shape,
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    get_array_builder_reencode,
+    progress::{Progress, ProgressCallback},
+    ZarrReencodingArgs,
+};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize, JsonSchema, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum GeneratePattern {
+    /// A constant value.
+    #[default]
+    Constant,
+    /// Uniform noise between low and high.
+    Uniform,
+    /// Normal (Gaussian) noise with a mean and standard deviation.
+    Normal,
+    /// A linear ramp from low to high along an axis.
+    Gradient,
+    /// Alternating squares of low and high.
+    Checkerboard,
+}
+
+fn default_high() -> f64 {
+    1.0
+}
+
+fn default_std() -> f64 {
+    1.0
+}
+
+fn default_period() -> u64 {
+    8
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct GenerateArguments {
+    /// The synthetic pattern.
+    #[arg(value_enum)]
+    pub pattern: GeneratePattern,
+    /// The array shape, comma delimited.
+    #[arg(long, required = true, value_delimiter = ',')]
+    pub shape: Vec<u64>,
+    /// The constant value.
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values(true))]
+    #[serde(default)]
+    pub value: f64,
+    /// The low value of uniform noise, gradients, and checkerboards.
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values(true))]
+    #[serde(default)]
+    pub low: f64,
+    /// The high value of uniform noise, gradients, and checkerboards.
+    #[arg(long, default_value_t = default_high(), allow_hyphen_values(true))]
+    #[serde(default = "default_high")]
+    pub high: f64,
+    /// The mean of normal noise.
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values(true))]
+    #[serde(default)]
+    pub mean: f64,
+    /// The standard deviation of normal noise.
+    #[arg(long, default_value_t = default_std())]
+    #[serde(default = "default_std")]
+    pub std: f64,
+    /// The gradient axis. Defaults to the last axis.
+    #[arg(long)]
+    #[serde(default)]
+    pub axis: Option<usize>,
+    /// The checkerboard square size.
+    #[arg(long, default_value_t = default_period())]
+    #[serde(default = "default_period")]
+    pub period: u64,
+    /// The fraction of the low-order bits of each element that are random, in [0, 1].
+    ///
+    /// This controls the compressibility of the output independently of the pattern.
+    /// 0 retains the pattern, 1 produces random bytes.
+    /// Floating point elements have their low-order mantissa bits randomised first.
+    #[arg(long, default_value_t = 0.0)]
+    #[serde(default)]
+    pub entropy: f64,
+}
+
+impl FilterArguments for GenerateArguments {
+    fn name(&self) -> String {
+        "generate".to_string()
+    }
+
+    fn is_stochastic(&self) -> bool {
+        matches!(
+            self.pattern,
+            GeneratePattern::Uniform | GeneratePattern::Normal
+        ) || self.entropy > 0.0
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        if self.shape.is_empty() {
+            return Err(FilterError::InvalidParameters(
+                "generate shape must not be empty".to_string(),
+            ));
+        }
+        if self.pattern == GeneratePattern::Gradient
+            && self.axis.is_some_and(|axis| axis >= self.shape.len())
+        {
+            return Err(FilterError::InvalidParameters(format!(
+                "generate axis must be less than the dimensionality {}",
+                self.shape.len()
+            )));
+        }
+        if self.period == 0 {
+            return Err(FilterError::InvalidParameters(
+                "generate period must be positive".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.entropy) {
+            return Err(FilterError::InvalidParameters(
+                "generate entropy must be in [0, 1]".to_string(),
+            ));
+        }
+        Ok(Box::new(Generate::new(
+            self,
+            common_args.seed().unwrap_or_else(|| fastrand::u64(..)),
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// Returns a random number generator for the chunk at `chunk_indices`, so that the output is independent of chunk processing order.
+pub(crate) fn chunk_rng(seed: u64, chunk_indices: &[u64]) -> fastrand::Rng {
+    let chunk_seed = chunk_indices.iter().fold(seed, |seed, index| {
+        seed.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            .wrapping_add(*index)
+    });
+    fastrand::Rng::with_seed(chunk_seed)
+}
+
+/// Sample from the standard normal distribution with the Box-Muller transform.
+pub(crate) fn sample_standard_normal(rng: &mut fastrand::Rng) -> f64 {
+    let u1 = 1.0 - rng.f64(); // (0, 1]
+    let u2 = rng.f64();
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
+
+/// Replace the low-order `fraction` of the bits of each element with random bits.
+fn randomise_low_bits<T: bytemuck::Pod>(
+    elements: &mut [T],
+    fraction: f64,
+    rng: &mut fastrand::Rng,
+) {
+    macro_rules! randomise {
+        ($t:ty, $rand:ident) => {{
+            let bits = (fraction * <$t>::BITS as f64).round() as u32;
+            let mask = <$t>::MAX.checked_shr(<$t>::BITS - bits).unwrap_or(0);
+            for element in bytemuck::cast_slice_mut::<T, $t>(elements) {
+                *element = (*element & !mask) | (rng.$rand(..) & mask);
+            }
+        }};
+    }
+    match core::mem::size_of::<T>() {
+        1 => randomise!(u8, u8),
+        2 => randomise!(u16, u16),
+        4 => randomise!(u32, u32),
+        8 => randomise!(u64, u64),
+        _ => unreachable!(),
+    }
+}
+
+/// A generator of synthetic arrays.
+pub struct Generate {
+    arguments: GenerateArguments,
+    seed: u64,
+    chunk_limit: Option<usize>,
+}
+
+impl Generate {
+    pub fn new(arguments: &GenerateArguments, seed: u64, chunk_limit: Option<usize>) -> Self {
+        Self {
+            arguments: arguments.clone(),
+            seed,
+            chunk_limit,
+        }
+    }
+
+    pub fn apply_chunk<TOut>(
+        &self,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError>
+    where
+        TOut: Element + bytemuck::Pod + Send + Sync + Copy + 'static,
+        f64: AsPrimitive<TOut>,
+    {
+        let subset_output = output.chunk_subset_bounded(chunk_indices).unwrap();
+        let output_elements = progress.process(|| {
+            let mut output_elements = self
+                .generate_ndarray(subset_output.start(), subset_output.shape(), chunk_indices)
+                .into_iter()
+                .map(|value| value.as_())
+                .collect::<Vec<TOut>>();
+            if self.arguments.entropy > 0.0 {
+                let mut rng = chunk_rng(!self.seed, chunk_indices);
+                randomise_low_bits(&mut output_elements, self.arguments.entropy, &mut rng);
+            }
+            output_elements
+        });
+        progress.write(|| {
+            output.store_array_subset_elements::<TOut>(&subset_output, &output_elements)
+        })?;
+        progress.next()?;
+        Ok(())
+    }
+
+    /// Generate the values of the subset with `start` and `shape` of the chunk at `chunk_indices`.
+    pub fn generate_ndarray(
+        &self,
+        start: &[u64],
+        shape: &[u64],
+        chunk_indices: &[u64],
+    ) -> ArrayD<f64> {
+        let shape = shape
+            .iter()
+            .map(|size| usize::try_from(*size).unwrap())
+            .collect::<Vec<_>>();
+        let args = &self.arguments;
+        let mut rng = chunk_rng(self.seed, chunk_indices);
+        match args.pattern {
+            GeneratePattern::Constant => ArrayD::from_elem(shape, args.value),
+            GeneratePattern::Uniform => ArrayD::from_shape_simple_fn(shape, || {
+                args.low + (args.high - args.low) * rng.f64()
+            }),
+            GeneratePattern::Normal => ArrayD::from_shape_simple_fn(shape, || {
+                args.mean + args.std * sample_standard_normal(&mut rng)
+            }),
+            GeneratePattern::Gradient => {
+                let axis = args.axis.unwrap_or(args.shape.len() - 1);
+                let length = args.shape[axis].saturating_sub(1).max(1) as f64;
+                ArrayD::from_shape_fn(shape, |index| {
+                    let position = (start[axis] + index[axis] as u64) as f64 / length;
+                    args.low + (args.high - args.low) * position
+                })
+            }
+            GeneratePattern::Checkerboard => ArrayD::from_shape_fn(shape, |index| {
+                let parity = start
+                    .iter()
+                    .zip(index.slice())
+                    .map(|(start, index)| (start + *index as u64) / args.period)
+                    .sum::<u64>()
+                    % 2;
+                if parity == 0 {
+                    args.low
+                } else {
+                    args.high
+                }
+            }),
+        }
+    }
+}
+
+impl FilterTraits for Generate {
+    fn is_compatible(
+        &self,
+        _chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        if self.arguments.entropy > 0.0 && chunk_output.data_type() == &DataType::Bool {
+            return Err(FilterError::InvalidParameters(
+                "generate entropy is not supported for the bool data type".to_string(),
+            ));
+        }
+        match chunk_output.data_type() {
+            DataType::Bool
+            | DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float16
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::BFloat16 => {}
+            data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        };
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        _chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_output.num_elements_usize()
+            * (core::mem::size_of::<f64>() + chunk_output.data_type().fixed_size().unwrap())
+    }
+
+    fn generator_array_builder(
+        &self,
+        reencoding_args: &ZarrReencodingArgs,
+    ) -> Result<ArrayBuilder, FilterError> {
+        // A float32 template array with an automatic chunk shape, which is reencoded with the reencoding arguments
+        let chunk_shape = auto_chunk_shape(&self.arguments.shape, 4, 4 * 1024 * 1024);
+        let template = ArrayBuilder::new(
+            self.arguments.shape.clone(),
+            DataType::Float32,
+            chunk_shape.try_into().unwrap(),
+            FillValue::from(0.0f32),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        Ok(get_array_builder_reencode(
+            reencoding_args,
+            &template,
+            None,
+        )?)
+    }
+
+    fn generate(
+        &self,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), self.arguments.shape);
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let chunk_representation =
+            output.chunk_array_representation(&vec![0; output.dimensionality()])?;
+        let memory_per_chunk = self.memory_per_chunk(&chunk_representation, &chunk_representation);
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                macro_rules! apply_output {
+                    ([$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
+                        match output.data_type() {
+                            $(DataType::$data_type_out => { self.apply_chunk::<$type_out>(&output, &chunk_indices, &progress) } ,)*
+                            _ => panic!()
+                        }
+                    };
+                }
+                apply_output!([
+                    (Bool, u8),
+                    (Int8, i8),
+                    (Int16, i16),
+                    (Int32, i32),
+                    (Int64, i64),
+                    (UInt8, u8),
+                    (UInt16, u16),
+                    (UInt32, u32),
+                    (UInt64, u64),
+                    (BFloat16, half::bf16),
+                    (Float16, half::f16),
+                    (Float32, f32),
+                    (Float64, f64)
+                ])
+            },
+        )
+    }
+
+    fn apply(
+        &self,
+        _input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        self.generate(output, progress_callback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::ProgressStats;
+
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn generate_checkerboard() -> Result<(), Box<dyn Error>> {
+        let arguments = GenerateArguments::parse_from([
+            "generate",
+            "checkerboard",
+            "--shape",
+            "8,6",
+            "--low",
+            "1",
+            "--high",
+            "2",
+            "--period",
+            "2",
+        ]);
+        let generate = Generate::new(&arguments, 0, None);
+        let reencoding_args = ZarrReencodingArgs::parse_from([
+            "reencode",
+            "--data-type",
+            "uint8",
+            "--chunk-shape",
+            "3,4",
+        ]);
+        let path = tempfile::TempDir::new()?;
+        let store = FilesystemStore::new(path.path())?;
+        let mut array = generate
+            .generator_array_builder(&reencoding_args)?
+            .build(store.into(), "/")?;
+        assert_eq!(array.data_type(), &DataType::UInt8);
+        let progress_callback = |_stats: ProgressStats| {};
+        generate.generate(&mut array, &ProgressCallback::new(&progress_callback))?;
+
+        let elements = array.retrieve_array_subset_ndarray::<u8>(&array.subset_all())?;
+        let elements_ref = ndarray::Array2::from_shape_fn((8, 6), |(y, x)| {
+            if (y / 2 + x / 2) % 2 == 0 {
+                1u8
+            } else {
+                2u8
+            }
+        })
+        .into_dyn();
+        assert_eq!(elements, elements_ref);
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_uniform_reproducible() {
+        let arguments =
+            GenerateArguments::parse_from(["generate", "uniform", "--shape", "16", "--low", "-1"]);
+        let generate = Generate::new(&arguments, 42, None);
+        let a = generate.generate_ndarray(&[0], &[16], &[0]);
+        let b = generate.generate_ndarray(&[0], &[16], &[0]);
+        assert_eq!(a, b);
+        assert!(a.iter().all(|value| (-1.0..1.0).contains(value)));
+    }
+
+    #[test]
+    fn generate_entropy() {
+        let mut rng = fastrand::Rng::with_seed(0);
+        let mut elements = vec![0xABCDu16; 256];
+        randomise_low_bits(&mut elements, 0.0, &mut rng);
+        assert!(elements.iter().all(|element| *element == 0xABCD));
+        randomise_low_bits(&mut elements, 0.5, &mut rng);
+        assert!(elements.iter().all(|element| element & 0xFF00 == 0xAB00));
+        assert!(elements.iter().any(|element| element & 0x00FF != 0xCD));
+    }
+}
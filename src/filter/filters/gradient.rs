@@ -0,0 +1,297 @@
+use clap::Parser;
+use ndarray::ArrayD;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{codec::CodecOptions, data_type::UnsupportedDataTypeError, Array, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, retrieve_ndarray_as, store_ndarray_as, try_for_each_chunk,
+        ArraySubsetOverlap,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+use crate::filter::{
+    filter_error::FilterError, filter_traits::FilterTraits, FilterArguments, FilterCommonArguments,
+};
+
+use super::gradient_magnitude::GradientMagnitudeOperator;
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema, Default)]
+pub struct GradientArguments {
+    /// Gradient operator (kernel).
+    #[arg(long)]
+    #[clap(value_enum, default_value_t=GradientMagnitudeOperator::Sobel)]
+    pub operator: GradientMagnitudeOperator,
+}
+
+impl FilterArguments for GradientArguments {
+    fn name(&self) -> String {
+        "gradient".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        Ok(Box::new(Gradient::new(self, *common_args.chunk_limit())))
+    }
+}
+
+/// The per-axis partial derivatives of an array.
+///
+/// The output has a trailing dimension holding the partial derivative along each input axis.
+pub struct Gradient {
+    operator: GradientMagnitudeOperator,
+    chunk_limit: Option<usize>,
+}
+
+impl Gradient {
+    pub fn new(arguments: &GradientArguments, chunk_limit: Option<usize>) -> Self {
+        Self {
+            operator: arguments.operator,
+            chunk_limit,
+        }
+    }
+
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        // Determine the input and output subset, the trailing output axis holds the gradient components
+        let subset_output = output.chunk_subset_bounded(chunk_indices).unwrap();
+        let dimensionality = input.dimensionality();
+        let subset_output_spatial = ArraySubset::new_with_start_shape(
+            subset_output.start()[..dimensionality].to_vec(),
+            subset_output.shape()[..dimensionality].to_vec(),
+        )
+        .unwrap();
+        let axes = subset_output.start()[dimensionality] as usize
+            ..subset_output.end_exc()[dimensionality] as usize;
+        let subset_overlap = ArraySubsetOverlap::new(
+            input.shape(),
+            &subset_output_spatial,
+            &vec![1; dimensionality],
+        );
+
+        let input_array =
+            progress.read(|| retrieve_ndarray_as::<f32>(input, subset_overlap.subset_input()))?;
+
+        let gradient = progress.process(|| {
+            let gradient = self.apply_ndarray(&input_array, axes);
+            let gradient = gradient
+                .iter()
+                .map(|gradient| subset_overlap.extract_subset(gradient))
+                .collect::<Vec<_>>();
+            ndarray::stack(
+                ndarray::Axis(dimensionality),
+                &gradient.iter().map(ArrayD::view).collect::<Vec<_>>(),
+            )
+            .unwrap()
+        });
+        drop(input_array);
+
+        progress.write(|| {
+            store_ndarray_as(
+                output,
+                subset_output.start(),
+                &gradient,
+                &CodecOptions::default(),
+            )
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+
+    /// Return the partial derivatives of `input` along each of `axes`.
+    pub fn apply_ndarray(
+        &self,
+        input: &ndarray::ArrayD<f32>,
+        axes: std::ops::Range<usize>,
+    ) -> Vec<ndarray::ArrayD<f32>> {
+        let mut staging = ArrayD::<f32>::zeros(input.shape());
+        axes.map(|axis| {
+            let mut gradient = ArrayD::<f32>::zeros(input.shape());
+            self.operator
+                .apply_axis(input, axis, &mut staging, &mut gradient);
+            gradient
+        })
+        .collect()
+    }
+}
+
+impl FilterTraits for Gradient {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Bool
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float16
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::BFloat16 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        let num_input_elements = usize::try_from(
+            chunk_input
+                .shape()
+                .iter()
+                .map(|s| s.get() + 2)
+                .product::<u64>(),
+        )
+        .unwrap();
+        let num_components = chunk_output.shape().last().unwrap().get() as usize;
+        let num_output_elements = chunk_output.num_elements_usize();
+        num_input_elements
+            * (chunk_input.data_type().fixed_size().unwrap()
+                + core::mem::size_of::<f32>() * (2 + num_components))
+            + num_output_elements
+                * (core::mem::size_of::<f32>() * 2 + chunk_output.data_type().fixed_size().unwrap())
+    }
+
+    fn output_axes(
+        &self,
+        array_input: &Array<FilesystemStore>,
+    ) -> Result<Option<Vec<Option<usize>>>, FilterError> {
+        Ok(Some(
+            (0..array_input.dimensionality())
+                .map(Some)
+                .chain(std::iter::once(None))
+                .collect(),
+        ))
+    }
+
+    fn output_shape(&self, array_input: &Array<FilesystemStore>) -> Option<Vec<u64>> {
+        let mut shape = array_input.shape().to_vec();
+        shape.push(array_input.dimensionality() as u64);
+        Some(shape)
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(Some(output.shape().to_vec()), self.output_shape(input));
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; output.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| self.apply_chunk(input, output, &chunk_indices, &progress),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        filter::filters::gradient_magnitude::{GradientMagnitude, GradientMagnitudeArguments},
+        progress::ProgressStats,
+    };
+
+    use super::*;
+    use std::error::Error;
+    use zarrs::array::ArrayBuilder;
+
+    #[test]
+    fn gradient() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = FilesystemStore::new(path.path())?;
+        let array = ArrayBuilder::new(
+            vec![4, 4],
+            DataType::Float32,
+            vec![2, 2].try_into()?,
+            0.0f32.into(),
+        )
+        .build(store.into(), "/")?;
+        let array_subset = array.subset_all();
+        array.store_array_subset_elements(
+            &array_subset,
+            &(0..array_subset.num_elements_usize())
+                .map(|u| {
+                    (2 * (u / array.shape()[1] as usize) + u % array.shape()[1] as usize) as f32
+                })
+                .collect::<Vec<f32>>(),
+        )?;
+
+        let arguments = GradientArguments {
+            operator: GradientMagnitudeOperator::CentralDifference,
+        };
+        let gradient = Gradient::new(&arguments, None);
+        let path = tempfile::TempDir::new()?;
+        let store: FilesystemStore = FilesystemStore::new(path.path())?;
+        let mut array_output = gradient
+            .output_array_builder(&array, &Default::default())?
+            .build(store.into(), "/")?;
+        assert_eq!(array_output.shape(), &[4, 4, 2]);
+        let progress_callback = |_stats: ProgressStats| {};
+        gradient.apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_ndarray::<f32>(&array_output.subset_all())?;
+
+        // The gradient is (2, 1) away from the edges
+        let interior = elements.slice(ndarray::s![1..3, 1..3, ..]);
+        approx::assert_abs_diff_eq!(
+            interior.to_owned().into_dyn(),
+            ndarray::array![[[2.0, 1.0], [2.0, 1.0]], [[2.0, 1.0], [2.0, 1.0]]].into_dyn()
+        );
+
+        // The gradient magnitude is the norm of the gradient
+        let magnitude = GradientMagnitude::new(
+            &GradientMagnitudeArguments {
+                operator: GradientMagnitudeOperator::CentralDifference,
+            },
+            None,
+        )
+        .apply_ndarray(&array.retrieve_array_subset_ndarray::<f32>(&array_subset)?);
+        let magnitude_from_gradient = elements.map_axis(ndarray::Axis(2), |g| g.dot(&g).sqrt());
+        approx::assert_abs_diff_eq!(magnitude, magnitude_from_gradient, epsilon = 1e-6);
+
+        Ok(())
+    }
+}
@@ -1,16 +1,18 @@
 use clap::{Parser, ValueEnum};
 use ndarray::ArrayD;
-use num_traits::AsPrimitive;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
-    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
+    array::{codec::CodecOptions, data_type::UnsupportedDataTypeError, Array, DataType},
     array_subset::ArraySubset,
     filesystem::FilesystemStore,
 };
 
 use crate::{
-    filter::{calculate_chunk_limit, ArraySubsetOverlap},
+    filter::{
+        calculate_chunk_limit, retrieve_ndarray_as, store_ndarray_as, try_for_each_chunk,
+        ArraySubsetOverlap,
+    },
     progress::{Progress, ProgressCallback},
 };
 
@@ -21,14 +23,46 @@ use crate::filter::{
     FilterArguments, FilterCommonArguments,
 };
 
-#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize, JsonSchema, Default)]
 pub enum GradientMagnitudeOperator {
     #[default]
     Sobel,
     CentralDifference,
 }
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize, Default)]
+impl GradientMagnitudeOperator {
+    /// Compute the partial derivative of `input` along `axis` into `output`.
+    ///
+    /// `staging` is a scratch array with the same shape as `input`.
+    pub fn apply_axis(
+        self,
+        input: &ArrayD<f32>,
+        axis: usize,
+        staging: &mut ArrayD<f32>,
+        output: &mut ArrayD<f32>,
+    ) {
+        match self {
+            GradientMagnitudeOperator::Sobel => {
+                staging.assign(input);
+                for i in 0..input.ndim() {
+                    if i == axis {
+                        apply_1d_difference_operator(i, staging, output);
+                    } else {
+                        apply_1d_triangle_filter(i, staging, output);
+                    }
+                    if i != input.ndim() - 1 {
+                        std::mem::swap(staging, output);
+                    }
+                }
+            }
+            GradientMagnitudeOperator::CentralDifference => {
+                apply_1d_difference_operator(axis, input, output);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema, Default)]
 pub struct GradientMagnitudeArguments {
     /// Gradient magnitude operator (kernel).
     #[arg(long)]
@@ -65,18 +99,13 @@ impl GradientMagnitude {
         }
     }
 
-    pub fn apply_chunk<TIn, TOut>(
+    pub fn apply_chunk(
         &self,
         input: &Array<FilesystemStore>,
         output: &Array<FilesystemStore>,
         chunk_indices: &[u64],
         progress: &Progress,
-    ) -> Result<(), FilterError>
-    where
-        TIn: ElementOwned + AsPrimitive<f32>,
-        TOut: Element + Copy + 'static,
-        f32: AsPrimitive<TOut>,
-    {
+    ) -> Result<(), FilterError> {
         // Determine the input and output subset
         let subset_output = output.chunk_subset_bounded(chunk_indices).unwrap();
         let subset_overlap = ArraySubsetOverlap::new(
@@ -85,61 +114,39 @@ impl GradientMagnitude {
             &vec![1; input.dimensionality()],
         );
 
-        let input_array = progress
-            .read(|| input.retrieve_array_subset_ndarray::<TIn>(subset_overlap.subset_input()))?;
+        let input_array =
+            progress.read(|| retrieve_ndarray_as::<f32>(input, subset_overlap.subset_input()))?;
 
         let gradient_magnitude = progress.process(|| {
-            let input_array_f32 = input_array.map(|x| x.as_());
-            let gradient_magnitude = self.apply_ndarray(&input_array_f32);
-            let gradient_magnitude = subset_overlap.extract_subset(&gradient_magnitude);
-            gradient_magnitude.map(|x| x.as_())
+            let gradient_magnitude = self.apply_ndarray(&input_array);
+            subset_overlap.extract_subset(&gradient_magnitude)
         });
         drop(input_array);
 
         progress.write(|| {
-            output
-                .store_array_subset_ndarray::<TOut, _>(subset_output.start(), gradient_magnitude)
-                .unwrap()
-        });
-
-        progress.next();
+            store_ndarray_as(
+                output,
+                subset_output.start(),
+                &gradient_magnitude,
+                &CodecOptions::default(),
+            )
+        })?;
+
+        progress.next()?;
         Ok(())
     }
 
     pub fn apply_ndarray(&self, input: &ndarray::ArrayD<f32>) -> ndarray::ArrayD<f32> {
+        let mut staging_in = ArrayD::<f32>::zeros(input.shape());
         let mut staging_out = ArrayD::<f32>::zeros(input.shape());
         let mut gradient_magnitude = ArrayD::<f32>::zeros(input.shape());
 
-        match self.operator {
-            GradientMagnitudeOperator::Sobel => {
-                let mut staging_in = ArrayD::<f32>::zeros(input.shape());
-                for axis in 0..input.ndim() {
-                    staging_in.assign(input);
-                    for i in 0..input.ndim() {
-                        if i == axis {
-                            apply_1d_difference_operator(i, &staging_in, &mut staging_out);
-                        } else {
-                            apply_1d_triangle_filter(i, &staging_in, &mut staging_out);
-                        }
-                        if i != input.ndim() - 1 {
-                            std::mem::swap(&mut staging_in, &mut staging_out);
-                        }
-                    }
-
-                    ndarray::Zip::from(&mut gradient_magnitude)
-                        .and(&staging_out)
-                        .par_for_each(|g, &s| *g += s * s);
-                }
-            }
-            GradientMagnitudeOperator::CentralDifference => {
-                for axis in 0..input.ndim() {
-                    apply_1d_difference_operator(axis, input, &mut staging_out);
-
-                    ndarray::Zip::from(&mut gradient_magnitude)
-                        .and(&staging_out)
-                        .par_for_each(|g, &s| *g += s * s);
-                }
-            }
+        for axis in 0..input.ndim() {
+            self.operator
+                .apply_axis(input, axis, &mut staging_in, &mut staging_out);
+            ndarray::Zip::from(&mut gradient_magnitude)
+                .and(&staging_out)
+                .par_for_each(|g, &s| *g += s * s);
         }
         gradient_magnitude.map_inplace(|x| *x = x.sqrt());
 
@@ -205,71 +212,18 @@ impl FilterTraits for GradientMagnitude {
         let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
         let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
         let indices = chunks.indices();
-        rayon_iter_concurrent_limit::iter_concurrent_limit!(
+        try_for_each_chunk(
             chunk_limit,
-            indices,
-            try_for_each,
-            |chunk_indices: Vec<u64>| {
-                macro_rules! apply_output {
-                    ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
-                        match output.data_type() {
-                            $(DataType::$data_type_out => { self.apply_chunk::<$type_in, $type_out>(input, output, &chunk_indices, &progress) } ,)*
-                            _ => panic!()
-                        }
-                    };
-                }
-                macro_rules! apply_input {
-                    ([$( ( $data_type_in:ident, $type_in:ty ) ),* ]) => {
-                            match input.data_type() {
-                                $(
-                                    DataType::$data_type_in => {
-                                        apply_output!($type_in, [
-                                            (Bool, u8),
-                                            (Int8, i8),
-                                            (Int16, i16),
-                                            (Int32, i32),
-                                            (Int64, i64),
-                                            (UInt8, u8),
-                                            (UInt16, u16),
-                                            (UInt32, u32),
-                                            (UInt64, u64),
-                                            (BFloat16, half::bf16),
-                                            (Float16, half::f16),
-                                            (Float32, f32),
-                                            (Float64, f64)
-                                        ]
-                                    )}
-                                ,)*
-                                _ => panic!()
-                            }
-                        };
-                    }
-                apply_input!([
-                    (Bool, u8),
-                    (Int8, i8),
-                    (Int16, i16),
-                    (Int32, i32),
-                    (Int64, i64),
-                    (UInt8, u8),
-                    (UInt16, u16),
-                    (UInt32, u32),
-                    (UInt64, u64),
-                    (BFloat16, half::bf16),
-                    (Float16, half::f16),
-                    (Float32, f32),
-                    (Float64, f64)
-                ])
-            }
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| self.apply_chunk(input, output, &chunk_indices, &progress),
         )
     }
 }
@@ -279,7 +233,7 @@ mod tests {
     use crate::progress::ProgressStats;
 
     use super::*;
-    use std::error::Error;
+    use std::{error::Error, f32::consts::SQRT_2};
     use zarrs::array::ArrayBuilder;
 
     #[test]
@@ -318,8 +272,8 @@ mod tests {
 
         let elements_ref: ndarray::ArrayD<f32> = ndarray::array![
             [0.70710677, 1.118034, 1.118034, 0.70710677],
-            [1.118034, 1.4142135, 1.4142135, 1.118034],
-            [1.118034, 1.4142135, 1.4142135, 1.118034],
+            [1.118034, SQRT_2, SQRT_2, 1.118034],
+            [1.118034, SQRT_2, SQRT_2, 1.118034],
             [0.70710677, 1.118034, 1.118034, 0.70710677]
         ]
         .into_dyn();
@@ -1,11 +1,11 @@
 use clap::Parser;
-use num_traits::AsPrimitive;
 use rayon::iter::{
     IndexedParallelIterator, IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator,
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
-    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
+    array::{codec::CodecOptions, data_type::UnsupportedDataTypeError, Array, DataType},
     array_subset::ArraySubset,
     filesystem::FilesystemStore,
 };
@@ -14,12 +14,13 @@ use crate::filter::filters::summed_area_table::{summed_area_table, summed_area_t
 use crate::{
     filter::{
         calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
-        ArraySubsetOverlap, FilterArguments, FilterCommonArguments,
+        retrieve_ndarray_as, store_ndarray_as, try_for_each_chunk, ArraySubsetOverlap,
+        FilterArguments, FilterCommonArguments,
     },
     progress::{Progress, ProgressCallback},
 };
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
 pub struct GuidedFilterArguments {
     /// Guided filter "epsilon".
     #[arg(required = true)]
@@ -69,18 +70,13 @@ impl GuidedFilter {
         self.radius
     }
 
-    pub fn apply_chunk<TIn, TOut>(
+    pub fn apply_chunk(
         &self,
         input: &Array<FilesystemStore>,
         output: &Array<FilesystemStore>,
         chunk_indices: &[u64],
         progress: &Progress,
-    ) -> Result<(), FilterError>
-    where
-        TIn: ElementOwned + Send + Sync + AsPrimitive<f32>,
-        TOut: Element + Send + Sync + Copy + 'static,
-        f32: AsPrimitive<TOut>,
-    {
+    ) -> Result<(), FilterError> {
         let subset_output = output.chunk_subset_bounded(chunk_indices).unwrap();
         let subset_overlap = ArraySubsetOverlap::new(
             input.shape(),
@@ -89,24 +85,24 @@ impl GuidedFilter {
             &vec![(self.radius * 2) as u64; input.dimensionality()],
         );
 
-        let input_array = progress
-            .read(|| input.retrieve_array_subset_ndarray::<TIn>(subset_overlap.subset_input()))?;
+        let input_array =
+            progress.read(|| retrieve_ndarray_as::<f32>(input, subset_overlap.subset_input()))?;
 
-        let output_array = progress.process(|| {
-            let input_array = input_array.mapv(|x| x.as_()); // par?
+        let output_array = progress.process_step("guided_filter", || {
             let output_array = self.apply_ndarray(input_array);
-            let output_array = subset_overlap.extract_subset(&output_array);
-            Ok::<_, FilterError>(output_array.mapv(|x| x.as_())) // par?
-        })?;
-        drop(input_array);
+            subset_overlap.extract_subset(&output_array)
+        });
 
         progress.write(|| {
-            output
-                .store_array_subset_ndarray::<TOut, _>(subset_output.start(), output_array)
-                .unwrap()
-        });
+            store_ndarray_as(
+                output,
+                subset_output.start(),
+                &output_array,
+                &CodecOptions::default(),
+            )
+        })?;
 
-        progress.next();
+        progress.next()?;
         Ok(())
     }
 
@@ -245,71 +241,18 @@ impl FilterTraits for GuidedFilter {
         let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
         let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
         let indices = chunks.indices();
-        rayon_iter_concurrent_limit::iter_concurrent_limit!(
+        try_for_each_chunk(
             chunk_limit,
-            indices,
-            try_for_each,
-            |chunk_indices: Vec<u64>| {
-                macro_rules! apply_output {
-                    ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
-                        match output.data_type() {
-                            $(DataType::$data_type_out => { self.apply_chunk::<$type_in, $type_out>(&input, &output, &chunk_indices, &progress) } ,)*
-                            _ => panic!()
-                        }
-                    };
-                }
-                macro_rules! apply_input {
-                ([$( ( $data_type_in:ident, $type_in:ty ) ),* ]) => {
-                        match input.data_type() {
-                            $(
-                                DataType::$data_type_in => {
-                                    apply_output!($type_in, [
-                                        (Bool, u8),
-                                        (Int8, i8),
-                                        (Int16, i16),
-                                        (Int32, i32),
-                                        (Int64, i64),
-                                        (UInt8, u8),
-                                        (UInt16, u16),
-                                        (UInt32, u32),
-                                        (UInt64, u64),
-                                        (BFloat16, half::bf16),
-                                        (Float16, half::f16),
-                                        (Float32, f32),
-                                        (Float64, f64)
-                                    ]
-                                )}
-                            ,)*
-                            _ => panic!()
-                        }
-                    };
-                }
-                apply_input!([
-                    (Bool, u8),
-                    (Int8, i8),
-                    (Int16, i16),
-                    (Int32, i32),
-                    (Int64, i64),
-                    (UInt8, u8),
-                    (UInt16, u16),
-                    (UInt32, u32),
-                    (UInt64, u64),
-                    (BFloat16, half::bf16),
-                    (Float16, half::f16),
-                    (Float32, f32),
-                    (Float64, f64)
-                ])
-            }
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| self.apply_chunk(input, output, &chunk_indices, &progress),
         )?;
 
         Ok(())
@@ -0,0 +1,348 @@
+use clap::Parser;
+use ndarray::{ArrayViewD, Dimension, Slice};
+use num_traits::AsPrimitive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, ArraySubsetOverlap, FilterArguments, FilterCommonArguments,
+        FilterTable,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct LocalMaximaArguments {
+    /// The minimum value of a peak.
+    #[arg(long, allow_hyphen_values(true))]
+    pub threshold: Option<f64>,
+
+    /// The minimum separation of peaks, in elements.
+    ///
+    /// A peak is the maximum within a window extending this distance along each axis.
+    /// Ties are resolved in favour of the peak with the lowest coordinates, so peaks are always further apart than this distance along some axis.
+    #[arg(long, default_value_t = 1)]
+    #[serde(default = "default_min_distance")]
+    pub min_distance: u64,
+}
+
+fn default_min_distance() -> u64 {
+    1
+}
+
+impl FilterArguments for LocalMaximaArguments {
+    fn name(&self) -> String {
+        "local maxima".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        if self.min_distance == 0 {
+            return Err(FilterError::InvalidParameters(
+                "the minimum distance must be positive".to_string(),
+            ));
+        }
+        Ok(Box::new(LocalMaxima::new(
+            self.threshold,
+            self.min_distance,
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// Returns the coordinates of the local maxima of `values` with coordinates in `region`.
+///
+/// Elements outside of `values` and NaN elements are ignored.
+pub fn local_maxima(
+    values: &ArrayViewD<f64>,
+    region: &ArraySubset,
+    threshold: Option<f64>,
+    min_distance: usize,
+) -> Vec<Vec<usize>> {
+    let shape = values.shape();
+    let mut maxima = vec![];
+    for (coordinates, &value) in values.indexed_iter() {
+        let coordinates = coordinates.slice();
+        if value.is_nan()
+            || threshold.is_some_and(|threshold| value < threshold)
+            || !itertools::izip!(coordinates, region.start(), region.end_exc())
+                .all(|(c, start, end)| (*start..end).contains(&(*c as u64)))
+        {
+            continue;
+        }
+        let window_start = coordinates
+            .iter()
+            .map(|c| c.saturating_sub(min_distance))
+            .collect::<Vec<_>>();
+        let window = values.slice_each_axis(|axis| {
+            let i = axis.axis.index();
+            Slice::from(window_start[i]..(coordinates[i] + min_distance + 1).min(shape[i]))
+        });
+        let is_maximum = window.indexed_iter().all(|(neighbour, &neighbour_value)| {
+            if neighbour_value.is_nan() || neighbour_value < value {
+                true
+            } else if neighbour_value > value {
+                false
+            } else {
+                // Ties are resolved in favour of the lowest coordinates
+                let ordering = std::iter::zip(neighbour.slice(), &window_start)
+                    .map(|(n, start)| n + start)
+                    .cmp(coordinates.iter().copied());
+                ordering.is_ge()
+            }
+        });
+        if is_maximum {
+            maxima.push(coordinates.to_vec());
+        }
+    }
+    maxima
+}
+
+/// Detect local maxima (peaks) above a threshold with a minimum separation.
+pub struct LocalMaxima {
+    threshold: Option<f64>,
+    min_distance: u64,
+    chunk_limit: Option<usize>,
+}
+
+impl LocalMaxima {
+    pub fn new(threshold: Option<f64>, min_distance: u64, chunk_limit: Option<usize>) -> Self {
+        Self {
+            threshold,
+            min_distance,
+            chunk_limit,
+        }
+    }
+
+    /// Returns the rows (coordinates and value) of the local maxima in a chunk of the input.
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<Vec<Vec<f64>>, FilterError> {
+        let subset_chunk = input.chunk_subset_bounded(chunk_indices).unwrap();
+        let subset_overlap = ArraySubsetOverlap::new(
+            input.shape(),
+            &subset_chunk,
+            &vec![self.min_distance; input.dimensionality()],
+        );
+        let subset_input = subset_overlap.subset_input();
+        let values = progress.read(|| retrieve_values(input, subset_input))?;
+
+        let rows = progress.process(|| {
+            let values = ArrayViewD::from_shape(subset_input.shape_usize(), &values).unwrap();
+            let region = ArraySubset::new_with_start_shape(
+                std::iter::zip(subset_chunk.start(), subset_input.start())
+                    .map(|(start, start_input)| start - start_input)
+                    .collect(),
+                subset_chunk.shape().to_vec(),
+            )
+            .unwrap();
+            local_maxima(&values, &region, self.threshold, self.min_distance as usize)
+                .into_iter()
+                .map(|coordinates| {
+                    let value = values[coordinates.as_slice()];
+                    std::iter::zip(&coordinates, subset_input.start())
+                        .map(|(c, start)| (*c as u64 + start) as f64)
+                        .chain(std::iter::once(value))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        });
+
+        progress.next()?;
+        Ok(rows)
+    }
+}
+
+/// Retrieve the elements of a numeric array in `subset` as `f64`.
+pub fn retrieve_values(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+) -> Result<Vec<f64>, FilterError> {
+    macro_rules! retrieve {
+        ( $( ( $data_type:ident, $t:ty ) ),* ) => {
+            match array.data_type() {
+                $(DataType::$data_type => Ok(array
+                    .retrieve_array_subset_elements::<$t>(subset)?
+                    .into_iter()
+                    .map(AsPrimitive::<f64>::as_)
+                    .collect()),)*
+                data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            }
+        };
+    }
+    retrieve!(
+        (Int8, i8),
+        (Int16, i16),
+        (Int32, i32),
+        (Int64, i64),
+        (UInt8, u8),
+        (UInt16, u16),
+        (UInt32, u32),
+        (UInt64, u64),
+        (BFloat16, half::bf16),
+        (Float16, half::f16),
+        (Float32, f32),
+        (Float64, f64)
+    )
+}
+
+impl FilterTraits for LocalMaxima {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        _chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        match chunk_input.data_type() {
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::BFloat16
+            | DataType::Float16
+            | DataType::Float32
+            | DataType::Float64 => Ok(()),
+            data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        }
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        _chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        // The f64 values of a chunk with its overlap
+        chunk_input
+            .shape()
+            .iter()
+            .map(|chunk_size| usize::try_from(chunk_size.get() + 2 * self.min_distance).unwrap())
+            .product::<usize>()
+            * std::mem::size_of::<f64>()
+    }
+
+    fn apply(
+        &self,
+        _input: &Array<FilesystemStore>,
+        _output: &mut Array<FilesystemStore>,
+        _progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        Err(FilterError::Other(
+            "the local maxima filter outputs a table".to_string(),
+        ))
+    }
+
+    fn analyse(
+        &self,
+        input: &Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<FilterTable, FilterError> {
+        let chunks = ArraySubset::new_with_shape(input.chunk_grid_shape().unwrap());
+        let chunk_input = input.chunk_array_representation(&vec![0; input.dimensionality()])?;
+        let memory_per_chunk = self.memory_per_chunk(&chunk_input, &chunk_input);
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+        let indices = chunks.indices();
+        let rows = std::sync::Mutex::new(Vec::new());
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                let chunk_rows = self.apply_chunk(input, &chunk_indices, &progress)?;
+                rows.lock().unwrap().extend(chunk_rows);
+                Ok::<_, FilterError>(())
+            },
+        )?;
+
+        let columns = match input.dimension_names() {
+            Some(dimension_names) => dimension_names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    name.as_str()
+                        .map_or_else(|| format!("axis{i}"), str::to_string)
+                })
+                .collect(),
+            None => (0..input.dimensionality())
+                .map(|i| format!("axis{i}"))
+                .collect::<Vec<_>>(),
+        };
+        let mut table = FilterTable::new(
+            columns
+                .into_iter()
+                .chain(std::iter::once("value".to_string()))
+                .collect(),
+        );
+        table.extend(rows.into_inner().unwrap());
+        table.sort();
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, sync::Arc};
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn local_maxima_ties() {
+        #[rustfmt::skip]
+        let values = [
+            1.0, 3.0, 3.0, 0.0,
+            0.0, 0.0, 0.0, f64::NAN,
+            5.0, 0.0, 0.0, 2.0,
+        ];
+        let values = ArrayViewD::from_shape(vec![3, 4], &values).unwrap();
+        let region = ArraySubset::new_with_shape(vec![3, 4]);
+        assert_eq!(
+            local_maxima(&values, &region, None, 1),
+            vec![vec![0, 1], vec![2, 0], vec![2, 3]]
+        );
+        assert_eq!(
+            local_maxima(&values, &region, Some(2.5), 2),
+            vec![vec![2, 0]]
+        );
+    }
+
+    #[test]
+    fn local_maxima_chunks() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = Arc::new(FilesystemStore::new(path.path())?);
+        let input = ArrayBuilder::new(
+            vec![8],
+            DataType::UInt8,
+            vec![4].try_into()?,
+            FillValue::from(0u8),
+        )
+        .build(store, "/")?;
+        input.store_array_subset_elements::<u8>(&input.subset_all(), &[0, 2, 0, 4, 5, 0, 1, 0])?;
+
+        let progress_callback = |_stats: ProgressStats| {};
+        let local_maxima = LocalMaxima::new(Some(1.5), 1, None);
+        let table = local_maxima.analyse(&input, &ProgressCallback::new(&progress_callback))?;
+        assert_eq!(table.columns(), ["axis0", "value"]);
+        assert_eq!(table.rows(), [vec![1.0, 2.0], vec![4.0, 5.0]]);
+        Ok(())
+    }
+}
@@ -0,0 +1,554 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        retrieve_elements_as, store_elements_as, try_for_each_chunk, FilterArguments,
+        FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+    WriteShapeArgs,
+};
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct LutArguments {
+    /// Apply a gamma curve with this exponent.
+    ///
+    /// The input range is normalised to [0, 1], raised to the power of gamma, and scaled to the output range.
+    /// A gamma less than 1 brightens (e.g. 0.4545 for display encoding), and a gamma greater than 1 darkens.
+    #[arg(long, required_unless_present_any = ["lut", "points"], conflicts_with_all = ["lut", "points"])]
+    pub gamma: Option<f64>,
+
+    /// The path to a 1D lookup table.
+    ///
+    /// Either a CSV file (.csv) or a JSON file with an array.
+    /// A table of values (one per line) is spread evenly over the input range.
+    /// A table of "x,y" pairs holds control points in input units.
+    /// Values are in output units, and are linearly interpolated.
+    ///
+    /// Examples:
+    ///   CSV:  0
+    ///         200
+    ///         255
+    ///   JSON: [0, 200, 255]
+    ///   JSON: [[0, 0], [1000, 200], [4095, 255]]
+    #[arg(long, verbatim_doc_comment, conflicts_with = "points")]
+    pub lut: Option<PathBuf>,
+
+    /// Control points of a piecewise linear curve. Comma separated X:Y pairs in input and output units.
+    ///
+    /// Elements outside of the control points are mapped to the nearest end point.
+    ///
+    /// Examples:
+    ///   0:0,1000:200,4095:255
+    #[arg(long, verbatim_doc_comment, value_delimiter = ',', allow_hyphen_values(true), value_parser = parse_point)]
+    pub points: Option<Vec<(f64, f64)>>,
+
+    /// The input range of a gamma curve or a table of values. Comma separated MIN,MAX.
+    ///
+    /// Defaults to the range of the input data type if it is an integer, otherwise 0,1.
+    /// Elements outside of the input range are clamped.
+    #[arg(long, value_delimiter = ',', allow_hyphen_values(true))]
+    pub input_range: Option<Vec<f64>>,
+
+    /// The output range of a gamma curve. Comma separated MIN,MAX.
+    ///
+    /// Defaults to the range of the output data type if it is an integer, otherwise 0,1.
+    #[arg(long, value_delimiter = ',', allow_hyphen_values(true))]
+    pub output_range: Option<Vec<f64>>,
+}
+
+fn parse_point(point: &str) -> Result<(f64, f64), String> {
+    point
+        .split_once(':')
+        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+        .ok_or_else(|| format!("invalid control point {point}, expected X:Y"))
+}
+
+fn parse_range(range: Option<&Vec<f64>>) -> Result<Option<(f64, f64)>, FilterError> {
+    match range.map(Vec::as_slice) {
+        None => Ok(None),
+        Some(&[min, max]) if min < max => Ok(Some((min, max))),
+        Some(range) => Err(FilterError::InvalidParameters(format!(
+            "invalid range {range:?}, expected MIN,MAX with MIN < MAX"
+        ))),
+    }
+}
+
+impl FilterArguments for LutArguments {
+    fn name(&self) -> String {
+        "lut".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        let curve = if let Some(gamma) = self.gamma {
+            if gamma.is_nan() || gamma <= 0.0 {
+                return Err(FilterError::InvalidParameters(
+                    "the gamma must be positive".to_string(),
+                ));
+            }
+            Curve::Gamma(gamma)
+        } else if let Some(lut) = &self.lut {
+            read_lut(lut)?
+        } else if let Some(points) = &self.points {
+            Curve::points(points.clone())?
+        } else {
+            return Err(FilterError::InvalidParameters(
+                "one of a gamma, a lookup table, or control points is required".to_string(),
+            ));
+        };
+        Ok(Box::new(Lut::new(
+            curve,
+            parse_range(self.input_range.as_ref())?,
+            parse_range(self.output_range.as_ref())?,
+            *common_args.chunk_limit(),
+            common_args.write().clone(),
+        )))
+    }
+}
+
+/// A tone curve.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Curve {
+    /// A gamma curve with an exponent.
+    Gamma(f64),
+    /// A table of output values spread evenly over the input range.
+    Table(Vec<f64>),
+    /// Control points (input, output) of a piecewise linear curve, sorted by input.
+    Points(Vec<(f64, f64)>),
+}
+
+impl Curve {
+    /// Create a piecewise linear curve from control points.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if there are no points or the points are not finite.
+    pub fn points(mut points: Vec<(f64, f64)>) -> Result<Self, FilterError> {
+        if points.is_empty() {
+            return Err(FilterError::InvalidParameters(
+                "a curve requires at least one control point".to_string(),
+            ));
+        }
+        if points.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+            return Err(FilterError::InvalidParameters(
+                "control points must be finite".to_string(),
+            ));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self::Points(points))
+    }
+
+    /// Create a table of output values spread evenly over the input range.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if the table is empty.
+    pub fn table(values: Vec<f64>) -> Result<Self, FilterError> {
+        if values.is_empty() {
+            return Err(FilterError::InvalidParameters(
+                "a lookup table requires at least one value".to_string(),
+            ));
+        }
+        Ok(Self::Table(values))
+    }
+}
+
+/// Read a 1D lookup table from a CSV or JSON file.
+///
+/// A CSV file can have a header and `#` comments.
+///
+/// # Errors
+/// Returns a [`FilterError`] if the file cannot be read or is not a valid lookup table.
+pub fn read_lut(path: &Path) -> Result<Curve, FilterError> {
+    let contents = std::fs::read_to_string(path)?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        parse_lut_csv(&contents)
+    } else {
+        parse_lut_json(&contents)
+    }
+}
+
+fn parse_lut_csv(contents: &str) -> Result<Curve, FilterError> {
+    let mut rows: Vec<Vec<f64>> = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let row = line
+            .split(',')
+            .map(|value| value.trim().parse().ok())
+            .collect::<Option<Vec<f64>>>();
+        match row {
+            Some(row) if (1..=2).contains(&row.len()) => rows.push(row),
+            None if rows.is_empty() && i == 0 => {} // header
+            _ => {
+                return Err(FilterError::InvalidParameters(format!(
+                    "invalid lookup table on line {}: {line}",
+                    i + 1
+                )))
+            }
+        }
+    }
+    if rows.iter().all(|row| row.len() == 1) {
+        Curve::table(rows.into_iter().map(|row| row[0]).collect())
+    } else if rows.iter().all(|row| row.len() == 2) {
+        Curve::points(rows.into_iter().map(|row| (row[0], row[1])).collect())
+    } else {
+        Err(FilterError::InvalidParameters(
+            "a lookup table must have either one or two columns on every line".to_string(),
+        ))
+    }
+}
+
+fn parse_lut_json(contents: &str) -> Result<Curve, FilterError> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Lut {
+        Table(Vec<f64>),
+        Points(Vec<(f64, f64)>),
+    }
+    match serde_json::from_str(contents)? {
+        Lut::Table(values) => Curve::table(values),
+        Lut::Points(points) => Curve::points(points),
+    }
+}
+
+/// Returns the range of an integer data type, otherwise [0, 1].
+fn data_type_range(data_type: &DataType) -> (f64, f64) {
+    match data_type {
+        DataType::Int8 => (i8::MIN.into(), i8::MAX.into()),
+        DataType::Int16 => (i16::MIN.into(), i16::MAX.into()),
+        DataType::Int32 => (i32::MIN.into(), i32::MAX.into()),
+        DataType::Int64 => (i64::MIN as f64, i64::MAX as f64),
+        DataType::UInt8 => (0.0, u8::MAX.into()),
+        DataType::UInt16 => (0.0, u16::MAX.into()),
+        DataType::UInt32 => (0.0, u32::MAX.into()),
+        DataType::UInt64 => (0.0, u64::MAX as f64),
+        _ => (0.0, 1.0),
+    }
+}
+
+fn is_integer(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+    )
+}
+
+/// Apply a gamma curve or a 1D lookup table per element.
+pub struct Lut {
+    curve: Curve,
+    input_range: Option<(f64, f64)>,
+    output_range: Option<(f64, f64)>,
+    chunk_limit: Option<usize>,
+    write: WriteShapeArgs,
+}
+
+impl Lut {
+    pub fn new(
+        curve: Curve,
+        input_range: Option<(f64, f64)>,
+        output_range: Option<(f64, f64)>,
+        chunk_limit: Option<usize>,
+        write: WriteShapeArgs,
+    ) -> Self {
+        Self {
+            curve,
+            input_range,
+            output_range,
+            chunk_limit,
+            write,
+        }
+    }
+
+    /// Map a value with the curve, given the resolved input and output range.
+    ///
+    /// NaN is preserved.
+    pub fn map(&self, value: f64, input_range: (f64, f64), output_range: (f64, f64)) -> f64 {
+        if value.is_nan() {
+            return value;
+        }
+        let normalised =
+            ((value - input_range.0) / (input_range.1 - input_range.0)).clamp(0.0, 1.0);
+        match &self.curve {
+            Curve::Gamma(gamma) => normalised
+                .powf(*gamma)
+                .mul_add(output_range.1 - output_range.0, output_range.0),
+            Curve::Table(values) => {
+                let position = normalised * (values.len() - 1) as f64;
+                let i = (position.floor() as usize).min(values.len() - 1);
+                let j = (i + 1).min(values.len() - 1);
+                let t = position - i as f64;
+                values[i] + (values[j] - values[i]) * t
+            }
+            Curve::Points(points) => {
+                let j = points.partition_point(|(x, _)| *x <= value);
+                if j == 0 {
+                    points[0].1
+                } else if j == points.len() {
+                    points[j - 1].1
+                } else {
+                    let (x0, y0) = points[j - 1];
+                    let (x1, y1) = points[j];
+                    y0 + (y1 - y0) * (value - x0) / (x1 - x0)
+                }
+            }
+        }
+    }
+
+    /// Map `elements` in place, rounding if `round` is true.
+    pub fn apply_elements_inplace(
+        &self,
+        elements: &mut [f64],
+        input_range: (f64, f64),
+        output_range: (f64, f64),
+        round: bool,
+    ) {
+        elements.par_iter_mut().for_each(|value| {
+            let mapped = self.map(*value, input_range, output_range);
+            *value = if round { mapped.round() } else { mapped };
+        });
+    }
+
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        input_output_subset: &ArraySubset,
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let mut elements =
+            progress.read(|| retrieve_elements_as::<f64>(input, input_output_subset))?;
+
+        let input_range = self
+            .input_range
+            .unwrap_or_else(|| data_type_range(input.data_type()));
+        let output_range = self
+            .output_range
+            .unwrap_or_else(|| data_type_range(output.data_type()));
+        progress.process(|| {
+            self.apply_elements_inplace(
+                &mut elements,
+                input_range,
+                output_range,
+                is_integer(output.data_type()),
+            );
+        });
+
+        progress.write(|| {
+            store_elements_as(
+                output,
+                input_output_subset,
+                &elements,
+                &self.write.codec_options(output),
+            )
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for Lut {
+    fn supports_write_shape(&self) -> bool {
+        true
+    }
+
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float16
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::BFloat16 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize() * chunk_input.fixed_element_size().unwrap()
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let subsets = self.write.write_subsets(output)?;
+        let progress = Progress::new(subsets.len(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            subsets,
+            |subset: ArraySubset| self.apply_chunk(input, output, &subset, &progress),
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn lut_parse() -> Result<(), FilterError> {
+        assert_eq!(
+            parse_lut_csv("value\n0\n# midpoint\n200\n255\n")?,
+            Curve::Table(vec![0.0, 200.0, 255.0])
+        );
+        assert_eq!(
+            parse_lut_csv("4095,255\n0,0\n")?,
+            Curve::Points(vec![(0.0, 0.0), (4095.0, 255.0)])
+        );
+        assert_eq!(
+            parse_lut_json("[0, 200, 255]")?,
+            Curve::Table(vec![0.0, 200.0, 255.0])
+        );
+        assert_eq!(
+            parse_lut_json("[[0, 0], [1000, 200]]")?,
+            Curve::Points(vec![(0.0, 0.0), (1000.0, 200.0)])
+        );
+        assert!(parse_lut_csv("0\n1,2\n").is_err());
+        assert!(parse_lut_json("[]").is_err());
+        assert_eq!(parse_point("-1:2.5"), Ok((-1.0, 2.5)));
+        assert!(parse_point("1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn lut_map() -> Result<(), FilterError> {
+        let range = (0.0, 100.0);
+        let gamma = Lut::new(
+            Curve::Gamma(0.5),
+            None,
+            None,
+            None,
+            WriteShapeArgs::default(),
+        );
+        assert_eq!(gamma.map(25.0, range, (0.0, 1.0)), 0.5);
+        assert_eq!(gamma.map(200.0, range, (0.0, 10.0)), 10.0);
+        assert!(gamma.map(f64::NAN, range, (0.0, 1.0)).is_nan());
+
+        let table = Lut::new(
+            Curve::table(vec![0.0, 10.0, 30.0])?,
+            None,
+            None,
+            None,
+            WriteShapeArgs::default(),
+        );
+        assert_eq!(table.map(25.0, range, range), 5.0);
+        assert_eq!(table.map(75.0, range, range), 20.0);
+        assert_eq!(table.map(100.0, range, range), 30.0);
+
+        let points = Lut::new(
+            Curve::points(vec![(10.0, 0.0), (0.0, 5.0), (20.0, 10.0)])?,
+            None,
+            None,
+            None,
+            WriteShapeArgs::default(),
+        );
+        assert_eq!(points.map(-5.0, range, range), 5.0);
+        assert_eq!(points.map(5.0, range, range), 2.5);
+        assert_eq!(points.map(15.0, range, range), 5.0);
+        assert_eq!(points.map(25.0, range, range), 10.0);
+        Ok(())
+    }
+
+    #[test]
+    fn lut_gamma_u16_to_u8() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![4],
+            DataType::UInt16,
+            vec![2].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<u16>(&array.subset_all(), &[0, 1000, 4000, 5000])?;
+
+        let mut array_output = ArrayBuilder::new(
+            vec![4],
+            DataType::UInt8,
+            vec![2].try_into()?,
+            FillValue::from(0u8),
+        )
+        .build(store, "/output")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        Lut::new(
+            Curve::Gamma(0.5),
+            Some((0.0, 4000.0)),
+            None,
+            None,
+            WriteShapeArgs::default(),
+        )
+        .apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<u8>(&array_output.subset_all())?;
+        assert_eq!(elements, [0, 128, 255, 255]);
+        Ok(())
+    }
+}
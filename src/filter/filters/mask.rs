@@ -0,0 +1,247 @@
+use std::{path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, ArrayBytes, ArrayError, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct MaskArguments {
+    /// The path to the mask array (bool or uint8). It must have the same shape as the input.
+    ///
+    /// Elements are retained where the mask is nonzero and set to the fill value elsewhere.
+    pub mask: PathBuf,
+
+    /// Skip chunks that are entirely masked out, leaving them empty (sparse) in the output.
+    #[arg(long, default_value_t = false)]
+    #[serde(default)]
+    pub skip_masked_chunks: bool,
+}
+
+impl FilterArguments for MaskArguments {
+    fn name(&self) -> String {
+        "mask".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        let store = FilesystemStore::new(&self.mask).map_err(|err| {
+            FilterError::InvalidParameters(format!("cannot open mask {:?}: {err}", self.mask))
+        })?;
+        let mask = Array::open(Arc::new(store), "/")?;
+        Ok(Box::new(Mask::new(
+            mask,
+            self.skip_masked_chunks,
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// Set the elements of an array outside of a mask to the fill value.
+pub struct Mask {
+    mask: Array<FilesystemStore>,
+    skip_masked_chunks: bool,
+    chunk_limit: Option<usize>,
+}
+
+impl Mask {
+    pub fn new(
+        mask: Array<FilesystemStore>,
+        skip_masked_chunks: bool,
+        chunk_limit: Option<usize>,
+    ) -> Self {
+        Self {
+            mask,
+            skip_masked_chunks,
+            chunk_limit,
+        }
+    }
+
+    /// Set the elements of `bytes` (with `fill_value` sized elements) to `fill_value` where `mask` is zero.
+    pub fn apply_bytes(bytes: &mut [u8], mask: &[u8], fill_value: &[u8]) {
+        let element_size = fill_value.len();
+        for (element, mask) in std::iter::zip(bytes.chunks_exact_mut(element_size), mask) {
+            if *mask == 0 {
+                element.copy_from_slice(fill_value);
+            }
+        }
+    }
+
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
+
+        let mask = progress.read(|| {
+            self.mask
+                .retrieve_array_subset(&input_output_subset)?
+                .into_fixed()
+                .map_err(ArrayError::from)
+        })?;
+        if self.skip_masked_chunks && mask.iter().all(|mask| *mask == 0) {
+            progress.next()?;
+            return Ok(());
+        }
+
+        let mut bytes = progress.read(|| {
+            input
+                .retrieve_array_subset(&input_output_subset)?
+                .into_fixed()
+                .map_err(ArrayError::from)
+        })?;
+        progress.process(|| {
+            Self::apply_bytes(bytes.to_mut(), &mask, output.fill_value().as_ne_bytes());
+        });
+        drop(mask);
+
+        progress.write(|| {
+            output.store_array_subset(&input_output_subset, ArrayBytes::new_flen(bytes))
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for Mask {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        match self.mask.data_type() {
+            DataType::Bool | DataType::UInt8 => {}
+            data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        }
+        if chunk_input.data_type().fixed_size().is_none() {
+            Err(UnsupportedDataTypeError::from(
+                chunk_input.data_type().to_string(),
+            ))?;
+        }
+        if chunk_input.data_type() != chunk_output.data_type() {
+            return Err(FilterError::InvalidParameters(
+                "the mask filter does not support changing the data type".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize() * (chunk_input.fixed_element_size().unwrap() + 1)
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+        if self.mask.shape() != input.shape() {
+            return Err(FilterError::InvalidParameters(format!(
+                "the mask shape {:?} does not match the input shape {:?}",
+                self.mask.shape(),
+                input.shape()
+            )));
+        }
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| self.apply_chunk(input, output, &chunk_indices, &progress),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::{
+        array::{ArrayBuilder, FillValue},
+        storage::ReadableStorageTraits,
+    };
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn mask() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![6],
+            DataType::UInt16,
+            vec![2].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<u16>(&array.subset_all(), &[1, 2, 3, 4, 5, 6])?;
+
+        let mask = ArrayBuilder::new(
+            vec![6],
+            DataType::Bool,
+            vec![6].try_into()?,
+            FillValue::from(false),
+        )
+        .build(store.clone(), "/mask")?;
+        mask.store_array_subset_elements::<bool>(
+            &mask.subset_all(),
+            &[true, false, false, false, true, true],
+        )?;
+
+        let mut array_output = ArrayBuilder::new(
+            vec![6],
+            DataType::UInt16,
+            vec![2].try_into()?,
+            FillValue::from(9u16),
+        )
+        .build(store.clone(), "/output")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        Mask::new(mask, true, None).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<u16>(&array_output.subset_all())?;
+        assert_eq!(elements, [1, 9, 9, 9, 5, 6]);
+        assert!(store.get(&"output/c/1".try_into()?)?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+use std::sync::Mutex;
+
+use clap::Parser;
+use num_traits::AsPrimitive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+use super::local_maxima::retrieve_values;
+
+/// The normalisation of a slice.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum NormaliseMethod {
+    /// Subtract the mean and divide by the standard deviation.
+    #[default]
+    ZScore,
+    /// Scale the minimum and maximum to 0 and 1.
+    MinMax,
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct NormaliseSlicesArguments {
+    /// The axis of the slices (e.g. the time axis of a time-lapse).
+    pub axis: usize,
+
+    /// The normalisation of each slice.
+    #[arg(long, value_enum, default_value_t = NormaliseMethod::ZScore)]
+    #[serde(default)]
+    pub method: NormaliseMethod,
+}
+
+impl FilterArguments for NormaliseSlicesArguments {
+    fn name(&self) -> String {
+        "normalise_slices".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        Ok(Box::new(NormaliseSlices::new(
+            self.axis,
+            self.method,
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// The statistics of a slice, ignoring NaN elements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SliceStatistics {
+    /// The number of elements.
+    pub count: u64,
+    /// The mean.
+    pub mean: f64,
+    /// The sum of squared differences from the mean.
+    pub m2: f64,
+    /// The minimum.
+    pub min: f64,
+    /// The maximum.
+    pub max: f64,
+}
+
+impl Default for SliceStatistics {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl SliceStatistics {
+    /// Add a value (Welford's algorithm).
+    pub fn push(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Merge the statistics of another part of the slice (Chan et al.).
+    pub fn merge(&mut self, other: &Self) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 +=
+            other.m2 + delta * delta * (self.count as f64 * other.count as f64) / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Returns the (population) standard deviation.
+    pub fn std(&self) -> f64 {
+        if self.count > 0 {
+            (self.m2 / self.count as f64).sqrt()
+        } else {
+            0.0
+        }
+    }
+
+    /// Normalise a value with the statistics.
+    ///
+    /// Values of constant slices become zero.
+    pub fn normalise(&self, value: f64, method: NormaliseMethod) -> f64 {
+        let (offset, scale) = match method {
+            NormaliseMethod::ZScore => (self.mean, self.std()),
+            NormaliseMethod::MinMax => (self.min, self.max - self.min),
+        };
+        if scale > 0.0 {
+            (value - offset) / scale
+        } else if value.is_nan() {
+            value
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Returns the index along `axis` of each element of a subset with `shape` (in C order).
+fn axis_indices(shape: &[u64], axis: usize) -> impl Iterator<Item = usize> {
+    let stride = shape[axis + 1..].iter().product::<u64>() as usize;
+    let size = shape[axis] as usize;
+    let num_elements = shape.iter().product::<u64>() as usize;
+    (0..num_elements).map(move |i| (i / stride) % size)
+}
+
+/// Normalise each slice along an axis independently (e.g. to correct intensity drift or bleaching of the frames of a time-lapse).
+///
+/// The statistics of each slice are aggregated across chunks in a first pass, and the slices are normalised in a second pass.
+pub struct NormaliseSlices {
+    axis: usize,
+    method: NormaliseMethod,
+    chunk_limit: Option<usize>,
+}
+
+impl NormaliseSlices {
+    pub fn new(axis: usize, method: NormaliseMethod, chunk_limit: Option<usize>) -> Self {
+        Self {
+            axis,
+            method,
+            chunk_limit,
+        }
+    }
+
+    /// Add the elements of a chunk of the input to the statistics of each slice.
+    pub fn chunk_statistics(
+        &self,
+        input: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        statistics: &Mutex<Vec<SliceStatistics>>,
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let subset = input.chunk_subset_bounded(chunk_indices).unwrap();
+        let values = progress.read(|| retrieve_values(input, &subset))?;
+        let chunk_statistics = progress.process(|| {
+            let mut chunk_statistics =
+                vec![SliceStatistics::default(); subset.shape()[self.axis] as usize];
+            for (index, value) in std::iter::zip(axis_indices(subset.shape(), self.axis), values) {
+                chunk_statistics[index].push(value);
+            }
+            chunk_statistics
+        });
+        let start = subset.start()[self.axis] as usize;
+        let mut statistics = statistics.lock().unwrap();
+        for (statistics, chunk_statistics) in
+            std::iter::zip(&mut statistics[start..], &chunk_statistics)
+        {
+            statistics.merge(chunk_statistics);
+        }
+        drop(statistics);
+        progress.next()?;
+        Ok(())
+    }
+
+    pub fn apply_chunk<TOut>(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        statistics: &[SliceStatistics],
+        progress: &Progress,
+    ) -> Result<(), FilterError>
+    where
+        TOut: Element + Copy + Send + Sync + 'static,
+        f64: AsPrimitive<TOut>,
+    {
+        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
+        let values = progress.read(|| retrieve_values(input, &input_output_subset))?;
+        let start = input_output_subset.start()[self.axis] as usize;
+        let elements_out = progress.process(|| {
+            std::iter::zip(axis_indices(input_output_subset.shape(), self.axis), values)
+                .map(|(index, value)| {
+                    statistics[start + index]
+                        .normalise(value, self.method)
+                        .as_()
+                })
+                .collect::<Vec<TOut>>()
+        });
+        progress.write(|| {
+            output.store_array_subset_elements::<TOut>(&input_output_subset, &elements_out)
+        })?;
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for NormaliseSlices {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        let ndim = chunk_input.dimensionality();
+        if self.axis >= ndim {
+            return Err(FilterError::InvalidParameters(format!(
+                "normalise slices axis {} must be less than the array dimensionality {ndim}",
+                self.axis
+            )));
+        }
+        match chunk_input.data_type() {
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::BFloat16
+            | DataType::Float16
+            | DataType::Float32
+            | DataType::Float64 => {}
+            data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        };
+        match chunk_output.data_type() {
+            DataType::BFloat16 | DataType::Float16 | DataType::Float32 | DataType::Float64 => {}
+            data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        };
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize()
+            * (chunk_input.fixed_element_size().unwrap() + std::mem::size_of::<f64>())
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let input_chunks = ArraySubset::new_with_shape(input.chunk_grid_shape().unwrap());
+        let output_chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(
+            input_chunks.num_elements_usize() + output_chunks.num_elements_usize(),
+            progress_callback,
+        );
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        // Aggregate the statistics of each slice
+        let statistics = Mutex::new(vec![
+            SliceStatistics::default();
+            usize::try_from(input.shape()[self.axis]).unwrap()
+        ]);
+        let indices = input_chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                self.chunk_statistics(input, &chunk_indices, &statistics, &progress)
+            },
+        )?;
+        let statistics = statistics.into_inner().unwrap();
+
+        // Normalise each slice
+        let indices = output_chunks.indices();
+        let output_ref = &*output;
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                macro_rules! apply_output {
+                    ([$( ( $data_type:ident, $t_out:ty ) ),* ]) => {
+                        match output_ref.data_type() {
+                            $(DataType::$data_type => self.apply_chunk::<$t_out>(input, output_ref, &chunk_indices, &statistics, &progress),)*
+                            _ => panic!()
+                        }
+                    };
+                }
+                apply_output!([
+                    (BFloat16, half::bf16),
+                    (Float16, half::f16),
+                    (Float32, f32),
+                    (Float64, f64)
+                ])
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn slice_statistics_merge() {
+        let values = [1.0, 4.0, f64::NAN, 2.0, 8.0, 5.0];
+        let mut all = SliceStatistics::default();
+        values.iter().for_each(|value| all.push(*value));
+        let mut a = SliceStatistics::default();
+        let mut b = SliceStatistics::default();
+        values[..2].iter().for_each(|value| a.push(*value));
+        values[2..].iter().for_each(|value| b.push(*value));
+        a.merge(&b);
+        assert_eq!(a.count, 5);
+        assert!((a.mean - all.mean).abs() < 1e-12);
+        assert!((a.m2 - all.m2).abs() < 1e-12);
+        assert_eq!((a.min, a.max), (1.0, 8.0));
+        assert!((all.mean - 4.0).abs() < 1e-12);
+        assert!((all.std() - 6f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn normalise_slices() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![3, 4],
+            DataType::UInt16,
+            vec![2, 3].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(store.clone(), "/input")?;
+        #[rustfmt::skip]
+        array.store_array_subset_elements::<u16>(&array.subset_all(), &[
+            0, 10, 20, 30,
+            5, 5, 5, 5,
+            100, 200, 300, 400,
+        ])?;
+
+        let mut array_output = ArrayBuilder::new(
+            vec![3, 4],
+            DataType::Float32,
+            vec![3, 2].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(store, "/output")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        NormaliseSlices::new(0, NormaliseMethod::MinMax, None).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<f32>(&array_output.subset_all())?;
+        let third = 1.0 / 3.0;
+        let two_thirds = 2.0 / 3.0;
+        assert_eq!(
+            elements,
+            [0.0, third, two_thirds, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, third, two_thirds, 1.0]
+        );
+
+        NormaliseSlices::new(1, NormaliseMethod::ZScore, None).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<f32>(&array_output.subset_all())?;
+        // The z-scores of each column sum to zero
+        for column in 0..4 {
+            let column = [elements[column], elements[4 + column], elements[8 + column]];
+            assert!(column.iter().sum::<f32>().abs() < 1e-5);
+        }
+        Ok(())
+    }
+}
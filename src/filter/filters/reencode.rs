@@ -1,23 +1,32 @@
 use clap::Parser;
-use num_traits::AsPrimitive;
-use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use num_complex::Complex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
-    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
+    array::{
+        data_type::UnsupportedDataTypeError, Array, ArrayBytes, DataType, Element, ElementOwned,
+    },
     array_subset::ArraySubset,
     filesystem::FilesystemStore,
 };
 
 use crate::{
+    cast::{CastElement, ElementCastArgs},
     filter::{
         calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
-        FilterArguments, FilterCommonArguments,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
     },
     progress::{Progress, ProgressCallback},
+    WriteShapeArgs,
 };
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
-pub struct ReencodeArguments {}
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct ReencodeArguments {
+    /// Cast policies if the data type is changed.
+    #[command(flatten)]
+    #[serde(flatten)]
+    pub cast: ElementCastArgs,
+}
 
 impl FilterArguments for ReencodeArguments {
     fn name(&self) -> String {
@@ -28,30 +37,189 @@ impl FilterArguments for ReencodeArguments {
         &self,
         common_args: &FilterCommonArguments,
     ) -> Result<Box<dyn FilterTraits>, FilterError> {
-        Ok(Box::new(Reencode::new(*common_args.chunk_limit())))
+        Ok(Box::new(Reencode::new(
+            self.cast,
+            *common_args.chunk_limit(),
+            common_args.write().clone(),
+        )))
+    }
+}
+
+/// Returns true if `data_type` is complex.
+pub fn is_complex(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Complex64 | DataType::Complex128)
+}
+
+/// Check that elements can be cast from `data_type_in` to `data_type_out`.
+///
+/// Real data types can be cast to real or complex data types, and complex data types can be cast to complex data types.
+/// Strings are passed through, but cannot be cast to or from other data types.
+pub fn is_castable(data_type_in: &DataType, data_type_out: &DataType) -> Result<(), FilterError> {
+    if *data_type_in == DataType::String && *data_type_out == DataType::String {
+        return Ok(());
+    }
+    for data_type in [data_type_in, data_type_out] {
+        match data_type {
+            DataType::Bool
+            | DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float16
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::BFloat16
+            | DataType::Complex64
+            | DataType::Complex128 => {}
+            _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        };
+    }
+    if is_complex(data_type_in) && !is_complex(data_type_out) {
+        return Err(FilterError::InvalidParameters(format!(
+            "cannot cast {data_type_in} to {data_type_out}, use the complex_to_real filter"
+        )));
+    }
+    Ok(())
+}
+
+/// Cast `bytes` with `data_type_in` to the complex `data_type_out`.
+///
+/// Real elements become complex elements with a zero imaginary part.
+///
+/// # Errors
+/// Returns a [`FilterError`] if `data_type_out` is not complex or an element cannot be cast.
+pub fn cast_bytes_to_complex(
+    cast: &ElementCastArgs,
+    bytes: ArrayBytes<'_>,
+    data_type_in: &DataType,
+    data_type_out: &DataType,
+) -> Result<ArrayBytes<'static>, FilterError> {
+    match data_type_out {
+        DataType::Complex64 => {
+            cast_bytes_to_complex_t::<f32>(cast, bytes, data_type_in, data_type_out)
+        }
+        DataType::Complex128 => {
+            cast_bytes_to_complex_t::<f64>(cast, bytes, data_type_in, data_type_out)
+        }
+        _ => Err(UnsupportedDataTypeError::from(data_type_out.to_string()))?,
+    }
+}
+
+fn cast_bytes_to_complex_t<TOut>(
+    cast: &ElementCastArgs,
+    bytes: ArrayBytes<'_>,
+    data_type_in: &DataType,
+    data_type_out: &DataType,
+) -> Result<ArrayBytes<'static>, FilterError>
+where
+    TOut: CastElement,
+    Complex<TOut>: Element,
+{
+    macro_rules! real {
+        ( $type_in:ty ) => {
+            cast.cast_elements_real_to_complex::<$type_in, TOut>(&<$type_in>::from_array_bytes(
+                data_type_in,
+                bytes,
+            )?)?
+        };
+    }
+    macro_rules! complex {
+        ( $type_in:ty ) => {
+            cast.cast_elements_complex::<$type_in, TOut>(&Complex::<$type_in>::from_array_bytes(
+                data_type_in,
+                bytes,
+            )?)?
+        };
     }
+    let elements = match data_type_in {
+        DataType::Bool => real!(bool),
+        DataType::Int8 => real!(i8),
+        DataType::Int16 => real!(i16),
+        DataType::Int32 => real!(i32),
+        DataType::Int64 => real!(i64),
+        DataType::UInt8 => real!(u8),
+        DataType::UInt16 => real!(u16),
+        DataType::UInt32 => real!(u32),
+        DataType::UInt64 => real!(u64),
+        DataType::BFloat16 => real!(half::bf16),
+        DataType::Float16 => real!(half::f16),
+        DataType::Float32 => real!(f32),
+        DataType::Float64 => real!(f64),
+        DataType::Complex64 => complex!(f32),
+        DataType::Complex128 => complex!(f64),
+        _ => Err(UnsupportedDataTypeError::from(data_type_in.to_string()))?,
+    };
+    Ok(Complex::<TOut>::into_array_bytes(data_type_out, &elements)?.into_owned())
 }
 
 pub struct Reencode {
+    cast: ElementCastArgs,
     chunk_limit: Option<usize>,
+    write: WriteShapeArgs,
 }
 
 impl Reencode {
-    pub fn new(chunk_limit: Option<usize>) -> Self {
-        Self { chunk_limit }
+    pub fn new(cast: ElementCastArgs, chunk_limit: Option<usize>, write: WriteShapeArgs) -> Self {
+        Self {
+            cast,
+            chunk_limit,
+            write,
+        }
     }
 
     pub fn apply_chunk(
         &self,
         input: &Array<FilesystemStore>,
         output: &Array<FilesystemStore>,
-        chunk_indices: &[u64],
+        input_output_subset: &ArraySubset,
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let subset_bytes = progress.read(|| input.retrieve_array_subset(input_output_subset))?;
+        let num_bytes = subset_bytes.size();
+        progress.add_bytes_read(num_bytes);
+        progress.write(|| {
+            output.store_array_subset_opt(
+                input_output_subset,
+                subset_bytes,
+                &self.write.codec_options(output),
+            )
+        })?;
+        progress.add_bytes_written(num_bytes);
+        progress.next()?;
+        Ok(())
+    }
+
+    pub fn apply_chunk_convert_complex(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        input_output_subset: &ArraySubset,
         progress: &Progress,
     ) -> Result<(), FilterError> {
-        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
-        let subset_bytes = progress.read(|| input.retrieve_array_subset(&input_output_subset))?;
-        progress.write(|| output.store_array_subset(&input_output_subset, subset_bytes))?;
-        progress.next();
+        let input_bytes = progress.read(|| input.retrieve_array_subset(input_output_subset))?;
+        progress.add_bytes_read(input_bytes.size());
+        let output_bytes = progress.process(|| {
+            cast_bytes_to_complex(
+                &self.cast,
+                input_bytes,
+                input.data_type(),
+                output.data_type(),
+            )
+        })?;
+        let num_bytes = output_bytes.size();
+        progress.write(|| {
+            output.store_array_subset_opt(
+                input_output_subset,
+                output_bytes,
+                &self.write.codec_options(output),
+            )
+        })?;
+        progress.add_bytes_written(num_bytes);
+        progress.next()?;
         Ok(())
     }
 
@@ -59,60 +227,46 @@ impl Reencode {
         &self,
         input: &Array<FilesystemStore>,
         output: &Array<FilesystemStore>,
-        chunk_indices: &[u64],
+        input_output_subset: &ArraySubset,
         progress: &Progress,
     ) -> Result<(), FilterError>
     where
-        TIn: ElementOwned + Send + Sync + AsPrimitive<TOut>,
-        TOut: Element + Send + Sync + Copy + 'static,
+        TIn: ElementOwned + CastElement,
+        TOut: Element + CastElement,
     {
-        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
-
         let input_elements =
-            progress.read(|| input.retrieve_array_subset_elements::<TIn>(&input_output_subset))?;
-
-        let output_elements = progress.process(|| {
-            input_elements
-                .par_iter()
-                .map(|input| input.as_())
-                .collect::<Vec<TOut>>()
-        });
+            progress.read(|| input.retrieve_array_subset_elements::<TIn>(input_output_subset))?;
+        progress.add_bytes_read(std::mem::size_of_val(input_elements.as_slice()));
+
+        let output_elements =
+            progress.process(|| self.cast.cast_elements::<TIn, TOut>(&input_elements))?;
         drop(input_elements);
 
         progress.write(|| {
-            output.store_array_subset_elements::<TOut>(&input_output_subset, &output_elements)
+            output.store_array_subset_elements_opt::<TOut>(
+                input_output_subset,
+                &output_elements,
+                &self.write.codec_options(output),
+            )
         })?;
+        progress.add_bytes_written(std::mem::size_of_val(output_elements.as_slice()));
 
-        progress.next();
+        progress.next()?;
         Ok(())
     }
 }
 
 impl FilterTraits for Reencode {
+    fn supports_write_shape(&self) -> bool {
+        true
+    }
+
     fn is_compatible(
         &self,
         chunk_input: &zarrs::array::ChunkRepresentation,
         chunk_output: &zarrs::array::ChunkRepresentation,
     ) -> Result<(), FilterError> {
-        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
-            match data_type {
-                DataType::Bool
-                | DataType::Int8
-                | DataType::Int16
-                | DataType::Int32
-                | DataType::Int64
-                | DataType::UInt8
-                | DataType::UInt16
-                | DataType::UInt32
-                | DataType::UInt64
-                | DataType::Float16
-                | DataType::Float32
-                | DataType::Float64
-                | DataType::BFloat16 => {}
-                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
-            };
-        }
-        Ok(())
+        is_castable(chunk_input.data_type(), chunk_output.data_type())
     }
 
     fn memory_per_chunk(
@@ -120,7 +274,9 @@ impl FilterTraits for Reencode {
         _chunk_input: &zarrs::array::ChunkRepresentation,
         chunk_output: &zarrs::array::ChunkRepresentation,
     ) -> usize {
-        chunk_output.fixed_element_size().unwrap()
+        chunk_output
+            .fixed_element_size()
+            .unwrap_or(std::mem::size_of::<u64>())
     }
 
     fn apply(
@@ -131,38 +287,45 @@ impl FilterTraits for Reencode {
     ) -> Result<(), FilterError> {
         assert_eq!(input.shape(), output.shape());
 
-        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
-        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+        let subsets = self.write.write_subsets(output)?;
+        let num_bytes = input
+            .data_type()
+            .fixed_size()
+            .map(|size| size * input.subset_all().num_elements_usize());
+        let progress = Progress::new_with_bytes(subsets.len(), num_bytes, progress_callback);
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
-        let indices = chunks.indices();
         if output.data_type() == input.data_type() {
-            rayon_iter_concurrent_limit::iter_concurrent_limit!(
+            try_for_each_chunk(
+                chunk_limit,
+                memory_per_chunk,
+                subsets,
+                |subset: ArraySubset| self.apply_chunk(input, output, &subset, &progress),
+            )?;
+        } else if is_complex(output.data_type()) {
+            try_for_each_chunk(
                 chunk_limit,
-                indices,
-                try_for_each,
-                |chunk_indices: Vec<u64>| {
-                    self.apply_chunk(input, output, &chunk_indices, &progress)
-                }
+                memory_per_chunk,
+                subsets,
+                |subset: ArraySubset| {
+                    self.apply_chunk_convert_complex(input, output, &subset, &progress)
+                },
             )?;
         } else {
-            rayon_iter_concurrent_limit::iter_concurrent_limit!(
+            try_for_each_chunk(
                 chunk_limit,
-                indices,
-                try_for_each,
-                |chunk_indices: Vec<u64>| {
+                memory_per_chunk,
+                subsets,
+                |subset: ArraySubset| {
                     macro_rules! apply_output {
                         ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
                             match output.data_type() {
-                                $(DataType::$data_type_out => { self.apply_chunk_convert::<$type_in, $type_out>(input, output, &chunk_indices, &progress) } ,)*
+                                $(DataType::$data_type_out => { self.apply_chunk_convert::<$type_in, $type_out>(input, output, &subset, &progress) } ,)*
                                 _ => panic!()
                             }
                         };
@@ -173,7 +336,7 @@ impl FilterTraits for Reencode {
                                 $(
                                     DataType::$data_type_in => {
                                         apply_output!($type_in, [
-                                            (Bool, u8),
+                                            (Bool, bool),
                                             (Int8, i8),
                                             (Int16, i16),
                                             (Int32, i32),
@@ -194,7 +357,7 @@ impl FilterTraits for Reencode {
                         };
                     }
                     apply_input!([
-                        (Bool, u8),
+                        (Bool, bool),
                         (Int8, i8),
                         (Int16, i16),
                         (Int32, i32),
@@ -208,10 +371,138 @@ impl FilterTraits for Reencode {
                         (Float32, f32),
                         (Float64, f64)
                     ])
-                }
+                },
             )?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    /// Reencode a 1D array with chunks of 2 into an array with chunks of 3.
+    fn reencode<TIn: ElementOwned, TOut: ElementOwned>(
+        data_type_in: DataType,
+        fill_value_in: FillValue,
+        data_type_out: DataType,
+        fill_value_out: FillValue,
+        elements: &[TIn],
+    ) -> Result<Vec<TOut>, Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let shape = vec![elements.len() as u64];
+        let array = ArrayBuilder::new(
+            shape.clone(),
+            data_type_in,
+            vec![2].try_into()?,
+            fill_value_in,
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<TIn>(&array.subset_all(), elements)?;
+
+        let reencode = Reencode::new(ElementCastArgs::default(), None, WriteShapeArgs::default());
+        let mut array_output =
+            ArrayBuilder::new(shape, data_type_out, vec![3].try_into()?, fill_value_out)
+                .build(store, "/output")?;
+        reencode.is_compatible(
+            &array.chunk_array_representation(&[0])?,
+            &array_output.chunk_array_representation(&[0])?,
+        )?;
+        let progress_callback = |_stats: ProgressStats| {};
+        reencode.apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        Ok(array_output.retrieve_array_subset_elements::<TOut>(&array_output.subset_all())?)
+    }
+
+    #[test]
+    fn reencode_complex() -> Result<(), Box<dyn Error>> {
+        let elements: Vec<Complex<f32>> = (0..5u8)
+            .map(|i| Complex::new(f32::from(i), -f32::from(i) / 2.0))
+            .collect();
+        let fill_value = FillValue::from(Complex::<f32>::new(0.0, 0.0));
+        let elements_out = reencode::<Complex<f32>, Complex<f32>>(
+            DataType::Complex64,
+            fill_value.clone(),
+            DataType::Complex64,
+            fill_value.clone(),
+            &elements,
+        )?;
+        assert_eq!(elements_out, elements);
+
+        let elements_out = reencode::<Complex<f32>, Complex<f64>>(
+            DataType::Complex64,
+            fill_value,
+            DataType::Complex128,
+            FillValue::from(Complex::<f64>::new(0.0, 0.0)),
+            &elements,
+        )?;
+        let elements_ref: Vec<Complex<f64>> = elements
+            .iter()
+            .map(|value| Complex::new(f64::from(value.re), f64::from(value.im)))
+            .collect();
+        assert_eq!(elements_out, elements_ref);
+
+        let fill_value = FillValue::from(Complex::<f64>::new(0.0, 0.0));
+        let elements_out = reencode::<Complex<f64>, Complex<f64>>(
+            DataType::Complex128,
+            fill_value.clone(),
+            DataType::Complex128,
+            fill_value,
+            &elements_ref,
+        )?;
+        assert_eq!(elements_out, elements_ref);
+
+        let elements_out = reencode::<f32, Complex<f32>>(
+            DataType::Float32,
+            FillValue::from(0.0f32),
+            DataType::Complex64,
+            FillValue::from(Complex::<f32>::new(0.0, 0.0)),
+            &[1.0, -2.0, 3.5],
+        )?;
+        assert_eq!(
+            elements_out,
+            [
+                Complex::new(1.0, 0.0),
+                Complex::new(-2.0, 0.0),
+                Complex::new(3.5, 0.0)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_string() -> Result<(), Box<dyn Error>> {
+        let elements = ["a", "", "bc", "déf", "ghij"].map(String::from);
+        let elements_out = reencode::<String, String>(
+            DataType::String,
+            FillValue::from(""),
+            DataType::String,
+            FillValue::from(""),
+            &elements,
+        )?;
+        assert_eq!(elements_out, elements);
+        assert!(is_castable(&DataType::String, &DataType::Float32).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_complex_to_real() {
+        assert!(is_castable(&DataType::Float32, &DataType::Complex64).is_ok());
+        assert!(matches!(
+            is_castable(&DataType::Complex64, &DataType::Float32),
+            Err(FilterError::InvalidParameters(_))
+        ));
+    }
+}
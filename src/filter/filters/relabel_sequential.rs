@@ -0,0 +1,329 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    hash::Hash,
+    sync::Mutex,
+};
+
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+use super::remap_labels::{RemapLabels, UnmappedLabels};
+
+/// The attribute key of the label mapping written by [`RelabelSequential`].
+pub const RELABEL_SEQUENTIAL_ATTRIBUTE: &str = "relabel_sequential";
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct RelabelSequentialArguments {
+    /// The first sequential label. Label 0 (background) is unchanged.
+    #[arg(long, default_value_t = 1)]
+    #[serde(default = "default_offset")]
+    pub offset: u64,
+}
+
+fn default_offset() -> u64 {
+    1
+}
+
+impl FilterArguments for RelabelSequentialArguments {
+    fn name(&self) -> String {
+        "relabel_sequential".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        Ok(Box::new(RelabelSequential::new(
+            self.offset,
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// Relabel integer labels (e.g. of a segmentation) into a compact sequential range.
+///
+/// The sorted non-zero labels of the input are written to the output attributes under [`RELABEL_SEQUENTIAL_ATTRIBUTE`], such that label `labels[i]` becomes `offset + i`.
+pub struct RelabelSequential {
+    offset: u64,
+    chunk_limit: Option<usize>,
+}
+
+impl RelabelSequential {
+    pub fn new(offset: u64, chunk_limit: Option<usize>) -> Self {
+        Self {
+            offset,
+            chunk_limit,
+        }
+    }
+
+    /// Returns the unique labels of a chunk.
+    pub fn chunk_labels<TIn>(
+        &self,
+        input: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<HashSet<TIn>, FilterError>
+    where
+        TIn: ElementOwned + Copy + Hash + Eq,
+    {
+        let input_subset = input.chunk_subset_bounded(chunk_indices).unwrap();
+        let input_elements =
+            progress.read(|| input.retrieve_array_subset_elements::<TIn>(&input_subset))?;
+        let labels = progress.process(|| input_elements.iter().copied().collect());
+        progress.next()?;
+        Ok(labels)
+    }
+
+    /// Returns the sequential mapping of the non-zero `labels`.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if a sequential label is not representable by the output type.
+    pub fn sequential_mapping<TIn, TOut>(
+        &self,
+        labels: &BTreeSet<TIn>,
+    ) -> Result<HashMap<TIn, TOut>, FilterError>
+    where
+        TIn: Copy + Hash + Eq + Into<i128> + Default,
+        TOut: TryFrom<i128> + Default,
+    {
+        let mut mapping = HashMap::with_capacity(labels.len() + 1);
+        mapping.insert(TIn::default(), TOut::default());
+        let labels = labels
+            .iter()
+            .filter(|label| Into::<i128>::into(**label) != 0);
+        for (label, sequential) in labels.zip(i128::from(self.offset)..) {
+            let sequential = TOut::try_from(sequential).map_err(|_| {
+                FilterError::InvalidParameters(format!(
+                    "sequential label {sequential} is not representable by the output data type"
+                ))
+            })?;
+            mapping.insert(*label, sequential);
+        }
+        Ok(mapping)
+    }
+
+    pub fn apply_typed<TIn, TOut>(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        chunk_limit: usize,
+        memory_per_chunk: usize,
+        progress: &Progress,
+    ) -> Result<(), FilterError>
+    where
+        TIn: ElementOwned + Copy + Send + Sync + Hash + Eq + Ord + Into<i128> + Default,
+        TOut: Element + Copy + Send + Sync + TryFrom<i128> + Default,
+    {
+        // Find the used labels
+        let chunks = ArraySubset::new_with_shape(input.chunk_grid_shape().unwrap());
+        let labels = Mutex::new(BTreeSet::<TIn>::new());
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                let chunk_labels = self.chunk_labels::<TIn>(input, &chunk_indices, progress)?;
+                labels.lock().unwrap().extend(chunk_labels);
+                Ok::<_, FilterError>(())
+            },
+        )?;
+        let labels = labels.into_inner().unwrap();
+
+        // Rewrite the labels
+        let mapping = self.sequential_mapping::<TIn, TOut>(&labels)?;
+        let remap = RemapLabels::new(HashMap::new(), UnmappedLabels::Pass, None);
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let indices = chunks.indices();
+        let output_ref = &*output;
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                remap.apply_chunk::<TIn, TOut>(
+                    input,
+                    output_ref,
+                    &chunk_indices,
+                    &mapping,
+                    progress,
+                )
+            },
+        )?;
+
+        // Record the mapping
+        let labels: Vec<i128> = labels
+            .into_iter()
+            .map(Into::into)
+            .filter(|label| *label != 0)
+            .collect();
+        output.attributes_mut().insert(
+            RELABEL_SEQUENTIAL_ATTRIBUTE.to_string(),
+            serde_json::json!({
+                "offset": self.offset,
+                "labels": labels,
+            }),
+        );
+        Ok(())
+    }
+}
+
+impl FilterTraits for RelabelSequential {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize() * chunk_input.fixed_element_size().unwrap()
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let num_input_chunks = input.chunk_grid_shape().unwrap().iter().product::<u64>();
+        let num_output_chunks = output.chunk_grid_shape().unwrap().iter().product::<u64>();
+        let progress = Progress::new(
+            usize::try_from(num_input_chunks + num_output_chunks).unwrap(),
+            progress_callback,
+        );
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        macro_rules! apply_output {
+            ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
+                match output.data_type() {
+                    $(DataType::$data_type_out => {
+                        self.apply_typed::<$type_in, $type_out>(input, output, chunk_limit, memory_per_chunk, &progress)
+                    } ,)*
+                    _ => panic!()
+                }
+            };
+        }
+        macro_rules! apply_input {
+            ([$( ( $data_type_in:ident, $type_in:ty ) ),* ]) => {
+                match input.data_type() {
+                    $(
+                        DataType::$data_type_in => {
+                            apply_output!($type_in, [
+                                (Int8, i8),
+                                (Int16, i16),
+                                (Int32, i32),
+                                (Int64, i64),
+                                (UInt8, u8),
+                                (UInt16, u16),
+                                (UInt32, u32),
+                                (UInt64, u64)
+                            ])
+                        }
+                    ,)*
+                    _ => panic!()
+                }
+            };
+        }
+        apply_input!([
+            (Int8, i8),
+            (Int16, i16),
+            (Int32, i32),
+            (Int64, i64),
+            (UInt8, u8),
+            (UInt16, u16),
+            (UInt32, u32),
+            (UInt64, u64)
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn relabel_sequential() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![6],
+            DataType::UInt64,
+            vec![2].try_into()?,
+            FillValue::from(0u64),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<u64>(
+            &array.subset_all(),
+            &[0, 1000, 7, 1000, u64::MAX, 0],
+        )?;
+
+        let mut array_output = ArrayBuilder::new(
+            vec![6],
+            DataType::UInt8,
+            vec![3].try_into()?,
+            FillValue::from(0u8),
+        )
+        .build(store, "/output")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        RelabelSequential::new(1, None).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<u8>(&array_output.subset_all())?;
+        assert_eq!(elements, [0, 2, 1, 2, 3, 0]);
+        assert_eq!(
+            array_output.attributes()[RELABEL_SEQUENTIAL_ATTRIBUTE],
+            serde_json::json!({ "offset": 1, "labels": [7, 1000, u64::MAX] })
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use std::{collections::HashMap, hash::Hash, path::Path, path::PathBuf};
+
+use clap::Parser;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+/// The handling of labels without a mapping.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum UnmappedLabels {
+    /// Unmapped labels are unchanged.
+    #[default]
+    Pass,
+    /// Unmapped labels become zero.
+    Zero,
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct RemapLabelsArguments {
+    /// The path to a label mapping.
+    ///
+    /// Either a CSV file (.csv) with a "from,to" pair per line, or a JSON file with an object or an array of pairs.
+    ///
+    /// Examples:
+    ///   CSV:  1,5
+    ///         2,5
+    ///   JSON: { "1": 5, "2": 5 }
+    ///   JSON: [[1, 5], [2, 5]]
+    #[arg(verbatim_doc_comment)]
+    pub mapping: PathBuf,
+
+    /// The handling of labels without a mapping.
+    #[arg(long, value_enum, default_value_t = UnmappedLabels::Pass)]
+    #[serde(default)]
+    pub unmapped: UnmappedLabels,
+}
+
+impl FilterArguments for RemapLabelsArguments {
+    fn name(&self) -> String {
+        "remap_labels".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        Ok(Box::new(RemapLabels::new(
+            read_label_mapping(&self.mapping)?,
+            self.unmapped,
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// Read a label mapping from a CSV or JSON file.
+///
+/// A CSV file can have a header and `#` comments.
+///
+/// # Errors
+/// Returns a [`FilterError`] if the file cannot be read or is not a valid label mapping.
+pub fn read_label_mapping(path: &Path) -> Result<HashMap<i128, i128>, FilterError> {
+    let contents = std::fs::read_to_string(path)?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        parse_label_mapping_csv(&contents)
+    } else {
+        parse_label_mapping_json(&contents)
+    }
+}
+
+fn parse_label_mapping_csv(contents: &str) -> Result<HashMap<i128, i128>, FilterError> {
+    let mut mapping = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let pair = line
+            .split_once(',')
+            .and_then(|(from, to)| Some((from.trim().parse().ok()?, to.trim().parse().ok()?)));
+        match pair {
+            Some((from, to)) => {
+                mapping.insert(from, to);
+            }
+            None if mapping.is_empty() && i == 0 => {} // header
+            None => {
+                return Err(FilterError::InvalidParameters(format!(
+                    "invalid label mapping on line {}: {line}",
+                    i + 1
+                )))
+            }
+        }
+    }
+    Ok(mapping)
+}
+
+fn parse_label_mapping_json(contents: &str) -> Result<HashMap<i128, i128>, FilterError> {
+    // An untagged enum is not used, since buffered content does not support 128-bit integers
+    if let Ok(pairs) = serde_json::from_str::<Vec<(i128, i128)>>(contents) {
+        return Ok(pairs.into_iter().collect());
+    }
+    serde_json::from_str::<HashMap<String, i128>>(contents)?
+        .into_iter()
+        .map(|(from, to)| {
+            let from = from.parse().map_err(|_| {
+                FilterError::InvalidParameters(format!("invalid label {from} in mapping"))
+            })?;
+            Ok((from, to))
+        })
+        .collect()
+}
+
+/// Remap integer labels (e.g. of a segmentation) with a hash map lookup.
+pub struct RemapLabels {
+    mapping: HashMap<i128, i128>,
+    unmapped: UnmappedLabels,
+    chunk_limit: Option<usize>,
+}
+
+impl RemapLabels {
+    pub fn new(
+        mapping: HashMap<i128, i128>,
+        unmapped: UnmappedLabels,
+        chunk_limit: Option<usize>,
+    ) -> Self {
+        Self {
+            mapping,
+            unmapped,
+            chunk_limit,
+        }
+    }
+
+    /// Returns the mapping for the input and output element types.
+    ///
+    /// Labels not representable by the input type are ignored, as they cannot occur.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if a mapped label is not representable by the output type.
+    pub fn typed_mapping<TIn, TOut>(&self) -> Result<HashMap<TIn, TOut>, FilterError>
+    where
+        TIn: TryFrom<i128> + Hash + Eq,
+        TOut: TryFrom<i128>,
+    {
+        self.mapping
+            .iter()
+            .filter_map(|(&from, &to)| Some((TIn::try_from(from).ok()?, to)))
+            .map(|(from, to)| {
+                let to = TOut::try_from(to).map_err(|_| {
+                    FilterError::InvalidParameters(format!(
+                        "label {to} is not representable by the output data type"
+                    ))
+                })?;
+                Ok((from, to))
+            })
+            .collect()
+    }
+
+    pub fn apply_elements<TIn, TOut>(
+        &self,
+        input_elements: &[TIn],
+        mapping: &HashMap<TIn, TOut>,
+    ) -> Result<Vec<TOut>, FilterError>
+    where
+        TIn: Copy + Send + Sync + Hash + Eq + Into<i128>,
+        TOut: Copy + Send + Sync + TryFrom<i128> + Default,
+    {
+        input_elements
+            .par_iter()
+            .map(|label| match (mapping.get(label), self.unmapped) {
+                (Some(&label), _) => Ok(label),
+                (None, UnmappedLabels::Zero) => Ok(TOut::default()),
+                (None, UnmappedLabels::Pass) => {
+                    let label: i128 = (*label).into();
+                    TOut::try_from(label).map_err(|_| {
+                        FilterError::Other(format!(
+                            "unmapped label {label} is not representable by the output data type"
+                        ))
+                    })
+                }
+            })
+            .collect()
+    }
+
+    pub fn apply_chunk<TIn, TOut>(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        mapping: &HashMap<TIn, TOut>,
+        progress: &Progress,
+    ) -> Result<(), FilterError>
+    where
+        TIn: ElementOwned + Copy + Send + Sync + Hash + Eq + Into<i128>,
+        TOut: Element + Copy + Send + Sync + TryFrom<i128> + Default,
+    {
+        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
+
+        let input_elements =
+            progress.read(|| input.retrieve_array_subset_elements::<TIn>(&input_output_subset))?;
+
+        let output_elements =
+            progress.process(|| self.apply_elements::<TIn, TOut>(&input_elements, mapping))?;
+        drop(input_elements);
+
+        progress.write(|| {
+            output.store_array_subset_elements::<TOut>(&input_output_subset, &output_elements)
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for RemapLabels {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_input.num_elements_usize() * chunk_input.fixed_element_size().unwrap()
+            + chunk_output.num_elements_usize() * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        macro_rules! apply_output {
+            ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
+                match output.data_type() {
+                    $(DataType::$data_type_out => {
+                        let mapping = self.typed_mapping::<$type_in, $type_out>()?;
+                        let indices = chunks.indices();
+                        try_for_each_chunk(chunk_limit, memory_per_chunk, &indices, |chunk_indices: Vec<u64>| {
+                                self.apply_chunk::<$type_in, $type_out>(input, output, &chunk_indices, &mapping, &progress)
+                            })
+                    } ,)*
+                    _ => panic!()
+                }
+            };
+        }
+        macro_rules! apply_input {
+            ([$( ( $data_type_in:ident, $type_in:ty ) ),* ]) => {
+                match input.data_type() {
+                    $(
+                        DataType::$data_type_in => {
+                            apply_output!($type_in, [
+                                (Int8, i8),
+                                (Int16, i16),
+                                (Int32, i32),
+                                (Int64, i64),
+                                (UInt8, u8),
+                                (UInt16, u16),
+                                (UInt32, u32),
+                                (UInt64, u64)
+                            ])
+                        }
+                    ,)*
+                    _ => panic!()
+                }
+            };
+        }
+        apply_input!([
+            (Int8, i8),
+            (Int16, i16),
+            (Int32, i32),
+            (Int64, i64),
+            (UInt8, u8),
+            (UInt16, u16),
+            (UInt32, u32),
+            (UInt64, u64)
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn remap_labels_mapping() -> Result<(), FilterError> {
+        let expected = HashMap::from([(1, 5), (2, 5)]);
+        assert_eq!(
+            parse_label_mapping_csv("from,to\n1,5\n# merged\n2, 5\n")?,
+            expected
+        );
+        assert_eq!(parse_label_mapping_json(r#"{ "1": 5, "2": 5 }"#)?, expected);
+        assert_eq!(parse_label_mapping_json("[[1, 5], [2, 5]]")?, expected);
+        assert!(parse_label_mapping_csv("1,5\nx,5\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn remap_labels() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![5],
+            DataType::UInt16,
+            vec![5].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<u16>(&array.subset_all(), &[0, 1, 2, 3, 300])?;
+
+        let mut array_output = ArrayBuilder::new(
+            vec![5],
+            DataType::UInt8,
+            vec![5].try_into()?,
+            FillValue::from(0u8),
+        )
+        .build(store, "/output")?;
+        let mapping = HashMap::from([(1, 5), (2, 5), (300, 7)]);
+        let progress_callback = |_stats: ProgressStats| {};
+        RemapLabels::new(mapping.clone(), UnmappedLabels::Pass, None).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<u8>(&array_output.subset_all())?;
+        assert_eq!(elements, [0, 5, 5, 3, 7]);
+
+        RemapLabels::new(mapping, UnmappedLabels::Zero, None).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<u8>(&array_output.subset_all())?;
+        assert_eq!(elements, [0, 5, 5, 0, 7]);
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 use clap::Parser;
+use num_complex::Complex;
 use num_traits::AsPrimitive;
-use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use rayon::iter::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
     array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
@@ -11,12 +13,16 @@ use zarrs::{
 use crate::{
     filter::{
         calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
-        FilterArguments, FilterCommonArguments,
+        retrieve_elements_as, store_elements_as, try_for_each_chunk, FilterArguments,
+        FilterCommonArguments, MissingData,
     },
     progress::{Progress, ProgressCallback},
+    WriteShapeArgs,
 };
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
+use super::reencode::is_complex;
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
 pub struct RescaleArguments {
     /// Multiplier term.
     #[arg(allow_hyphen_values(true))]
@@ -28,6 +34,12 @@ pub struct RescaleArguments {
     #[arg(long)]
     #[serde(default)]
     pub add_first: bool,
+    /// Treat fill value and NaN input elements as missing data, which are the output fill value rather than rescaled.
+    ///
+    /// Unsupported for complex data types.
+    #[arg(long, default_value_t = false)]
+    #[serde(default)]
+    pub missing_data: bool,
 }
 
 impl FilterArguments for RescaleArguments {
@@ -43,7 +55,9 @@ impl FilterArguments for RescaleArguments {
             self.multiply,
             self.add,
             self.add_first,
+            self.missing_data,
             *common_args.chunk_limit(),
+            common_args.write().clone(),
         )))
     }
 }
@@ -52,82 +66,149 @@ pub struct Rescale {
     multiply: f64,
     add: f64,
     add_first: bool,
+    missing_data: bool,
     chunk_limit: Option<usize>,
+    write: WriteShapeArgs,
 }
 
 impl Rescale {
-    pub fn new(multiply: f64, add: f64, add_first: bool, chunk_limit: Option<usize>) -> Self {
+    pub fn new(
+        multiply: f64,
+        add: f64,
+        add_first: bool,
+        missing_data: bool,
+        chunk_limit: Option<usize>,
+        write: WriteShapeArgs,
+    ) -> Self {
         Self {
             multiply,
             add,
             add_first,
+            missing_data,
             chunk_limit,
+            write,
         }
     }
 
-    pub fn apply_chunk<TIn, TOut>(
+    pub fn apply_chunk(
         &self,
         input: &Array<FilesystemStore>,
         output: &Array<FilesystemStore>,
-        chunk_indices: &[u64],
+        input_output_subset: &ArraySubset,
+        missing_data: Option<&MissingData>,
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let mut elements =
+            progress.read(|| retrieve_elements_as::<f64>(input, input_output_subset))?;
+        progress.process(|| self.apply_elements_inplace(&mut elements, missing_data));
+
+        progress.write(|| {
+            store_elements_as(
+                output,
+                input_output_subset,
+                &elements,
+                &self.write.codec_options(output),
+            )
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+
+    /// Rescale the magnitude of complex elements, preserving their phase.
+    pub fn apply_chunk_complex<TIn, TOut>(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        input_output_subset: &ArraySubset,
         progress: &Progress,
     ) -> Result<(), FilterError>
     where
-        TIn: ElementOwned + Send + Sync + AsPrimitive<f64>,
-        TOut: Element + Send + Sync + Copy + 'static,
+        TIn: Send + Sync + Copy + AsPrimitive<f64>,
+        TOut: Send + Sync + Copy + 'static,
+        Complex<TIn>: ElementOwned,
+        Complex<TOut>: Element,
         f64: AsPrimitive<TOut>,
     {
-        // Determine the input and output subset
-        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
-
-        let elements_in =
-            progress.read(|| input.retrieve_array_subset_elements::<TIn>(&input_output_subset))?;
-
-        let elements_out = if self.add_first {
-            progress.process(|| {
-                elements_in
-                    .iter()
-                    .map(|value| {
-                        let value_f64: f64 = value.as_();
-                        ((value_f64 + self.add) * self.multiply).as_()
-                    })
-                    .collect::<Vec<TOut>>()
-            })
-        } else {
-            progress.process(|| self.apply_elements(&elements_in))
-        };
+        let elements_in = progress
+            .read(|| input.retrieve_array_subset_elements::<Complex<TIn>>(input_output_subset))?;
+        let elements_out = progress.process(|| self.apply_elements_complex(&elements_in));
         drop(elements_in);
 
         progress.write(|| {
-            output.store_array_subset_elements::<TOut>(&input_output_subset, &elements_out)
+            output.store_array_subset_elements_opt::<Complex<TOut>>(
+                input_output_subset,
+                &elements_out,
+                &self.write.codec_options(output),
+            )
         })?;
 
-        progress.next();
+        progress.next()?;
         Ok(())
     }
 
-    pub fn apply_elements<TIn, TOut>(&self, elements_in: &[TIn]) -> Vec<TOut>
+    pub fn apply_elements_complex<TIn, TOut>(
+        &self,
+        elements_in: &[Complex<TIn>],
+    ) -> Vec<Complex<TOut>>
     where
-        TIn: Send + Sync + AsPrimitive<f64>,
+        TIn: Send + Sync + Copy + AsPrimitive<f64>,
         TOut: Send + Sync + Copy + 'static,
         f64: AsPrimitive<TOut>,
     {
         elements_in
             .par_iter()
             .map(|value| {
-                let value_f64: f64 = value.as_();
-                value_f64.mul_add(self.multiply, self.add).as_()
+                let (magnitude, phase) =
+                    Complex::<f64>::new(value.re.as_(), value.im.as_()).to_polar();
+                let magnitude = if self.add_first {
+                    (magnitude + self.add) * self.multiply
+                } else {
+                    magnitude.mul_add(self.multiply, self.add)
+                };
+                let value = Complex::from_polar(magnitude, phase);
+                Complex::new(value.re.as_(), value.im.as_())
             })
-            .collect::<Vec<TOut>>()
+            .collect()
+    }
+
+    /// Rescale `elements` in place, replacing missing input elements with the output fill value.
+    pub fn apply_elements_inplace(&self, elements: &mut [f64], missing_data: Option<&MissingData>) {
+        elements.par_iter_mut().for_each(|value| {
+            *value = match missing_data {
+                Some(missing_data) if missing_data.is_missing(*value) => missing_data.fill_value(),
+                _ if self.add_first => (*value + self.add) * self.multiply,
+                _ => value.mul_add(self.multiply, self.add),
+            };
+        });
     }
 }
 
 impl FilterTraits for Rescale {
+    fn supports_write_shape(&self) -> bool {
+        true
+    }
+
     fn is_compatible(
         &self,
         chunk_input: &zarrs::array::ChunkRepresentation,
         chunk_output: &zarrs::array::ChunkRepresentation,
     ) -> Result<(), FilterError> {
+        let complex_input = is_complex(chunk_input.data_type());
+        if complex_input != is_complex(chunk_output.data_type()) {
+            return Err(FilterError::InvalidParameters(
+                "the rescale filter requires complex input and output data types or real input and output data types".to_string(),
+            ));
+        }
+        if complex_input {
+            if self.missing_data {
+                return Err(FilterError::InvalidParameters(
+                    "the rescale filter does not support missing data for complex data types"
+                        .to_string(),
+                ));
+            }
+            return Ok(());
+        }
         for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
             match data_type {
                 DataType::Bool
@@ -165,76 +246,152 @@ impl FilterTraits for Rescale {
     ) -> Result<(), FilterError> {
         assert_eq!(output.shape(), input.shape());
 
-        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
-        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+        let subsets = self.write.write_subsets(output)?;
+        let progress = Progress::new(subsets.len(), progress_callback);
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
-        let indices = chunks.indices();
-        rayon_iter_concurrent_limit::iter_concurrent_limit!(
-            chunk_limit,
-            indices,
-            try_for_each,
-            |chunk_indices: Vec<u64>| {
-                macro_rules! apply_input {
-                    ( $t_out:ty, [$( ( $data_type:ident, $t_in:ty ) ),* ]) => {
-                        match input.data_type() {
-                            $(DataType::$data_type => { self.apply_chunk::<$t_in, $t_out>(&input, &output, &chunk_indices, &progress) } ,)*
-                            _ => panic!()
-                        }
-                    };
-                }
-                macro_rules! apply_output {
-                    ([$( ( $data_type:ident, $type_out:ty ) ),* ]) => {
-                            match output.data_type() {
-                                $(
-                                    DataType::$data_type => {
-                                        apply_input!($type_out, [
-                                            (Bool, u8),
-                                            (Int8, i8),
-                                            (Int16, i16),
-                                            (Int32, i32),
-                                            (Int64, i64),
-                                            (UInt8, u8),
-                                            (UInt16, u16),
-                                            (UInt32, u32),
-                                            (UInt64, u64),
-                                            (BFloat16, half::bf16),
-                                            (Float16, half::f16),
-                                            (Float32, f32),
-                                            (Float64, f64)
-                                        ]
-                                    )}
-                                ,)*
-                                _ => panic!()
-                            }
-                        };
+        if is_complex(output.data_type()) {
+            return try_for_each_chunk(
+                chunk_limit,
+                memory_per_chunk,
+                subsets,
+                |subset: ArraySubset| match (input.data_type(), output.data_type()) {
+                    (DataType::Complex64, DataType::Complex64) => {
+                        self.apply_chunk_complex::<f32, f32>(input, output, &subset, &progress)
                     }
-                apply_output!([
-                    (Bool, u8),
-                    (Int8, i8),
-                    (Int16, i16),
-                    (Int32, i32),
-                    (Int64, i64),
-                    (UInt8, u8),
-                    (UInt16, u16),
-                    (UInt32, u32),
-                    (UInt64, u64),
-                    (BFloat16, half::bf16),
-                    (Float16, half::f16),
-                    (Float32, f32),
-                    (Float64, f64)
-                ])
-            }
+                    (DataType::Complex64, DataType::Complex128) => {
+                        self.apply_chunk_complex::<f32, f64>(input, output, &subset, &progress)
+                    }
+                    (DataType::Complex128, DataType::Complex64) => {
+                        self.apply_chunk_complex::<f64, f32>(input, output, &subset, &progress)
+                    }
+                    (DataType::Complex128, DataType::Complex128) => {
+                        self.apply_chunk_complex::<f64, f64>(input, output, &subset, &progress)
+                    }
+                    _ => panic!(),
+                },
+            );
+        }
+        let missing_data = self
+            .missing_data
+            .then(|| MissingData::from_arrays(input, output))
+            .transpose()?;
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            subsets,
+            |subset: ArraySubset| {
+                self.apply_chunk(input, output, &subset, missing_data.as_ref(), &progress)
+            },
         )?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, num::NonZeroU64};
+
+    use zarrs::array::{ArrayBuilder, ChunkRepresentation, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn rescale_complex() -> Result<(), Box<dyn Error>> {
+        let rescale = Rescale::new(2.0, 1.0, false, false, None, WriteShapeArgs::default());
+        let elements_out = rescale
+            .apply_elements_complex::<f64, f64>(&[Complex::new(3.0, 4.0), Complex::new(0.0, -2.0)]);
+        for (value_out, value_ref) in std::iter::zip(
+            &elements_out,
+            [Complex::new(6.6, 8.8), Complex::new(0.0, -5.0)],
+        ) {
+            assert!((value_out - value_ref).norm() < 1e-12);
+        }
+
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![4],
+            DataType::Complex64,
+            vec![2].try_into()?,
+            FillValue::from(Complex::<f32>::new(0.0, 0.0)),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<Complex<f32>>(
+            &array.subset_all(),
+            &[
+                Complex::new(3.0, 4.0),
+                Complex::new(-1.0, 0.0),
+                Complex::new(0.0, 2.0),
+                Complex::new(-6.0, -8.0),
+            ],
+        )?;
+        let mut array_output = ArrayBuilder::new(
+            vec![4],
+            DataType::Complex128,
+            vec![3].try_into()?,
+            FillValue::from(Complex::<f64>::new(0.0, 0.0)),
+        )
+        .build(store, "/output")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        let rescale = Rescale::new(0.5, 0.0, false, false, None, WriteShapeArgs::default());
+        rescale.is_compatible(
+            &array.chunk_array_representation(&[0])?,
+            &array_output.chunk_array_representation(&[0])?,
+        )?;
+        rescale.apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements = array_output
+            .retrieve_array_subset_elements::<Complex<f64>>(&array_output.subset_all())?;
+        // The magnitude is rescaled and the phase is preserved
+        let elements_ref = [
+            Complex::new(1.5, 2.0),
+            Complex::new(-0.5, 0.0),
+            Complex::new(0.0, 1.0),
+            Complex::new(-3.0, -4.0),
+        ];
+        for (value_out, value_ref) in std::iter::zip(&elements, elements_ref) {
+            assert!((value_out - value_ref).norm() < 1e-6);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rescale_complex_incompatible() -> Result<(), Box<dyn Error>> {
+        let chunk_shape = vec![NonZeroU64::new(2).unwrap()];
+        let chunk_complex = ChunkRepresentation::new(
+            chunk_shape.clone(),
+            DataType::Complex64,
+            FillValue::from(Complex::<f32>::new(0.0, 0.0)),
+        )?;
+        let chunk_real = ChunkRepresentation::new(chunk_shape, DataType::Float32, 0.0f32.into())?;
+        let rescale = Rescale::new(2.0, 1.0, false, false, None, WriteShapeArgs::default());
+        assert!(rescale
+            .is_compatible(&chunk_complex, &chunk_complex)
+            .is_ok());
+        assert!(matches!(
+            rescale.is_compatible(&chunk_complex, &chunk_real),
+            Err(FilterError::InvalidParameters(_))
+        ));
+        assert!(matches!(
+            rescale.is_compatible(&chunk_real, &chunk_complex),
+            Err(FilterError::InvalidParameters(_))
+        ));
+        let rescale = Rescale::new(2.0, 1.0, false, true, None, WriteShapeArgs::default());
+        assert!(rescale
+            .is_compatible(&chunk_complex, &chunk_complex)
+            .is_err());
+        Ok(())
+    }
+}
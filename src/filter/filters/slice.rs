@@ -0,0 +1,586 @@
+use std::{num::NonZeroU64, str::FromStr};
+
+use clap::Parser;
+use num_traits::AsPrimitive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+/// A slice of an array axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SliceSpec {
+    /// Select a single element and remove the axis.
+    Index(u64),
+    /// Select elements from `start` (inclusive) to `stop` (exclusive) with a `step`.
+    ///
+    /// The `start` and `stop` default to the start and end of the axis.
+    Range {
+        start: Option<u64>,
+        stop: Option<u64>,
+        step: NonZeroU64,
+    },
+}
+
+impl JsonSchema for SliceSpec {
+    fn schema_name() -> String {
+        "SliceSpec".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl FromStr for SliceSpec {
+    type Err = String;
+
+    fn from_str(slice: &str) -> Result<Self, Self::Err> {
+        let slice = slice.trim();
+        let invalid =
+            || format!("{slice:?} is not a valid slice, expected an index or start:stop:step");
+        if slice.contains(':') {
+            let parts: Vec<&str> = slice.split(':').map(str::trim).collect();
+            if parts.len() > 3 {
+                return Err(invalid());
+            }
+            let parse = |part: &str| -> Result<Option<u64>, String> {
+                if part.is_empty() {
+                    Ok(None)
+                } else {
+                    part.parse().map(Some).map_err(|_| invalid())
+                }
+            };
+            let start = parse(parts[0])?;
+            let stop = parse(parts[1])?;
+            let step = match parts.get(2).map(|part| parse(part)).transpose()?.flatten() {
+                Some(step) => NonZeroU64::new(step).ok_or_else(invalid)?,
+                None => NonZeroU64::MIN,
+            };
+            Ok(Self::Range { start, stop, step })
+        } else {
+            slice.parse().map(Self::Index).map_err(|_| invalid())
+        }
+    }
+}
+
+impl std::fmt::Display for SliceSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{index}"),
+            Self::Range { start, stop, step } => {
+                if let Some(start) = start {
+                    write!(f, "{start}")?;
+                }
+                write!(f, ":")?;
+                if let Some(stop) = stop {
+                    write!(f, "{stop}")?;
+                }
+                if step.get() != 1 {
+                    write!(f, ":{step}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for SliceSpec {
+    type Error = String;
+
+    fn try_from(slice: String) -> Result<Self, Self::Error> {
+        slice.parse()
+    }
+}
+
+impl From<SliceSpec> for String {
+    fn from(slice: SliceSpec) -> Self {
+        slice.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct SliceArguments {
+    /// Slices per axis, comma delimited.
+    ///
+    /// Each slice is either:
+    ///  - an index (e.g. 2), which selects a single element and removes the axis, or
+    ///  - a range start:stop:step (e.g. ":", "10:20", "::2"), where each component is optional.
+    ///
+    /// Axes without a slice are retained in full.
+    #[arg(required = true, value_delimiter = ',', verbatim_doc_comment)]
+    pub slices: Vec<SliceSpec>,
+}
+
+impl FilterArguments for SliceArguments {
+    fn name(&self) -> String {
+        "slice".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        Ok(Box::new(Slice::new(
+            self.slices.clone(),
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// A slice resolved against an axis of the input array.
+#[derive(Debug, Clone, Copy)]
+struct AxisSlice {
+    start: u64,
+    step: u64,
+    len: u64,
+    retain_axis: bool,
+}
+
+pub struct Slice {
+    slices: Vec<SliceSpec>,
+    chunk_limit: Option<usize>,
+}
+
+impl Slice {
+    pub fn new(slices: Vec<SliceSpec>, chunk_limit: Option<usize>) -> Self {
+        Self {
+            slices,
+            chunk_limit,
+        }
+    }
+
+    /// Resolve the slices against the input array shape.
+    fn axis_slices(&self, shape: &[u64]) -> Result<Vec<AxisSlice>, FilterError> {
+        if self.slices.len() > shape.len() {
+            return Err(FilterError::InvalidParameters(format!(
+                "{} slices were supplied for an array with {} dimensions",
+                self.slices.len(),
+                shape.len()
+            )));
+        }
+        shape
+            .iter()
+            .enumerate()
+            .map(|(axis, &size)| match self.slices.get(axis) {
+                Some(SliceSpec::Index(index)) => {
+                    if *index < size {
+                        Ok(AxisSlice {
+                            start: *index,
+                            step: 1,
+                            len: 1,
+                            retain_axis: false,
+                        })
+                    } else {
+                        Err(FilterError::InvalidParameters(format!(
+                            "index {index} is out of bounds for axis {axis} with size {size}"
+                        )))
+                    }
+                }
+                Some(SliceSpec::Range { start, stop, step }) => {
+                    let start = start.unwrap_or(0).min(size);
+                    let stop = stop.unwrap_or(size).min(size);
+                    let len = stop.saturating_sub(start).div_ceil(step.get());
+                    if len == 0 {
+                        Err(FilterError::InvalidParameters(format!(
+                            "slice {} is empty for axis {axis} with size {size}",
+                            self.slices[axis]
+                        )))
+                    } else {
+                        Ok(AxisSlice {
+                            start,
+                            step: step.get(),
+                            len,
+                            retain_axis: true,
+                        })
+                    }
+                }
+                None => Ok(AxisSlice {
+                    start: 0,
+                    step: 1,
+                    len: size,
+                    retain_axis: true,
+                }),
+            })
+            .collect()
+    }
+
+    // Determine the input subset and output subset
+    fn get_input_output_subset(
+        &self,
+        axis_slices: &[AxisSlice],
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+    ) -> (ArraySubset, ArraySubset) {
+        let output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
+        let mut output_ranges = std::iter::zip(output_subset.start(), output_subset.shape());
+        let (input_start, input_shape): (Vec<u64>, Vec<u64>) = axis_slices
+            .iter()
+            .map(|axis_slice| {
+                if axis_slice.retain_axis {
+                    let (output_start, output_size) = output_ranges.next().unwrap();
+                    (
+                        axis_slice.start + output_start * axis_slice.step,
+                        (output_size - 1) * axis_slice.step + 1,
+                    )
+                } else {
+                    (axis_slice.start, 1)
+                }
+            })
+            .unzip();
+        let input_subset = ArraySubset::new_with_start_shape(input_start, input_shape).unwrap();
+        (input_subset, output_subset)
+    }
+
+    /// Select every `step`th element of each axis of an input array.
+    fn select_steps<T: Clone>(input: ndarray::ArrayD<T>, steps: &[u64]) -> Vec<T> {
+        input
+            .slice_each_axis(|axis| {
+                ndarray::Slice::new(
+                    0,
+                    None,
+                    steps
+                        .get(axis.axis.index())
+                        .map_or(1, |step| *step as isize),
+                )
+            })
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    fn apply_chunk(
+        &self,
+        axis_slices: &[AxisSlice],
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let (input_subset, output_subset) =
+            self.get_input_output_subset(axis_slices, output, chunk_indices);
+        let steps = axis_slices.iter().map(|s| s.step).collect::<Vec<_>>();
+
+        let input_bytes = progress.read(|| input.retrieve_array_subset(&input_subset))?;
+        let output_bytes = progress.process(|| {
+            // Treat the bytes of each element as an additional axis
+            let element_size = input.data_type().fixed_size().unwrap();
+            let input_bytes = input_bytes.into_fixed().unwrap().into_owned();
+            let mut shape = input_subset.shape_usize();
+            shape.push(element_size);
+            let input_bytes = ndarray::ArrayD::from_shape_vec(shape, input_bytes).unwrap();
+            Self::select_steps(input_bytes, &steps)
+        });
+
+        progress.write(|| output.store_array_subset(&output_subset, output_bytes))?;
+        progress.next()?;
+        Ok(())
+    }
+
+    fn apply_chunk_convert<TIn, TOut>(
+        &self,
+        axis_slices: &[AxisSlice],
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError>
+    where
+        TIn: ElementOwned + Send + Sync + AsPrimitive<TOut>,
+        TOut: Element + Send + Sync + Copy + 'static,
+    {
+        let (input_subset, output_subset) =
+            self.get_input_output_subset(axis_slices, output, chunk_indices);
+        let steps = axis_slices.iter().map(|s| s.step).collect::<Vec<_>>();
+
+        let input_elements =
+            progress.read(|| input.retrieve_array_subset_ndarray::<TIn>(&input_subset))?;
+
+        let output_elements = progress.process(|| {
+            Self::select_steps(input_elements, &steps)
+                .into_iter()
+                .map(|input| input.as_())
+                .collect::<Vec<TOut>>()
+        });
+
+        progress.write(|| {
+            output.store_array_subset_elements::<TOut>(&output_subset, &output_elements)
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for Slice {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        if chunk_input.data_type() == chunk_output.data_type() {
+            if chunk_input.data_type().fixed_size().is_none() {
+                Err(UnsupportedDataTypeError::from(
+                    chunk_input.data_type().to_string(),
+                ))?;
+            }
+            return Ok(());
+        }
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Bool
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float16
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::BFloat16 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        let steps: usize = self
+            .slices
+            .iter()
+            .map(|slice| match slice {
+                SliceSpec::Index(_) => 1,
+                SliceSpec::Range { step, .. } => usize::try_from(step.get()).unwrap(),
+            })
+            .product();
+        let num_elements = chunk_output.num_elements_usize();
+        num_elements * steps * chunk_input.fixed_element_size().unwrap()
+            + num_elements * chunk_output.fixed_element_size().unwrap()
+    }
+
+    fn output_axes(
+        &self,
+        array_input: &Array<FilesystemStore>,
+    ) -> Result<Option<Vec<Option<usize>>>, FilterError> {
+        let axis_slices = self.axis_slices(array_input.shape())?;
+        Ok(Some(
+            axis_slices
+                .iter()
+                .enumerate()
+                .filter(|(_, axis_slice)| axis_slice.retain_axis)
+                .map(|(axis, _)| Some(axis))
+                .collect(),
+        ))
+    }
+
+    fn output_shape(&self, array_input: &Array<FilesystemStore>) -> Option<Vec<u64>> {
+        let axis_slices = self.axis_slices(array_input.shape()).ok()?;
+        Some(
+            axis_slices
+                .iter()
+                .filter(|axis_slice| axis_slice.retain_axis)
+                .map(|axis_slice| axis_slice.len)
+                .collect(),
+        )
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        let axis_slices = self.axis_slices(input.shape())?;
+        assert_eq!(Some(output.shape().to_vec()), self.output_shape(input));
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; output.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                if input.data_type() == output.data_type() {
+                    self.apply_chunk(&axis_slices, input, output, &chunk_indices, &progress)
+                } else {
+                    macro_rules! apply_output {
+                        ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
+                            match output.data_type() {
+                                $(DataType::$data_type_out => { self.apply_chunk_convert::<$type_in, $type_out>(&axis_slices, input, output, &chunk_indices, &progress) } ,)*
+                                _ => panic!()
+                            }
+                        };
+                    }
+                    macro_rules! apply_input {
+                    ([$( ( $data_type_in:ident, $type_in:ty ) ),* ]) => {
+                            match input.data_type() {
+                                $(
+                                    DataType::$data_type_in => {
+                                        apply_output!($type_in, [
+                                            (Bool, u8),
+                                            (Int8, i8),
+                                            (Int16, i16),
+                                            (Int32, i32),
+                                            (Int64, i64),
+                                            (UInt8, u8),
+                                            (UInt16, u16),
+                                            (UInt32, u32),
+                                            (UInt64, u64),
+                                            (BFloat16, half::bf16),
+                                            (Float16, half::f16),
+                                            (Float32, f32),
+                                            (Float64, f64)
+                                        ]
+                                    )}
+                                ,)*
+                                _ => panic!()
+                            }
+                        };
+                    }
+                    apply_input!([
+                        (Bool, u8),
+                        (Int8, i8),
+                        (Int16, i16),
+                        (Int32, i32),
+                        (Int64, i64),
+                        (UInt8, u8),
+                        (UInt16, u16),
+                        (UInt32, u32),
+                        (UInt64, u64),
+                        (BFloat16, half::bf16),
+                        (Float16, half::f16),
+                        (Float32, f32),
+                        (Float64, f64)
+                    ])
+                }
+            },
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::ProgressStats;
+
+    use super::*;
+    use std::error::Error;
+    use zarrs::array::ArrayBuilder;
+
+    #[test]
+    fn slice_spec() {
+        for (slice, expected) in [
+            ("2", SliceSpec::Index(2)),
+            (
+                ":",
+                SliceSpec::Range {
+                    start: None,
+                    stop: None,
+                    step: NonZeroU64::MIN,
+                },
+            ),
+            (
+                "1:5:2",
+                SliceSpec::Range {
+                    start: Some(1),
+                    stop: Some(5),
+                    step: NonZeroU64::new(2).unwrap(),
+                },
+            ),
+            (
+                "::3",
+                SliceSpec::Range {
+                    start: None,
+                    stop: None,
+                    step: NonZeroU64::new(3).unwrap(),
+                },
+            ),
+        ] {
+            let spec: SliceSpec = slice.parse().unwrap();
+            assert_eq!(spec, expected);
+            assert_eq!(spec.to_string().parse::<SliceSpec>().unwrap(), expected);
+        }
+        for slice in ["", "a", "::0", "1:2:3:4", "-1"] {
+            assert!(slice.parse::<SliceSpec>().is_err());
+        }
+    }
+
+    #[test]
+    fn slice() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = FilesystemStore::new(path.path())?;
+        let array = ArrayBuilder::new(
+            vec![3, 4, 5],
+            DataType::UInt16,
+            vec![2, 2, 2].try_into()?,
+            0u16.into(),
+        )
+        .dimension_names(["c", "y", "x"].into())
+        .build(store.into(), "/")?;
+        let array_subset = array.subset_all();
+        array.store_array_subset_elements(
+            &array_subset,
+            &(0..array_subset.num_elements())
+                .map(|u| u as u16)
+                .collect::<Vec<u16>>(),
+        )?;
+
+        // Take channel 2, every second row, and columns 1..4
+        let slice = Slice::new(vec!["2".parse()?, "::2".parse()?, "1:4".parse()?], None);
+        let path = tempfile::TempDir::new()?;
+        let store: FilesystemStore = FilesystemStore::new(path.path())?;
+        let mut array_output = slice
+            .output_array_builder(&array, &Default::default())?
+            .build(store.into(), "/")?;
+        assert_eq!(array_output.shape(), &[2, 3]);
+        assert_eq!(
+            array_output.dimension_names(),
+            &Some(vec!["y".into(), "x".into()])
+        );
+        let progress_callback = |_stats: ProgressStats| {};
+        slice.apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_ndarray::<u16>(&array_output.subset_all())?;
+        let elements_ref: ndarray::ArrayD<u16> =
+            ndarray::array![[41, 42, 43], [51, 52, 53]].into_dyn();
+        assert_eq!(elements, elements_ref);
+
+        // Out of bounds
+        let slice = Slice::new(vec!["3".parse()?], None);
+        assert!(slice.output_axes(&array).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,477 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use clap::Parser;
+use num_traits::AsPrimitive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, ArrayBuilder, DataType, Element},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    get_array_builder_reencode,
+    progress::{Progress, ProgressCallback},
+    ZarrReencodingArgs,
+};
+
+use super::local_maxima::retrieve_values;
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct StitchArguments {
+    /// A CSV file (.csv) with a "path,offset_0,offset_1,..." tile per line.
+    ///
+    /// Each tile is a zarr array placed in the output at its offset.
+    /// Relative tile paths are relative to the directory of the CSV file.
+    /// The CSV file can have a header and `#` comments.
+    pub tiles: PathBuf,
+
+    /// The width of the linear blending ramp at the edges of each tile.
+    ///
+    /// Overlapping tiles are blended with a weighted mean, where the weight of an element decreases linearly towards the edges of its tile.
+    /// If unset, the ramp extends to the centre of each tile.
+    #[arg(long)]
+    #[serde(default)]
+    pub blend_width: Option<u64>,
+}
+
+impl FilterArguments for StitchArguments {
+    fn name(&self) -> String {
+        "stitch".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        if self.blend_width == Some(0) {
+            return Err(FilterError::InvalidParameters(
+                "stitch blend width must be positive".to_string(),
+            ));
+        }
+        let tiles = read_tiles(&self.tiles)?
+            .into_iter()
+            .map(|(path, offset)| {
+                let store = FilesystemStore::new(&path).map_err(|err| {
+                    FilterError::InvalidParameters(format!("cannot open tile {path:?}: {err}"))
+                })?;
+                let array = Array::open(Arc::new(store), "/")?;
+                Ok(Tile::new(array, offset))
+            })
+            .collect::<Result<Vec<_>, FilterError>>()?;
+        Ok(Box::new(Stitch::new(
+            tiles,
+            self.blend_width,
+            *common_args.chunk_limit(),
+        )?))
+    }
+}
+
+/// Read the tile paths and offsets of a tile CSV file.
+///
+/// Relative tile paths are resolved relative to the directory of the CSV file.
+///
+/// # Errors
+/// Returns a [`FilterError`] if the file cannot be read or is not a valid tile CSV file.
+pub fn read_tiles(path: &Path) -> Result<Vec<(PathBuf, Vec<u64>)>, FilterError> {
+    let contents = std::fs::read_to_string(path)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    Ok(parse_tiles_csv(&contents)?
+        .into_iter()
+        .map(|(tile_path, offset)| (directory.join(tile_path), offset))
+        .collect())
+}
+
+fn parse_tiles_csv(contents: &str) -> Result<Vec<(PathBuf, Vec<u64>)>, FilterError> {
+    let mut tiles = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split(',').map(str::trim);
+        let path = fields.next().unwrap_or_default();
+        let offset = fields
+            .map(|offset| offset.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>();
+        match offset {
+            Some(offset) if !path.is_empty() && !offset.is_empty() => {
+                tiles.push((PathBuf::from(path), offset));
+            }
+            _ if tiles.is_empty() && i == 0 => {} // header
+            _ => {
+                return Err(FilterError::InvalidParameters(format!(
+                    "invalid tile on line {}: {line}",
+                    i + 1
+                )))
+            }
+        }
+    }
+    Ok(tiles)
+}
+
+/// A tile of a [`Stitch`].
+pub struct Tile {
+    array: Array<FilesystemStore>,
+    offset: Vec<u64>,
+}
+
+impl Tile {
+    pub fn new(array: Array<FilesystemStore>, offset: Vec<u64>) -> Self {
+        Self { array, offset }
+    }
+
+    /// Returns the subset of the tile in the output.
+    fn subset(&self) -> ArraySubset {
+        ArraySubset::new_with_start_shape(self.offset.clone(), self.array.shape().to_vec()).unwrap()
+    }
+}
+
+/// Returns the blending weight of each element along an axis of length `size` of a tile.
+///
+/// The weight increases linearly from the edges of the tile, up to `blend_width` if set.
+fn axis_weights(size: u64, blend_width: Option<u64>) -> Vec<f64> {
+    (0..size)
+        .map(|position| {
+            let distance = std::cmp::min(position, size - 1 - position) + 1;
+            blend_width.map_or(distance, |blend_width| distance.min(blend_width)) as f64
+        })
+        .collect()
+}
+
+/// Assemble an array from overlapping tiles with linear blending in overlap regions.
+pub struct Stitch {
+    tiles: Vec<Tile>,
+    shape: Vec<u64>,
+    blend_width: Option<u64>,
+    chunk_limit: Option<usize>,
+}
+
+impl Stitch {
+    /// Create a new stitch filter of `tiles`.
+    ///
+    /// The output shape is the union of the extents of the tiles.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if there are no tiles or the tiles have inconsistent dimensionality.
+    pub fn new(
+        tiles: Vec<Tile>,
+        blend_width: Option<u64>,
+        chunk_limit: Option<usize>,
+    ) -> Result<Self, FilterError> {
+        let Some(first) = tiles.first() else {
+            return Err(FilterError::InvalidParameters(
+                "stitch requires at least one tile".to_string(),
+            ));
+        };
+        let dimensionality = first.array.dimensionality();
+        let mut shape = vec![0; dimensionality];
+        for tile in &tiles {
+            if tile.array.dimensionality() != dimensionality || tile.offset.len() != dimensionality
+            {
+                return Err(FilterError::InvalidParameters(format!(
+                    "stitch tile with shape {:?} and offset {:?} does not have the dimensionality {dimensionality}",
+                    tile.array.shape(),
+                    tile.offset
+                )));
+            }
+            for ((size, offset), tile_size) in
+                shape.iter_mut().zip(&tile.offset).zip(tile.array.shape())
+            {
+                *size = std::cmp::max(*size, offset + tile_size);
+            }
+        }
+        Ok(Self {
+            tiles,
+            shape,
+            blend_width,
+            chunk_limit,
+        })
+    }
+
+    /// Returns the output shape.
+    pub fn shape(&self) -> &[u64] {
+        &self.shape
+    }
+
+    /// Blend the tiles overlapping `subset` of the output.
+    ///
+    /// Returns the blended value of each element of the subset, which is [`None`] if no tile covers the element.
+    /// NaN tile elements are ignored.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if a tile cannot be read or has an unsupported data type.
+    pub fn blend_subset(&self, subset: &ArraySubset) -> Result<Vec<Option<f64>>, FilterError> {
+        let num_elements = subset.num_elements_usize();
+        let mut sums = vec![0.0; num_elements];
+        let mut weights = vec![0.0; num_elements];
+
+        let shape = subset.shape();
+        let mut strides = vec![1; shape.len()];
+        for axis in (0..shape.len().saturating_sub(1)).rev() {
+            strides[axis] = strides[axis + 1] * usize::try_from(shape[axis + 1]).unwrap();
+        }
+
+        for tile in &self.tiles {
+            let overlap = subset.overlap(&tile.subset()).unwrap();
+            if overlap.is_empty() {
+                continue;
+            }
+            let overlap_tile = overlap.relative_to(&tile.offset).unwrap();
+            let overlap_subset = overlap.relative_to(subset.start()).unwrap();
+            let axis_weights = std::iter::zip(tile.array.shape(), overlap_tile.to_ranges())
+                .map(|(size, range)| {
+                    let weights = axis_weights(*size, self.blend_width);
+                    weights
+                        [usize::try_from(range.start).unwrap()..usize::try_from(range.end).unwrap()]
+                        .to_vec()
+                })
+                .collect::<Vec<_>>();
+            let values = retrieve_values(&tile.array, &overlap_tile)?;
+
+            let overlap_shape = overlap.shape();
+            let mut index = vec![0; overlap_shape.len()];
+            for value in values {
+                if !value.is_nan() {
+                    let weight = std::iter::zip(&axis_weights, &index)
+                        .map(|(weights, index)| weights[*index])
+                        .product::<f64>();
+                    let position = itertools::izip!(overlap_subset.start(), &index, &strides)
+                        .map(|(start, index, stride)| {
+                            (usize::try_from(*start).unwrap() + index) * stride
+                        })
+                        .sum::<usize>();
+                    sums[position] += weight * value;
+                    weights[position] += weight;
+                }
+                for axis in (0..index.len()).rev() {
+                    index[axis] += 1;
+                    if (index[axis] as u64) < overlap_shape[axis] {
+                        break;
+                    }
+                    index[axis] = 0;
+                }
+            }
+        }
+
+        Ok(std::iter::zip(sums, weights)
+            .map(|(sum, weight)| (weight > 0.0).then(|| sum / weight))
+            .collect())
+    }
+
+    pub fn apply_chunk<TOut>(
+        &self,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError>
+    where
+        TOut: Element + bytemuck::Pod + Send + Sync + Copy + 'static,
+        f64: AsPrimitive<TOut>,
+    {
+        let subset_output = output.chunk_subset_bounded(chunk_indices).unwrap();
+        let values = progress.read(|| self.blend_subset(&subset_output))?;
+        if values.iter().all(Option::is_none) {
+            // Not covered by any tile
+            progress.next()?;
+            return Ok(());
+        }
+
+        let is_integer = !matches!(
+            output.data_type(),
+            DataType::BFloat16 | DataType::Float16 | DataType::Float32 | DataType::Float64
+        );
+        let fill_value: TOut = bytemuck::pod_read_unaligned(output.fill_value().as_ne_bytes());
+        let output_elements = progress.process(|| {
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Some(value) if is_integer => value.round().as_(),
+                    Some(value) => value.as_(),
+                    None => fill_value,
+                })
+                .collect::<Vec<TOut>>()
+        });
+        progress.write(|| {
+            output.store_array_subset_elements::<TOut>(&subset_output, &output_elements)
+        })?;
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for Stitch {
+    fn is_compatible(
+        &self,
+        _chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        match chunk_output.data_type() {
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::BFloat16
+            | DataType::Float16
+            | DataType::Float32
+            | DataType::Float64 => {}
+            data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        };
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        _chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        chunk_output.num_elements_usize()
+            * (4 * core::mem::size_of::<f64>() + chunk_output.fixed_element_size().unwrap())
+    }
+
+    fn generator_array_builder(
+        &self,
+        reencoding_args: &ZarrReencodingArgs,
+    ) -> Result<ArrayBuilder, FilterError> {
+        // The first tile is the template of the output, which is reencoded with the reencoding arguments
+        Ok(get_array_builder_reencode(
+            reencoding_args,
+            &self.tiles[0].array,
+            Some(self.shape.clone()),
+        )?)
+    }
+
+    fn generate(
+        &self,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), self.shape);
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let chunk_representation =
+            output.chunk_array_representation(&vec![0; output.dimensionality()])?;
+        let memory_per_chunk = self.memory_per_chunk(&chunk_representation, &chunk_representation);
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        let output_ref = &*output;
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                macro_rules! apply_output {
+                    ([$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
+                        match output_ref.data_type() {
+                            $(DataType::$data_type_out => { self.apply_chunk::<$type_out>(output_ref, &chunk_indices, &progress) } ,)*
+                            _ => panic!()
+                        }
+                    };
+                }
+                apply_output!([
+                    (Int8, i8),
+                    (Int16, i16),
+                    (Int32, i32),
+                    (Int64, i64),
+                    (UInt8, u8),
+                    (UInt16, u16),
+                    (UInt32, u32),
+                    (UInt64, u64),
+                    (BFloat16, half::bf16),
+                    (Float16, half::f16),
+                    (Float32, f32),
+                    (Float64, f64)
+                ])
+            },
+        )
+    }
+
+    fn apply(
+        &self,
+        _input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        self.generate(output, progress_callback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::FillValue;
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn stitch_tiles_csv() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            parse_tiles_csv("path,y,x\n# row 0\na.zarr,0,0\n b.zarr , 0, 90\n")?,
+            vec![
+                (PathBuf::from("a.zarr"), vec![0, 0]),
+                (PathBuf::from("b.zarr"), vec![0, 90])
+            ]
+        );
+        assert!(parse_tiles_csv("a.zarr,0,0\nb.zarr,x,0\n").is_err());
+        assert_eq!(axis_weights(5, None), vec![1.0, 2.0, 3.0, 2.0, 1.0]);
+        assert_eq!(axis_weights(5, Some(2)), vec![1.0, 2.0, 2.0, 2.0, 1.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn stitch() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let tile = |name: &str, value: f32| -> Result<Array<FilesystemStore>, Box<dyn Error>> {
+            let store = FilesystemStore::new(path.path().join(name))?;
+            let array = ArrayBuilder::new(
+                vec![2, 4],
+                DataType::Float32,
+                vec![2, 2].try_into()?,
+                FillValue::from(0.0f32),
+            )
+            .build(store.into(), "/")?;
+            array.store_metadata()?;
+            array.store_array_subset_elements(&array.subset_all(), &[value; 8])?;
+            Ok(array)
+        };
+        let tiles = vec![
+            Tile::new(tile("a.zarr", 1.0)?, vec![0, 0]),
+            Tile::new(tile("b.zarr", 4.0)?, vec![0, 2]),
+        ];
+        let stitch = Stitch::new(tiles, None, None)?;
+        assert_eq!(stitch.shape(), &[2, 6]);
+
+        let reencoding_args = ZarrReencodingArgs::parse_from(["reencode", "--chunk-shape", "1,4"]);
+        let store = FilesystemStore::new(path.path().join("output.zarr"))?;
+        let mut array = stitch
+            .generator_array_builder(&reencoding_args)?
+            .build(store.into(), "/")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        stitch.generate(&mut array, &ProgressCallback::new(&progress_callback))?;
+
+        // Tile a has weights 1,2,2,1 and tile b has weights 1,2,2,1 at columns 2..6
+        let elements = array.retrieve_array_subset_elements::<f32>(&array.subset_all())?;
+        let row = [1.0, 1.0, (2.0 + 4.0) / 3.0, (1.0 + 8.0) / 3.0, 4.0, 4.0];
+        assert_eq!(elements, [row, row].concat());
+
+        Ok(())
+    }
+}
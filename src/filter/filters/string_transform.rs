@@ -0,0 +1,250 @@
+use std::borrow::Cow;
+
+use clap::Parser;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+/// A case conversion of a string.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum StringCase {
+    /// Convert to uppercase.
+    Upper,
+    /// Convert to lowercase.
+    Lower,
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct StringTransformArguments {
+    /// A regular expression to replace.
+    ///
+    /// See https://docs.rs/regex/latest/regex/#syntax for the syntax.
+    #[arg(long)]
+    #[serde(default)]
+    pub regex: Option<String>,
+    /// The replacement of regular expression matches.
+    ///
+    /// Capture groups can be referenced with `$1`, `$name`, etc.
+    #[arg(long, default_value_t = String::new(), requires = "regex")]
+    #[serde(default)]
+    pub replacement: String,
+    /// Convert the case after any regular expression replacement.
+    #[arg(long, value_enum)]
+    #[serde(default)]
+    pub case: Option<StringCase>,
+}
+
+impl FilterArguments for StringTransformArguments {
+    fn name(&self) -> String {
+        "string_transform".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        if self.regex.is_none() && self.case.is_none() {
+            return Err(FilterError::InvalidParameters(
+                "the string transform filter requires a regex or case".to_string(),
+            ));
+        }
+        let regex = self
+            .regex
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|err| FilterError::InvalidParameters(err.to_string()))?;
+        Ok(Box::new(StringTransform::new(
+            regex,
+            self.replacement.clone(),
+            self.case,
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// Transform the elements of a string array with a regular expression replacement and/or case conversion.
+pub struct StringTransform {
+    regex: Option<Regex>,
+    replacement: String,
+    case: Option<StringCase>,
+    chunk_limit: Option<usize>,
+}
+
+impl StringTransform {
+    pub fn new(
+        regex: Option<Regex>,
+        replacement: String,
+        case: Option<StringCase>,
+        chunk_limit: Option<usize>,
+    ) -> Self {
+        Self {
+            regex,
+            replacement,
+            case,
+            chunk_limit,
+        }
+    }
+
+    /// Transform a string.
+    pub fn apply_element(&self, element: &str) -> String {
+        let element = match &self.regex {
+            Some(regex) => regex.replace_all(element, self.replacement.as_str()),
+            None => Cow::Borrowed(element),
+        };
+        match self.case {
+            Some(StringCase::Upper) => element.to_uppercase(),
+            Some(StringCase::Lower) => element.to_lowercase(),
+            None => element.into_owned(),
+        }
+    }
+
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let input_output_subset = output.chunk_subset_bounded(chunk_indices).unwrap();
+
+        let elements_in = progress
+            .read(|| input.retrieve_array_subset_elements::<String>(&input_output_subset))?;
+        let elements_out = progress.process(|| {
+            elements_in
+                .par_iter()
+                .map(|element| self.apply_element(element))
+                .collect::<Vec<_>>()
+        });
+        drop(elements_in);
+
+        progress.write(|| {
+            output.store_array_subset_elements::<String>(&input_output_subset, &elements_out)
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+}
+
+impl FilterTraits for StringTransform {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::String => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        // Strings are variable length, so this is an estimate
+        (chunk_input.num_elements_usize() + chunk_output.num_elements_usize())
+            * std::mem::size_of::<String>()
+            * 2
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| self.apply_chunk(input, output, &chunk_indices, &progress),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::{ArrayBuilder, FillValue};
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn string_transform() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = std::sync::Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![4],
+            DataType::String,
+            vec![2].try_into()?,
+            FillValue::from(""),
+        )
+        .build(store.clone(), "/input")?;
+        array.store_array_subset_elements::<&str>(
+            &array.subset_all(),
+            &["cell_01", "Cell_2", "nucleus", ""],
+        )?;
+
+        let mut array_output = ArrayBuilder::new(
+            vec![4],
+            DataType::String,
+            vec![3].try_into()?,
+            FillValue::from(""),
+        )
+        .build(store, "/output")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        StringTransform::new(
+            Some(Regex::new(r"(?i)cell_0*(\d+)")?),
+            "cell-$1".to_string(),
+            Some(StringCase::Upper),
+            None,
+        )
+        .apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements =
+            array_output.retrieve_array_subset_elements::<String>(&array_output.subset_all())?;
+        assert_eq!(elements, ["CELL-1", "CELL-2", "NUCLEUS", ""]);
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use clap::Parser;
 use itertools::Itertools;
 use num_traits::{AsPrimitive, Zero};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
     array::{data_type::UnsupportedDataTypeError, Array, DataType, Element, ElementOwned},
@@ -14,12 +15,12 @@ use zarrs::{
 use crate::{
     filter::{
         calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
-        FilterArguments, FilterCommonArguments,
+        try_for_each_chunk, FilterArguments, FilterCommonArguments,
     },
     progress::{Progress, ProgressCallback},
 };
 
-#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
 pub struct SummedAreaTableArguments {}
 
 impl FilterArguments for SummedAreaTableArguments {
@@ -99,7 +100,7 @@ impl SummedAreaTable {
             });
 
             progress.write(|| output.store_chunk_ndarray(&chunk_indices, chunk))?;
-            progress.next();
+            progress.next()?;
         }
 
         Ok(())
@@ -158,14 +159,11 @@ impl FilterTraits for SummedAreaTable {
             )
         };
 
-        let chunk_limit = if let Some(chunk_limit) = self.chunk_limit {
-            chunk_limit
-        } else {
-            calculate_chunk_limit(self.memory_per_chunk(
-                &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
-                &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
-            ))?
-        };
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
 
         let dimensionality = output.chunk_grid().dimensionality();
         let chunk_grid_shape = output.chunk_grid_shape().unwrap();
@@ -177,10 +175,10 @@ impl FilterTraits for SummedAreaTable {
                 .collect_vec();
             let chunks_dim = ArraySubset::new_with_shape(chunk_grid_shape_dim);
             let indices = chunks_dim.indices();
-            rayon_iter_concurrent_limit::iter_concurrent_limit!(
+            try_for_each_chunk(
                 chunk_limit,
-                indices,
-                try_for_each,
+                memory_per_chunk,
+                &indices,
                 |chunk_start_dim: Vec<u64>| {
                     macro_rules! sat {
                         ( $t_in:ty, $t_out:ty) => {{
@@ -245,7 +243,7 @@ impl FilterTraits for SummedAreaTable {
                         (Float64, f64)
                     ]);
                     Ok::<_, FilterError>(())
-                }
+                },
             )?;
         }
 
@@ -253,71 +251,6 @@ impl FilterTraits for SummedAreaTable {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::progress::ProgressStats;
-
-    use super::*;
-    use std::error::Error;
-    use zarrs::array::ArrayBuilder;
-
-    #[test]
-    fn summed_area_table() -> Result<(), Box<dyn Error>> {
-        let path = tempfile::TempDir::new()?;
-        let store = FilesystemStore::new(path.path())?;
-        let array = ArrayBuilder::new(
-            vec![6, 6],
-            DataType::UInt8,
-            vec![2, 2].try_into()?,
-            0u8.into(),
-        )
-        .build(store.into(), "/")?;
-        let array_subset = array.subset_all();
-        let elements_in: ndarray::ArrayD<u8> = ndarray::array![
-            [31, 2, 4, 33, 5, 36],
-            [12, 26, 9, 10, 29, 25],
-            [13, 17, 21, 22, 20, 18],
-            [24, 23, 15, 16, 14, 19],
-            [30, 8, 28, 27, 11, 7],
-            [1, 35, 34, 3, 32, 6],
-        ]
-        .into_dyn();
-        array.store_array_subset_ndarray(array_subset.start(), elements_in)?;
-
-        let elements = array.retrieve_array_subset_ndarray::<u8>(&array_subset)?;
-        println!("{elements:?}");
-
-        let path = tempfile::TempDir::new()?;
-        let store: FilesystemStore = FilesystemStore::new(path.path())?;
-        let mut array_output = array
-            .builder()
-            .data_type(DataType::UInt16)
-            .fill_value(0u16.into())
-            .build(store.into(), "/")?;
-        let progress_callback = |_stats: ProgressStats| {};
-        SummedAreaTable::new(None).apply(
-            &array,
-            &mut array_output,
-            &ProgressCallback::new(&progress_callback),
-        )?;
-        let elements = array_output.retrieve_array_subset_ndarray::<u16>(&array_subset)?;
-        println!("{elements:?}");
-
-        let elements_ref: ndarray::ArrayD<u16> = ndarray::array![
-            [31, 33, 37, 70, 75, 111],
-            [43, 71, 84, 127, 161, 222],
-            [56, 101, 135, 200, 254, 333],
-            [80, 148, 197, 278, 346, 444],
-            [110, 186, 263, 371, 450, 555],
-            [111, 222, 333, 444, 555, 666],
-        ]
-        .into_dyn();
-        approx::assert_abs_diff_eq!(elements, elements_ref);
-
-        Ok(())
-    }
-}
-
 /// Computes the summed area table on a single ndarray. Not suitable for computing on an entire zarr array.
 // FIXME: Generic
 pub fn summed_area_table_inplace(mut array: ndarray::ArrayD<f64>) -> ndarray::ArrayD<f64> {
@@ -409,3 +342,68 @@ pub fn summed_area_table_mean(
         .product::<usize>();
     sum / n_elements as f32
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::ProgressStats;
+
+    use super::*;
+    use std::error::Error;
+    use zarrs::array::ArrayBuilder;
+
+    #[test]
+    fn summed_area_table() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = FilesystemStore::new(path.path())?;
+        let array = ArrayBuilder::new(
+            vec![6, 6],
+            DataType::UInt8,
+            vec![2, 2].try_into()?,
+            0u8.into(),
+        )
+        .build(store.into(), "/")?;
+        let array_subset = array.subset_all();
+        let elements_in: ndarray::ArrayD<u8> = ndarray::array![
+            [31, 2, 4, 33, 5, 36],
+            [12, 26, 9, 10, 29, 25],
+            [13, 17, 21, 22, 20, 18],
+            [24, 23, 15, 16, 14, 19],
+            [30, 8, 28, 27, 11, 7],
+            [1, 35, 34, 3, 32, 6],
+        ]
+        .into_dyn();
+        array.store_array_subset_ndarray(array_subset.start(), elements_in)?;
+
+        let elements = array.retrieve_array_subset_ndarray::<u8>(&array_subset)?;
+        println!("{elements:?}");
+
+        let path = tempfile::TempDir::new()?;
+        let store: FilesystemStore = FilesystemStore::new(path.path())?;
+        let mut array_output = array
+            .builder()
+            .data_type(DataType::UInt16)
+            .fill_value(0u16.into())
+            .build(store.into(), "/")?;
+        let progress_callback = |_stats: ProgressStats| {};
+        SummedAreaTable::new(None).apply(
+            &array,
+            &mut array_output,
+            &ProgressCallback::new(&progress_callback),
+        )?;
+        let elements = array_output.retrieve_array_subset_ndarray::<u16>(&array_subset)?;
+        println!("{elements:?}");
+
+        let elements_ref: ndarray::ArrayD<u16> = ndarray::array![
+            [31, 33, 37, 70, 75, 111],
+            [43, 71, 84, 127, 161, 222],
+            [56, 101, 135, 200, 254, 333],
+            [80, 148, 197, 278, 346, 444],
+            [110, 186, 263, 371, 450, 555],
+            [111, 222, 333, 444, 555, 666],
+        ]
+        .into_dyn();
+        approx::assert_abs_diff_eq!(elements, elements_ref);
+
+        Ok(())
+    }
+}
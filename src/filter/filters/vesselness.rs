@@ -0,0 +1,446 @@
+use clap::{Parser, ValueEnum};
+use ndarray::ArrayD;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{codec::CodecOptions, data_type::UnsupportedDataTypeError, Array, DataType},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        kernel::apply_1d_difference_operator, retrieve_ndarray_as, store_ndarray_as,
+        try_for_each_chunk, ArraySubsetOverlap, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+use super::gaussian::Gaussian;
+
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize, JsonSchema, Default)]
+pub enum VesselnessMethod {
+    /// Frangi et al. (1998) vesselness.
+    #[default]
+    Frangi,
+    /// Sato et al. (1998) tubeness.
+    Sato,
+}
+
+fn default_alpha() -> f32 {
+    0.5
+}
+
+fn default_beta() -> f32 {
+    0.5
+}
+
+fn default_gamma() -> f32 {
+    15.0
+}
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct VesselnessArguments {
+    /// Gaussian scales (sigma) in elements, comma delimited.
+    ///
+    /// The output is the maximum response over all scales.
+    #[arg(required = true, value_delimiter = ',')]
+    pub sigmas: Vec<f32>,
+    /// Vesselness method.
+    #[arg(long, value_enum, default_value_t = VesselnessMethod::Frangi)]
+    #[serde(default)]
+    pub method: VesselnessMethod,
+    /// Frangi sensitivity to deviation from a plate-like structure. Ignored for 2D arrays.
+    #[arg(long, default_value_t = default_alpha())]
+    #[serde(default = "default_alpha")]
+    pub alpha: f32,
+    /// Frangi sensitivity to deviation from a blob-like structure.
+    #[arg(long, default_value_t = default_beta())]
+    #[serde(default = "default_beta")]
+    pub beta: f32,
+    /// Frangi sensitivity to second order structureness (the Hessian norm).
+    ///
+    /// This should be set relative to the intensity of the structures of interest, such as half of the maximum Hessian norm.
+    #[arg(long, default_value_t = default_gamma())]
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+    /// Enhance dark structures on a bright background, rather than bright structures on a dark background.
+    #[arg(long, default_value_t = false)]
+    #[serde(default)]
+    pub black_ridges: bool,
+}
+
+impl FilterArguments for VesselnessArguments {
+    fn name(&self) -> String {
+        "vesselness".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        if self.sigmas.iter().any(|sigma| *sigma <= 0.0) {
+            return Err(FilterError::InvalidParameters(
+                "vesselness sigmas must be positive".to_string(),
+            ));
+        }
+        Ok(Box::new(Vesselness::new(self, *common_args.chunk_limit())))
+    }
+}
+
+pub struct Vesselness {
+    arguments: VesselnessArguments,
+    chunk_limit: Option<usize>,
+}
+
+impl Vesselness {
+    pub fn new(arguments: &VesselnessArguments, chunk_limit: Option<usize>) -> Self {
+        Self {
+            arguments: arguments.clone(),
+            chunk_limit,
+        }
+    }
+
+    /// The Gaussian kernel half size for a scale.
+    fn kernel_half_size(sigma: f32) -> u64 {
+        (3.0 * sigma).ceil() as u64
+    }
+
+    /// The overlap required to compute the Hessian at all scales.
+    fn overlap(&self) -> u64 {
+        let sigma_max = self.arguments.sigmas.iter().copied().fold(0.0, f32::max);
+        Self::kernel_half_size(sigma_max) + 2
+    }
+
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        progress: &Progress,
+    ) -> Result<(), FilterError> {
+        let subset_output = output.chunk_subset_bounded(chunk_indices).unwrap();
+        let subset_overlap = ArraySubsetOverlap::new(
+            input.shape(),
+            &subset_output,
+            &vec![self.overlap(); input.dimensionality()],
+        );
+
+        let input_array =
+            progress.read(|| retrieve_ndarray_as::<f32>(input, subset_overlap.subset_input()))?;
+
+        let output_array = progress.process(|| {
+            let output_array = self.apply_ndarray(input_array);
+            subset_overlap.extract_subset(&output_array)
+        });
+
+        progress.write(|| {
+            store_ndarray_as(
+                output,
+                subset_output.start(),
+                &output_array,
+                &CodecOptions::default(),
+            )
+        })?;
+
+        progress.next()?;
+        Ok(())
+    }
+
+    /// Return the scale normalised Hessian of `input` at `sigma`.
+    ///
+    /// The upper triangular elements are returned in row major order.
+    /// The Hessian is negated unless detecting black ridges, so that ridges have positive principal curvature.
+    fn hessian(&self, input: ndarray::ArrayD<f32>, sigma: f32) -> Vec<ndarray::ArrayD<f32>> {
+        let ndim = input.ndim();
+        let smoothed = Gaussian::new(
+            vec![sigma; ndim],
+            vec![Self::kernel_half_size(sigma); ndim],
+            false,
+            None,
+        )
+        .apply_ndarray(input);
+        let scale = if self.arguments.black_ridges {
+            sigma * sigma
+        } else {
+            -sigma * sigma
+        };
+
+        let mut gradient = ArrayD::<f32>::zeros(smoothed.shape());
+        let mut hessian = Vec::with_capacity(ndim * (ndim + 1) / 2);
+        for i in 0..ndim {
+            apply_1d_difference_operator(i, &smoothed, &mut gradient);
+            for j in i..ndim {
+                let mut hessian_ij = ArrayD::<f32>::zeros(smoothed.shape());
+                apply_1d_difference_operator(j, &gradient, &mut hessian_ij);
+                hessian_ij.par_mapv_inplace(|h| h * scale);
+                hessian.push(hessian_ij);
+            }
+        }
+        hessian
+    }
+
+    /// Return the vesselness of the eigenvalues of a Hessian sorted by increasing magnitude.
+    fn vesselness(&self, eigenvalues: &[f64]) -> f64 {
+        let alpha = f64::from(self.arguments.alpha);
+        let beta = f64::from(self.arguments.beta);
+        let gamma = f64::from(self.arguments.gamma);
+        match (self.arguments.method, eigenvalues) {
+            (VesselnessMethod::Frangi, &[l1, l2]) => {
+                if l2 <= 0.0 {
+                    return 0.0;
+                }
+                let rb = l1 / l2;
+                let s2 = l1 * l1 + l2 * l2;
+                (-rb * rb / (2.0 * beta * beta)).exp() * (1.0 - (-s2 / (2.0 * gamma * gamma)).exp())
+            }
+            (VesselnessMethod::Frangi, &[l1, l2, l3]) => {
+                if l2 <= 0.0 || l3 <= 0.0 {
+                    return 0.0;
+                }
+                let ra = l2 / l3;
+                let rb = l1.abs() / (l2 * l3).sqrt();
+                let s2 = l1 * l1 + l2 * l2 + l3 * l3;
+                (1.0 - (-ra * ra / (2.0 * alpha * alpha)).exp())
+                    * (-rb * rb / (2.0 * beta * beta)).exp()
+                    * (1.0 - (-s2 / (2.0 * gamma * gamma)).exp())
+            }
+            (VesselnessMethod::Sato, eigenvalues) => {
+                // The geometric mean of all but the lowest eigenvalue, clipped to zero
+                let lowest = (0..eigenvalues.len())
+                    .min_by(|&a, &b| eigenvalues[a].total_cmp(&eigenvalues[b]))
+                    .unwrap();
+                let product = eigenvalues
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != lowest)
+                    .map(|(_, l)| l.max(0.0))
+                    .product::<f64>();
+                product.powf(1.0 / (eigenvalues.len() - 1) as f64)
+            }
+            _ => unreachable!("vesselness is only supported for 2D and 3D arrays"),
+        }
+    }
+
+    pub fn apply_ndarray(&self, input: ndarray::ArrayD<f32>) -> ndarray::ArrayD<f32> {
+        let mut vesselness = ArrayD::<f32>::zeros(input.shape());
+        for &sigma in &self.arguments.sigmas {
+            let hessian = self.hessian(input.clone(), sigma);
+            let hessian = hessian
+                .iter()
+                .map(|h| h.as_slice().unwrap())
+                .collect::<Vec<_>>();
+            vesselness
+                .as_slice_mut()
+                .unwrap()
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(i, v)| {
+                    let h = |k: usize| f64::from(hessian[k][i]);
+                    let vesselness_sigma = if hessian.len() == 3 {
+                        self.vesselness(&symmetric_eigenvalues_2(h(0), h(1), h(2)))
+                    } else {
+                        self.vesselness(&symmetric_eigenvalues_3(
+                            h(0),
+                            h(1),
+                            h(2),
+                            h(3),
+                            h(4),
+                            h(5),
+                        ))
+                    };
+                    *v = v.max(vesselness_sigma as f32);
+                });
+        }
+        vesselness
+    }
+}
+
+/// Return the eigenvalues of a 2x2 symmetric matrix sorted by increasing magnitude.
+fn symmetric_eigenvalues_2(a11: f64, a12: f64, a22: f64) -> [f64; 2] {
+    let mean = 0.5 * (a11 + a22);
+    let radius = (0.25 * (a11 - a22) * (a11 - a22) + a12 * a12).sqrt();
+    let mut eigenvalues = [mean - radius, mean + radius];
+    eigenvalues.sort_by(|a, b| a.abs().total_cmp(&b.abs()));
+    eigenvalues
+}
+
+/// Return the eigenvalues of a 3x3 symmetric matrix sorted by increasing magnitude.
+///
+/// Uses the trigonometric solution of the characteristic equation.
+fn symmetric_eigenvalues_3(a11: f64, a12: f64, a13: f64, a22: f64, a23: f64, a33: f64) -> [f64; 3] {
+    let p1 = a12 * a12 + a13 * a13 + a23 * a23;
+    let mut eigenvalues = if p1 == 0.0 {
+        [a11, a22, a33]
+    } else {
+        let q = (a11 + a22 + a33) / 3.0;
+        let p2 = (a11 - q).powi(2) + (a22 - q).powi(2) + (a33 - q).powi(2) + 2.0 * p1;
+        let p = (p2 / 6.0).sqrt();
+        let (b11, b22, b33) = ((a11 - q) / p, (a22 - q) / p, (a33 - q) / p);
+        let (b12, b13, b23) = (a12 / p, a13 / p, a23 / p);
+        let det_b = b11 * (b22 * b33 - b23 * b23) - b12 * (b12 * b33 - b23 * b13)
+            + b13 * (b12 * b23 - b22 * b13);
+        let phi = (det_b / 2.0).clamp(-1.0, 1.0).acos() / 3.0;
+        let l1 = q + 2.0 * p * phi.cos();
+        let l3 = q + 2.0 * p * (phi + 2.0 * std::f64::consts::PI / 3.0).cos();
+        [l1, 3.0 * q - l1 - l3, l3]
+    };
+    eigenvalues.sort_by(|a, b| a.abs().total_cmp(&b.abs()));
+    eigenvalues
+}
+
+impl FilterTraits for Vesselness {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        if !matches!(chunk_input.dimensionality(), 2 | 3) {
+            return Err(FilterError::InvalidParameters(format!(
+                "vesselness is only supported for 2D and 3D arrays, the input is {}D",
+                chunk_input.dimensionality()
+            )));
+        }
+        for data_type in [chunk_input.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Bool
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float16
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::BFloat16 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        let overlap = self.overlap();
+        let num_input_elements = usize::try_from(
+            chunk_output
+                .shape()
+                .iter()
+                .map(|s| s.get() + overlap * 2)
+                .product::<u64>(),
+        )
+        .unwrap();
+        let ndim = chunk_input.dimensionality();
+        let num_output_elements = chunk_output.num_elements_usize();
+        // input, smoothed, gradient, hessian, and vesselness
+        num_input_elements
+            * (chunk_input.data_type().fixed_size().unwrap()
+                + core::mem::size_of::<f32>() * (5 + ndim * (ndim + 1) / 2))
+            + num_output_elements
+                * (core::mem::size_of::<f32>() + chunk_output.data_type().fixed_size().unwrap())
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| self.apply_chunk(input, output, &chunk_indices, &progress),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::ProgressStats;
+
+    use super::*;
+    use std::error::Error;
+    use zarrs::array::ArrayBuilder;
+
+    #[test]
+    fn symmetric_eigenvalues() {
+        let eigenvalues = symmetric_eigenvalues_3(2.0, 1.0, 0.0, 2.0, 0.0, -5.0);
+        approx::assert_abs_diff_eq!(&eigenvalues[..], &[1.0, 3.0, -5.0][..], epsilon = 1e-9);
+        let eigenvalues = symmetric_eigenvalues_2(2.0, 1.0, 2.0);
+        approx::assert_abs_diff_eq!(&eigenvalues[..], &[1.0, 3.0][..], epsilon = 1e-9);
+    }
+
+    #[test]
+    fn vesselness() -> Result<(), Box<dyn Error>> {
+        // A bright tube along the last axis
+        let path = tempfile::TempDir::new()?;
+        let store = FilesystemStore::new(path.path())?;
+        let array = ArrayBuilder::new(
+            vec![16, 16, 16],
+            DataType::Float32,
+            vec![8, 8, 8].try_into()?,
+            0.0f32.into(),
+        )
+        .build(store.into(), "/")?;
+        let tube = ndarray::Array3::from_shape_fn((16, 16, 16), |(z, y, _x)| {
+            let r2 = (z as f32 - 8.0).powi(2) + (y as f32 - 8.0).powi(2);
+            100.0 * (-r2 / 4.0).exp()
+        });
+        array.store_array_subset_ndarray(&[0, 0, 0], tube)?;
+
+        for method in [VesselnessMethod::Frangi, VesselnessMethod::Sato] {
+            let arguments = VesselnessArguments {
+                sigmas: vec![1.0, 2.0],
+                method,
+                alpha: default_alpha(),
+                beta: default_beta(),
+                gamma: default_gamma(),
+                black_ridges: false,
+            };
+            let vesselness = Vesselness::new(&arguments, None);
+            let path = tempfile::TempDir::new()?;
+            let store: FilesystemStore = FilesystemStore::new(path.path())?;
+            let mut array_output = array.builder().build(store.into(), "/")?;
+            let progress_callback = |_stats: ProgressStats| {};
+            vesselness.apply(
+                &array,
+                &mut array_output,
+                &ProgressCallback::new(&progress_callback),
+            )?;
+            let elements =
+                array_output.retrieve_array_subset_ndarray::<f32>(&array.subset_all())?;
+
+            // Chunked processing matches processing the whole array
+            let elements_ref = vesselness
+                .apply_ndarray(array.retrieve_array_subset_ndarray::<f32>(&array.subset_all())?);
+            approx::assert_abs_diff_eq!(elements, elements_ref, epsilon = 1e-4);
+
+            // The response is high in the tube and low in the background
+            assert!(elements[[8, 8, 8]] > 0.5 * elements.fold(0.0, |a: f32, &b| a.max(b)));
+            assert!(elements[[1, 1, 8]] < 0.01 * elements[[8, 8, 8]]);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,563 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Arc,
+    },
+};
+
+use clap::Parser;
+use num_traits::AsPrimitive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zarrs::{
+    array::{data_type::UnsupportedDataTypeError, Array, DataType, FillValue},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+};
+
+use crate::{
+    filter::{
+        calculate_chunk_limit, filter_error::FilterError, filter_traits::FilterTraits,
+        try_for_each_chunk, ArraySubsetOverlap, FilterArguments, FilterCommonArguments,
+    },
+    progress::{Progress, ProgressCallback},
+};
+
+#[derive(Debug, Clone, Parser, Serialize, Deserialize, JsonSchema)]
+pub struct WatershedArguments {
+    /// The path to the seed array (integer). It must have the same shape as the input.
+    ///
+    /// Nonzero elements are seeds of their label. The output has the data type of the seed array.
+    pub seeds: PathBuf,
+
+    /// The overlap (halo) of each chunk along each axis, in elements.
+    ///
+    /// Basins are flooded from seeds within the overlap of a chunk, so a larger overlap better approximates a global watershed.
+    /// It must not exceed the output chunk shape.
+    #[arg(long, default_value_t = 16)]
+    #[serde(default = "default_overlap")]
+    pub overlap: u64,
+
+    /// The maximum number of boundary merging passes.
+    ///
+    /// Each pass floods unlabelled elements from the labels of neighbouring chunks, until no elements change.
+    #[arg(long, default_value_t = 16)]
+    #[serde(default = "default_max_merge_passes")]
+    pub max_merge_passes: usize,
+}
+
+fn default_overlap() -> u64 {
+    16
+}
+
+fn default_max_merge_passes() -> usize {
+    16
+}
+
+impl FilterArguments for WatershedArguments {
+    fn name(&self) -> String {
+        "watershed".to_string()
+    }
+
+    fn init(
+        &self,
+        common_args: &FilterCommonArguments,
+    ) -> Result<Box<dyn FilterTraits>, FilterError> {
+        let store = FilesystemStore::new(&self.seeds).map_err(|err| {
+            FilterError::InvalidParameters(format!("cannot open seeds {:?}: {err}", self.seeds))
+        })?;
+        let seeds = Array::open(Arc::new(store), "/")?;
+        Ok(Box::new(Watershed::new(
+            seeds,
+            self.overlap,
+            self.max_merge_passes,
+            *common_args.chunk_limit(),
+        )))
+    }
+}
+
+/// An element in the priority queue of a flood, ordered by increasing intensity and then insertion order.
+struct FloodElement {
+    intensity: f64,
+    order: usize,
+    index: usize,
+}
+
+impl PartialEq for FloodElement {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FloodElement {}
+
+impl PartialOrd for FloodElement {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloodElement {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, so the binary heap pops the lowest intensity first
+        other
+            .intensity
+            .total_cmp(&self.intensity)
+            .then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+/// Flood the unlabelled (zero) elements of `labels` from labelled elements in order of increasing `intensity` (priority flooding), with face connectivity.
+///
+/// Returns true if any element was labelled.
+pub fn flood(intensity: &[f64], labels: &mut [u64], shape: &[usize]) -> bool {
+    assert_eq!(intensity.len(), labels.len());
+    let mut strides = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1];
+    }
+    let strides = &strides;
+    let neighbours = move |index: usize| {
+        std::iter::zip(strides, shape).flat_map(move |(&stride, &size)| {
+            let coordinate = (index / stride) % size;
+            [
+                (coordinate > 0).then(|| index - stride),
+                (coordinate + 1 < size).then(|| index + stride),
+            ]
+            .into_iter()
+            .flatten()
+        })
+    };
+
+    let mut heap = BinaryHeap::new();
+    let mut order = 0;
+    for index in 0..labels.len() {
+        if labels[index] != 0 && neighbours(index).any(|neighbour| labels[neighbour] == 0) {
+            heap.push(FloodElement {
+                intensity: intensity[index],
+                order,
+                index,
+            });
+            order += 1;
+        }
+    }
+
+    let mut changed = false;
+    while let Some(FloodElement { index, .. }) = heap.pop() {
+        let label = labels[index];
+        for neighbour in neighbours(index) {
+            if labels[neighbour] == 0 {
+                labels[neighbour] = label;
+                heap.push(FloodElement {
+                    intensity: intensity[neighbour],
+                    order,
+                    index: neighbour,
+                });
+                order += 1;
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// Seeded watershed segmentation.
+///
+/// Each chunk (with an overlap) is flooded from the seeds in order of increasing input intensity (e.g. a gradient magnitude).
+/// Elements not reached from a seed within the overlap of their chunk are then flooded from the labels of neighbouring chunks in boundary merging passes.
+pub struct Watershed {
+    seeds: Array<FilesystemStore>,
+    overlap: u64,
+    max_merge_passes: usize,
+    chunk_limit: Option<usize>,
+}
+
+impl Watershed {
+    pub fn new(
+        seeds: Array<FilesystemStore>,
+        overlap: u64,
+        max_merge_passes: usize,
+        chunk_limit: Option<usize>,
+    ) -> Self {
+        Self {
+            seeds,
+            overlap,
+            max_merge_passes,
+            chunk_limit,
+        }
+    }
+
+    /// Flood a chunk of the output from the seeds, or from the labels of the output if `merge` is true.
+    ///
+    /// Returns true if the chunk was written, which is only if an element was labelled when merging.
+    pub fn apply_chunk(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &Array<FilesystemStore>,
+        chunk_indices: &[u64],
+        merge: bool,
+        progress: &Progress,
+    ) -> Result<bool, FilterError> {
+        let subset_output = output.chunk_subset_bounded(chunk_indices).unwrap();
+        let count_unlabelled = |labels: &[u64]| labels.iter().filter(|label| **label == 0).count();
+        let unlabelled = if merge {
+            let labels = progress.read(|| retrieve_labels(output, &subset_output))?;
+            let unlabelled = count_unlabelled(&labels);
+            if unlabelled == 0 {
+                progress.next()?;
+                return Ok(false);
+            }
+            unlabelled
+        } else {
+            0
+        };
+
+        let subset_overlap = ArraySubsetOverlap::new(
+            input.shape(),
+            &subset_output,
+            &vec![self.overlap; input.dimensionality()],
+        );
+        let subset_input = subset_overlap.subset_input();
+        let mut labels = progress
+            .read(|| retrieve_labels(if merge { output } else { &self.seeds }, subset_input))?;
+        if merge && labels.iter().all(|label| *label == 0) {
+            progress.next()?;
+            return Ok(false);
+        }
+        let intensity = progress.read(|| retrieve_intensity(input, subset_input))?;
+
+        let shape = subset_input.shape_usize();
+        let changed = progress.process_step("flood", || flood(&intensity, &mut labels, &shape));
+        drop(intensity);
+        if merge && !changed {
+            progress.next()?;
+            return Ok(false);
+        }
+
+        let labels = ndarray::ArrayD::from_shape_vec(shape, labels).unwrap();
+        let labels = subset_overlap.extract_subset(&labels);
+        let labels = labels.iter().copied().collect::<Vec<_>>();
+        if merge && count_unlabelled(&labels) == unlabelled {
+            progress.next()?;
+            return Ok(false);
+        }
+        progress.write(|| store_labels(output, &subset_output, labels))?;
+
+        progress.next()?;
+        Ok(true)
+    }
+}
+
+/// Retrieve the elements of an integer array in `subset` as `u64` labels.
+fn retrieve_labels(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+) -> Result<Vec<u64>, FilterError> {
+    macro_rules! retrieve {
+        ( $( ( $data_type:ident, $t:ty ) ),* ) => {
+            match array.data_type() {
+                $(DataType::$data_type => Ok(array
+                    .retrieve_array_subset_elements::<$t>(subset)?
+                    .into_iter()
+                    .map(|label| label as u64)
+                    .collect()),)*
+                data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            }
+        };
+    }
+    retrieve!(
+        (Int8, i8),
+        (Int16, i16),
+        (Int32, i32),
+        (Int64, i64),
+        (UInt8, u8),
+        (UInt16, u16),
+        (UInt32, u32),
+        (UInt64, u64)
+    )
+}
+
+/// Store `u64` labels in `subset` of an integer array.
+fn store_labels(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+    labels: Vec<u64>,
+) -> Result<(), FilterError> {
+    macro_rules! store {
+        ( $( ( $data_type:ident, $t:ty ) ),* ) => {
+            match array.data_type() {
+                $(DataType::$data_type => {
+                    let labels = labels.into_iter().map(|label| label as $t).collect::<Vec<_>>();
+                    array.store_array_subset_elements::<$t>(subset, &labels)?;
+                })*
+                data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            }
+        };
+    }
+    store!(
+        (Int8, i8),
+        (Int16, i16),
+        (Int32, i32),
+        (Int64, i64),
+        (UInt8, u8),
+        (UInt16, u16),
+        (UInt32, u32),
+        (UInt64, u64)
+    );
+    Ok(())
+}
+
+/// Retrieve the elements of a numeric array in `subset` as `f64`.
+fn retrieve_intensity(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+) -> Result<Vec<f64>, FilterError> {
+    macro_rules! retrieve {
+        ( $( ( $data_type:ident, $t:ty ) ),* ) => {
+            match array.data_type() {
+                $(DataType::$data_type => Ok(array
+                    .retrieve_array_subset_elements::<$t>(subset)?
+                    .into_iter()
+                    .map(AsPrimitive::<f64>::as_)
+                    .collect()),)*
+                data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            }
+        };
+    }
+    retrieve!(
+        (Int8, i8),
+        (Int16, i16),
+        (Int32, i32),
+        (Int64, i64),
+        (UInt8, u8),
+        (UInt16, u16),
+        (UInt32, u32),
+        (UInt64, u64),
+        (BFloat16, half::bf16),
+        (Float16, half::f16),
+        (Float32, f32),
+        (Float64, f64)
+    )
+}
+
+impl FilterTraits for Watershed {
+    fn is_compatible(
+        &self,
+        chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> Result<(), FilterError> {
+        match chunk_input.data_type() {
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::BFloat16
+            | DataType::Float16
+            | DataType::Float32
+            | DataType::Float64 => {}
+            data_type => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+        };
+        for data_type in [self.seeds.data_type(), chunk_output.data_type()] {
+            match data_type {
+                DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64 => {}
+                _ => Err(UnsupportedDataTypeError::from(data_type.to_string()))?,
+            };
+        }
+        if chunk_output
+            .shape()
+            .iter()
+            .any(|chunk_size| self.overlap > chunk_size.get())
+        {
+            return Err(FilterError::InvalidParameters(format!(
+                "the watershed overlap {} exceeds the output chunk shape {:?}",
+                self.overlap,
+                chunk_output.shape()
+            )));
+        }
+        Ok(())
+    }
+
+    fn memory_per_chunk(
+        &self,
+        _chunk_input: &zarrs::array::ChunkRepresentation,
+        chunk_output: &zarrs::array::ChunkRepresentation,
+    ) -> usize {
+        // The intensity (f64), labels (u64), and priority queue of a chunk with its overlap
+        let num_elements = chunk_output
+            .shape()
+            .iter()
+            .map(|chunk_size| usize::try_from(chunk_size.get() + 2 * self.overlap).unwrap())
+            .product::<usize>();
+        num_elements * (2 * std::mem::size_of::<u64>() + std::mem::size_of::<FloodElement>())
+    }
+
+    fn output_data_type(&self, _input: &Array<FilesystemStore>) -> Option<(DataType, FillValue)> {
+        let data_type = self.seeds.data_type().clone();
+        let fill_value = FillValue::new(vec![0; data_type.fixed_size().unwrap_or(1)]);
+        Some((data_type, fill_value))
+    }
+
+    fn apply(
+        &self,
+        input: &Array<FilesystemStore>,
+        output: &mut Array<FilesystemStore>,
+        progress_callback: &ProgressCallback,
+    ) -> Result<(), FilterError> {
+        assert_eq!(output.shape(), input.shape());
+        if self.seeds.shape() != input.shape() {
+            return Err(FilterError::InvalidParameters(format!(
+                "the seeds shape {:?} does not match the input shape {:?}",
+                self.seeds.shape(),
+                input.shape()
+            )));
+        }
+
+        let chunks = ArraySubset::new_with_shape(output.chunk_grid_shape().unwrap());
+        let memory_per_chunk = self.memory_per_chunk(
+            &input.chunk_array_representation(&vec![0; input.dimensionality()])?,
+            &output.chunk_array_representation(&vec![0; input.dimensionality()])?,
+        );
+        let chunk_limit = calculate_chunk_limit(self.chunk_limit);
+
+        // Flood each chunk from the seeds
+        let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+        let indices = chunks.indices();
+        try_for_each_chunk(
+            chunk_limit,
+            memory_per_chunk,
+            &indices,
+            |chunk_indices: Vec<u64>| {
+                self.apply_chunk(input, output, &chunk_indices, false, &progress)
+                    .map(|_| ())
+            },
+        )?;
+
+        // Flood unlabelled elements from neighbouring chunks until no elements change.
+        // Chunks are processed in 2^N alternating groups, so the overlap of a chunk is not written concurrently.
+        let dimensionality = output.dimensionality();
+        for _ in 0..self.max_merge_passes {
+            let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+            let changed = AtomicBool::new(false);
+            for group in 0..1usize << dimensionality {
+                let indices = chunks
+                    .indices()
+                    .into_iter()
+                    .filter(|chunk_indices| {
+                        chunk_indices
+                            .iter()
+                            .enumerate()
+                            .all(|(i, index)| (index % 2) as usize == (group >> i) & 1)
+                    })
+                    .collect::<Vec<_>>();
+                try_for_each_chunk(
+                    chunk_limit,
+                    memory_per_chunk,
+                    indices,
+                    |chunk_indices: Vec<u64>| {
+                        if self.apply_chunk(input, output, &chunk_indices, true, &progress)? {
+                            changed.store(true, AtomicOrdering::Relaxed);
+                        }
+                        Ok::<_, FilterError>(())
+                    },
+                )?;
+            }
+            if !changed.into_inner() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::array::ArrayBuilder;
+
+    use crate::progress::ProgressStats;
+
+    use super::*;
+
+    #[test]
+    fn watershed_flood() {
+        #[rustfmt::skip]
+        let intensity = [
+            0.0, 5.0, 0.0,
+            0.0, 5.0, 0.0,
+            0.0, 5.0, 0.0,
+        ];
+        #[rustfmt::skip]
+        let mut labels = [
+            1, 0, 2,
+            0, 0, 0,
+            0, 0, 0,
+        ];
+        assert!(flood(&intensity, &mut labels, &[3, 3]));
+        for row in labels.chunks(3) {
+            assert_eq!((row[0], row[2]), (1, 2));
+        }
+        assert!(!flood(&intensity, &mut labels, &[3, 3]));
+    }
+
+    #[test]
+    fn watershed() -> Result<(), Box<dyn Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = Arc::new(FilesystemStore::new(path.path())?);
+        let input = ArrayBuilder::new(
+            vec![8],
+            DataType::Float32,
+            vec![4].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(store.clone(), "/input")?;
+        input.store_array_subset_elements::<f32>(
+            &input.subset_all(),
+            &[0.0, 1.0, 2.0, 3.0, 9.0, 3.0, 2.0, 1.0],
+        )?;
+
+        let progress_callback = |_stats: ProgressStats| {};
+        for (seeds_elements, expected) in [
+            // Seeds in both chunks
+            ([1, 0, 0, 0, 0, 0, 0, 2], [1, 1, 1, 1, 2, 2, 2, 2]),
+            // Seeds in one chunk, which are merged into the other
+            ([0, 3, 0, 0, 0, 0, 0, 0], [3; 8]),
+        ] {
+            let seeds = ArrayBuilder::new(
+                vec![8],
+                DataType::UInt8,
+                vec![8].try_into()?,
+                FillValue::from(0u8),
+            )
+            .build(store.clone(), "/seeds")?;
+            seeds.store_array_subset_elements::<u8>(&seeds.subset_all(), &seeds_elements)?;
+
+            let watershed = Watershed::new(seeds, 1, 16, None);
+            let (data_type, fill_value) = watershed.output_data_type(&input).unwrap();
+            let mut output = ArrayBuilder::new(vec![8], data_type, vec![4].try_into()?, fill_value)
+                .build(store.clone(), "/output")?;
+            watershed.apply(
+                &input,
+                &mut output,
+                &ProgressCallback::new(&progress_callback),
+            )?;
+            let elements = output.retrieve_array_subset_elements::<u8>(&output.subset_all())?;
+            assert_eq!(elements, expected);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use ndarray::ArrayD;
+use num_traits::AsPrimitive;
+use zarrs::array::{Array, DataType};
+
+use crate::cast::{convert_fill_value, CastPolicy};
+
+use super::filter_error::FilterError;
+
+/// Identifies missing data (fill value or NaN) elements of a filter input.
+///
+/// Filters supporting missing data exclude missing elements from their computations, and write the output fill value where there is no valid support.
+#[derive(Debug, Clone, Copy)]
+pub struct MissingData {
+    fill_value_input: f64,
+    fill_value_output: f64,
+}
+
+/// Returns the fill value of `array` as a [`f64`].
+fn fill_value_f64<TStorage: ?Sized>(array: &Array<TStorage>) -> Result<f64, FilterError> {
+    let fill_value = convert_fill_value(
+        array.data_type(),
+        array.fill_value(),
+        &DataType::Float64,
+        CastPolicy::Saturate,
+    )?;
+    Ok(f64::from_ne_bytes(
+        fill_value.as_ne_bytes().try_into().unwrap(),
+    ))
+}
+
+impl MissingData {
+    /// Create a new missing data identifier given the input and output fill values.
+    pub fn new(fill_value_input: f64, fill_value_output: f64) -> Self {
+        Self {
+            fill_value_input,
+            fill_value_output,
+        }
+    }
+
+    /// Create a new missing data identifier given the fill values of the `input` and `output` arrays.
+    ///
+    /// # Errors
+    /// Returns a [`FilterError`] if a fill value cannot be converted to a [`f64`] (e.g. a complex data type).
+    pub fn from_arrays<TStorageIn: ?Sized, TStorageOut: ?Sized>(
+        input: &Array<TStorageIn>,
+        output: &Array<TStorageOut>,
+    ) -> Result<Self, FilterError> {
+        Ok(Self::new(fill_value_f64(input)?, fill_value_f64(output)?))
+    }
+
+    /// Returns true if an input `value` is missing.
+    pub fn is_missing(&self, value: f64) -> bool {
+        value.is_nan() || value == self.fill_value_input
+    }
+
+    /// Returns the output fill value.
+    pub fn fill_value<T: Copy + 'static>(&self) -> T
+    where
+        f64: AsPrimitive<T>,
+    {
+        self.fill_value_output.as_()
+    }
+
+    /// Returns true for each element of `input` that is not missing.
+    pub fn valid<T: Copy + AsPrimitive<f64>>(&self, input: &ArrayD<T>) -> ArrayD<bool> {
+        input.map(|value| !self.is_missing(value.as_()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, sync::Arc};
+
+    use zarrs::{
+        array::{ArrayBuilder, FillValue},
+        storage::store::MemoryStore,
+    };
+
+    use super::*;
+
+    #[test]
+    fn missing_data() -> Result<(), Box<dyn Error>> {
+        let store = Arc::new(MemoryStore::new());
+        let input = ArrayBuilder::new(
+            vec![4],
+            DataType::UInt16,
+            vec![4].try_into()?,
+            FillValue::from(65535u16),
+        )
+        .build(store.clone(), "/input")?;
+        let output = ArrayBuilder::new(
+            vec![4],
+            DataType::Float32,
+            vec![4].try_into()?,
+            FillValue::from(-1.0f32),
+        )
+        .build(store, "/output")?;
+        let missing_data = MissingData::from_arrays(&input, &output)?;
+        assert!(missing_data.is_missing(65535.0));
+        assert!(missing_data.is_missing(f64::NAN));
+        assert!(!missing_data.is_missing(0.0));
+        assert_eq!(missing_data.fill_value::<f32>(), -1.0);
+
+        let values = ndarray::array![1u16, 65535, 3].into_dyn();
+        assert_eq!(
+            missing_data.valid(&values),
+            ndarray::array![true, false, true].into_dyn()
+        );
+        Ok(())
+    }
+}
@@ -14,6 +14,16 @@ pub fn parse_path_or_identifier(path_or_id: &str) -> std::io::Result<PathOrIdent
     }
 }
 
+impl schemars::JsonSchema for PathOrIdentifier {
+    fn schema_name() -> String {
+        "PathOrIdentifier".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        <String as schemars::JsonSchema>::json_schema(gen)
+    }
+}
+
 impl<'de> serde::Deserialize<'de> for PathOrIdentifier {
     fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
         let path_or_id = String::deserialize(d)?;
@@ -0,0 +1,273 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use schemars::{schema::RootSchema, JsonSchema};
+use serde_json::Value;
+
+use super::{FilterCommand, FilterError};
+
+/// Load a JSON run configuration from `path`.
+///
+/// A run configuration is an array of filters, or a single filter.
+///
+/// Strings may reference variables as `${NAME}` or `${NAME:-default}`, which are resolved from `variables`, then the environment, then the default.
+/// `$${` is a literal `${`.
+///
+/// A filter with an `include` (a path or an array of paths, relative to the including configuration) is merged over the included configurations.
+/// An included configuration can be a partial filter (e.g. shared encoding parameters), or an array of filters which are spliced into the run configuration.
+///
+/// # Errors
+/// Returns a [`FilterError`] if a configuration cannot be read or parsed, a variable is undefined, or includes are recursive.
+pub fn load_run_config(
+    path: &Path,
+    variables: &HashMap<String, String>,
+) -> Result<Vec<FilterCommand>, FilterError> {
+    let filters = load(path, variables, &mut vec![])?;
+    Ok(serde_json::from_value(Value::Array(filters))?)
+}
+
+/// Returns a JSON Schema of a run configuration, derived from [`FilterCommand`].
+///
+/// The schema describes a run configuration after variables and includes are resolved.
+pub fn run_config_schema() -> RootSchema {
+    /// A run configuration.
+    #[derive(JsonSchema)]
+    #[schemars(untagged)]
+    #[allow(dead_code)]
+    enum RunConfig {
+        Filters(Vec<FilterCommand>),
+        Filter(Box<FilterCommand>),
+    }
+    let mut schema = schemars::schema_for!(RunConfig);
+    schema.schema.metadata().title = Some("zarrs_filter run configuration".to_string());
+    schema
+}
+
+/// Load the configuration at `path` as an array of (possibly partial) filters.
+fn load(
+    path: &Path,
+    variables: &HashMap<String, String>,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Vec<Value>, FilterError> {
+    let canonical_path = path.canonicalize().map_err(|err| {
+        FilterError::InvalidParameters(format!("cannot read run configuration {path:?}: {err}"))
+    })?;
+    if stack.contains(&canonical_path) {
+        return Err(FilterError::InvalidParameters(format!(
+            "run configuration {path:?} includes itself"
+        )));
+    }
+    let config: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let config = substitute_value(config, variables)?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    stack.push(canonical_path);
+    let filters = expand(config, &dir, variables, stack);
+    stack.pop();
+    filters
+}
+
+/// Expand the includes of `config`.
+fn expand(
+    config: Value,
+    dir: &Path,
+    variables: &HashMap<String, String>,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Vec<Value>, FilterError> {
+    match config {
+        Value::Array(configs) => {
+            let mut filters = vec![];
+            for config in configs {
+                filters.append(&mut expand(config, dir, variables, stack)?);
+            }
+            Ok(filters)
+        }
+        Value::Object(mut overrides) => {
+            let Some(include) = overrides.remove("include") else {
+                return Ok(vec![Value::Object(overrides)]);
+            };
+            let includes = match include {
+                Value::String(include) => vec![include],
+                Value::Array(includes) => includes
+                    .into_iter()
+                    .map(|include| match include {
+                        Value::String(include) => Ok(include),
+                        _ => Err(FilterError::InvalidParameters(
+                            "include must be a path or an array of paths".to_string(),
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?,
+                _ => {
+                    return Err(FilterError::InvalidParameters(
+                        "include must be a path or an array of paths".to_string(),
+                    ))
+                }
+            };
+
+            // Merge each included filter over each filter of the previous includes
+            let mut filters = vec![Value::Object(serde_json::Map::new())];
+            for include in includes {
+                let included = load(&dir.join(include), variables, stack)?;
+                filters = filters
+                    .iter()
+                    .flat_map(|filter| {
+                        included.iter().map(|included| {
+                            let mut filter = filter.clone();
+                            merge(&mut filter, included.clone());
+                            filter
+                        })
+                    })
+                    .collect();
+            }
+            let overrides = Value::Object(overrides);
+            for filter in &mut filters {
+                merge(filter, overrides.clone());
+            }
+            Ok(filters)
+        }
+        _ => Err(FilterError::InvalidParameters(
+            "a run configuration must be an array of filters or a filter".to_string(),
+        )),
+    }
+}
+
+/// Merge `value` into `target`, recursively merging objects.
+fn merge(target: &mut Value, value: Value) {
+    match (target, value) {
+        (Value::Object(target), Value::Object(value)) => {
+            for (key, value) in value {
+                match target.get_mut(&key) {
+                    Some(target) => merge(target, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, value) => *target = value,
+    }
+}
+
+/// Substitute variables in the strings of `value`.
+fn substitute_value(
+    value: Value,
+    variables: &HashMap<String, String>,
+) -> Result<Value, FilterError> {
+    Ok(match value {
+        Value::String(string) => Value::String(substitute(&string, variables)?),
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| substitute_value(value, variables))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| Ok((key, substitute_value(value, variables)?)))
+                .collect::<Result<_, FilterError>>()?,
+        ),
+        value => value,
+    })
+}
+
+/// Substitute `${NAME}` and `${NAME:-default}` variables in `string`.
+fn substitute(string: &str, variables: &HashMap<String, String>) -> Result<String, FilterError> {
+    let mut output = String::with_capacity(string.len());
+    let mut remaining = string;
+    while let Some(start) = remaining.find("${") {
+        if remaining[..start].ends_with('$') {
+            // Escaped
+            output.push_str(&remaining[..start - 1]);
+            output.push_str("${");
+            remaining = &remaining[start + 2..];
+            continue;
+        }
+        output.push_str(&remaining[..start]);
+        let Some(end) = remaining[start..].find('}') else {
+            return Err(FilterError::InvalidParameters(format!(
+                "unterminated variable in {string:?}"
+            )));
+        };
+        let variable = &remaining[start + 2..start + end];
+        let (name, default) = variable
+            .split_once(":-")
+            .map_or((variable, None), |(name, default)| (name, Some(default)));
+        let value = variables
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+            .or_else(|| default.map(str::to_string))
+            .ok_or_else(|| FilterError::InvalidParameters(format!("undefined variable {name}")))?;
+        output.push_str(&value);
+        remaining = &remaining[start + end + 1..];
+    }
+    output.push_str(remaining);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_config_substitute() -> Result<(), FilterError> {
+        let variables = HashMap::from([("INPUT".to_string(), "array.zarr".to_string())]);
+        assert_eq!(substitute("${INPUT}", &variables)?, "array.zarr");
+        assert_eq!(
+            substitute("out/${NAME:-default}.zarr", &variables)?,
+            "out/default.zarr"
+        );
+        assert_eq!(substitute("$${INPUT} $tmp", &variables)?, "${INPUT} $tmp");
+        assert!(substitute("${ZARRS_TOOLS_UNDEFINED}", &variables).is_err());
+        assert!(substitute("${INPUT", &variables).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn run_config_include() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(
+            dir.path().join("encoding.json"),
+            r#"{ "filter": "reencode", "chunk_shape": [32, 32], "output": "${OUTPUT:-out.zarr}" }"#,
+        )?;
+        std::fs::write(
+            dir.path().join("run.json"),
+            r#"[{ "include": "encoding.json", "input": "${INPUT}", "data_type": "float32" }]"#,
+        )?;
+        let variables = HashMap::from([("INPUT".to_string(), "in.zarr".to_string())]);
+        let filters = load(&dir.path().join("run.json"), &variables, &mut vec![])?;
+        assert_eq!(
+            filters,
+            [serde_json::json!({
+                "filter": "reencode",
+                "chunk_shape": [32, 32],
+                "output": "out.zarr",
+                "input": "in.zarr",
+                "data_type": "float32"
+            })]
+        );
+        assert_eq!(
+            load_run_config(&dir.path().join("run.json"), &variables)?.len(),
+            1
+        );
+
+        std::fs::write(
+            dir.path().join("recursive.json"),
+            r#"{ "include": "recursive.json" }"#,
+        )?;
+        assert!(load(&dir.path().join("recursive.json"), &variables, &mut vec![]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn run_config_schema_filters() {
+        let schema = serde_json::to_value(run_config_schema()).unwrap();
+        assert_eq!(schema["title"], "zarrs_filter run configuration");
+        let schema = schema.to_string();
+        assert!(schema.contains(r#""gaussian""#));
+        assert!(schema.contains(r#""kernel_half_size""#));
+        assert!(schema.contains(r#""chunk_shape""#));
+    }
+}
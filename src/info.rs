@@ -1,5 +1,15 @@
+pub mod chunk_statistics;
+pub mod codec_metrics;
 pub mod histogram;
+pub mod lint;
+pub mod ome;
 pub mod range;
+pub mod storage_usage;
 
+pub use chunk_statistics::*;
+pub use codec_metrics::*;
 pub use histogram::*;
+pub use lint::*;
+pub use ome::*;
 pub use range::*;
+pub use storage_usage::*;
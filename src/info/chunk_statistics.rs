@@ -0,0 +1,272 @@
+use std::sync::Arc;
+
+use half::{bf16, f16};
+use num_traits::AsPrimitive;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon_iter_concurrent_limit::iter_concurrent_limit;
+use serde::Serialize;
+use zarrs::{
+    array::{
+        Array, ArrayBuilder, ArrayCreateError, ArrayError, DataType, Element, ElementOwned,
+        FillValue,
+    },
+    array_subset::ArraySubset,
+    group::{GroupBuilder, GroupCreateError},
+    storage::{ReadableStorageTraits, ReadableWritableStorageTraits, StorageError},
+};
+
+/// The attribute key of the chunk statistics sidecar group.
+pub const CHUNK_STATISTICS_ATTRIBUTE: &str = "chunk_statistics";
+
+/// Histogram parameters of chunk statistics.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChunkStatisticsHistogram {
+    /// The number of bins.
+    pub n_bins: usize,
+    /// The lower edge of the first bin.
+    pub min: f64,
+    /// The upper edge of the last bin.
+    pub max: f64,
+}
+
+/// A summary of a chunk statistics sidecar.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChunkStatistics {
+    /// The shape of the chunk grid of the input array, which is the shape of the `min`/`max` arrays.
+    pub chunk_grid_shape: Vec<u64>,
+    /// The number of chunks.
+    pub num_chunks: u64,
+    /// The histogram parameters, if histograms were computed.
+    pub histogram: Option<ChunkStatisticsHistogram>,
+}
+
+/// A chunk statistics error.
+#[derive(Debug, thiserror::Error)]
+pub enum ChunkStatisticsError {
+    #[error(transparent)]
+    Array(#[from] ArrayError),
+    #[error(transparent)]
+    ArrayCreate(#[from] ArrayCreateError),
+    #[error(transparent)]
+    GroupCreate(#[from] GroupCreateError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error("unsupported data type {_0}")]
+    UnsupportedDataType(String),
+}
+
+/// Compute the minimum and maximum (and optionally a histogram) of each chunk of `array` and write them to a sidecar group in `store_out`.
+///
+/// The sidecar group contains:
+///  - `min` and `max`: arrays with the shape of the chunk grid and the data type of `array`, and
+///  - `histogram`: a `uint64` array with the shape of the chunk grid with an additional trailing axis of length `n_bins`.
+///
+/// The statistics of a chunk that does not exist in the store are those of the fill value.
+/// NaN elements are ignored, unless all elements of a chunk are NaN.
+///
+/// # Errors
+/// Returns a [`ChunkStatisticsError`] if a chunk cannot be retrieved, the data type is unsupported, or the sidecar cannot be written.
+pub fn calculate_chunk_statistics<
+    TStorage: ReadableStorageTraits + ?Sized + 'static,
+    TStorageOut: ReadableWritableStorageTraits + ?Sized + 'static,
+>(
+    array: &Array<TStorage>,
+    store_out: Arc<TStorageOut>,
+    histogram: Option<ChunkStatisticsHistogram>,
+    chunk_limit: usize,
+) -> Result<ChunkStatistics, ChunkStatisticsError> {
+    macro_rules! calculate {
+        ( $( ( $data_type:ident, $type:ty ) ),* ) => {
+            match array.data_type() {
+                $(DataType::$data_type => calculate_chunk_statistics_t::<_, _, $type>(
+                    array,
+                    store_out,
+                    histogram,
+                    chunk_limit,
+                ),)*
+                data_type => Err(ChunkStatisticsError::UnsupportedDataType(data_type.to_string())),
+            }
+        };
+    }
+    calculate!(
+        (Int8, i8),
+        (Int16, i16),
+        (Int32, i32),
+        (Int64, i64),
+        (UInt8, u8),
+        (UInt16, u16),
+        (UInt32, u32),
+        (UInt64, u64),
+        (Float16, f16),
+        (BFloat16, bf16),
+        (Float32, f32),
+        (Float64, f64)
+    )
+}
+
+#[allow(clippy::eq_op)]
+fn is_not_nan<T: PartialEq>(element: &&T) -> bool {
+    element == element
+}
+
+pub fn calculate_chunk_statistics_t<
+    TStorage: ReadableStorageTraits + ?Sized + 'static,
+    TStorageOut: ReadableWritableStorageTraits + ?Sized + 'static,
+    T: Element + ElementOwned + PartialOrd + Copy + Send + Sync + AsPrimitive<f64>,
+>(
+    array: &Array<TStorage>,
+    store_out: Arc<TStorageOut>,
+    histogram: Option<ChunkStatisticsHistogram>,
+    chunk_limit: usize,
+) -> Result<ChunkStatistics, ChunkStatisticsError> {
+    let chunk_grid_shape = array.chunk_grid_shape().unwrap();
+    let chunks = ArraySubset::new_with_shape(chunk_grid_shape.clone());
+    let n_bins = histogram.as_ref().map_or(0, |histogram| histogram.n_bins);
+
+    let chunk_statistics = |chunk_indices: Vec<u64>| {
+        let elements = array.retrieve_chunk_elements::<T>(&chunk_indices)?;
+        let mut values = elements.iter().filter(is_not_nan);
+        let first = values.next().or(elements.first()).copied();
+        let mut min_max = first.map(|first| (first, first));
+        if let Some((min, max)) = &mut min_max {
+            for &element in values {
+                *min = if element < *min { element } else { *min };
+                *max = if element > *max { element } else { *max };
+            }
+        }
+        let mut hist = vec![0u64; n_bins];
+        if let Some(histogram) = &histogram {
+            for element in elements.iter().filter(is_not_nan) {
+                let norm: f64 = (element.as_() - histogram.min) / (histogram.max - histogram.min);
+                let bin = ((norm * n_bins as f64).max(0.0).floor() as usize).min(n_bins - 1);
+                hist[bin] += 1;
+            }
+        }
+        let index = std::iter::zip(&chunk_indices, &chunk_grid_shape)
+            .fold(0, |index, (i, s)| index * s + i);
+        Ok::<_, ArrayError>((index, min_max, hist))
+    };
+
+    let indices = chunks.indices();
+    let mut statistics = iter_concurrent_limit!(chunk_limit, indices, map, chunk_statistics)
+        .collect::<Result<Vec<_>, _>>()?;
+    statistics.sort_unstable_by_key(|(index, _, _)| *index);
+
+    let fill_value = array.fill_value().clone();
+    let fill_value_t = T::from_array_bytes(
+        array.data_type(),
+        zarrs::array::ArrayBytes::new_flen(fill_value.as_ne_bytes().to_vec()),
+    )?[0];
+    let mut min = Vec::with_capacity(statistics.len());
+    let mut max = Vec::with_capacity(statistics.len());
+    let mut hist = Vec::with_capacity(statistics.len() * n_bins);
+    for (_, min_max, chunk_hist) in statistics {
+        let (chunk_min, chunk_max) = min_max.unwrap_or((fill_value_t, fill_value_t));
+        min.push(chunk_min);
+        max.push(chunk_max);
+        hist.extend(chunk_hist);
+    }
+
+    // Write the sidecar
+    let summary = ChunkStatistics {
+        num_chunks: chunks.num_elements(),
+        chunk_grid_shape: chunk_grid_shape.clone(),
+        histogram,
+    };
+    let mut attributes = serde_json::Map::new();
+    attributes.insert(
+        CHUNK_STATISTICS_ATTRIBUTE.to_string(),
+        serde_json::to_value(&summary).unwrap(),
+    );
+    GroupBuilder::new()
+        .attributes(attributes)
+        .build(store_out.clone(), "/")?
+        .store_metadata()?;
+
+    let statistics_chunk_shape = chunk_grid_shape
+        .iter()
+        .map(|s| (*s).max(1))
+        .collect::<Vec<_>>();
+    for (name, elements) in [("min", min), ("max", max)] {
+        let array_out = ArrayBuilder::new(
+            chunk_grid_shape.clone(),
+            array.data_type().clone(),
+            statistics_chunk_shape.clone().try_into().unwrap(),
+            fill_value.clone(),
+        )
+        .dimension_names(array.dimension_names().clone())
+        .build(store_out.clone(), &format!("/{name}"))?;
+        array_out.store_metadata()?;
+        array_out.store_array_subset_elements::<T>(&array_out.subset_all(), &elements)?;
+    }
+    if let Some(histogram) = &summary.histogram {
+        let histogram_shape = [chunk_grid_shape.as_slice(), &[histogram.n_bins as u64]].concat();
+        let histogram_chunk_shape = histogram_shape
+            .iter()
+            .map(|s| (*s).max(1))
+            .collect::<Vec<_>>();
+        let array_out = ArrayBuilder::new(
+            histogram_shape,
+            DataType::UInt64,
+            histogram_chunk_shape.try_into().unwrap(),
+            FillValue::from(0u64),
+        )
+        .build(store_out.clone(), "/histogram")?;
+        array_out.store_metadata()?;
+        array_out.store_array_subset_elements::<u64>(&array_out.subset_all(), &hist)?;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use zarrs::{filesystem::FilesystemStore, storage::store::MemoryStore};
+
+    use super::*;
+
+    #[test]
+    fn chunk_statistics() -> Result<(), Box<dyn Error>> {
+        let store = Arc::new(MemoryStore::new());
+        let array = ArrayBuilder::new(
+            vec![6],
+            DataType::Float32,
+            vec![2].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(store, "/")?;
+        array.store_array_subset_elements::<f32>(
+            &ArraySubset::new_with_shape(vec![4]),
+            &[1.0, 3.0, f32::NAN, -2.0],
+        )?;
+
+        let path = tempfile::TempDir::new()?;
+        let store_out = Arc::new(FilesystemStore::new(path.path())?);
+        let summary = calculate_chunk_statistics(
+            &array,
+            store_out.clone(),
+            Some(ChunkStatisticsHistogram {
+                n_bins: 2,
+                min: -4.0,
+                max: 4.0,
+            }),
+            1,
+        )?;
+        assert_eq!(summary.chunk_grid_shape, [3]);
+
+        let retrieve = |name: &str| -> Result<Vec<f32>, Box<dyn Error>> {
+            let array = Array::open(store_out.clone(), &format!("/{name}"))?;
+            Ok(array.retrieve_array_subset_elements::<f32>(&array.subset_all())?)
+        };
+        assert_eq!(retrieve("min")?, [1.0, -2.0, 0.0]);
+        assert_eq!(retrieve("max")?, [3.0, -2.0, 0.0]);
+        let histogram = Array::open(store_out.clone(), "/histogram")?;
+        assert_eq!(
+            histogram.retrieve_array_subset_elements::<u64>(&histogram.subset_all())?,
+            [0, 2, 1, 0, 0, 2]
+        );
+        Ok(())
+    }
+}
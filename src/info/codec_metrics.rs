@@ -0,0 +1,180 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use zarrs::{
+    array::{
+        codec::{CodecOptions, CodecTraits},
+        Array, ArrayError,
+    },
+    array_subset::ArraySubset,
+    storage::ReadableStorageTraits,
+};
+
+/// Decode metrics of a codec in a codec chain, accumulated over sampled chunks.
+#[derive(Serialize, Debug, Clone)]
+pub struct CodecMetrics {
+    /// The codec name.
+    pub codec: String,
+    /// The total decode time (in seconds).
+    pub decode_time: f64,
+    /// The fraction of the total decode time spent in this codec.
+    pub decode_time_fraction: f64,
+    /// The total size of the codec input when decoding (in bytes).
+    pub encoded_size: usize,
+    /// The total size of the codec output when decoding (in bytes).
+    pub decoded_size: usize,
+}
+
+/// Codec-level decode metrics of sampled chunks in an array.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChunkCodecMetrics {
+    /// The number of sampled chunks that were decoded.
+    ///
+    /// Sampled chunks that do not exist in the store are skipped.
+    pub num_chunks: usize,
+    /// The total time retrieving encoded chunks from the store (in seconds).
+    pub retrieve_time: f64,
+    /// The metrics of each codec, in decode order.
+    pub codecs: Vec<CodecMetrics>,
+    /// The codec with the largest decode time.
+    pub dominant_codec: Option<String>,
+}
+
+fn codec_name<T: CodecTraits + ?Sized>(codec: &T) -> String {
+    codec.create_metadata().map_or_else(
+        || "unknown".to_string(),
+        |metadata| metadata.name().to_string(),
+    )
+}
+
+/// Sample up to `num_chunks` chunks evenly spaced throughout `array` and measure the time and sizes of each codec stage when decoding.
+///
+/// Chunks are decoded one at a time through each codec in the codec chain, so that the decode time of each codec can be measured.
+/// Codecs may still use internal concurrency (e.g. sharding).
+///
+/// # Errors
+/// Returns an [`ArrayError`] if a chunk cannot be retrieved or decoded.
+pub fn calculate_codec_metrics<TStorage: ReadableStorageTraits + ?Sized + 'static>(
+    array: &Array<TStorage>,
+    num_chunks: usize,
+) -> Result<ChunkCodecMetrics, ArrayError> {
+    let codecs = array.codecs();
+    let array_to_array_codecs = codecs.array_to_array_codecs();
+    let array_to_bytes_codec = codecs.array_to_bytes_codec();
+    let bytes_to_bytes_codecs = codecs.bytes_to_bytes_codecs();
+    let options = CodecOptions::default();
+
+    // Metrics in decode order: bytes to bytes (reversed), array to bytes, array to array (reversed)
+    let mut metrics = bytes_to_bytes_codecs
+        .iter()
+        .rev()
+        .map(|codec| codec_name(codec.as_ref()))
+        .chain(std::iter::once(codec_name(array_to_bytes_codec.as_ref())))
+        .chain(
+            array_to_array_codecs
+                .iter()
+                .rev()
+                .map(|codec| codec_name(codec.as_ref())),
+        )
+        .map(|codec| (codec, Duration::ZERO, 0, 0))
+        .collect::<Vec<_>>();
+
+    let chunks = ArraySubset::new_with_shape(array.chunk_grid_shape().unwrap());
+    let num_chunks_total = chunks.num_elements_usize();
+    let step = num_chunks_total.div_ceil(num_chunks.max(1)).max(1);
+
+    let mut num_chunks_decoded = 0;
+    let mut retrieve_time = Duration::ZERO;
+    let indices = chunks.indices();
+    for chunk_indices in indices.iter().step_by(step).take(num_chunks) {
+        let start = Instant::now();
+        let Some(encoded) = array.retrieve_encoded_chunk(&chunk_indices)? else {
+            continue;
+        };
+        retrieve_time += start.elapsed();
+        num_chunks_decoded += 1;
+
+        // The decoded representation of each codec
+        let mut array_representations = vec![array.chunk_array_representation(&chunk_indices)?];
+        for codec in array_to_array_codecs {
+            array_representations
+                .push(codec.compute_encoded_size(array_representations.last().unwrap())?);
+        }
+        let mut bytes_representations =
+            vec![array_to_bytes_codec.compute_encoded_size(array_representations.last().unwrap())?];
+        for codec in bytes_to_bytes_codecs {
+            bytes_representations
+                .push(codec.compute_encoded_size(bytes_representations.last().unwrap()));
+        }
+
+        let mut metrics_iter = metrics.iter_mut();
+        let mut record = |encoded_size: usize, decoded_size: usize, duration: Duration| {
+            let metrics = metrics_iter.next().unwrap();
+            metrics.1 += duration;
+            metrics.2 += encoded_size;
+            metrics.3 += decoded_size;
+        };
+
+        let mut bytes: zarrs::array::RawBytes = encoded.into();
+        for (codec, decoded_representation) in bytes_to_bytes_codecs
+            .iter()
+            .zip(&bytes_representations)
+            .rev()
+        {
+            let start = Instant::now();
+            let encoded_size = bytes.len();
+            bytes = codec.decode(bytes, decoded_representation, &options)?;
+            record(encoded_size, bytes.len(), start.elapsed());
+        }
+
+        let start = Instant::now();
+        let encoded_size = bytes.len();
+        let mut array_bytes =
+            array_to_bytes_codec.decode(bytes, array_representations.last().unwrap(), &options)?;
+        record(encoded_size, array_bytes.size(), start.elapsed());
+
+        for (codec, decoded_representation) in array_to_array_codecs
+            .iter()
+            .zip(&array_representations)
+            .rev()
+        {
+            let start = Instant::now();
+            let encoded_size = array_bytes.size();
+            array_bytes = codec.decode(array_bytes, decoded_representation, &options)?;
+            record(encoded_size, array_bytes.size(), start.elapsed());
+        }
+    }
+
+    let decode_time_total = metrics
+        .iter()
+        .map(|(_, duration, _, _)| duration.as_secs_f64())
+        .sum::<f64>();
+    let dominant_codec = metrics
+        .iter()
+        .max_by_key(|(_, duration, _, _)| *duration)
+        .filter(|_| num_chunks_decoded > 0)
+        .map(|(codec, _, _, _)| codec.clone());
+    let codecs = metrics
+        .into_iter()
+        .map(
+            |(codec, duration, encoded_size, decoded_size)| CodecMetrics {
+                codec,
+                decode_time: duration.as_secs_f64(),
+                decode_time_fraction: if decode_time_total > 0.0 {
+                    duration.as_secs_f64() / decode_time_total
+                } else {
+                    0.0
+                },
+                encoded_size,
+                decoded_size,
+            },
+        )
+        .collect();
+
+    Ok(ChunkCodecMetrics {
+        num_chunks: num_chunks_decoded,
+        retrieve_time: retrieve_time.as_secs_f64(),
+        codecs,
+        dominant_codec,
+    })
+}
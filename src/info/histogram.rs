@@ -3,32 +3,58 @@ use num_traits::AsPrimitive;
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 use zarrs::{
     array::{Array, ArrayError, DataType, ElementOwned},
-    array_subset::ArraySubset,
     storage::ReadableStorageTraits,
 };
 
+/// Calculate a histogram with `n_bins` bins between `min` and `max` of the elements in the chunks at `chunk_indices`.
+///
+/// Elements outside of the bins are counted in the first or last bin.
+/// Pass every chunk for the exact histogram, or a sample of chunks (e.g. from [`sample_chunk_indices`](crate::sample_chunk_indices)) for an approximate histogram.
 pub fn calculate_histogram<TStorage: ReadableStorageTraits + 'static>(
     array: &Array<TStorage>,
     n_bins: usize,
     min: f64,
     max: f64,
+    chunk_indices: &[Vec<u64>],
     chunk_limit: usize,
 ) -> Result<(Vec<f64>, Vec<u64>), ArrayError> {
     match array.data_type() {
-        DataType::Int8 => calculate_histogram_t::<_, i8>(array, n_bins, min, max, chunk_limit),
-        DataType::Int16 => calculate_histogram_t::<_, i16>(array, n_bins, min, max, chunk_limit),
-        DataType::Int32 => calculate_histogram_t::<_, i32>(array, n_bins, min, max, chunk_limit),
-        DataType::Int64 => calculate_histogram_t::<_, i64>(array, n_bins, min, max, chunk_limit),
-        DataType::UInt8 => calculate_histogram_t::<_, u8>(array, n_bins, min, max, chunk_limit),
-        DataType::UInt16 => calculate_histogram_t::<_, u16>(array, n_bins, min, max, chunk_limit),
-        DataType::UInt32 => calculate_histogram_t::<_, u32>(array, n_bins, min, max, chunk_limit),
-        DataType::UInt64 => calculate_histogram_t::<_, u64>(array, n_bins, min, max, chunk_limit),
-        DataType::Float16 => calculate_histogram_t::<_, f16>(array, n_bins, min, max, chunk_limit),
+        DataType::Int8 => {
+            calculate_histogram_t::<_, i8>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::Int16 => {
+            calculate_histogram_t::<_, i16>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::Int32 => {
+            calculate_histogram_t::<_, i32>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::Int64 => {
+            calculate_histogram_t::<_, i64>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::UInt8 => {
+            calculate_histogram_t::<_, u8>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::UInt16 => {
+            calculate_histogram_t::<_, u16>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::UInt32 => {
+            calculate_histogram_t::<_, u32>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::UInt64 => {
+            calculate_histogram_t::<_, u64>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::Float16 => {
+            calculate_histogram_t::<_, f16>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
         DataType::BFloat16 => {
-            calculate_histogram_t::<_, bf16>(array, n_bins, min, max, chunk_limit)
+            calculate_histogram_t::<_, bf16>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::Float32 => {
+            calculate_histogram_t::<_, f32>(array, n_bins, min, max, chunk_indices, chunk_limit)
+        }
+        DataType::Float64 => {
+            calculate_histogram_t::<_, f64>(array, n_bins, min, max, chunk_indices, chunk_limit)
         }
-        DataType::Float32 => calculate_histogram_t::<_, f32>(array, n_bins, min, max, chunk_limit),
-        DataType::Float64 => calculate_histogram_t::<_, f64>(array, n_bins, min, max, chunk_limit),
         DataType::Bool | DataType::Complex64 | DataType::Complex128 | DataType::RawBits(_) => {
             unimplemented!("Data type not supported")
         }
@@ -44,14 +70,14 @@ pub fn calculate_histogram_t<
     n_bins: usize,
     min: f64,
     max: f64,
+    chunk_indices: &[Vec<u64>],
     chunk_limit: usize,
 ) -> Result<(Vec<f64>, Vec<u64>), ArrayError> {
-    let chunks = ArraySubset::new_with_shape(array.chunk_grid_shape().unwrap());
-
     let chunk_incr_histogram = |histogram: Result<Vec<u64>, ArrayError>,
-                                chunk_indices: Vec<u64>| {
+                                chunk_indices: &Vec<u64>| {
         let mut histogram = histogram?;
-        let elements = array.retrieve_chunk_elements::<T>(&chunk_indices)?;
+        let elements = array
+            .retrieve_array_subset_elements::<T>(&array.chunk_subset_bounded(chunk_indices)?)?;
         for element in elements {
             let norm: f64 = (element.as_() - min) / (max - min);
             let bin = ((norm * n_bins as f64).max(0.0).floor() as usize).min(n_bins - 1);
@@ -67,11 +93,10 @@ pub fn calculate_histogram_t<
         })
         .collect();
 
-    let indices = chunks.indices();
-    let hist = indices
+    let hist = chunk_indices
         .into_par_iter()
         .fold_chunks(
-            indices.len().div_ceil(chunk_limit).max(1),
+            chunk_indices.len().div_ceil(chunk_limit).max(1),
             || Ok(vec![0; n_bins]),
             chunk_incr_histogram,
         )
@@ -82,7 +107,7 @@ pub fn calculate_histogram_t<
                 .map(|(a, b)| a + b)
                 .collect::<Vec<_>>())
         })
-        .expect("a value since the chunk is not empty")?;
+        .expect("a value since the chunk indices are not empty")?;
 
     Ok((bin_edges, hist))
 }
@@ -0,0 +1,774 @@
+use serde::Serialize;
+use serde_json::Value;
+use zarrs::{
+    array::{codec::Codec, ChunkGrid, ChunkKeyEncoding, DataType, FillValueMetadataV3},
+    metadata::{
+        v2::{ArrayMetadataV2, GroupMetadataV2},
+        v2_to_v3::{array_metadata_v2_to_v3, group_metadata_v2_to_v3},
+        v3::{array::data_type::DataTypeMetadataV3, MetadataV3},
+    },
+    storage::{ReadableStorageTraits, StorageError, StoreKey},
+};
+
+/// The severity of a [`LintFinding`].
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    /// A suggestion that does not affect interoperability.
+    Info,
+    /// An interoperability hazard. The node may not be readable by some implementations.
+    Warning,
+    /// A spec violation or unsupported extension. The node is unlikely to be readable.
+    Error,
+}
+
+/// A metadata lint finding.
+#[derive(Serialize, Debug, Clone)]
+pub struct LintFinding {
+    /// The severity.
+    pub severity: LintSeverity,
+    /// A stable identifier of the check (e.g. `unknown-extension`).
+    pub code: &'static str,
+    /// The path of the node.
+    pub node: String,
+    /// A [JSON pointer](https://datatracker.ietf.org/doc/html/rfc6901) to the offending metadata (e.g. `/codecs/1`).
+    pub pointer: String,
+    /// A human readable description.
+    pub message: String,
+}
+
+/// Codecs registered with the Zarr V3 specification.
+const STANDARD_CODECS: &[&str] = &[
+    "blosc",
+    "bytes",
+    "crc32c",
+    "gzip",
+    "sharding_indexed",
+    "transpose",
+    "vlen-bytes",
+    "vlen-utf8",
+    "zstd",
+];
+
+/// Codecs supported by `zarrs` under a name that other implementations recognise by an alias.
+const CODEC_ALIASES: &[(&str, &str)] = &[
+    ("adler32", "numcodecs.adler32"),
+    ("bitround", "numcodecs.bitround"),
+    ("bz2", "numcodecs.bz2"),
+    ("delta", "numcodecs.delta"),
+    ("fixedscaleoffset", "numcodecs.fixedscaleoffset"),
+    ("fletcher32", "numcodecs.fletcher32"),
+    ("packbits", "numcodecs.packbits"),
+    ("pcodec", "numcodecs.pcodec"),
+    ("shuffle", "numcodecs.shuffle"),
+    ("zfp", "numcodecs.zfpy"),
+    ("zlib", "numcodecs.zlib"),
+];
+
+const ARRAY_FIELDS: &[&str] = &[
+    "zarr_format",
+    "node_type",
+    "shape",
+    "data_type",
+    "chunk_grid",
+    "chunk_key_encoding",
+    "fill_value",
+    "codecs",
+    "attributes",
+    "dimension_names",
+    "storage_transformers",
+];
+
+const GROUP_FIELDS: &[&str] = &["zarr_format", "node_type", "attributes"];
+
+struct Linter {
+    node: String,
+    findings: Vec<LintFinding>,
+}
+
+impl Linter {
+    fn push(
+        &mut self,
+        severity: LintSeverity,
+        code: &'static str,
+        pointer: impl Into<String>,
+        message: impl Into<String>,
+    ) {
+        self.findings.push(LintFinding {
+            severity,
+            code,
+            node: self.node.clone(),
+            pointer: pointer.into(),
+            message: message.into(),
+        });
+    }
+}
+
+/// Returns the name of extension point metadata, which is either a string or an object with a `name`.
+fn extension_name(metadata: &Value) -> Option<&str> {
+    match metadata {
+        Value::String(name) => Some(name),
+        Value::Object(object) => object.get("name").and_then(Value::as_str),
+        _ => None,
+    }
+}
+
+/// Returns `must_understand` of extension point (or additional field) metadata, which defaults to true.
+fn must_understand(metadata: &Value) -> bool {
+    metadata
+        .get("must_understand")
+        .and_then(Value::as_bool)
+        .unwrap_or(true)
+}
+
+/// Parse extension point metadata, ignoring `must_understand`.
+fn extension_metadata(metadata: &Value) -> Result<MetadataV3, serde_json::Error> {
+    let mut metadata = metadata.clone();
+    if let Value::Object(object) = &mut metadata {
+        object.remove("must_understand");
+    }
+    serde_json::from_value(metadata)
+}
+
+/// Returns the key of `name` in the node at `path`.
+fn node_key(path: &str, name: &str) -> Result<StoreKey, StorageError> {
+    let prefix = path.trim_matches('/');
+    let key = if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}/{name}")
+    };
+    StoreKey::new(key).map_err(|err| StorageError::from(err.to_string()))
+}
+
+fn get_json<TStorage: ?Sized + ReadableStorageTraits>(
+    storage: &TStorage,
+    path: &str,
+    name: &str,
+) -> Result<Option<Result<Value, serde_json::Error>>, StorageError> {
+    Ok(storage
+        .get(&node_key(path, name)?)?
+        .map(|bytes| serde_json::from_slice(&bytes)))
+}
+
+/// Retrieve the metadata of the node at `path` as Zarr V3 metadata, converting Zarr V2 metadata.
+///
+/// Returns [`None`] and records a finding if the metadata is missing or invalid.
+fn node_metadata_v3<TStorage: ?Sized + ReadableStorageTraits>(
+    storage: &TStorage,
+    path: &str,
+    linter: &mut Linter,
+) -> Result<Option<Value>, StorageError> {
+    if let Some(metadata) = get_json(storage, path, "zarr.json")? {
+        return Ok(match metadata {
+            Ok(metadata) => Some(metadata),
+            Err(err) => {
+                linter.push(
+                    LintSeverity::Error,
+                    "invalid-json",
+                    "",
+                    format!("zarr.json is not valid JSON: {err}"),
+                );
+                None
+            }
+        });
+    }
+
+    let attributes = match get_json(storage, path, ".zattrs")? {
+        Some(Ok(Value::Object(attributes))) => attributes,
+        Some(_) => {
+            linter.push(
+                LintSeverity::Error,
+                "invalid-json",
+                "/attributes",
+                ".zattrs is not a valid JSON object",
+            );
+            serde_json::Map::new()
+        }
+        None => serde_json::Map::new(),
+    };
+    let metadata = if let Some(metadata) = get_json(storage, path, ".zarray")? {
+        metadata
+            .and_then(serde_json::from_value::<ArrayMetadataV2>)
+            .map_err(|err| err.to_string())
+            .and_then(|metadata| array_metadata_v2_to_v3(&metadata).map_err(|err| err.to_string()))
+            .and_then(|metadata| serde_json::to_value(metadata).map_err(|err| err.to_string()))
+    } else if let Some(metadata) = get_json(storage, path, ".zgroup")? {
+        metadata
+            .and_then(serde_json::from_value::<GroupMetadataV2>)
+            .map(|metadata| group_metadata_v2_to_v3(&metadata))
+            .and_then(serde_json::to_value)
+            .map_err(|err| err.to_string())
+    } else {
+        linter.push(
+            LintSeverity::Error,
+            "missing-metadata",
+            "",
+            "no Zarr V3 (zarr.json) or V2 (.zarray/.zgroup) metadata",
+        );
+        return Ok(None);
+    };
+    match metadata {
+        Ok(mut metadata) => {
+            linter.push(
+                LintSeverity::Info,
+                "zarr-v2",
+                "",
+                "Zarr V2 metadata, interpreted as V3. Consider converting to Zarr V3 (e.g. with zarrs_reencode)",
+            );
+            if let Value::Object(metadata) = &mut metadata {
+                metadata.insert("attributes".to_string(), Value::Object(attributes));
+            }
+            Ok(Some(metadata))
+        }
+        Err(err) => {
+            linter.push(
+                LintSeverity::Error,
+                "invalid-metadata",
+                "",
+                format!("Zarr V2 metadata is invalid or cannot be interpreted as V3: {err}"),
+            );
+            Ok(None)
+        }
+    }
+}
+
+fn lint_additional_fields(
+    metadata: &serde_json::Map<String, Value>,
+    known: &[&str],
+    linter: &mut Linter,
+) {
+    for (name, value) in metadata {
+        if known.contains(&name.as_str()) {
+            continue;
+        }
+        if must_understand(value) {
+            linter.push(
+                LintSeverity::Error,
+                "unknown-extension",
+                format!("/{name}"),
+                format!("unknown field `{name}` must be understood"),
+            );
+        } else {
+            linter.push(
+                LintSeverity::Info,
+                "ignored-extension",
+                format!("/{name}"),
+                format!("unknown field `{name}` is ignored (must_understand is false)"),
+            );
+        }
+    }
+}
+
+/// Check that extension point metadata at `pointer` is supported, returning the parsed metadata if so.
+fn lint_extension<T, E: std::fmt::Display>(
+    metadata: &Value,
+    kind: &str,
+    pointer: &str,
+    create: impl FnOnce(&MetadataV3) -> Result<T, E>,
+    linter: &mut Linter,
+) -> Option<T> {
+    let name = extension_name(metadata).unwrap_or_default().to_string();
+    let result = extension_metadata(metadata)
+        .map_err(|err| err.to_string())
+        .and_then(|metadata| create(&metadata).map_err(|err| err.to_string()));
+    match result {
+        Ok(extension) => Some(extension),
+        Err(err) => {
+            if must_understand(metadata) {
+                linter.push(
+                    LintSeverity::Error,
+                    "unknown-extension",
+                    pointer,
+                    format!("unsupported {kind} `{name}` must be understood: {err}"),
+                );
+            } else {
+                linter.push(
+                    LintSeverity::Warning,
+                    "ignored-extension",
+                    pointer,
+                    format!(
+                        "unsupported {kind} `{name}` is ignored (must_understand is false): {err}"
+                    ),
+                );
+            }
+            None
+        }
+    }
+}
+
+/// Check the names of `codecs` at `pointer`, recursing into the codecs of sharding codecs.
+fn lint_codec_names(codecs: &[Value], pointer: &str, linter: &mut Linter) {
+    for (i, codec) in codecs.iter().enumerate() {
+        let pointer = format!("{pointer}/{i}");
+        let Some(name) = extension_name(codec) else {
+            linter.push(
+                LintSeverity::Error,
+                "invalid-metadata",
+                &pointer,
+                "codec metadata must be a string or an object with a name",
+            );
+            continue;
+        };
+        if let Some((_, alias)) = CODEC_ALIASES.iter().find(|(codec, _)| *codec == name) {
+            linter.push(
+                LintSeverity::Warning,
+                "non-standard-codec",
+                &pointer,
+                format!("codec `{name}` is not registered with the Zarr V3 specification, other implementations may require the alias `{alias}`"),
+            );
+        } else if name.starts_with("numcodecs.") {
+            linter.push(
+                LintSeverity::Info,
+                "non-standard-codec",
+                &pointer,
+                format!("codec `{name}` is not registered with the Zarr V3 specification"),
+            );
+        } else if !STANDARD_CODECS.contains(&name) {
+            linter.push(
+                LintSeverity::Warning,
+                "non-standard-codec",
+                &pointer,
+                format!("codec `{name}` is not registered with the Zarr V3 specification and may not be supported by other implementations"),
+            );
+        }
+        if name == "sharding_indexed" {
+            for field in ["codecs", "index_codecs"] {
+                if let Some(Value::Array(codecs)) =
+                    codec.pointer(&format!("/configuration/{field}"))
+                {
+                    lint_codec_names(codecs, &format!("{pointer}/configuration/{field}"), linter);
+                }
+            }
+        }
+    }
+}
+
+fn lint_array(metadata: &serde_json::Map<String, Value>, linter: &mut Linter) {
+    for field in [
+        "shape",
+        "data_type",
+        "chunk_grid",
+        "chunk_key_encoding",
+        "fill_value",
+        "codecs",
+    ] {
+        if !metadata.contains_key(field) {
+            linter.push(
+                LintSeverity::Error,
+                "missing-field",
+                format!("/{field}"),
+                format!("the required field `{field}` is missing"),
+            );
+        }
+    }
+    lint_additional_fields(metadata, ARRAY_FIELDS, linter);
+
+    let shape: Option<Vec<u64>> = metadata
+        .get("shape")
+        .and_then(|shape| serde_json::from_value(shape.clone()).ok());
+    if shape.is_none() && metadata.contains_key("shape") {
+        linter.push(
+            LintSeverity::Error,
+            "invalid-metadata",
+            "/shape",
+            "the shape must be an array of non-negative integers",
+        );
+    }
+
+    // Data type and fill value
+    let data_type = metadata.get("data_type").and_then(|data_type| {
+        lint_extension(
+            data_type,
+            "data type",
+            "/data_type",
+            |_| {
+                serde_json::from_value::<DataTypeMetadataV3>(data_type.clone())
+                    .map_err(|err| err.to_string())
+                    .and_then(|data_type| {
+                        DataType::from_metadata(&data_type).map_err(|err| err.to_string())
+                    })
+            },
+            linter,
+        )
+    });
+    if let (Some(data_type), Some(fill_value)) = (&data_type, metadata.get("fill_value")) {
+        let fill_value = serde_json::from_value::<FillValueMetadataV3>(fill_value.clone())
+            .map_err(|err| err.to_string())
+            .and_then(|fill_value| {
+                data_type
+                    .fill_value_from_metadata(&fill_value)
+                    .map_err(|err| err.to_string())
+            });
+        if let Err(err) = fill_value {
+            linter.push(
+                LintSeverity::Error,
+                "fill-value-mismatch",
+                "/fill_value",
+                format!("the fill value is incompatible with the data type {data_type}: {err}"),
+            );
+        }
+    }
+
+    // Chunk grid
+    if let Some(chunk_grid) = metadata.get("chunk_grid") {
+        lint_extension(
+            chunk_grid,
+            "chunk grid",
+            "/chunk_grid",
+            ChunkGrid::from_metadata,
+            linter,
+        );
+        if let (Some("regular"), Some(shape)) = (extension_name(chunk_grid), &shape) {
+            let chunk_shape: Option<Vec<u64>> = chunk_grid
+                .pointer("/configuration/chunk_shape")
+                .and_then(|chunk_shape| serde_json::from_value(chunk_shape.clone()).ok());
+            if let Some(chunk_shape) = chunk_shape {
+                if chunk_shape.len() != shape.len() {
+                    linter.push(
+                        LintSeverity::Error,
+                        "dimensionality-mismatch",
+                        "/chunk_grid/configuration/chunk_shape",
+                        format!(
+                            "the chunk shape {chunk_shape:?} does not match the dimensionality of the array shape {shape:?}"
+                        ),
+                    );
+                } else if std::iter::zip(&chunk_shape, shape).any(|(c, s)| *s > 0 && c > s) {
+                    linter.push(
+                        LintSeverity::Warning,
+                        "chunk-exceeds-shape",
+                        "/chunk_grid/configuration/chunk_shape",
+                        format!("the chunk shape {chunk_shape:?} exceeds the array shape {shape:?}, wasting memory and storage"),
+                    );
+                }
+                if let Some(Value::Array(codecs)) = metadata.get("codecs") {
+                    lint_sharding(codecs, &chunk_shape, linter);
+                }
+            }
+        }
+    }
+
+    // Chunk key encoding
+    if let Some(chunk_key_encoding) = metadata.get("chunk_key_encoding") {
+        lint_extension(
+            chunk_key_encoding,
+            "chunk key encoding",
+            "/chunk_key_encoding",
+            ChunkKeyEncoding::from_metadata,
+            linter,
+        );
+    }
+
+    // Codecs
+    match metadata.get("codecs") {
+        Some(Value::Array(codecs)) => {
+            lint_codec_names(codecs, "/codecs", linter);
+            for (i, codec) in codecs.iter().enumerate() {
+                lint_extension(
+                    codec,
+                    "codec",
+                    &format!("/codecs/{i}"),
+                    Codec::from_metadata,
+                    linter,
+                );
+            }
+        }
+        Some(_) => linter.push(
+            LintSeverity::Error,
+            "invalid-metadata",
+            "/codecs",
+            "the codecs must be an array",
+        ),
+        None => {}
+    }
+
+    // Storage transformers
+    if let Some(Value::Array(storage_transformers)) = metadata.get("storage_transformers") {
+        for (i, storage_transformer) in storage_transformers.iter().enumerate() {
+            let name = extension_name(storage_transformer).unwrap_or_default();
+            linter.push(
+                if must_understand(storage_transformer) {
+                    LintSeverity::Error
+                } else {
+                    LintSeverity::Warning
+                },
+                "storage-transformer",
+                format!("/storage_transformers/{i}"),
+                format!("storage transformer `{name}` is not supported by most implementations"),
+            );
+        }
+    }
+
+    // Dimension names
+    match metadata.get("dimension_names") {
+        Some(Value::Array(dimension_names)) => {
+            if shape
+                .as_ref()
+                .is_some_and(|shape| shape.len() != dimension_names.len())
+            {
+                linter.push(
+                    LintSeverity::Error,
+                    "dimensionality-mismatch",
+                    "/dimension_names",
+                    "the number of dimension names does not match the dimensionality of the array",
+                );
+            } else if dimension_names.iter().all(Value::is_null) {
+                linter.push(
+                    LintSeverity::Info,
+                    "missing-dimension-names",
+                    "/dimension_names",
+                    "all dimension names are null",
+                );
+            }
+        }
+        Some(Value::Null) | None => linter.push(
+            LintSeverity::Info,
+            "missing-dimension-names",
+            "/dimension_names",
+            "the array has no dimension names",
+        ),
+        Some(_) => linter.push(
+            LintSeverity::Error,
+            "invalid-metadata",
+            "/dimension_names",
+            "the dimension names must be an array",
+        ),
+    }
+}
+
+/// Check that the inner chunk shape of a sharding codec evenly divides the shard shape.
+fn lint_sharding(codecs: &[Value], shard_shape: &[u64], linter: &mut Linter) {
+    for (i, codec) in codecs.iter().enumerate() {
+        if extension_name(codec) != Some("sharding_indexed") {
+            continue;
+        }
+        let chunk_shape: Option<Vec<u64>> = codec
+            .pointer("/configuration/chunk_shape")
+            .and_then(|chunk_shape| serde_json::from_value(chunk_shape.clone()).ok());
+        if let Some(chunk_shape) = chunk_shape {
+            if chunk_shape.len() != shard_shape.len()
+                || std::iter::zip(shard_shape, &chunk_shape).any(|(s, c)| *c == 0 || s % c != 0)
+            {
+                linter.push(
+                    LintSeverity::Error,
+                    "invalid-sharding",
+                    format!("/codecs/{i}/configuration/chunk_shape"),
+                    format!("the inner chunk shape {chunk_shape:?} does not evenly divide the shard shape {shard_shape:?}"),
+                );
+            }
+        }
+    }
+}
+
+/// Returns the OME-Zarr multiscales of group `attributes` (version 0.5 or earlier).
+fn ome_multiscales(attributes: &Value) -> Option<(&str, &Vec<Value>)> {
+    if let Some(Value::Array(multiscales)) = attributes.pointer("/ome/multiscales") {
+        Some(("/attributes/ome/multiscales", multiscales))
+    } else if let Some(Value::Array(multiscales)) = attributes.get("multiscales") {
+        Some(("/attributes/multiscales", multiscales))
+    } else {
+        None
+    }
+}
+
+fn lint_ome<TStorage: ?Sized + ReadableStorageTraits>(
+    storage: &TStorage,
+    path: &str,
+    metadata: &serde_json::Map<String, Value>,
+    linter: &mut Linter,
+    findings: &mut Vec<LintFinding>,
+) -> Result<(), StorageError> {
+    let Some(attributes) = metadata.get("attributes") else {
+        return Ok(());
+    };
+    let Some((pointer, multiscales)) = ome_multiscales(attributes) else {
+        return Ok(());
+    };
+    for (i, multiscale) in multiscales.iter().enumerate() {
+        let axes: Option<Vec<&str>> =
+            multiscale
+                .get("axes")
+                .and_then(Value::as_array)
+                .map(|axes| {
+                    axes.iter()
+                        .map(|axis| axis.get("name").and_then(Value::as_str).unwrap_or_default())
+                        .collect()
+                });
+        let datasets = multiscale
+            .get("datasets")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for (j, dataset) in datasets.iter().enumerate() {
+            let dataset_pointer = format!("{pointer}/{i}/datasets/{j}");
+            let Some(dataset_path) = dataset.get("path").and_then(Value::as_str) else {
+                linter.push(
+                    LintSeverity::Error,
+                    "ome-invalid-dataset",
+                    &dataset_pointer,
+                    "the dataset has no path",
+                );
+                continue;
+            };
+            let array_path = format!("{}/{dataset_path}", path.trim_end_matches('/'));
+            let mut array_findings = lint(storage, &array_path)?;
+            if array_findings
+                .iter()
+                .any(|finding| finding.code == "missing-metadata")
+            {
+                linter.push(
+                    LintSeverity::Error,
+                    "ome-missing-dataset",
+                    &dataset_pointer,
+                    format!("the dataset `{dataset_path}` does not exist"),
+                );
+                continue;
+            }
+            let dimension_names = node_metadata_v3(
+                storage,
+                &array_path,
+                &mut Linter {
+                    node: array_path.clone(),
+                    findings: vec![],
+                },
+            )?
+            .and_then(|metadata| metadata.get("dimension_names").cloned());
+            let dimension_names: Option<Vec<Option<String>>> = dimension_names
+                .and_then(|dimension_names| serde_json::from_value(dimension_names).ok());
+            if let Some(axes) = &axes {
+                match dimension_names {
+                    Some(dimension_names) if dimension_names.len() != axes.len() => {
+                        linter.push(
+                            LintSeverity::Error,
+                            "ome-axes-mismatch",
+                            &dataset_pointer,
+                            format!("the dataset `{dataset_path}` has {} dimensions, but there are {} axes", dimension_names.len(), axes.len()),
+                        );
+                    }
+                    Some(dimension_names)
+                        if dimension_names.iter().any(Option::is_some)
+                            && std::iter::zip(&dimension_names, axes)
+                                .any(|(name, axis)| name.as_deref() != Some(axis)) =>
+                    {
+                        linter.push(
+                            LintSeverity::Warning,
+                            "ome-dimension-names-mismatch",
+                            &dataset_pointer,
+                            format!("the dimension names {dimension_names:?} of dataset `{dataset_path}` do not match the axes {axes:?}"),
+                        );
+                    }
+                    Some(dimension_names) if dimension_names.iter().any(Option::is_some) => {}
+                    _ => {
+                        linter.push(
+                            LintSeverity::Warning,
+                            "ome-missing-dimension-names",
+                            &dataset_pointer,
+                            format!("the dataset `{dataset_path}` has no dimension names, OME-Zarr 0.5 requires dimension names matching the axes {axes:?}"),
+                        );
+                    }
+                }
+            }
+            findings.append(&mut array_findings);
+        }
+    }
+    Ok(())
+}
+
+/// Check the metadata of the array or group at `path` for spec violations and interoperability hazards.
+///
+/// Zarr V2 metadata is interpreted as V3.
+/// The datasets of OME-Zarr multiscales groups are also checked.
+/// Findings are ordered by node, with the most severe findings of each node first.
+///
+/// # Errors
+/// Returns a [`StorageError`] if the metadata cannot be retrieved from the store.
+pub fn lint<TStorage: ?Sized + ReadableStorageTraits>(
+    storage: &TStorage,
+    path: &str,
+) -> Result<Vec<LintFinding>, StorageError> {
+    let mut linter = Linter {
+        node: path.to_string(),
+        findings: vec![],
+    };
+    let mut children = vec![];
+    if let Some(metadata) = node_metadata_v3(storage, path, &mut linter)? {
+        if let Value::Object(metadata) = &metadata {
+            if metadata.get("zarr_format") != Some(&Value::from(3)) {
+                linter.push(
+                    LintSeverity::Error,
+                    "invalid-metadata",
+                    "/zarr_format",
+                    "the zarr format must be 3",
+                );
+            }
+            match metadata.get("node_type").and_then(Value::as_str) {
+                Some("array") => lint_array(metadata, &mut linter),
+                Some("group") => {
+                    lint_additional_fields(metadata, GROUP_FIELDS, &mut linter);
+                    lint_ome(storage, path, metadata, &mut linter, &mut children)?;
+                }
+                _ => linter.push(
+                    LintSeverity::Error,
+                    "invalid-metadata",
+                    "/node_type",
+                    "the node type must be array or group",
+                ),
+            }
+        } else {
+            linter.push(
+                LintSeverity::Error,
+                "invalid-metadata",
+                "",
+                "the metadata must be a JSON object",
+            );
+        }
+    }
+    let mut findings = linter.findings;
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    findings.append(&mut children);
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::{filesystem::FilesystemStore, storage::WritableStorageTraits};
+
+    use super::*;
+
+    #[test]
+    fn lint_array_metadata() -> Result<(), Box<dyn std::error::Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = FilesystemStore::new(path.path())?;
+        let metadata = serde_json::json!({
+            "zarr_format": 3,
+            "node_type": "array",
+            "shape": [10, 10],
+            "data_type": "uint8",
+            "chunk_grid": { "name": "regular", "configuration": { "chunk_shape": [20, 5] } },
+            "chunk_key_encoding": { "name": "default" },
+            "fill_value": -1,
+            "codecs": [{ "name": "bytes" }, { "name": "unknown_codec" }],
+            "unknown_field": { "must_understand": false },
+        });
+        store.set(
+            &StoreKey::new("zarr.json")?,
+            serde_json::to_vec(&metadata)?.into(),
+        )?;
+
+        let findings = lint(&store, "/")?;
+        let codes = findings
+            .iter()
+            .map(|finding| (finding.severity, finding.code))
+            .collect::<Vec<_>>();
+        assert!(codes.contains(&(LintSeverity::Error, "fill-value-mismatch")));
+        assert!(codes.contains(&(LintSeverity::Error, "unknown-extension")));
+        assert!(codes.contains(&(LintSeverity::Warning, "non-standard-codec")));
+        assert!(codes.contains(&(LintSeverity::Warning, "chunk-exceeds-shape")));
+        assert!(codes.contains(&(LintSeverity::Info, "ignored-extension")));
+        assert!(codes.contains(&(LintSeverity::Info, "missing-dimension-names")));
+        assert_eq!(findings[0].severity, LintSeverity::Error);
+
+        assert_eq!(lint(&store, "/missing")?[0].code, "missing-metadata");
+        Ok(())
+    }
+}
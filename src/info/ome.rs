@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+use zarrs::{
+    array::{Array, ArrayShardedExt},
+    group::Group,
+    storage::{ListableStorageTraits, ReadableStorageTraits},
+};
+
+use crate::{node_prefix, physical_metadata::compose_transforms};
+
+/// A level (dataset) of an OME-Zarr multiscale image.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct OmeLevel {
+    /// The dataset path, relative to the multiscales group.
+    pub path: String,
+    /// The array shape, [`None`] if the dataset cannot be opened.
+    pub shape: Option<Vec<u64>>,
+    /// The chunk shape (the inner chunk shape if sharded), [`None`] if the chunk grid is not regular.
+    pub chunk_shape: Option<Vec<u64>>,
+    /// The shard shape, [`None`] if not sharded.
+    pub shard_shape: Option<Vec<u64>>,
+    /// The scale, composed from the dataset and multiscale coordinate transformations.
+    pub scale: Option<Vec<f64>>,
+    /// The translation, composed from the dataset and multiscale coordinate transformations.
+    pub translation: Option<Vec<f64>>,
+    /// The stored size of the dataset (in bytes), the total size of its keys.
+    pub stored_bytes: u64,
+}
+
+/// A summary of an OME-Zarr multiscale image.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct OmeMultiscale {
+    /// The multiscale name.
+    pub name: Option<String>,
+    /// The axis names.
+    pub axes: Vec<String>,
+    /// The levels, from the highest resolution.
+    pub levels: Vec<OmeLevel>,
+    /// Validation errors (e.g. a dataset does not exist or does not match the axes).
+    pub errors: Vec<String>,
+}
+
+/// Returns the chunk shape and shard shape of `array`.
+fn chunk_and_shard_shape<TStorage: ?Sized>(
+    array: &Array<TStorage>,
+) -> (Option<Vec<u64>>, Option<Vec<u64>>) {
+    if array.chunk_grid().create_metadata().name() != "regular" {
+        return (None, None);
+    }
+    let Ok(chunk_shape) = array.chunk_shape(&vec![0; array.dimensionality()]) else {
+        return (None, None);
+    };
+    let chunk_shape = chunk_shape.to_array_shape();
+    match array.inner_chunk_shape() {
+        Some(inner_chunk_shape) => (Some(inner_chunk_shape.to_array_shape()), Some(chunk_shape)),
+        None => (Some(chunk_shape), None),
+    }
+}
+
+/// Summarise the OME-Zarr multiscales of the group at `path`.
+///
+/// Multiscales metadata is read from the `ome` attribute (0.5) or the group attributes (0.4 and earlier).
+/// Each multiscale is validated, recording an error if a dataset does not exist or its dimensionality, scale, or translation does not match the axes.
+///
+/// # Errors
+/// Returns an error if the group cannot be opened or has no multiscales metadata.
+pub fn summarise_ome_multiscales<
+    TStorage: ?Sized + ReadableStorageTraits + ListableStorageTraits + 'static,
+>(
+    storage: &Arc<TStorage>,
+    path: &str,
+) -> anyhow::Result<Vec<OmeMultiscale>> {
+    let group = Group::open(storage.clone(), path)?;
+    let attributes = group.attributes();
+    let Some(Value::Array(multiscales)) = attributes
+        .get("ome")
+        .and_then(|ome| ome.get("multiscales"))
+        .or_else(|| attributes.get("multiscales"))
+    else {
+        anyhow::bail!("the group at {path} has no OME-Zarr multiscales metadata");
+    };
+
+    let mut summaries = vec![];
+    for multiscale in multiscales {
+        let mut summary = OmeMultiscale {
+            name: multiscale
+                .get("name")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            ..Default::default()
+        };
+        if let Some(axes) = multiscale.get("axes").and_then(Value::as_array) {
+            summary.axes = axes
+                .iter()
+                .map(|axis| match axis {
+                    Value::String(name) => name.clone(), // 0.3
+                    axis => axis
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string(),
+                })
+                .collect();
+        } else {
+            summary
+                .errors
+                .push("the multiscale has no axes".to_string());
+        }
+        let transforms = |value: &Value| {
+            value
+                .get("coordinateTransformations")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default()
+        };
+        let datasets = multiscale
+            .get("datasets")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if datasets.is_empty() {
+            summary
+                .errors
+                .push("the multiscale has no datasets".to_string());
+        }
+        for (i, dataset) in datasets.iter().enumerate() {
+            let Some(dataset_path) = dataset.get("path").and_then(Value::as_str) else {
+                summary.errors.push(format!("dataset {i} has no path"));
+                continue;
+            };
+            let mut level = OmeLevel {
+                path: dataset_path.to_string(),
+                ..Default::default()
+            };
+            compose_transforms(
+                &transforms(dataset),
+                &mut level.scale,
+                &mut level.translation,
+            );
+            compose_transforms(
+                &transforms(multiscale),
+                &mut level.scale,
+                &mut level.translation,
+            );
+
+            let array_path = format!("{}/{dataset_path}", path.trim_end_matches('/'));
+            match Array::open(storage.clone(), &array_path) {
+                Ok(array) => {
+                    level.shape = Some(array.shape().to_vec());
+                    (level.chunk_shape, level.shard_shape) = chunk_and_shard_shape(&array);
+                    level.stored_bytes = storage.size_prefix(&node_prefix(&array_path))?;
+                    if !summary.axes.is_empty() && array.dimensionality() != summary.axes.len() {
+                        summary.errors.push(format!(
+                            "the dataset `{dataset_path}` has {} dimensions, but there are {} axes",
+                            array.dimensionality(),
+                            summary.axes.len()
+                        ));
+                    }
+                }
+                Err(err) => summary.errors.push(format!(
+                    "the dataset `{dataset_path}` cannot be opened: {err}"
+                )),
+            }
+            for transform in transforms(dataset).iter().chain(&transforms(multiscale)) {
+                for name in ["scale", "translation"] {
+                    if let Some(Value::Array(transform)) = transform.get(name) {
+                        if !summary.axes.is_empty() && transform.len() != summary.axes.len() {
+                            summary.errors.push(format!(
+                                "the {name} of dataset `{dataset_path}` has {} elements, but there are {} axes",
+                                transform.len(),
+                                summary.axes.len()
+                            ));
+                        }
+                    }
+                }
+            }
+            summary.levels.push(level);
+        }
+        summaries.push(summary);
+    }
+    Ok(summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::{
+        array::{codec::array_to_bytes::sharding::ShardingCodecBuilder, ArrayBuilder, DataType},
+        filesystem::FilesystemStore,
+        group::GroupBuilder,
+    };
+
+    use super::*;
+
+    #[test]
+    fn ome_multiscales_summary() -> Result<(), Box<dyn std::error::Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = Arc::new(FilesystemStore::new(path.path())?);
+
+        let multiscales = serde_json::json!([{
+            "name": "image",
+            "axes": [{ "name": "y", "type": "space" }, { "name": "x", "type": "space" }],
+            "datasets": [
+                { "path": "0", "coordinateTransformations": [{ "type": "scale", "scale": [1.0, 1.0] }] },
+                { "path": "1", "coordinateTransformations": [{ "type": "scale", "scale": [2.0, 2.0] }] },
+                { "path": "2", "coordinateTransformations": [{ "type": "scale", "scale": [4.0, 4.0, 4.0] }] },
+                { "path": "missing" },
+            ],
+            "coordinateTransformations": [{ "type": "scale", "scale": [0.5, 0.5] }],
+        }]);
+        let mut group = GroupBuilder::new().build(store.clone(), "/")?;
+        group.attributes_mut().insert(
+            "ome".to_string(),
+            serde_json::json!({ "multiscales": multiscales }),
+        );
+        group.store_metadata()?;
+
+        let array = ArrayBuilder::new(
+            vec![8, 8],
+            DataType::UInt8,
+            vec![4, 4].try_into()?,
+            0u8.into(),
+        )
+        .build(store.clone(), "/0")?;
+        array.store_metadata()?;
+        array.store_chunk_elements::<u8>(&[0, 0], &[1; 16])?;
+        let array = ArrayBuilder::new(
+            vec![4, 4],
+            DataType::UInt8,
+            vec![4, 4].try_into()?,
+            0u8.into(),
+        )
+        .array_to_bytes_codec(Arc::new(
+            ShardingCodecBuilder::new(vec![2, 2].try_into()?).build(),
+        ))
+        .build(store.clone(), "/1")?;
+        array.store_metadata()?;
+        let array = ArrayBuilder::new(
+            vec![1, 2, 2],
+            DataType::UInt8,
+            vec![1, 2, 2].try_into()?,
+            0u8.into(),
+        )
+        .build(store.clone(), "/2")?;
+        array.store_metadata()?;
+
+        let summaries = summarise_ome_multiscales(&store, "/")?;
+        assert_eq!(summaries.len(), 1);
+        let summary = &summaries[0];
+        assert_eq!(summary.name.as_deref(), Some("image"));
+        assert_eq!(summary.axes, ["y", "x"]);
+        assert_eq!(summary.levels.len(), 4);
+
+        let level = &summary.levels[0];
+        assert_eq!(level.shape, Some(vec![8, 8]));
+        assert_eq!(level.chunk_shape, Some(vec![4, 4]));
+        assert_eq!(level.shard_shape, None);
+        assert_eq!(level.scale, Some(vec![0.5, 0.5]));
+        assert!(level.stored_bytes > 0);
+
+        let level = &summary.levels[1];
+        assert_eq!(level.chunk_shape, Some(vec![2, 2]));
+        assert_eq!(level.shard_shape, Some(vec![4, 4]));
+        assert_eq!(level.scale, Some(vec![1.0, 1.0]));
+
+        assert_eq!(summary.levels[3].shape, None);
+        assert_eq!(summary.errors.len(), 3);
+        assert!(summary.errors[0].contains("dataset `2` has 3 dimensions"));
+        assert!(summary.errors[1].contains("scale of dataset `2`"));
+        assert!(summary.errors[2].contains("dataset `missing` cannot be opened"));
+
+        assert!(summarise_ome_multiscales(&store, "/0").is_err());
+        Ok(())
+    }
+}
@@ -3,88 +3,119 @@ use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use rayon_iter_concurrent_limit::iter_concurrent_limit;
 use zarrs::{
     array::{Array, ArrayError, DataType, ElementOwned},
-    array_subset::ArraySubset,
     storage::ReadableStorageTraits,
 };
 
+/// Calculate the minimum and maximum of the elements in the chunks at `chunk_indices`.
+///
+/// Pass every chunk for the exact range, or a sample of chunks (e.g. from [`sample_chunk_indices`](crate::sample_chunk_indices)) for an approximate range.
 // TODO: Support Infinity, -Infinity, NaN, etc.
 pub fn calculate_range<TStorage: ReadableStorageTraits + 'static>(
     array: &Array<TStorage>,
+    chunk_indices: &[Vec<u64>],
     chunk_limit: usize,
 ) -> Result<(serde_json::Number, serde_json::Number), ArrayError> {
     match array.data_type() {
         DataType::Int8 => {
-            let (min, max) = calculate_range_t(array, i8::MIN, i8::MAX, chunk_limit)?;
+            let (min, max) =
+                calculate_range_t(array, i8::MIN, i8::MAX, chunk_indices, chunk_limit)?;
             let min = serde_json::Number::from(min);
             let max = serde_json::Number::from(max);
             Ok((min, max))
         }
         DataType::Int16 => {
-            let (min, max) = calculate_range_t(array, i16::MIN, i16::MAX, chunk_limit)?;
+            let (min, max) =
+                calculate_range_t(array, i16::MIN, i16::MAX, chunk_indices, chunk_limit)?;
             let min = serde_json::Number::from(min);
             let max = serde_json::Number::from(max);
             Ok((min, max))
         }
         DataType::Int32 => {
-            let (min, max) = calculate_range_t(array, i32::MIN, i32::MAX, chunk_limit)?;
+            let (min, max) =
+                calculate_range_t(array, i32::MIN, i32::MAX, chunk_indices, chunk_limit)?;
             let min = serde_json::Number::from(min);
             let max = serde_json::Number::from(max);
             Ok((min, max))
         }
         DataType::Int64 => {
-            let (min, max) = calculate_range_t(array, i64::MIN, i64::MAX, chunk_limit)?;
+            let (min, max) =
+                calculate_range_t(array, i64::MIN, i64::MAX, chunk_indices, chunk_limit)?;
             let min = serde_json::Number::from(min);
             let max = serde_json::Number::from(max);
             Ok((min, max))
         }
         DataType::UInt8 => {
-            let (min, max) = calculate_range_t(array, u8::MIN, u8::MAX, chunk_limit)?;
+            let (min, max) =
+                calculate_range_t(array, u8::MIN, u8::MAX, chunk_indices, chunk_limit)?;
             let min = serde_json::Number::from(min);
             let max = serde_json::Number::from(max);
             Ok((min, max))
         }
         DataType::UInt16 => {
-            let (min, max) = calculate_range_t(array, u16::MIN, u16::MAX, chunk_limit)?;
+            let (min, max) =
+                calculate_range_t(array, u16::MIN, u16::MAX, chunk_indices, chunk_limit)?;
             let min = serde_json::Number::from(min);
             let max = serde_json::Number::from(max);
             Ok((min, max))
         }
         DataType::UInt32 => {
-            let (min, max) = calculate_range_t(array, u32::MIN, u32::MAX, chunk_limit)?;
+            let (min, max) =
+                calculate_range_t(array, u32::MIN, u32::MAX, chunk_indices, chunk_limit)?;
             let min = serde_json::Number::from(min);
             let max = serde_json::Number::from(max);
             Ok((min, max))
         }
         DataType::UInt64 => {
-            let (min, max) = calculate_range_t(array, u64::MIN, u64::MAX, chunk_limit)?;
+            let (min, max) =
+                calculate_range_t(array, u64::MIN, u64::MAX, chunk_indices, chunk_limit)?;
             let min = serde_json::Number::from(min);
             let max = serde_json::Number::from(max);
             Ok((min, max))
         }
         DataType::Float16 => {
-            let (min, max) =
-                calculate_range_t(array, f16::NEG_INFINITY, f16::INFINITY, chunk_limit)?;
+            let (min, max) = calculate_range_t(
+                array,
+                f16::NEG_INFINITY,
+                f16::INFINITY,
+                chunk_indices,
+                chunk_limit,
+            )?;
             let min = serde_json::Number::from_f64(min.to_f64()).unwrap();
             let max = serde_json::Number::from_f64(max.to_f64()).unwrap();
             Ok((min, max))
         }
         DataType::BFloat16 => {
-            let (min, max) =
-                calculate_range_t(array, bf16::NEG_INFINITY, bf16::INFINITY, chunk_limit)?;
+            let (min, max) = calculate_range_t(
+                array,
+                bf16::NEG_INFINITY,
+                bf16::INFINITY,
+                chunk_indices,
+                chunk_limit,
+            )?;
             let min = serde_json::Number::from_f64(min.to_f64()).unwrap();
             let max = serde_json::Number::from_f64(max.to_f64()).unwrap();
             Ok((min, max))
         }
         DataType::Float32 => {
-            let (min, max) =
-                calculate_range_t(array, f32::NEG_INFINITY, f32::INFINITY, chunk_limit)?;
+            let (min, max) = calculate_range_t(
+                array,
+                f32::NEG_INFINITY,
+                f32::INFINITY,
+                chunk_indices,
+                chunk_limit,
+            )?;
             let min = serde_json::Number::from_f64(min as f64).unwrap();
             let max = serde_json::Number::from_f64(max as f64).unwrap();
             Ok((min, max))
         }
         DataType::Float64 => {
-            let (min, max) =
-                calculate_range_t(array, f64::NEG_INFINITY, f64::INFINITY, chunk_limit)?;
+            let (min, max) = calculate_range_t(
+                array,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                chunk_indices,
+                chunk_limit,
+            )?;
             let min = serde_json::Number::from_f64(min).unwrap();
             let max = serde_json::Number::from_f64(max).unwrap();
             Ok((min, max))
@@ -96,6 +127,7 @@ pub fn calculate_range<TStorage: ReadableStorageTraits + 'static>(
     }
 }
 
+/// Calculate the minimum and maximum of the elements in the chunks at `chunk_indices`, where `t_min` and `t_max` are the lowest and highest values of `T`.
 pub fn calculate_range_t<
     TStorage: ReadableStorageTraits + 'static,
     T: ElementOwned + PartialOrd + Send + Sync,
@@ -103,14 +135,14 @@ pub fn calculate_range_t<
     array: &Array<TStorage>,
     t_min: T,
     t_max: T,
+    chunk_indices: &[Vec<u64>],
     chunk_limit: usize,
 ) -> Result<(T, T), ArrayError> {
-    let chunks = ArraySubset::new_with_shape(array.chunk_grid_shape().unwrap());
-
-    let chunk_min_max = |chunk_indices: Vec<u64>| {
+    let chunk_min_max = |chunk_indices: &Vec<u64>| {
         // TODO: Codec concurrent limit
-        let elements = array.retrieve_chunk_elements::<T>(&chunk_indices)?;
-        let (mut min, mut max) = (t_min.clone(), t_max.clone());
+        let elements = array
+            .retrieve_array_subset_elements::<T>(&array.chunk_subset_bounded(chunk_indices)?)?;
+        let (mut min, mut max) = (t_max.clone(), t_min.clone());
         for element in &elements {
             min = if element < &min { element.clone() } else { min };
             max = if element > &max { element.clone() } else { max };
@@ -118,17 +150,53 @@ pub fn calculate_range_t<
         Ok::<_, ArrayError>((min, max))
     };
 
-    let indices = chunks.indices();
-    let iter_min_max = iter_concurrent_limit!(chunk_limit, indices, map, chunk_min_max);
+    let iter_min_max = iter_concurrent_limit!(chunk_limit, chunk_indices, map, chunk_min_max);
     let (min, max) = iter_min_max
         .try_reduce_with(|(amin, amax), (bmin, bmax)| {
             Ok({
                 let min = if amin < bmin { amin } else { bmin };
-                let max = if amax < bmax { amax } else { bmax };
+                let max = if amax > bmax { amax } else { bmax };
                 (min, max)
             })
         })
-        .expect("a value since the chunk is not empty")?;
+        .expect("a value since the chunk indices are not empty")?;
 
     Ok((min, max))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, sync::Arc};
+
+    use zarrs::{
+        array::{ArrayBuilder, FillValue},
+        array_subset::ArraySubset,
+        storage::store::MemoryStore,
+    };
+
+    use super::*;
+
+    #[test]
+    fn range() -> Result<(), Box<dyn Error>> {
+        let store = Arc::new(MemoryStore::new());
+        let array = ArrayBuilder::new(
+            vec![6],
+            DataType::Int16,
+            vec![2].try_into()?,
+            FillValue::from(0i16),
+        )
+        .build(store, "/")?;
+        array.store_array_subset_elements::<i16>(
+            &ArraySubset::new_with_shape(vec![6]),
+            &[5, 3, -2, 7, 1, 4],
+        )?;
+
+        let chunk_indices = vec![vec![0], vec![1], vec![2]];
+        let (min, max) = calculate_range(&array, &chunk_indices, 2)?;
+        assert_eq!((min.as_i64(), max.as_i64()), (Some(-2), Some(7)));
+
+        let (min, max) = calculate_range(&array, &[vec![0], vec![2]], 2)?;
+        assert_eq!((min.as_i64(), max.as_i64()), (Some(1), Some(5)));
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Serialize;
+use zarrs::storage::{ListableStorageTraits, ReadableStorageTraits, StorageError, StoreKey};
+
+use crate::node_prefix;
+
+/// The names of Zarr V3 and V2 metadata documents.
+const METADATA_DOCUMENTS: [&str; 5] = ["zarr.json", ".zarray", ".zgroup", ".zattrs", ".zmetadata"];
+
+/// A store key and its size.
+#[derive(Serialize, Debug, Clone)]
+pub struct KeySize {
+    /// The store key.
+    pub key: String,
+    /// The size of the value (in bytes).
+    pub size: u64,
+}
+
+/// The storage usage of an array or group.
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeStorageUsage {
+    /// The node path.
+    pub path: String,
+    /// The number of keys of the node, excluding those of its children.
+    pub keys: usize,
+    /// The size of the keys of the node, excluding those of its children (in bytes).
+    pub bytes: u64,
+    /// The number of keys of the node and its descendants.
+    pub total_keys: usize,
+    /// The size of the keys of the node and its descendants (in bytes).
+    pub total_bytes: u64,
+}
+
+/// List the keys under the node at `node_path` with their sizes, in key order.
+///
+/// # Errors
+/// Returns a [`StorageError`] if the store cannot be listed or a size cannot be retrieved.
+pub fn list_keys<TStorage: ?Sized + ListableStorageTraits + ReadableStorageTraits>(
+    storage: &TStorage,
+    node_path: &str,
+) -> Result<Vec<KeySize>, StorageError> {
+    let keys = storage.list_prefix(&node_prefix(node_path))?;
+    let mut keys = keys
+        .into_par_iter()
+        .map(|key: StoreKey| {
+            let size = storage.size_key(&key)?.unwrap_or_default();
+            Ok(KeySize {
+                key: key.as_str().to_string(),
+                size,
+            })
+        })
+        .collect::<Result<Vec<_>, StorageError>>()?;
+    keys.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(keys)
+}
+
+/// Returns the storage usage of each array and group under the node at `node_path` (similar to `du`), in path order.
+///
+/// Nodes are identified by their metadata documents, and keys belong to the deepest node that contains them.
+/// Keys that are not under any node (e.g. of an implicit group) belong to the node at `node_path`.
+///
+/// # Errors
+/// Returns a [`StorageError`] if the store cannot be listed or a size cannot be retrieved.
+pub fn calculate_storage_usage<TStorage: ?Sized + ListableStorageTraits + ReadableStorageTraits>(
+    storage: &TStorage,
+    node_path: &str,
+) -> Result<Vec<NodeStorageUsage>, StorageError> {
+    let keys = list_keys(storage, node_path)?;
+    let root = node_prefix(node_path);
+
+    // Find the node prefixes, deepest first
+    let mut prefixes = vec![root.as_str().to_string()];
+    for KeySize { key, .. } in &keys {
+        let (parent, name) = key.rsplit_once('/').unwrap_or(("", key.as_str()));
+        if METADATA_DOCUMENTS.contains(&name) {
+            let prefix = if parent.is_empty() {
+                String::new()
+            } else {
+                format!("{parent}/")
+            };
+            if !prefixes.contains(&prefix) {
+                prefixes.push(prefix);
+            }
+        }
+    }
+    prefixes.sort_by_key(|prefix| std::cmp::Reverse(prefix.len()));
+
+    let mut usage = prefixes
+        .iter()
+        .map(|prefix| NodeStorageUsage {
+            path: format!("/{}", prefix.trim_end_matches('/')),
+            keys: 0,
+            bytes: 0,
+            total_keys: 0,
+            total_bytes: 0,
+        })
+        .collect::<Vec<_>>();
+    for KeySize { key, size } in &keys {
+        for (prefix, usage) in std::iter::zip(&prefixes, &mut usage) {
+            if key.starts_with(prefix.as_str()) {
+                usage.total_keys += 1;
+                usage.total_bytes += size;
+            }
+        }
+        // The deepest node that contains the key owns it
+        if let Some((_, usage)) = std::iter::zip(&prefixes, &mut usage)
+            .find(|(prefix, _)| key.starts_with(prefix.as_str()))
+        {
+            usage.keys += 1;
+            usage.bytes += size;
+        }
+    }
+    usage.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::storage::{store::MemoryStore, WritableStorageTraits};
+
+    use super::*;
+
+    #[test]
+    fn storage_usage() -> Result<(), Box<dyn std::error::Error>> {
+        let store = MemoryStore::new();
+        for (key, size) in [
+            ("zarr.json", 10),
+            ("0/zarr.json", 20),
+            ("0/c/0/0", 100),
+            ("0/c/0/1", 50),
+            ("1/zarr.json", 20),
+            ("1/c/0/0", 30),
+            ("OME/METADATA.ome.xml", 5),
+        ] {
+            store.set(&StoreKey::new(key)?, vec![0; size].into())?;
+        }
+
+        let keys = list_keys(&store, "/0")?;
+        assert_eq!(
+            keys.iter().map(|key| key.key.as_str()).collect::<Vec<_>>(),
+            ["0/c/0/0", "0/c/0/1", "0/zarr.json"]
+        );
+
+        let usage = calculate_storage_usage(&store, "/")?;
+        let usage = usage
+            .iter()
+            .map(|usage| {
+                (
+                    usage.path.as_str(),
+                    usage.keys,
+                    usage.bytes,
+                    usage.total_keys,
+                    usage.total_bytes,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            usage,
+            [
+                ("/", 2, 15, 7, 235),
+                ("/0", 3, 170, 3, 170),
+                ("/1", 2, 50, 2, 50),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+//! A read-only local filesystem store backed by [`io_uring`](https://man7.org/linux/man-pages/man7/io_uring.7.html) (Linux only).
+//!
+//! Reads are submitted to a ring owned by a dedicated thread, and completions resolve the futures of [`AsyncReadableStorageTraits`] methods.
+//! Unlike a thread pool backed store, many reads can be in flight without blocking threads, which suits NVMe devices with deep queues.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, Sender, TryRecvError},
+    thread::JoinHandle,
+};
+
+use futures::channel::oneshot;
+use io_uring::{opcode, types, IoUring};
+use zarrs::storage::{
+    byte_range::ByteRange, AsyncBytes, AsyncReadableStorageTraits, MaybeAsyncBytes, StorageError,
+    StoreKey,
+};
+
+/// The default number of submission queue entries.
+pub const IO_URING_STORE_DEFAULT_ENTRIES: u32 = 256;
+
+/// A read request of `buffer.len()` bytes from `offset` in `file`.
+struct ReadRequest {
+    file: File,
+    offset: u64,
+    buffer: Vec<u8>,
+    sender: oneshot::Sender<std::io::Result<Vec<u8>>>,
+}
+
+/// A submitted read request and the number of bytes read so far.
+struct InFlight {
+    request: ReadRequest,
+    filled: usize,
+}
+
+/// A read-only local filesystem store backed by `io_uring`.
+pub struct IoUringStore {
+    base_path: PathBuf,
+    sender: Option<Sender<ReadRequest>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl IoUringStore {
+    /// Create a new store at `base_path` with [`IO_URING_STORE_DEFAULT_ENTRIES`] submission queue entries.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the ring cannot be created (e.g. `io_uring` is unsupported or disabled by the kernel).
+    pub fn new<P: AsRef<Path>>(base_path: P) -> Result<Self, StorageError> {
+        Self::new_with_entries(base_path, IO_URING_STORE_DEFAULT_ENTRIES)
+    }
+
+    /// Create a new store at `base_path` with `entries` submission queue entries.
+    ///
+    /// `entries` limits the number of reads in flight.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the ring cannot be created (e.g. `io_uring` is unsupported or disabled by the kernel).
+    pub fn new_with_entries<P: AsRef<Path>>(
+        base_path: P,
+        entries: u32,
+    ) -> Result<Self, StorageError> {
+        let ring = IoUring::new(entries)?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let worker = std::thread::Builder::new()
+            .name("io_uring".to_string())
+            .spawn(move || run_ring(ring, &receiver))?;
+        Ok(Self {
+            base_path: base_path.as_ref().to_path_buf(),
+            sender: Some(sender),
+            worker: Some(worker),
+        })
+    }
+
+    fn key_to_path(&self, key: &StoreKey) -> PathBuf {
+        self.base_path.join(key.as_str())
+    }
+
+    /// Open the file of `key`, returning [`None`] if it does not exist.
+    fn open(&self, key: &StoreKey) -> Result<Option<File>, StorageError> {
+        match File::open(self.key_to_path(key)) {
+            Ok(file) => Ok(Some(file)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Read `length` bytes from `offset` in `file`.
+    async fn read(&self, file: File, offset: u64, length: u64) -> Result<AsyncBytes, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        let request = ReadRequest {
+            file,
+            offset,
+            buffer: vec![0; usize::try_from(length).unwrap()],
+            sender,
+        };
+        self.sender
+            .as_ref()
+            .expect("the sender is only taken on drop")
+            .send(request)
+            .map_err(|_| StorageError::from("the io_uring thread has stopped".to_string()))?;
+        let bytes = receiver
+            .await
+            .map_err(|_| StorageError::from("the io_uring thread has stopped".to_string()))??;
+        if bytes.len() as u64 == length {
+            Ok(bytes.into())
+        } else {
+            Err(StorageError::from(format!(
+                "unexpected end of file, read {} of {length} bytes",
+                bytes.len()
+            )))
+        }
+    }
+}
+
+impl Drop for IoUringStore {
+    fn drop(&mut self) {
+        // Disconnect the channel, so the thread stops after completing reads in flight
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Submit read requests from `receiver` to `ring` and complete them, until the channel is disconnected and no reads are in flight.
+fn run_ring(mut ring: IoUring, receiver: &Receiver<ReadRequest>) {
+    let capacity = ring.params().sq_entries() as usize;
+    let mut queue = VecDeque::<ReadRequest>::new();
+    let mut in_flight: Vec<Option<InFlight>> = Vec::with_capacity(capacity);
+    let mut free_slots: Vec<usize> = Vec::with_capacity(capacity);
+    let mut num_in_flight = 0;
+    let mut disconnected = false;
+
+    let submit = |ring: &mut IoUring, slot: usize, in_flight: &mut InFlight| {
+        let remaining = &mut in_flight.request.buffer[in_flight.filled..];
+        let entry = opcode::Read::new(
+            types::Fd(in_flight.request.file.as_raw_fd()),
+            remaining.as_mut_ptr(),
+            u32::try_from(remaining.len()).unwrap_or(u32::MAX),
+        )
+        .offset(in_flight.request.offset + in_flight.filled as u64)
+        .build()
+        .user_data(slot as u64);
+        // SAFETY: The buffer and file of the request outlive the read, since the request is not dropped until it completes
+        unsafe { ring.submission().push(&entry) }
+            .expect("the number of reads in flight does not exceed the submission queue size");
+    };
+
+    loop {
+        // Receive requests, blocking if idle
+        if num_in_flight == 0 && queue.is_empty() && !disconnected {
+            match receiver.recv() {
+                Ok(request) => queue.push_back(request),
+                Err(_) => disconnected = true,
+            }
+        }
+        loop {
+            match receiver.try_recv() {
+                Ok(request) => queue.push_back(request),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+            }
+        }
+        if disconnected && num_in_flight == 0 && queue.is_empty() {
+            return;
+        }
+
+        // Submit queued requests
+        while num_in_flight < capacity {
+            let Some(request) = queue.pop_front() else {
+                break;
+            };
+            if request.buffer.is_empty() {
+                let _ = request.sender.send(Ok(request.buffer));
+                continue;
+            }
+            let slot = free_slots.pop().unwrap_or_else(|| {
+                in_flight.push(None);
+                in_flight.len() - 1
+            });
+            let entry = in_flight[slot].insert(InFlight { request, filled: 0 });
+            submit(&mut ring, slot, entry);
+            num_in_flight += 1;
+        }
+        if num_in_flight == 0 {
+            continue;
+        }
+
+        // Wait for and handle completions
+        if let Err(err) = ring.submit_and_wait(1) {
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            panic!("io_uring submission failed: {err}");
+        }
+        let completions = ring
+            .completion()
+            .map(|entry| (entry.user_data() as usize, entry.result()))
+            .collect::<Vec<_>>();
+        for (slot, result) in completions {
+            let entry = in_flight[slot]
+                .as_mut()
+                .expect("completions correspond to reads in flight");
+            let complete = match result {
+                result if result < 0 => Some(Err(std::io::Error::from_raw_os_error(-result))),
+                0 => {
+                    // End of file
+                    entry.request.buffer.truncate(entry.filled);
+                    Some(Ok(()))
+                }
+                result => {
+                    entry.filled += result as usize;
+                    (entry.filled == entry.request.buffer.len()).then_some(Ok(()))
+                }
+            };
+            if let Some(complete) = complete {
+                let InFlight { request, .. } = in_flight[slot].take().unwrap();
+                let _ = request.sender.send(complete.map(|()| request.buffer));
+                free_slots.push(slot);
+                num_in_flight -= 1;
+            } else {
+                // Short read, read the remainder
+                submit(&mut ring, slot, entry);
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncReadableStorageTraits for IoUringStore {
+    async fn get(&self, key: &StoreKey) -> Result<MaybeAsyncBytes, StorageError> {
+        let Some(file) = self.open(key)? else {
+            return Ok(None);
+        };
+        let size = file.metadata()?.len();
+        Ok(Some(self.read(file, 0, size).await?))
+    }
+
+    async fn get_partial_values_key(
+        &self,
+        key: &StoreKey,
+        byte_ranges: &[ByteRange],
+    ) -> Result<Option<Vec<AsyncBytes>>, StorageError> {
+        let Some(file) = self.open(key)? else {
+            return Ok(None);
+        };
+        let size = file.metadata()?.len();
+        let reads = byte_ranges
+            .iter()
+            .map(|byte_range| {
+                let (offset, length) = match byte_range {
+                    ByteRange::FromStart(offset, None) => (*offset, size.saturating_sub(*offset)),
+                    ByteRange::FromStart(offset, Some(length)) => (*offset, *length),
+                    ByteRange::Suffix(length) => (size.saturating_sub(*length), *length),
+                };
+                Ok::<_, StorageError>(self.read(file.try_clone()?, offset, length))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(futures::future::try_join_all(reads).await?))
+    }
+
+    async fn size_key(&self, key: &StoreKey) -> Result<Option<u64>, StorageError> {
+        match std::fs::metadata(self.key_to_path(key)) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn io_uring_store() -> Result<(), Box<dyn std::error::Error>> {
+        let path = tempfile::TempDir::new()?;
+        let data = (0..=255u8).cycle().take(100_000).collect::<Vec<_>>();
+        std::fs::create_dir(path.path().join("c"))?;
+        std::fs::write(path.path().join("c/0"), &data)?;
+
+        let Ok(store) = IoUringStore::new(path.path()) else {
+            // io_uring may be disabled, e.g. in containers
+            return Ok(());
+        };
+        let key = StoreKey::new("c/0")?;
+        assert_eq!(store.get(&key).await?.unwrap().as_ref(), data.as_slice());
+        assert_eq!(store.size_key(&key).await?, Some(data.len() as u64));
+        assert!(store.get(&StoreKey::new("c/1")?).await?.is_none());
+
+        let bytes = store
+            .get_partial_values_key(
+                &key,
+                &[ByteRange::FromStart(10, Some(5)), ByteRange::Suffix(3)],
+            )
+            .await?
+            .unwrap();
+        assert_eq!(bytes[0].as_ref(), &data[10..15]);
+        assert_eq!(bytes[1].as_ref(), &data[data.len() - 3..]);
+        Ok(())
+    }
+}
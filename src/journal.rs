@@ -0,0 +1,182 @@
+//! An append-only journal of the completed chunks of an output array.
+//!
+//! The journal enables resuming an interrupted run (by skipping completed chunks) and auditing which run wrote each chunk.
+
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A journal entry, recording that a chunk was written by a run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The run identifier (e.g. the provenance timestamp).
+    pub run: String,
+    /// The chunk indices.
+    pub chunk: Vec<u64>,
+}
+
+/// Read the entries of the journal at `path`.
+///
+/// A truncated last line (e.g. of an interrupted write) is ignored.
+///
+/// # Errors
+/// Returns an error if the journal cannot be read or has an invalid entry.
+pub fn read_journal(path: &Path) -> std::io::Result<Vec<JournalEntry>> {
+    let lines = BufReader::new(File::open(path)?)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut entries = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) if i + 1 == lines.len() => {}
+            Err(err) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid journal entry on line {}: {err}", i + 1),
+                ))
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// An append-only journal of the completed chunks of an output array.
+///
+/// Each entry is a line of JSON (see [`JournalEntry`]), appended as each chunk is completed.
+pub struct ChunkJournal {
+    file: Mutex<File>,
+    run: String,
+    completed: HashSet<Vec<u64>>,
+}
+
+impl ChunkJournal {
+    /// Open the journal at `path` to record the chunks completed by `run`.
+    ///
+    /// If `resume` is true, the journal is appended to and the chunks completed by previous runs are loaded.
+    /// Otherwise, the journal is truncated.
+    ///
+    /// # Errors
+    /// Returns an error if the journal cannot be opened or read.
+    pub fn open(path: &Path, run: &str, resume: bool) -> std::io::Result<Self> {
+        let completed = if resume && path.exists() {
+            read_journal(path)?
+                .into_iter()
+                .map(|entry| entry.chunk)
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(path)?;
+        if resume {
+            // Remove a truncated last line, so that new entries start on a new line
+            let contents = std::fs::read(path)?;
+            let len = contents
+                .iter()
+                .rposition(|byte| *byte == b'\n')
+                .map_or(0, |position| position + 1);
+            file.set_len(len as u64)?;
+        }
+        Ok(Self {
+            file: Mutex::new(file),
+            run: run.to_string(),
+            completed,
+        })
+    }
+
+    /// Returns the run identifier.
+    pub fn run(&self) -> &str {
+        &self.run
+    }
+
+    /// Returns the number of chunks completed by previous runs.
+    pub fn num_completed(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Returns true if the chunk with `chunk_indices` was completed by a previous run.
+    pub fn is_completed(&self, chunk_indices: &[u64]) -> bool {
+        self.completed.contains(chunk_indices)
+    }
+
+    /// Record that the chunk with `chunk_indices` is completed.
+    ///
+    /// # Errors
+    /// Returns an error if the entry cannot be written.
+    pub fn record(&self, chunk_indices: &[u64]) -> std::io::Result<()> {
+        let entry = JournalEntry {
+            run: self.run.clone(),
+            chunk: chunk_indices.to_vec(),
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        // A single write per entry, so concurrent entries are not interleaved
+        self.file.lock().unwrap().write_all(&line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_journal() -> std::io::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("journal.jsonl");
+
+        let journal = ChunkJournal::open(&path, "a", false)?;
+        journal.record(&[0, 0])?;
+        journal.record(&[0, 1])?;
+        drop(journal);
+        // An interrupted write
+        OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"{\"run\":\"a\",\"chu")?;
+
+        let journal = ChunkJournal::open(&path, "b", true)?;
+        assert_eq!(journal.num_completed(), 2);
+        assert!(journal.is_completed(&[0, 1]));
+        assert!(!journal.is_completed(&[1, 0]));
+        journal.record(&[1, 0])?;
+        drop(journal);
+        assert_eq!(
+            read_journal(&path)?,
+            [
+                JournalEntry {
+                    run: "a".to_string(),
+                    chunk: vec![0, 0]
+                },
+                JournalEntry {
+                    run: "a".to_string(),
+                    chunk: vec![0, 1]
+                },
+                JournalEntry {
+                    run: "b".to_string(),
+                    chunk: vec![1, 0]
+                },
+            ]
+        );
+
+        // The journal is truncated if not resuming
+        let journal = ChunkJournal::open(&path, "c", false)?;
+        assert_eq!(journal.num_completed(), 0);
+        drop(journal);
+        assert!(read_journal(&path)?.is_empty());
+        Ok(())
+    }
+}
@@ -2,37 +2,77 @@
 #![doc(hidden)]
 
 use std::{
+    ffi::OsString,
     num::NonZeroU64,
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     time::SystemTime,
 };
 
+use cast::{convert_fill_value, CastPolicy, FillValueConversionError};
 use clap::Parser;
+use error_metrics::{ErrorMetrics, ErrorTolerance};
+use itertools::Itertools;
+use journal::ChunkJournal;
 use progress::{Progress, ProgressCallback};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use rayon_iter_concurrent_limit::iter_concurrent_limit;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use zarrs::{
     array::{
+        chunk_key_encoding::ChunkKeySeparator,
         codec::{
-            array_to_bytes::sharding, ArrayCodecTraits, ArrayToBytesCodecTraits, BytesCodec, Codec,
-            CodecOptionsBuilder, Crc32cCodec, ShardingCodec,
+            array_to_bytes::sharding::ShardingIndexLocation, ArrayCodecTraits,
+            ArrayToArrayCodecTraits, ArrayToBytesCodecTraits, BytesCodec, BytesToBytesCodecTraits,
+            Codec, CodecOptions, CodecOptionsBuilder, Crc32cCodec, ShardingCodec,
         },
         concurrency::RecommendedConcurrency,
-        Array, ArrayBuilder, ArrayChunkCacheExt, ArrayError, ArrayShardedExt,
-        ChunkCacheDecodedLruChunkLimit, ChunkCacheDecodedLruChunkLimitThreadLocal,
-        ChunkCacheDecodedLruSizeLimit, ChunkCacheDecodedLruSizeLimitThreadLocal,
-        ChunkRepresentation, CodecChain, DataType, DimensionName, FillValue, FillValueMetadataV3,
+        unravel_index, Array, ArrayBuilder, ArrayBytes, ArrayChunkCacheExt, ArrayError,
+        ArrayMetadata, ArrayShardedExt, ChunkCache, ChunkCacheDecodedLruChunkLimit,
+        ChunkCacheDecodedLruChunkLimitThreadLocal, ChunkCacheDecodedLruSizeLimit,
+        ChunkCacheDecodedLruSizeLimitThreadLocal, ChunkCacheTypeDecoded, ChunkGrid,
+        ChunkRepresentation, CodecChain, DataType, DimensionName, FillValueMetadataV3,
     },
-    array_subset::ArraySubset,
+    array_subset::{ArraySubset, IncompatibleDimensionalityError},
     config::global_config,
+    filesystem::{FilesystemStore, FilesystemStoreCreateError, FilesystemStoreOptions},
+    group::{Group, GroupBuilder, GroupCreateError},
     metadata::v3::{array::data_type::DataTypeMetadataV3, MetadataV3},
-    storage::{ReadableStorageTraits, ReadableWritableStorageTraits},
+    storage::{
+        ReadableListableStorage, ReadableStorageTraits, ReadableWritableStorageTraits,
+        StorageError, StorePrefix, WritableStorageTraits,
+    },
 };
 
+pub mod benchmark;
+pub mod benchmark_suite;
+pub mod binary_header;
+pub mod cast;
+pub mod config;
+pub mod error_metrics;
+pub mod fill_value_from_data;
 pub mod filter;
 pub mod info;
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+pub mod io_uring_store;
+pub mod journal;
+pub mod memory_budget;
+pub mod metadata_comparison;
+pub mod n5;
+pub mod ome_xml;
+pub mod physical_metadata;
 pub mod progress;
+pub mod query;
+pub mod rate_limit;
+pub mod read_cache;
+pub mod reshard;
+pub mod transcode;
+
+pub use memory_budget::{MemoryBudget, MemoryPermit, MEMORY_LIMIT_ENV};
 
 /// The `zarrs` tools version with the `zarrs` version.
 ///
@@ -44,6 +84,83 @@ pub const ZARRS_TOOLS_VERSION_WITH_ZARRS: &str = const_format::formatcp!(
     zarrs::version::version_str(),
 );
 
+/// The attribute key of [`Provenance`] metadata.
+pub const PROVENANCE_ATTRIBUTE: &str = "zarrs_tools";
+
+/// Provenance metadata recording how an output was created by a tool.
+///
+/// Tools write this to the attributes of their outputs under [`PROVENANCE_ATTRIBUTE`] unless `--no-provenance` is set.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Provenance {
+    /// The tool name.
+    pub tool: String,
+    /// The tool version with the `zarrs` version.
+    pub version: String,
+    /// The command line arguments parsed by the tool, including the binary name.
+    pub arguments: Vec<String>,
+    /// The input paths.
+    pub inputs: Vec<String>,
+    /// The UTC creation time (RFC 3339).
+    pub timestamp: String,
+    /// The random seed of a stochastic tool or filter, which reproduces the output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl Provenance {
+    /// Create provenance metadata for `tool` with the command line `arguments` it parsed (including the binary name) and `inputs` at the current time.
+    ///
+    /// The `arguments` are those passed to the tool rather than [`std::env::args`], which differ if the tool is run as a subcommand of the `zarrs` binary.
+    pub fn new(tool: &str, arguments: &[OsString], inputs: Vec<String>) -> Self {
+        Self {
+            tool: tool.to_string(),
+            version: ZARRS_TOOLS_VERSION_WITH_ZARRS.to_string(),
+            arguments: arguments
+                .iter()
+                .map(|argument| argument.to_string_lossy().into_owned())
+                .collect(),
+            inputs,
+            timestamp: timestamp_utc(SystemTime::now()),
+            seed: None,
+        }
+    }
+
+    /// Insert the provenance metadata into `attributes` under [`PROVENANCE_ATTRIBUTE`].
+    pub fn insert(&self, attributes: &mut serde_json::Map<String, serde_json::Value>) {
+        attributes.insert(
+            PROVENANCE_ATTRIBUTE.to_string(),
+            serde_json::to_value(self).unwrap(),
+        );
+    }
+}
+
+/// Format `time` as a UTC RFC 3339 timestamp (e.g. `2024-01-31T12:00:00Z`).
+fn timestamp_utc(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+    // Civil date from days since the epoch (https://howardhinnant.github.io/date_algorithms.html)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
 #[derive(Parser)]
 #[allow(rustdoc::bare_urls)]
 pub struct ZarrEncodingArgs {
@@ -64,16 +181,58 @@ pub struct ZarrEncodingArgs {
 
     /// Chunk shape. A comma separated list of the chunk size along each array dimension.
     ///
-    /// If any dimension has size zero, it will be set to match the array shape.
-    #[arg(short, long, required = true, value_delimiter = ',')]
-    pub chunk_shape: Vec<u64>,
+    /// Each size is an integer, full (or 0) to match the array shape, or auto.
+    /// Auto sizes are chosen so that the decoded chunk size does not exceed 1MiB.
+    ///
+    /// Examples:
+    ///   64,64,64
+    ///   auto,full,256,256
+    #[arg(
+        short,
+        long,
+        verbatim_doc_comment,
+        required_unless_present = "chunk_grid",
+        value_delimiter = ','
+    )]
+    pub chunk_shape: Vec<ChunkSize>,
+
+    /// Chunk grid (optional).
+    ///
+    /// JSON holding chunk grid metadata, used instead of --chunk-shape.
+    /// This supports variable chunk grids (ZEP0003), e.g. for irregular chunking along a time axis.
+    /// The chunk grid must span the array shape.
+    ///
+    /// Examples:
+    ///   '{ "name": "rectangular", "configuration": { "chunk_shape": [[10, 20, 30, 40], 256, 256] } }'
+    #[arg(long, verbatim_doc_comment, conflicts_with_all = ["chunk_shape", "shard_shape"])]
+    pub chunk_grid: Option<String>,
 
     /// Shard shape (optional). A comma separated list of the shard size along each array dimension.
     ///
     /// If specified, the array is encoded using the sharding codec.
-    /// If any dimension has size zero, it will be set to match the array shape.
+    /// Each size is an integer, full (or 0) to match the array shape, or auto.
+    /// Auto sizes are chosen so that the decoded shard size does not exceed 256MiB.
+    /// The shard shape is rounded up to a multiple of the chunk shape.
     #[arg(short, long, verbatim_doc_comment, value_delimiter = ',')]
-    pub shard_shape: Option<Vec<u64>>,
+    pub shard_shape: Option<Vec<ChunkSize>>,
+
+    /// The shard index location. Either start or end.
+    ///
+    /// Ignored if the array is not sharded.
+    #[arg(long, default_value = "end", value_parser = parse_shard_index_location)]
+    pub shard_index_location: ShardingIndexLocation,
+
+    /// Shard index codecs (optional).
+    ///
+    /// JSON holding an array of codec metadata used to encode the shard index.
+    /// If unspecified, this defaults to the `bytes` codec followed by the `crc32c` codec.
+    /// Ignored if the array is not sharded.
+    ///
+    /// Examples:
+    ///   '[ { "name": "bytes", "configuration": { "endian": "little" } } ]'
+    ///   '[ { "name": "bytes", "configuration": { "endian": "big" } }, { "name": "crc32c" } ]'
+    #[arg(long, verbatim_doc_comment)]
+    pub shard_index_codecs: Option<String>,
 
     /// Array to array codecs (optional).
     ///
@@ -121,39 +280,195 @@ pub struct ZarrEncodingArgs {
 
 fn parse_data_type(data_type: &str) -> std::io::Result<DataTypeMetadataV3> {
     serde_json::from_value(serde_json::Value::String(data_type.to_string()))
-        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))
+        .map_err(|err| std::io::Error::other(err.to_string()))
 }
 
 fn parse_fill_value(fill_value: &str) -> std::io::Result<FillValueMetadataV3> {
-    serde_json::from_str(fill_value)
-        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))
+    serde_json::from_str(fill_value).map_err(|err| std::io::Error::other(err.to_string()))
 }
 
-#[must_use]
+fn parse_shard_index_location(index_location: &str) -> std::io::Result<ShardingIndexLocation> {
+    serde_json::from_value(serde_json::Value::String(index_location.to_string()))
+        .map_err(|err| std::io::Error::other(err.to_string()))
+}
+
+/// The default target decoded chunk size of [`ChunkSize::Auto`] chunk dimensions (1MiB).
+pub const AUTO_CHUNK_SIZE_DEFAULT: u64 = 1 << 20;
+
+/// The default target decoded shard size of [`ChunkSize::Auto`] shard dimensions (256MiB).
+pub const AUTO_SHARD_SIZE_DEFAULT: u64 = 1 << 28;
+
+/// The size of a chunk or shard along a dimension.
+///
+/// Parsed from an integer, `full` (or `0`), or `auto`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkSize {
+    /// A size chosen for a target decoded chunk or shard size.
+    Auto,
+    /// The array size.
+    Full,
+    /// A fixed size.
+    Size(NonZeroU64),
+}
+
+impl From<u64> for ChunkSize {
+    fn from(size: u64) -> Self {
+        NonZeroU64::new(size).map_or(Self::Full, Self::Size)
+    }
+}
+
+impl std::str::FromStr for ChunkSize {
+    type Err = std::io::Error;
+
+    fn from_str(size: &str) -> Result<Self, Self::Err> {
+        match size.trim() {
+            "auto" => Ok(Self::Auto),
+            "full" => Ok(Self::Full),
+            size => size.parse::<u64>().map(Self::from).map_err(|_| {
+                std::io::Error::other(format!(
+                    "invalid chunk size {size}, expected an integer, full, or auto"
+                ))
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for ChunkSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Full => write!(f, "full"),
+            Self::Size(size) => write!(f, "{size}"),
+        }
+    }
+}
+
+impl Serialize for ChunkSize {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Size(size) => serializer.serialize_u64(size.get()),
+            Self::Auto | Self::Full => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ChunkSize {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum ChunkSizeMetadata {
+            Size(u64),
+            Keyword(String),
+        }
+        match ChunkSizeMetadata::deserialize(deserializer)? {
+            ChunkSizeMetadata::Size(size) => Ok(Self::from(size)),
+            ChunkSizeMetadata::Keyword(size) => size.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl JsonSchema for ChunkSize {
+    fn schema_name() -> String {
+        "ChunkSize".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{InstanceType, SchemaObject, SubschemaValidation};
+        let keyword = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(vec!["auto".into(), "full".into()]),
+            ..Default::default()
+        };
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![gen.subschema_for::<u64>(), keyword.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// Get a chunk grid from JSON chunk grid metadata (e.g. a `regular` or `rectangular` chunk grid).
+fn get_chunk_grid(chunk_grid: &str) -> anyhow::Result<ChunkGrid> {
+    let metadata = MetadataV3::try_from(chunk_grid)
+        .map_err(|err| anyhow::anyhow!("invalid chunk grid {chunk_grid}: {err}"))?;
+    ChunkGrid::from_metadata(&metadata)
+        .map_err(|err| anyhow::anyhow!("unsupported chunk grid {chunk_grid}: {err}"))
+}
+
+/// Get the default shard index codecs, `bytes` and `crc32c`.
+fn default_shard_index_codecs() -> Arc<CodecChain> {
+    Arc::new(CodecChain::new(
+        vec![],
+        Arc::<BytesCodec>::default(),
+        vec![Arc::new(Crc32cCodec::new())],
+    ))
+}
+
+/// Get the shard index codecs from JSON codec metadata, defaulting to `bytes` and `crc32c`.
+///
+/// # Errors
+/// Returns [`ArrayBuilderReencodeError::InvalidShardIndexCodecs`] if the codec metadata is invalid or unsupported.
+fn get_shard_index_codecs(
+    shard_index_codecs: Option<&str>,
+) -> Result<Arc<CodecChain>, ArrayBuilderReencodeError> {
+    let Some(shard_index_codecs) = shard_index_codecs else {
+        return Ok(default_shard_index_codecs());
+    };
+    let invalid = |err: String| {
+        ArrayBuilderReencodeError::InvalidShardIndexCodecs(shard_index_codecs.to_string(), err)
+    };
+    let metadatas: Vec<MetadataV3> =
+        serde_json::from_str(shard_index_codecs).map_err(|err| invalid(err.to_string()))?;
+    Ok(Arc::new(
+        CodecChain::from_metadata(&metadatas).map_err(|err| invalid(err.to_string()))?,
+    ))
+}
+
+/// Get an array builder for a new array from the encoding arguments.
+///
+/// # Errors
+/// Returns an error if the chunk grid or shard index codecs are invalid or unsupported, the chunk or shard shape does not match the dimensionality of the array shape, or an auto chunk or shard size is used with a variable sized data type.
 pub fn get_array_builder(
     encoding_args: &ZarrEncodingArgs,
     array_shape: &[u64],
     data_type: DataType,
     dimension_names: Option<Vec<DimensionName>>,
-) -> zarrs::array::ArrayBuilder {
-    // Set the chunk/shard shape to the array shape where it is 0, otherwise make it <= array shape
-    let shard_shape = encoding_args.shard_shape.as_ref().map(|shard_shape| {
-        std::iter::zip(shard_shape, array_shape)
-            .map(|(&s, &a)| if s == 0 { a } else { std::cmp::min(s, a) })
-            .collect::<Vec<_>>()
-    });
+) -> anyhow::Result<zarrs::array::ArrayBuilder> {
+    if encoding_args.chunk_grid.is_none() {
+        check_chunk_shape_dimensionality("chunk", &encoding_args.chunk_shape, array_shape)?;
+    }
+    if let Some(shard_shape) = &encoding_args.shard_shape {
+        check_chunk_shape_dimensionality("shard", shard_shape, array_shape)?;
+    }
 
-    // Also ensure shard shape is a multiple of chunk shape
-    let chunk_shape = std::iter::zip(&encoding_args.chunk_shape, array_shape)
-        .map(|(&c, &a)| if c == 0 { a } else { c })
-        .collect::<Vec<_>>();
-    let shard_shape: Option<Vec<u64>> = shard_shape.map(|shard_shape| {
-        std::iter::zip(&shard_shape, &chunk_shape)
-            .map(|(s, c)| {
-                // the shard shape must be a multiple of the chunk shape
-                s.next_multiple_of(*c)
-            })
-            .collect()
+    // Resolve the auto/full dimensions of the chunk/shard shape
+    let element_size = if has_auto_size(
+        &encoding_args.chunk_shape,
+        encoding_args.shard_shape.as_deref(),
+    ) {
+        data_type
+            .fixed_size()
+            .ok_or_else(|| ArrayBuilderReencodeError::AutoChunkSize(data_type.to_string()))?
+    } else {
+        1
+    };
+    let chunk_shape = resolve_chunk_shape(
+        &encoding_args.chunk_shape,
+        array_shape,
+        element_size,
+        AUTO_CHUNK_SIZE_DEFAULT,
+    );
+    let shard_shape: Option<Vec<u64>> = encoding_args.shard_shape.as_ref().map(|shard_shape| {
+        resolve_shard_shape(
+            shard_shape,
+            array_shape,
+            &chunk_shape,
+            element_size,
+            AUTO_SHARD_SIZE_DEFAULT,
+        )
     });
 
     // Get the "block shape", which is the shard shape if sharding, otherwise the chunk shape
@@ -216,12 +531,12 @@ pub fn get_array_builder(
         .unwrap();
 
     // Create array
-    let mut array_builder = ArrayBuilder::new(
-        array_shape.to_vec(),
-        data_type,
-        block_shape.clone().try_into().unwrap(),
-        fill_value,
-    );
+    let chunk_grid = encoding_args.chunk_grid.as_deref().map_or_else(
+        || Ok(block_shape.clone().try_into().unwrap()),
+        get_chunk_grid,
+    )?;
+    let mut array_builder =
+        ArrayBuilder::new(array_shape.to_vec(), data_type, chunk_grid, fill_value);
     array_builder.dimension_names(dimension_names);
     if let Some(attributes) = &encoding_args.attributes {
         let attributes: serde_json::Map<String, serde_json::Value> =
@@ -230,11 +545,7 @@ pub fn get_array_builder(
     }
     array_builder.chunk_key_encoding_default_separator(encoding_args.separator.try_into().unwrap());
     if shard_shape.is_some() {
-        let index_codecs = Arc::new(CodecChain::new(
-            vec![],
-            Arc::<BytesCodec>::default(),
-            vec![Arc::new(Crc32cCodec::new())],
-        ));
+        let index_codecs = get_shard_index_codecs(encoding_args.shard_index_codecs.as_deref())?;
         let inner_codecs = Arc::new(CodecChain::new(
             array_to_array_codecs,
             array_to_bytes_codec,
@@ -244,7 +555,7 @@ pub fn get_array_builder(
             chunk_shape.try_into().unwrap(),
             inner_codecs,
             index_codecs,
-            sharding::ShardingIndexLocation::End,
+            encoding_args.shard_index_location,
         )));
     } else {
         array_builder.array_to_array_codecs(array_to_array_codecs);
@@ -252,11 +563,10 @@ pub fn get_array_builder(
         array_builder.bytes_to_bytes_codecs(bytes_to_bytes_codecs);
     }
 
-    array_builder
+    Ok(array_builder)
 }
 
-#[derive(Parser, Debug, Clone, Default, Serialize, Deserialize)]
-
+#[derive(Parser, Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ZarrReencodingArgs {
     /// The data type as a string
     ///
@@ -269,6 +579,7 @@ pub struct ZarrReencodingArgs {
     ///   - r* (raw bits, where * is a multiple of 8)
     #[serde(skip_serializing_if = "Option::is_none")]
     #[arg(short, long, verbatim_doc_comment, value_parser = parse_data_type)]
+    #[schemars(with = "Option<String>")]
     pub data_type: Option<DataTypeMetadataV3>,
 
     /// Fill value. See <https://zarr-specs.readthedocs.io/en/latest/v3/core/v3.0.html#fill-value>
@@ -281,8 +592,17 @@ pub struct ZarrReencodingArgs {
     ///   r*: "[0, 255]"
     #[serde(skip_serializing_if = "Option::is_none")]
     #[arg(short, long, verbatim_doc_comment, allow_hyphen_values(true), value_parser = parse_fill_value)]
+    #[schemars(with = "Option<serde_json::Value>")]
     pub fill_value: Option<FillValueMetadataV3>,
 
+    /// The cast policy for converting the fill value to a new data type.
+    ///
+    /// Applies if the data type is changed without an explicit fill value.
+    /// Defaults to saturate, which clamps the fill value to the range of the new data type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[arg(long, value_enum)]
+    pub fill_value_cast: Option<CastPolicy>,
+
     /// The chunk key encoding separator. Either . or /.
     #[serde(skip_serializing_if = "Option::is_none")]
     #[arg(long)]
@@ -290,18 +610,58 @@ pub struct ZarrReencodingArgs {
 
     /// Chunk shape. A comma separated list of the chunk size along each array dimension.
     ///
-    /// If any dimension has size zero, it will be set to match the array shape.
+    /// Each size is an integer, full (or 0) to match the array shape, or auto.
+    /// Auto sizes are chosen so that the decoded chunk size does not exceed 1MiB.
+    ///
+    /// Examples:
+    ///   64,64,64
+    ///   auto,full,256,256
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[arg(short, long, verbatim_doc_comment, value_delimiter = ',')]
+    pub chunk_shape: Option<Vec<ChunkSize>>,
+
+    /// Chunk grid.
+    ///
+    /// JSON holding chunk grid metadata, used instead of --chunk-shape and --shard-shape.
+    /// This supports variable chunk grids (ZEP0003), e.g. for irregular chunking along a time axis.
+    /// The chunk grid must span the array shape.
+    /// Defaults to the input chunk grid if it is not regular and the array, chunk, and shard shape are unchanged.
+    ///
+    /// Examples:
+    ///   '{ "name": "rectangular", "configuration": { "chunk_shape": [[10, 20, 30, 40], 256, 256] } }'
     #[serde(skip_serializing_if = "Option::is_none")]
-    #[arg(short, long, value_delimiter = ',')]
-    pub chunk_shape: Option<Vec<u64>>,
+    #[arg(long, verbatim_doc_comment, conflicts_with_all = ["chunk_shape", "shard_shape"])]
+    pub chunk_grid: Option<String>,
 
     /// Shard shape. A comma separated list of the shard size along each array dimension.
     ///
     /// If specified, the array is encoded using the sharding codec.
-    /// If any dimension has size zero, it will be set to match the array shape.
+    /// Each size is an integer, full (or 0) to match the array shape, or auto.
+    /// Auto sizes are chosen so that the decoded shard size does not exceed 256MiB.
+    /// The shard shape is rounded up to a multiple of the chunk shape.
     #[serde(skip_serializing_if = "Option::is_none")]
     #[arg(short, long, verbatim_doc_comment, value_delimiter = ',')]
-    pub shard_shape: Option<Vec<u64>>,
+    pub shard_shape: Option<Vec<ChunkSize>>,
+
+    /// The shard index location. Either start or end.
+    ///
+    /// Defaults to the input shard index location if sharded, otherwise end.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[arg(long, value_parser = parse_shard_index_location)]
+    #[schemars(with = "Option<String>")]
+    pub shard_index_location: Option<ShardingIndexLocation>,
+
+    /// Shard index codecs.
+    ///
+    /// JSON holding an array of codec metadata used to encode the shard index.
+    /// Defaults to the input shard index codecs if sharded, otherwise the `bytes` codec followed by the `crc32c` codec.
+    ///
+    /// Examples:
+    ///   '[ { "name": "bytes", "configuration": { "endian": "little" } } ]'
+    ///   '[ { "name": "bytes", "configuration": { "endian": "big" } }, { "name": "crc32c" } ]'
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[arg(long, verbatim_doc_comment)]
+    pub shard_index_codecs: Option<String>,
 
     /// Array to array codecs.
     ///
@@ -341,10 +701,30 @@ pub struct ZarrReencodingArgs {
     pub bytes_to_bytes_codecs: Option<String>,
 
     /// Dimension names (optional). Comma separated.
+    ///
+    /// There must be a unique name for each array dimension.
+    /// Defaults to the input dimension names.
     #[serde(skip_serializing_if = "Option::is_none")]
     #[arg(long, verbatim_doc_comment, value_delimiter = ',')]
     pub dimension_names: Option<Vec<String>>,
 
+    /// Rename dimensions (optional). Comma separated AXIS=NAME pairs.
+    ///
+    /// AXIS is a dimension index or an input dimension name.
+    /// Other dimension names are retained from the input.
+    ///
+    /// Examples:
+    ///   0=t
+    ///   z=depth,c=channel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[arg(
+        long,
+        verbatim_doc_comment,
+        value_delimiter = ',',
+        conflicts_with = "dimension_names"
+    )]
+    pub rename_dim: Option<Vec<String>>,
+
     /// Attributes (optional).
     ///
     /// JSON holding array attributes.
@@ -358,6 +738,14 @@ pub struct ZarrReencodingArgs {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[arg(long)]
     pub attributes_append: Option<String>,
+
+    /// Attributes to remove (optional). Comma separated.
+    ///
+    /// Top-level attribute keys or JSON pointers (e.g. /omero/channels/0/label), removed after attributes are appended.
+    /// Removing _zarrs also disables writing the zarrs provenance metadata.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[arg(long, value_delimiter = ',')]
+    pub attributes_remove: Option<Vec<String>>,
 }
 
 pub enum ZarrReEncodingChangeType {
@@ -372,29 +760,456 @@ impl ZarrReencodingArgs {
             || self.fill_value.is_some()
             || self.separator.is_some()
             || self.chunk_shape.is_some()
+            || self.chunk_grid.is_some()
             || self.shard_shape.is_some()
+            || self.shard_index_location.is_some()
+            || self.shard_index_codecs.is_some()
             || self.array_to_array_codecs.is_some()
             || self.array_to_bytes_codec.is_some()
             || self.bytes_to_bytes_codecs.is_some()
         {
             ZarrReEncodingChangeType::MetadataAndChunks
         } else if self.dimension_names.is_some()
+            || self.rename_dim.is_some()
             || self.attributes.is_some()
             || self.attributes_append.is_some()
+            || self.attributes_remove.is_some()
         {
             ZarrReEncodingChangeType::Metadata
         } else {
             ZarrReEncodingChangeType::None
         }
     }
+
+    /// Returns the output dimension names from `--dimension-names` or `--rename-dim` applied to the `input` dimension names.
+    ///
+    /// Returns [`None`] if neither is set.
+    /// Input dimension names are ignored if they do not match the output `dimensionality`.
+    ///
+    /// # Errors
+    /// Returns a [`ArrayBuilderReencodeError`] if the number of dimension names does not match `dimensionality`, a renamed dimension does not exist or is ambiguous, or the output dimension names are not unique.
+    pub fn output_dimension_names(
+        &self,
+        input: Option<&[DimensionName]>,
+        dimensionality: usize,
+    ) -> Result<Option<Vec<DimensionName>>, ArrayBuilderReencodeError> {
+        let dimension_names = if let Some(dimension_names) = &self.dimension_names {
+            if dimension_names.len() != dimensionality {
+                return Err(ArrayBuilderReencodeError::InvalidDimensionNames(format!(
+                    "{} dimension names were given for an array with {dimensionality} dimensions",
+                    dimension_names.len()
+                )));
+            }
+            dimension_names.iter().map(DimensionName::new).collect()
+        } else if let Some(renames) = &self.rename_dim {
+            let mut dimension_names = input
+                .filter(|input| input.len() == dimensionality)
+                .map_or_else(
+                    || vec![DimensionName::default(); dimensionality],
+                    <[_]>::to_vec,
+                );
+            for rename in renames {
+                let (axis, name) = rename.split_once('=').ok_or_else(|| {
+                    ArrayBuilderReencodeError::InvalidDimensionNames(format!(
+                        "{rename} is not an AXIS=NAME pair"
+                    ))
+                })?;
+                let index = match axis.parse::<usize>() {
+                    Ok(index) if index < dimensionality => Some(index),
+                    _ => {
+                        let mut named = dimension_names
+                            .iter()
+                            .positions(|dimension_name| dimension_name.as_str() == Some(axis));
+                        let index = named.next();
+                        if named.next().is_some() {
+                            return Err(ArrayBuilderReencodeError::InvalidDimensionNames(format!(
+                                "dimension {axis} is ambiguous"
+                            )));
+                        }
+                        index
+                    }
+                };
+                let index = index.ok_or_else(|| {
+                    ArrayBuilderReencodeError::InvalidDimensionNames(format!(
+                        "dimension {axis} does not exist"
+                    ))
+                })?;
+                dimension_names[index] = DimensionName::new(name);
+            }
+            dimension_names
+        } else {
+            return Ok(None);
+        };
+        if let Some(name) = dimension_names
+            .iter()
+            .filter_map(DimensionName::as_str)
+            .duplicates()
+            .next()
+        {
+            return Err(ArrayBuilderReencodeError::InvalidDimensionNames(format!(
+                "dimension {name} is duplicated"
+            )));
+        }
+        Ok(Some(dimension_names))
+    }
+
+    /// Returns true if the `_zarrs` provenance attribute is removed by `--attributes-remove`.
+    pub fn removes_zarrs_metadata(&self) -> bool {
+        self.attributes_remove
+            .iter()
+            .flatten()
+            .any(|key| key == "_zarrs" || key == "/_zarrs")
+    }
+}
+
+/// A codec preset, which expands into a curated codec chain and chunk/shard size targets.
+///
+/// | Preset             | Bytes to bytes codecs | Decoded chunk size | Decoded shard size |
+/// |--------------------|-----------------------|--------------------|--------------------|
+/// | `fast`             | zstd (level 1)        | 4MiB               | unsharded          |
+/// | `balanced`         | zstd (level 5)        | 1MiB               | 64MiB              |
+/// | `max-compression`  | zstd (level 19)       | 4MiB               | 256MiB             |
+/// | `viewer-optimised` | zstd (level 3)        | 256KiB             | 64MiB              |
+///
+/// Larger chunks compress better and reduce per-chunk overhead, whereas smaller chunks reduce the data decoded for small reads (e.g. a viewer panning through a volume).
+/// Sharding keeps the number of files manageable when chunks are small.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CodecPreset {
+    /// Light compression for fast encoding and decoding: zstd (level 1), 4MiB chunks, unsharded.
+    Fast,
+    /// A balance of compression ratio and speed: zstd (level 5), 1MiB chunks, 64MiB shards.
+    Balanced,
+    /// The highest compression ratio, with slow encoding: zstd (level 19), 4MiB chunks, 256MiB shards.
+    MaxCompression,
+    /// Small chunks in shards for interactive viewers: zstd (level 3), 256KiB chunks, 64MiB shards.
+    ViewerOptimised,
+}
+
+impl CodecPreset {
+    /// Returns the bytes to bytes codecs (JSON) of the preset.
+    #[must_use]
+    pub fn bytes_to_bytes_codecs(&self) -> String {
+        let level = match self {
+            CodecPreset::Fast => 1,
+            CodecPreset::Balanced => 5,
+            CodecPreset::MaxCompression => 19,
+            CodecPreset::ViewerOptimised => 3,
+        };
+        format!(
+            r#"[ {{ "name": "zstd", "configuration": {{ "level": {level}, "checksum": false }} }} ]"#
+        )
+    }
+
+    /// Returns the target decoded chunk size (in bytes) of the preset.
+    #[must_use]
+    pub fn chunk_size(&self) -> u64 {
+        match self {
+            CodecPreset::Fast | CodecPreset::MaxCompression => 4 << 20,
+            CodecPreset::Balanced => 1 << 20,
+            CodecPreset::ViewerOptimised => 256 << 10,
+        }
+    }
+
+    /// Returns the target decoded shard size (in bytes) of the preset, or [`None`] if the preset is unsharded.
+    #[must_use]
+    pub fn shard_size(&self) -> Option<u64> {
+        match self {
+            CodecPreset::Fast => None,
+            CodecPreset::Balanced | CodecPreset::ViewerOptimised => Some(64 << 20),
+            CodecPreset::MaxCompression => Some(256 << 20),
+        }
+    }
+
+    /// Set the codecs of `encoding` that are not explicitly set to those of the preset.
+    pub fn apply_codecs(&self, encoding: &mut ZarrReencodingArgs) {
+        if encoding.bytes_to_bytes_codecs.is_none() {
+            encoding.bytes_to_bytes_codecs = Some(self.bytes_to_bytes_codecs());
+        }
+    }
+}
+
+/// Remove attributes by top-level key or [JSON pointer](https://datatracker.ietf.org/doc/html/rfc6901) (e.g. `/omero/channels/0/label`).
+///
+/// Keys and pointers that do not exist are ignored.
+pub fn remove_attributes(
+    attributes: &mut serde_json::Map<String, serde_json::Value>,
+    keys: &[String],
+) {
+    let mut value = serde_json::Value::Object(std::mem::take(attributes));
+    for key in keys {
+        let (parent, last) = if key.starts_with('/') {
+            let (parent, last) = key.rsplit_once('/').unwrap();
+            (parent, last.replace("~1", "/").replace("~0", "~"))
+        } else {
+            ("", key.clone())
+        };
+        match value.pointer_mut(parent) {
+            Some(serde_json::Value::Object(object)) => {
+                object.remove(&last);
+            }
+            Some(serde_json::Value::Array(array)) => {
+                if let Ok(index) = last.parse::<usize>() {
+                    if index < array.len() {
+                        array.remove(index);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if let serde_json::Value::Object(value) = value {
+        *attributes = value;
+    }
+}
+
+/// An error creating an [`ArrayBuilder`] with [`get_array_builder_reencode`].
+#[derive(Debug, thiserror::Error)]
+pub enum ArrayBuilderReencodeError {
+    #[error(transparent)]
+    FillValueConversion(#[from] FillValueConversionError),
+    #[error("invalid dimension names: {_0}")]
+    InvalidDimensionNames(String),
+    #[error("the {_0} codec is not representable with the output data type: {_1}")]
+    UnrepresentableCodec(String, String),
+    #[error("auto chunk and shard sizes are not supported for the variable sized data type {_0}")]
+    AutoChunkSize(String),
+    #[error("{_0}")]
+    InvalidChunkGrid(String),
+    #[error("the {_0} shape {_1} does not match the dimensionality of the array shape {_2:?}")]
+    IncompatibleDimensionality(&'static str, String, Vec<u64>),
+    #[error("invalid shard index codecs {_0}: {_1}")]
+    InvalidShardIndexCodecs(String, String),
+}
+
+/// Returns the little-endian `numpy` data type string of a data type (e.g. `<i2`).
+fn numpy_dtype(data_type: &DataType) -> Option<&'static str> {
+    Some(match data_type {
+        DataType::Bool => "|b1",
+        DataType::Int8 => "|i1",
+        DataType::Int16 => "<i2",
+        DataType::Int32 => "<i4",
+        DataType::Int64 => "<i8",
+        DataType::UInt8 => "|u1",
+        DataType::UInt16 => "<u2",
+        DataType::UInt32 => "<u4",
+        DataType::UInt64 => "<u8",
+        DataType::Float16 => "<f2",
+        DataType::Float32 => "<f4",
+        DataType::Float64 => "<f8",
+        DataType::Complex64 => "<c8",
+        DataType::Complex128 => "<c16",
+        _ => return None,
+    })
+}
+
+/// The codecs with a data type dependent configuration supported by [`translate_codec_metadata`].
+const DATA_TYPE_DEPENDENT_CODECS: [&str; 4] = ["delta", "quantize", "fixedscaleoffset", "shuffle"];
+
+/// Returns the codec identifier of a codec `name`, without a `numcodecs.` prefix.
+fn codec_identifier(name: &str) -> &str {
+    name.strip_prefix("numcodecs.").unwrap_or(name)
+}
+
+/// Returns the names of the codecs of `array` with a data type dependent configuration (e.g. Zarr V2 filters).
+fn data_type_dependent_codec_names<TStorage: ?Sized>(array: &Array<TStorage>) -> Vec<String> {
+    array
+        .codecs()
+        .create_metadatas()
+        .iter()
+        .map(MetadataV3::name)
+        .filter(|name| DATA_TYPE_DEPENDENT_CODECS.contains(&codec_identifier(name)))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Translate the metadata of a codec with a data type dependent configuration (e.g. Zarr V2 filters) from `data_type_in` to `data_type_out`.
+///
+/// Returns [`None`] if the codec does not need to be translated.
+/// Supports the `delta`, `quantize`, `fixedscaleoffset`, and `shuffle` codecs (with or without a `numcodecs.` prefix).
+///
+/// # Errors
+/// Returns [`ArrayBuilderReencodeError::UnrepresentableCodec`] if the codec cannot be represented with `data_type_out`.
+fn translate_codec_metadata(
+    metadata: &MetadataV3,
+    data_type_in: &DataType,
+    data_type_out: &DataType,
+) -> Result<Option<MetadataV3>, ArrayBuilderReencodeError> {
+    let name = metadata.name();
+    let unrepresentable =
+        |reason: String| ArrayBuilderReencodeError::UnrepresentableCodec(name.to_string(), reason);
+    let mut configuration = metadata.configuration().cloned().unwrap_or_default();
+
+    // Replace a `dtype`/`astype` that matches the input data type, in the same (numpy or Zarr V3) representation
+    let mut replace_dtype = |key: &str| -> Result<(), ArrayBuilderReencodeError> {
+        let Some(dtype) = configuration.get(key).and_then(|dtype| dtype.as_str()) else {
+            return Ok(());
+        };
+        let (dtype_in, dtype_out) = if dtype.starts_with(['<', '>', '|']) {
+            // The big-endian byte order of the input dtype is retained for multi-byte output data types
+            let dtype_out = numpy_dtype(data_type_out).map(|dtype_out| {
+                match (dtype.starts_with('>'), dtype_out.strip_prefix('<')) {
+                    (true, Some(dtype_out)) => format!(">{dtype_out}"),
+                    _ => dtype_out.to_string(),
+                }
+            });
+            (
+                numpy_dtype(data_type_in).unwrap_or_default().to_string(),
+                dtype_out,
+            )
+        } else {
+            (data_type_in.to_string(), Some(data_type_out.to_string()))
+        };
+        if dtype.trim_start_matches(['<', '>', '|']) == dtype_in.trim_start_matches(['<', '>', '|'])
+        {
+            let dtype_out = dtype_out.ok_or_else(|| {
+                unrepresentable(format!("the {data_type_out} data type is not supported"))
+            })?;
+            configuration.insert(key.to_string(), serde_json::Value::String(dtype_out));
+        }
+        Ok(())
+    };
+
+    match codec_identifier(name) {
+        "delta" => {
+            replace_dtype("dtype")?;
+            replace_dtype("astype")?;
+        }
+        "quantize" => {
+            if !matches!(
+                data_type_out,
+                DataType::Float16 | DataType::Float32 | DataType::Float64
+            ) {
+                return Err(unrepresentable(format!(
+                    "quantization requires a floating point data type, not {data_type_out}"
+                )));
+            }
+            replace_dtype("dtype")?;
+            replace_dtype("astype")?;
+        }
+        "fixedscaleoffset" => {
+            // The encoded type (`astype`) is retained
+            replace_dtype("dtype")?;
+        }
+        "shuffle" => {
+            let element_size = data_type_out.fixed_size().ok_or_else(|| {
+                unrepresentable(format!("the {data_type_out} data type is variable length"))
+            })?;
+            configuration.insert("elementsize".to_string(), element_size.into());
+        }
+        _ => return Ok(None),
+    }
+    let metadata = serde_json::from_value(serde_json::json!({
+        "name": name,
+        "configuration": configuration,
+    }))
+    .map_err(|err| unrepresentable(err.to_string()))?;
+    Ok(Some(metadata))
+}
+
+/// Translate data type dependent codecs (e.g. Zarr V2 filters) from `data_type_in` to `data_type_out`.
+///
+/// See [`translate_codec_metadata`].
+fn translate_codecs(
+    array_to_array_codecs: &mut [Arc<dyn ArrayToArrayCodecTraits>],
+    bytes_to_bytes_codecs: &mut [Arc<dyn BytesToBytesCodecTraits>],
+    data_type_in: &DataType,
+    data_type_out: &DataType,
+) -> Result<(), ArrayBuilderReencodeError> {
+    let translate =
+        |metadata: Option<MetadataV3>| -> Result<Option<Codec>, ArrayBuilderReencodeError> {
+            let Some(metadata) = metadata else {
+                return Ok(None);
+            };
+            let Some(metadata) = translate_codec_metadata(&metadata, data_type_in, data_type_out)?
+            else {
+                return Ok(None);
+            };
+            Codec::from_metadata(&metadata).map(Some).map_err(|err| {
+                ArrayBuilderReencodeError::UnrepresentableCodec(
+                    metadata.name().to_string(),
+                    err.to_string(),
+                )
+            })
+        };
+    for codec in array_to_array_codecs {
+        if let Some(Codec::ArrayToArray(translated)) = translate(codec.create_metadata())? {
+            *codec = translated;
+        }
+    }
+    for codec in bytes_to_bytes_codecs {
+        if let Some(Codec::BytesToBytes(translated)) = translate(codec.create_metadata())? {
+            *codec = translated;
+        }
+    }
+    Ok(())
 }
 
+/// The attribute holding the dimension names of a Zarr V2 array (the xarray convention).
+pub const ARRAY_DIMENSIONS_ATTRIBUTE: &str = "_ARRAY_DIMENSIONS";
+
+/// Returns the dimension names of `array`.
+///
+/// The dimension names of a Zarr V2 array are read from its `_ARRAY_DIMENSIONS` attribute if they are otherwise unset.
 #[must_use]
+pub fn array_dimension_names<TStorage: ?Sized>(
+    array: &Array<TStorage>,
+) -> Option<Vec<DimensionName>> {
+    if let Some(dimension_names) = array.dimension_names() {
+        return Some(dimension_names.clone());
+    }
+    if !matches!(array.metadata(), ArrayMetadata::V2(_)) {
+        return None;
+    }
+    let dimension_names = array
+        .attributes()
+        .get(ARRAY_DIMENSIONS_ATTRIBUTE)?
+        .as_array()?
+        .iter()
+        .map(|name| match name {
+            serde_json::Value::String(name) => Some(DimensionName::new(name)),
+            serde_json::Value::Null => Some(DimensionName::default()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (dimension_names.len() == array.dimensionality()).then_some(dimension_names)
+}
+
+/// Convert an `array_builder` derived from the Zarr V2 `array` to create a Zarr V3 array.
+///
+/// The `_ARRAY_DIMENSIONS` attribute is removed and becomes the dimension names (unless they are already set), and the `v2` chunk key encoding is replaced by the `default` chunk key encoding with a `/` separator (unless the separator is set in `encoding_args`).
+/// The builder is unchanged if `array` is a Zarr V3 array.
+pub fn array_builder_v2_to_v3<TStorage: ?Sized>(
+    array: &Array<TStorage>,
+    array_builder: &mut ArrayBuilder,
+    encoding_args: &ZarrReencodingArgs,
+) {
+    if !matches!(array.metadata(), ArrayMetadata::V2(_)) {
+        return;
+    }
+    array_builder.attributes.remove(ARRAY_DIMENSIONS_ATTRIBUTE);
+    if array_builder.dimension_names.is_none() {
+        array_builder.dimension_names(
+            array_dimension_names(array)
+                .filter(|dimension_names| dimension_names.len() == array_builder.shape.len()),
+        );
+    }
+    if encoding_args.separator.is_none() {
+        array_builder.chunk_key_encoding_default_separator(ChunkKeySeparator::Slash);
+    }
+}
+
+/// Get an array builder for reencoding an array.
+///
+/// Data type dependent codecs of the input (e.g. the `delta`, `quantize`, `fixedscaleoffset`, and `shuffle` filters of a Zarr V2 array) are translated if the data type is changed or the input is a Zarr V2 array.
+///
+/// # Errors
+/// Returns a [`ArrayBuilderReencodeError`] if the data type is changed and the fill value or a codec cannot be converted, the dimension names, chunk grid, or shard index codecs are invalid, or the chunk or shard shape does not match the dimensionality of the array shape.
 pub fn get_array_builder_reencode<TStorage: ?Sized>(
     encoding_args: &ZarrReencodingArgs,
     array: &Array<TStorage>,
     array_shape: Option<Vec<u64>>,
-) -> zarrs::array::ArrayBuilder {
+) -> Result<zarrs::array::ArrayBuilder, ArrayBuilderReencodeError> {
     let array_to_bytes_metadata = array
         .codecs()
         .array_to_bytes_codec()
@@ -403,9 +1218,10 @@ pub fn get_array_builder_reencode<TStorage: ?Sized>(
     let (
         chunk_shape,
         shard_shape,
-        array_to_array_codecs,
+        shard_index,
+        mut array_to_array_codecs,
         array_array_to_bytes_codec,
-        bytes_to_bytes_codecs,
+        mut bytes_to_bytes_codecs,
     ) = if array_to_bytes_metadata.name() == "sharding_indexed" {
         let sharding_configuration = array_to_bytes_metadata.configuration().unwrap();
         // println!("{sharding_configuration:#?}");
@@ -423,15 +1239,25 @@ pub fn get_array_builder_reencode<TStorage: ?Sized>(
         let array_to_array_codecs = codec_chain.array_to_array_codecs().to_vec();
         let array_to_bytes_codec = codec_chain.array_to_bytes_codec().clone();
         let bytes_to_bytes_codecs = codec_chain.bytes_to_bytes_codecs().to_vec();
+        let index_location: ShardingIndexLocation =
+            serde_json::from_value(sharding_configuration["index_location"].clone())
+                .unwrap_or_default();
+        let index_codecs: Vec<MetadataV3> =
+            serde_json::from_value(sharding_configuration["index_codecs"].clone()).unwrap();
+        let index_codecs = Arc::new(CodecChain::from_metadata(&index_codecs).unwrap());
         (
             chunk_shape,
             Some(shard_shape),
+            Some((index_location, index_codecs)),
             array_to_array_codecs,
             array_to_bytes_codec,
             bytes_to_bytes_codecs,
         )
     } else {
-        let chunk_shape = array.chunk_grid_shape().unwrap().to_vec();
+        let chunk_shape = array
+            .chunk_shape(&vec![0; array.dimensionality()])
+            .unwrap()
+            .to_array_shape();
         let shard_shape = None;
         let array_to_array_codecs = array.codecs().array_to_array_codecs().to_vec();
         let array_to_bytes_codec = array.codecs().array_to_bytes_codec().clone();
@@ -439,46 +1265,85 @@ pub fn get_array_builder_reencode<TStorage: ?Sized>(
         (
             chunk_shape,
             shard_shape,
+            None,
             array_to_array_codecs,
             array_to_bytes_codec,
             bytes_to_bytes_codecs,
         )
     };
 
-    // Chunk shape override
+    // Translate data type dependent input codecs if the data type is changed or the input is a Zarr V2 array (e.g. with filters)
+    let data_type = match &encoding_args.data_type {
+        Some(data_type) => DataType::from_metadata(data_type).unwrap(),
+        None => array.data_type().clone(),
+    };
+    if &data_type != array.data_type() || matches!(array.metadata(), ArrayMetadata::V2(_)) {
+        translate_codecs(
+            &mut array_to_array_codecs,
+            &mut bytes_to_bytes_codecs,
+            array.data_type(),
+            &data_type,
+        )?;
+    }
+
+    // Retain a variable (e.g. rectangular) input chunk grid if the array, chunk, and shard shape are unchanged
+    let retain_chunk_grid = array.chunk_grid().create_metadata().name() != "regular"
+        && array_shape
+            .as_deref()
+            .map_or(true, |shape| shape == array.shape())
+        && encoding_args.chunk_shape.is_none()
+        && encoding_args.shard_shape.is_none();
+
+    // Chunk and shard shape overrides
+    // Resolve the auto/full dimensions for the output array shape
+    let output_shape = array_shape.as_deref().unwrap_or(array.shape());
+    if let Some(chunk_shape) = &encoding_args.chunk_shape {
+        check_chunk_shape_dimensionality("chunk", chunk_shape, output_shape)?;
+    }
+    if let Some(shard_shape) = &encoding_args.shard_shape {
+        check_chunk_shape_dimensionality("shard", shard_shape, output_shape)?;
+    }
+    let element_size = if has_auto_size(
+        encoding_args.chunk_shape.as_deref().unwrap_or_default(),
+        encoding_args.shard_shape.as_deref(),
+    ) {
+        let data_type = match &encoding_args.data_type {
+            Some(data_type) => DataType::from_metadata(data_type).unwrap(),
+            None => array.data_type().clone(),
+        };
+        data_type
+            .fixed_size()
+            .ok_or_else(|| ArrayBuilderReencodeError::AutoChunkSize(data_type.to_string()))?
+    } else {
+        1
+    };
     let chunk_shape = encoding_args
         .chunk_shape
         .as_ref()
         .map(|chunk_shape| {
-            std::iter::zip(chunk_shape.as_slice(), array.shape())
-                .map(|(&c, &a)| if c == 0 { a } else { c })
-                .collect::<Vec<_>>()
+            resolve_chunk_shape(
+                chunk_shape,
+                output_shape,
+                element_size,
+                AUTO_CHUNK_SIZE_DEFAULT,
+            )
         })
         .unwrap_or(chunk_shape);
-
-    // Shard shape override
-    // Set the shard shape to the array shape where it is 0, otherwise make it <= array shape
-    let shard_shape: Option<Vec<u64>> =
-        encoding_args
-            .shard_shape
-            .as_ref()
-            .map_or(shard_shape, |shard_shape| {
-                let shard_shape = std::iter::zip(shard_shape, array.shape())
-                    .map(|(&s, &a)| if s == 0 { a } else { std::cmp::min(s, a) })
-                    .collect::<Vec<_>>();
-                Some(shard_shape)
-            });
-
-    // Ensure shard shape is a multiple of the chunk shape
-    let shard_shape: Option<Vec<u64>> = shard_shape.clone().map_or(shard_shape, |shard_shape| {
-        let shard_shape = std::iter::zip(shard_shape.as_slice(), chunk_shape.as_slice())
-            .map(|(s, c)| {
-                // the shard shape must be a multiple of the chunk shape
-                s.next_multiple_of(*c)
-            })
-            .collect::<Vec<_>>();
-        Some(shard_shape)
-    });
+    let shard_shape: Option<Vec<u64>> = match &encoding_args.shard_shape {
+        Some(shard_shape) => Some(resolve_shard_shape(
+            shard_shape,
+            output_shape,
+            &chunk_shape,
+            element_size,
+            AUTO_SHARD_SIZE_DEFAULT,
+        )),
+        None => shard_shape.map(|shard_shape| {
+            // The input shard shape must be a multiple of the chunk shape
+            std::iter::zip(shard_shape.as_slice(), chunk_shape.as_slice())
+                .map(|(s, c)| s.next_multiple_of(*c))
+                .collect()
+        }),
+    };
 
     // println!("{chunk_shape:?} {shard_shape:?}");
 
@@ -543,6 +1408,10 @@ pub fn get_array_builder_reencode<TStorage: ?Sized>(
         array_builder.attributes.append(&mut attributes_append);
     }
 
+    if let Some(attributes_remove) = &encoding_args.attributes_remove {
+        remove_attributes(&mut array_builder.attributes, attributes_remove);
+    }
+
     if let Some(separator) = encoding_args.separator {
         array_builder.chunk_key_encoding_default_separator(separator.try_into().unwrap());
     }
@@ -556,9 +1425,11 @@ pub fn get_array_builder_reencode<TStorage: ?Sized>(
         array_builder.data_type(data_type.clone());
     }
 
-    if let Some(dimension_names) = encoding_args.dimension_names.clone() {
-        // TODO: Remove clone with zarrs 0.15.1+
-        array_builder.dimension_names(dimension_names.into());
+    if let Some(dimension_names) = encoding_args.output_dimension_names(
+        array_dimension_names(array).as_deref(),
+        array_builder.shape.len(),
+    )? {
+        array_builder.dimension_names(Some(dimension_names));
     }
 
     if let Some(fill_value) = &encoding_args.fill_value {
@@ -571,17 +1442,35 @@ pub fn get_array_builder_reencode<TStorage: ?Sized>(
     } else if let Some(data_type) = &encoding_args.data_type {
         // The data type was changed, but no fill value supplied, so just cast it
         let data_type = DataType::from_metadata(data_type).unwrap();
-        let fill_value = convert_fill_value(array.data_type(), array.fill_value(), &data_type);
+        let fill_value = convert_fill_value(
+            array.data_type(),
+            array.fill_value(),
+            &data_type,
+            encoding_args.fill_value_cast.unwrap_or_default(),
+        )?;
         array_builder.fill_value(fill_value);
     }
 
-    if let Some(shard_shape) = shard_shape {
-        array_builder.chunk_grid(shard_shape.try_into().unwrap());
-        let index_codecs = Arc::new(CodecChain::new(
-            vec![],
-            Arc::<BytesCodec>::default(),
-            vec![Arc::new(Crc32cCodec::new())],
-        ));
+    if let Some(chunk_grid) = &encoding_args.chunk_grid {
+        array_builder.chunk_grid(
+            get_chunk_grid(chunk_grid)
+                .map_err(|err| ArrayBuilderReencodeError::InvalidChunkGrid(err.to_string()))?,
+        );
+        array_builder.array_to_array_codecs(array_to_array_codecs);
+        array_builder.array_to_bytes_codec(array_to_bytes_codec);
+        array_builder.bytes_to_bytes_codecs(bytes_to_bytes_codecs);
+    } else if let Some(shard_shape) = shard_shape {
+        if !retain_chunk_grid {
+            array_builder.chunk_grid(shard_shape.try_into().unwrap());
+        }
+        let (index_location, index_codecs) = shard_index
+            .unwrap_or_else(|| (ShardingIndexLocation::End, default_shard_index_codecs()));
+        let index_location = encoding_args.shard_index_location.unwrap_or(index_location);
+        let index_codecs = if encoding_args.shard_index_codecs.is_some() {
+            get_shard_index_codecs(encoding_args.shard_index_codecs.as_deref())?
+        } else {
+            index_codecs
+        };
         let inner_codecs = Arc::new(CodecChain::new(
             array_to_array_codecs,
             array_to_bytes_codec,
@@ -592,16 +1481,19 @@ pub fn get_array_builder_reencode<TStorage: ?Sized>(
             chunk_shape.try_into().unwrap(),
             inner_codecs,
             index_codecs,
-            sharding::ShardingIndexLocation::End,
+            index_location,
         )));
         array_builder.bytes_to_bytes_codecs(vec![]);
     } else {
+        if !retain_chunk_grid {
+            array_builder.chunk_grid(chunk_shape.try_into().unwrap());
+        }
         array_builder.array_to_array_codecs(array_to_array_codecs);
         array_builder.array_to_bytes_codec(array_to_bytes_codec);
         array_builder.bytes_to_bytes_codecs(bytes_to_bytes_codecs);
     }
 
-    array_builder
+    Ok(array_builder)
 }
 
 pub enum CacheSize {
@@ -612,25 +1504,609 @@ pub enum CacheSize {
     ChunksPerThread(u64),
 }
 
+/// Decoded chunk cache arguments.
+#[derive(Parser, Debug, Clone, Default)]
+pub struct ChunkCacheArgs {
+    /// An optional chunk cache size (in bytes).
+    #[arg(long)]
+    pub cache_size: Option<u64>,
+
+    /// An optional chunk cache size (in chunks).
+    #[arg(long)]
+    pub cache_chunks: Option<u64>,
+
+    /// An optional per-thread chunk cache size (in bytes).
+    #[arg(long)]
+    pub cache_size_thread: Option<u64>,
+
+    /// An optional per-thread chunk cache size (in chunks).
+    #[arg(long)]
+    pub cache_chunks_thread: Option<u64>,
+}
+
+impl ChunkCacheArgs {
+    /// Returns the [`CacheSize`] of the arguments.
+    ///
+    /// Per-thread sizes take precedence over total sizes, and sizes in bytes take precedence over sizes in chunks.
+    #[must_use]
+    pub fn cache_size(&self) -> CacheSize {
+        if let Some(cache_size_thread) = self.cache_size_thread {
+            CacheSize::SizePerThread(cache_size_thread)
+        } else if let Some(cache_size) = self.cache_size {
+            CacheSize::SizeTotal(cache_size)
+        } else if let Some(cache_chunks_thread) = self.cache_chunks_thread {
+            CacheSize::ChunksPerThread(cache_chunks_thread)
+        } else if let Some(cache_chunks) = self.cache_chunks {
+            CacheSize::ChunksTotal(cache_chunks)
+        } else {
+            CacheSize::None
+        }
+    }
+}
+
+/// Thread pool arguments.
+#[derive(Parser, Debug, Clone, Default)]
+pub struct ThreadsArgs {
+    /// The number of threads.
+    ///
+    /// This limits the size of the thread pool and the concurrency targets of chunk and codec operations.
+    /// Defaults to the RAYON_NUM_THREADS environment variable or the number of logical CPUs.
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub threads: Option<usize>,
+}
+
+impl ThreadsArgs {
+    /// Run `op` in a thread pool with the number of threads, which is also the `zarrs` codec concurrency target while `op` runs.
+    ///
+    /// `op` runs in the global thread pool if the number of threads is unset.
+    ///
+    /// # Errors
+    /// Returns a [`rayon::ThreadPoolBuildError`] if the thread pool cannot be built, or the error returned by `op`.
+    pub fn install<T, E>(&self, op: impl FnOnce() -> Result<T, E> + Send) -> Result<T, E>
+    where
+        T: Send,
+        E: From<rayon::ThreadPoolBuildError> + Send,
+    {
+        let Some(threads) = self.threads else {
+            return op();
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()?;
+        let codec_concurrent_target = zarrs::config::global_config().codec_concurrent_target();
+        zarrs::config::global_config_mut().set_codec_concurrent_target(threads);
+        let result = pool.install(op);
+        zarrs::config::global_config_mut().set_codec_concurrent_target(codec_concurrent_target);
+        result
+    }
+}
+
+/// Storage rate limit arguments.
+///
+/// Limits apply to each store independently (e.g. the input and output stores).
+#[derive(Parser, Debug, Clone, Default)]
+pub struct RateLimitArgs {
+    /// The maximum read throughput (MB/s) of the input store.
+    #[arg(long, value_parser = parse_positive_f64)]
+    pub max_read_mbps: Option<f64>,
+    /// The maximum write throughput (MB/s) of the output store.
+    #[arg(long, value_parser = parse_positive_f64)]
+    pub max_write_mbps: Option<f64>,
+    /// The maximum number of storage requests per second of each store.
+    #[arg(long, value_parser = parse_positive_f64)]
+    pub max_requests_per_sec: Option<f64>,
+}
+
+impl RateLimitArgs {
+    /// Returns true if no limits are set.
+    #[must_use]
+    pub fn is_unlimited(&self) -> bool {
+        self.max_read_mbps.is_none()
+            && self.max_write_mbps.is_none()
+            && self.max_requests_per_sec.is_none()
+    }
+
+    /// Limit the reads and requests of an input store.
+    pub fn input<TStorage: ?Sized>(
+        &self,
+        storage: Arc<TStorage>,
+    ) -> rate_limit::RateLimitStorageAdapter<TStorage> {
+        rate_limit::RateLimitStorageAdapter::new(
+            storage,
+            self.max_read_mbps.map(|mbps| mbps * 1e6),
+            None,
+            self.max_requests_per_sec,
+        )
+    }
+
+    /// Limit the writes and requests of an output store.
+    pub fn output<TStorage: ?Sized>(
+        &self,
+        storage: Arc<TStorage>,
+    ) -> rate_limit::RateLimitStorageAdapter<TStorage> {
+        rate_limit::RateLimitStorageAdapter::new(
+            storage,
+            None,
+            self.max_write_mbps.map(|mbps| mbps * 1e6),
+            self.max_requests_per_sec,
+        )
+    }
+}
+
+/// On-disk read cache arguments.
+#[derive(Parser, Debug, Clone, Default)]
+pub struct ReadCacheArgs {
+    /// A directory for caching the values read from remote (http/https) inputs on disk.
+    ///
+    /// Repeated runs over the same remote input (e.g. comparing codecs) read cached chunks rather than downloading them again.
+    /// Cached values are keyed by the input, the store key, and the byte ranges.
+    /// Cached values are not revalidated against the input, so use a new cache directory if a remote input changes.
+    /// Only cache entries (files named by a 64 character hex digest) in the directory are managed, other files are left untouched.
+    /// Local inputs are not cached.
+    #[arg(long)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// The maximum size of the --cache-dir cache (e.g. 10GB), evicting the least recently used values. Unlimited if unset.
+    #[arg(long, value_parser = parse_size, requires = "cache_dir")]
+    pub cache_dir_max_size: Option<u64>,
+}
+
+impl ReadCacheArgs {
+    /// Cache the reads of `storage` in the cache directory, if set.
+    ///
+    /// `identifier` distinguishes stores sharing a cache directory (e.g. the URL of the store).
+    ///
+    /// # Errors
+    /// Returns a [`std::io::Error`] if the cache directory cannot be created or read.
+    pub fn cache(
+        &self,
+        storage: ReadableListableStorage,
+        identifier: &str,
+    ) -> std::io::Result<ReadableListableStorage> {
+        if let Some(cache_dir) = &self.cache_dir {
+            Ok(Arc::new(read_cache::ReadCacheStorageAdapter::new(
+                storage,
+                cache_dir,
+                identifier,
+                self.cache_dir_max_size,
+            )?))
+        } else {
+            Ok(storage)
+        }
+    }
+}
+
+fn parse_positive_f64(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!("{value} is not a positive number")),
+    }
+}
+
+/// Write shape arguments.
+#[derive(Parser, Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct WriteShapeArgs {
+    /// Write shape (optional). A comma separated list of the write size along each array dimension.
+    ///
+    /// Use this parameter to incrementally write shards in batches of chunks of the specified write shape.
+    /// The write shape defaults to the shard shape for sharded arrays.
+    /// This parameter is ignored for unsharded arrays (the write shape is the chunk shape).
+    ///
+    /// Prefer to set the write shape to an integer multiple of the chunk shape to avoid unnecessary reads.
+    ///
+    #[arg(long, verbatim_doc_comment, value_delimiter = ',')]
+    #[serde(default)]
+    pub write_shape: Option<Vec<NonZeroU64>>,
+}
+
+impl WriteShapeArgs {
+    /// Returns the write shape of `array`, which is [`None`] if unset or `array` is unsharded.
+    ///
+    /// # Errors
+    /// Returns an [`ArrayError`] if the write shape dimensionality does not match `array`.
+    pub fn write_shape<TStorage: ?Sized>(
+        &self,
+        array: &Array<TStorage>,
+    ) -> Result<Option<&[NonZeroU64]>, ArrayError> {
+        match &self.write_shape {
+            Some(write_shape) if array.is_sharded() => {
+                if write_shape.len() == array.dimensionality() {
+                    Ok(Some(write_shape))
+                } else {
+                    Err(IncompatibleDimensionalityError::new(
+                        write_shape.len(),
+                        array.dimensionality(),
+                    )
+                    .into())
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the subsets written to `array`, in chunk order.
+    ///
+    /// Each chunk (or shard) is split into subsets of the write shape if `array` is sharded and the write shape is set.
+    /// Subsets are bounded by the array shape.
+    ///
+    /// # Errors
+    /// Returns an [`ArrayError`] if the write shape dimensionality does not match `array`.
+    pub fn write_subsets<TStorage: ?Sized>(
+        &self,
+        array: &Array<TStorage>,
+    ) -> Result<Vec<ArraySubset>, ArrayError> {
+        let write_shape = self.write_shape(array)?;
+        let chunks = ArraySubset::new_with_shape(array.chunk_grid_shape().unwrap());
+        let mut subsets = Vec::with_capacity(chunks.num_elements_usize());
+        for chunk_indices in &chunks.indices() {
+            let chunk_subset = array.chunk_subset_bounded(&chunk_indices)?;
+            if let Some(write_shape) = write_shape {
+                for (_, subset) in &chunk_subset.chunks(write_shape)? {
+                    subsets.push(subset.overlap(&chunk_subset)?);
+                }
+            } else {
+                subsets.push(chunk_subset);
+            }
+        }
+        Ok(subsets)
+    }
+
+    /// Returns the codec options for writing subsets to `array`.
+    ///
+    /// Partial encoding is enabled if subsets are smaller than a shard, so that shards are not held in memory.
+    #[must_use]
+    pub fn codec_options<TStorage: ?Sized>(&self, array: &Array<TStorage>) -> CodecOptions {
+        let partial_encoding = matches!(self.write_shape(array), Ok(Some(_)));
+        CodecOptionsBuilder::new()
+            .experimental_partial_encoding(partial_encoding)
+            .build()
+    }
+}
+
+/// Returns the concurrency target of chunk and codec operations.
+///
+/// This is the number of threads of the current thread pool (see [`ThreadsArgs`]).
+#[must_use]
+pub fn concurrent_target() -> usize {
+    rayon::current_num_threads()
+}
+
 pub enum Cache {
-    SizeDefault(ChunkCacheDecodedLruSizeLimit),
-    SizeThreadLocal(ChunkCacheDecodedLruSizeLimitThreadLocal),
-    ChunksDefault(ChunkCacheDecodedLruChunkLimit),
-    ChunksThreadLocal(ChunkCacheDecodedLruChunkLimitThreadLocal),
+    SizeDefault(ChunkCacheCounted<ChunkCacheDecodedLruSizeLimit>),
+    SizeThreadLocal(Box<ChunkCacheCounted<ChunkCacheDecodedLruSizeLimitThreadLocal>>),
+    ChunksDefault(ChunkCacheCounted<ChunkCacheDecodedLruChunkLimit>),
+    ChunksThreadLocal(Box<ChunkCacheCounted<ChunkCacheDecodedLruChunkLimitThreadLocal>>),
 }
 
+impl Cache {
+    /// Create a cache of size `cache_size`, or [`None`] if [`CacheSize::None`].
+    #[must_use]
+    pub fn new(cache_size: CacheSize) -> Option<Self> {
+        match cache_size {
+            CacheSize::None => None,
+            CacheSize::SizeTotal(size) => Some(Self::SizeDefault(ChunkCacheCounted::new(
+                ChunkCacheDecodedLruSizeLimit::new(size),
+            ))),
+            CacheSize::SizePerThread(size) => Some(Self::SizeThreadLocal(Box::new(
+                ChunkCacheCounted::new(ChunkCacheDecodedLruSizeLimitThreadLocal::new(size)),
+            ))),
+            CacheSize::ChunksTotal(chunks) => Some(Self::ChunksDefault(ChunkCacheCounted::new(
+                ChunkCacheDecodedLruChunkLimit::new(chunks),
+            ))),
+            CacheSize::ChunksPerThread(chunks) => Some(Self::ChunksThreadLocal(Box::new(
+                ChunkCacheCounted::new(ChunkCacheDecodedLruChunkLimitThreadLocal::new(chunks)),
+            ))),
+        }
+    }
+
+    /// Returns true if the cache is per-thread.
+    #[must_use]
+    pub fn is_thread_local(&self) -> bool {
+        matches!(
+            self,
+            Cache::SizeThreadLocal(_) | Cache::ChunksThreadLocal(_)
+        )
+    }
+
+    /// Retrieve and decode the chunks of `array` intersecting `array_subset` into the cache, with up to `concurrency` chunks retrieved concurrently.
+    ///
+    /// This coalesces the chunk reads of a subset spanning many chunks (e.g. an output shard when reencoding) into concurrent requests, which suits high latency stores (e.g. HTTP or S3).
+    /// Chunks are evicted before they are used if the decoded subset exceeds the cache size.
+    ///
+    /// # Errors
+    /// Returns an [`ArrayError`] if a chunk cannot be retrieved or decoded.
+    pub fn prefetch_array_subset<TStorage: ?Sized + ReadableStorageTraits + 'static>(
+        &self,
+        array: &Array<TStorage>,
+        array_subset: &ArraySubset,
+        concurrency: usize,
+        options: &CodecOptions,
+    ) -> Result<(), ArrayError> {
+        let Some(chunks) = array.chunks_in_array_subset(array_subset)? else {
+            return Ok(());
+        };
+        let indices = chunks.indices();
+        iter_concurrent_limit!(
+            concurrency.max(1),
+            indices,
+            try_for_each,
+            |chunk_indices: Vec<u64>| {
+                self.retrieve_chunk_opt(array, &chunk_indices, options)
+                    .map(|_| ())
+            }
+        )
+    }
+
+    /// Retrieve and decode the chunk at `chunk_indices` of `array` through the cache.
+    ///
+    /// # Errors
+    /// Returns an [`ArrayError`] if the chunk cannot be retrieved or decoded.
+    pub fn retrieve_chunk_opt<TStorage: ?Sized + ReadableStorageTraits + 'static>(
+        &self,
+        array: &Array<TStorage>,
+        chunk_indices: &[u64],
+        options: &CodecOptions,
+    ) -> Result<Arc<ArrayBytes<'static>>, ArrayError> {
+        match self {
+            Cache::SizeDefault(cache) => cache.retrieve_chunk(array, chunk_indices, options),
+            Cache::SizeThreadLocal(cache) => cache.retrieve_chunk(array, chunk_indices, options),
+            Cache::ChunksDefault(cache) => cache.retrieve_chunk(array, chunk_indices, options),
+            Cache::ChunksThreadLocal(cache) => cache.retrieve_chunk(array, chunk_indices, options),
+        }
+    }
+
+    /// Asynchronously retrieve and decode the chunk at `chunk_indices` of `array` through the cache.
+    ///
+    /// # Errors
+    /// Returns an [`ArrayError`] if the chunk cannot be retrieved or decoded.
+    #[cfg(feature = "async")]
+    pub async fn async_retrieve_chunk_opt<
+        TStorage: ?Sized + zarrs::storage::AsyncReadableStorageTraits + 'static,
+    >(
+        &self,
+        array: &Array<TStorage>,
+        chunk_indices: &[u64],
+        options: &CodecOptions,
+    ) -> Result<Arc<ArrayBytes<'static>>, ArrayError> {
+        match self {
+            Cache::SizeDefault(cache) => {
+                cache
+                    .async_retrieve_chunk(array, chunk_indices, options)
+                    .await
+            }
+            Cache::SizeThreadLocal(cache) => {
+                cache
+                    .async_retrieve_chunk(array, chunk_indices, options)
+                    .await
+            }
+            Cache::ChunksDefault(cache) => {
+                cache
+                    .async_retrieve_chunk(array, chunk_indices, options)
+                    .await
+            }
+            Cache::ChunksThreadLocal(cache) => {
+                cache
+                    .async_retrieve_chunk(array, chunk_indices, options)
+                    .await
+            }
+        }
+    }
+
+    /// Returns the number of chunk requests and cache misses.
+    pub fn requests_misses(&self) -> (usize, usize) {
+        match self {
+            Cache::SizeDefault(cache) => cache.requests_misses(),
+            Cache::SizeThreadLocal(cache) => cache.requests_misses(),
+            Cache::ChunksDefault(cache) => cache.requests_misses(),
+            Cache::ChunksThreadLocal(cache) => cache.requests_misses(),
+        }
+    }
+}
+
+/// A decoded chunk cache wrapper that counts chunk requests and cache misses.
+pub struct ChunkCacheCounted<TCache> {
+    cache: TCache,
+    requests: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl<TCache> ChunkCacheCounted<TCache> {
+    pub fn new(cache: TCache) -> Self {
+        Self {
+            cache,
+            requests: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of chunk requests and cache misses.
+    pub fn requests_misses(&self) -> (usize, usize) {
+        (
+            self.requests.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(feature = "async")]
+impl<TCache: ChunkCache<ChunkCacheTypeDecoded>> ChunkCacheCounted<TCache> {
+    /// Asynchronously retrieve and decode the chunk at `chunk_indices` of `array` through the cache.
+    ///
+    /// Unlike [`ChunkCache::retrieve_chunk`], concurrent requests for the same uncached chunk may each retrieve it.
+    ///
+    /// # Errors
+    /// Returns an [`ArrayError`] if the chunk cannot be retrieved or decoded.
+    pub async fn async_retrieve_chunk<
+        TStorage: ?Sized + zarrs::storage::AsyncReadableStorageTraits + 'static,
+    >(
+        &self,
+        array: &Array<TStorage>,
+        chunk_indices: &[u64],
+        options: &CodecOptions,
+    ) -> Result<Arc<ArrayBytes<'static>>, ArrayError> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if let Some(chunk) = self.cache.get(chunk_indices) {
+            return Ok(chunk);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let chunk = Arc::new(
+            array
+                .async_retrieve_chunk_opt(chunk_indices, options)
+                .await?
+                .into_owned(),
+        );
+        self.cache.insert(chunk_indices.to_vec(), chunk.clone());
+        Ok(chunk)
+    }
+}
+
+impl<TCache: ChunkCache<ChunkCacheTypeDecoded>> ChunkCache<ChunkCacheTypeDecoded>
+    for ChunkCacheCounted<TCache>
+{
+    fn retrieve_chunk<TStorage: ?Sized + ReadableStorageTraits + 'static>(
+        &self,
+        array: &Array<TStorage>,
+        chunk_indices: &[u64],
+        options: &CodecOptions,
+    ) -> Result<Arc<ArrayBytes<'static>>, ArrayError> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.cache
+            .try_get_or_insert_with::<_, ArrayError>(chunk_indices.to_vec(), || {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Ok(Arc::new(
+                    array
+                        .retrieve_chunk_opt(chunk_indices, options)?
+                        .into_owned(),
+                ))
+            })
+            .map_err(|err| {
+                // ArrayError is not cloneable
+                Arc::try_unwrap(err).unwrap_or_else(|err| {
+                    ArrayError::StorageError(StorageError::from(err.to_string()))
+                })
+            })
+    }
+
+    fn get(&self, chunk_indices: &[u64]) -> Option<Arc<ChunkCacheTypeDecoded>> {
+        self.cache.get(chunk_indices)
+    }
+
+    fn insert(&self, chunk_indices: Vec<u64>, chunk: Arc<ChunkCacheTypeDecoded>) {
+        self.cache.insert(chunk_indices, chunk);
+    }
+
+    fn try_get_or_insert_with<F, E>(
+        &self,
+        chunk_indices: Vec<u64>,
+        f: F,
+    ) -> Result<Arc<ChunkCacheTypeDecoded>, Arc<ArrayError>>
+    where
+        F: FnOnce() -> Result<Arc<ChunkCacheTypeDecoded>, ArrayError>,
+    {
+        self.cache.try_get_or_insert_with::<F, E>(chunk_indices, f)
+    }
+
+    fn len(&self) -> usize {
+        self.cache.len()
+    }
+}
+
+/// Statistics returned by [`do_reencode`].
+#[derive(Debug, Clone, Default)]
+pub struct ReencodeStats {
+    /// The total duration (s).
+    pub duration: f32,
+    /// The approximate read duration (s).
+    pub duration_read: f32,
+    /// The approximate write duration (s).
+    pub duration_write: f32,
+    /// The number of decoded bytes.
+    pub bytes_decoded: usize,
+    /// The number of output chunks (or shards if sharded).
+    pub num_chunks: usize,
+    /// The number of write operations, which may exceed `num_chunks` if a write shape is set.
+    pub num_writes: usize,
+    /// The number of chunk cache requests and misses, if a chunk cache is used.
+    pub cache_requests_misses: Option<(usize, usize)>,
+    /// The error of the output relative to the input, if computed.
+    pub error_metrics: Option<ErrorMetrics>,
+}
+
+/// The validation of data written by [`do_reencode`].
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReencodeValidation {
+    /// Do not validate written data.
+    #[default]
+    Off,
+    /// Compare written data to the data read back from the output (a storage round trip).
+    RoundTrip,
+    /// Additionally compare written data to an independent (uncached) read of the input.
+    ///
+    /// This catches chunk cache and conversion errors, but decodes the input twice.
+    Input,
+}
+
+/// Return the overlap of `subset` and `subset_other`, or [`None`] if they do not intersect.
+///
+/// Unlike [`ArraySubset::overlap`], this does not overflow if the subsets are disjoint.
+fn subset_intersection(
+    subset: &ArraySubset,
+    subset_other: &ArraySubset,
+) -> anyhow::Result<Option<ArraySubset>> {
+    if subset.dimensionality() != subset_other.dimensionality() {
+        Err(IncompatibleDimensionalityError::new(
+            subset_other.dimensionality(),
+            subset.dimensionality(),
+        ))?;
+    }
+    let disjoint = std::iter::zip(subset.start(), subset.end_exc())
+        .zip(std::iter::zip(subset_other.start(), subset_other.end_exc()))
+        .any(|((start, end), (other_start, other_end))| start >= &other_end || other_start >= &end);
+    if disjoint {
+        Ok(None)
+    } else {
+        let subset = subset.overlap(subset_other)?;
+        Ok((subset.num_elements() > 0).then_some(subset))
+    }
+}
+
+/// Reencode `array_in` to `array_out`.
+///
+/// If the array shape differs, the output is cropped or padded with its fill value at the high end of each dimension.
+///
+/// If `error_tolerance` is set, the [`ErrorMetrics`] of the output relative to the input (e.g. introduced by a lossy codec) are computed by reading back each written subset.
+/// The reencode fails at the first subset that exceeds the tolerance.
+///
+/// If `journal` is set, each completed output chunk is recorded in the journal and chunks completed by a previous run are skipped.
+///
+/// # Errors
+/// Returns an error if the arrays have a different dimensionality or data type, a subset cannot be read or written, validation fails, the error tolerance is exceeded, or the journal cannot be written.
+#[allow(clippy::too_many_arguments)]
 pub fn do_reencode<
     TStorageIn: ReadableStorageTraits + ?Sized + 'static,
     TStorageOut: ReadableWritableStorageTraits + ?Sized + 'static,
 >(
     array_in: &Array<TStorageIn>,
     array_out: &Array<TStorageOut>,
-    validate: bool,
+    validate: ReencodeValidation,
     concurrent_chunks: Option<usize>,
     progress_callback: &ProgressCallback,
     cache_size: CacheSize,
     write_shape: Option<Vec<NonZeroU64>>,
-) -> anyhow::Result<(f32, f32, f32, usize)> {
+    prefetch_concurrency: Option<usize>,
+    error_tolerance: Option<ErrorTolerance>,
+    journal: Option<&ChunkJournal>,
+) -> anyhow::Result<ReencodeStats> {
+    if array_in.dimensionality() != array_out.dimensionality() {
+        anyhow::bail!("Input and output array dimensionality differ");
+    }
+    if error_tolerance.is_some() && !ErrorMetrics::supports_data_type(array_out.data_type()) {
+        anyhow::bail!(
+            "Error metrics do not support the data type {}",
+            array_out.data_type()
+        );
+    }
     if let Some(write_shape) = &write_shape {
         if write_shape.len() != array_out.chunk_grid().dimensionality() {
             anyhow::bail!("Write shape dimensionality does not match chunk grid dimensionality");
@@ -639,29 +2115,19 @@ pub fn do_reencode<
 
     let start = SystemTime::now();
     let bytes_decoded = Mutex::new(0);
+    let error_metrics = Mutex::new(ErrorMetrics::default());
 
-    let cache = match cache_size {
-        CacheSize::None => None,
-        CacheSize::SizeTotal(size) => {
-            Some(Cache::SizeDefault(ChunkCacheDecodedLruSizeLimit::new(size)))
-        }
-        CacheSize::SizePerThread(size) => Some(Cache::SizeThreadLocal(
-            ChunkCacheDecodedLruSizeLimitThreadLocal::new(size),
-        )),
-        CacheSize::ChunksTotal(chunks) => Some(Cache::ChunksDefault(
-            ChunkCacheDecodedLruChunkLimit::new(chunks),
-        )),
-        CacheSize::ChunksPerThread(chunks) => Some(Cache::ChunksThreadLocal(
-            ChunkCacheDecodedLruChunkLimitThreadLocal::new(chunks),
-        )),
-    };
+    let cache = Cache::new(cache_size);
+    if prefetch_concurrency.is_some() && cache.as_ref().map_or(true, Cache::is_thread_local) {
+        anyhow::bail!("Prefetching requires a shared chunk cache (--cache-size or --cache-chunks)");
+    }
 
     let chunk_representation = array_out
         .chunk_array_representation(&vec![0; array_out.chunk_grid().dimensionality()])
         .unwrap();
     let chunks = ArraySubset::new_with_shape(array_out.chunk_grid_shape().unwrap());
 
-    let concurrent_target = std::thread::available_parallelism().unwrap().get();
+    let concurrent_target = concurrent_target();
     let (chunks_concurrent_limit, codec_concurrent_target) = calculate_chunk_and_codec_concurrency(
         concurrent_target,
         concurrent_chunks,
@@ -694,7 +2160,11 @@ pub fn do_reencode<
         chunks.num_elements_usize()
     };
 
-    let progress = Progress::new(num_iterations, progress_callback);
+    let num_bytes = array_out
+        .data_type()
+        .fixed_size()
+        .map(|size| usize::try_from(array_out.shape().iter().product::<u64>()).unwrap() * size);
+    let progress = Progress::new_with_bytes(num_iterations, num_bytes, progress_callback);
 
     let retrieve_array_subset = |subset: &ArraySubset| {
         if let Some(cache) = &cache {
@@ -702,72 +2172,223 @@ pub fn do_reencode<
                 Cache::SizeDefault(cache) => {
                     array_in.retrieve_array_subset_opt_cached(cache, subset, &codec_options)
                 }
-                Cache::SizeThreadLocal(cache) => {
-                    array_in.retrieve_array_subset_opt_cached(cache, subset, &codec_options)
-                }
+                Cache::SizeThreadLocal(cache) => array_in.retrieve_array_subset_opt_cached(
+                    cache.as_ref(),
+                    subset,
+                    &codec_options,
+                ),
                 Cache::ChunksDefault(cache) => {
                     array_in.retrieve_array_subset_opt_cached(cache, subset, &codec_options)
                 }
-                Cache::ChunksThreadLocal(cache) => {
-                    array_in.retrieve_array_subset_opt_cached(cache, subset, &codec_options)
-                }
+                Cache::ChunksThreadLocal(cache) => array_in.retrieve_array_subset_opt_cached(
+                    cache.as_ref(),
+                    subset,
+                    &codec_options,
+                ),
             }
         } else {
             array_in.retrieve_array_subset_opt(subset, &codec_options)
         }
     };
 
+    // The in-flight bytes of a subset being decoded and encoded
+    let element_size = array_out
+        .data_type()
+        .fixed_size()
+        .unwrap_or(std::mem::size_of::<u64>());
+    let subset_bytes = |subset: &ArraySubset| subset.num_elements_usize() * element_size * 2;
+    let memory_budget = MemoryBudget::global();
+
+    // Compare the data written to a subset with the data read back from the output, and optionally an uncached read of the input
+    let validate_subset =
+        |subset: &ArraySubset, bytes: &ArrayBytes, bytes_out: &ArrayBytes| -> anyhow::Result<()> {
+            if bytes != bytes_out {
+                anyhow::bail!(
+                "Validation failed: the output subset {subset:?} does not match the written data"
+            );
+            }
+            if validate == ReencodeValidation::Input {
+                let bytes_in = array_in.retrieve_array_subset_opt(subset, &codec_options)?;
+                if &bytes_in != bytes_out {
+                    anyhow::bail!(
+                        "Validation failed: the output subset {subset:?} does not match the input"
+                    );
+                }
+            }
+            Ok(())
+        };
+
+    // Accumulate the error of the data read back from the output relative to the data written to a subset
+    let measure_subset = |subset: &ArraySubset,
+                          bytes: &ArrayBytes,
+                          bytes_out: &ArrayBytes|
+     -> anyhow::Result<()> {
+        let Some(error_tolerance) = &error_tolerance else {
+            return Ok(());
+        };
+        let subset_metrics = ErrorMetrics::from_bytes(array_out.data_type(), bytes, bytes_out)?;
+        if let Some(exceeded) = subset_metrics.exceeds(error_tolerance) {
+            anyhow::bail!("Error tolerance exceeded: {exceeded} in the output subset {subset:?}");
+        }
+        let mut error_metrics = error_metrics.lock().unwrap();
+        *error_metrics = error_metrics.merge(&subset_metrics);
+        Ok(())
+    };
+
+    // The written data is read back from the output if it is validated or its error is measured
+    let read_back = validate != ReencodeValidation::Off || error_tolerance.is_some();
+    let check_subset =
+        |subset: &ArraySubset, bytes: &ArrayBytes, bytes_out: &ArrayBytes| -> anyhow::Result<()> {
+            if validate != ReencodeValidation::Off {
+                validate_subset(subset, bytes, bytes_out)?;
+            }
+            measure_subset(subset, bytes, bytes_out)
+        };
+
+    // If the array shape changes, only the part of an output subset within the input is retrieved
+    let subset_in_all = array_in.subset_all();
+    let reshaped = array_in.shape() != array_out.shape();
+    let input_overlap = |subset: &ArraySubset| -> anyhow::Result<Option<ArraySubset>> {
+        if reshaped {
+            subset_intersection(subset, &subset_in_all)
+        } else {
+            Ok(Some(subset.clone()))
+        }
+    };
+
+    // Reencode a subset, which may not be aligned with the output chunks
+    let reencode_subset = |subset: &ArraySubset| -> anyhow::Result<()> {
+        let bytes = progress.read(|| retrieve_array_subset(subset))?;
+        *bytes_decoded.lock().unwrap() += bytes.size();
+        progress.add_bytes_read(bytes.size());
+        progress.add_bytes_written(bytes.size());
+        if read_back {
+            progress.write(|| {
+                array_out.store_array_subset_opt(subset, bytes.clone(), &codec_options)
+            })?;
+            let bytes_out = array_out.retrieve_array_subset_opt(subset, &codec_options)?;
+            check_subset(subset, &bytes, &bytes_out)?;
+        } else {
+            progress.write(|| array_out.store_array_subset_opt(subset, bytes, &codec_options))?;
+        }
+        Ok(())
+    };
+
     let indices = chunks.indices();
     if array_in.data_type() == array_out.data_type() {
-        iter_concurrent_limit!(
-            chunks_concurrent_limit,
-            indices,
-            try_for_each,
-            |chunk_indices: Vec<u64>| {
-                let chunk_subset = array_out.chunk_subset(&chunk_indices).unwrap();
-                if let Some(write_shape) = &write_shape {
-                    for (_, chunk_subset_write) in &chunk_subset.chunks(write_shape)? {
-                        let chunk_subset_write = chunk_subset_write.overlap(&chunk_subset)?;
-                        let bytes = progress.read(|| retrieve_array_subset(&chunk_subset_write))?;
-                        *bytes_decoded.lock().unwrap() += bytes.size();
-                        progress.write(|| {
-                            array_out.store_array_subset_opt(
-                                &chunk_subset_write,
-                                bytes,
-                                &codec_options,
-                            )
-                        })?;
-                        progress.next();
+        let reencode_chunk = |chunk_indices: &[u64]| {
+            let chunk_subset = array_out.chunk_subset(chunk_indices).unwrap();
+            if let (Some(cache), Some(prefetch_concurrency), Some(subset_in)) =
+                (&cache, prefetch_concurrency, input_overlap(&chunk_subset)?)
+            {
+                progress.read(|| {
+                    cache.prefetch_array_subset(
+                        array_in,
+                        &subset_in,
+                        prefetch_concurrency,
+                        &codec_options,
+                    )
+                })?;
+            }
+            if let Some(write_shape) = &write_shape {
+                for (_, chunk_subset_write) in &chunk_subset.chunks(write_shape)? {
+                    let chunk_subset_write = chunk_subset_write.overlap(&chunk_subset)?;
+                    if let Some(chunk_subset_write) = input_overlap(&chunk_subset_write)? {
+                        reencode_subset(&chunk_subset_write)?;
+                    }
+                    progress.next()?;
+                }
+            } else {
+                match input_overlap(&chunk_subset)? {
+                    Some(subset) if subset == chunk_subset => {}
+                    Some(subset) => {
+                        // The chunk is only partially within the input, the remainder is the fill value
+                        reencode_subset(&subset)?;
+                        progress.next()?;
+                        return Ok(());
                     }
+                    None => {
+                        progress.next()?;
+                        return Ok(());
+                    }
+                }
+                let bytes = progress.read(|| retrieve_array_subset(&chunk_subset))?;
+                *bytes_decoded.lock().unwrap() += bytes.size();
+                progress.add_bytes_read(bytes.size());
+                progress.add_bytes_written(bytes.size());
+
+                if read_back {
+                    progress.write(|| {
+                        array_out.store_chunk_opt(chunk_indices, bytes.clone(), &codec_options)
+                    })?;
+                    let bytes_out = array_out.retrieve_chunk_opt(chunk_indices, &codec_options)?;
+                    check_subset(&chunk_subset, &bytes, &bytes_out)?;
                 } else {
-                    let bytes = progress.read(|| retrieve_array_subset(&chunk_subset))?;
-                    *bytes_decoded.lock().unwrap() += bytes.size();
-
-                    if validate {
-                        progress.write(|| {
-                            array_out.store_chunk_opt(&chunk_indices, bytes.clone(), &codec_options)
-                        })?;
-                        let bytes_out = array_out
-                            .retrieve_chunk_opt(&chunk_indices, &codec_options)
-                            .unwrap();
-                        assert!(bytes == bytes_out);
-                        // let bytes_in = array_in
-                        //     .retrieve_array_subset_opt(&chunk_subset, &codec_options)
-                        //     .unwrap();
-                        // assert!(bytes_in == bytes_out);
+                    progress.write(|| {
+                        array_out.store_chunk_opt(chunk_indices, bytes, &codec_options)
+                    })?;
+                }
+                progress.next()?;
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+        // A chunk is admitted with the bytes of its largest subset in flight, write subsets of a chunk are reencoded in sequence
+        let chunk_bytes = |chunk_indices: &Vec<u64>| {
+            if journal.is_some_and(|journal| journal.is_completed(chunk_indices)) {
+                return 0;
+            }
+            let chunk_subset = array_out.chunk_subset(chunk_indices).unwrap();
+            let bytes = subset_bytes(&chunk_subset);
+            if let Some(write_shape) = &write_shape {
+                let write_elements = write_shape
+                    .iter()
+                    .map(|size| usize::try_from(size.get()).unwrap())
+                    .product::<usize>();
+                bytes.min(write_elements * element_size * 2)
+            } else {
+                bytes
+            }
+        };
+        memory_budget.try_for_each(
+            chunks_concurrent_limit,
+            &indices,
+            chunk_bytes,
+            |chunk_indices: Vec<u64>| {
+                let Some(journal) = journal else {
+                    return reencode_chunk(&chunk_indices);
+                };
+                if journal.is_completed(&chunk_indices) {
+                    // Skip a chunk completed by a previous run
+                    let num_iterations = if let Some(write_shape) = &write_shape {
+                        array_out
+                            .chunk_subset(&chunk_indices)?
+                            .chunks(write_shape)?
+                            .len()
                     } else {
-                        progress.write(|| {
-                            array_out.store_chunk_opt(&chunk_indices, bytes, &codec_options)
-                        })?;
+                        1
+                    };
+                    for _ in 0..num_iterations {
+                        progress.next()?;
                     }
-                    progress.next();
+                } else {
+                    reencode_chunk(&chunk_indices)?;
+                    journal.record(&chunk_indices)?;
                 }
-                Ok::<_, ArrayError>(())
-            }
+                Ok(())
+            },
         )?;
     } else {
-        // FIXME
-        todo!("zarrs_reencode does not yet support data type conversion!")
+        let codec_names = data_type_dependent_codec_names(array_out);
+        anyhow::bail!(
+            "Data type conversion from {} to {} is not supported{}",
+            array_in.data_type(),
+            array_out.data_type(),
+            if codec_names.is_empty() {
+                String::new()
+            } else {
+                format!(" (data type dependent codecs: {})", codec_names.join(", "))
+            }
+        );
     }
 
     let duration = start.elapsed().unwrap().as_secs_f32();
@@ -778,78 +2399,684 @@ pub fn do_reencode<
     let duration_read = duration_read * duration / duration_read_write;
     let duration_write = duration_write * duration / duration_read_write;
 
-    Ok((
+    Ok(ReencodeStats {
         duration,
         duration_read,
         duration_write,
-        bytes_decoded.into_inner().unwrap(),
-    ))
+        bytes_decoded: bytes_decoded.into_inner().unwrap(),
+        num_chunks: chunks.num_elements_usize(),
+        num_writes: num_iterations,
+        cache_requests_misses: cache.as_ref().map(Cache::requests_misses),
+        error_metrics: error_tolerance.map(|_| error_metrics.into_inner().unwrap()),
+    })
 }
 
-/// Convert an arrays fill value to a new data type
-fn convert_fill_value(
-    data_type_in: &DataType,
-    fill_value_in: &FillValue,
-    data_type_out: &DataType,
-) -> FillValue {
-    macro_rules! convert {
-        ( $t_in:ty, $t_out:ty) => {{
-            let input_fill_value =
-                <$t_in>::from_ne_bytes(fill_value_in.as_ne_bytes().try_into().unwrap());
-            use num_traits::AsPrimitive;
-            let output_fill_value: $t_out = input_fill_value.as_();
-            FillValue::from(output_fill_value)
-        }};
-    }
-    macro_rules! apply_inner {
-        ( $type_in:ty, [$( ( $data_type_out:ident, $type_out:ty ) ),* ]) => {
-            match data_type_out {
-                $(DataType::$data_type_out => { convert!($type_in, $type_out) } ,)*
-                _ => panic!()
+/// Returns the indices of `sample` distinct randomly chosen chunks of `chunks`, in order.
+///
+/// All chunks are returned if `sample` exceeds the number of chunks.
+pub fn sample_chunk_indices(
+    chunks: &ArraySubset,
+    sample: usize,
+    rng: &mut fastrand::Rng,
+) -> Vec<Vec<u64>> {
+    let num_chunks = chunks.num_elements();
+    let sample = std::cmp::min(sample as u64, num_chunks);
+    let mut chunk_linear_indices = std::collections::BTreeSet::new();
+    while (chunk_linear_indices.len() as u64) < sample {
+        chunk_linear_indices.insert(rng.u64(0..num_chunks));
+    }
+    chunk_linear_indices
+        .into_iter()
+        .map(|chunk_linear_index| {
+            std::iter::zip(
+                unravel_index(chunk_linear_index, chunks.shape()),
+                chunks.start(),
+            )
+            .map(|(i, start)| i + start)
+            .collect()
+        })
+        .collect()
+}
+
+/// The result of verifying an output array against its input with [`do_verify`].
+#[derive(Serialize, Debug, Clone)]
+pub struct VerifyReport {
+    /// The number of chunks of the output array.
+    pub num_chunks: usize,
+    /// The number of chunks verified.
+    pub num_chunks_verified: usize,
+    /// The random seed of the chunk sample, if chunks were sampled.
+    pub seed: Option<u64>,
+    /// The indices of the verified chunks that do not match the input.
+    pub mismatched_chunks: Vec<Vec<u64>>,
+    /// The duration of the verification (s).
+    pub duration: f32,
+}
+
+impl VerifyReport {
+    /// Returns true if every verified chunk matches the input.
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.mismatched_chunks.is_empty()
+    }
+}
+
+/// Verify `array_out` against `array_in` by re-reading a random sample (or all) of the output chunks.
+///
+/// Unlike [`ReencodeValidation`], this is a separate pass after all chunks are written, so it detects writes that are not (yet) visible, such as on eventually consistent object stores.
+/// If the array shape differs, chunks are only compared where they overlap the input.
+/// If `sample` is set and `seed` is not, a random seed is chosen and recorded in the report.
+///
+/// # Errors
+/// Returns an error if the arrays have a different dimensionality or data type, or a chunk cannot be read.
+pub fn do_verify<
+    TStorageIn: ReadableStorageTraits + ?Sized + 'static,
+    TStorageOut: ReadableStorageTraits + ?Sized + 'static,
+>(
+    array_in: &Array<TStorageIn>,
+    array_out: &Array<TStorageOut>,
+    sample: Option<usize>,
+    seed: Option<u64>,
+    concurrent_chunks: Option<usize>,
+    progress_callback: &ProgressCallback,
+) -> anyhow::Result<VerifyReport> {
+    if array_in.dimensionality() != array_out.dimensionality() {
+        anyhow::bail!("Input and output array dimensionality differ");
+    }
+    if array_in.data_type() != array_out.data_type() {
+        anyhow::bail!("Input and output array data types differ");
+    }
+
+    let start = SystemTime::now();
+    let chunks = ArraySubset::new_with_shape(array_out.chunk_grid_shape().unwrap());
+    let (chunk_indices, seed) = if let Some(sample) = sample {
+        let seed = seed.unwrap_or_else(|| fastrand::u64(..));
+        let mut rng = fastrand::Rng::with_seed(seed);
+        (sample_chunk_indices(&chunks, sample, &mut rng), Some(seed))
+    } else {
+        (chunks.indices().iter().collect::<Vec<_>>(), None)
+    };
+
+    let chunk_representation = array_out
+        .chunk_array_representation(&vec![0; array_out.chunk_grid().dimensionality()])
+        .unwrap();
+    let (chunks_concurrent_limit, codec_concurrent_target) = calculate_chunk_and_codec_concurrency(
+        concurrent_target(),
+        concurrent_chunks,
+        array_out.codecs(),
+        chunk_indices.len(),
+        &chunk_representation,
+    );
+    let codec_options = CodecOptionsBuilder::new()
+        .concurrent_target(codec_concurrent_target)
+        .build();
+
+    let num_chunks_verified = chunk_indices.len();
+    let progress = Progress::new(num_chunks_verified, progress_callback);
+    let subset_in_all = array_in.subset_all();
+    let mismatched_chunks = Mutex::new(vec![]);
+    iter_concurrent_limit!(
+        chunks_concurrent_limit,
+        chunk_indices,
+        try_for_each,
+        |chunk_indices: Vec<u64>| {
+            let chunk_subset = array_out.chunk_subset_bounded(&chunk_indices)?;
+            if let Some(subset) = subset_intersection(&chunk_subset, &subset_in_all)? {
+                let bytes_out = progress
+                    .read(|| array_out.retrieve_array_subset_opt(&subset, &codec_options))?;
+                let bytes_in = progress
+                    .read(|| array_in.retrieve_array_subset_opt(&subset, &codec_options))?;
+                if progress.process(|| bytes_in != bytes_out) {
+                    mismatched_chunks.lock().unwrap().push(chunk_indices);
+                }
             }
-        };
+            progress.next()?;
+            Ok::<_, anyhow::Error>(())
+        }
+    )?;
+
+    let mut mismatched_chunks = mismatched_chunks.into_inner().unwrap();
+    mismatched_chunks.sort();
+    Ok(VerifyReport {
+        num_chunks: chunks.num_elements_usize(),
+        num_chunks_verified,
+        seed,
+        mismatched_chunks,
+        duration: start.elapsed().unwrap().as_secs_f32(),
+    })
+}
+
+/// Returns true if the chunks of `array_in` can be copied to `array_out` without decoding.
+///
+/// This requires that the array shape, data type, fill value, chunk grid, chunk key encoding, and codecs are unchanged.
+/// Other metadata, such as attributes and dimension names, may differ.
+pub fn chunk_encoding_unchanged<TStorageIn: ?Sized, TStorageOut: ?Sized>(
+    array_in: &Array<TStorageIn>,
+    array_out: &Array<TStorageOut>,
+) -> bool {
+    array_in.shape() == array_out.shape()
+        && array_in.data_type() == array_out.data_type()
+        && array_in.fill_value() == array_out.fill_value()
+        && array_in.chunk_grid().create_metadata() == array_out.chunk_grid().create_metadata()
+        && array_in.chunk_key_encoding().create_metadata()
+            == array_out.chunk_key_encoding().create_metadata()
+        && array_in.codecs().create_metadatas() == array_out.codecs().create_metadatas()
+}
+
+/// Copy the encoded chunks of `array_in` to `array_out` without decoding.
+///
+/// This is a fast path for reencoding where only the array metadata changes (e.g. attributes or dimension names).
+/// Chunks that do not exist in the input are skipped.
+/// The array metadata of `array_out` is not written.
+///
+/// # Errors
+/// Returns an error if the chunk encoding differs (see [`chunk_encoding_unchanged`]) or a chunk cannot be read or written.
+pub fn do_copy_chunks<
+    TStorageIn: ReadableStorageTraits + ?Sized + 'static,
+    TStorageOut: WritableStorageTraits + ?Sized + 'static,
+>(
+    array_in: &Array<TStorageIn>,
+    array_out: &Array<TStorageOut>,
+    storage_out: &TStorageOut,
+    concurrent_chunks: Option<usize>,
+    progress_callback: &ProgressCallback,
+) -> anyhow::Result<ReencodeStats> {
+    if !chunk_encoding_unchanged(array_in, array_out) {
+        anyhow::bail!("The chunk encoding of the input and output arrays differ");
     }
-    macro_rules! apply_outer {
-    ([$( ( $data_type_in:ident, $type_in:ty ) ),* ]) => {
-            match data_type_in {
-                $(
-                    DataType::$data_type_in => {
-                        apply_inner!($type_in, [
-                            (Bool, u8),
-                            (Int8, i8),
-                            (Int16, i16),
-                            (Int32, i32),
-                            (Int64, i64),
-                            (UInt8, u8),
-                            (UInt16, u16),
-                            (UInt32, u32),
-                            (UInt64, u64),
-                            (BFloat16, half::bf16),
-                            (Float16, half::f16),
-                            (Float32, f32),
-                            (Float64, f64)
-                        ]
-                    )}
-                ,)*
-                _ => panic!()
+
+    let start = SystemTime::now();
+    let num_writes = AtomicUsize::new(0);
+
+    let chunks = ArraySubset::new_with_shape(array_out.chunk_grid_shape().unwrap());
+    let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+    let chunks_concurrent_limit = concurrent_chunks.unwrap_or_else(concurrent_target).max(1);
+
+    let indices = chunks.indices();
+    iter_concurrent_limit!(
+        chunks_concurrent_limit,
+        indices,
+        try_for_each,
+        |chunk_indices: Vec<u64>| {
+            let bytes = progress.read(|| array_in.retrieve_encoded_chunk(&chunk_indices))?;
+            if let Some(bytes) = bytes {
+                progress.add_bytes_read(bytes.len());
+                progress.add_bytes_written(bytes.len());
+                progress.write(|| {
+                    storage_out.set(&array_out.chunk_key(&chunk_indices), bytes.into())
+                })?;
+                num_writes.fetch_add(1, Ordering::Relaxed);
             }
+            progress.next()?;
+            Ok::<_, StorageError>(())
+        }
+    )?;
+
+    let duration = start.elapsed().unwrap().as_secs_f32();
+    let stats = progress.stats();
+    let duration_read = stats.read.as_secs_f32();
+    let duration_write = stats.write.as_secs_f32();
+    let duration_read_write = duration_read + duration_write;
+    let duration_read = duration_read * duration / duration_read_write;
+    let duration_write = duration_write * duration / duration_read_write;
+
+    Ok(ReencodeStats {
+        duration,
+        duration_read,
+        duration_write,
+        bytes_decoded: 0,
+        num_chunks: chunks.num_elements_usize(),
+        num_writes: num_writes.into_inner(),
+        cache_requests_misses: None,
+        error_metrics: None,
+    })
+}
+
+/// The use of direct I/O (bypassing the page cache) by filesystem stores.
+///
+/// Direct I/O can improve throughput on some NVMe workloads, but hurts others (e.g. repeated reads that would hit the page cache).
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectIo {
+    /// Use direct I/O for input stores.
+    Read,
+    /// Use direct I/O for output stores.
+    Write,
+    /// Use direct I/O for input and output stores.
+    Both,
+    /// Do not use direct I/O.
+    #[default]
+    Off,
+}
+
+impl DirectIo {
+    /// Returns true if direct I/O is used for input stores.
+    #[must_use]
+    pub fn read(self) -> bool {
+        matches!(self, Self::Read | Self::Both)
+    }
+
+    /// Returns true if direct I/O is used for output stores.
+    #[must_use]
+    pub fn write(self) -> bool {
+        matches!(self, Self::Write | Self::Both)
+    }
+}
+
+/// Create a [`FilesystemStore`] at `path`, optionally with direct I/O.
+///
+/// # Errors
+/// Returns a [`FilesystemStoreCreateError`] if the store cannot be created.
+pub fn filesystem_store<P: AsRef<Path>>(
+    path: P,
+    direct_io: bool,
+) -> Result<FilesystemStore, FilesystemStoreCreateError> {
+    FilesystemStore::new_with_options(
+        path,
+        FilesystemStoreOptions::default()
+            .direct_io(direct_io)
+            .clone(),
+    )
+}
+
+/// Split `path` into a store path and a node path, given an optional node path suffix (e.g. `output.zarr#/labels/cells`).
+///
+/// The node path is `/` (the store root) if `path` has no `#/` suffix.
+#[must_use]
+pub fn split_node_path(path: &Path) -> (PathBuf, String) {
+    match path.to_str().and_then(|path| path.split_once("#/")) {
+        Some((store_path, node_path)) => (
+            PathBuf::from(store_path),
+            format!("/{}", node_path.trim_matches('/')),
+        ),
+        None => (path.to_path_buf(), "/".to_string()),
+    }
+}
+
+/// The store prefix of the node at `node_path` (e.g. `/a/b` becomes `a/b/`).
+#[must_use]
+pub fn node_prefix(node_path: &str) -> StorePrefix {
+    let node_path = node_path.trim_matches('/');
+    if node_path.is_empty() {
+        StorePrefix::root()
+    } else {
+        StorePrefix::new(format!("{node_path}/")).unwrap()
+    }
+}
+
+/// Create groups for the ancestors of the node at `node_path` without metadata (e.g. `/` and `/a` for `/a/b`).
+///
+/// Existing groups are left unchanged, so their attributes and other children are preserved.
+///
+/// # Errors
+/// Returns a [`GroupCreateError`] if an ancestor is an array or a group cannot be created.
+pub fn create_parent_groups<
+    TStorage: ?Sized + ReadableStorageTraits + WritableStorageTraits + 'static,
+>(
+    storage: &Arc<TStorage>,
+    node_path: &str,
+) -> Result<(), GroupCreateError> {
+    let names = node_path
+        .split('/')
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>();
+    for depth in 0..names.len() {
+        let parent = format!("/{}", names[..depth].join("/"));
+        if Group::open(storage.clone(), &parent).is_ok() {
+            continue;
+        }
+        if Array::open(storage.clone(), &parent).is_ok() {
+            return Err(StorageError::Other(format!(
+                "cannot create {node_path}, {parent} is an array"
+            ))
+            .into());
+        }
+        GroupBuilder::new()
+            .build(storage.clone(), &parent)?
+            .store_metadata()?;
+    }
+    Ok(())
+}
+
+/// The method used to copy files between local filesystem paths.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileCopyMode {
+    /// Copy the file contents.
+    #[default]
+    Copy,
+    /// Create a hard link. The source and destination must be on the same filesystem.
+    ///
+    /// The output shares storage with the input, so modifying one in place modifies the other.
+    Hardlink,
+    /// Create a reflink (copy-on-write clone). The filesystem must support reflinks (e.g. Btrfs, XFS, APFS).
+    Reflink,
+    /// Create a reflink if supported by the filesystem, otherwise copy the file contents.
+    ReflinkOrCopy,
+}
+
+/// Copy the file at `src` to `dst` with a [`FileCopyMode`].
+///
+/// An existing file at `dst` is replaced.
+///
+/// # Errors
+/// Returns an [`std::io::Error`] if the file cannot be copied, hard linked, or reflinked.
+pub fn copy_file(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    mode: FileCopyMode,
+) -> std::io::Result<()> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    if mode != FileCopyMode::Copy && dst.exists() {
+        std::fs::remove_file(dst)?;
+    }
+    match mode {
+        FileCopyMode::Copy => std::fs::copy(src, dst).map(|_| ()),
+        FileCopyMode::Hardlink => std::fs::hard_link(src, dst),
+        FileCopyMode::Reflink => reflink_copy::reflink(src, dst),
+        FileCopyMode::ReflinkOrCopy => reflink_copy::reflink_or_copy(src, dst).map(|_| ()),
+    }
+}
+
+/// The verification of copied files.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyVerification {
+    /// Do not verify copied files.
+    #[default]
+    None,
+    /// Check that copied files have the size of the source.
+    Size,
+    /// Check that copied files have the size and SHA-256 checksum of the source.
+    Checksum,
+}
+
+/// Returns the SHA-256 checksum of the file at `path`.
+fn file_checksum(path: &Path) -> std::io::Result<Vec<u8>> {
+    use sha2::{Digest, Sha256};
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let length = std::io::Read::read(&mut file, &mut buffer)?;
+        if length == 0 {
+            break;
+        }
+        hasher.update(&buffer[..length]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Verify that the file at `dst` is a copy of the file at `src` with a [`CopyVerification`].
+///
+/// # Errors
+/// Returns an [`std::io::Error`] if either file cannot be read, or the files differ.
+pub fn verify_copy(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    verification: CopyVerification,
+) -> std::io::Result<()> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    if verification == CopyVerification::None {
+        return Ok(());
+    }
+    let (size_src, size_dst) = (std::fs::metadata(src)?.len(), std::fs::metadata(dst)?.len());
+    if size_src != size_dst {
+        return Err(std::io::Error::other(format!(
+            "the copy {dst:?} of {src:?} has size {size_dst}, expected {size_src}"
+        )));
+    }
+    if verification == CopyVerification::Checksum && file_checksum(src)? != file_checksum(dst)? {
+        return Err(std::io::Error::other(format!(
+            "the copy {dst:?} of {src:?} has a different checksum"
+        )));
+    }
+    Ok(())
+}
+
+/// Copy the chunk files of `array_in` to `array_out` without decoding, where both are stored on a local filesystem.
+///
+/// This is equivalent to [`do_copy_chunks`], except chunk files can be hard linked or reflinked rather than copied.
+/// `path_in` and `path_out` are the filesystem store roots of the input and output arrays.
+/// The array metadata of `array_out` is not written.
+///
+/// # Errors
+/// Returns an error if the chunk encoding differs (see [`chunk_encoding_unchanged`]) or a chunk file cannot be copied.
+pub fn do_copy_chunk_files<TStorageIn: ?Sized + Send + Sync, TStorageOut: ?Sized + Send + Sync>(
+    array_in: &Array<TStorageIn>,
+    array_out: &Array<TStorageOut>,
+    path_in: &Path,
+    path_out: &Path,
+    mode: FileCopyMode,
+    concurrent_chunks: Option<usize>,
+    progress_callback: &ProgressCallback,
+) -> anyhow::Result<ReencodeStats> {
+    if !chunk_encoding_unchanged(array_in, array_out) {
+        anyhow::bail!("The chunk encoding of the input and output arrays differ");
+    }
+
+    let start = SystemTime::now();
+    let num_writes = AtomicUsize::new(0);
+
+    let chunks = ArraySubset::new_with_shape(array_out.chunk_grid_shape().unwrap());
+    let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+    let chunks_concurrent_limit = concurrent_chunks.unwrap_or_else(concurrent_target).max(1);
+
+    let indices = chunks.indices();
+    iter_concurrent_limit!(
+        chunks_concurrent_limit,
+        indices,
+        try_for_each,
+        |chunk_indices: Vec<u64>| {
+            let src = path_in.join(array_in.chunk_key(&chunk_indices).as_str());
+            if src.is_file() {
+                let dst = path_out.join(array_out.chunk_key(&chunk_indices).as_str());
+                progress.write(|| {
+                    if let Some(parent) = dst.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    copy_file(&src, &dst, mode)
+                })?;
+                let size = usize::try_from(std::fs::metadata(&dst)?.len()).unwrap();
+                progress.add_bytes_read(size);
+                progress.add_bytes_written(size);
+                num_writes.fetch_add(1, Ordering::Relaxed);
+            }
+            progress.next()?;
+            Ok::<_, std::io::Error>(())
+        }
+    )?;
+
+    let duration = start.elapsed().unwrap().as_secs_f32();
+
+    Ok(ReencodeStats {
+        duration,
+        duration_read: 0.0,
+        duration_write: duration,
+        bytes_decoded: 0,
+        num_chunks: chunks.num_elements_usize(),
+        num_writes: num_writes.into_inner(),
+        cache_requests_misses: None,
+        error_metrics: None,
+    })
+}
+
+/// Parse a size in bytes with an optional unit suffix.
+///
+/// Decimal (`KB`, `MB`, `GB`, `TB`) and binary (`KiB`, `MiB`, `GiB`, `TiB`) units are supported, e.g. `1048576`, `1MiB`, `1.5GB`.
+pub fn parse_size(size: &str) -> std::io::Result<u64> {
+    let size = size.trim();
+    let unit_start = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(unit_start);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| std::io::Error::other(format!("invalid size {size}")))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" | "k" => 1_000,
+        "mb" | "m" => 1_000_000,
+        "gb" | "g" => 1_000_000_000,
+        "tb" | "t" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => {
+            return Err(std::io::Error::other(format!(
+                "invalid size unit {unit} in {size}"
+            )))
+        }
+    };
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Check that a `kind` (chunk or shard) shape has a size for each dimension of `array_shape`.
+fn check_chunk_shape_dimensionality(
+    kind: &'static str,
+    shape: &[ChunkSize],
+    array_shape: &[u64],
+) -> Result<(), ArrayBuilderReencodeError> {
+    if shape.len() == array_shape.len() {
+        Ok(())
+    } else {
+        Err(ArrayBuilderReencodeError::IncompatibleDimensionality(
+            kind,
+            shape.iter().join(","),
+            array_shape.to_vec(),
+        ))
+    }
+}
+
+/// Returns true if any dimension of a chunk shape or shard shape is [`ChunkSize::Auto`].
+fn has_auto_size(chunk_shape: &[ChunkSize], shard_shape: Option<&[ChunkSize]>) -> bool {
+    chunk_shape.contains(&ChunkSize::Auto)
+        || shard_shape.is_some_and(|shard_shape| shard_shape.contains(&ChunkSize::Auto))
+}
+
+/// Compute a chunk shape with a decoded size of at most `target_size` bytes.
+///
+/// The chunk shape starts at the array shape and its largest dimension is halved until the target size is met.
+/// Ties are broken in favour of the slowest varying (first) dimension, so chunks stay contiguous along the fastest varying dimensions.
+#[must_use]
+pub fn auto_chunk_shape(array_shape: &[u64], element_size: usize, target_size: u64) -> Vec<u64> {
+    resolve_chunk_shape(
+        &vec![ChunkSize::Auto; array_shape.len()],
+        array_shape,
+        element_size,
+        target_size,
+    )
+}
+
+/// Resolve a chunk shape with [`ChunkSize::Auto`] and [`ChunkSize::Full`] dimensions for an array shape.
+///
+/// Full dimensions match the array shape.
+/// Auto dimensions are chosen as in [`auto_chunk_shape`], except that only auto dimensions are halved.
+#[must_use]
+pub fn resolve_chunk_shape(
+    chunk_shape: &[ChunkSize],
+    array_shape: &[u64],
+    element_size: usize,
+    target_size: u64,
+) -> Vec<u64> {
+    let mut chunk_shape_resolved: Vec<u64> = std::iter::zip(chunk_shape, array_shape)
+        .map(|(chunk_size, array_size)| match chunk_size {
+            ChunkSize::Auto => (*array_size).max(1),
+            ChunkSize::Full => (*array_size).max(1),
+            ChunkSize::Size(size) => size.get(),
+        })
+        .collect();
+    let chunk_size =
+        |chunk_shape: &[u64]| chunk_shape.iter().product::<u64>() * element_size as u64;
+    while chunk_size(&chunk_shape_resolved) > target_size {
+        // max_by_key returns the last maximum, so iterate in reverse to prefer the first dimension
+        let Some((i, size)) = chunk_shape_resolved
+            .iter()
+            .copied()
+            .enumerate()
+            .rev()
+            .filter(|(i, _)| chunk_shape[*i] == ChunkSize::Auto)
+            .max_by_key(|(_, size)| *size)
+        else {
+            break;
         };
+        if size == 1 {
+            break;
+        }
+        chunk_shape_resolved[i] = size.div_ceil(2);
+    }
+    chunk_shape_resolved
+}
+
+/// Compute a shard shape with a decoded size of at most `target_size` bytes.
+///
+/// The shard shape starts at the chunk shape and its smallest dimension is doubled until the target size would be exceeded.
+/// Ties are broken in favour of the fastest varying (last) dimension.
+/// The shard shape is a multiple of the chunk shape and does not exceed the array shape rounded up to a multiple of the chunk shape.
+#[must_use]
+pub fn auto_shard_shape(
+    array_shape: &[u64],
+    chunk_shape: &[u64],
+    element_size: usize,
+    target_size: u64,
+) -> Vec<u64> {
+    resolve_shard_shape(
+        &vec![ChunkSize::Auto; array_shape.len()],
+        array_shape,
+        chunk_shape,
+        element_size,
+        target_size,
+    )
+}
+
+/// Resolve a shard shape with [`ChunkSize::Auto`] and [`ChunkSize::Full`] dimensions for an array shape and chunk shape.
+///
+/// Full dimensions match the array shape and fixed sizes are limited to the array shape.
+/// Auto dimensions are chosen as in [`auto_shard_shape`], except that only auto dimensions are doubled.
+/// The shard shape is rounded up to a multiple of the chunk shape.
+#[must_use]
+pub fn resolve_shard_shape(
+    shard_shape: &[ChunkSize],
+    array_shape: &[u64],
+    chunk_shape: &[u64],
+    element_size: usize,
+    target_size: u64,
+) -> Vec<u64> {
+    let shard_shape_max: Vec<u64> = std::iter::zip(array_shape, chunk_shape)
+        .map(|(array_size, chunk_size)| array_size.div_ceil(*chunk_size).max(1) * chunk_size)
+        .collect();
+    let mut shard_shape_resolved: Vec<u64> =
+        itertools::izip!(shard_shape, array_shape, chunk_shape)
+            .map(|(shard_size, array_size, chunk_size)| match shard_size {
+                ChunkSize::Auto => *chunk_size,
+                ChunkSize::Full => (*array_size).max(1).next_multiple_of(*chunk_size),
+                ChunkSize::Size(size) => {
+                    std::cmp::min(size.get(), (*array_size).max(1)).next_multiple_of(*chunk_size)
+                }
+            })
+            .collect();
+    let shard_size =
+        |shard_shape: &[u64]| shard_shape.iter().product::<u64>() * element_size as u64;
+    loop {
+        let mut candidates: Vec<usize> = (0..shard_shape_resolved.len())
+            .filter(|&i| {
+                shard_shape[i] == ChunkSize::Auto && shard_shape_resolved[i] < shard_shape_max[i]
+            })
+            .collect();
+        candidates.sort_by_key(|&i| (shard_shape_resolved[i], std::cmp::Reverse(i)));
+        let grown = candidates.into_iter().find_map(|i| {
+            let mut shard_shape_candidate = shard_shape_resolved.clone();
+            shard_shape_candidate[i] = (shard_shape_resolved[i] * 2).min(shard_shape_max[i]);
+            (shard_size(&shard_shape_candidate) <= target_size).then_some(shard_shape_candidate)
+        });
+        if let Some(grown) = grown {
+            shard_shape_resolved = grown;
+        } else {
+            break;
+        }
     }
-    apply_outer!([
-        (Bool, u8),
-        (Int8, i8),
-        (Int16, i16),
-        (Int32, i32),
-        (Int64, i64),
-        (UInt8, u8),
-        (UInt16, u16),
-        (UInt32, u32),
-        (UInt64, u64),
-        (BFloat16, half::bf16),
-        (Float16, half::f16),
-        (Float32, f32),
-        (Float64, f64)
-    ])
+    shard_shape_resolved
 }
 
 pub fn calculate_chunk_and_codec_concurrency(
@@ -874,3 +3101,683 @@ pub fn calculate_chunk_and_codec_concurrency(
             .unwrap(),
     )
 }
+
+/// Returns the available space (in bytes) of the filesystem containing `path`, if it can be determined.
+///
+/// `path` does not need to exist, the filesystem of its nearest existing ancestor is used.
+#[must_use]
+pub fn available_space(path: &Path) -> Option<u64> {
+    let path = std::path::absolute(path).ok()?;
+    let path = path
+        .ancestors()
+        .find_map(|ancestor| ancestor.canonicalize().ok())?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(sysinfo::Disk::available_space)
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::{
+        array::{codec::array_to_bytes::sharding::ShardingCodecBuilder, FillValue},
+        storage::store::MemoryStore,
+    };
+
+    use super::*;
+
+    fn codec_metadata(metadata: serde_json::Value) -> MetadataV3 {
+        serde_json::from_value(metadata).unwrap()
+    }
+
+    fn translate(
+        metadata: serde_json::Value,
+        data_type_in: &DataType,
+        data_type_out: &DataType,
+    ) -> Result<Option<serde_json::Value>, ArrayBuilderReencodeError> {
+        translate_codec_metadata(&codec_metadata(metadata), data_type_in, data_type_out)
+            .map(|metadata| metadata.map(|metadata| serde_json::to_value(metadata).unwrap()))
+    }
+
+    #[test]
+    fn translate_codec_metadata_delta() {
+        let delta = serde_json::json!({"name": "delta", "configuration": {"dtype": "<u2", "astype": "<u2"}});
+        assert_eq!(
+            translate(delta.clone(), &DataType::UInt16, &DataType::Int32).unwrap(),
+            Some(
+                serde_json::json!({"name": "delta", "configuration": {"dtype": "<i4", "astype": "<i4"}})
+            )
+        );
+        // Unchanged data type
+        assert_eq!(
+            translate(delta, &DataType::UInt16, &DataType::UInt16).unwrap(),
+            Some(
+                serde_json::json!({"name": "delta", "configuration": {"dtype": "<u2", "astype": "<u2"}})
+            )
+        );
+        // Zarr V3 data type representation, and an `astype` differing from the input data type is retained
+        assert_eq!(
+            translate(
+                serde_json::json!({"name": "numcodecs.delta", "configuration": {"dtype": "uint16", "astype": "uint8"}}),
+                &DataType::UInt16,
+                &DataType::UInt32
+            )
+            .unwrap(),
+            Some(serde_json::json!({"name": "numcodecs.delta", "configuration": {"dtype": "uint32", "astype": "uint8"}}))
+        );
+    }
+
+    #[test]
+    fn translate_codec_metadata_quantize() {
+        let quantize = serde_json::json!({"name": "quantize", "configuration": {"digits": 3, "dtype": "<f8", "astype": "<f8"}});
+        assert_eq!(
+            translate(quantize.clone(), &DataType::Float64, &DataType::Float32).unwrap(),
+            Some(
+                serde_json::json!({"name": "quantize", "configuration": {"digits": 3, "dtype": "<f4", "astype": "<f4"}})
+            )
+        );
+        assert!(matches!(
+            translate(quantize, &DataType::Float64, &DataType::Int32),
+            Err(ArrayBuilderReencodeError::UnrepresentableCodec(name, _)) if name == "quantize"
+        ));
+    }
+
+    #[test]
+    fn translate_codec_metadata_fixedscaleoffset() {
+        let fixedscaleoffset = serde_json::json!({"name": "fixedscaleoffset", "configuration": {"scale": 10, "offset": 1000, "dtype": "<f8", "astype": "<u2"}});
+        assert_eq!(
+            translate(fixedscaleoffset, &DataType::Float64, &DataType::Float32).unwrap(),
+            Some(
+                serde_json::json!({"name": "fixedscaleoffset", "configuration": {"scale": 10, "offset": 1000, "dtype": "<f4", "astype": "<u2"}})
+            )
+        );
+    }
+
+    #[test]
+    fn translate_codec_metadata_big_endian() {
+        let delta = serde_json::json!({"name": "numcodecs.delta", "configuration": {"dtype": ">i2", "astype": ">i2"}});
+        assert_eq!(
+            translate(delta.clone(), &DataType::Int16, &DataType::Int32).unwrap(),
+            Some(
+                serde_json::json!({"name": "numcodecs.delta", "configuration": {"dtype": ">i4", "astype": ">i4"}})
+            )
+        );
+        // Single byte data types have no byte order
+        assert_eq!(
+            translate(delta, &DataType::Int16, &DataType::UInt8).unwrap(),
+            Some(
+                serde_json::json!({"name": "numcodecs.delta", "configuration": {"dtype": "|u1", "astype": "|u1"}})
+            )
+        );
+        assert_eq!(
+            translate(
+                serde_json::json!({"name": "quantize", "configuration": {"digits": 3, "dtype": ">f8"}}),
+                &DataType::Float64,
+                &DataType::Float32
+            )
+            .unwrap(),
+            Some(serde_json::json!({"name": "quantize", "configuration": {"digits": 3, "dtype": ">f4"}}))
+        );
+    }
+
+    #[test]
+    fn translate_codec_metadata_shuffle() {
+        let shuffle = serde_json::json!({"name": "shuffle", "configuration": {"elementsize": 2}});
+        assert_eq!(
+            translate(shuffle.clone(), &DataType::UInt16, &DataType::Float64).unwrap(),
+            Some(serde_json::json!({"name": "shuffle", "configuration": {"elementsize": 8}}))
+        );
+        assert!(matches!(
+            translate(shuffle, &DataType::UInt16, &DataType::String),
+            Err(ArrayBuilderReencodeError::UnrepresentableCodec(name, _)) if name == "shuffle"
+        ));
+    }
+
+    #[test]
+    fn translate_codec_metadata_errors() {
+        // No numpy representation of the output data type
+        assert!(matches!(
+            translate(
+                serde_json::json!({"name": "delta", "configuration": {"dtype": "<u2"}}),
+                &DataType::UInt16,
+                &DataType::RawBits(2)
+            ),
+            Err(ArrayBuilderReencodeError::UnrepresentableCodec(name, _)) if name == "delta"
+        ));
+        // Other codecs are not translated
+        assert_eq!(
+            translate(
+                serde_json::json!({"name": "gzip", "configuration": {"level": 5}}),
+                &DataType::UInt16,
+                &DataType::Int32
+            )
+            .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn reencode_data_type_conversion_unsupported() -> Result<(), Box<dyn std::error::Error>> {
+        let array_in = ArrayBuilder::new(
+            vec![4, 4],
+            DataType::UInt16,
+            vec![2, 2].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        let array_out = ArrayBuilder::new(
+            vec![4, 4],
+            DataType::Float32,
+            vec![2, 2].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        let progress_callback = |_| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let err = do_reencode(
+            &array_in,
+            &array_out,
+            ReencodeValidation::Off,
+            None,
+            &progress_callback,
+            CacheSize::None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Data type conversion from uint16 to float32 is not supported"
+        );
+        Ok(())
+    }
+
+    /// Reencode a `7x6` array with elements `0..42` to an array of `shape` with a fill value of 99, which is optionally sharded and written with `write_shape`.
+    ///
+    /// Returns the elements of the output.
+    fn reencode_reshaped(
+        shape: Vec<u64>,
+        sharded: bool,
+        write_shape: Option<Vec<NonZeroU64>>,
+    ) -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+        let array_in = ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            vec![4, 4].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        let elements = (0..42).collect::<Vec<u16>>();
+        array_in.store_array_subset_elements::<u16>(&array_in.subset_all(), &elements)?;
+
+        let mut builder = ArrayBuilder::new(
+            shape,
+            DataType::UInt16,
+            vec![4, 4].try_into()?,
+            FillValue::from(99u16),
+        );
+        if sharded {
+            builder.array_to_bytes_codec(Arc::new(
+                ShardingCodecBuilder::new(vec![2, 2].try_into()?).build(),
+            ));
+        }
+        let array_out = builder.build(Arc::new(MemoryStore::new()), "/")?;
+        let progress_callback = |_| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        do_reencode(
+            &array_in,
+            &array_out,
+            ReencodeValidation::RoundTrip,
+            None,
+            &progress_callback,
+            CacheSize::None,
+            write_shape,
+            None,
+            None,
+            None,
+        )?;
+        Ok(array_out.retrieve_array_subset_elements::<u16>(&array_out.subset_all())?)
+    }
+
+    /// The expected elements of a `7x6` array with elements `0..42` cropped or padded with 99 to `shape`.
+    fn reshaped_elements(shape: [u16; 2]) -> Vec<u16> {
+        (0..shape[0])
+            .flat_map(|i| (0..shape[1]).map(move |j| if i < 7 && j < 6 { i * 6 + j } else { 99 }))
+            .collect()
+    }
+
+    #[test]
+    fn reencode_array_shape() -> Result<(), Box<dyn std::error::Error>> {
+        let write_shape = || Some(vec![NonZeroU64::new(2).unwrap(); 2]);
+        for shape in [[5, 3], [9, 10], [5, 10], [7, 6]] {
+            let expected = reshaped_elements(shape);
+            let shape = shape.map(u64::from).to_vec();
+            assert_eq!(reencode_reshaped(shape.clone(), false, None)?, expected);
+            assert_eq!(reencode_reshaped(shape.clone(), true, None)?, expected);
+            assert_eq!(reencode_reshaped(shape, true, write_shape())?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_array_shape_padding() -> Result<(), Box<dyn std::error::Error>> {
+        // Padding reads back as the fill value, including chunks entirely outside of the input
+        let elements = reencode_reshaped(vec![12, 6], true, None)?;
+        assert!(elements[42..].iter().all(|element| *element == 99));
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_array_shape_dimensionality() -> Result<(), Box<dyn std::error::Error>> {
+        let array_in = ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            vec![4, 4].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        let array_out = ArrayBuilder::new(
+            vec![7, 6, 1],
+            DataType::UInt16,
+            vec![4, 4, 1].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        let progress_callback = |_| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let err = do_reencode(
+            &array_in,
+            &array_out,
+            ReencodeValidation::Off,
+            None,
+            &progress_callback,
+            CacheSize::None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Input and output array dimensionality differ"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_grid_errors() {
+        assert!(
+            get_chunk_grid(r#"{"name":"regular","configuration":{"chunk_shape":[2,2]}}"#).is_ok()
+        );
+        assert!(get_chunk_grid("[2, 2]")
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid chunk grid [2, 2]"));
+        assert!(get_chunk_grid(r#"{"name":"unknown"}"#)
+            .unwrap_err()
+            .to_string()
+            .starts_with(r#"unsupported chunk grid {"name":"unknown"}"#));
+    }
+
+    /// Returns the output dimension names of `ZarrReencodingArgs` with `dimension_names` and `rename_dim` for an array with the `input` dimension names.
+    fn output_dimension_names(
+        dimension_names: Option<&[&str]>,
+        rename_dim: Option<&[&str]>,
+        input: Option<&[&str]>,
+    ) -> Result<Option<Vec<Option<String>>>, ArrayBuilderReencodeError> {
+        let to_strings = |names: &[&str]| names.iter().map(ToString::to_string).collect();
+        let args = ZarrReencodingArgs {
+            dimension_names: dimension_names.map(to_strings),
+            rename_dim: rename_dim.map(to_strings),
+            ..Default::default()
+        };
+        let input: Option<Vec<DimensionName>> =
+            input.map(|input| input.iter().copied().map(DimensionName::new).collect());
+        let dimensionality = input.as_ref().map_or(3, Vec::len);
+        Ok(args
+            .output_dimension_names(input.as_deref(), dimensionality)?
+            .map(|dimension_names| {
+                dimension_names
+                    .iter()
+                    .map(|name| name.as_str().map(ToString::to_string))
+                    .collect()
+            }))
+    }
+
+    #[test]
+    fn dimension_names() -> Result<(), ArrayBuilderReencodeError> {
+        let names =
+            |names: &[&str]| Some(names.iter().map(|name| Some(name.to_string())).collect());
+        assert_eq!(
+            output_dimension_names(None, None, Some(&["z", "y", "x"]))?,
+            None
+        );
+        assert_eq!(
+            output_dimension_names(Some(&["c", "b", "a"]), None, Some(&["z", "y", "x"]))?,
+            names(&["c", "b", "a"])
+        );
+        assert_eq!(
+            output_dimension_names(None, Some(&["z=depth", "2=width"]), Some(&["z", "y", "x"]))?,
+            names(&["depth", "y", "width"])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dimension_names_count() {
+        assert_eq!(
+            output_dimension_names(Some(&["y", "x"]), None, Some(&["z", "y", "x"]))
+                .unwrap_err()
+                .to_string(),
+            "invalid dimension names: 2 dimension names were given for an array with 3 dimensions"
+        );
+    }
+
+    #[test]
+    fn dimension_names_duplicate() {
+        assert_eq!(
+            output_dimension_names(Some(&["x", "y", "x"]), None, None)
+                .unwrap_err()
+                .to_string(),
+            "invalid dimension names: dimension x is duplicated"
+        );
+        assert_eq!(
+            output_dimension_names(None, Some(&["z=x"]), Some(&["z", "y", "x"]))
+                .unwrap_err()
+                .to_string(),
+            "invalid dimension names: dimension x is duplicated"
+        );
+        // A duplicated input dimension cannot be renamed by name, but can be renamed by index
+        assert_eq!(
+            output_dimension_names(None, Some(&["x=z"]), Some(&["x", "y", "x"]))
+                .unwrap_err()
+                .to_string(),
+            "invalid dimension names: dimension x is ambiguous"
+        );
+        assert_eq!(
+            output_dimension_names(None, Some(&["0=z"]), Some(&["x", "y", "x"])).unwrap(),
+            Some(vec![
+                Some("z".to_string()),
+                Some("y".to_string()),
+                Some("x".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn dimension_names_rename_missing() {
+        assert_eq!(
+            output_dimension_names(None, Some(&["t=time"]), Some(&["z", "y", "x"]))
+                .unwrap_err()
+                .to_string(),
+            "invalid dimension names: dimension t does not exist"
+        );
+        assert_eq!(
+            output_dimension_names(None, Some(&["3=time"]), Some(&["z", "y", "x"]))
+                .unwrap_err()
+                .to_string(),
+            "invalid dimension names: dimension 3 does not exist"
+        );
+        assert_eq!(
+            output_dimension_names(None, Some(&["time"]), Some(&["z", "y", "x"]))
+                .unwrap_err()
+                .to_string(),
+            "invalid dimension names: time is not an AXIS=NAME pair"
+        );
+    }
+
+    #[test]
+    fn dimension_names_unnamed_input() -> Result<(), Box<dyn std::error::Error>> {
+        // Dimensions of an array without dimension names can only be renamed by index, others are unnamed
+        assert_eq!(
+            output_dimension_names(None, Some(&["1=y"]), None)?,
+            Some(vec![None, Some("y".to_string()), None])
+        );
+        assert!(output_dimension_names(None, Some(&["y=y"]), None).is_err());
+
+        let array = ArrayBuilder::new(
+            vec![4, 4],
+            DataType::UInt8,
+            vec![2, 2].try_into()?,
+            FillValue::from(0u8),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        assert!(array.dimension_names().is_none());
+        let args = ZarrReencodingArgs {
+            rename_dim: Some(vec!["0=y".to_string()]),
+            ..Default::default()
+        };
+        let array_out = get_array_builder_reencode(&args, &array, None)?
+            .build(Arc::new(MemoryStore::new()), "/")?;
+        assert_eq!(
+            array_out.dimension_names(),
+            &Some(vec![DimensionName::new("y"), DimensionName::default()])
+        );
+        let array_out = get_array_builder_reencode(&ZarrReencodingArgs::default(), &array, None)?
+            .build(Arc::new(MemoryStore::new()), "/")?;
+        assert!(array_out.dimension_names().is_none());
+        Ok(())
+    }
+
+    /// Parse `ZarrEncodingArgs` from command line arguments (excluding the fill value).
+    fn parse_encoding_args(args: &[&str]) -> Result<ZarrEncodingArgs, clap::Error> {
+        ZarrEncodingArgs::try_parse_from(["test", "--fill-value=0"].iter().chain(args))
+    }
+
+    #[test]
+    fn chunk_shape_parse() -> Result<(), clap::Error> {
+        let encoding_args =
+            parse_encoding_args(&["--chunk-shape=auto,full,256,256", "--shard-shape=0,auto"])?;
+        assert_eq!(
+            encoding_args.chunk_shape,
+            [
+                ChunkSize::Auto,
+                ChunkSize::Full,
+                ChunkSize::Size(NonZeroU64::new(256).unwrap()),
+                ChunkSize::Size(NonZeroU64::new(256).unwrap())
+            ]
+        );
+        assert_eq!(
+            encoding_args.shard_shape,
+            Some(vec![ChunkSize::Full, ChunkSize::Auto])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_shape_parse_invalid() {
+        for chunk_shape in ["64,foo", "-1", "1.5", "64,,64", "", "AUTO"] {
+            let err = parse_encoding_args(&[&format!("--chunk-shape={chunk_shape}")])
+                .err()
+                .unwrap_or_else(|| panic!("--chunk-shape={chunk_shape} should not parse"));
+            assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+        }
+        assert!(parse_encoding_args(&["--chunk-shape=1", "--shard-shape=full,x"]).is_err());
+        assert_eq!(
+            "foo".parse::<ChunkSize>().unwrap_err().to_string(),
+            "invalid chunk size foo, expected an integer, full, or auto"
+        );
+    }
+
+    #[test]
+    fn chunk_shape_resolve() -> Result<(), Box<dyn std::error::Error>> {
+        let encoding_args = parse_encoding_args(&["--chunk-shape=auto,full,256,256"])?;
+        let array_builder =
+            get_array_builder(&encoding_args, &[10, 8, 1000, 1000], DataType::UInt8, None)?;
+        let chunk_grid: ChunkGrid = vec![2, 8, 256, 256].try_into()?;
+        assert_eq!(
+            array_builder.chunk_grid.create_metadata(),
+            chunk_grid.create_metadata()
+        );
+
+        // Auto dimensions are halved to 1 if the target size is zero, other dimensions are unchanged
+        assert_eq!(
+            resolve_chunk_shape(
+                &[ChunkSize::Auto, ChunkSize::Full, ChunkSize::Auto],
+                &[100, 10, 100],
+                4,
+                0
+            ),
+            [1, 10, 1]
+        );
+        assert_eq!(
+            resolve_shard_shape(&[ChunkSize::Auto; 2], &[100, 100], &[10, 10], 4, 0),
+            [10, 10]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_shape_zero_length_dimension() -> Result<(), Box<dyn std::error::Error>> {
+        // Zero length dimensions have a chunk and shard size of 1
+        assert_eq!(
+            resolve_chunk_shape(&[ChunkSize::Full, ChunkSize::Auto], &[0, 0], 1, 1024),
+            [1, 1]
+        );
+        assert_eq!(
+            resolve_shard_shape(
+                &[
+                    ChunkSize::Full,
+                    ChunkSize::Size(NonZeroU64::new(4).unwrap())
+                ],
+                &[0, 0],
+                &[1, 1],
+                1,
+                1024
+            ),
+            [1, 1]
+        );
+
+        let encoding_args =
+            parse_encoding_args(&["--chunk-shape=full,8", "--shard-shape=full,full"])?;
+        let array_builder = get_array_builder(&encoding_args, &[0, 16], DataType::UInt8, None)?;
+        let chunk_grid: ChunkGrid = vec![1, 16].try_into()?;
+        assert_eq!(
+            array_builder.chunk_grid.create_metadata(),
+            chunk_grid.create_metadata()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_shape_auto_variable_size() -> Result<(), clap::Error> {
+        let encoding_args = parse_encoding_args(&["--chunk-shape=auto,16"])?;
+        let err = get_array_builder(&encoding_args, &[32, 32], DataType::String, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "auto chunk and shard sizes are not supported for the variable sized data type string"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_shape_dimensionality() -> Result<(), Box<dyn std::error::Error>> {
+        let encoding_args = parse_encoding_args(&["--chunk-shape=auto,full,256"])?;
+        let err = get_array_builder(&encoding_args, &[64, 64], DataType::UInt8, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the chunk shape auto,full,256 does not match the dimensionality of the array shape [64, 64]"
+        );
+        let encoding_args = parse_encoding_args(&["--chunk-shape=8,8", "--shard-shape=16"])?;
+        let err = get_array_builder(&encoding_args, &[64, 64], DataType::UInt8, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the shard shape 16 does not match the dimensionality of the array shape [64, 64]"
+        );
+
+        let array = ArrayBuilder::new(
+            vec![64, 64],
+            DataType::UInt8,
+            vec![8, 8].try_into()?,
+            FillValue::from(0u8),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        let reencoding_args = ZarrReencodingArgs {
+            chunk_shape: Some(vec![ChunkSize::Auto]),
+            ..Default::default()
+        };
+        let err = get_array_builder_reencode(&reencoding_args, &array, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the chunk shape auto does not match the dimensionality of the array shape [64, 64]"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shard_index_codecs_invalid() -> Result<(), Box<dyn std::error::Error>> {
+        for (shard_index_codecs, message) in [
+            ("[{\"name\":", "invalid shard index codecs [{\"name\":"),
+            (
+                "[{\"name\":\"unknown\"}]",
+                "invalid shard index codecs [{\"name\":\"unknown\"}]",
+            ),
+        ] {
+            let encoding_args = parse_encoding_args(&[
+                "--chunk-shape=8,8",
+                "--shard-shape=16,16",
+                format!("--shard-index-codecs={shard_index_codecs}").as_str(),
+            ])?;
+            let err =
+                get_array_builder(&encoding_args, &[64, 64], DataType::UInt8, None).unwrap_err();
+            assert!(err.to_string().starts_with(message), "{err}");
+
+            let array = ArrayBuilder::new(
+                vec![64, 64],
+                DataType::UInt8,
+                vec![8, 8].try_into()?,
+                FillValue::from(0u8),
+            )
+            .build(Arc::new(MemoryStore::new()), "/")?;
+            let reencoding_args = ZarrReencodingArgs {
+                shard_shape: Some(vec![ChunkSize::Size(NonZeroU64::new(16).unwrap()); 2]),
+                shard_index_codecs: Some(shard_index_codecs.to_string()),
+                ..Default::default()
+            };
+            let err = get_array_builder_reencode(&reencoding_args, &array, None).unwrap_err();
+            assert!(matches!(
+                err,
+                ArrayBuilderReencodeError::InvalidShardIndexCodecs(..)
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn sample_chunk_indices_seeded() {
+        let chunks = ArraySubset::new_with_start_shape(vec![1, 2], vec![4, 5]).unwrap();
+        let sample = |seed| sample_chunk_indices(&chunks, 6, &mut fastrand::Rng::with_seed(seed));
+        let chunk_indices = sample(42);
+        assert_eq!(chunk_indices, sample(42));
+        assert_eq!(chunk_indices.len(), 6);
+        assert!(chunk_indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(chunk_indices.iter().all(|indices| chunks.contains(indices)));
+        assert_eq!(
+            sample_chunk_indices(&chunks, 100, &mut fastrand::Rng::with_seed(0)),
+            chunks.indices().iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn threads_install() -> Result<(), rayon::ThreadPoolBuildError> {
+        let current_num_threads = || Ok(rayon::current_num_threads());
+        // Each call uses its own thread pool, unlike a global thread pool which can only be built once
+        for threads in [1, 3] {
+            let threads_args = ThreadsArgs {
+                threads: Some(threads),
+            };
+            assert_eq!(threads_args.install(current_num_threads)?, threads);
+        }
+        assert_eq!(
+            ThreadsArgs::default().install(current_num_threads)?,
+            rayon::current_num_threads()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+//! A memory budget shared by concurrent chunk tasks.
+//!
+//! [`MemoryBudget::try_for_each`] admits chunk tasks by their estimated in-flight bytes on the calling thread, before they enter the rayon thread pool.
+//! Rayon workers therefore never wait on the budget while holding a permit, which could otherwise deadlock when a waiting worker picks up another task.
+
+use std::sync::{Condvar, Mutex, OnceLock};
+
+/// The environment variable holding the [`MemoryBudget::global`] limit in bytes.
+pub const MEMORY_LIMIT_ENV: &str = "ZARRS_TOOLS_MEMORY_LIMIT";
+
+/// A memory budget shared by concurrent chunk tasks.
+///
+/// Chunk tasks are admitted with a [`MemoryPermit`] for their estimated in-flight bytes (e.g. chunks being decoded and encoded).
+/// A task is admitted once its bytes fit within the budget, rather than admitting a fixed number of tasks, so huge chunks or shards do not exhaust memory.
+/// A task exceeding the limit on its own is admitted when no other task is in flight.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: usize,
+    in_flight: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A permit for in-flight bytes of a [`MemoryBudget`], which are released on drop.
+#[derive(Debug)]
+pub struct MemoryPermit<'a> {
+    budget: &'a MemoryBudget,
+    bytes: usize,
+}
+
+impl MemoryBudget {
+    /// Create a memory budget with a `limit` in bytes.
+    #[must_use]
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            in_flight: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Create a memory budget with the limit of [`MEMORY_LIMIT_ENV`] if set, otherwise 80% of the available memory.
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self::from_limit(std::env::var(MEMORY_LIMIT_ENV).ok().as_deref())
+    }
+
+    /// Create a memory budget with a `limit` in bytes if it is set and valid, otherwise 80% of the available memory.
+    fn from_limit(limit: Option<&str>) -> Self {
+        let limit = limit
+            .and_then(|limit| limit.parse().ok())
+            .unwrap_or_else(|| {
+                let system = sysinfo::System::new_with_specifics(
+                    sysinfo::RefreshKind::new()
+                        .with_memory(sysinfo::MemoryRefreshKind::new().with_ram()),
+                );
+                usize::try_from(system.available_memory()).unwrap() * 8 / 10
+            });
+        Self::new(limit)
+    }
+
+    /// Returns the memory budget shared by all tools.
+    ///
+    /// The budget is created with [`MemoryBudget::from_env`] on first use.
+    pub fn global() -> &'static MemoryBudget {
+        static MEMORY_BUDGET: OnceLock<MemoryBudget> = OnceLock::new();
+        MEMORY_BUDGET.get_or_init(MemoryBudget::from_env)
+    }
+
+    /// Returns the limit in bytes.
+    #[must_use]
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the bytes currently in flight.
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        *self.in_flight.lock().unwrap()
+    }
+
+    fn fits(&self, in_flight: usize, bytes: usize) -> bool {
+        in_flight == 0 || in_flight + bytes <= self.limit
+    }
+
+    /// Acquire `bytes` if they fit within the budget.
+    pub fn try_acquire(&self, bytes: usize) -> Option<MemoryPermit<'_>> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        self.fits(*in_flight, bytes).then(|| {
+            *in_flight += bytes;
+            MemoryPermit {
+                budget: self,
+                bytes,
+            }
+        })
+    }
+
+    /// Acquire `bytes`, waiting until they fit within the budget.
+    ///
+    /// This must not be called while holding a permit of the same budget.
+    /// On a rayon worker thread, pending rayon work is executed while waiting rather than blocking the worker.
+    pub fn acquire(&self, bytes: usize) -> MemoryPermit<'_> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while !self.fits(*in_flight, bytes) {
+            if rayon::current_thread_index().is_some() {
+                drop(in_flight);
+                if rayon::yield_now() != Some(rayon::Yield::Executed) {
+                    std::thread::yield_now();
+                }
+                in_flight = self.in_flight.lock().unwrap();
+            } else {
+                in_flight = self.released.wait(in_flight).unwrap();
+            }
+        }
+        *in_flight += bytes;
+        MemoryPermit {
+            budget: self,
+            bytes,
+        }
+    }
+
+    /// Apply `op` to each of `items` on the rayon thread pool, with at most `concurrent_limit` items in flight.
+    ///
+    /// Each item is admitted on the calling thread once its in-flight `bytes` fit within the budget, and its permit is held until `op` returns.
+    /// No further items are admitted after an error, and the first error is returned.
+    ///
+    /// # Errors
+    /// Returns the first error returned by `op`.
+    pub fn try_for_each<T, E>(
+        &self,
+        concurrent_limit: usize,
+        items: impl IntoIterator<Item = T>,
+        bytes: impl Fn(&T) -> usize,
+        op: impl Fn(T) -> Result<(), E> + Sync,
+    ) -> Result<(), E>
+    where
+        T: Send,
+        E: Send,
+    {
+        // A budget of one per task limits the number of items in flight
+        let tasks = MemoryBudget::new(concurrent_limit.max(1));
+        let error = Mutex::new(None);
+        rayon::in_place_scope(|scope| {
+            for item in items {
+                if error.lock().unwrap().is_some() {
+                    break;
+                }
+                let task = tasks.acquire(1);
+                let permit = self.acquire(bytes(&item));
+                let (op, error) = (&op, &error);
+                scope.spawn(move |_| {
+                    let _permits = (task, permit);
+                    if let Err(err) = op(item) {
+                        error.lock().unwrap().get_or_insert(err);
+                    }
+                });
+            }
+        });
+        error.into_inner().unwrap().map_or(Ok(()), Err)
+    }
+}
+
+impl Drop for MemoryPermit<'_> {
+    fn drop(&mut self) {
+        *self.budget.in_flight.lock().unwrap() -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn memory_budget_acquire() {
+        let budget = MemoryBudget::new(100);
+        let permit = budget.acquire(60);
+        assert_eq!(budget.in_flight(), 60);
+        assert!(budget.try_acquire(50).is_none());
+        let permit_small = budget.try_acquire(40).unwrap();
+        assert_eq!(budget.in_flight(), 100);
+        drop(permit_small);
+        drop(permit);
+        assert_eq!(budget.in_flight(), 0);
+
+        // A request exceeding the limit is admitted when nothing else is in flight
+        let permit = budget.try_acquire(150).unwrap();
+        assert!(budget.try_acquire(1).is_none());
+        drop(permit);
+        assert_eq!(budget.in_flight(), 0);
+    }
+
+    #[test]
+    fn memory_budget_acquire_waits_for_release() {
+        let budget = MemoryBudget::new(100);
+        let permit = budget.acquire(80);
+        let released = AtomicBool::new(false);
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                started_tx.send(()).unwrap();
+                let _permit = budget.acquire(80);
+                // The permit must not be acquired before the first permit is released
+                (released.load(Ordering::SeqCst), budget.in_flight())
+            });
+            started_rx.recv().unwrap();
+            released.store(true, Ordering::SeqCst);
+            drop(permit);
+            assert_eq!(waiter.join().unwrap(), (true, 80));
+        });
+        assert_eq!(budget.in_flight(), 0);
+    }
+
+    #[test]
+    fn memory_budget_try_for_each() {
+        let budget = MemoryBudget::new(100);
+        let max_in_flight = AtomicUsize::new(0);
+        budget
+            .try_for_each(
+                8,
+                0..64usize,
+                |_| 30,
+                |_| {
+                    max_in_flight.fetch_max(budget.in_flight(), Ordering::Relaxed);
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                    Ok::<_, ()>(())
+                },
+            )
+            .unwrap();
+        assert!(max_in_flight.load(Ordering::Relaxed) <= 90);
+        assert_eq!(budget.in_flight(), 0);
+
+        let result = budget.try_for_each(
+            8,
+            0..64usize,
+            |_| 30,
+            |item| if item == 10 { Err(item) } else { Ok(()) },
+        );
+        assert_eq!(result, Err(10));
+        assert_eq!(budget.in_flight(), 0);
+    }
+
+    #[test]
+    fn memory_budget_from_limit() {
+        assert_eq!(MemoryBudget::from_limit(Some("12345")).limit(), 12345);
+        assert_ne!(MemoryBudget::from_limit(Some("invalid")).limit(), 0);
+    }
+}
@@ -0,0 +1,242 @@
+//! Comparison of the dimension names, units, and attributes of arrays (e.g. to detect metadata lost by a conversion).
+
+use serde_json::Value;
+use zarrs::array::Array;
+
+use crate::physical_metadata::PhysicalMetadata;
+
+/// The behaviour if the compared metadata of arrays differs.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataMismatch {
+    /// Print a warning.
+    Warn,
+    /// Fail.
+    Error,
+}
+
+/// Metadata comparison options.
+#[derive(clap::Parser, Debug, Clone, Default)]
+pub struct MetadataComparisonArgs {
+    /// Compare the dimension names of the arrays.
+    #[arg(long, default_value_t = false)]
+    pub compare_dimension_names: bool,
+
+    /// Compare the units of the arrays.
+    ///
+    /// Units are read from OME-Zarr multiscales, a `units` attribute, or OME-XML in the attributes.
+    #[arg(long, default_value_t = false)]
+    pub compare_units: bool,
+
+    /// Compare attributes by top-level key or JSON pointer (e.g. /omero/channels/0/label), comma delimited.
+    #[arg(long, value_delimiter = ',')]
+    pub compare_attributes: Vec<String>,
+
+    /// The behaviour if compared metadata differs or is missing.
+    ///
+    /// Defaults to error for zarrs_validate and warn for zarrs_reencode.
+    #[arg(long, value_enum)]
+    pub metadata_mismatch: Option<MetadataMismatch>,
+}
+
+/// A difference in the metadata of two arrays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataDifference {
+    /// The name of the metadata (e.g. `dimension names` or `attribute /units`).
+    pub name: String,
+    /// The metadata of the first array, [`None`] if missing.
+    pub first: Option<Value>,
+    /// The metadata of the second array, [`None`] if missing.
+    pub second: Option<Value>,
+}
+
+impl std::fmt::Display for MetadataDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.first, &self.second) {
+            (Some(first), None) => {
+                write!(f, "{} {first} is missing in the second array", self.name)
+            }
+            (None, Some(second)) => {
+                write!(f, "{} {second} is missing in the first array", self.name)
+            }
+            (first, second) => write!(
+                f,
+                "{} differ: {} vs {}",
+                self.name,
+                first.as_ref().unwrap_or(&Value::Null),
+                second.as_ref().unwrap_or(&Value::Null)
+            ),
+        }
+    }
+}
+
+/// The compared metadata of arrays differs.
+#[derive(Debug, thiserror::Error)]
+#[error("Metadata does not match: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+pub struct MetadataMismatchError(pub Vec<MetadataDifference>);
+
+/// Returns the attribute of `attributes` at a top-level key or JSON pointer.
+fn attribute<'a>(attributes: &'a serde_json::Map<String, Value>, key: &str) -> Option<&'a Value> {
+    if let Some(pointer) = key.strip_prefix('/') {
+        let (first, rest) = pointer.split_once('/').unwrap_or((pointer, ""));
+        let first = first.replace("~1", "/").replace("~0", "~");
+        let value = attributes.get(&first)?;
+        if rest.is_empty() {
+            Some(value)
+        } else {
+            value.pointer(&format!("/{rest}"))
+        }
+    } else {
+        attributes.get(key)
+    }
+}
+
+/// Returns the units of `array`.
+fn units<TStorage: ?Sized>(array: &Array<TStorage>) -> Option<Value> {
+    PhysicalMetadata::from_attributes(
+        array.attributes(),
+        None,
+        array.dimension_names().as_deref(),
+        array.dimensionality(),
+    )
+    .units
+    .map(|units| serde_json::to_value(units).unwrap())
+}
+
+impl MetadataComparisonArgs {
+    /// Returns true if any metadata is compared.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.compare_dimension_names || self.compare_units || !self.compare_attributes.is_empty()
+    }
+
+    /// Compare the metadata of `first` and `second`.
+    #[must_use]
+    pub fn compare<TStorage1: ?Sized, TStorage2: ?Sized>(
+        &self,
+        first: &Array<TStorage1>,
+        second: &Array<TStorage2>,
+    ) -> Vec<MetadataDifference> {
+        let mut differences = vec![];
+        let mut push = |name: String, first: Option<Value>, second: Option<Value>| {
+            if first != second {
+                differences.push(MetadataDifference {
+                    name,
+                    first,
+                    second,
+                });
+            }
+        };
+        if self.compare_dimension_names {
+            let dimension_names = |array_dimension_names: &Option<Vec<_>>| {
+                array_dimension_names
+                    .as_ref()
+                    .map(|dimension_names| serde_json::to_value(dimension_names).unwrap())
+            };
+            push(
+                "dimension names".to_string(),
+                dimension_names(first.dimension_names()),
+                dimension_names(second.dimension_names()),
+            );
+        }
+        if self.compare_units {
+            push("units".to_string(), units(first), units(second));
+        }
+        for key in &self.compare_attributes {
+            push(
+                format!("attribute {key}"),
+                attribute(first.attributes(), key).cloned(),
+                attribute(second.attributes(), key).cloned(),
+            );
+        }
+        differences
+    }
+
+    /// Compare the metadata of `first` and `second`, and return the differences.
+    ///
+    /// `default_mismatch` is the behaviour if `--metadata-mismatch` is unset.
+    ///
+    /// # Errors
+    /// Returns a [`MetadataMismatchError`] if the metadata differs and the mismatch behaviour is [`MetadataMismatch::Error`].
+    pub fn check<TStorage1: ?Sized, TStorage2: ?Sized>(
+        &self,
+        first: &Array<TStorage1>,
+        second: &Array<TStorage2>,
+        default_mismatch: MetadataMismatch,
+    ) -> Result<Vec<MetadataDifference>, MetadataMismatchError> {
+        let differences = self.compare(first, second);
+        if !differences.is_empty()
+            && self.metadata_mismatch.unwrap_or(default_mismatch) == MetadataMismatch::Error
+        {
+            Err(MetadataMismatchError(differences))
+        } else {
+            Ok(differences)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zarrs::{
+        array::{ArrayBuilder, DataType, FillValue},
+        storage::store::MemoryStore,
+    };
+
+    use super::*;
+
+    #[test]
+    fn metadata_comparison() {
+        let store = Arc::new(MemoryStore::new());
+        let mut builder = ArrayBuilder::new(
+            vec![4, 4],
+            DataType::UInt8,
+            vec![2, 2].try_into().unwrap(),
+            FillValue::from(0u8),
+        );
+        let mut attributes = serde_json::Map::new();
+        attributes.insert("units".to_string(), serde_json::json!(["um", "um"]));
+        attributes.insert("spacing".to_string(), serde_json::json!([1.0, 1.0]));
+        attributes.insert("a/b".to_string(), serde_json::json!({"c": 1}));
+        let first = builder
+            .dimension_names(Some(["y", "x"]))
+            .attributes(attributes)
+            .build(store.clone(), "/first")
+            .unwrap();
+        let second = builder
+            .dimension_names(None::<Vec<&str>>)
+            .attributes(serde_json::Map::new())
+            .build(store, "/second")
+            .unwrap();
+
+        let args = MetadataComparisonArgs::default();
+        assert!(!args.is_enabled());
+        assert!(args.compare(&first, &second).is_empty());
+
+        let args = MetadataComparisonArgs {
+            compare_dimension_names: true,
+            compare_units: true,
+            compare_attributes: vec!["/a~1b/c".to_string(), "missing".to_string()],
+            metadata_mismatch: None,
+        };
+        let differences = args.compare(&first, &second);
+        assert_eq!(
+            differences
+                .iter()
+                .map(|difference| difference.name.as_str())
+                .collect::<Vec<_>>(),
+            ["dimension names", "units", "attribute /a~1b/c"]
+        );
+        assert_eq!(
+            differences[2].to_string(),
+            "attribute /a~1b/c 1 is missing in the second array"
+        );
+        assert!(args.compare(&first, &first).is_empty());
+        assert!(args
+            .check(&first, &second, MetadataMismatch::Warn)
+            .is_ok_and(|differences| differences.len() == 3));
+        assert!(args
+            .check(&first, &second, MetadataMismatch::Error)
+            .is_err());
+    }
+}
@@ -0,0 +1,520 @@
+//! Read an [N5](https://github.com/saalfeldlab/n5) dataset as a Zarr V3 array.
+//!
+//! [`N5Store`] presents an N5 dataset as a store containing a single Zarr V3 array at the root, so it can be used as the input of any tool that reads Zarr arrays.
+//!
+//! N5 dimensions are in column-major (Fortran) order, so the dimensions of the Zarr array are reversed.
+//! Only `raw` and `gzip` compression are supported.
+//! Complete blocks are passed through without decoding where possible, whereas edge blocks (which are truncated in N5) are padded with zeros.
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::GzEncoder,
+    Compression,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use zarrs::{
+    byte_range::{extract_byte_ranges, ByteRange},
+    storage::{
+        Bytes, ListableStorageTraits, MaybeBytes, ReadableStorageTraits, StorageError, StoreKey,
+        StoreKeys, StoreKeysPrefixes, StorePrefix,
+    },
+};
+
+const N5_ATTRIBUTES: &str = "attributes.json";
+const ZARR_JSON: &str = "zarr.json";
+
+/// N5 block compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum N5Compression {
+    /// Uncompressed.
+    Raw,
+    /// Gzip (or zlib if `use_zlib`) compression.
+    Gzip { level: u32, use_zlib: bool },
+}
+
+/// N5 dataset attributes (`attributes.json`).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct N5DatasetAttributes {
+    dimensions: Vec<u64>,
+    block_size: Vec<u64>,
+    data_type: String,
+    #[serde(default)]
+    compression: Option<Value>,
+    /// The compression of N5 versions prior to 1.0.0.
+    #[serde(default)]
+    compression_type: Option<String>,
+    #[serde(flatten)]
+    attributes: serde_json::Map<String, Value>,
+}
+
+impl N5DatasetAttributes {
+    fn compression(&self) -> Result<N5Compression, StorageError> {
+        let compression_type = if let Some(compression) = &self.compression {
+            compression["type"].as_str().map(str::to_string)
+        } else {
+            self.compression_type.clone()
+        };
+        match compression_type.as_deref() {
+            None | Some("raw") => Ok(N5Compression::Raw),
+            Some("gzip") => {
+                let compression = self.compression.clone().unwrap_or_default();
+                let level = compression["level"]
+                    .as_i64()
+                    .and_then(|level| u32::try_from(level).ok())
+                    .unwrap_or(6)
+                    .min(9);
+                let use_zlib = compression["useZlib"].as_bool().unwrap_or(false);
+                Ok(N5Compression::Gzip { level, use_zlib })
+            }
+            Some(compression_type) => Err(StorageError::from(format!(
+                "unsupported N5 compression {compression_type}, only raw and gzip are supported"
+            ))),
+        }
+    }
+}
+
+/// Returns true if `path` is an N5 dataset (i.e. it has an `attributes.json` with `dimensions`).
+#[must_use]
+pub fn is_n5_dataset(path: &Path) -> bool {
+    std::fs::read(path.join(N5_ATTRIBUTES))
+        .ok()
+        .and_then(|attributes| serde_json::from_slice::<Value>(&attributes).ok())
+        .is_some_and(|attributes| attributes.get("dimensions").is_some())
+}
+
+/// A read-only store presenting an N5 dataset as a Zarr V3 array at the root.
+///
+/// The Zarr array has the `default` chunk key encoding with a `/` separator and a `bytes` (big endian) codec, followed by a `gzip` codec if the dataset is gzip compressed.
+/// Zlib compressed blocks are decompressed, since there is no equivalent Zarr V3 codec.
+/// The fill value is zero.
+#[derive(Debug)]
+pub struct N5Store {
+    path: PathBuf,
+    block_size: Vec<u64>,
+    element_size: usize,
+    compression: N5Compression,
+    metadata: Vec<u8>,
+}
+
+impl N5Store {
+    /// Create a new N5 store for the dataset at `path`.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the dataset attributes cannot be read or are unsupported.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let path = path.as_ref().to_path_buf();
+        let attributes = std::fs::read(path.join(N5_ATTRIBUTES))?;
+        let attributes: N5DatasetAttributes = serde_json::from_slice(&attributes)
+            .map_err(|err| StorageError::from(format!("invalid N5 dataset attributes: {err}")))?;
+        let compression = attributes.compression()?;
+
+        let (data_type, element_size, fill_value) = match attributes.data_type.as_str() {
+            "int8" => ("int8", 1, json!(0)),
+            "int16" => ("int16", 2, json!(0)),
+            "int32" => ("int32", 4, json!(0)),
+            "int64" => ("int64", 8, json!(0)),
+            "uint8" => ("uint8", 1, json!(0)),
+            "uint16" => ("uint16", 2, json!(0)),
+            "uint32" => ("uint32", 4, json!(0)),
+            "uint64" => ("uint64", 8, json!(0)),
+            "float32" => ("float32", 4, json!(0.0)),
+            "float64" => ("float64", 8, json!(0.0)),
+            data_type => {
+                return Err(StorageError::from(format!(
+                    "unsupported N5 data type {data_type}"
+                )))
+            }
+        };
+        if attributes.dimensions.len() != attributes.block_size.len() {
+            return Err(StorageError::from(
+                "N5 dimensions and block size dimensionality differ".to_string(),
+            ));
+        }
+
+        let mut codecs = vec![if element_size == 1 {
+            json!({"name": "bytes"})
+        } else {
+            json!({"name": "bytes", "configuration": {"endian": "big"}})
+        }];
+        if let N5Compression::Gzip {
+            level,
+            use_zlib: false,
+        } = compression
+        {
+            codecs.push(json!({"name": "gzip", "configuration": {"level": level}}));
+        }
+
+        let mut user_attributes = attributes.attributes.clone();
+        // N5 axis names (e.g. from n5-viewer) are mapped to dimension names
+        let dimension_names = user_attributes
+            .get("axes")
+            .and_then(|axes| serde_json::from_value::<Vec<String>>(axes.clone()).ok())
+            .filter(|axes| axes.len() == attributes.dimensions.len())
+            .map(|axes| axes.into_iter().rev().collect::<Vec<_>>());
+        if dimension_names.is_some() {
+            user_attributes.remove("axes");
+        }
+
+        let block_size = attributes
+            .block_size
+            .iter()
+            .rev()
+            .copied()
+            .collect::<Vec<_>>();
+        let mut metadata = json!({
+            "zarr_format": 3,
+            "node_type": "array",
+            "shape": attributes.dimensions.iter().rev().collect::<Vec<_>>(),
+            "data_type": data_type,
+            "chunk_grid": {"name": "regular", "configuration": {"chunk_shape": block_size}},
+            "chunk_key_encoding": {"name": "default", "configuration": {"separator": "/"}},
+            "fill_value": fill_value,
+            "codecs": codecs,
+            "attributes": user_attributes,
+        });
+        if let Some(dimension_names) = dimension_names {
+            metadata["dimension_names"] = json!(dimension_names);
+        }
+
+        Ok(Self {
+            path,
+            block_size,
+            element_size,
+            compression,
+            metadata: serde_json::to_vec_pretty(&metadata).unwrap(),
+        })
+    }
+
+    /// Returns the chunk indices of a Zarr chunk key (e.g. `c/1/2/3`).
+    fn chunk_indices(&self, key: &StoreKey) -> Option<Vec<u64>> {
+        let indices = key
+            .as_str()
+            .strip_prefix("c/")?
+            .split('/')
+            .map(|index| index.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        (indices.len() == self.block_size.len()).then_some(indices)
+    }
+
+    /// Returns the path of the N5 block of a Zarr chunk.
+    fn block_path(&self, chunk_indices: &[u64]) -> PathBuf {
+        let mut path = self.path.clone();
+        for index in chunk_indices.iter().rev() {
+            path.push(index.to_string());
+        }
+        path
+    }
+
+    /// Retrieve the N5 block of a Zarr chunk encoded with the codecs of the Zarr array metadata.
+    fn get_chunk(&self, chunk_indices: &[u64]) -> Result<MaybeBytes, StorageError> {
+        let block = match std::fs::read(self.block_path(chunk_indices)) {
+            Ok(block) => block,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        // Parse the block header
+        let invalid_block = || {
+            StorageError::from(format!(
+                "invalid N5 block {}",
+                self.block_path(chunk_indices).display()
+            ))
+        };
+        let read_u16 = |offset: usize| -> Result<u16, StorageError> {
+            let bytes = block.get(offset..offset + 2).ok_or_else(invalid_block)?;
+            Ok(u16::from_be_bytes(bytes.try_into().unwrap()))
+        };
+        let read_u32 = |offset: usize| -> Result<u32, StorageError> {
+            let bytes = block.get(offset..offset + 4).ok_or_else(invalid_block)?;
+            Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+        };
+        let mode = read_u16(0)?;
+        let ndim = usize::from(read_u16(2)?);
+        if ndim != self.block_size.len() {
+            return Err(invalid_block());
+        }
+        let mut block_shape = (0..ndim)
+            .map(|dim| read_u32(4 + dim * 4).map(u64::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        block_shape.reverse();
+        let mut offset = 4 + ndim * 4;
+        match mode {
+            0 => {}
+            1 => offset += 4, // varlength: number of elements
+            _ => {
+                return Err(StorageError::from(format!(
+                    "unsupported N5 block mode {mode}"
+                )))
+            }
+        }
+        let data = &block[offset..];
+
+        // Pass through complete blocks if the compression is supported by the Zarr codecs
+        let complete = block_shape == self.block_size;
+        match self.compression {
+            N5Compression::Raw
+            | N5Compression::Gzip {
+                use_zlib: false, ..
+            } if complete => return Ok(Some(Bytes::copy_from_slice(data))),
+            _ => {}
+        }
+
+        // Decompress
+        let decoded = match self.compression {
+            N5Compression::Raw => data.to_vec(),
+            N5Compression::Gzip { use_zlib, .. } => {
+                let mut decoded = vec![];
+                if use_zlib {
+                    ZlibDecoder::new(data).read_to_end(&mut decoded)?;
+                } else {
+                    GzDecoder::new(data).read_to_end(&mut decoded)?;
+                }
+                decoded
+            }
+        };
+        let num_elements = usize::try_from(block_shape.iter().product::<u64>()).unwrap();
+        if decoded.len() < num_elements * self.element_size {
+            return Err(invalid_block());
+        }
+
+        let decoded = if complete {
+            decoded
+        } else {
+            pad_block(&decoded, &block_shape, &self.block_size, self.element_size)
+        };
+
+        // Encode
+        match self.compression {
+            N5Compression::Gzip {
+                level,
+                use_zlib: false,
+            } => {
+                let mut encoder = GzEncoder::new(vec![], Compression::new(level));
+                std::io::Write::write_all(&mut encoder, &decoded)?;
+                Ok(Some(encoder.finish()?.into()))
+            }
+            _ => Ok(Some(decoded.into())),
+        }
+    }
+
+    /// Recursively list the keys of the N5 dataset below `directory` as Zarr keys.
+    fn list_directory(
+        &self,
+        directory: &Path,
+        indices: &mut Vec<String>,
+        keys: &mut StoreKeys,
+    ) -> Result<(), StorageError> {
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.parse::<u64>().is_err() {
+                continue;
+            }
+            indices.push(name);
+            if entry.file_type()?.is_dir() {
+                self.list_directory(&entry.path(), indices, keys)?;
+            } else if indices.len() == self.block_size.len() {
+                let key = format!(
+                    "c/{}",
+                    indices.iter().rev().cloned().collect::<Vec<_>>().join("/")
+                );
+                keys.push(StoreKey::new(key).map_err(|err| StorageError::from(err.to_string()))?);
+            }
+            indices.pop();
+        }
+        Ok(())
+    }
+}
+
+/// Pad a C order `block` with shape `block_shape` to `chunk_shape` with zeros.
+fn pad_block(
+    block: &[u8],
+    block_shape: &[u64],
+    chunk_shape: &[u64],
+    element_size: usize,
+) -> Vec<u8> {
+    let num_elements = usize::try_from(chunk_shape.iter().product::<u64>()).unwrap();
+    let mut chunk = vec![0; num_elements * element_size];
+    let ndim = block_shape.len();
+    let run_length = usize::try_from(block_shape[ndim - 1]).unwrap() * element_size;
+    if run_length == 0 {
+        return chunk;
+    }
+    let num_runs = block_shape[..ndim - 1].iter().product::<u64>();
+    for run in 0..num_runs {
+        let mut remainder = run;
+        let mut offset = 0;
+        let mut stride = chunk_shape[ndim - 1];
+        for dim in (0..ndim - 1).rev() {
+            offset += (remainder % block_shape[dim]) * stride;
+            remainder /= block_shape[dim];
+            stride *= chunk_shape[dim];
+        }
+        let chunk_offset = usize::try_from(offset).unwrap() * element_size;
+        let block_offset = usize::try_from(run).unwrap() * run_length;
+        chunk[chunk_offset..chunk_offset + run_length]
+            .copy_from_slice(&block[block_offset..block_offset + run_length]);
+    }
+    chunk
+}
+
+impl ReadableStorageTraits for N5Store {
+    fn get(&self, key: &StoreKey) -> Result<MaybeBytes, StorageError> {
+        if key.as_str() == ZARR_JSON {
+            Ok(Some(self.metadata.clone().into()))
+        } else if let Some(chunk_indices) = self.chunk_indices(key) {
+            self.get_chunk(&chunk_indices)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn get_partial_values_key(
+        &self,
+        key: &StoreKey,
+        byte_ranges: &[ByteRange],
+    ) -> Result<Option<Vec<Bytes>>, StorageError> {
+        let Some(bytes) = self.get(key)? else {
+            return Ok(None);
+        };
+        let bytes = extract_byte_ranges(&bytes, byte_ranges)
+            .map_err(|err| StorageError::from(err.to_string()))?;
+        Ok(Some(bytes.into_iter().map(Bytes::from).collect()))
+    }
+
+    fn size_key(&self, key: &StoreKey) -> Result<Option<u64>, StorageError> {
+        Ok(self.get(key)?.map(|bytes| bytes.len() as u64))
+    }
+}
+
+impl ListableStorageTraits for N5Store {
+    fn list(&self) -> Result<StoreKeys, StorageError> {
+        let mut keys = vec![StoreKey::new(ZARR_JSON).unwrap()];
+        self.list_directory(&self.path, &mut vec![], &mut keys)?;
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn list_prefix(&self, prefix: &StorePrefix) -> Result<StoreKeys, StorageError> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|key| key.as_str().starts_with(prefix.as_str()))
+            .collect())
+    }
+
+    fn list_dir(&self, prefix: &StorePrefix) -> Result<StoreKeysPrefixes, StorageError> {
+        let mut keys = vec![];
+        let mut prefixes = vec![];
+        for key in self.list_prefix(prefix)? {
+            let suffix = &key.as_str()[prefix.as_str().len()..];
+            if let Some((child, _)) = suffix.split_once('/') {
+                let child = StorePrefix::new(format!("{}{child}/", prefix.as_str()))
+                    .map_err(|err| StorageError::from(err.to_string()))?;
+                if !prefixes.contains(&child) {
+                    prefixes.push(child);
+                }
+            } else {
+                keys.push(key);
+            }
+        }
+        Ok(StoreKeysPrefixes::new(keys, prefixes))
+    }
+
+    fn size_prefix(&self, prefix: &StorePrefix) -> Result<u64, StorageError> {
+        self.list_prefix(prefix)?
+            .iter()
+            .map(|key| Ok::<_, StorageError>(self.size_key(key)?.unwrap_or_default()))
+            .sum()
+    }
+
+    fn size(&self) -> Result<u64, StorageError> {
+        self.size_prefix(&StorePrefix::root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use zarrs::array::{Array, DimensionName};
+
+    fn write_block(path: &Path, block_indices: &[u64], block_shape: &[u32], data: &[u16]) {
+        let mut block = vec![];
+        block.extend(0u16.to_be_bytes());
+        block.extend(u16::try_from(block_shape.len()).unwrap().to_be_bytes());
+        for size in block_shape {
+            block.extend(size.to_be_bytes());
+        }
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        for value in data {
+            std::io::Write::write_all(&mut encoder, &value.to_be_bytes()).unwrap();
+        }
+        block.extend(encoder.finish().unwrap());
+        let mut block_path = path.to_path_buf();
+        for index in block_indices {
+            block_path.push(index.to_string());
+        }
+        std::fs::create_dir_all(block_path.parent().unwrap()).unwrap();
+        std::fs::write(block_path, block).unwrap();
+    }
+
+    #[test]
+    fn n5_store() -> Result<(), Box<dyn std::error::Error>> {
+        // A 3x5 (x, y) dataset with 2x3 blocks
+        let path = tempfile::TempDir::new()?;
+        std::fs::write(
+            path.path().join(N5_ATTRIBUTES),
+            r#"{
+                "dimensions": [3, 5],
+                "blockSize": [2, 3],
+                "dataType": "uint16",
+                "compression": {"type": "gzip", "level": -1},
+                "axes": ["x", "y"]
+            }"#,
+        )?;
+        assert!(is_n5_dataset(path.path()));
+        let value = |x: u16, y: u16| y * 3 + x;
+        for block_y in 0..2 {
+            for block_x in 0..2 {
+                let x_range = block_x * 2..std::cmp::min(block_x * 2 + 2, 3);
+                let y_range = block_y * 3..std::cmp::min(block_y * 3 + 3, 5);
+                let data = y_range
+                    .clone()
+                    .flat_map(|y| x_range.clone().map(move |x| value(x, y)))
+                    .collect::<Vec<_>>();
+                write_block(
+                    path.path(),
+                    &[u64::from(block_x), u64::from(block_y)],
+                    &[x_range.len() as u32, y_range.len() as u32],
+                    &data,
+                );
+            }
+        }
+
+        let store = Arc::new(N5Store::new(path.path())?);
+        assert_eq!(store.list()?.len(), 5);
+        let array = Array::open(store, "/")?;
+        assert_eq!(array.shape(), &[5, 3]);
+        assert_eq!(
+            array.dimension_names(),
+            &Some(vec![DimensionName::new("y"), DimensionName::new("x")])
+        );
+        let elements = array.retrieve_array_subset_ndarray::<u16>(&array.subset_all())?;
+        let elements_ref = ndarray::Array2::from_shape_fn((5, 3), |(y, x)| {
+            value(u16::try_from(x).unwrap(), u16::try_from(y).unwrap())
+        })
+        .into_dyn();
+        assert_eq!(elements, elements_ref);
+
+        Ok(())
+    }
+}
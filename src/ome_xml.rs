@@ -0,0 +1,278 @@
+//! Companion OME-XML metadata (`OME/METADATA.ome.xml`) of a "bioformats2raw.layout" hierarchy.
+//!
+//! The image of series `N` is the OME-XML `Image` with the ID `Image:N`.
+
+use zarrs::array::DataType;
+
+use crate::physical_metadata::{ome_xml_unit, xml_attribute};
+
+/// The path of the companion OME-XML relative to the root of a "bioformats2raw.layout" hierarchy.
+pub const OME_XML_PATH: &str = "OME/METADATA.ome.xml";
+
+/// The OME-XML dimensions, in the order of [`OmeXmlPixels::sizes`].
+const DIMENSIONS: [char; 5] = ['X', 'Y', 'Z', 'C', 'T'];
+
+/// An OME-XML error.
+#[derive(Debug, thiserror::Error)]
+pub enum OmeXmlError {
+    #[error("axis {_0:?} is not one of t, c, z, y, or x")]
+    UnsupportedAxis(String),
+    #[error("unsupported data type {_0}")]
+    UnsupportedDataType(String),
+    #[error("the OME-XML does not have an image with the ID Image:{_0}")]
+    MissingImage(u64),
+    #[error("the OME-XML image Image:{_0} does not match the array:\n{_1}")]
+    Mismatch(u64, String),
+}
+
+/// The pixels of an OME-XML image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OmeXmlPixels {
+    /// The pixel type (e.g. `uint16`).
+    pub pixel_type: String,
+    /// The sizes of the X, Y, Z, C, and T dimensions.
+    pub sizes: [u64; 5],
+}
+
+impl OmeXmlPixels {
+    /// Create the OME-XML pixels of an array with axes named `axis_names` (e.g. `t`, `c`, `z`, `y`, `x`).
+    ///
+    /// Dimensions without an axis have a size of 1.
+    ///
+    /// # Errors
+    /// Returns an [`OmeXmlError`] if an axis name or the data type is not supported by OME-XML.
+    pub fn from_array(
+        axis_names: &[String],
+        shape: &[u64],
+        data_type: &DataType,
+    ) -> Result<Self, OmeXmlError> {
+        let mut sizes = [1; 5];
+        let mut found = [false; 5];
+        for (name, size) in std::iter::zip(axis_names, shape) {
+            let index = DIMENSIONS
+                .iter()
+                .position(|dimension| name.eq_ignore_ascii_case(&dimension.to_string()))
+                .filter(|index| !found[*index])
+                .ok_or_else(|| OmeXmlError::UnsupportedAxis(name.clone()))?;
+            sizes[index] = *size;
+            found[index] = true;
+        }
+        Ok(Self {
+            pixel_type: pixel_type(data_type)?.to_string(),
+            sizes,
+        })
+    }
+
+    /// Parse the pixels of the image `Image:{series}` of an OME-XML document.
+    ///
+    /// # Errors
+    /// Returns [`OmeXmlError::MissingImage`] if the image or its pixels are not found.
+    pub fn from_ome_xml(xml: &str, series: u64) -> Result<Self, OmeXmlError> {
+        let missing = || OmeXmlError::MissingImage(series);
+        let (start, end) = find_image(xml, series).ok_or_else(missing)?;
+        let image = &xml[start..end];
+        let pixels_start = image.find("<Pixels ").ok_or_else(missing)?;
+        let pixels_end = image[pixels_start..].find('>').ok_or_else(missing)?;
+        let pixels = &image[pixels_start..pixels_start + pixels_end];
+        let mut sizes = [1; 5];
+        for (size, dimension) in std::iter::zip(&mut sizes, DIMENSIONS) {
+            if let Some(value) = xml_attribute(pixels, &format!("Size{dimension}")) {
+                *size = value.parse().map_err(|_| missing())?;
+            }
+        }
+        Ok(Self {
+            pixel_type: xml_attribute(pixels, "Type")
+                .unwrap_or_default()
+                .to_string(),
+            sizes,
+        })
+    }
+
+    /// Returns the differences from `other`, one per line, or [`None`] if they match.
+    pub fn diff(&self, other: &Self) -> Option<String> {
+        let mut lines = vec![];
+        if self.pixel_type != other.pixel_type {
+            lines.push(format!(
+                "  Type: {} (OME-XML) != {} (array)",
+                self.pixel_type, other.pixel_type
+            ));
+        }
+        for ((size, other_size), dimension) in
+            std::iter::zip(std::iter::zip(self.sizes, other.sizes), DIMENSIONS)
+        {
+            if size != other_size {
+                lines.push(format!(
+                    "  Size{dimension}: {size} (OME-XML) != {other_size} (array)"
+                ));
+            }
+        }
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    /// Check that the image `Image:{series}` of an OME-XML document matches these pixels.
+    ///
+    /// # Errors
+    /// Returns an [`OmeXmlError`] if the image is missing or its pixels differ.
+    pub fn validate(&self, xml: &str, series: u64) -> Result<(), OmeXmlError> {
+        let pixels = Self::from_ome_xml(xml, series)?;
+        match pixels.diff(self) {
+            Some(diff) => Err(OmeXmlError::Mismatch(series, diff)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the OME-XML pixel type of a data type.
+fn pixel_type(data_type: &DataType) -> Result<&'static str, OmeXmlError> {
+    Ok(match data_type {
+        DataType::Bool => "bit",
+        DataType::Int8 => "int8",
+        DataType::Int16 => "int16",
+        DataType::Int32 => "int32",
+        DataType::UInt8 => "uint8",
+        DataType::UInt16 => "uint16",
+        DataType::UInt32 => "uint32",
+        DataType::Float32 => "float",
+        DataType::Float64 => "double",
+        DataType::Complex64 => "complex",
+        DataType::Complex128 => "double-complex",
+        _ => return Err(OmeXmlError::UnsupportedDataType(data_type.to_string())),
+    })
+}
+
+/// Returns the byte range of the image `Image:{series}` of an OME-XML document.
+fn find_image(xml: &str, series: u64) -> Option<(usize, usize)> {
+    let start = xml.find(&format!("<Image ID=\"Image:{series}\""))?;
+    let end = xml[start..].find("</Image>")? + start + "</Image>".len();
+    Some((start, end))
+}
+
+/// Escape the special characters of an XML attribute value.
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Insert or replace the image `Image:{series}` of an OME-XML document, or create a new document if `xml` is [`None`].
+///
+/// `physical_sizes` are the physical sizes and OME-Zarr units (e.g. `micrometer`) of the X, Y, and Z dimensions, if known.
+/// Images are kept in series order.
+pub fn upsert_image(
+    xml: Option<&str>,
+    series: u64,
+    name: &str,
+    pixels: &OmeXmlPixels,
+    physical_sizes: &[Option<(f64, String)>; 3],
+) -> String {
+    let mut attributes = format!(
+        "ID=\"Pixels:{series}\" DimensionOrder=\"XYZCT\" Type=\"{}\"",
+        pixels.pixel_type
+    );
+    for (size, dimension) in std::iter::zip(pixels.sizes, DIMENSIONS) {
+        attributes.push_str(&format!(" Size{dimension}=\"{size}\""));
+    }
+    for (physical_size, dimension) in std::iter::zip(physical_sizes, DIMENSIONS) {
+        let Some((size, unit)) = physical_size else {
+            continue;
+        };
+        // Physical sizes in units without an OME-XML symbol are omitted
+        let symbol = ["Å", "nm", "µm", "mm", "cm", "m"]
+            .into_iter()
+            .find(|symbol| ome_xml_unit(symbol) == Some(unit.as_str()));
+        if let Some(symbol) = symbol {
+            attributes.push_str(&format!(
+                " PhysicalSize{dimension}=\"{size}\" PhysicalSize{dimension}Unit=\"{symbol}\""
+            ));
+        }
+    }
+    let image = format!(
+        "<Image ID=\"Image:{series}\" Name=\"{}\">\n    <Pixels {attributes} BigEndian=\"false\">\n      <MetadataOnly/>\n    </Pixels>\n  </Image>",
+        escape(name)
+    );
+
+    let Some(xml) = xml else {
+        return format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<OME xmlns=\"http://www.openmicroscopy.org/Schemas/OME/2016-06\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://www.openmicroscopy.org/Schemas/OME/2016-06 http://www.openmicroscopy.org/Schemas/OME/2016-06/ome.xsd\" Creator=\"zarrs_tools {}\">\n  {image}\n</OME>\n",
+            env!("CARGO_PKG_VERSION")
+        );
+    };
+    if let Some((start, end)) = find_image(xml, series) {
+        return format!("{}{image}{}", &xml[..start], &xml[end..]);
+    }
+
+    // Insert before the first image of a later series, or at the end of the document
+    let mut insert = xml.rfind("</OME>").unwrap_or(xml.len());
+    let mut offset = 0;
+    while let Some(start) = xml[offset..].find("<Image ID=\"Image:") {
+        let start = offset + start;
+        let id_start = start + "<Image ID=\"Image:".len();
+        let id = xml[id_start..]
+            .split('"')
+            .next()
+            .and_then(|id| id.parse::<u64>().ok());
+        if id.is_some_and(|id| id > series) {
+            insert = start;
+            break;
+        }
+        offset = id_start;
+    }
+    let (before, after) = xml.split_at(insert);
+    if after.starts_with("<Image") {
+        format!("{before}{image}\n  {after}")
+    } else {
+        format!("{}\n  {image}\n{after}", before.trim_end())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ome_xml_upsert_validate() {
+        let axes = ["c", "y", "x"].map(str::to_string);
+        let pixels = OmeXmlPixels::from_array(&axes, &[2, 64, 128], &DataType::UInt16).unwrap();
+        assert_eq!(pixels.sizes, [128, 64, 1, 2, 1]);
+        let physical_sizes = [
+            Some((0.5, "micrometer".to_string())),
+            Some((0.5, "micrometer".to_string())),
+            None,
+        ];
+
+        let xml = upsert_image(None, 1, "a & b", &pixels, &physical_sizes);
+        assert!(xml.contains("Name=\"a &amp; b\""));
+        assert!(xml.contains("PhysicalSizeX=\"0.5\" PhysicalSizeXUnit=\"µm\""));
+        pixels.validate(&xml, 1).unwrap();
+        assert!(matches!(
+            pixels.validate(&xml, 0),
+            Err(OmeXmlError::MissingImage(0))
+        ));
+
+        // Insert series 0 before series 1, and replace series 1
+        let pixels_0 = OmeXmlPixels::from_array(&axes, &[1, 8, 8], &DataType::Float32).unwrap();
+        let xml = upsert_image(Some(&xml), 0, "0", &pixels_0, &[None, None, None]);
+        let xml = upsert_image(Some(&xml), 1, "1", &pixels_0, &[None, None, None]);
+        assert!(xml.find("Image:0").unwrap() < xml.find("Image:1").unwrap());
+        assert_eq!(xml.matches("<Image ").count(), 2);
+        pixels_0.validate(&xml, 0).unwrap();
+        let Err(OmeXmlError::Mismatch(1, diff)) = pixels.validate(&xml, 1) else {
+            panic!()
+        };
+        assert_eq!(
+            diff,
+            "  Type: float (OME-XML) != uint16 (array)\n  SizeX: 8 (OME-XML) != 128 (array)\n  SizeY: 8 (OME-XML) != 64 (array)\n  SizeC: 1 (OME-XML) != 2 (array)"
+        );
+    }
+
+    #[test]
+    fn ome_xml_unsupported_axis() {
+        let axes = ["y", "y"].map(str::to_string);
+        assert!(matches!(
+            OmeXmlPixels::from_array(&axes, &[1, 1], &DataType::UInt8),
+            Err(OmeXmlError::UnsupportedAxis(_))
+        ));
+    }
+}
@@ -0,0 +1,345 @@
+//! Physical metadata (scale, translation, and units) of an array from its attributes.
+//!
+//! Supported sources, in order of precedence:
+//!  - OME-Zarr (NGFF) multiscales of the array or its parent group (0.4 and 0.5),
+//!  - `spacing`, `origin`, and `units` attributes (e.g. written by `zarrs_binary2zarr` from a detached header), and
+//!  - OME-XML stashed in a string attribute (e.g. by Bio-Formats based converters).
+
+use serde_json::{Map, Value};
+use zarrs::array::DimensionName;
+
+/// Physical metadata of an array, with an element per axis.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhysicalMetadata {
+    /// The physical size of an element along each axis.
+    pub scale: Option<Vec<f64>>,
+    /// The physical position of the first element along each axis.
+    pub translation: Option<Vec<f64>>,
+    /// The physical unit of each axis (e.g. `micrometer`).
+    pub units: Option<Vec<Option<String>>>,
+}
+
+impl PhysicalMetadata {
+    /// Returns true if no physical metadata is set.
+    pub fn is_empty(&self) -> bool {
+        self.scale.is_none() && self.translation.is_none() && self.units.is_none()
+    }
+
+    /// Parse the physical metadata of an array from its `attributes` and the attributes of its parent group.
+    ///
+    /// `name` is the name of the array in the parent group, which is matched against the multiscales dataset paths.
+    /// Metadata that does not match the array `dimensionality` is ignored.
+    pub fn from_attributes(
+        attributes: &Map<String, Value>,
+        group_attributes: Option<(&Map<String, Value>, &str)>,
+        dimension_names: Option<&[DimensionName]>,
+        dimensionality: usize,
+    ) -> Self {
+        let metadata = from_multiscales(attributes, None)
+            .or_else(|| {
+                group_attributes.and_then(|(group_attributes, name)| {
+                    from_multiscales(group_attributes, Some(name))
+                })
+            })
+            .or_else(|| from_spacing(attributes))
+            .or_else(|| from_ome_xml(attributes, dimension_names, dimensionality))
+            .unwrap_or_default();
+        let valid = |len: usize| len == dimensionality;
+        Self {
+            scale: metadata.scale.filter(|scale| valid(scale.len())),
+            translation: metadata
+                .translation
+                .filter(|translation| valid(translation.len())),
+            units: metadata.units.filter(|units| valid(units.len())),
+        }
+    }
+}
+
+fn as_f64_vec(value: &Value) -> Option<Vec<f64>> {
+    value
+        .as_array()?
+        .iter()
+        .map(Value::as_f64)
+        .collect::<Option<Vec<_>>>()
+}
+
+/// Compose a list of NGFF coordinate transformations into a scale and translation.
+pub(crate) fn compose_transforms(
+    transforms: &[Value],
+    scale: &mut Option<Vec<f64>>,
+    translation: &mut Option<Vec<f64>>,
+) {
+    for transform in transforms {
+        match transform.get("type").and_then(Value::as_str) {
+            Some("scale") => {
+                let Some(s) = transform.get("scale").and_then(as_f64_vec) else {
+                    continue;
+                };
+                if let Some(translation) = translation {
+                    std::iter::zip(translation.iter_mut(), &s).for_each(|(t, s)| *t *= s);
+                }
+                *scale = Some(match scale.take() {
+                    Some(scale) => std::iter::zip(scale, &s).map(|(a, b)| a * b).collect(),
+                    None => s,
+                });
+            }
+            Some("translation") => {
+                let Some(t) = transform.get("translation").and_then(as_f64_vec) else {
+                    continue;
+                };
+                *translation = Some(match translation.take() {
+                    Some(translation) => std::iter::zip(translation, &t)
+                        .map(|(a, b)| a + b)
+                        .collect(),
+                    None => t,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parse OME-Zarr multiscales metadata, selecting the dataset with `path` (or the first dataset).
+fn from_multiscales(
+    attributes: &Map<String, Value>,
+    path: Option<&str>,
+) -> Option<PhysicalMetadata> {
+    let multiscales = attributes
+        .get("ome")
+        .and_then(|ome| ome.get("multiscales"))
+        .or_else(|| attributes.get("multiscales"))?
+        .as_array()?
+        .first()?;
+    let datasets = multiscales.get("datasets")?.as_array()?;
+    let dataset = match path {
+        Some(path) => datasets
+            .iter()
+            .find(|dataset| dataset.get("path").and_then(Value::as_str) == Some(path))?,
+        None => datasets.first()?,
+    };
+
+    let mut scale = None;
+    let mut translation = None;
+    let transforms = |value: &Value| {
+        value
+            .get("coordinateTransformations")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+    };
+    compose_transforms(&transforms(dataset), &mut scale, &mut translation);
+    compose_transforms(&transforms(multiscales), &mut scale, &mut translation);
+
+    let units = multiscales
+        .get("axes")
+        .and_then(Value::as_array)
+        .map(|axes| {
+            axes.iter()
+                .map(|axis| {
+                    match axis.get("type").and_then(Value::as_str) {
+                        Some("channel") => Some("channel"),
+                        _ => axis.get("unit").and_then(Value::as_str),
+                    }
+                    .map(str::to_string)
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|units| units.iter().any(Option::is_some));
+    Some(PhysicalMetadata {
+        scale,
+        translation,
+        units,
+    })
+}
+
+/// Parse `spacing`, `origin`, and `units` attributes.
+fn from_spacing(attributes: &Map<String, Value>) -> Option<PhysicalMetadata> {
+    let spacing = attributes.get("spacing").and_then(|spacing| {
+        // Null spacing (e.g. a non-spatial axis) becomes 1
+        spacing
+            .as_array()?
+            .iter()
+            .map(|s| if s.is_null() { Some(1.0) } else { s.as_f64() })
+            .collect::<Option<Vec<_>>>()
+    });
+    let origin = attributes.get("origin").and_then(as_f64_vec);
+    let units = attributes.get("units").and_then(|units| {
+        Some(
+            units
+                .as_array()?
+                .iter()
+                .map(|unit| {
+                    // Unit symbols (e.g. `mm` in NRRD headers) are converted to OME-Zarr units
+                    unit.as_str()
+                        .filter(|unit| !unit.is_empty())
+                        .map(|unit| ome_xml_unit(unit).unwrap_or(unit).to_string())
+                })
+                .collect::<Vec<_>>(),
+        )
+    });
+    let metadata = PhysicalMetadata {
+        scale: spacing,
+        translation: origin,
+        units,
+    };
+    (!metadata.is_empty()).then_some(metadata)
+}
+
+/// Convert an OME-XML unit symbol (e.g. `µm`) to an OME-Zarr unit.
+pub(crate) fn ome_xml_unit(unit: &str) -> Option<&'static str> {
+    Some(match unit {
+        "Å" => "angstrom",
+        "nm" => "nanometer",
+        "µm" | "um" => "micrometer",
+        "mm" => "millimeter",
+        "cm" => "centimeter",
+        "m" => "meter",
+        _ => return None,
+    })
+}
+
+/// Returns the value of an XML attribute `name` in `element`.
+pub(crate) fn xml_attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let start = element.find(&format!(" {name}=\""))? + name.len() + 3;
+    let end = element[start..].find('"')?;
+    Some(&element[start..start + end])
+}
+
+/// Parse the physical pixel sizes of OME-XML in a string attribute.
+///
+/// Axes are matched to `x`, `y`, and `z` by their dimension names (case insensitive), or are assumed to be the trailing axes.
+fn from_ome_xml(
+    attributes: &Map<String, Value>,
+    dimension_names: Option<&[DimensionName]>,
+    dimensionality: usize,
+) -> Option<PhysicalMetadata> {
+    let pixels = attributes.values().find_map(|value| {
+        let xml = value.as_str()?;
+        let start = xml.find("<Pixels ")?;
+        let end = xml[start..].find('>')?;
+        Some(&xml[start..start + end])
+    })?;
+
+    let mut scale = vec![1.0; dimensionality];
+    let mut units = vec![None; dimensionality];
+    let mut found = false;
+    for (i, axis) in ["x", "y", "z"].into_iter().enumerate() {
+        let index = match dimension_names {
+            Some(dimension_names) => dimension_names.iter().position(|name| {
+                name.as_str()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(axis))
+            }),
+            None => dimensionality.checked_sub(i + 1),
+        };
+        let Some(index) = index else {
+            continue;
+        };
+        let upper = axis.to_uppercase();
+        if let Some(size) = xml_attribute(pixels, &format!("PhysicalSize{upper}"))
+            .and_then(|size| size.parse::<f64>().ok())
+        {
+            scale[index] = size;
+            // The default unit of OME-XML is the micrometer
+            let unit = xml_attribute(pixels, &format!("PhysicalSize{upper}Unit")).unwrap_or("µm");
+            units[index] = ome_xml_unit(unit).map(str::to_string);
+            found = true;
+        }
+    }
+    found.then_some(PhysicalMetadata {
+        scale: Some(scale),
+        translation: None,
+        units: Some(units),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn physical_metadata_multiscales() {
+        let group_attributes = attributes(serde_json::json!({
+            "ome": {
+                "version": "0.5",
+                "multiscales": [{
+                    "axes": [
+                        { "name": "c", "type": "channel" },
+                        { "name": "y", "type": "space", "unit": "micrometer" },
+                        { "name": "x", "type": "space", "unit": "micrometer" }
+                    ],
+                    "datasets": [
+                        { "path": "0", "coordinateTransformations": [{ "type": "scale", "scale": [1.0, 0.5, 0.5] }] },
+                        { "path": "1", "coordinateTransformations": [
+                            { "type": "scale", "scale": [1.0, 1.0, 1.0] },
+                            { "type": "translation", "translation": [0.0, 0.5, 0.5] }
+                        ] }
+                    ],
+                    "coordinateTransformations": [{ "type": "scale", "scale": [1.0, 2.0, 2.0] }]
+                }]
+            }
+        }));
+        let metadata =
+            PhysicalMetadata::from_attributes(&Map::new(), Some((&group_attributes, "1")), None, 3);
+        assert_eq!(
+            metadata,
+            PhysicalMetadata {
+                scale: Some(vec![1.0, 2.0, 2.0]),
+                translation: Some(vec![0.0, 1.0, 1.0]),
+                units: Some(vec![
+                    Some("channel".to_string()),
+                    Some("micrometer".to_string()),
+                    Some("micrometer".to_string())
+                ]),
+            }
+        );
+    }
+
+    #[test]
+    fn physical_metadata_spacing_ome_xml() {
+        let metadata = PhysicalMetadata::from_attributes(
+            &attributes(serde_json::json!({
+                "spacing": [2.0, 0.5, 0.5],
+                "units": ["micrometer", "micrometer", "micrometer"]
+            })),
+            None,
+            None,
+            3,
+        );
+        assert_eq!(metadata.scale, Some(vec![2.0, 0.5, 0.5]));
+        assert_eq!(metadata.translation, None);
+
+        let metadata = PhysicalMetadata::from_attributes(
+            &attributes(serde_json::json!({
+                "ome_xml": r#"<OME><Image><Pixels DimensionOrder="XYZCT" PhysicalSizeX="0.25" PhysicalSizeY="0.25" PhysicalSizeZ="1.5" PhysicalSizeZUnit="nm" Type="uint16"/></Image></OME>"#
+            })),
+            None,
+            Some(&["z", "y", "x"].map(DimensionName::new)),
+            3,
+        );
+        assert_eq!(metadata.scale, Some(vec![1.5, 0.25, 0.25]));
+        assert_eq!(
+            metadata.units,
+            Some(vec![
+                Some("nanometer".to_string()),
+                Some("micrometer".to_string()),
+                Some("micrometer".to_string())
+            ])
+        );
+
+        // Mismatched dimensionality is ignored
+        let metadata = PhysicalMetadata::from_attributes(
+            &attributes(serde_json::json!({ "spacing": [2.0, 0.5] })),
+            None,
+            None,
+            3,
+        );
+        assert!(metadata.is_empty());
+    }
+}
@@ -1,24 +1,195 @@
 use std::{
-    sync::{atomic::AtomicUsize, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex, Once,
+    },
     time::{Duration, Instant},
 };
 
+use indicatif::{FormattedDuration, ProgressState};
+use zarrs::storage::StorageError;
+
+/// The error of an operation cancelled through its [`ProgressCallback`] (e.g. on SIGINT).
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("cancelled")]
+pub struct Cancelled;
+
+impl From<Cancelled> for std::io::Error {
+    fn from(err: Cancelled) -> Self {
+        Self::new(std::io::ErrorKind::Interrupted, err)
+    }
+}
+
+impl From<Cancelled> for StorageError {
+    fn from(err: Cancelled) -> Self {
+        Self::from(err.to_string())
+    }
+}
+
+/// Returns a flag that is set on SIGINT or SIGTERM, for the cooperative cancellation of long running operations (see [`ProgressCallback::with_cancellation`]).
+///
+/// The signal handler is installed on the first call.
+/// A second signal exits the process immediately.
+pub fn cancel_on_signal() -> &'static AtomicBool {
+    static CANCELLED: AtomicBool = AtomicBool::new(false);
+    static HANDLER: Once = Once::new();
+    HANDLER.call_once(|| {
+        let handler = ctrlc::set_handler(|| {
+            if CANCELLED.swap(true, Ordering::SeqCst) {
+                std::process::exit(130);
+            }
+            eprintln!(
+                "Cancelling after the chunks in progress (interrupt again to exit immediately)"
+            );
+        });
+        if let Err(err) = handler {
+            eprintln!("Warning: cannot cancel on interrupt: {err}");
+        }
+    });
+    &CANCELLED
+}
+
+/// The unit of progress.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProgressMode {
+    /// The number of completed steps (e.g. chunks).
+    #[default]
+    Steps,
+    /// The number of bytes read, so steps are weighted by their size.
+    ///
+    /// Falls back to steps if the total number of bytes is unknown.
+    Bytes,
+}
+
+#[derive(Debug, Clone)]
 pub struct ProgressStats {
     pub step: usize,
     pub num_steps: usize,
+    /// The number of bytes read.
+    pub bytes_read: usize,
+    /// The number of bytes written.
+    pub bytes_written: usize,
+    /// The total number of bytes to read, if known.
+    pub num_bytes: Option<usize>,
     pub read: Duration,
     pub process: Duration,
-    pub process_steps: Vec<Duration>,
+    /// The processing time of named sub-steps (e.g. `gaussian`, `downsample`), in order of first use.
+    ///
+    /// Sub-step durations are included in the `process` duration.
+    pub process_steps: Vec<(&'static str, Duration)>,
     pub write: Duration,
 }
 
+impl ProgressStats {
+    /// Returns the progress position and length in the units of a [`ProgressMode`].
+    pub fn position_length(&self, mode: ProgressMode) -> (u64, u64) {
+        match (mode, self.num_bytes) {
+            (ProgressMode::Bytes, Some(num_bytes)) => (self.bytes_read as u64, num_bytes as u64),
+            _ => (self.step as u64, self.num_steps as u64),
+        }
+    }
+
+    /// Returns the processing time of each named sub-step formatted as `name:seconds`, separated by spaces.
+    pub fn process_steps_message(&self) -> String {
+        self.process_steps
+            .iter()
+            .map(|(name, duration)| format!("{name}:{:.2}", duration.as_secs_f32()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns the fraction of progress complete (in [0, 1]) in the units of a [`ProgressMode`].
+    pub fn fraction(&self, mode: ProgressMode) -> f64 {
+        let (position, length) = self.position_length(mode);
+        if length == 0 {
+            1.0
+        } else {
+            (position as f64 / length as f64).min(1.0)
+        }
+    }
+}
+
+/// Aggregate progress over the stages of a multi-stage pipeline (e.g. the filters of `zarrs_filter` or the levels of `zarrs_ome`).
+///
+/// Stages are weighted by their estimated work (e.g. the number of chunks multiplied by the memory per chunk), so the overall fraction complete (and the estimated total duration) accounts for stages of different sizes.
+pub struct PipelineProgress {
+    /// The cumulative weight before each stage, followed by the total weight.
+    cumulative: Vec<f64>,
+}
+
+impl PipelineProgress {
+    /// The length of a pipeline progress bar, such that the position is the fraction complete in hundredths of a percent.
+    pub const LENGTH: u64 = 10_000;
+
+    /// Create a new [`PipelineProgress`] with the estimated work of each stage.
+    ///
+    /// Weights that are not positive and finite are treated as zero, and stages are weighted equally if no stage has a positive weight.
+    pub fn new(weights: &[f64]) -> Self {
+        let weights = weights
+            .iter()
+            .map(|weight| {
+                if weight.is_finite() && *weight > 0.0 {
+                    *weight
+                } else {
+                    0.0
+                }
+            })
+            .collect::<Vec<_>>();
+        let uniform = weights.iter().all(|weight| *weight == 0.0);
+        let mut cumulative = Vec::with_capacity(weights.len() + 1);
+        cumulative.push(0.0);
+        for weight in weights {
+            let weight = if uniform { 1.0 } else { weight };
+            cumulative.push(cumulative.last().unwrap() + weight);
+        }
+        Self { cumulative }
+    }
+
+    /// Returns the number of stages.
+    pub fn num_stages(&self) -> usize {
+        self.cumulative.len() - 1
+    }
+
+    /// Returns the overall fraction complete (in [0, 1]) given the `fraction` complete of `stage`, where previous stages are complete.
+    pub fn fraction(&self, stage: usize, fraction: f64) -> f64 {
+        let total = *self.cumulative.last().unwrap();
+        if stage >= self.num_stages() || total == 0.0 {
+            return 1.0;
+        }
+        let (start, end) = (self.cumulative[stage], self.cumulative[stage + 1]);
+        (start + fraction.clamp(0.0, 1.0) * (end - start)) / total
+    }
+
+    /// Returns the position of a pipeline progress bar with length [`PipelineProgress::LENGTH`] given the `fraction` complete of `stage`.
+    pub fn position(&self, stage: usize, fraction: f64) -> u64 {
+        (self.fraction(stage, fraction) * Self::LENGTH as f64).round() as u64
+    }
+}
+
+/// An [`indicatif`] template key for the estimated total duration, e.g. `{duration_smoothed}`.
+///
+/// The estimate uses the average rate since the start rather than the recent rate of the `{duration}` key, so it is stable when steps vary in duration.
+pub fn duration_smoothed(state: &ProgressState, w: &mut dyn std::fmt::Write) {
+    let fraction = state.fraction();
+    let elapsed = state.elapsed();
+    let duration = if fraction > 0.0 {
+        elapsed.div_f32(fraction)
+    } else {
+        elapsed
+    };
+    let _ = write!(w, "{}", FormattedDuration(duration));
+}
+
 pub struct Progress<'a> {
     progress_callback: &'a ProgressCallback<'a>,
     step: AtomicUsize,
     num_steps: usize,
+    bytes_read: AtomicUsize,
+    bytes_written: AtomicUsize,
+    num_bytes: Option<usize>,
     duration_read: Mutex<Duration>,
     duration_process: Mutex<Duration>,
-    duration_process_steps: Mutex<Vec<Duration>>,
+    duration_process_steps: Mutex<Vec<(&'static str, Duration)>>,
     duration_write: Mutex<Duration>,
     // Chunk cache hit/cache miss?
     // Bytes read/written?
@@ -26,10 +197,24 @@ pub struct Progress<'a> {
 
 impl<'a> Progress<'a> {
     pub fn new(num_steps: usize, progress_callback: &'a ProgressCallback) -> Self {
+        Self::new_with_bytes(num_steps, None, progress_callback)
+    }
+
+    /// Create a new [`Progress`] with the total number of bytes to read, if known.
+    ///
+    /// This enables bytes-based progress (see [`ProgressMode::Bytes`]).
+    pub fn new_with_bytes(
+        num_steps: usize,
+        num_bytes: Option<usize>,
+        progress_callback: &'a ProgressCallback,
+    ) -> Self {
         let progress: Progress = Self {
             progress_callback,
             step: AtomicUsize::new(0),
             num_steps,
+            bytes_read: AtomicUsize::new(0),
+            bytes_written: AtomicUsize::new(0),
+            num_bytes,
             duration_read: Mutex::new(Duration::ZERO),
             duration_process: Mutex::new(Duration::ZERO),
             duration_process_steps: Mutex::new(vec![]),
@@ -55,16 +240,21 @@ impl<'a> Progress<'a> {
         result
     }
 
-    pub fn process_step<F: FnOnce() -> T, T>(&self, step: usize, f: F) -> T {
+    /// Process a named sub-step (e.g. `gaussian`, `downsample`, `cast`).
+    ///
+    /// The duration is added to the sub-step and the process duration, so this should not be nested within [`Progress::process`].
+    pub fn process_step<F: FnOnce() -> T, T>(&self, name: &'static str, f: F) -> T {
         let start = Instant::now();
         let result = f();
         let elapsed = start.elapsed();
+        *self.duration_process.lock().unwrap() += elapsed;
         {
             let mut steps = self.duration_process_steps.lock().unwrap();
-            if step >= steps.len() {
-                steps.resize(step + 1, Duration::ZERO);
+            if let Some((_, duration)) = steps.iter_mut().find(|(step, _)| *step == name) {
+                *duration += elapsed;
+            } else {
+                steps.push((name, elapsed));
             }
-            steps[step] += elapsed;
         }
         result
     }
@@ -77,8 +267,22 @@ impl<'a> Progress<'a> {
         result
     }
 
+    /// Add to the number of bytes read.
+    pub fn add_bytes_read(&self, bytes: usize) {
+        self.bytes_read
+            .fetch_add(bytes, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Add to the number of bytes written.
+    pub fn add_bytes_written(&self, bytes: usize) {
+        self.bytes_written
+            .fetch_add(bytes, std::sync::atomic::Ordering::SeqCst);
+    }
+
     pub fn stats(&self) -> ProgressStats {
         let step = self.step.load(std::sync::atomic::Ordering::SeqCst);
+        let bytes_read = self.bytes_read.load(std::sync::atomic::Ordering::SeqCst);
+        let bytes_written = self.bytes_written.load(std::sync::atomic::Ordering::SeqCst);
         let read = *self.duration_read.lock().unwrap();
         let process = *self.duration_process.lock().unwrap();
         let process_steps = self.duration_process_steps.lock().unwrap().clone();
@@ -86,6 +290,9 @@ impl<'a> Progress<'a> {
         ProgressStats {
             step,
             num_steps: self.num_steps,
+            bytes_read,
+            bytes_written,
+            num_bytes: self.num_bytes,
             read,
             process,
             process_steps,
@@ -98,22 +305,82 @@ impl<'a> Progress<'a> {
         self.progress_callback.update(stats);
     }
 
-    pub fn next(&self) {
+    /// Advance to the next step.
+    ///
+    /// # Errors
+    /// Returns [`Cancelled`] if the progress callback has been cancelled, so that chunk loops stop early.
+    pub fn next(&self) -> Result<(), Cancelled> {
         self.step.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         self.update();
+        if self.progress_callback.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
     }
 }
 
 pub struct ProgressCallback<'a> {
     callback: &'a (dyn Fn(ProgressStats) + Send + Sync),
+    cancelled: Option<&'a AtomicBool>,
 }
 
 impl<'a> ProgressCallback<'a> {
     pub fn new(callback: &'a (dyn Fn(ProgressStats) + Send + Sync)) -> Self {
-        Self { callback }
+        Self {
+            callback,
+            cancelled: None,
+        }
+    }
+
+    /// Cancel operations reporting progress to this callback when `cancelled` is set (e.g. by [`cancel_on_signal`]).
+    ///
+    /// Cancellation is checked as each step completes (see [`Progress::next`]).
+    #[must_use]
+    pub fn with_cancellation(mut self, cancelled: &'a AtomicBool) -> Self {
+        self.cancelled = Some(cancelled);
+        self
+    }
+
+    /// Returns true if operations reporting progress to this callback have been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+            .is_some_and(|cancelled| cancelled.load(Ordering::SeqCst))
     }
 
     pub fn update(&self, stats: ProgressStats) {
         (self.callback)(stats);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_progress() {
+        let pipeline = PipelineProgress::new(&[1.0, 3.0]);
+        assert_eq!(pipeline.num_stages(), 2);
+        assert_eq!(pipeline.fraction(0, 0.0), 0.0);
+        assert_eq!(pipeline.fraction(0, 1.0), 0.25);
+        assert_eq!(pipeline.fraction(1, 0.5), 0.625);
+        assert_eq!(pipeline.fraction(2, 0.0), 1.0);
+        assert_eq!(pipeline.position(1, 1.0), PipelineProgress::LENGTH);
+
+        // Stages without an estimate are weighted equally
+        let pipeline = PipelineProgress::new(&[0.0, f64::NAN]);
+        assert_eq!(pipeline.fraction(1, 0.0), 0.5);
+    }
+
+    #[test]
+    fn progress_cancellation() {
+        let callback = |_| {};
+        let cancelled = AtomicBool::new(false);
+        let progress_callback = ProgressCallback::new(&callback).with_cancellation(&cancelled);
+        let progress = Progress::new(3, &progress_callback);
+        assert!(progress.next().is_ok());
+        cancelled.store(true, Ordering::SeqCst);
+        assert!(progress.next().is_err());
+        assert_eq!(progress.stats().step, 2);
+    }
+}
@@ -0,0 +1,331 @@
+//! Box and point queries of arrays, reading only the intersecting chunks.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon_iter_concurrent_limit::iter_concurrent_limit;
+use zarrs::{array::Array, array_subset::ArraySubset, filesystem::FilesystemStore};
+
+use crate::{concurrent_target, filter::filters::local_maxima::retrieve_values};
+
+/// A statistic of the elements of a box.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Statistic {
+    /// The number of (non-NaN) elements.
+    Count,
+    /// The sum.
+    Sum,
+    /// The mean.
+    Mean,
+    /// The minimum.
+    Min,
+    /// The maximum.
+    Max,
+}
+
+impl Statistic {
+    /// Returns the name of the statistic.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Mean => "mean",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+
+/// The statistics of the elements of a box.
+///
+/// NaN elements are excluded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxStatistics {
+    /// The number of (non-NaN) elements.
+    pub count: u64,
+    /// The sum.
+    pub sum: f64,
+    /// The minimum, infinite if there are no elements.
+    pub min: f64,
+    /// The maximum, negative infinite if there are no elements.
+    pub max: f64,
+}
+
+impl Default for BoxStatistics {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl BoxStatistics {
+    /// Add an element `value`, which is ignored if NaN.
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Merge the statistics of `other` (e.g. of another chunk of a box).
+    pub fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Returns the value of `statistic`.
+    ///
+    /// The mean, minimum, and maximum are NaN if there are no elements.
+    #[must_use]
+    pub fn statistic(&self, statistic: Statistic) -> f64 {
+        if self.count == 0 && !matches!(statistic, Statistic::Count | Statistic::Sum) {
+            return f64::NAN;
+        }
+        match statistic {
+            Statistic::Count => self.count as f64,
+            Statistic::Sum => self.sum,
+            Statistic::Mean => self.sum / self.count as f64,
+            Statistic::Min => self.min,
+            Statistic::Max => self.max,
+        }
+    }
+}
+
+/// Parse a box given as an offset and shape (e.g. `0,0,0:64,64,64`).
+///
+/// # Errors
+/// Returns an error if the box is not an `OFFSET:SHAPE` pair of comma delimited integers with the same length.
+pub fn parse_box(query_box: &str) -> Result<ArraySubset, String> {
+    let (offset, shape) = query_box
+        .split_once(':')
+        .ok_or_else(|| format!("{query_box} is not an OFFSET:SHAPE box"))?;
+    let parse = |list: &str| {
+        list.split(',')
+            .map(|value| {
+                value
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|err| format!("{query_box} is not an OFFSET:SHAPE box: {err}"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    ArraySubset::new_with_start_shape(parse(offset)?, parse(shape)?)
+        .map_err(|err| format!("{query_box} is not an OFFSET:SHAPE box: {err}"))
+}
+
+/// Parse points from CSV `text`, with the coordinates of a point per row.
+///
+/// The first `dimensionality` columns are the coordinates, and other columns are ignored (e.g. the value column of `zarrs_zarr2points`).
+/// The first row is skipped if it is a header.
+///
+/// # Errors
+/// Returns an error if a row has too few columns or a coordinate is not a non-negative integer.
+pub fn parse_points_csv(text: &str, dimensionality: usize) -> anyhow::Result<Vec<Vec<u64>>> {
+    let mut points = vec![];
+    for (row, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let columns = line.split(',').map(str::trim).collect::<Vec<_>>();
+        if columns.len() < dimensionality {
+            anyhow::bail!(
+                "row {row} has {} columns, but the array has {dimensionality} dimensions",
+                columns.len()
+            );
+        }
+        match columns[..dimensionality]
+            .iter()
+            .map(|column| column.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(point) => points.push(point),
+            Err(_) if row == 0 => {} // header
+            Err(err) => anyhow::bail!("row {row} is not a point: {err}"),
+        }
+    }
+    Ok(points)
+}
+
+/// Returns an error if `subset` is not within `array`.
+fn check_subset(array: &Array<FilesystemStore>, subset: &ArraySubset) -> anyhow::Result<()> {
+    let shape = array.shape();
+    if subset.dimensionality() != shape.len()
+        || std::iter::zip(subset.end_exc(), shape).any(|(end, size)| end > *size)
+    {
+        anyhow::bail!(
+            "the box with offset {:?} and shape {:?} is not within the array with shape {shape:?}",
+            subset.start(),
+            subset.shape()
+        );
+    }
+    Ok(())
+}
+
+/// Returns the values of the elements of `subset` of `array` in C order.
+///
+/// # Errors
+/// Returns an error if `subset` is not within the array, the data type is not numeric, or a chunk cannot be read.
+pub fn query_box_values(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+) -> anyhow::Result<Vec<f64>> {
+    check_subset(array, subset)?;
+    Ok(retrieve_values(array, subset)?)
+}
+
+/// Returns the statistics of the elements of `subset` of `array`.
+///
+/// Only the chunks intersecting `subset` are read, and each chunk is reduced independently so that large boxes are not held in memory.
+///
+/// # Errors
+/// Returns an error if `subset` is not within the array, the data type is not numeric, or a chunk cannot be read.
+pub fn query_box_statistics(
+    array: &Array<FilesystemStore>,
+    subset: &ArraySubset,
+    concurrent_chunks: Option<usize>,
+) -> anyhow::Result<BoxStatistics> {
+    check_subset(array, subset)?;
+    let statistics = Mutex::new(BoxStatistics::default());
+    let Some(chunks) = array.chunks_in_array_subset(subset)? else {
+        anyhow::bail!("the chunk grid does not cover the box");
+    };
+    let indices = chunks.indices();
+    iter_concurrent_limit!(
+        concurrent_chunks.unwrap_or_else(concurrent_target).max(1),
+        indices,
+        try_for_each,
+        |chunk_indices: Vec<u64>| {
+            let chunk_subset = array.chunk_subset(&chunk_indices)?;
+            let mut chunk_statistics = BoxStatistics::default();
+            for value in retrieve_values(array, &chunk_subset.overlap(subset)?)? {
+                chunk_statistics.add(value);
+            }
+            statistics.lock().unwrap().merge(&chunk_statistics);
+            Ok::<_, anyhow::Error>(())
+        }
+    )?;
+    Ok(statistics.into_inner().unwrap())
+}
+
+/// Returns the values of `array` at `points`.
+///
+/// Points are grouped by chunk, and only the bounding box of the points within each chunk is read.
+///
+/// # Errors
+/// Returns an error if a point is not within the array, the data type is not numeric, or a chunk cannot be read.
+pub fn query_points(
+    array: &Array<FilesystemStore>,
+    points: &[Vec<u64>],
+    concurrent_chunks: Option<usize>,
+) -> anyhow::Result<Vec<f64>> {
+    let shape = array.shape();
+    let mut chunk_points = HashMap::<Vec<u64>, Vec<usize>>::new();
+    for (i, point) in points.iter().enumerate() {
+        if point.len() != shape.len() || std::iter::zip(point, shape).any(|(p, s)| p >= s) {
+            anyhow::bail!("the point {point:?} is not within the array with shape {shape:?}");
+        }
+        let Some(chunk_indices) = array.chunk_grid().chunk_indices(point, shape)? else {
+            anyhow::bail!("the chunk grid does not cover the point {point:?}");
+        };
+        chunk_points.entry(chunk_indices).or_default().push(i);
+    }
+
+    let values = Mutex::new(vec![f64::NAN; points.len()]);
+    let chunk_points = chunk_points.into_values().collect::<Vec<_>>();
+    iter_concurrent_limit!(
+        concurrent_chunks.unwrap_or_else(concurrent_target).max(1),
+        chunk_points,
+        try_for_each,
+        |point_indices: Vec<usize>| {
+            let axis_range = |axis: usize| {
+                let coordinates = point_indices.iter().map(|i| points[*i][axis]);
+                coordinates.clone().min().unwrap()..coordinates.max().unwrap() + 1
+            };
+            let subset =
+                ArraySubset::new_with_ranges(&(0..shape.len()).map(axis_range).collect::<Vec<_>>());
+            let subset_values = retrieve_values(array, &subset)?;
+            let mut values = values.lock().unwrap();
+            for i in point_indices {
+                let index = itertools::izip!(&points[i], subset.start(), subset.shape())
+                    .fold(0, |index, (p, start, size)| index * size + (p - start));
+                values[i] = subset_values[usize::try_from(index).unwrap()];
+            }
+            Ok::<_, anyhow::Error>(())
+        }
+    )?;
+    Ok(values.into_inner().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zarrs::array::{ArrayBuilder, DataType, FillValue};
+
+    use super::*;
+
+    #[test]
+    fn query_parse() {
+        let subset = parse_box("1,2:3,4").unwrap();
+        assert_eq!(subset, ArraySubset::new_with_ranges(&[1..4, 2..6]));
+        assert!(parse_box("1,2").is_err());
+        assert!(parse_box("1,2:3").is_err());
+
+        let points = parse_points_csv("y,x,value\n1,2,5.0\n\n3, 4,6.0\n", 2).unwrap();
+        assert_eq!(points, [[1, 2], [3, 4]]);
+        assert!(parse_points_csv("1,2\n3,a\n", 2).is_err());
+        assert!(parse_points_csv("1\n", 2).is_err());
+    }
+
+    #[test]
+    fn query_box_points() -> Result<(), Box<dyn std::error::Error>> {
+        let path = tempfile::TempDir::new()?;
+        let store = Arc::new(FilesystemStore::new(path.path())?);
+        let array = ArrayBuilder::new(
+            vec![4, 5],
+            DataType::Float32,
+            vec![2, 2].try_into()?,
+            FillValue::from(0.0f32),
+        )
+        .build(store, "/")?;
+        let elements = (0..20).map(|i| i as f32).collect::<Vec<_>>();
+        array.store_array_subset_elements::<f32>(&array.subset_all(), &elements)?;
+        array.store_array_subset_elements::<f32>(
+            &ArraySubset::new_with_ranges(&[1..2, 1..2]),
+            &[f32::NAN],
+        )?;
+
+        // Elements 5, 7, 10, 11, 12 (6 is NaN)
+        let statistics =
+            query_box_statistics(&array, &ArraySubset::new_with_ranges(&[1..3, 0..3]), None)?;
+        assert_eq!(statistics.statistic(Statistic::Count), 5.0);
+        assert_eq!(statistics.statistic(Statistic::Sum), 45.0);
+        assert_eq!(statistics.statistic(Statistic::Mean), 9.0);
+        assert_eq!(statistics.statistic(Statistic::Min), 5.0);
+        assert_eq!(statistics.statistic(Statistic::Max), 12.0);
+        assert!(
+            query_box_statistics(&array, &ArraySubset::new_with_ranges(&[3..5, 0..1]), None)
+                .is_err()
+        );
+        assert_eq!(
+            query_box_values(&array, &ArraySubset::new_with_ranges(&[2..4, 3..5]))?,
+            [13.0, 14.0, 18.0, 19.0]
+        );
+
+        let values = query_points(&array, &[vec![3, 4], vec![0, 0], vec![2, 3]], None)?;
+        assert_eq!(values, [19.0, 0.0, 13.0]);
+        assert!(query_points(&array, &[vec![4, 0]], None).is_err());
+        Ok(())
+    }
+}
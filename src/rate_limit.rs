@@ -0,0 +1,243 @@
+//! Rate limiting of storage operations.
+//!
+//! [`RateLimitStorageAdapter`] limits the throughput and request rate of a store, so that long running tools do not saturate shared network egress or trip the request rate limits of object stores.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use zarrs::{
+    byte_range::ByteRange,
+    storage::{
+        Bytes, ListableStorageTraits, MaybeBytes, ReadableStorageTraits, StorageError, StoreKey,
+        StoreKeyOffsetValue, StoreKeys, StoreKeysPrefixes, StorePrefix, WritableStorageTraits,
+    },
+};
+
+/// A limiter of the rate of some quantity (e.g. bytes or requests) per second.
+///
+/// Callers block until their quantity fits within the rate, in the order that they call [`RateLimiter::acquire`].
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter of `rate` per second.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not positive.
+    #[must_use]
+    pub fn new(rate: f64) -> Self {
+        assert!(rate > 0.0, "the rate must be positive");
+        Self {
+            rate,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Returns the rate per second.
+    #[must_use]
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Reserve `amount` of the rate, returning the duration to wait before it is available.
+    pub fn reserve(&self, amount: f64) -> Duration {
+        let mut next = self.next.lock().unwrap();
+        let now = Instant::now();
+        let start = (*next).max(now);
+        *next = start + Duration::from_secs_f64(amount / self.rate);
+        start - now
+    }
+
+    /// Block until `amount` of the rate is available.
+    pub fn acquire(&self, amount: f64) {
+        let wait = self.reserve(amount);
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// A storage adapter that limits the read throughput, write throughput, and request rate of a store.
+///
+/// Reads are limited after bytes are retrieved (as their size is unknown beforehand) and writes are limited before bytes are stored.
+pub struct RateLimitStorageAdapter<TStorage: ?Sized> {
+    storage: Arc<TStorage>,
+    read: Option<RateLimiter>,
+    write: Option<RateLimiter>,
+    requests: Option<RateLimiter>,
+}
+
+impl<TStorage: ?Sized> RateLimitStorageAdapter<TStorage> {
+    /// Create a new rate limited store.
+    ///
+    /// Limits are in bytes per second for reads and writes, and operations per second for requests.
+    /// A limit of [`None`] is unlimited.
+    pub fn new(
+        storage: Arc<TStorage>,
+        read_bytes_per_sec: Option<f64>,
+        write_bytes_per_sec: Option<f64>,
+        requests_per_sec: Option<f64>,
+    ) -> Self {
+        Self {
+            storage,
+            read: read_bytes_per_sec.map(RateLimiter::new),
+            write: write_bytes_per_sec.map(RateLimiter::new),
+            requests: requests_per_sec.map(RateLimiter::new),
+        }
+    }
+
+    /// Returns true if no limits are set.
+    pub fn is_unlimited(&self) -> bool {
+        self.read.is_none() && self.write.is_none() && self.requests.is_none()
+    }
+
+    fn request(&self) {
+        if let Some(requests) = &self.requests {
+            requests.acquire(1.0);
+        }
+    }
+
+    fn read(&self, bytes: usize) {
+        if let Some(read) = &self.read {
+            read.acquire(bytes as f64);
+        }
+    }
+
+    fn write(&self, bytes: usize) {
+        if let Some(write) = &self.write {
+            write.acquire(bytes as f64);
+        }
+    }
+}
+
+impl<TStorage: ?Sized + ReadableStorageTraits> ReadableStorageTraits
+    for RateLimitStorageAdapter<TStorage>
+{
+    fn get(&self, key: &StoreKey) -> Result<MaybeBytes, StorageError> {
+        self.request();
+        let bytes = self.storage.get(key)?;
+        self.read(bytes.as_ref().map_or(0, Bytes::len));
+        Ok(bytes)
+    }
+
+    fn get_partial_values_key(
+        &self,
+        key: &StoreKey,
+        byte_ranges: &[ByteRange],
+    ) -> Result<Option<Vec<Bytes>>, StorageError> {
+        self.request();
+        let bytes = self.storage.get_partial_values_key(key, byte_ranges)?;
+        self.read(
+            bytes
+                .as_ref()
+                .map_or(0, |bytes| bytes.iter().map(Bytes::len).sum()),
+        );
+        Ok(bytes)
+    }
+
+    fn size_key(&self, key: &StoreKey) -> Result<Option<u64>, StorageError> {
+        self.request();
+        self.storage.size_key(key)
+    }
+}
+
+impl<TStorage: ?Sized + ListableStorageTraits> ListableStorageTraits
+    for RateLimitStorageAdapter<TStorage>
+{
+    fn list(&self) -> Result<StoreKeys, StorageError> {
+        self.request();
+        self.storage.list()
+    }
+
+    fn list_prefix(&self, prefix: &StorePrefix) -> Result<StoreKeys, StorageError> {
+        self.request();
+        self.storage.list_prefix(prefix)
+    }
+
+    fn list_dir(&self, prefix: &StorePrefix) -> Result<StoreKeysPrefixes, StorageError> {
+        self.request();
+        self.storage.list_dir(prefix)
+    }
+
+    fn size_prefix(&self, prefix: &StorePrefix) -> Result<u64, StorageError> {
+        self.request();
+        self.storage.size_prefix(prefix)
+    }
+
+    fn size(&self) -> Result<u64, StorageError> {
+        self.request();
+        self.storage.size()
+    }
+}
+
+impl<TStorage: ?Sized + WritableStorageTraits> WritableStorageTraits
+    for RateLimitStorageAdapter<TStorage>
+{
+    fn set(&self, key: &StoreKey, value: Bytes) -> Result<(), StorageError> {
+        self.request();
+        self.write(value.len());
+        self.storage.set(key, value)
+    }
+
+    fn set_partial_values(
+        &self,
+        key_offset_values: &[StoreKeyOffsetValue],
+    ) -> Result<(), StorageError> {
+        self.request();
+        self.write(
+            key_offset_values
+                .iter()
+                .map(|key_offset_value| key_offset_value.value().len())
+                .sum(),
+        );
+        self.storage.set_partial_values(key_offset_values)
+    }
+
+    fn erase(&self, key: &StoreKey) -> Result<(), StorageError> {
+        self.request();
+        self.storage.erase(key)
+    }
+
+    fn erase_prefix(&self, prefix: &StorePrefix) -> Result<(), StorageError> {
+        self.request();
+        self.storage.erase_prefix(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::storage::store::MemoryStore;
+
+    use super::*;
+
+    #[test]
+    fn rate_limiter() {
+        let limiter = RateLimiter::new(100.0);
+        assert!(limiter.reserve(50.0).is_zero());
+        let wait = limiter.reserve(50.0);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+        let wait = limiter.reserve(1.0);
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn rate_limit_storage_adapter() -> Result<(), Box<dyn std::error::Error>> {
+        let store = RateLimitStorageAdapter::new(
+            Arc::new(MemoryStore::new()),
+            Some(1e6),
+            Some(1e6),
+            Some(1000.0),
+        );
+        assert!(!store.is_unlimited());
+        let key = StoreKey::new("a/b")?;
+        store.set(&key, vec![1, 2, 3].into())?;
+        assert_eq!(store.get(&key)?.unwrap(), Bytes::from(vec![1, 2, 3]));
+        assert_eq!(store.size()?, 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,412 @@
+//! On-disk caching of reads from remote stores.
+//!
+//! [`ReadCacheStorageAdapter`] caches the values read from a store in a local directory, so that repeated runs over the same remote input (e.g. comparing codecs) do not download the same chunks again.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+use zarrs::{
+    byte_range::ByteRange,
+    storage::{
+        Bytes, ListableStorageTraits, MaybeBytes, ReadableStorageTraits, StorageError, StoreKey,
+        StoreKeys, StoreKeysPrefixes, StorePrefix,
+    },
+};
+
+/// The file extension of partially written cache entries.
+const TMP_EXTENSION: &str = "tmp";
+
+/// The length of the hex digest naming a cache entry.
+const ENTRY_NAME_LENGTH: usize = 64;
+
+/// A storage adapter that caches the values read from a store in a local directory.
+///
+/// Cache entries are keyed by a store identifier (e.g. a URL), the store key, and the byte ranges.
+/// A cache hit does not make any request to the store, so values that change in the store are not detected and stale values are returned until they are evicted.
+/// Use a new cache directory or identifier if the store changes.
+/// The least recently used entries are evicted if the cache exceeds its maximum size.
+///
+/// Only files named like cache entries (a 64 character hex digest) are counted towards the cache size and evicted, so other files in the directory are left untouched.
+///
+/// The cache is best effort, errors reading or writing cache entries are ignored.
+pub struct ReadCacheStorageAdapter<TStorage: ?Sized> {
+    storage: Arc<TStorage>,
+    directory: PathBuf,
+    identifier: String,
+    max_size: Option<u64>,
+    size: Mutex<u64>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Returns true if `name` is a hex digest naming a cache entry.
+fn is_entry_name(name: &str) -> bool {
+    name.len() == ENTRY_NAME_LENGTH && name.bytes().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Returns true if `name` is a partially written cache entry (i.e. `<entry>.<16 hex digits>.tmp`).
+fn is_tmp_entry_name(name: &str) -> bool {
+    name.strip_suffix(TMP_EXTENSION)
+        .and_then(|name| name.strip_suffix('.'))
+        .and_then(|name| name.split_once('.'))
+        .is_some_and(|(entry, suffix)| {
+            is_entry_name(entry)
+                && suffix.len() == 16
+                && suffix.bytes().all(|c| c.is_ascii_hexdigit())
+        })
+}
+
+/// Returns the files (path, size, and modification time) in `directory` with a name satisfying `filter`.
+fn cache_files(
+    directory: &Path,
+    filter: impl Fn(&str) -> bool,
+) -> std::io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let mut entries = vec![];
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        if !entry.file_name().to_str().is_some_and(&filter) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            entries.push((entry.path(), metadata.len(), metadata.modified()?));
+        }
+    }
+    Ok(entries)
+}
+
+/// Returns the cache entry of partial `values`, the little-endian [`u64`] length of each value followed by the values.
+fn partial_values_entry(values: &[Bytes]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(
+        values.len() * size_of::<u64>() + values.iter().map(Bytes::len).sum::<usize>(),
+    );
+    for value in values {
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    }
+    for value in values {
+        entry.extend_from_slice(value);
+    }
+    entry
+}
+
+/// Split a cache entry of `num_values` partial values written by [`partial_values_entry`].
+///
+/// Returns [`None`] if the entry is corrupt.
+fn split_partial_values_entry(entry: &Bytes, num_values: usize) -> Option<Vec<Bytes>> {
+    let header_length = num_values * size_of::<u64>();
+    let lengths = entry
+        .get(..header_length)?
+        .chunks_exact(size_of::<u64>())
+        .map(|length| usize::try_from(u64::from_le_bytes(length.try_into().unwrap())).ok())
+        .collect::<Option<Vec<_>>>()?;
+    if lengths
+        .iter()
+        .try_fold(header_length, |sum, length| sum.checked_add(*length))
+        != Some(entry.len())
+    {
+        return None;
+    }
+    let mut offset = header_length;
+    Some(
+        lengths
+            .iter()
+            .map(|length| {
+                offset += length;
+                entry.slice(offset - length..offset)
+            })
+            .collect(),
+    )
+}
+
+impl<TStorage: ?Sized> ReadCacheStorageAdapter<TStorage> {
+    /// Create a new read cache of `storage` in `directory`.
+    ///
+    /// `identifier` distinguishes stores sharing a cache directory (e.g. the URL of the store).
+    /// The cache size is unlimited if `max_size` is [`None`].
+    ///
+    /// # Errors
+    /// Returns a [`std::io::Error`] if the cache directory cannot be created or read.
+    pub fn new(
+        storage: Arc<TStorage>,
+        directory: &Path,
+        identifier: &str,
+        max_size: Option<u64>,
+    ) -> std::io::Result<Self> {
+        std::fs::create_dir_all(directory)?;
+        for (path, _, _) in cache_files(directory, is_tmp_entry_name)? {
+            // Left behind by an interrupted write
+            let _ = std::fs::remove_file(path);
+        }
+        let size = cache_files(directory, is_entry_name)?
+            .iter()
+            .map(|(_, entry_size, _)| entry_size)
+            .sum();
+        Ok(Self {
+            storage,
+            directory: directory.to_path_buf(),
+            identifier: identifier.to_string(),
+            max_size,
+            size: Mutex::new(size),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns the number of reads served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of reads from the store.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Returns the path of the cache entry of `key` with optional `byte_ranges`.
+    fn entry_path(&self, key: &StoreKey, byte_ranges: Option<&[ByteRange]>) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(self.identifier.as_bytes());
+        hasher.update([0]);
+        hasher.update(key.as_str().as_bytes());
+        if let Some(byte_ranges) = byte_ranges {
+            for byte_range in byte_ranges {
+                hasher.update(format!("{byte_range:?}").as_bytes());
+            }
+        }
+        let name = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        self.directory.join(name)
+    }
+
+    /// Read the cache entry at `path`, marking it as recently used.
+    fn read_entry(&self, path: &Path) -> Option<Bytes> {
+        let bytes = std::fs::read(path).ok()?;
+        if let Ok(file) = std::fs::File::options().write(true).open(path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(Bytes::from(bytes))
+    }
+
+    /// Write the cache entry at `path`, evicting the least recently used entries if the cache exceeds its maximum size.
+    fn write_entry(&self, path: &Path, bytes: &[u8]) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if self
+            .max_size
+            .is_some_and(|max_size| bytes.len() as u64 > max_size)
+        {
+            return;
+        }
+        let path_tmp = path.with_extension(format!("{:016x}.{TMP_EXTENSION}", fastrand::u64(..)));
+        let written = std::fs::File::create(&path_tmp)
+            .and_then(|mut file| file.write_all(bytes))
+            .and_then(|()| std::fs::rename(&path_tmp, path));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&path_tmp);
+            return;
+        }
+
+        let mut size = self.size.lock().unwrap();
+        *size += bytes.len() as u64;
+        if let Some(max_size) = self.max_size {
+            if *size > max_size {
+                self.evict(&mut size, max_size);
+            }
+        }
+    }
+
+    /// Evict the least recently used entries until the cache `size` is at most `max_size`.
+    fn evict(&self, size: &mut u64, max_size: u64) {
+        let Ok(mut entries) = cache_files(&self.directory, is_entry_name) else {
+            return;
+        };
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        *size = entries.iter().map(|(_, entry_size, _)| entry_size).sum();
+        for (path, entry_size, _) in entries {
+            if *size <= max_size {
+                break;
+            }
+            if std::fs::remove_file(path).is_ok() {
+                *size -= entry_size;
+            }
+        }
+    }
+}
+
+impl<TStorage: ?Sized + ReadableStorageTraits> ReadableStorageTraits
+    for ReadCacheStorageAdapter<TStorage>
+{
+    fn get(&self, key: &StoreKey) -> Result<MaybeBytes, StorageError> {
+        let path = self.entry_path(key, None);
+        if let Some(bytes) = self.read_entry(&path) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(bytes));
+        }
+        let bytes = self.storage.get(key)?;
+        if let Some(bytes) = &bytes {
+            self.write_entry(&path, bytes);
+        }
+        Ok(bytes)
+    }
+
+    fn get_partial_values_key(
+        &self,
+        key: &StoreKey,
+        byte_ranges: &[ByteRange],
+    ) -> Result<Option<Vec<Bytes>>, StorageError> {
+        let path = self.entry_path(key, Some(byte_ranges));
+        if let Some(values) = self
+            .read_entry(&path)
+            .and_then(|bytes| split_partial_values_entry(&bytes, byte_ranges.len()))
+        {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(values));
+        }
+        let values = self.storage.get_partial_values_key(key, byte_ranges)?;
+        if let Some(values) = &values {
+            self.write_entry(&path, &partial_values_entry(values));
+        }
+        Ok(values)
+    }
+
+    fn size_key(&self, key: &StoreKey) -> Result<Option<u64>, StorageError> {
+        self.storage.size_key(key)
+    }
+}
+
+impl<TStorage: ?Sized + ListableStorageTraits> ListableStorageTraits
+    for ReadCacheStorageAdapter<TStorage>
+{
+    fn list(&self) -> Result<StoreKeys, StorageError> {
+        self.storage.list()
+    }
+
+    fn list_prefix(&self, prefix: &StorePrefix) -> Result<StoreKeys, StorageError> {
+        self.storage.list_prefix(prefix)
+    }
+
+    fn list_dir(&self, prefix: &StorePrefix) -> Result<StoreKeysPrefixes, StorageError> {
+        self.storage.list_dir(prefix)
+    }
+
+    fn size_prefix(&self, prefix: &StorePrefix) -> Result<u64, StorageError> {
+        self.storage.size_prefix(prefix)
+    }
+
+    fn size(&self) -> Result<u64, StorageError> {
+        self.storage.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::storage::{store::MemoryStore, WritableStorageTraits};
+
+    use super::*;
+
+    #[test]
+    fn read_cache_storage_adapter() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = tempfile::TempDir::new()?;
+        let store = Arc::new(MemoryStore::new());
+        let key_a = StoreKey::new("a")?;
+        let key_b = StoreKey::new("b")?;
+        store.set(&key_a, vec![1, 2, 3, 4].into())?;
+        store.set(&key_b, vec![5, 6, 7, 8].into())?;
+
+        let cache = ReadCacheStorageAdapter::new(store.clone(), directory.path(), "mem", Some(24))?;
+        assert_eq!(cache.get(&key_a)?.unwrap(), Bytes::from(vec![1, 2, 3, 4]));
+        assert_eq!(cache.get(&key_a)?.unwrap(), Bytes::from(vec![1, 2, 3, 4]));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        let byte_ranges = [ByteRange::FromStart(1, Some(2)), ByteRange::Suffix(1)];
+        let values = vec![Bytes::from(vec![6, 7]), Bytes::from(vec![8])];
+        assert_eq!(
+            cache.get_partial_values_key(&key_b, &byte_ranges)?.unwrap(),
+            values
+        );
+        assert_eq!(
+            cache.get_partial_values_key(&key_b, &byte_ranges)?.unwrap(),
+            values
+        );
+        assert_eq!((cache.hits(), cache.misses()), (2, 2));
+
+        // A cache hit does not read the store, so a changed value is stale
+        store.set(&key_a, vec![9, 9].into())?;
+        assert_eq!(cache.get(&key_a)?.unwrap(), Bytes::from(vec![1, 2, 3, 4]));
+        assert_eq!((cache.hits(), cache.misses()), (3, 2));
+
+        // A different identifier reads the store again
+        let cache =
+            ReadCacheStorageAdapter::new(store.clone(), directory.path(), "mem2", Some(24))?;
+        assert_eq!(cache.get(&key_a)?.unwrap(), Bytes::from(vec![9, 9]));
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+
+        // Entries are evicted to fit the maximum size, and persist across instances
+        assert!(*cache.size.lock().unwrap() <= 7);
+        let cache = ReadCacheStorageAdapter::new(store, directory.path(), "mem2", Some(24))?;
+        assert!(*cache.size.lock().unwrap() <= 7);
+        assert_eq!(cache.get(&key_a)?.unwrap(), Bytes::from(vec![9, 9]));
+        assert_eq!((cache.hits(), cache.misses()), (1, 0));
+        assert!(cache.get(&StoreKey::new("c")?)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn read_cache_partial_values_entry() {
+        let values = vec![Bytes::from(vec![1, 2]), Bytes::new(), Bytes::from(vec![3])];
+        let entry = Bytes::from(partial_values_entry(&values));
+        assert_eq!(split_partial_values_entry(&entry, 3), Some(values));
+        assert_eq!(split_partial_values_entry(&entry, 2), None);
+        assert_eq!(split_partial_values_entry(&entry.slice(..25), 3), None);
+    }
+
+    #[test]
+    fn read_cache_entry_names() {
+        let entry = "0123456789abcdef".repeat(4);
+        assert!(is_entry_name(&entry));
+        assert!(!is_entry_name(&entry[1..]));
+        assert!(!is_entry_name(&format!("{}g", &entry[1..])));
+        assert!(is_tmp_entry_name(&format!("{entry}.0123456789abcdef.tmp")));
+        assert!(!is_tmp_entry_name(&format!("{entry}.tmp")));
+        assert!(!is_tmp_entry_name("download.tmp"));
+    }
+
+    #[test]
+    fn read_cache_ignores_other_files() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = tempfile::TempDir::new()?;
+        let other = ["notes.txt", "download.tmp", "data.0123456789abcdef.tmp"];
+        for name in other {
+            std::fs::write(directory.path().join(name), [0; 16])?;
+        }
+        let store = Arc::new(MemoryStore::new());
+        let keys = ["a", "b", "c"]
+            .into_iter()
+            .map(StoreKey::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        for key in &keys {
+            store.set(key, vec![1, 2, 3, 4].into())?;
+        }
+
+        // Other files are not counted towards the cache size, removed on startup, or evicted
+        let cache = ReadCacheStorageAdapter::new(store, directory.path(), "mem", Some(8))?;
+        assert_eq!(*cache.size.lock().unwrap(), 0);
+        for key in &keys {
+            cache.get(key)?;
+        }
+        assert!(*cache.size.lock().unwrap() <= 8);
+        for name in other {
+            assert!(directory.path().join(name).exists(), "{name}");
+        }
+        Ok(())
+    }
+}
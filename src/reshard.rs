@@ -0,0 +1,448 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroU64,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon_iter_concurrent_limit::iter_concurrent_limit;
+use serde::Deserialize;
+use zarrs::{
+    array::{
+        codec::{
+            array_to_bytes::sharding::ShardingIndexLocation, ArrayToBytesCodecTraits, CodecOptions,
+        },
+        unravel_index, Array, ArrayError, ChunkRepresentation, CodecChain, DataType, Element,
+        ElementOwned, FillValue,
+    },
+    array_subset::ArraySubset,
+    metadata::v3::MetadataV3,
+    storage::{ReadableStorageTraits, WritableStorageTraits},
+};
+
+use crate::{
+    concurrent_target,
+    progress::{Progress, ProgressCallback},
+    ReencodeStats,
+};
+
+/// The sharding codec configuration.
+#[derive(Deserialize)]
+struct ShardingConfiguration {
+    chunk_shape: Vec<u64>,
+    codecs: Vec<MetadataV3>,
+    index_codecs: Vec<MetadataV3>,
+    #[serde(default)]
+    index_location: ShardingIndexLocation,
+}
+
+/// The shard index encoding of a sharded array.
+struct ShardIndex {
+    location: ShardingIndexLocation,
+    codecs: CodecChain,
+    representation: ChunkRepresentation,
+    /// The encoded size of the shard index (in bytes).
+    size: usize,
+}
+
+impl ShardIndex {
+    fn new(
+        location: ShardingIndexLocation,
+        codecs: &[MetadataV3],
+        chunks_per_shard: &[u64],
+    ) -> Option<Self> {
+        let codecs = CodecChain::from_metadata(codecs).ok()?;
+        let shape = chunks_per_shard
+            .iter()
+            .chain(&[2])
+            .map(|&size| NonZeroU64::new(size))
+            .collect::<Option<Vec<_>>>()?;
+        let representation =
+            ChunkRepresentation::new(shape, DataType::UInt64, FillValue::from(u64::MAX)).ok()?;
+        let size = codecs.compute_encoded_size(&representation).ok()?.size()?;
+        Some(Self {
+            location,
+            codecs,
+            representation,
+            size: usize::try_from(size).ok()?,
+        })
+    }
+
+    /// Decode the shard index of an encoded shard.
+    fn decode(&self, shard: &[u8]) -> anyhow::Result<Vec<u64>> {
+        if shard.len() < self.size {
+            anyhow::bail!(
+                "The shard size {} is less than the shard index size {}",
+                shard.len(),
+                self.size
+            );
+        }
+        let index = match self.location {
+            ShardingIndexLocation::Start => &shard[..self.size],
+            ShardingIndexLocation::End => &shard[shard.len() - self.size..],
+        };
+        let index =
+            self.codecs
+                .decode(index.into(), &self.representation, &CodecOptions::default())?;
+        Ok(u64::from_array_bytes(&DataType::UInt64, index)?)
+    }
+
+    /// Encode a shard index.
+    fn encode(&self, index: &[u64]) -> Result<Vec<u8>, ArrayError> {
+        let index = u64::into_array_bytes(&DataType::UInt64, index)?;
+        Ok(self
+            .codecs
+            .encode(index, &self.representation, &CodecOptions::default())?
+            .into_owned())
+    }
+}
+
+/// The encoded bytes of an input shard (or unsharded chunk), and its decoded shard index if sharded.
+type EncodedShard = (Vec<u8>, Option<Vec<u64>>);
+
+/// The encoded chunk layout of an array with a regular chunk grid.
+struct ShardLayout {
+    /// The shape of the chunks (shards if sharded).
+    shard_shape: Vec<u64>,
+    /// The shape of the inner chunks (the chunks if not sharded).
+    inner_chunk_shape: Vec<u64>,
+    /// The codecs of the inner chunks (the chunks if not sharded).
+    inner_codecs: Vec<MetadataV3>,
+    /// The shard index encoding, if sharded.
+    index: Option<ShardIndex>,
+}
+
+impl ShardLayout {
+    /// Returns the layout of `array`, or [`None`] if its chunks cannot be split into encoded inner chunks.
+    ///
+    /// This requires a regular chunk grid, and either no sharding or only the sharding codec with a fixed size shard index.
+    fn new<TStorage: ?Sized>(array: &Array<TStorage>) -> Option<Self> {
+        if array.chunk_grid().create_metadata().name() != "regular" {
+            return None;
+        }
+        let shard_shape = array
+            .chunk_shape(&vec![0; array.dimensionality()])
+            .ok()?
+            .to_array_shape();
+        let codecs = array.codecs().create_metadatas();
+        match codecs.as_slice() {
+            [sharding] if sharding.name() == "sharding_indexed" => {
+                let configuration: ShardingConfiguration =
+                    serde_json::to_value(sharding.configuration()?)
+                        .and_then(serde_json::from_value)
+                        .ok()?;
+                if configuration.chunk_shape.len() != shard_shape.len()
+                    || std::iter::zip(&shard_shape, &configuration.chunk_shape)
+                        .any(|(shard, chunk)| *chunk == 0 || shard % chunk != 0)
+                {
+                    return None;
+                }
+                let chunks_per_shard = std::iter::zip(&shard_shape, &configuration.chunk_shape)
+                    .map(|(shard, chunk)| shard / chunk)
+                    .collect::<Vec<_>>();
+                let index = ShardIndex::new(
+                    configuration.index_location,
+                    &configuration.index_codecs,
+                    &chunks_per_shard,
+                )?;
+                Some(Self {
+                    shard_shape,
+                    inner_chunk_shape: configuration.chunk_shape,
+                    inner_codecs: configuration.codecs,
+                    index: Some(index),
+                })
+            }
+            codecs
+                if codecs
+                    .iter()
+                    .all(|codec| codec.name() != "sharding_indexed") =>
+            {
+                Some(Self {
+                    inner_chunk_shape: shard_shape.clone(),
+                    shard_shape,
+                    inner_codecs: codecs.to_vec(),
+                    index: None,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The number of inner chunks per shard along each dimension.
+    fn chunks_per_shard(&self) -> Vec<u64> {
+        std::iter::zip(&self.shard_shape, &self.inner_chunk_shape)
+            .map(|(shard, chunk)| shard / chunk)
+            .collect()
+    }
+}
+
+/// Returns true if `array_in` can be resharded to `array_out` by [`do_reshard`] without decoding.
+///
+/// This requires that:
+///  - the array shape, data type, and fill value are unchanged,
+///  - the output is sharded, and the input is either unsharded or sharded,
+///  - the inner chunk shape and inner codecs of the output match the inner chunks of the input (or the chunks of an unsharded input),
+///  - both arrays have a regular chunk grid and no codecs other than the sharding codec, and
+///  - the shard indexes have a fixed encoded size (e.g. no compression).
+///
+/// The shard shape, shard index location, and shard index codecs may differ.
+pub fn reshard_compatible<TStorageIn: ?Sized, TStorageOut: ?Sized>(
+    array_in: &Array<TStorageIn>,
+    array_out: &Array<TStorageOut>,
+) -> bool {
+    if array_in.shape() != array_out.shape()
+        || array_in.data_type() != array_out.data_type()
+        || array_in.fill_value() != array_out.fill_value()
+    {
+        return false;
+    }
+    let (Some(layout_in), Some(layout_out)) =
+        (ShardLayout::new(array_in), ShardLayout::new(array_out))
+    else {
+        return false;
+    };
+    layout_out.index.is_some()
+        && layout_in.inner_chunk_shape == layout_out.inner_chunk_shape
+        && layout_in.inner_codecs == layout_out.inner_codecs
+}
+
+/// Reshard `array_in` to `array_out` by moving encoded inner chunks between shards without decoding.
+///
+/// This is a fast path for reencoding where only the shard shape (or the shard index encoding) changes, or an unsharded array is sharded with the same chunk encoding.
+/// The shard indexes are rewritten and the encoded inner chunks are concatenated, skipping the array codec pipeline entirely.
+/// An input shard is read once per overlapping output shard, and shards without any inner chunks are not written.
+/// The array metadata of `array_out` is not written.
+///
+/// # Errors
+/// Returns an error if the arrays are not compatible (see [`reshard_compatible`]), or a shard cannot be read, decoded, or written.
+pub fn do_reshard<
+    TStorageIn: ReadableStorageTraits + ?Sized + 'static,
+    TStorageOut: WritableStorageTraits + ?Sized + 'static,
+>(
+    array_in: &Array<TStorageIn>,
+    array_out: &Array<TStorageOut>,
+    storage_out: &TStorageOut,
+    concurrent_chunks: Option<usize>,
+    progress_callback: &ProgressCallback,
+) -> anyhow::Result<ReencodeStats> {
+    if !reshard_compatible(array_in, array_out) {
+        anyhow::bail!("The input array cannot be resharded to the output array without decoding");
+    }
+    let (Some(layout_in), Some(layout_out)) =
+        (ShardLayout::new(array_in), ShardLayout::new(array_out))
+    else {
+        unreachable!("compatibility has been checked")
+    };
+    let index_out = layout_out.index.as_ref().unwrap();
+    let chunks_per_shard_in = layout_in.chunks_per_shard();
+    let chunks_per_shard_out = layout_out.chunks_per_shard();
+    let shard_grid_shape_in = array_in.chunk_grid_shape().unwrap();
+
+    let start = SystemTime::now();
+    let num_writes = AtomicUsize::new(0);
+
+    let shards = ArraySubset::new_with_shape(array_out.chunk_grid_shape().unwrap());
+    let progress = Progress::new(shards.num_elements_usize(), progress_callback);
+    let chunks_concurrent_limit = concurrent_chunks.unwrap_or_else(concurrent_target).max(1);
+
+    // Returns the encoded bytes and shard index of an input shard, or just the encoded bytes of an unsharded input chunk
+    let retrieve_shard_in = |shard_indices: &[u64]| -> anyhow::Result<Option<EncodedShard>> {
+        let Some(bytes) = progress.read(|| array_in.retrieve_encoded_chunk(shard_indices))? else {
+            return Ok(None);
+        };
+        progress.add_bytes_read(bytes.len());
+        let index = layout_in
+            .index
+            .as_ref()
+            .map(|index| index.decode(&bytes))
+            .transpose()?;
+        Ok(Some((bytes, index)))
+    };
+
+    let num_chunks_per_shard_out = chunks_per_shard_out.iter().product::<u64>();
+    let indices = shards.indices();
+    iter_concurrent_limit!(
+        chunks_concurrent_limit,
+        indices,
+        try_for_each,
+        |shard_indices: Vec<u64>| {
+            let mut shards_in: HashMap<Vec<u64>, Option<EncodedShard>> = HashMap::new();
+            let mut index = vec![u64::MAX; usize::try_from(num_chunks_per_shard_out).unwrap() * 2];
+            let mut bytes = vec![];
+            for chunk_linear_index in 0..num_chunks_per_shard_out {
+                // The indices of the inner chunk in the inner chunk grid of the array
+                let chunk_indices = std::iter::zip(
+                    unravel_index(chunk_linear_index, &chunks_per_shard_out),
+                    std::iter::zip(&shard_indices, &chunks_per_shard_out),
+                )
+                .map(|(i, (shard, chunks_per_shard))| shard * chunks_per_shard + i)
+                .collect::<Vec<_>>();
+                let shard_indices_in = std::iter::zip(&chunk_indices, &chunks_per_shard_in)
+                    .map(|(chunk, chunks_per_shard)| chunk / chunks_per_shard)
+                    .collect::<Vec<_>>();
+                if std::iter::zip(&shard_indices_in, &shard_grid_shape_in)
+                    .any(|(shard, num_shards)| shard >= num_shards)
+                {
+                    // The inner chunk is outside of the array
+                    continue;
+                }
+                if !shards_in.contains_key(&shard_indices_in) {
+                    let shard_in = retrieve_shard_in(&shard_indices_in)?;
+                    shards_in.insert(shard_indices_in.clone(), shard_in);
+                }
+                let Some((bytes_in, index_in)) = &shards_in[&shard_indices_in] else {
+                    continue;
+                };
+                let chunk_bytes = if let Some(index_in) = index_in {
+                    let chunk_linear_index_in = std::iter::zip(
+                        &chunk_indices,
+                        &chunks_per_shard_in,
+                    )
+                    .fold(0, |linear_index, (chunk, chunks_per_shard)| {
+                        linear_index * chunks_per_shard + chunk % chunks_per_shard
+                    });
+                    let chunk_linear_index_in = usize::try_from(chunk_linear_index_in).unwrap();
+                    let (offset, size) = (
+                        index_in[chunk_linear_index_in * 2],
+                        index_in[chunk_linear_index_in * 2 + 1],
+                    );
+                    if offset == u64::MAX && size == u64::MAX {
+                        continue;
+                    }
+                    let (offset, size) = (
+                        usize::try_from(offset).unwrap(),
+                        usize::try_from(size).unwrap(),
+                    );
+                    let Some(chunk_bytes) = bytes_in.get(offset..offset + size) else {
+                        anyhow::bail!(
+                            "The shard index of input shard {shard_indices_in:?} is out of bounds"
+                        );
+                    };
+                    chunk_bytes
+                } else {
+                    &bytes_in[..]
+                };
+                let chunk_linear_index = usize::try_from(chunk_linear_index).unwrap();
+                index[chunk_linear_index * 2] = bytes.len() as u64;
+                index[chunk_linear_index * 2 + 1] = chunk_bytes.len() as u64;
+                bytes.extend_from_slice(chunk_bytes);
+            }
+            drop(shards_in);
+
+            if !bytes.is_empty() {
+                let shard = match index_out.location {
+                    ShardingIndexLocation::Start => {
+                        for offset in index.iter_mut().step_by(2) {
+                            if *offset != u64::MAX {
+                                *offset += index_out.size as u64;
+                            }
+                        }
+                        let mut shard = index_out.encode(&index)?;
+                        shard.extend_from_slice(&bytes);
+                        shard
+                    }
+                    ShardingIndexLocation::End => {
+                        bytes.extend_from_slice(&index_out.encode(&index)?);
+                        bytes
+                    }
+                };
+                progress.add_bytes_written(shard.len());
+                progress.write(|| {
+                    storage_out.set(&array_out.chunk_key(&shard_indices), shard.into())
+                })?;
+                num_writes.fetch_add(1, Ordering::Relaxed);
+            }
+            progress.next()?;
+            Ok::<_, anyhow::Error>(())
+        }
+    )?;
+
+    let duration = start.elapsed().unwrap().as_secs_f32();
+    let stats = progress.stats();
+    let duration_read = stats.read.as_secs_f32();
+    let duration_write = stats.write.as_secs_f32();
+    let duration_read_write = duration_read + duration_write;
+    let duration_read = duration_read * duration / duration_read_write;
+    let duration_write = duration_write * duration / duration_read_write;
+
+    Ok(ReencodeStats {
+        duration,
+        duration_read,
+        duration_write,
+        bytes_decoded: 0,
+        num_chunks: shards.num_elements_usize(),
+        num_writes: num_writes.into_inner(),
+        cache_requests_misses: None,
+        error_metrics: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zarrs::{
+        array::{codec::array_to_bytes::sharding::ShardingCodecBuilder, ArrayBuilder},
+        storage::store::MemoryStore,
+    };
+
+    use super::*;
+
+    fn sharded_array(
+        store: Arc<MemoryStore>,
+        shard_shape: Vec<u64>,
+        index_location: ShardingIndexLocation,
+    ) -> Result<Array<MemoryStore>, Box<dyn std::error::Error>> {
+        let mut sharding_codec_builder = ShardingCodecBuilder::new(vec![2, 2].try_into()?);
+        sharding_codec_builder.index_location(index_location);
+        Ok(ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            shard_shape.try_into()?,
+            FillValue::from(0u16),
+        )
+        .array_to_bytes_codec(Arc::new(sharding_codec_builder.build()))
+        .build(store, "/")?)
+    }
+
+    #[test]
+    fn reshard() -> Result<(), Box<dyn std::error::Error>> {
+        let elements = (0..42).map(|i| i as u16 % 20).collect::<Vec<_>>();
+        let array_in = sharded_array(
+            Arc::new(MemoryStore::new()),
+            vec![4, 2],
+            ShardingIndexLocation::End,
+        )?;
+        array_in.store_array_subset_elements::<u16>(&array_in.subset_all(), &elements)?;
+
+        let store_out = Arc::new(MemoryStore::new());
+        let array_out = sharded_array(store_out.clone(), vec![2, 6], ShardingIndexLocation::Start)?;
+        assert!(reshard_compatible(&array_in, &array_out));
+        let progress_callback = |_| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let stats = do_reshard(
+            &array_in,
+            &array_out,
+            store_out.as_ref(),
+            None,
+            &progress_callback,
+        )?;
+        assert_eq!(stats.num_chunks, 4);
+        assert_eq!(
+            array_out.retrieve_array_subset_elements::<u16>(&array_out.subset_all())?,
+            elements
+        );
+
+        // The inner chunk shape differs
+        let array_other = ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            vec![3, 3].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        assert!(!reshard_compatible(&array_in, &array_other));
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon_iter_concurrent_limit::iter_concurrent_limit;
+use zarrs::{
+    array::{
+        codec::{ArrayToArrayCodecTraits, CodecOptions},
+        Array, BytesRepresentation, RawBytes,
+    },
+    array_subset::ArraySubset,
+    storage::{ReadableStorageTraits, WritableStorageTraits},
+};
+
+use crate::{
+    concurrent_target,
+    progress::{Progress, ProgressCallback},
+    ReencodeStats,
+};
+
+/// Returns true if the chunks of `array_in` can be transcoded to `array_out` by only reencoding the bytes to bytes codecs (e.g. compression).
+///
+/// This requires that the array shape, data type, fill value, chunk grid, array to array codecs, and array to bytes codec are unchanged.
+/// The bytes to bytes codecs and chunk key encoding may differ.
+pub fn transcode_compatible<TStorageIn: ?Sized, TStorageOut: ?Sized>(
+    array_in: &Array<TStorageIn>,
+    array_out: &Array<TStorageOut>,
+) -> bool {
+    let (codecs_in, codecs_out) = (array_in.codecs(), array_out.codecs());
+    let array_to_array_metadatas = |codecs: &[Arc<dyn ArrayToArrayCodecTraits>]| {
+        codecs
+            .iter()
+            .map(|codec| codec.create_metadata())
+            .collect::<Vec<_>>()
+    };
+    array_in.shape() == array_out.shape()
+        && array_in.data_type() == array_out.data_type()
+        && array_in.fill_value() == array_out.fill_value()
+        && array_in.chunk_grid().create_metadata() == array_out.chunk_grid().create_metadata()
+        && array_to_array_metadatas(codecs_in.array_to_array_codecs())
+            == array_to_array_metadatas(codecs_out.array_to_array_codecs())
+        && codecs_in.array_to_bytes_codec().create_metadata()
+            == codecs_out.array_to_bytes_codec().create_metadata()
+}
+
+/// Returns the decoded representation of each bytes to bytes codec of a chunk of `array`.
+fn bytes_representations<TStorage: ?Sized>(
+    array: &Array<TStorage>,
+    chunk_indices: &[u64],
+) -> anyhow::Result<Vec<BytesRepresentation>> {
+    let codecs = array.codecs();
+    let mut array_representation = array.chunk_array_representation(chunk_indices)?;
+    for codec in codecs.array_to_array_codecs() {
+        array_representation = codec.compute_encoded_size(&array_representation)?;
+    }
+    let mut bytes_representations = vec![codecs
+        .array_to_bytes_codec()
+        .compute_encoded_size(&array_representation)?];
+    for codec in codecs.bytes_to_bytes_codecs() {
+        bytes_representations
+            .push(codec.compute_encoded_size(bytes_representations.last().unwrap()));
+    }
+    bytes_representations.pop();
+    Ok(bytes_representations)
+}
+
+/// Transcode `array_in` to `array_out` by decoding and reencoding only the bytes to bytes codecs of each chunk.
+///
+/// This is a fast path for reencoding where only the compression (or other bytes to bytes codecs) changes.
+/// The array to bytes encoded chunks are passed between the codecs directly, skipping array decoding and encoding entirely.
+/// Chunks that do not exist in the input are skipped.
+/// The array metadata of `array_out` is not written.
+///
+/// # Errors
+/// Returns an error if the arrays are not compatible (see [`transcode_compatible`]), or a chunk cannot be read, decoded, encoded, or written.
+pub fn do_transcode<
+    TStorageIn: ReadableStorageTraits + ?Sized + 'static,
+    TStorageOut: WritableStorageTraits + ?Sized + 'static,
+>(
+    array_in: &Array<TStorageIn>,
+    array_out: &Array<TStorageOut>,
+    storage_out: &TStorageOut,
+    concurrent_chunks: Option<usize>,
+    progress_callback: &ProgressCallback,
+) -> anyhow::Result<ReencodeStats> {
+    if !transcode_compatible(array_in, array_out) {
+        anyhow::bail!("The input array cannot be transcoded to the output array without decoding");
+    }
+    let codecs_in = array_in.codecs();
+    let codecs_out = array_out.codecs();
+    let options = CodecOptions::default();
+
+    let start = SystemTime::now();
+    let num_writes = AtomicUsize::new(0);
+    let bytes_decoded = AtomicUsize::new(0);
+
+    let chunks = ArraySubset::new_with_shape(array_in.chunk_grid_shape().unwrap());
+    let progress = Progress::new(chunks.num_elements_usize(), progress_callback);
+    let chunks_concurrent_limit = concurrent_chunks.unwrap_or_else(concurrent_target).max(1);
+
+    // The chunk indices of an array without chunks cannot be iterated
+    if chunks.num_elements() > 0 {
+        let indices = chunks.indices();
+        iter_concurrent_limit!(
+            chunks_concurrent_limit,
+            indices,
+            try_for_each,
+            |chunk_indices: Vec<u64>| {
+                let Some(encoded) =
+                    progress.read(|| array_in.retrieve_encoded_chunk(&chunk_indices))?
+                else {
+                    progress.next()?;
+                    return Ok(());
+                };
+                progress.add_bytes_read(encoded.len());
+
+                let bytes = progress.process(|| -> anyhow::Result<Vec<u8>> {
+                    let mut bytes: RawBytes = encoded.into();
+                    for (codec, decoded_representation) in codecs_in
+                        .bytes_to_bytes_codecs()
+                        .iter()
+                        .zip(&bytes_representations(array_in, &chunk_indices)?)
+                        .rev()
+                    {
+                        bytes = codec.decode(bytes, decoded_representation, &options)?;
+                    }
+                    bytes_decoded.fetch_add(bytes.len(), Ordering::Relaxed);
+                    for codec in codecs_out.bytes_to_bytes_codecs() {
+                        bytes = codec.encode(bytes, &options)?;
+                    }
+                    Ok(bytes.into_owned())
+                })?;
+
+                progress.add_bytes_written(bytes.len());
+                progress.write(|| {
+                    storage_out.set(&array_out.chunk_key(&chunk_indices), bytes.into())
+                })?;
+                num_writes.fetch_add(1, Ordering::Relaxed);
+                progress.next()?;
+                Ok::<_, anyhow::Error>(())
+            }
+        )?;
+    }
+
+    let duration = start.elapsed().unwrap().as_secs_f32();
+    let stats = progress.stats();
+    let duration_read = stats.read.as_secs_f32();
+    let duration_write = stats.write.as_secs_f32();
+    let duration_read_write = duration_read + duration_write;
+    // There are no reads or writes if the array has no chunks
+    let (duration_read, duration_write) = if duration_read_write > 0.0 {
+        (
+            duration_read * duration / duration_read_write,
+            duration_write * duration / duration_read_write,
+        )
+    } else {
+        (0.0, 0.0)
+    };
+
+    Ok(ReencodeStats {
+        duration,
+        duration_read,
+        duration_write,
+        bytes_decoded: bytes_decoded.into_inner(),
+        num_chunks: chunks.num_elements_usize(),
+        num_writes: num_writes.into_inner(),
+        cache_requests_misses: None,
+        error_metrics: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use zarrs::{
+        array::{
+            codec::{GzipCodec, ZstdCodec},
+            ArrayBuilder, DataType, FillValue,
+        },
+        storage::store::MemoryStore,
+    };
+
+    use super::*;
+
+    #[test]
+    fn transcode() -> Result<(), Box<dyn std::error::Error>> {
+        let elements = (0..42).map(|i| i as u16 % 20).collect::<Vec<_>>();
+        let mut builder = ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            vec![4, 4].try_into()?,
+            FillValue::from(0u16),
+        );
+        let array_in = builder
+            .bytes_to_bytes_codecs(vec![Arc::new(GzipCodec::new(5)?)])
+            .build(Arc::new(MemoryStore::new()), "/")?;
+        array_in.store_array_subset_elements::<u16>(
+            &ArraySubset::new_with_ranges(&[0..4, 0..6]),
+            &elements[..24],
+        )?;
+
+        let store_out = Arc::new(MemoryStore::new());
+        let array_out = builder
+            .bytes_to_bytes_codecs(vec![Arc::new(ZstdCodec::new(3, false))])
+            .build(store_out.clone(), "/")?;
+        assert!(transcode_compatible(&array_in, &array_out));
+        let progress_callback = |_| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let stats = do_transcode(
+            &array_in,
+            &array_out,
+            store_out.as_ref(),
+            None,
+            &progress_callback,
+        )?;
+        assert_eq!(stats.num_chunks, 4);
+        assert_eq!(stats.num_writes, 2);
+        let mut elements_expected = elements[..24].to_vec();
+        elements_expected.resize(42, 0);
+        assert_eq!(
+            array_out.retrieve_array_subset_elements::<u16>(&array_out.subset_all())?,
+            elements_expected
+        );
+
+        // The chunk shape differs
+        let array_other = ArrayBuilder::new(
+            vec![7, 6],
+            DataType::UInt16,
+            vec![2, 2].try_into()?,
+            FillValue::from(0u16),
+        )
+        .build(Arc::new(MemoryStore::new()), "/")?;
+        assert!(!transcode_compatible(&array_in, &array_other));
+        Ok(())
+    }
+
+    #[test]
+    fn transcode_no_chunks() -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = ArrayBuilder::new(
+            vec![0, 6],
+            DataType::UInt16,
+            vec![4, 4].try_into()?,
+            FillValue::from(0u16),
+        );
+        let array_in = builder
+            .bytes_to_bytes_codecs(vec![Arc::new(GzipCodec::new(5)?)])
+            .build(Arc::new(MemoryStore::new()), "/")?;
+        let store_out = Arc::new(MemoryStore::new());
+        let array_out = builder
+            .bytes_to_bytes_codecs(vec![Arc::new(ZstdCodec::new(3, false))])
+            .build(store_out.clone(), "/")?;
+        let progress_callback = |_| {};
+        let progress_callback = ProgressCallback::new(&progress_callback);
+        let stats = do_transcode(
+            &array_in,
+            &array_out,
+            store_out.as_ref(),
+            None,
+            &progress_callback,
+        )?;
+        assert_eq!(stats.num_chunks, 0);
+        assert_eq!(stats.num_writes, 0);
+        assert_eq!((stats.duration_read, stats.duration_write), (0.0, 0.0));
+        Ok(())
+    }
+}